@@ -0,0 +1,238 @@
+//! 下载事件的合并
+//!
+//! 进度类事件按任务合并，每个刷新间隔最多发送一次，只保留最新的值；生命周期和校验结果
+//! 等事件立即发送。同一任务的事件保持原来的顺序：立即发送某个任务的事件前，先发送
+//! 该任务尚未发出的进度。不同任务之间的事件不保证顺序。
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use yushi_core::{DownloaderEvent, EventEnvelope, ProgressEvent, TaskEvent, VerificationEvent};
+
+/// 可以合并的事件种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ProgressKind {
+    Download,
+    Finalize,
+    Verify,
+}
+
+pub struct EventAggregator {
+    interval: Duration,
+    /// 等待发送的进度事件，同一任务同一种类只保留最新的一个
+    pending: HashMap<(String, ProgressKind), EventEnvelope>,
+    last_flush: Instant,
+}
+
+impl EventAggregator {
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            pending: HashMap::new(),
+            last_flush: now,
+        }
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// 收到一个事件，返回需要立即发送的事件
+    pub fn push(&mut self, envelope: EventEnvelope, now: Instant) -> Vec<EventEnvelope> {
+        if let Some((task_id, kind)) = progress_key(&envelope.event) {
+            self.pending.insert((task_id.to_string(), kind), envelope);
+            if now >= self.last_flush + self.interval {
+                return self.flush(now);
+            }
+            return Vec::new();
+        }
+
+        let mut ready = match task_id(&envelope.event) {
+            Some(task_id) => self.take_task(task_id),
+            None => Vec::new(),
+        };
+        ready.push(envelope);
+        ready
+    }
+
+    /// 下一次需要调用 [`EventAggregator::flush`] 的时间，没有等待发送的进度时为 None
+    pub fn next_flush(&self) -> Option<Instant> {
+        (!self.pending.is_empty()).then(|| self.last_flush + self.interval)
+    }
+
+    /// 取出所有等待发送的进度，按事件序号排序
+    pub fn flush(&mut self, now: Instant) -> Vec<EventEnvelope> {
+        self.last_flush = now;
+        let mut ready: Vec<_> = self.pending.drain().map(|(_, e)| e).collect();
+        ready.sort_by_key(|e| e.seq);
+        ready
+    }
+
+    /// 取出一个任务等待发送的进度
+    fn take_task(&mut self, task_id: &str) -> Vec<EventEnvelope> {
+        let keys: Vec<_> = self
+            .pending
+            .keys()
+            .filter(|(id, _)| id == task_id)
+            .cloned()
+            .collect();
+        let mut ready: Vec<_> = keys
+            .iter()
+            .filter_map(|key| self.pending.remove(key))
+            .collect();
+        ready.sort_by_key(|e| e.seq);
+        ready
+    }
+}
+
+fn progress_key(event: &DownloaderEvent) -> Option<(&str, ProgressKind)> {
+    match event {
+        DownloaderEvent::Progress(ProgressEvent::Updated { task_id, .. }) => {
+            Some((task_id, ProgressKind::Download))
+        }
+        DownloaderEvent::Progress(ProgressEvent::Finalizing { task_id, .. }) => {
+            Some((task_id, ProgressKind::Finalize))
+        }
+        DownloaderEvent::Verification(VerificationEvent::Progress { task_id, .. }) => {
+            Some((task_id, ProgressKind::Verify))
+        }
+        _ => None,
+    }
+}
+
+/// 事件所属的任务，与单个任务无关的事件返回 None
+fn task_id(event: &DownloaderEvent) -> Option<&str> {
+    match event {
+        DownloaderEvent::Task(event) => match event {
+            TaskEvent::Added { task_id }
+            | TaskEvent::Started { task_id }
+            | TaskEvent::Completed { task_id, .. }
+            | TaskEvent::Failed { task_id, .. }
+            | TaskEvent::PartiallyCompleted { task_id, .. }
+            | TaskEvent::Paused { task_id }
+            | TaskEvent::Resumed { task_id }
+            | TaskEvent::Cancelled { task_id }
+            | TaskEvent::LinkFailed { task_id, .. }
+            | TaskEvent::Boosted { task_id, .. }
+            | TaskEvent::BoostEnded { task_id } => Some(task_id),
+            TaskEvent::GroupCompleted { .. } => None,
+        },
+        DownloaderEvent::Progress(event) => event.task_id(),
+        DownloaderEvent::Verification(event) => match event {
+            VerificationEvent::Started { task_id }
+            | VerificationEvent::Progress { task_id, .. }
+            | VerificationEvent::Completed { task_id, .. } => Some(task_id),
+        },
+        DownloaderEvent::Global(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yushi_core::{GlobalEvent, SchedulerState};
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn envelope(seq: u64, event: DownloaderEvent) -> EventEnvelope {
+        EventEnvelope {
+            seq,
+            timestamp_ms: 0,
+            replayed: false,
+            event,
+        }
+    }
+
+    fn progress(seq: u64, task_id: &str) -> EventEnvelope {
+        envelope(
+            seq,
+            DownloaderEvent::Progress(ProgressEvent::Updated {
+                task_id: task_id.to_string(),
+                downloaded: seq * 100,
+                total: 100_000,
+                speed: 1,
+                eta: None,
+                contiguous_prefix: 0,
+            }),
+        )
+    }
+
+    fn downloaded(envelope: &EventEnvelope) -> Option<u64> {
+        match &envelope.event {
+            DownloaderEvent::Progress(ProgressEvent::Updated { downloaded, .. }) => {
+                Some(*downloaded)
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_progress_coalesced_per_task() {
+        let start = Instant::now();
+        let mut aggregator = EventAggregator::new(INTERVAL, start);
+        assert_eq!(aggregator.next_flush(), None);
+
+        for seq in 1..=60 {
+            let task = if seq % 3 == 0 { "b" } else { "a" };
+            assert!(aggregator.push(progress(seq, task), start).is_empty());
+        }
+        assert_eq!(aggregator.next_flush(), Some(start + INTERVAL));
+
+        // 每个任务只发送最新的进度，按序号排序
+        let flushed = aggregator.flush(start + INTERVAL);
+        let seqs: Vec<_> = flushed.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, [59, 60]);
+        assert_eq!(downloaded(&flushed[0]), Some(5900));
+        assert_eq!(aggregator.next_flush(), None);
+
+        // 超过刷新间隔后收到的进度直接发送
+        let later = start + INTERVAL * 3;
+        let ready = aggregator.push(progress(61, "a"), later);
+        assert_eq!(ready.iter().map(|e| e.seq).collect::<Vec<_>>(), [61]);
+        assert!(aggregator.push(progress(62, "a"), later).is_empty());
+    }
+
+    #[test]
+    fn test_lifecycle_events_flush_their_task_first() {
+        let start = Instant::now();
+        let mut aggregator = EventAggregator::new(INTERVAL, start);
+        aggregator.push(progress(1, "a"), start);
+        aggregator.push(progress(2, "b"), start);
+        aggregator.push(
+            envelope(
+                3,
+                DownloaderEvent::Verification(VerificationEvent::Progress {
+                    task_id: "a".to_string(),
+                    verified: 10,
+                    total: 100,
+                }),
+            ),
+            start,
+        );
+
+        // 与任务无关的事件立即发送，不影响等待中的进度
+        let scheduler = envelope(
+            4,
+            DownloaderEvent::Global(GlobalEvent::SchedulerStateChanged(SchedulerState::default())),
+        );
+        let ready = aggregator.push(scheduler, start);
+        assert_eq!(ready.iter().map(|e| e.seq).collect::<Vec<_>>(), [4]);
+
+        // 任务完成前先发送它尚未发出的进度，进度不会出现在完成事件之后
+        let completed = envelope(
+            5,
+            DownloaderEvent::Task(TaskEvent::Completed {
+                task_id: "a".to_string(),
+                skipped_unchanged: false,
+                average_speed: 0,
+                peak_speed: 0,
+            }),
+        );
+        let ready = aggregator.push(completed, start);
+        assert_eq!(ready.iter().map(|e| e.seq).collect::<Vec<_>>(), [1, 3, 5]);
+
+        let rest = aggregator.flush(start + INTERVAL);
+        assert_eq!(rest.iter().map(|e| e.seq).collect::<Vec<_>>(), [2]);
+    }
+}
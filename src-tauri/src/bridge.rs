@@ -0,0 +1,73 @@
+//! 向所有窗口广播事件
+//!
+//! 主窗口和拖放窗口等多个 webview 都会收到同样的事件。每个事件带有连续递增的
+//! `bridge_seq`，窗口发现序号不连续时说明错过了事件，需要重新获取任务列表。
+//! 重新加载的窗口会单独收到最近事件的补发，补发不占用序号。
+
+use serde::Serialize;
+use std::{collections::HashSet, sync::Mutex};
+use tauri::{AppHandle, Emitter};
+
+/// 广播的事件内容
+#[derive(Serialize)]
+struct Broadcast<'a, T> {
+    /// 在所有广播的事件中连续递增，从 1 开始
+    bridge_seq: u64,
+    #[serde(flatten)]
+    payload: &'a T,
+}
+
+pub struct EventBridge {
+    app: AppHandle,
+    /// 最近一次广播的序号
+    seq: Mutex<u64>,
+    /// 已经加载过页面的窗口
+    loaded: Mutex<HashSet<String>>,
+}
+
+impl EventBridge {
+    pub fn new(app: AppHandle) -> Self {
+        Self {
+            app,
+            seq: Mutex::new(0),
+            loaded: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// 记录窗口加载完页面，返回是否是重新加载（开发时热重载或 webview 崩溃后恢复）
+    pub fn page_loaded(&self, label: &str) -> bool {
+        !self.loaded.lock().unwrap().insert(label.to_string())
+    }
+
+    /// 只向一个窗口补发事件，带有当前的序号，不影响其他窗口的序号检查
+    pub fn emit_replayed<T: Serialize>(&self, label: &str, event: &str, payload: &T) {
+        let seq = self.seq.lock().unwrap();
+        let _ = self.app.emit_to(
+            label,
+            event,
+            Broadcast {
+                bridge_seq: *seq,
+                payload,
+            },
+        );
+    }
+
+    /// 向所有窗口发送事件，`payload` 必须序列化为 JSON 对象
+    pub fn emit<T: Serialize>(&self, event: &str, payload: &T) {
+        // 持有锁直到发送完成，保证窗口收到的序号有序
+        let mut seq = self.seq.lock().unwrap();
+        *seq += 1;
+        let _ = self.app.emit(
+            event,
+            Broadcast {
+                bridge_seq: *seq,
+                payload,
+            },
+        );
+    }
+
+    /// 最近一次广播的序号，还没有广播过时为 0
+    pub fn seq(&self) -> u64 {
+        *self.seq.lock().unwrap()
+    }
+}
@@ -1,6 +1,39 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::path::PathBuf;
+use yushi_core::{IpPolicy, i18n};
+
+/// 每个任务连接数的上限
+const MAX_CONNECTIONS: usize = 64;
+/// 同时运行任务数的上限
+const MAX_TASKS: usize = 32;
+/// 分块大小的范围（字节）
+const CHUNK_SIZE_RANGE: std::ops::RangeInclusive<u64> = 64 * 1024..=1024 * 1024 * 1024;
+/// 超时的上限（秒）
+const MAX_TIMEOUT: u64 = 3600;
+/// 速度限制的上限（字节/秒），更大的值多半是单位填错了
+const MAX_SPEED_LIMIT: u64 = 10 * 1024 * 1024 * 1024;
+/// 下载进度事件刷新间隔的范围（毫秒）
+const EVENT_FLUSH_RANGE: std::ops::RangeInclusive<u64> = 16..=5000;
+
+/// 配置中有问题的字段
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigIssue {
+    /// 字段名，与配置文件中的键相同；为空表示整个配置文件
+    pub field: String,
+    /// 原因
+    pub message: String,
+}
+
+impl ConfigIssue {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
 
 /// 窗口状态
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +82,24 @@ pub struct AppConfig {
     pub user_agent: String,
     /// 主题设置 (light, dark, system)
     pub theme: String,
+    /// 所有任务的总速度限制（字节/秒），None 表示不限速
+    #[serde(default)]
+    pub speed_limit: Option<u64>,
+    /// 下载进度事件发送到前端的最小间隔（毫秒），同一任务在间隔内的进度只发送最新的
+    #[serde(default = "default_event_flush_interval_ms")]
+    pub event_flush_interval_ms: u64,
+    /// 错误信息、文件大小等文本的语言（en、zh），None 表示跟随系统
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// 下载完成后在这个目录中创建指向文件的链接，None 表示不创建；修改后重启生效
+    #[serde(default)]
+    pub completed_link_dir: Option<PathBuf>,
+    /// 无法创建链接时复制文件到 `completed_link_dir`
+    #[serde(default)]
+    pub completed_link_copy: bool,
+    /// 连接服务器时使用的 IP 协议版本，IPv6 不通的网络中可以只用 IPv4；修改后重启生效
+    #[serde(default)]
+    pub ip_policy: IpPolicy,
     /// 窗口状态
     #[serde(default)]
     pub window: WindowState,
@@ -67,21 +118,90 @@ impl Default for AppConfig {
             timeout: 30,
             user_agent: "YuShi/0.1.0".to_string(),
             theme: "system".to_string(),
+            speed_limit: None,
+            event_flush_interval_ms: default_event_flush_interval_ms(),
+            locale: None,
+            completed_link_dir: None,
+            completed_link_copy: false,
+            ip_policy: IpPolicy::Auto,
             window: WindowState::default(),
         }
     }
 }
 
+fn default_event_flush_interval_ms() -> u64 {
+    100
+}
+
 impl AppConfig {
     /// 从文件加载配置
-    pub async fn load(path: &PathBuf) -> Result<Self> {
+    ///
+    /// 无法解析或不合法的字段逐个恢复为默认值，同时返回被修正的字段。
+    /// 文件不是合法的 JSON 时返回错误。
+    pub async fn load(path: &PathBuf) -> Result<(Self, Vec<ConfigIssue>)> {
         if path.exists() {
             let content = fs_err::tokio::read_to_string(path).await?;
-            let config: AppConfig = serde_json::from_str(&content)?;
-            Ok(config)
+            Ok(Self::from_value(serde_json::from_str(&content)?))
         } else {
             // 如果配置文件不存在，返回默认配置
-            Ok(Self::default())
+            Ok((Self::default(), Vec::new()))
+        }
+    }
+
+    /// 从 JSON 值解析配置，无法解析或不合法的字段使用默认值
+    pub fn from_value(value: Value) -> (Self, Vec<ConfigIssue>) {
+        let defaults = Self::default();
+        let mut merged = serde_json::to_value(&defaults).expect("config is serializable");
+        let fields = merged.as_object_mut().expect("config is an object");
+        let mut issues = Vec::new();
+
+        match value {
+            Value::Object(loaded) => {
+                for (key, value) in loaded {
+                    // 未知字段忽略
+                    let Some(previous) = fields.insert(key.clone(), value) else {
+                        fields.remove(&key);
+                        continue;
+                    };
+                    if serde_json::from_value::<Self>(Value::Object(fields.clone())).is_err() {
+                        fields.insert(key.clone(), previous);
+                        issues.push(ConfigIssue::new(&key, "invalid value"));
+                    }
+                }
+            }
+            _ => issues.push(ConfigIssue::new("", "config is not a JSON object")),
+        }
+
+        let mut config: Self = serde_json::from_value(merged).unwrap_or_else(|_| defaults.clone());
+        if let Err(invalid) = config.validate() {
+            for issue in &invalid {
+                config.reset_field(&issue.field, &defaults);
+            }
+            issues.extend(invalid);
+        }
+        (config, issues)
+    }
+
+    /// 把一个字段恢复为默认值
+    fn reset_field(&mut self, field: &str, defaults: &Self) {
+        match field {
+            "default_download_path" => {
+                self.default_download_path = defaults.default_download_path.clone()
+            }
+            "max_concurrent_downloads" => {
+                self.max_concurrent_downloads = defaults.max_concurrent_downloads
+            }
+            "max_concurrent_tasks" => self.max_concurrent_tasks = defaults.max_concurrent_tasks,
+            "chunk_size" => self.chunk_size = defaults.chunk_size,
+            "timeout" => self.timeout = defaults.timeout,
+            "theme" => self.theme = defaults.theme.clone(),
+            "speed_limit" => self.speed_limit = defaults.speed_limit,
+            "locale" => self.locale = defaults.locale.clone(),
+            "completed_link_dir" => self.completed_link_dir = defaults.completed_link_dir.clone(),
+            "event_flush_interval_ms" => {
+                self.event_flush_interval_ms = defaults.event_flush_interval_ms
+            }
+            _ => {}
         }
     }
 
@@ -92,20 +212,200 @@ impl AppConfig {
         Ok(())
     }
 
-    /// 验证配置的有效性
-    pub fn validate(&self) -> Result<()> {
-        if self.max_concurrent_downloads == 0 {
-            anyhow::bail!("max_concurrent_downloads must be greater than 0");
+    /// 验证配置的有效性，返回所有不合法的字段
+    pub fn validate(&self) -> std::result::Result<(), Vec<ConfigIssue>> {
+        let mut issues = Vec::new();
+        if !self.default_download_path.is_dir() {
+            issues.push(ConfigIssue::new(
+                "default_download_path",
+                "directory does not exist",
+            ));
         }
-        if self.max_concurrent_tasks == 0 {
-            anyhow::bail!("max_concurrent_tasks must be greater than 0");
+        if !(1..=MAX_CONNECTIONS).contains(&self.max_concurrent_downloads) {
+            issues.push(ConfigIssue::new(
+                "max_concurrent_downloads",
+                format!("must be between 1 and {}", MAX_CONNECTIONS),
+            ));
         }
-        if self.chunk_size == 0 {
-            anyhow::bail!("chunk_size must be greater than 0");
+        if !(1..=MAX_TASKS).contains(&self.max_concurrent_tasks) {
+            issues.push(ConfigIssue::new(
+                "max_concurrent_tasks",
+                format!("must be between 1 and {}", MAX_TASKS),
+            ));
         }
-        if self.timeout == 0 {
-            anyhow::bail!("timeout must be greater than 0");
+        if !CHUNK_SIZE_RANGE.contains(&self.chunk_size) {
+            issues.push(ConfigIssue::new(
+                "chunk_size",
+                format!(
+                    "must be between {} and {} bytes",
+                    CHUNK_SIZE_RANGE.start(),
+                    CHUNK_SIZE_RANGE.end()
+                ),
+            ));
         }
-        Ok(())
+        if !(1..=MAX_TIMEOUT).contains(&self.timeout) {
+            issues.push(ConfigIssue::new(
+                "timeout",
+                format!("must be between 1 and {} seconds", MAX_TIMEOUT),
+            ));
+        }
+        if !matches!(self.theme.as_str(), "light" | "dark" | "system") {
+            issues.push(ConfigIssue::new(
+                "theme",
+                "must be one of light, dark, system",
+            ));
+        }
+        if let Some(limit) = self.speed_limit
+            && !(1..=MAX_SPEED_LIMIT).contains(&limit)
+        {
+            issues.push(ConfigIssue::new(
+                "speed_limit",
+                format!(
+                    "must be between 1 and {} bytes/s, or null for unlimited",
+                    MAX_SPEED_LIMIT
+                ),
+            ));
+        }
+        if !EVENT_FLUSH_RANGE.contains(&self.event_flush_interval_ms) {
+            issues.push(ConfigIssue::new(
+                "event_flush_interval_ms",
+                format!(
+                    "must be between {} and {} milliseconds",
+                    EVENT_FLUSH_RANGE.start(),
+                    EVENT_FLUSH_RANGE.end()
+                ),
+            ));
+        }
+        if let Some(locale) = &self.locale
+            && !i18n::available_locales().contains(&i18n::normalize_locale(locale))
+        {
+            issues.push(ConfigIssue::new(
+                "locale",
+                format!("must be one of {}", i18n::available_locales().join(", ")),
+            ));
+        }
+        if let Some(dir) = &self.completed_link_dir
+            && !dir.is_dir()
+        {
+            issues.push(ConfigIssue::new(
+                "completed_link_dir",
+                "directory does not exist",
+            ));
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn valid_config() -> Value {
+        json!({
+            "default_download_path": std::env::temp_dir(),
+            "max_concurrent_downloads": 8,
+            "max_concurrent_tasks": 2,
+            "chunk_size": 1048576,
+            "timeout": 60,
+            "user_agent": "custom",
+            "theme": "dark",
+        })
+    }
+
+    fn fields(issues: &[ConfigIssue]) -> Vec<&str> {
+        issues.iter().map(|i| i.field.as_str()).collect()
+    }
+
+    #[test]
+    fn test_valid_config_loads_unchanged() {
+        let (config, issues) = AppConfig::from_value(valid_config());
+        assert!(issues.is_empty(), "{:?}", issues);
+        assert_eq!(config.max_concurrent_downloads, 8);
+        assert_eq!(config.user_agent, "custom");
+        assert_eq!(config.speed_limit, None);
+        // 旧配置文件中没有的字段使用默认值
+        assert_eq!(config.event_flush_interval_ms, 100);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_fields_fall_back_individually() {
+        let defaults = AppConfig::default();
+        let mut value = valid_config();
+        value["max_concurrent_downloads"] = json!(0);
+        value["max_concurrent_tasks"] = json!(-3);
+        value["timeout"] = json!("soon");
+        value["speed_limit"] = json!(u64::MAX);
+        value["default_download_path"] = json!("/nonexistent/yushi/downloads");
+        value["completed_link_dir"] = json!("/nonexistent/yushi/links");
+
+        let (config, issues) = AppConfig::from_value(value);
+        let mut corrected = fields(&issues);
+        corrected.sort();
+        assert_eq!(
+            corrected,
+            [
+                "completed_link_dir",
+                "default_download_path",
+                "max_concurrent_downloads",
+                "max_concurrent_tasks",
+                "speed_limit",
+                "timeout",
+            ]
+        );
+        assert_eq!(
+            config.max_concurrent_downloads,
+            defaults.max_concurrent_downloads
+        );
+        assert_eq!(config.max_concurrent_tasks, defaults.max_concurrent_tasks);
+        assert_eq!(config.timeout, defaults.timeout);
+        assert_eq!(config.speed_limit, None);
+        assert_eq!(config.default_download_path, defaults.default_download_path);
+        assert_eq!(config.completed_link_dir, None);
+        // 其他字段保持不变
+        assert_eq!(config.chunk_size, 1048576);
+        assert_eq!(config.user_agent, "custom");
+        assert_eq!(config.theme, "dark");
+
+        let (_, issues) = AppConfig::from_value(json!([1, 2]));
+        assert_eq!(fields(&issues), [""]);
+    }
+
+    #[test]
+    fn test_validate_reports_every_field() {
+        let config = AppConfig {
+            default_download_path: PathBuf::from("/nonexistent/yushi/downloads"),
+            max_concurrent_downloads: 0,
+            chunk_size: 0,
+            speed_limit: Some(0),
+            ..Default::default()
+        };
+        let issues = config.validate().unwrap_err();
+        assert_eq!(
+            fields(&issues),
+            [
+                "default_download_path",
+                "max_concurrent_downloads",
+                "chunk_size",
+                "speed_limit",
+            ]
+        );
+
+        let config = AppConfig {
+            default_download_path: std::env::temp_dir(),
+            speed_limit: Some(MAX_SPEED_LIMIT + 1),
+            event_flush_interval_ms: 1,
+            locale: Some("xx".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            fields(&config.validate().unwrap_err()),
+            ["speed_limit", "event_flush_interval_ms", "locale"]
+        );
     }
 }
@@ -0,0 +1,128 @@
+//! 主机认证信息
+//!
+//! 认证信息设置到下载队列中，只保存在内存里。启用 `keychain` 特性时同时保存到系统
+//! 钥匙串，启动时重新加载；未启用时重启后需要重新设置。
+
+use crate::AppState;
+use tauri::State;
+use yushi_core::Credential;
+
+/// 设置主机的认证信息，之后发往匹配主机的请求自动带上认证头
+#[tauri::command]
+pub fn set_host_credentials(
+    state: State<'_, AppState>,
+    host_pattern: String,
+    credential: Credential,
+) -> Result<(), String> {
+    keychain::save(&host_pattern, &credential)?;
+    state.queue.set_host_credentials(&host_pattern, credential);
+    Ok(())
+}
+
+/// 删除主机的认证信息，不存在时返回 false
+#[tauri::command]
+pub fn remove_host_credentials(
+    state: State<'_, AppState>,
+    host_pattern: String,
+) -> Result<bool, String> {
+    keychain::remove(&host_pattern)?;
+    Ok(state.queue.remove_host_credentials(&host_pattern))
+}
+
+/// 读取保存在钥匙串中的所有认证信息
+pub fn load_saved() -> Vec<(String, Credential)> {
+    keychain::load_all()
+}
+
+#[cfg(feature = "keychain")]
+mod keychain {
+    use keyring::Entry;
+    use yushi_core::Credential;
+
+    const SERVICE: &str = "com.tangxiangong.YuShi";
+    /// 钥匙串无法列出条目，单独保存所有主机模式的列表
+    const HOSTS_ENTRY: &str = "hosts";
+
+    fn entry(name: &str) -> Result<Entry, String> {
+        Entry::new(SERVICE, name).map_err(|e| e.to_string())
+    }
+
+    fn hosts() -> Vec<String> {
+        entry(HOSTS_ENTRY)
+            .and_then(|e| e.get_password().map_err(|e| e.to_string()))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn set_hosts(hosts: &[String]) -> Result<(), String> {
+        let json = serde_json::to_string(hosts).map_err(|e| e.to_string())?;
+        entry(HOSTS_ENTRY)?
+            .set_password(&json)
+            .map_err(|e| e.to_string())
+    }
+
+    /// 主机模式前加前缀，避免与主机列表的条目重名
+    fn host_entry(host_pattern: &str) -> Result<Entry, String> {
+        entry(&format!(
+            "host:{}",
+            host_pattern.trim().to_ascii_lowercase()
+        ))
+    }
+
+    pub fn save(host_pattern: &str, credential: &Credential) -> Result<(), String> {
+        let json = serde_json::to_string(credential).map_err(|e| e.to_string())?;
+        host_entry(host_pattern)?
+            .set_password(&json)
+            .map_err(|e| e.to_string())?;
+        let host = host_pattern.trim().to_ascii_lowercase();
+        let mut hosts = hosts();
+        if !hosts.contains(&host) {
+            hosts.push(host);
+            set_hosts(&hosts)?;
+        }
+        Ok(())
+    }
+
+    pub fn remove(host_pattern: &str) -> Result<(), String> {
+        match host_entry(host_pattern)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+        let host = host_pattern.trim().to_ascii_lowercase();
+        let mut hosts = hosts();
+        if hosts.contains(&host) {
+            hosts.retain(|h| h != &host);
+            set_hosts(&hosts)?;
+        }
+        Ok(())
+    }
+
+    pub fn load_all() -> Vec<(String, Credential)> {
+        hosts()
+            .into_iter()
+            .filter_map(|host| {
+                let json = host_entry(&host).ok()?.get_password().ok()?;
+                let credential = serde_json::from_str(&json).ok()?;
+                Some((host, credential))
+            })
+            .collect()
+    }
+}
+
+#[cfg(not(feature = "keychain"))]
+mod keychain {
+    use yushi_core::Credential;
+
+    pub fn save(_host_pattern: &str, _credential: &Credential) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn remove(_host_pattern: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn load_all() -> Vec<(String, Credential)> {
+        Vec::new()
+    }
+}
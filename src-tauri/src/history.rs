@@ -1,6 +1,10 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use yushi_core::{Provenance, TaskStatus};
 
 /// 已完成的下载任务记录
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,15 +21,41 @@ pub struct CompletedTask {
     pub completed_at: u64,
     /// 下载耗时（秒）
     pub duration: u64,
-    /// 平均速度（字节/秒）
+    /// 平均速度（字节/秒），不含暂停时间
     pub avg_speed: u64,
+    /// 最高速度（字节/秒）
+    #[serde(default)]
+    pub peak_speed: u64,
+    /// 任务的下载环境
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+    /// 任务最后一次结束时的状态
+    #[serde(default = "completed_status")]
+    pub status: TaskStatus,
+}
+
+fn completed_status() -> TaskStatus {
+    TaskStatus::Completed
+}
+
+/// 历史记录文件的格式
+#[derive(Serialize, Deserialize)]
+struct HistoryFile {
+    /// 记录列表，最新的在前面
+    completed_tasks: Vec<CompletedTask>,
+    max_history: usize,
 }
 
 /// 下载历史记录
+///
+/// 按任务 ID 保存，每个任务只有一条记录。
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "HistoryFile", into = "HistoryFile")]
 pub struct DownloadHistory {
-    /// 已完成的任务列表
-    pub completed_tasks: Vec<CompletedTask>,
+    /// 按任务 ID 索引的记录
+    records: HashMap<String, CompletedTask>,
+    /// 显示顺序，最新的在前面
+    order: Vec<String>,
     /// 最大历史记录数
     pub max_history: usize,
 }
@@ -33,49 +63,91 @@ pub struct DownloadHistory {
 impl Default for DownloadHistory {
     fn default() -> Self {
         Self {
-            completed_tasks: Vec::new(),
+            records: HashMap::new(),
+            order: Vec::new(),
             max_history: 100,
         }
     }
 }
 
+impl From<HistoryFile> for DownloadHistory {
+    /// 旧版本会为同一任务追加多条记录，只保留完成时间最晚的一条
+    fn from(file: HistoryFile) -> Self {
+        let mut history = Self {
+            max_history: file.max_history,
+            ..Default::default()
+        };
+        // 从最旧的开始依次写入，完成时间相同时保留列表中靠前（较新）的记录
+        for task in file.completed_tasks.into_iter().rev() {
+            let newer = history
+                .records
+                .get(&task.id)
+                .is_none_or(|old| task.completed_at >= old.completed_at);
+            if newer {
+                history.add_completed(task);
+            }
+        }
+        history
+    }
+}
+
+impl From<DownloadHistory> for HistoryFile {
+    fn from(history: DownloadHistory) -> Self {
+        Self {
+            completed_tasks: history.get_all(),
+            max_history: history.max_history,
+        }
+    }
+}
+
 impl DownloadHistory {
-    /// 添加已完成的任务到历史记录
+    /// 添加或更新任务的历史记录
+    ///
+    /// 任务已有记录时替换为新的记录（完成时间、状态等），并移到列表开头。
     pub fn add_completed(&mut self, task: CompletedTask) {
+        self.order.retain(|id| *id != task.id);
         // 添加到列表开头（最新的在前面）
-        self.completed_tasks.insert(0, task);
+        self.order.insert(0, task.id.clone());
+        self.records.insert(task.id.clone(), task);
 
         // 如果超过最大数量，删除最旧的记录
-        if self.completed_tasks.len() > self.max_history {
-            self.completed_tasks.truncate(self.max_history);
+        while self.order.len() > self.max_history {
+            if let Some(id) = self.order.pop() {
+                self.records.remove(&id);
+            }
         }
     }
 
     /// 清除所有历史记录
     pub fn clear(&mut self) {
-        self.completed_tasks.clear();
+        self.records.clear();
+        self.order.clear();
     }
 
     /// 删除指定的历史记录
     pub fn remove(&mut self, id: &str) -> bool {
-        if let Some(pos) = self.completed_tasks.iter().position(|t| t.id == id) {
-            self.completed_tasks.remove(pos);
-            true
-        } else {
-            false
-        }
+        self.order.retain(|i| i != id);
+        self.records.remove(id).is_some()
+    }
+
+    /// 获取指定任务的历史记录
+    pub fn get(&self, id: &str) -> Option<&CompletedTask> {
+        self.records.get(id)
     }
 
-    /// 获取所有历史记录
-    pub fn get_all(&self) -> &[CompletedTask] {
-        &self.completed_tasks
+    /// 获取所有历史记录，最新的在前面
+    pub fn get_all(&self) -> Vec<CompletedTask> {
+        self.iter().cloned().collect()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &CompletedTask> {
+        self.order.iter().filter_map(|id| self.records.get(id))
     }
 
     /// 搜索历史记录
     pub fn search(&self, query: &str) -> Vec<CompletedTask> {
         let query_lower = query.to_lowercase();
-        self.completed_tasks
-            .iter()
+        self.iter()
             .filter(|task| {
                 task.url.to_lowercase().contains(&query_lower)
                     || task
@@ -106,3 +178,81 @@ impl DownloadHistory {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, completed_at: u64) -> CompletedTask {
+        CompletedTask {
+            id: id.to_string(),
+            url: format!("https://example.com/{}.zip", id),
+            dest: PathBuf::from(format!("/downloads/{}.zip", id)),
+            total_size: 1024,
+            completed_at,
+            duration: 1,
+            avg_speed: 1024,
+            peak_speed: 2048,
+            provenance: None,
+            status: TaskStatus::Completed,
+        }
+    }
+
+    fn ids(history: &DownloadHistory) -> Vec<String> {
+        history.get_all().into_iter().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn test_add_completed_upserts() {
+        let mut history = DownloadHistory::default();
+        history.add_completed(record("a", 1));
+        history.add_completed(record("b", 2));
+        history.add_completed(CompletedTask {
+            status: TaskStatus::Failed,
+            ..record("a", 3)
+        });
+
+        assert_eq!(ids(&history), ["a", "b"]);
+        let a = history.get("a").unwrap();
+        assert_eq!(a.completed_at, 3);
+        assert_eq!(a.status, TaskStatus::Failed);
+
+        assert!(history.remove("a"));
+        assert!(!history.remove("a"));
+        assert_eq!(ids(&history), ["b"]);
+        assert_eq!(history.search("b.zip").len(), 1);
+        assert!(history.search("a.zip").is_empty());
+
+        history.max_history = 2;
+        for (i, id) in ["c", "d", "e"].into_iter().enumerate() {
+            history.add_completed(record(id, 10 + i as u64));
+        }
+        assert_eq!(ids(&history), ["e", "d"]);
+        assert!(history.get("c").is_none());
+    }
+
+    #[test]
+    fn test_migrate_duplicate_records() {
+        // 旧格式：最新的在前面，同一任务可能有多条记录，也没有 status 字段
+        let json = r#"{
+            "completed_tasks": [
+                {"id": "a", "url": "u", "dest": "/a", "total_size": 2, "completed_at": 30, "duration": 1, "avg_speed": 2},
+                {"id": "b", "url": "u", "dest": "/b", "total_size": 1, "completed_at": 20, "duration": 1, "avg_speed": 1},
+                {"id": "a", "url": "u", "dest": "/a", "total_size": 1, "completed_at": 10, "duration": 1, "avg_speed": 1}
+            ],
+            "max_history": 100
+        }"#;
+        let history: DownloadHistory = serde_json::from_str(json).unwrap();
+        assert_eq!(ids(&history), ["a", "b"]);
+        let a = history.get("a").unwrap();
+        assert_eq!(a.completed_at, 30);
+        assert_eq!(a.total_size, 2);
+        assert_eq!(a.status, TaskStatus::Completed);
+
+        // 保存后的格式不变，可以再次读取
+        let saved = serde_json::to_string(&history).unwrap();
+        let reloaded: DownloadHistory = serde_json::from_str(&saved).unwrap();
+        assert_eq!(ids(&reloaded), ["a", "b"]);
+        assert_eq!(reloaded.max_history, 100);
+    }
+}
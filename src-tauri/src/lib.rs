@@ -1,34 +1,337 @@
+mod aggregator;
+mod bridge;
 mod config;
+mod credentials;
 mod history;
+mod store;
 mod updater;
 
-use config::AppConfig;
+use aggregator::EventAggregator;
+use bridge::EventBridge;
+use config::{AppConfig, ConfigIssue};
 use history::{CompletedTask, DownloadHistory};
-use std::{path::PathBuf, sync::Arc};
-use tauri::{Emitter, Manager, State};
-use tokio::sync::RwLock;
-use yushi_core::{YuShi, types::DownloadTask};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use store::StoreHandle;
+use tauri::{Manager, RunEvent, State, webview::PageLoadEvent};
+use yushi_core::{
+    ChecksumType, CompactEvent, Config, DownloaderEvent, EventEnvelope, Forecast, GlobalEvent,
+    GroupProgress, OrphanReport, QueueStats, RemoveOptions, ScheduledSlot, TaskChanges,
+    TaskOptions, TaskPriority, TaskView, YuShi, filename_from_url, i18n, types::DownloadTask,
+};
 
 struct AppState {
     queue: Arc<YuShi>,
-    config: Arc<RwLock<AppConfig>>,
-    config_path: PathBuf,
-    history: Arc<RwLock<DownloadHistory>>,
-    history_path: PathBuf,
+    /// 配置和历史记录，所有读写都经过同一个后台任务
+    store: StoreHandle,
+    /// 启动时加载配置被恢复为默认值的字段
+    config_warnings: Vec<ConfigIssue>,
+    bridge: Arc<EventBridge>,
+    /// 下载进度事件的刷新间隔（毫秒），修改配置后立即生效
+    event_flush_ms: Arc<AtomicU64>,
+}
+
+/// `queue-heartbeat` 事件的间隔，事件内容为队列的汇总统计
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 退出时等待下载保存进度的最长时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `config-warnings` 事件的内容
+#[derive(Debug, Clone, Serialize)]
+struct ConfigWarnings {
+    issues: Vec<ConfigIssue>,
+}
+
+/// 窗口用来判断是否错过了事件的代次
+#[derive(Debug, Clone, Serialize)]
+struct StateGeneration {
+    /// 最近一次广播的事件序号
+    bridge_seq: u64,
+    /// 任务列表的代次，与 `get_tasks_snapshot` 返回的代次相同
+    tasks: u64,
 }
 
 #[tauri::command]
-async fn add_task(state: State<'_, AppState>, url: String, dest: String) -> Result<String, String> {
+async fn add_task(
+    state: State<'_, AppState>,
+    url: String,
+    dest: String,
+    display_name: Option<String>,
+    note: Option<String>,
+    checksum: Option<String>,
+    priority: Option<String>,
+) -> Result<String, String> {
+    // 算法由摘要长度决定
+    let checksum = checksum
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| ChecksumType::parse_auto(&s))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let priority = priority
+        .map(|p| p.parse::<TaskPriority>())
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let options = TaskOptions {
+        display_name: display_name.filter(|s| !s.is_empty()),
+        note: note.filter(|s| !s.is_empty()),
+        checksum,
+        priority: priority.unwrap_or_default(),
+        ..Default::default()
+    };
     state
         .queue
-        .add_task(url, PathBuf::from(dest))
+        .add_task_with(url, PathBuf::from(dest), options)
         .await
         .map_err(|e| e.to_string())
 }
 
+/// 添加只校验本地文件的任务，不下载
 #[tauri::command]
-async fn get_tasks(state: State<'_, AppState>) -> Result<Vec<DownloadTask>, String> {
-    Ok(state.queue.get_all_tasks().await)
+async fn add_verify_task(
+    state: State<'_, AppState>,
+    path: String,
+    checksum: ChecksumType,
+    priority: Option<TaskPriority>,
+) -> Result<String, String> {
+    state
+        .queue
+        .add_verify_task(PathBuf::from(path), checksum, priority.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 根据 URL 得到保存到 `dir` 中的完整路径，文件名经过清理，结果一定位于 `dir` 中
+#[tauri::command]
+fn resolve_destination(dir: String, url: String) -> String {
+    let name = filename_from_url(&url).unwrap_or_default();
+    yushi_core::resolve_destination(Path::new(&dir), &name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// 重新校验所有已完成且设置了校验和的任务，返回 (任务 ID, 是否通过)
+#[tauri::command]
+async fn verify_all(state: State<'_, AppState>) -> Result<Vec<(String, bool)>, String> {
+    state
+        .queue
+        .verify_all(|_| true)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 扫描默认下载目录和所有任务所在目录中的孤立文件
+#[tauri::command]
+async fn scan_orphans(state: State<'_, AppState>) -> Result<Vec<OrphanReport>, String> {
+    let mut dirs = vec![state.store.config().await?.default_download_path];
+    for task in state.queue.get_all_tasks().await {
+        if let Some(parent) = task.dest.parent() {
+            dirs.push(parent.to_path_buf());
+        }
+    }
+    dirs.sort();
+    dirs.dedup();
+    Ok(state.queue.scan_orphans(&dirs).await)
+}
+
+/// 删除扫描到的孤立文件，返回释放的字节数
+#[tauri::command]
+async fn clean_orphans(
+    state: State<'_, AppState>,
+    reports: Vec<OrphanReport>,
+) -> Result<u64, String> {
+    state
+        .queue
+        .clean_orphans(&reports, true)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 修改任务的显示名称和备注，空字符串表示清除
+#[tauri::command]
+async fn update_task_info(
+    state: State<'_, AppState>,
+    id: String,
+    display_name: Option<String>,
+    note: Option<String>,
+) -> Result<(), String> {
+    state
+        .queue
+        .set_task_display_name(&id, display_name.filter(|s| !s.is_empty()))
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .queue
+        .set_task_note(&id, note.filter(|s| !s.is_empty()))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 所有任务，`include_archived` 为 true 时包括已移除（归档）的任务
+#[tauri::command]
+async fn get_tasks(
+    state: State<'_, AppState>,
+    include_archived: Option<bool>,
+) -> Result<Vec<DownloadTask>, String> {
+    Ok(state
+        .queue
+        .get_tasks_filtered(include_archived.unwrap_or(false))
+        .await)
+}
+
+/// 任务最近的事件记录，任务详情中显示为时间线
+#[tauri::command]
+async fn get_task_events(
+    state: State<'_, AppState>,
+    task_id: String,
+) -> Result<Vec<CompactEvent>, String> {
+    state
+        .queue
+        .get_task_events(&task_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 任务列表的精简视图
+#[tauri::command]
+async fn get_task_views(state: State<'_, AppState>) -> Result<Vec<TaskView>, String> {
+    let tasks = state.queue.get_all_tasks().await;
+    Ok(tasks.iter().map(|t| t.to_view()).collect())
+}
+
+/// 任务列表的完整视图和代次，之后应用 `tasks-changed` 事件中的增量
+#[tauri::command]
+async fn get_tasks_snapshot(state: State<'_, AppState>) -> Result<(u64, Vec<TaskView>), String> {
+    Ok(state.queue.get_tasks_snapshot().await)
+}
+
+/// 错过 `tasks-changed` 事件时补齐，返回 None 时需要重新获取快照
+#[tauri::command]
+async fn get_changes_since(
+    state: State<'_, AppState>,
+    generation: u64,
+) -> Result<Option<TaskChanges>, String> {
+    Ok(state.queue.get_changes_since(generation))
+}
+
+/// 当前的事件序号和任务列表代次，与窗口记录的不同时需要重新获取快照
+#[tauri::command]
+fn get_state_generation(state: State<'_, AppState>) -> StateGeneration {
+    StateGeneration {
+        bridge_seq: state.bridge.seq(),
+        tasks: state.queue.tasks_generation(),
+    }
+}
+
+/// 按任务组分组的任务列表
+#[derive(Debug, Clone, Serialize)]
+struct GroupedTasks {
+    /// 任务组及其成员
+    groups: Vec<TaskGroupEntry>,
+    /// 不属于任何任务组的任务
+    ungrouped: Vec<DownloadTask>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TaskGroupEntry {
+    progress: GroupProgress,
+    tasks: Vec<DownloadTask>,
+}
+
+#[tauri::command]
+async fn get_grouped_tasks(state: State<'_, AppState>) -> Result<GroupedTasks, String> {
+    let tasks = state.queue.get_all_tasks().await;
+    let mut groups = state.queue.get_groups().await;
+    groups.sort_by_key(|g| g.created_at);
+
+    let mut entries = Vec::new();
+    for group in groups {
+        if let Some(progress) = state.queue.get_group_progress(&group.id).await {
+            let members = tasks
+                .iter()
+                .filter(|t| t.group_id.as_deref() == Some(group.id.as_str()))
+                .cloned()
+                .collect();
+            entries.push(TaskGroupEntry {
+                progress,
+                tasks: members,
+            });
+        }
+    }
+
+    Ok(GroupedTasks {
+        groups: entries,
+        ungrouped: tasks.into_iter().filter(|t| t.group_id.is_none()).collect(),
+    })
+}
+
+#[tauri::command]
+async fn pause_group(state: State<'_, AppState>, id: String) -> Result<(), String> {
+    state
+        .queue
+        .pause_group(&id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn resume_group(state: State<'_, AppState>, id: String) -> Result<(), String> {
+    state
+        .queue
+        .resume_group(&id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cancel_group(state: State<'_, AppState>, id: String) -> Result<(), String> {
+    state
+        .queue
+        .cancel_group(&id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_stats(state: State<'_, AppState>) -> Result<QueueStats, String> {
+    Ok(state.queue.get_stats())
+}
+
+/// 等待中和暂停的任务需要的磁盘空间预估，`dest_root` 指定时只统计该目录下的任务
+#[tauri::command]
+async fn get_forecast(
+    state: State<'_, AppState>,
+    dest_root: Option<String>,
+) -> Result<Forecast, String> {
+    let root = dest_root.map(PathBuf::from);
+    Ok(state.queue.forecast(root.as_deref()).await)
+}
+
+/// 模拟调度，预计等待中的任务的开始和完成时间，用于时间线视图
+#[tauri::command]
+async fn simulate_schedule(
+    state: State<'_, AppState>,
+    assumed_speed: Option<u64>,
+) -> Result<Vec<ScheduledSlot>, String> {
+    Ok(state.queue.simulate_schedule(assumed_speed).await)
+}
+
+#[tauri::command]
+async fn pause_queue(state: State<'_, AppState>) -> Result<(), String> {
+    state.queue.pause_queue().await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn resume_queue(state: State<'_, AppState>) -> Result<(), String> {
+    state.queue.resume_queue().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -36,6 +339,16 @@ async fn pause_task(state: State<'_, AppState>, id: String) -> Result<(), String
     state.queue.pause_task(&id).await.map_err(|e| e.to_string())
 }
 
+/// 临时解除任务的速度限制，返回加速的截止时间（Unix 毫秒）
+#[tauri::command]
+async fn boost_task(state: State<'_, AppState>, id: String, seconds: u64) -> Result<u64, String> {
+    state
+        .queue
+        .boost_task(&id, Duration::from_secs(seconds))
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn resume_task(state: State<'_, AppState>, id: String) -> Result<(), String> {
     state
@@ -45,6 +358,15 @@ async fn resume_task(state: State<'_, AppState>, id: String) -> Result<(), Strin
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+async fn retry_task(state: State<'_, AppState>, id: String, fresh: bool) -> Result<(), String> {
+    state
+        .queue
+        .retry_task(&id, fresh)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn cancel_task(state: State<'_, AppState>, id: String) -> Result<(), String> {
     state
@@ -55,68 +377,91 @@ async fn cancel_task(state: State<'_, AppState>, id: String) -> Result<(), Strin
 }
 
 #[tauri::command]
-async fn remove_task(state: State<'_, AppState>, id: String) -> Result<(), String> {
+async fn remove_task(
+    state: State<'_, AppState>,
+    id: String,
+    delete_file: Option<bool>,
+) -> Result<(), String> {
+    let options = RemoveOptions {
+        delete_file: delete_file.unwrap_or(false),
+    };
     state
         .queue
-        .remove_task(&id)
+        .remove_task_with_options(&id, options)
         .await
         .map_err(|e| e.to_string())
 }
 
+/// 删除任务记录，`remove_task` 只是归档
 #[tauri::command]
-async fn get_config(state: State<'_, AppState>) -> Result<AppConfig, String> {
-    let config = state.config.read().await;
-    Ok(config.clone())
+async fn purge_task(state: State<'_, AppState>, id: String) -> Result<(), String> {
+    state.queue.purge_task(&id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn update_config(state: State<'_, AppState>, new_config: AppConfig) -> Result<(), String> {
-    // 验证配置
-    new_config.validate().map_err(|e| e.to_string())?;
-
-    // 更新内存中的配置
-    let mut config = state.config.write().await;
-    *config = new_config.clone();
+async fn get_config(state: State<'_, AppState>) -> Result<AppConfig, String> {
+    state.store.config().await
+}
 
-    // 保存到文件
-    new_config
-        .save(&state.config_path)
-        .await
-        .map_err(|e| e.to_string())?;
+/// 启动时加载配置被恢复为默认值的字段，与 `config-warnings` 事件中的 `issues` 相同
+#[tauri::command]
+fn get_config_warnings(state: State<'_, AppState>) -> Vec<ConfigIssue> {
+    state.config_warnings.clone()
+}
 
+/// 更新配置，配置不合法时返回所有有问题的字段
+#[tauri::command]
+async fn update_config(
+    state: State<'_, AppState>,
+    new_config: AppConfig,
+) -> Result<(), Vec<ConfigIssue>> {
+    let speed_limit = new_config.speed_limit;
+    let event_flush_ms = new_config.event_flush_interval_ms;
+    let locale = new_config
+        .locale
+        .clone()
+        .unwrap_or_else(i18n::locale_from_env);
+    state.store.update_config(new_config).await?;
+    state.queue.set_global_speed_limit(speed_limit);
+    i18n::set_locale(&locale);
+    state
+        .event_flush_ms
+        .store(event_flush_ms, Ordering::Relaxed);
     Ok(())
 }
 
 #[tauri::command]
 async fn get_history(state: State<'_, AppState>) -> Result<Vec<CompletedTask>, String> {
-    let history = state.history.read().await;
-    Ok(history.get_all().to_vec())
+    state.store.history().await
 }
 
 #[tauri::command]
 async fn add_to_history(state: State<'_, AppState>, task: CompletedTask) -> Result<(), String> {
-    let mut history = state.history.write().await;
-    history.add_completed(task);
+    state.store.add_history(task).await
+}
 
-    // 保存到文件
-    history
-        .save(&state.history_path)
+#[tauri::command]
+async fn redownload_from_history(
+    state: State<'_, AppState>,
+    id: String,
+    fresh: bool,
+) -> Result<String, String> {
+    let record = state
+        .store
+        .history_record(id)
+        .await?
+        .ok_or_else(|| "History item not found".to_string())?;
+    let provenance = record.provenance.as_ref().filter(|_| !fresh);
+    state
+        .queue
+        .redownload(record.url.clone(), record.dest.clone(), provenance)
         .await
-        .map_err(|e| e.to_string())?;
-
-    Ok(())
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn remove_from_history(state: State<'_, AppState>, id: String) -> Result<(), String> {
-    let mut history = state.history.write().await;
-
-    if history.remove(&id) {
-        // 保存到文件
-        history
-            .save(&state.history_path)
-            .await
-            .map_err(|e| e.to_string())?;
+    if state.store.remove_history(id).await? {
         Ok(())
     } else {
         Err("History item not found".to_string())
@@ -125,16 +470,7 @@ async fn remove_from_history(state: State<'_, AppState>, id: String) -> Result<(
 
 #[tauri::command]
 async fn clear_history(state: State<'_, AppState>) -> Result<(), String> {
-    let mut history = state.history.write().await;
-    history.clear();
-
-    // 保存到文件
-    history
-        .save(&state.history_path)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    Ok(())
+    state.store.clear_history().await
 }
 
 #[tauri::command]
@@ -142,8 +478,30 @@ async fn search_history(
     state: State<'_, AppState>,
     query: String,
 ) -> Result<Vec<CompletedTask>, String> {
-    let history = state.history.read().await;
-    Ok(history.search(&query))
+    state.store.search_history(query).await
+}
+
+/// 把队列事件转发到所有窗口
+fn emit_download_event(events: &EventBridge, envelope: &EventEnvelope) {
+    match &envelope.event {
+        DownloaderEvent::Global(GlobalEvent::TasksChanged(changes)) => {
+            events.emit("tasks-changed", changes);
+        }
+        _ => events.emit("download-event", envelope),
+    }
+}
+
+/// 窗口重新加载后补发最近的事件，其间发出的事件都已错过
+fn replay_events(state: &AppState, label: &str) {
+    let mut rx = state.queue.subscribe_with_replay();
+    while let Ok(envelope) = rx.try_recv() {
+        if !envelope.replayed {
+            break;
+        }
+        state
+            .bridge
+            .emit_replayed(label, "download-event", &envelope);
+    }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -151,8 +509,18 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
+        .on_page_load(|webview, payload| {
+            if !matches!(payload.event(), PageLoadEvent::Finished) {
+                return;
+            }
+            if let Some(state) = webview.try_state::<AppState>()
+                && state.bridge.page_loaded(webview.label())
+            {
+                replay_events(&state, webview.label());
+            }
+        })
         .setup(|app| {
-            let app_handle = app.handle().clone();
+            let bridge = Arc::new(EventBridge::new(app.handle().clone()));
             let app_data_dir = app.path().app_data_dir().unwrap();
 
             // Ensure app data dir exists
@@ -164,10 +532,23 @@ pub fn run() {
             let config_path = app_data_dir.join("config.json");
             let history_path = app_data_dir.join("history.json");
 
-            // Load or create config
-            let config = tauri::async_runtime::block_on(async {
-                AppConfig::load(&config_path).await.unwrap_or_default()
+            // Load or create config; invalid fields fall back to their defaults
+            let (config, config_warnings) = tauri::async_runtime::block_on(async {
+                AppConfig::load(&config_path).await.unwrap_or_else(|e| {
+                    (
+                        AppConfig::default(),
+                        vec![ConfigIssue::new("", e.to_string())],
+                    )
+                })
             });
+            if !config_warnings.is_empty() {
+                bridge.emit(
+                    "config-warnings",
+                    &ConfigWarnings {
+                        issues: config_warnings.clone(),
+                    },
+                );
+            }
 
             // Save default config if it doesn't exist
             if !config_path.exists() {
@@ -182,14 +563,25 @@ pub fn run() {
             });
 
             // Initialize YuShi with queue functionality
-            let (queue, mut rx) = YuShi::new(
-                config.max_concurrent_downloads,
-                config.max_concurrent_tasks,
-                queue_path,
-            );
+            let core_config = Config {
+                max_concurrent: config.max_concurrent_downloads,
+                tasks_changed_interval: Some(Duration::from_millis(500)),
+                locale: config.locale.clone(),
+                completed_link_dir: config.completed_link_dir.clone(),
+                completed_link_copy: config.completed_link_copy,
+                ip_policy: config.ip_policy,
+                global_speed_limit: config.speed_limit,
+                ..Default::default()
+            };
+            let (queue, mut rx) =
+                YuShi::with_config(core_config, config.max_concurrent_tasks, queue_path)?;
+            for (host_pattern, credential) in credentials::load_saved() {
+                queue.set_host_credentials(&host_pattern, credential);
+            }
             let queue = Arc::new(queue);
-            let config = Arc::new(RwLock::new(config));
-            let history = Arc::new(RwLock::new(history));
+            let event_flush_ms = Arc::new(AtomicU64::new(config.event_flush_interval_ms));
+            let (store, actor) = StoreHandle::new(config, config_path, history, history_path);
+            tauri::async_runtime::spawn(actor.run());
 
             // Load existing tasks
             let queue_clone = queue.clone();
@@ -197,40 +589,103 @@ pub fn run() {
                 let _ = queue_clone.load_queue_from_state().await;
             });
 
-            // Spawn event listener
+            // Spawn event listener; progress is coalesced per task, everything else is
+            // forwarded immediately, and events go to every window through the bridge
+            let events = bridge.clone();
+            let stats_queue = queue.clone();
+            let flush_ms = event_flush_ms.clone();
             tauri::async_runtime::spawn(async move {
-                while let Some(event) = rx.recv().await {
-                    let _ = app_handle.emit("download-event", event);
+                let interval = |ms: &AtomicU64| Duration::from_millis(ms.load(Ordering::Relaxed));
+                let mut aggregator = EventAggregator::new(interval(&flush_ms), Instant::now());
+                let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+                heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    aggregator.set_interval(interval(&flush_ms));
+                    let flush_at = aggregator.next_flush();
+                    let ready = tokio::select! {
+                        envelope = rx.recv() => match envelope {
+                            Some(envelope) => aggregator.push(envelope, Instant::now()),
+                            None => break,
+                        },
+                        _ = tokio::time::sleep_until(
+                            flush_at.map_or_else(tokio::time::Instant::now, Into::into),
+                        ), if flush_at.is_some() => aggregator.flush(Instant::now()),
+                        _ = heartbeat.tick() => {
+                            events.emit("queue-heartbeat", &stats_queue.get_stats());
+                            continue;
+                        }
+                    };
+                    for envelope in ready {
+                        emit_download_event(&events, &envelope);
+                    }
+                }
+                for envelope in aggregator.flush(Instant::now()) {
+                    emit_download_event(&events, &envelope);
                 }
             });
 
             app.manage(AppState {
                 queue,
-                config,
-                config_path,
-                history,
-                history_path,
+                store,
+                config_warnings,
+                bridge,
+                event_flush_ms,
             });
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             add_task,
+            add_verify_task,
+            resolve_destination,
+            update_task_info,
+            verify_all,
+            scan_orphans,
+            clean_orphans,
             get_tasks,
+            get_task_events,
+            get_task_views,
+            get_tasks_snapshot,
+            get_changes_since,
+            get_state_generation,
+            get_grouped_tasks,
             pause_task,
             resume_task,
+            boost_task,
+            retry_task,
             cancel_task,
             remove_task,
+            purge_task,
+            pause_group,
+            resume_group,
+            cancel_group,
+            get_stats,
+            get_forecast,
+            simulate_schedule,
+            pause_queue,
+            resume_queue,
             get_config,
+            get_config_warnings,
             update_config,
             get_history,
             add_to_history,
+            redownload_from_history,
             remove_from_history,
             clear_history,
             search_history,
+            credentials::set_host_credentials,
+            credentials::remove_host_credentials,
             updater::check_for_updates,
             updater::download_and_install_update
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // 退出前停止下载并保存进度，下次启动后继续
+            if let RunEvent::Exit = event
+                && let Some(state) = app.try_state::<AppState>()
+            {
+                let _ = tauri::async_runtime::block_on(state.queue.shutdown(SHUTDOWN_TIMEOUT));
+            }
+        });
 }
@@ -0,0 +1,266 @@
+//! 历史记录和配置的存储
+//!
+//! 多个窗口可能同时调用读写历史记录或配置的命令。所有请求都发送给同一个后台任务
+//! 依次执行，修改内存中的数据和保存文件不会交错。
+
+use crate::{
+    config::{AppConfig, ConfigIssue},
+    history::{CompletedTask, DownloadHistory},
+};
+use std::path::PathBuf;
+use tokio::sync::{mpsc, oneshot};
+
+/// 后台任务已经退出时返回的错误
+const STORE_CLOSED: &str = "store is closed";
+
+enum Request {
+    GetConfig(oneshot::Sender<AppConfig>),
+    UpdateConfig(AppConfig, oneshot::Sender<Result<(), Vec<ConfigIssue>>>),
+    GetHistory(oneshot::Sender<Vec<CompletedTask>>),
+    GetHistoryRecord(String, oneshot::Sender<Option<CompletedTask>>),
+    SearchHistory(String, oneshot::Sender<Vec<CompletedTask>>),
+    AddHistory(CompletedTask, oneshot::Sender<Result<(), String>>),
+    RemoveHistory(String, oneshot::Sender<Result<bool, String>>),
+    ClearHistory(oneshot::Sender<Result<(), String>>),
+}
+
+/// 持有配置和历史记录的后台任务
+pub struct StoreActor {
+    config: AppConfig,
+    config_path: PathBuf,
+    history: DownloadHistory,
+    history_path: PathBuf,
+    rx: mpsc::Receiver<Request>,
+}
+
+/// 向 [`StoreActor`] 发送请求，可以在多个命令之间共享
+#[derive(Clone)]
+pub struct StoreHandle {
+    tx: mpsc::Sender<Request>,
+}
+
+impl StoreHandle {
+    /// 创建存储，返回的 [`StoreActor::run`] 需要由调用方放到异步运行时中执行
+    pub fn new(
+        config: AppConfig,
+        config_path: PathBuf,
+        history: DownloadHistory,
+        history_path: PathBuf,
+    ) -> (Self, StoreActor) {
+        let (tx, rx) = mpsc::channel(64);
+        let actor = StoreActor {
+            config,
+            config_path,
+            history,
+            history_path,
+            rx,
+        };
+        (Self { tx }, actor)
+    }
+
+    async fn request<T>(
+        &self,
+        make: impl FnOnce(oneshot::Sender<T>) -> Request,
+    ) -> Result<T, String> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(make(tx))
+            .await
+            .map_err(|_| STORE_CLOSED.to_string())?;
+        rx.await.map_err(|_| STORE_CLOSED.to_string())
+    }
+
+    pub async fn config(&self) -> Result<AppConfig, String> {
+        self.request(Request::GetConfig).await
+    }
+
+    /// 验证并保存配置，返回所有有问题的字段
+    pub async fn update_config(&self, config: AppConfig) -> Result<(), Vec<ConfigIssue>> {
+        self.request(|tx| Request::UpdateConfig(config, tx))
+            .await
+            .map_err(|e| vec![ConfigIssue::new("", e)])?
+    }
+
+    pub async fn history(&self) -> Result<Vec<CompletedTask>, String> {
+        self.request(Request::GetHistory).await
+    }
+
+    pub async fn history_record(&self, id: String) -> Result<Option<CompletedTask>, String> {
+        self.request(|tx| Request::GetHistoryRecord(id, tx)).await
+    }
+
+    pub async fn search_history(&self, query: String) -> Result<Vec<CompletedTask>, String> {
+        self.request(|tx| Request::SearchHistory(query, tx)).await
+    }
+
+    /// 添加或更新任务的历史记录并保存
+    pub async fn add_history(&self, task: CompletedTask) -> Result<(), String> {
+        self.request(|tx| Request::AddHistory(task, tx)).await?
+    }
+
+    /// 删除历史记录并保存，记录不存在时返回 false
+    pub async fn remove_history(&self, id: String) -> Result<bool, String> {
+        self.request(|tx| Request::RemoveHistory(id, tx)).await?
+    }
+
+    pub async fn clear_history(&self) -> Result<(), String> {
+        self.request(Request::ClearHistory).await?
+    }
+}
+
+impl StoreActor {
+    /// 依次处理请求，所有 [`StoreHandle`] 都被丢弃后退出
+    pub async fn run(mut self) {
+        while let Some(request) = self.rx.recv().await {
+            match request {
+                Request::GetConfig(reply) => {
+                    let _ = reply.send(self.config.clone());
+                }
+                Request::UpdateConfig(config, reply) => {
+                    let _ = reply.send(self.update_config(config).await);
+                }
+                Request::GetHistory(reply) => {
+                    let _ = reply.send(self.history.get_all());
+                }
+                Request::GetHistoryRecord(id, reply) => {
+                    let _ = reply.send(self.history.get(&id).cloned());
+                }
+                Request::SearchHistory(query, reply) => {
+                    let _ = reply.send(self.history.search(&query));
+                }
+                Request::AddHistory(task, reply) => {
+                    self.history.add_completed(task);
+                    let _ = reply.send(self.save_history().await);
+                }
+                Request::RemoveHistory(id, reply) => {
+                    let result = match self.history.remove(&id) {
+                        true => self.save_history().await.map(|_| true),
+                        false => Ok(false),
+                    };
+                    let _ = reply.send(result);
+                }
+                Request::ClearHistory(reply) => {
+                    self.history.clear();
+                    let _ = reply.send(self.save_history().await);
+                }
+            }
+        }
+    }
+
+    async fn update_config(&mut self, config: AppConfig) -> Result<(), Vec<ConfigIssue>> {
+        config.validate()?;
+        config
+            .save(&self.config_path)
+            .await
+            .map_err(|e| vec![ConfigIssue::new("", e.to_string())])?;
+        self.config = config;
+        Ok(())
+    }
+
+    async fn save_history(&self) -> Result<(), String> {
+        self.history
+            .save(&self.history_path)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: String, completed_at: u64) -> CompletedTask {
+        CompletedTask {
+            url: format!("https://example.com/{}", id),
+            dest: PathBuf::from(format!("/downloads/{}", id)),
+            id,
+            total_size: 1,
+            completed_at,
+            duration: 1,
+            avg_speed: 1,
+            peak_speed: 1,
+            provenance: None,
+            status: yushi_core::TaskStatus::Completed,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_command_streams() {
+        let dir = std::env::temp_dir().join(format!("yushi-store-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.json");
+        let history_path = dir.join("history.json");
+        let config = AppConfig {
+            default_download_path: dir.clone(),
+            ..Default::default()
+        };
+        let (store, actor) = StoreHandle::new(
+            config.clone(),
+            config_path.clone(),
+            DownloadHistory::default(),
+            history_path.clone(),
+        );
+        tokio::spawn(actor.run());
+
+        // 模拟两个窗口同时添加历史记录和修改配置，部分任务两边都会记录
+        let streams = ["main", "drop-zone"].map(|window| {
+            let store = store.clone();
+            let config = config.clone();
+            tokio::spawn(async move {
+                for i in 0..30u64 {
+                    store
+                        .add_history(record(format!("{}-{}", window, i), i))
+                        .await
+                        .unwrap();
+                    store
+                        .add_history(record(format!("shared-{}", i % 10), i))
+                        .await
+                        .unwrap();
+                    let tasks = (i % 5 + 1) as usize;
+                    store
+                        .update_config(AppConfig {
+                            max_concurrent_tasks: tasks,
+                            ..config.clone()
+                        })
+                        .await
+                        .unwrap();
+                }
+            })
+        });
+        for stream in streams {
+            stream.await.unwrap();
+        }
+
+        let history = store.history().await.unwrap();
+        let mut ids: Vec<_> = history.iter().map(|t| t.id.clone()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), history.len());
+        assert_eq!(history.len(), 70);
+
+        // 文件中的内容与内存一致
+        let saved = DownloadHistory::load(&history_path).await.unwrap();
+        assert_eq!(saved.get_all().len(), 70);
+        let (saved_config, issues) = AppConfig::load(&config_path).await.unwrap();
+        assert!(issues.is_empty());
+        assert_eq!(
+            saved_config.max_concurrent_tasks,
+            store.config().await.unwrap().max_concurrent_tasks
+        );
+
+        // 不合法的配置不会写入
+        let invalid = AppConfig {
+            max_concurrent_tasks: 0,
+            ..config.clone()
+        };
+        let issues = store.update_config(invalid).await.unwrap_err();
+        assert_eq!(issues[0].field, "max_concurrent_tasks");
+
+        assert!(store.remove_history("shared-0".to_string()).await.unwrap());
+        assert!(!store.remove_history("shared-0".to_string()).await.unwrap());
+        store.clear_history().await.unwrap();
+        assert!(store.history().await.unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,4 +1,4 @@
-import { useEffect, useState } from "react";
+import { useEffect, useRef, useState } from "react";
 import { listen } from "@tauri-apps/api/event";
 import { Layout } from "./components/Layout.tsx";
 import { TaskItem } from "./components/TaskItem.tsx";
@@ -6,8 +6,21 @@ import { AddTaskModal } from "./components/AddTaskModal.tsx";
 import { SettingsModal } from "./components/SettingsModal.tsx";
 import { HistoryModal } from "./components/HistoryModal.tsx";
 import { UpdateModal } from "./components/UpdateModal.tsx";
-import { DownloadTask, QueueEvent } from "./types.ts";
-import { getConfig, getTasks } from "./commands.ts";
+import { CleanupModal } from "./components/CleanupModal.tsx";
+import {
+  Broadcast,
+  ConfigIssue,
+  DownloadTask,
+  QueueEvent,
+  QueueStats,
+  TaskChanges,
+} from "./types.ts";
+import {
+  getConfig,
+  getConfigWarnings,
+  getStateGeneration,
+  getTasks,
+} from "./commands.ts";
 import { Inbox, Plus } from "lucide-react";
 
 function App() {
@@ -17,13 +30,35 @@ function App() {
   const [isSettingsOpen, setIsSettingsOpen] = useState(false);
   const [isHistoryOpen, setIsHistoryOpen] = useState(false);
   const [isUpdateOpen, setIsUpdateOpen] = useState(false);
+  const [isCleanupOpen, setIsCleanupOpen] = useState(false);
+
+  // Sequence number of the last broadcast event this window has seen
+  const lastSeq = useRef(0);
 
   const fetchTasks = () => {
     getTasks().then(setTasks).catch(console.error);
   };
 
+  // Resync when broadcast events were missed, e.g. while this window was hidden
+  const trackSeq = (seq: number) => {
+    const missed = seq !== lastSeq.current + 1;
+    lastSeq.current = Math.max(lastSeq.current, seq);
+    if (missed) fetchTasks();
+  };
+
+  const checkGeneration = () => {
+    getStateGeneration().then((generation) => {
+      if (generation.bridge_seq !== lastSeq.current) {
+        lastSeq.current = generation.bridge_seq;
+        fetchTasks();
+      }
+    }).catch(console.error);
+  };
+
   useEffect(() => {
     fetchTasks();
+    checkGeneration();
+    globalThis.addEventListener("focus", checkGeneration);
 
     // Load theme from backend config
     getConfig().then((config) => {
@@ -50,9 +85,20 @@ function App() {
       root.setAttribute("data-theme", systemTheme);
     });
 
+    // Report configuration fields that were reset to their defaults
+    const showConfigWarnings = (issues: ConfigIssue[]) => {
+      if (issues.length === 0) return;
+      const lines = issues.map((issue) =>
+        issue.field ? `${issue.field}: ${issue.message}` : issue.message
+      );
+      alert("以下配置无效，已恢复默认值:\n" + lines.join("\n"));
+    };
+    getConfigWarnings().then(showConfigWarnings).catch(console.error);
+
     // Listen for events
-    const unlisten = listen<QueueEvent>("download-event", (event) => {
+    const unlisten = listen<Broadcast<QueueEvent>>("download-event", (event) => {
       const data = event.payload;
+      if (!data.replayed) trackSeq(data.bridge_seq);
 
       if (data.type === "TaskAdded") {
         fetchTasks();
@@ -79,6 +125,7 @@ function App() {
             task.total_size = data.payload.total;
             task.speed = data.payload.speed;
             task.eta = data.payload.eta;
+            task.contiguous_prefix = data.payload.contiguous_prefix;
             task.status = "Downloading";
             break;
           case "TaskStarted":
@@ -100,9 +147,26 @@ function App() {
             task.error = data.payload.error;
             task.speed = 0;
             break;
+          case "TaskPartiallyCompleted":
+            task.status = "PartiallyCompleted";
+            task.missing_ranges = data.payload.missing_ranges;
+            task.speed = 0;
+            task.eta = undefined;
+            break;
           case "TaskCancelled":
             task.status = "Cancelled";
             break;
+          case "VerifyStarted":
+            task.status = "Verifying";
+            task.verified = 0;
+            task.speed = 0;
+            task.eta = undefined;
+            break;
+          case "VerifyProgress":
+            task.status = "Verifying";
+            task.verified = data.payload.verified;
+            task.total_size = data.payload.total;
+            break;
         }
 
         newTasks[taskIndex] = task;
@@ -110,15 +174,54 @@ function App() {
       });
     });
 
+    // Task list diffs arrive periodically alongside the coalesced progress events
+    const unlistenChanges = listen<Broadcast<TaskChanges>>(
+      "tasks-changed",
+      (event) => {
+        trackSeq(event.payload.bridge_seq);
+        const { added, updated, removed } = event.payload;
+        if (added.length > 0 || removed.length > 0) {
+          fetchTasks();
+          return;
+        }
+
+        setTasks((prevTasks) =>
+          prevTasks.map((task) => {
+            const view = updated.find((v) => v.id === task.id);
+            if (!view) return task;
+            return {
+              ...task,
+              status: view.status,
+              speed: view.speed,
+              eta: view.eta,
+              downloaded: view.progress != null && view.status !== "Verifying"
+                ? Math.round(task.total_size * view.progress / 100)
+                : task.downloaded,
+            };
+          })
+        );
+      },
+    );
+
+    // Aggregate queue stats, sent about once per second
+    const unlistenHeartbeat = listen<Broadcast<QueueStats>>(
+      "queue-heartbeat",
+      (event) => trackSeq(event.payload.bridge_seq),
+    );
+
     return () => {
+      globalThis.removeEventListener("focus", checkGeneration);
       unlisten.then((f) => f());
+      unlistenChanges.then((f) => f());
+      unlistenHeartbeat.then((f) => f());
     };
   }, []);
 
   // Filter tasks
   const filteredTasks = tasks.filter((task) => {
     if (activeTab === "downloading") {
-      return task.status === "Downloading" || task.status === "Pending";
+      return task.status === "Downloading" || task.status === "Verifying" ||
+        task.status === "Pending";
     }
     if (activeTab === "completed") {
       return task.status === "Completed";
@@ -128,8 +231,11 @@ function App() {
 
   // Sort: Downloading first, then by created_at desc
   filteredTasks.sort((a, b) => {
-    const aActive = a.status === "Downloading" || a.status === "Pending";
-    const bActive = b.status === "Downloading" || b.status === "Pending";
+    const isActive = (t: DownloadTask) =>
+      t.status === "Downloading" || t.status === "Verifying" ||
+      t.status === "Pending";
+    const aActive = isActive(a);
+    const bActive = isActive(b);
 
     if (aActive && !bActive) return -1;
     if (!aActive && bActive) return 1;
@@ -195,6 +301,7 @@ function App() {
         isOpen={isSettingsOpen}
         onClose={() => setIsSettingsOpen(false)}
         onOpenUpdate={() => setIsUpdateOpen(true)}
+        onOpenCleanup={() => setIsCleanupOpen(true)}
       />
       <HistoryModal
         isOpen={isHistoryOpen}
@@ -204,6 +311,10 @@ function App() {
         isOpen={isUpdateOpen}
         onClose={() => setIsUpdateOpen(false)}
       />
+      <CleanupModal
+        isOpen={isCleanupOpen}
+        onClose={() => setIsCleanupOpen(false)}
+      />
     </Layout>
   );
 }
@@ -1,8 +1,21 @@
 import { invoke } from "@tauri-apps/api/core";
 import type {
   AppConfig,
+  ChecksumType,
+  CompactEvent,
   CompletedTask,
+  ConfigIssue,
+  Credential,
   DownloadTask,
+  Forecast,
+  GroupedTasks,
+  OrphanReport,
+  Priority,
+  QueueStats,
+  ScheduledSlot,
+  StateGeneration,
+  TaskChanges,
+  TaskView,
   UpdateInfo,
 } from "./types.ts";
 
@@ -15,18 +28,220 @@ import type {
  * Add a new download task
  * @param url - The URL to download from
  * @param dest - The destination path to save the file
+ * @param displayName - Optional name shown instead of the file name
+ * @param note - Optional free-form note
+ * @param checksum - Optional hex digest; the algorithm is inferred from its length
+ * @param priority - Queue priority, defaults to `Normal`
  * @returns The task ID
  */
-export function addTask(url: string, dest: string): Promise<string> {
-  return invoke<string>("add_task", { url, dest });
+export function addTask(
+  url: string,
+  dest: string,
+  displayName?: string,
+  note?: string,
+  checksum?: string,
+  priority?: Priority,
+): Promise<string> {
+  return invoke<string>("add_task", {
+    url,
+    dest,
+    displayName,
+    note,
+    checksum,
+    priority,
+  });
+}
+
+/**
+ * Add a task that only verifies an existing local file; it cannot be paused
+ * @param path - File to hash
+ * @param checksum - Expected checksum
+ * @param priority - Queue priority, defaults to `Normal`
+ * @returns The task ID
+ */
+export function addVerifyTask(
+  path: string,
+  checksum: ChecksumType,
+  priority?: Priority,
+): Promise<string> {
+  return invoke<string>("add_verify_task", { path, checksum, priority });
+}
+
+/**
+ * Build the destination path for a URL inside a directory
+ * @param dir - Directory chosen by the user
+ * @param url - Download URL; its file name is sanitized so the result stays inside `dir`
+ * @returns Full destination path
+ */
+export function resolveDestination(dir: string, url: string): Promise<string> {
+  return invoke<string>("resolve_destination", { dir, url });
+}
+
+/**
+ * Re-verify every completed task that has a checksum
+ * @returns Pairs of task ID and whether the file passed verification
+ */
+export function verifyAll(): Promise<[string, boolean][]> {
+  return invoke<[string, boolean][]>("verify_all");
+}
+
+/**
+ * Find leftover state files and partial downloads that no task references
+ * @returns Files that can be cleaned up
+ */
+export function scanOrphans(): Promise<OrphanReport[]> {
+  return invoke<OrphanReport[]>("scan_orphans");
+}
+
+/**
+ * Delete files previously returned by scanOrphans; files claimed by a task in the meantime are kept
+ * @param reports - The files to delete
+ * @returns Bytes reclaimed
+ */
+export function cleanOrphans(reports: OrphanReport[]): Promise<number> {
+  return invoke<number>("clean_orphans", { reports });
+}
+
+/**
+ * Update the display name and note of a task
+ * @param id - The task ID
+ * @param displayName - New display name; null or empty clears it
+ * @param note - New note; null or empty clears it
+ */
+export function updateTaskInfo(
+  id: string,
+  displayName: string | null,
+  note: string | null,
+): Promise<void> {
+  return invoke<void>("update_task_info", { id, displayName, note });
 }
 
 /**
  * Get all download tasks
+ * @param includeArchived - Also return tasks that were removed (archived)
  * @returns Array of all download tasks
  */
-export function getTasks(): Promise<DownloadTask[]> {
-  return invoke<DownloadTask[]>("get_tasks");
+export function getTasks(includeArchived = false): Promise<DownloadTask[]> {
+  return invoke<DownloadTask[]>("get_tasks", { includeArchived });
+}
+
+/**
+ * Get a task's recent event journal, for the task details timeline
+ * @param taskId - Task ID
+ * @returns Events, oldest first
+ */
+export function getTaskEvents(taskId: string): Promise<CompactEvent[]> {
+  return invoke<CompactEvent[]>("get_task_events", { taskId });
+}
+
+/**
+ * Get a compact view of all download tasks, for lists that don't need full records
+ * @returns Array of task views
+ */
+export function getTaskViews(): Promise<TaskView[]> {
+  return invoke<TaskView[]>("get_task_views");
+}
+
+/**
+ * Get all task views together with the generation they correspond to.
+ * Apply later `tasks-changed` events whose generation is greater.
+ * @returns Tuple of generation and task views sorted by ID
+ */
+export function getTasksSnapshot(): Promise<[number, TaskView[]]> {
+  return invoke<[number, TaskView[]]>("get_tasks_snapshot");
+}
+
+/**
+ * Get every change after a generation merged into one, for recovering missed events
+ * @param generation - Last generation that was applied
+ * @returns The merged changes, or null if a fresh snapshot is needed
+ */
+export function getChangesSince(
+  generation: number,
+): Promise<TaskChanges | null> {
+  return invoke<TaskChanges | null>("get_changes_since", { generation });
+}
+
+/**
+ * Estimate the disk space pending and paused tasks still need
+ * @param destRoot - Only count tasks saved under this directory
+ * @returns Needed and free space per destination volume
+ */
+export function getForecast(destRoot?: string): Promise<Forecast> {
+  return invoke<Forecast>("get_forecast", { destRoot });
+}
+
+/**
+ * Simulate the scheduler to estimate when pending tasks start and finish
+ * @param assumedSpeed - Total speed in bytes per second; defaults to the global
+ *   speed limit, then the current speed
+ * @returns One slot per pending task, in start order
+ */
+export function simulateSchedule(
+  assumedSpeed?: number,
+): Promise<ScheduledSlot[]> {
+  return invoke<ScheduledSlot[]>("simulate_schedule", { assumedSpeed });
+}
+
+/**
+ * Get the latest broadcast sequence number and task list generation
+ * @returns The current generation; if it differs from what the window has seen, resync the task list
+ */
+export function getStateGeneration(): Promise<StateGeneration> {
+  return invoke<StateGeneration>("get_state_generation");
+}
+
+/**
+ * Get all download tasks grouped by task group
+ * @returns Task groups with their members, plus ungrouped tasks
+ */
+export function getGroupedTasks(): Promise<GroupedTasks> {
+  return invoke<GroupedTasks>("get_grouped_tasks");
+}
+
+/**
+ * Pause all running tasks in a group
+ * @param id - The group ID to pause
+ */
+export function pauseGroup(id: string): Promise<void> {
+  return invoke<void>("pause_group", { id });
+}
+
+/**
+ * Resume all paused tasks in a group
+ * @param id - The group ID to resume
+ */
+export function resumeGroup(id: string): Promise<void> {
+  return invoke<void>("resume_group", { id });
+}
+
+/**
+ * Cancel all unfinished tasks in a group
+ * @param id - The group ID to cancel
+ */
+export function cancelGroup(id: string): Promise<void> {
+  return invoke<void>("cancel_group", { id });
+}
+
+/**
+ * Get queue statistics and the scheduler state
+ */
+export function getStats(): Promise<QueueStats> {
+  return invoke<QueueStats>("get_stats");
+}
+
+/**
+ * Stop starting pending tasks; running tasks are not affected
+ */
+export function pauseQueue(): Promise<void> {
+  return invoke<void>("pause_queue");
+}
+
+/**
+ * Resume a manually paused queue and start pending tasks
+ */
+export function resumeQueue(): Promise<void> {
+  return invoke<void>("resume_queue");
 }
 
 /**
@@ -45,6 +260,25 @@ export function resumeTask(id: string): Promise<void> {
   return invoke<void>("resume_task", { id });
 }
 
+/**
+ * Lift the speed limits of a task for a while; boosting again extends the boost
+ * @param id - The task ID to boost
+ * @param seconds - How long the boost lasts
+ * @returns When the boost ends, in Unix milliseconds
+ */
+export function boostTask(id: string, seconds: number): Promise<number> {
+  return invoke<number>("boost_task", { id, seconds });
+}
+
+/**
+ * Retry a failed or cancelled task
+ * @param id - The task ID to retry
+ * @param fresh - Ignore the recorded download environment and use current settings
+ */
+export function retryTask(id: string, fresh = false): Promise<void> {
+  return invoke<void>("retry_task", { id, fresh });
+}
+
 /**
  * Cancel a download task
  * @param id - The task ID to cancel
@@ -54,11 +288,20 @@ export function cancelTask(id: string): Promise<void> {
 }
 
 /**
- * Remove a download task from the queue
+ * Remove a download task from the list; the record is archived, not deleted
  * @param id - The task ID to remove
+ * @param deleteFile - Also delete the downloaded file and its completed link
  */
-export function removeTask(id: string): Promise<void> {
-  return invoke<void>("remove_task", { id });
+export function removeTask(id: string, deleteFile = false): Promise<void> {
+  return invoke<void>("remove_task", { id, deleteFile });
+}
+
+/**
+ * Permanently delete a task record; `removeTask` only archives it
+ * @param id - The task ID to delete
+ */
+export function purgeTask(id: string): Promise<void> {
+  return invoke<void>("purge_task", { id });
 }
 
 /**
@@ -72,11 +315,41 @@ export function getConfig(): Promise<AppConfig> {
 /**
  * Update application configuration
  * @param config - New configuration to apply
+ * @throws ConfigIssue[] listing every invalid field
  */
 export function updateConfig(config: AppConfig): Promise<void> {
   return invoke<void>("update_config", { newConfig: config });
 }
 
+/**
+ * Get the fields that were reset to defaults when the configuration was loaded
+ * @returns Same issues as the `issues` field of the `config-warnings` event
+ */
+export function getConfigWarnings(): Promise<ConfigIssue[]> {
+  return invoke<ConfigIssue[]>("get_config_warnings");
+}
+
+/**
+ * Set credentials for a host; matching requests send them automatically
+ * @param hostPattern - `example.com`, `example.com:8443` or `*.example.com`
+ * @param credential - Basic or Bearer credentials
+ */
+export function setHostCredentials(
+  hostPattern: string,
+  credential: Credential,
+): Promise<void> {
+  return invoke<void>("set_host_credentials", { hostPattern, credential });
+}
+
+/**
+ * Remove the credentials of a host
+ * @param hostPattern - Host pattern passed to setHostCredentials
+ * @returns False if no credentials were set for the pattern
+ */
+export function removeHostCredentials(hostPattern: string): Promise<boolean> {
+  return invoke<boolean>("remove_host_credentials", { hostPattern });
+}
+
 /**
  * Get download history
  * @returns Array of completed tasks
@@ -93,6 +366,19 @@ export function addToHistory(task: CompletedTask): Promise<void> {
   return invoke<void>("add_to_history", { task });
 }
 
+/**
+ * Queue a history item for download again
+ * @param id - History item ID
+ * @param fresh - Ignore the recorded download environment and use current settings
+ * @returns The new task ID
+ */
+export function redownloadFromHistory(
+  id: string,
+  fresh = false,
+): Promise<string> {
+  return invoke<string>("redownload_from_history", { id, fresh });
+}
+
 /**
  * Remove a task from history
  * @param id - History item ID to remove
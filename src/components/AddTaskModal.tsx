@@ -1,6 +1,17 @@
 import { useEffect, useRef, useState } from "react";
-import { Download, FolderOpen, Link2, Loader2 } from "lucide-react";
-import { addTask, getConfig } from "../commands.ts";
+import {
+  Download,
+  FolderOpen,
+  Link2,
+  Loader2,
+  ShieldCheck,
+} from "lucide-react";
+import {
+  addTask,
+  getConfig,
+  getForecast,
+  resolveDestination,
+} from "../commands.ts";
 
 interface AddTaskModalProps {
   isOpen: boolean;
@@ -10,6 +21,7 @@ interface AddTaskModalProps {
 export function AddTaskModal({ isOpen, onClose }: AddTaskModalProps) {
   const [url, setUrl] = useState("");
   const [dest, setDest] = useState("");
+  const [checksum, setChecksum] = useState("");
   const [loading, setLoading] = useState(false);
   const dialogRef = useRef<HTMLDivElement>(null);
 
@@ -17,6 +29,7 @@ export function AddTaskModal({ isOpen, onClose }: AddTaskModalProps) {
   useEffect(() => {
     if (isOpen) {
       setUrl("");
+      setChecksum("");
       // Load default download path from config
       getConfig()
         .then((config) => {
@@ -36,7 +49,21 @@ export function AddTaskModal({ isOpen, onClose }: AddTaskModalProps) {
 
     setLoading(true);
     try {
-      await addTask(url, dest);
+      // Queued tasks for this directory already exceed the free space
+      const forecast = await getForecast(dest);
+      if (
+        forecast.will_fit === false &&
+        !confirm("目标磁盘的剩余空间不足以完成已排队的任务，仍要添加吗?")
+      ) {
+        return;
+      }
+      await addTask(
+        url,
+        await resolveDestination(dest, url),
+        undefined,
+        undefined,
+        checksum.trim() || undefined,
+      );
       onClose();
     } catch (err) {
       console.error(err);
@@ -130,6 +157,28 @@ export function AddTaskModal({ isOpen, onClose }: AddTaskModalProps) {
             </label>
           </div>
 
+          {/* Checksum Input */}
+          <div className="form-control space-y-2">
+            <label className="label pb-1">
+              <span className="label-text font-semibold flex items-center gap-2">
+                <ShieldCheck className="w-4 h-4 text-primary" />
+                校验和（可选）
+              </span>
+            </label>
+            <input
+              type="text"
+              placeholder="MD5 / SHA-256 / SHA-512"
+              className="input input-bordered w-full h-11 text-sm font-mono focus:input-primary transition-all shadow-sm hover:shadow-md"
+              value={checksum}
+              onChange={(e) => setChecksum(e.target.value)}
+            />
+            <label className="label pt-1">
+              <span className="label-text-alt text-base-content/60">
+                根据长度自动识别算法，下载完成后校验
+              </span>
+            </label>
+          </div>
+
           {/* Action Buttons */}
           <div className="pt-4 flex items-center justify-end gap-3 border-t border-base-200">
             <button
@@ -0,0 +1,158 @@
+import { useEffect, useState } from "react";
+import { FileIcon, FileJson, Loader2, Trash2, X } from "lucide-react";
+import { cleanOrphans, scanOrphans } from "../commands.ts";
+import type { OrphanReport } from "../types.ts";
+import { formatBytes } from "../utils/format.ts";
+
+interface CleanupModalProps {
+  isOpen: boolean;
+  onClose: () => void;
+}
+
+export function CleanupModal({ isOpen, onClose }: CleanupModalProps) {
+  const [orphans, setOrphans] = useState<OrphanReport[]>([]);
+  const [loading, setLoading] = useState(false);
+  const [cleaning, setCleaning] = useState(false);
+
+  // Scan when modal opens
+  useEffect(() => {
+    if (isOpen) {
+      setLoading(true);
+      scanOrphans()
+        .then(setOrphans)
+        .catch((err) => console.error("Failed to scan orphans:", err))
+        .finally(() => setLoading(false));
+    }
+  }, [isOpen]);
+
+  const totalSize = orphans.reduce((sum, item) => sum + item.size, 0);
+
+  const handleClean = async () => {
+    if (!confirm(`确定要删除这 ${orphans.length} 个文件吗？`)) {
+      return;
+    }
+
+    setCleaning(true);
+    try {
+      const reclaimed = await cleanOrphans(orphans);
+      setOrphans([]);
+      alert(`已释放 ${formatBytes(reclaimed)}`);
+    } catch (err) {
+      console.error("Failed to clean orphans:", err);
+      alert("清理失败: " + err);
+    } finally {
+      setCleaning(false);
+    }
+  };
+
+  if (!isOpen) return null;
+
+  return (
+    <div className="fixed inset-0 z-50 flex items-center justify-center animate-in fade-in">
+      {/* Backdrop */}
+      <div
+        className="absolute inset-0 bg-black/30 backdrop-blur-md"
+        onClick={onClose}
+      />
+
+      {/* Modal Content */}
+      <div className="relative w-[720px] max-w-[90vw] max-h-[85vh] bg-base-100 rounded-2xl shadow-2xl overflow-hidden flex flex-col animate-in zoom-in-95 duration-200 border border-base-300/50">
+        {/* Header */}
+        <div className="px-6 py-4 border-b border-base-200 bg-linear-to-r from-primary/5 via-transparent to-secondary/5 flex items-center justify-between">
+          <div className="flex items-center gap-3">
+            <div className="p-2 bg-primary/10 rounded-lg">
+              <Trash2 className="w-5 h-5 text-primary" />
+            </div>
+            <h2 className="text-lg font-bold text-base-content">
+              清理残留文件
+            </h2>
+            <span className="badge badge-neutral">
+              {formatBytes(totalSize)}
+            </span>
+          </div>
+          <button
+            type="button"
+            onClick={onClose}
+            className="btn btn-sm btn-ghost btn-square"
+          >
+            <X className="w-5 h-5" />
+          </button>
+        </div>
+
+        {/* Orphan List */}
+        <div className="flex-1 overflow-y-auto p-6">
+          {loading
+            ? (
+              <div className="flex items-center justify-center py-12">
+                <span className="loading loading-spinner loading-lg text-primary">
+                </span>
+              </div>
+            )
+            : orphans.length === 0
+            ? (
+              <div className="flex flex-col items-center justify-center py-12 text-base-content/50">
+                <Trash2 className="w-16 h-16 mb-4 opacity-20" />
+                <p className="text-sm font-medium">没有找到残留文件</p>
+              </div>
+            )
+            : (
+              <div className="space-y-2">
+                {orphans.map((item) => (
+                  <div
+                    key={item.path}
+                    className="flex items-center gap-3 p-3 rounded-lg bg-base-200 border border-base-300"
+                  >
+                    {item.kind === "StateFile"
+                      ? <FileJson className="w-4 h-4 text-base-content/60" />
+                      : <FileIcon className="w-4 h-4 text-warning" />}
+                    <div className="flex-1 min-w-0">
+                      <p
+                        className="text-sm font-mono truncate"
+                        title={item.path}
+                      >
+                        {item.path}
+                      </p>
+                      <p
+                        className="text-xs text-base-content/60 truncate"
+                        title={item.url}
+                      >
+                        {item.kind === "StateFile" ? "续传状态" : "未完成的文件"}
+                        {" · "}
+                        {item.url}
+                      </p>
+                    </div>
+                    <span className="text-xs text-base-content/70">
+                      {formatBytes(item.size)}
+                    </span>
+                  </div>
+                ))}
+              </div>
+            )}
+        </div>
+
+        {/* Footer Actions */}
+        <div className="px-6 py-4 border-t border-base-200 flex justify-end gap-3">
+          <button
+            type="button"
+            onClick={onClose}
+            disabled={cleaning}
+            className="btn btn-ghost h-10 min-h-0 font-medium"
+          >
+            关闭
+          </button>
+          <button
+            type="button"
+            onClick={handleClean}
+            disabled={cleaning || orphans.length === 0}
+            className="btn btn-error h-10 min-h-0 font-semibold gap-2"
+          >
+            {cleaning
+              ? <Loader2 className="w-4 h-4 animate-spin" />
+              : <Trash2 className="w-4 h-4" />}
+            全部删除
+          </button>
+        </div>
+      </div>
+    </div>
+  );
+}
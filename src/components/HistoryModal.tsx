@@ -207,7 +207,12 @@ export function HistoryModal({ isOpen, onClose }: HistoryModalProps) {
                               <FileIcon className="w-3 h-3" />
                               {formatBytes(item.total_size)}
                             </span>
-                            <span className="flex items-center gap-1">
+                            <span
+                              className="flex items-center gap-1"
+                              title={item.peak_speed
+                                ? `最高 ${formatBytes(item.peak_speed)}/s`
+                                : undefined}
+                            >
                               <Gauge className="w-3 h-3" />
                               {formatBytes(item.avg_speed)}/s
                             </span>
@@ -5,26 +5,29 @@ import {
   Loader2,
   Monitor,
   Moon,
+  Network,
   Palette,
   RefreshCw,
   Settings2,
   Sparkles,
   Sun,
+  Trash2,
 } from "lucide-react";
 import { cn } from "../lib/utils.ts";
 import { getConfig, updateConfig } from "../commands.ts";
-import type { AppConfig } from "../types.ts";
+import type { AppConfig, ConfigIssue, IpPolicy } from "../types.ts";
 
 interface SettingsModalProps {
   isOpen: boolean;
   onClose: () => void;
   onOpenUpdate?: () => void;
+  onOpenCleanup?: () => void;
 }
 
 type Theme = "light" | "dark" | "system";
 
 export function SettingsModal(
-  { isOpen, onClose, onOpenUpdate }: SettingsModalProps,
+  { isOpen, onClose, onOpenUpdate, onOpenCleanup }: SettingsModalProps,
 ) {
   const [config, setConfig] = useState<AppConfig | null>(null);
   const [loading, setLoading] = useState(false);
@@ -34,6 +37,8 @@ export function SettingsModal(
   const [defaultPath, setDefaultPath] = useState("");
   const [maxTasks, setMaxTasks] = useState("3");
   const [maxDownloads, setMaxDownloads] = useState("4");
+  const [ipPolicy, setIpPolicy] = useState<IpPolicy>("Auto");
+  const [fieldErrors, setFieldErrors] = useState<Record<string, string>>({});
 
   // Load config when modal opens
   useEffect(() => {
@@ -46,6 +51,7 @@ export function SettingsModal(
           setDefaultPath(cfg.default_download_path);
           setMaxTasks(cfg.max_concurrent_tasks.toString());
           setMaxDownloads(cfg.max_concurrent_downloads.toString());
+          setIpPolicy(cfg.ip_policy ?? "Auto");
         })
         .catch((err) => {
           console.error("Failed to load config:", err);
@@ -76,6 +82,7 @@ export function SettingsModal(
     if (!config) return;
 
     setSaving(true);
+    setFieldErrors({});
     try {
       const newConfig: AppConfig = {
         ...config,
@@ -83,6 +90,7 @@ export function SettingsModal(
         default_download_path: defaultPath,
         max_concurrent_tasks: parseInt(maxTasks),
         max_concurrent_downloads: parseInt(maxDownloads),
+        ip_policy: ipPolicy,
       };
 
       await updateConfig(newConfig);
@@ -90,7 +98,19 @@ export function SettingsModal(
       onClose();
     } catch (err) {
       console.error("Failed to save config:", err);
-      alert("保存配置失败: " + err);
+      if (Array.isArray(err)) {
+        // Field-level validation errors: highlight the offending inputs
+        const issues = err as ConfigIssue[];
+        setFieldErrors(
+          Object.fromEntries(issues.map((i) => [i.field, i.message])),
+        );
+        alert(
+          "保存配置失败:\n" +
+            issues.map((i) => `${i.field}: ${i.message}`).join("\n"),
+        );
+      } else {
+        alert("保存配置失败: " + err);
+      }
     } finally {
       setSaving(false);
     }
@@ -301,6 +321,11 @@ export function SettingsModal(
                     <p className="text-xs text-base-content/50 pl-6">
                       新建任务时的默认保存位置
                     </p>
+                    {fieldErrors.default_download_path && (
+                      <p className="text-xs text-error pl-6">
+                        {fieldErrors.default_download_path}
+                      </p>
+                    )}
                   </div>
 
                   {/* Max Tasks */}
@@ -321,7 +346,10 @@ export function SettingsModal(
                       step="1"
                       value={maxTasks}
                       onChange={(e) => setMaxTasks(e.target.value)}
-                      className="range range-primary"
+                      className={cn(
+                        "range range-primary",
+                        fieldErrors.max_concurrent_tasks && "range-error",
+                      )}
                     />
                     <div className="flex justify-between text-xs text-base-content/50 font-medium px-1">
                       <span>1</span>
@@ -333,6 +361,11 @@ export function SettingsModal(
                     <p className="text-xs text-base-content/50 pl-6">
                       控制同时进行的下载任务数量
                     </p>
+                    {fieldErrors.max_concurrent_tasks && (
+                      <p className="text-xs text-error pl-6">
+                        {fieldErrors.max_concurrent_tasks}
+                      </p>
+                    )}
                   </div>
 
                   <div className="divider my-2"></div>
@@ -355,7 +388,10 @@ export function SettingsModal(
                       step="1"
                       value={maxDownloads}
                       onChange={(e) => setMaxDownloads(e.target.value)}
-                      className="range range-secondary"
+                      className={cn(
+                        "range range-secondary",
+                        fieldErrors.max_concurrent_downloads && "range-error",
+                      )}
                     />
                     <div className="flex justify-between text-xs text-base-content/50 font-medium px-1">
                       <span>1</span>
@@ -367,6 +403,57 @@ export function SettingsModal(
                     <p className="text-xs text-base-content/50 pl-6">
                       控制单个任务的并发下载连接数（分块下载）
                     </p>
+                    {fieldErrors.max_concurrent_downloads && (
+                      <p className="text-xs text-error pl-6">
+                        {fieldErrors.max_concurrent_downloads}
+                      </p>
+                    )}
+                  </div>
+
+                  <div className="divider my-2"></div>
+
+                  {/* IP Version */}
+                  <div className="space-y-3">
+                    <label className="text-sm font-bold text-base-content flex items-center gap-2">
+                      <Network className="w-4 h-4 text-primary" />
+                      IP 协议
+                    </label>
+                    <select
+                      value={ipPolicy}
+                      onChange={(e) => setIpPolicy(e.target.value as IpPolicy)}
+                      className="select select-bordered w-full"
+                    >
+                      <option value="Auto">自动</option>
+                      <option value="PreferIpv4">优先 IPv4</option>
+                      <option value="PreferIpv6">优先 IPv6</option>
+                      <option value="Ipv4Only">只使用 IPv4</option>
+                      <option value="Ipv6Only">只使用 IPv6</option>
+                    </select>
+                    <p className="text-xs text-base-content/50 pl-6">
+                      IPv6 不通的网络中选择只使用 IPv4，修改后重启生效
+                    </p>
+                  </div>
+
+                  <div className="divider my-2"></div>
+
+                  {/* Orphan Cleanup */}
+                  <div className="space-y-3">
+                    <button
+                      type="button"
+                      onClick={() => {
+                        onClose();
+                        onOpenCleanup?.();
+                      }}
+                      className="btn btn-outline w-full justify-start gap-3 hover:btn-primary transition-all"
+                    >
+                      <Trash2 className="w-5 h-5" />
+                      <div className="flex-1 text-left">
+                        <div className="font-semibold">清理残留文件</div>
+                        <div className="text-xs opacity-70">
+                          查找没有任务使用的断点续传状态和未完成的文件
+                        </div>
+                      </div>
+                    </button>
                   </div>
 
                   <div className="divider my-2"></div>
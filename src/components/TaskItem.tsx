@@ -9,6 +9,7 @@ import {
   Pause,
   Play,
   RefreshCw,
+  ShieldCheck,
   Trash2,
   X,
 } from "lucide-react";
@@ -21,9 +22,9 @@ interface TaskItemProps {
 }
 
 export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
-  const progress = task.total_size > 0
-    ? (task.downloaded / task.total_size) * 100
-    : 0;
+  const isVerifying = task.status === "Verifying";
+  const done = isVerifying ? task.verified : task.downloaded;
+  const progress = task.total_size > 0 ? (done / task.total_size) * 100 : 0;
 
   const handlePause = async () => {
     await pauseTask(task.id);
@@ -58,6 +59,13 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
             下载中
           </div>
         );
+      case "Verifying":
+        return (
+          <div className="badge badge-info badge-sm gap-1.5 font-medium shadow-sm animate-pulse">
+            <ShieldCheck className="w-3 h-3" />
+            校验中
+          </div>
+        );
       case "Paused":
         return (
           <div className="badge badge-warning badge-sm gap-1.5 font-medium shadow-sm">
@@ -72,6 +80,13 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
             失败
           </div>
         );
+      case "PartiallyCompleted":
+        return (
+          <div className="badge badge-error badge-outline badge-sm gap-1.5 font-medium shadow-sm">
+            <AlertCircle className="w-3 h-3" />
+            部分完成
+          </div>
+        );
       case "Cancelled":
         return (
           <div className="badge badge-neutral badge-sm gap-1.5 font-medium shadow-sm">
@@ -94,9 +109,12 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
       case "Completed":
         return "progress-success";
       case "Failed":
+      case "PartiallyCompleted":
         return "progress-error";
       case "Paused":
         return "progress-warning";
+      case "Verifying":
+        return "progress-info";
       default:
         return "progress-primary";
     }
@@ -109,6 +127,7 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
       case "Downloading":
         return "border-primary/30 bg-primary/5 shadow-lg shadow-primary/10";
       case "Failed":
+      case "PartiallyCompleted":
         return "border-error/20 bg-error/5";
       default:
         return "border-base-300 bg-base-100";
@@ -167,8 +186,16 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
                   className="font-semibold text-base text-base-content truncate mb-1"
                   title={task.url}
                 >
-                  {task.url.split("/").pop() || task.url}
+                  {task.display_name || task.url.split("/").pop() || task.url}
                 </h3>
+                {task.note && (
+                  <p
+                    className="text-xs text-base-content/60 truncate mb-1"
+                    title={task.note}
+                  >
+                    {task.note}
+                  </p>
+                )}
                 <div className="flex items-center gap-2 text-xs text-base-content/60 flex-wrap">
                   {getStatusBadge(task.status)}
                   <span className="hidden sm:inline">•</span>
@@ -207,14 +234,20 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
                   </span>
                   <span className="opacity-60">•</span>
                   <span>
-                    {formatBytes(task.downloaded)} /{" "}
-                    {formatBytes(task.total_size)}
+                    {isVerifying && "校验 "}
+                    {formatBytes(done)} / {formatBytes(task.total_size)}
                   </span>
+                  {task.status === "PartiallyCompleted" &&
+                    task.missing_ranges && (
+                    <span className="text-error">
+                      缺失 {task.missing_ranges.length} 段
+                    </span>
+                  )}
                 </div>
                 {task.status === "Downloading" && (
                   <div className="flex gap-3 items-center">
                     <span className="text-primary font-semibold">
-                      {formatBytes(task.speed)}/s
+                      {formatBytes(task.speed ?? 0)}/s
                     </span>
                     <span className="opacity-60">•</span>
                     <span className="flex items-center gap-1">
@@ -229,7 +262,7 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
 
           {/* Actions */}
           <div className="flex sm:flex-col gap-2 self-end sm:self-center">
-            {task.status === "Downloading" && (
+            {(task.status === "Downloading" || isVerifying) && (
               <button
                 type="button"
                 onClick={handlePause}
@@ -249,7 +282,8 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
                 <Play className="w-4 h-4" />
               </button>
             )}
-            {task.status === "Failed" && (
+            {(task.status === "Failed" ||
+              task.status === "PartiallyCompleted") && (
               <button
                 type="button"
                 onClick={handleResume}
@@ -260,8 +294,8 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
                 <span className="hidden sm:inline">重试</span>
               </button>
             )}
-            {(task.status === "Downloading" || task.status === "Paused" ||
-              task.status === "Pending") && (
+            {(task.status === "Downloading" || isVerifying ||
+              task.status === "Paused" || task.status === "Pending") && (
               <button
                 type="button"
                 onClick={handleCancel}
@@ -272,7 +306,8 @@ export function TaskItem({ task, onRefreshNeeded }: TaskItemProps) {
               </button>
             )}
             {(task.status === "Completed" || task.status === "Cancelled" ||
-              task.status === "Failed") && (
+              task.status === "Failed" ||
+              task.status === "PartiallyCompleted") && (
               <button
                 type="button"
                 onClick={handleRemove}
@@ -1,16 +1,22 @@
 export type TaskStatus =
   | "Pending"
   | "Downloading"
+  | "Verifying"
   | "Paused"
   | "Completed"
   | "Failed"
-  | "Cancelled";
+  | "Cancelled"
+  | "PartiallyCompleted";
 
 export type Priority = "Low" | "Normal" | "High";
 
+/** `VerifyOnly` tasks hash an existing local file instead of downloading */
+export type TaskKind = "Download" | "VerifyOnly";
+
 export interface ChecksumType {
   Md5?: string;
   Sha256?: string;
+  Sha512?: string;
 }
 
 export interface DownloadTask {
@@ -23,13 +29,346 @@ export interface DownloadTask {
   created_at: number;
   error?: string;
   priority: Priority;
-  speed: number;
+  /** Current speed; only present while the task is running and updated by events */
+  speed?: number;
   eta?: number;
-  headers: Record<string, string>;
+  headers?: Record<string, string>;
   checksum?: ChecksumType;
+  group_id?: string;
+  /** Bytes hashed so far while verifying */
+  verified: number;
+  /** Download finished but verification has not completed yet */
+  verification_pending: boolean;
+  /** Cached result of the size/range probe */
+  remote?: RemoteInfo;
+  /** Parallel connections for this task; unset means the global default */
+  connections?: number;
+  /** Inclusive byte slice `[start, end]` to download; a null end means to EOF */
+  byte_range?: [number, number | null];
+  /** Name shown instead of the file name */
+  display_name?: string;
+  /** Free-form note */
+  note?: string;
+  /** Download with a single GET and no size probe */
+  skip_probe: boolean;
+  /** Order in which chunks are fetched; fixed when the task is added */
+  chunk_order?: ChunkOrder;
+  /** Download environment recorded when the task first started */
+  provenance?: Provenance;
+  /** Bytes from the start of the file that are complete and readable on disk */
+  contiguous_prefix: number;
+  /** What to do when the destination already exists */
+  conflict?: ConflictPolicy;
+  /** Completed without downloading because the remote file was unchanged */
+  skipped_unchanged?: boolean;
+  /** Removed from the list but kept for inspection */
+  archived?: boolean;
+  /** When the task was archived (Unix seconds) */
+  archived_at?: number;
+  /** Time spent downloading in milliseconds, excluding pauses and verification */
+  active_duration_ms?: number;
+  /** Highest sampled download speed in bytes/second */
+  peak_speed?: number;
+  /** Downloaded bytes divided by active duration; set when the task finishes */
+  average_speed?: number;
+  /** Missing in state files written before verification-only tasks existed */
+  kind?: TaskKind;
+  /** Inclusive byte ranges `[start, end]` still missing after a partial completion */
+  missing_ranges?: [number, number][];
+  /** Most recent lifecycle, retry and verification events, oldest first (at most 50) */
+  recent_events?: CompactEvent[];
+  /** Link (or copy) created in `completed_link_dir` when the task completed */
+  completed_link?: string;
+  /** Speed limit for this task in bytes/s, replacing the per-task default; 0 means unlimited */
+  speed_limit?: number;
+  /** Until when (Unix milliseconds) the task ignores speed limits, see `boostTask` */
+  boost_until?: number;
+  /** Time limit per run in milliseconds; the task fails if it is not downloaded by then */
+  deadline_ms?: number;
+  /** Final URL after redirects, used for chunk requests and resume */
+  resolved_url?: string;
+  /** Other URLs serving the same file; chunks are spread across them */
+  mirrors?: string[];
+}
+
+/**
+ * Entry in a task's persisted event journal
+ */
+export interface CompactEvent {
+  /** Unix milliseconds */
+  timestamp_ms: number;
+  kind: CompactEventKind;
+}
+
+/**
+ * Why a download reconnected: the whole task stalled and restarted, or one
+ * chunk connection stalled or dropped and was re-requested from its current offset
+ */
+export type RetryReason =
+  | "Stalled"
+  | { ConnectionStalled: { chunk_index: number } }
+  | { ConnectionLost: { chunk_index: number } };
+
+/**
+ * Why a download discarded its data and started over: the remote file's
+ * `ETag` / `Last-Modified` changed since the partial download began, or the
+ * server answered a chunk's Range request with the whole file
+ */
+export type RestartReason = "RemoteChanged" | "RangeIgnored" | "ResumeRejected";
+
+/**
+ * Why a mirror stopped being used for the rest of the download
+ */
+export type MirrorDemotion =
+  | "ProbeFailed"
+  | "SizeMismatch"
+  | "RangeIgnored"
+  | { Rejected: { status: number } }
+  | "TooManyFailures";
+
+/**
+ * What happened; progress updates are never journaled
+ */
+export type CompactEventKind =
+  | "Added"
+  | "Started"
+  | "Paused"
+  | "Resumed"
+  | "Cancelled"
+  | { Retried: { fresh: boolean } }
+  | { Completed: { skipped_unchanged: boolean } }
+  | { Failed: { error: string } }
+  | { PartiallyCompleted: { missing_ranges: number } }
+  | { Retrying: { attempt: number; reason: RetryReason } }
+  | { Restarted: { reason: RestartReason } }
+  | { MirrorDemoted: { mirror: string; reason: MirrorDemotion } }
+  | "VerifyStarted"
+  | { VerifyCompleted: { success: boolean; repaired_chunks: number } };
+
+/**
+ * Chunk fetch order; `SequentialFirst` carries the in-order prefix length in bytes
+ */
+export type ChunkOrder =
+  | "Parallel"
+  | "Sequential"
+  | { SequentialFirst: number }
+  | "Random";
+
+/**
+ * Handling of an existing destination file
+ */
+export type ConflictPolicy = "Overwrite" | "Rename" | "IfChanged";
+
+/**
+ * Options and environment a download ran with
+ */
+export interface Provenance {
+  core_version: string;
+  /** Unix seconds */
+  captured_at: number;
+  /** URL actually requested */
+  url: string;
+  user_agent?: string;
+  /** Effective headers; sensitive values are stored as `sha256:<digest>` */
+  headers: Record<string, string>;
+  /** Whether a proxy was used */
+  proxy: boolean;
+  connections: number;
+  chunk_size: number;
+  speed_limit?: number;
+  byte_range?: [number, number | null];
+  skip_probe: boolean;
+}
+
+/**
+ * Compact task summary for list views
+ */
+export interface TaskView {
+  id: string;
+  /** Display name, falling back to the destination file name */
+  name: string;
+  note?: string;
+  status: TaskStatus;
+  /** Percentage 0-100; verification progress while verifying; absent if size unknown */
+  progress?: number;
+  /** Speed in bytes/second */
+  speed: number;
+  /** Estimated remaining seconds */
+  eta?: number;
+  /** Human-readable downloaded size */
+  downloaded: string;
+  /** Human-readable total size; absent if unknown */
+  total_size?: string;
+}
+
+/**
+ * Incremental task list changes, emitted as the `tasks-changed` event
+ */
+export interface TaskChanges {
+  /** Generation after applying these changes; consecutive from 1 */
+  generation: number;
+  added: TaskView[];
+  updated: TaskView[];
+  /** IDs of removed tasks */
+  removed: string[];
+}
+
+/**
+ * Remote resource information discovered by probing
+ */
+export interface RemoteInfo {
+  /** Total size in bytes, if the server reported it */
+  total_size?: number;
+  /** Whether the server answers ranged requests */
+  supports_range: boolean;
+  /** `ETag` response header */
+  etag?: string;
+  /** `Last-Modified` response header */
+  last_modified?: string;
+  /** Filename suggested by `Content-Disposition`, already sanitized */
+  filename?: string;
+}
+
+/**
+ * A leftover file that no task references
+ */
+export interface OrphanReport {
+  path: string;
+  /** StateFile: a `<dest>.json` resume state; PartialFile: its unfinished data file */
+  kind: "StateFile" | "PartialFile";
+  /** Size in bytes */
+  size: number;
+  /** Download URL recorded in the state file */
+  url: string;
+}
+
+/**
+ * Why the scheduler is not starting new tasks
+ */
+export type PausedReason = "ManualPause" | "Shutdown";
+
+/**
+ * Queue scheduler state
+ */
+export interface SchedulerState {
+  /** Whether pending tasks may be started */
+  running: boolean;
+  reason?: PausedReason;
+  /** When the scheduler expects to resume on its own, if ever */
+  next_wakeup?: { secs_since_epoch: number; nanos_since_epoch: number };
+}
+
+/**
+ * Task counts by status and the scheduler state
+ */
+export interface QueueStats {
+  total: number;
+  pending: number;
+  downloading: number;
+  verifying: number;
+  paused: number;
+  completed: number;
+  failed: number;
+  cancelled: number;
+  /** Finished with byte ranges missing */
+  partially_completed?: number;
+  /** Aggregate speed in bytes/second */
+  speed: number;
+  /** Bytes received but not yet written to storage */
+  buffered_bytes: number;
+  scheduler: SchedulerState;
+  /** Bytes left per priority for pending, downloading and paused tasks of known size */
+  remaining_bytes_by_priority?: Partial<Record<Priority, number>>;
+  /** Pending, downloading and paused tasks of unknown size, per priority */
+  unknown_size_by_priority?: Partial<Record<Priority, number>>;
+  /** Downloading and verifying tasks per priority */
+  active_by_priority?: Partial<Record<Priority, number>>;
+  /** Seconds to download all known remaining bytes at the current speed */
+  estimated_drain_time?: number;
+}
+
+/**
+ * Estimated start and finish of a pending task, see `simulateSchedule`
+ */
+export interface ScheduledSlot {
+  task_id: string;
+  /** Seconds from now; null if it cannot be estimated */
+  est_start: number | null;
+  /** Seconds from now; null if it cannot be estimated */
+  est_finish: number | null;
+}
+
+/**
+ * Disk space needed by one destination volume
+ */
+export interface VolumeForecast {
+  /** An existing directory on the volume */
+  path: string;
+  /** Free space available to the user in bytes */
+  free_space: number;
+  /** Bytes still needed by tasks of known size */
+  required: number;
+  task_count: number;
+  /** Tasks whose size is unknown */
+  unknown_count: number;
+}
+
+/**
+ * Disk space forecast for pending and paused tasks
+ */
+export interface Forecast {
+  /** Bytes still needed by tasks of known size */
+  pending_bytes_known: number;
+  /** Tasks whose size is unknown */
+  pending_unknown_count: number;
+  free_space_by_volume: VolumeForecast[];
+  /** False if some volume is too small; null if unknown sizes prevent a decision */
+  will_fit: boolean | null;
+}
+
+/**
+ * Aggregated progress of a task group
+ */
+export interface GroupProgress {
+  /** Group ID */
+  group_id: string;
+  /** Group name */
+  name: string;
+  /** Number of member tasks */
+  total_tasks: number;
+  /** Number of completed members */
+  completed_tasks: number;
+  /** Number of failed or cancelled members */
+  failed_tasks: number;
+  /** Downloaded bytes across members */
+  downloaded: number;
+  /** Known total bytes across members */
+  total_size: number;
+  /** Aggregate speed in bytes/second */
+  speed: number;
+  /** Estimated remaining seconds */
+  eta?: number;
+}
+
+/**
+ * Task list grouped by task group
+ */
+export interface GroupedTasks {
+  groups: { progress: GroupProgress; tasks: DownloadTask[] }[];
+  ungrouped: DownloadTask[];
+}
+
+/**
+ * Ordering metadata attached to every download event
+ */
+export interface EventMeta {
+  /** Monotonically increasing sequence number, starting at 1 */
+  seq: number;
+  /** Emission time in Unix milliseconds */
+  timestamp_ms: number;
 }
 
-export type QueueEvent =
+export type QueueEvent = EventMeta & (
   | { type: "TaskAdded"; payload: { task_id: string } }
   | { type: "TaskStarted"; payload: { task_id: string } }
   | {
@@ -40,15 +379,72 @@ export type QueueEvent =
       total: number;
       speed: number;
       eta?: number;
+      /** See DownloadTask.contiguous_prefix */
+      contiguous_prefix: number;
+    };
+  }
+  | {
+    type: "TaskCompleted";
+    payload: {
+      task_id: string;
+      skipped_unchanged?: boolean;
+      /** Average download speed in bytes/second, excluding paused time */
+      average_speed?: number;
+      /** Highest sampled download speed in bytes/second */
+      peak_speed?: number;
     };
   }
-  | { type: "TaskCompleted"; payload: { task_id: string } }
   | { type: "TaskFailed"; payload: { task_id: string; error: string } }
+  | {
+    type: "TaskPartiallyCompleted";
+    payload: { task_id: string; missing_ranges: [number, number][] };
+  }
+  | { type: "TaskLinkFailed"; payload: { task_id: string; error: string } }
+  | { type: "TaskBoosted"; payload: { task_id: string; until_ms: number } }
+  | { type: "TaskBoostEnded"; payload: { task_id: string } }
   | { type: "TaskPaused"; payload: { task_id: string } }
   | { type: "TaskResumed"; payload: { task_id: string } }
   | { type: "TaskCancelled"; payload: { task_id: string } }
   | { type: "VerifyStarted"; payload: { task_id: string } }
-  | { type: "VerifyCompleted"; payload: { task_id: string; success: boolean } };
+  | {
+    type: "VerifyProgress";
+    payload: { task_id: string; verified: number; total: number };
+  }
+  | {
+    type: "VerifyCompleted";
+    payload: { task_id: string; success: boolean; repaired_chunks: number };
+  }
+  | { type: "SchedulerStateChanged"; payload: SchedulerState }
+  | { type: "Stats"; payload: QueueStats }
+  | {
+    type: "DiskPressure";
+    /** `throttled_to` is null once the slowdown has been lifted */
+    payload: { latency_ms: number; throttled_to: number | null };
+  }
+);
+
+/**
+ * Payload of an event broadcast to every window
+ */
+export type Broadcast<T> = T & {
+  /** Sequence number across all broadcast events, consecutive from 1 */
+  bridge_seq: number;
+  /**
+   * Re-sent to this window only after it reloaded; carries the current
+   * `bridge_seq` without advancing it
+   */
+  replayed?: boolean;
+};
+
+/**
+ * Counters a window compares with what it has seen to detect missed events
+ */
+export interface StateGeneration {
+  /** Sequence number of the latest broadcast event */
+  bridge_seq: number;
+  /** Task list generation, as returned by `get_tasks_snapshot` */
+  tasks: number;
+}
 
 /**
  * Window state
@@ -68,6 +464,19 @@ export interface WindowState {
   sidebar_open: boolean;
 }
 
+/**
+ * IP version used to connect to servers
+ *
+ * Preferred versions fall back to the other one quickly (Happy Eyeballs);
+ * the `Only` variants never use the other version.
+ */
+export type IpPolicy =
+  | "Auto"
+  | "PreferIpv4"
+  | "PreferIpv6"
+  | "Ipv4Only"
+  | "Ipv6Only";
+
 /**
  * Application configuration
  */
@@ -86,10 +495,42 @@ export interface AppConfig {
   user_agent: string;
   /** Theme setting (light, dark, system) */
   theme: string;
+  /** Total speed limit in bytes/second; null means unlimited */
+  speed_limit?: number | null;
+  /**
+   * Minimum interval in milliseconds between progress events of a task;
+   * only the latest progress within an interval is sent
+   */
+  event_flush_interval_ms?: number;
+  /** Language of error messages and sizes ("en" or "zh"); null follows the system */
+  locale?: string | null;
+  /** Directory where a link to each completed file is created; takes effect after restart */
+  completed_link_dir?: string | null;
+  /** Copy the file into `completed_link_dir` when neither a symlink nor a hardlink works */
+  completed_link_copy?: boolean;
+  /** IP version used to connect to servers; takes effect after restart */
+  ip_policy?: IpPolicy;
   /** Window state */
   window: WindowState;
 }
 
+/**
+ * An invalid configuration field
+ */
+export interface ConfigIssue {
+  /** Field name as in AppConfig; empty for the whole configuration file */
+  field: string;
+  /** Why the value was rejected */
+  message: string;
+}
+
+/**
+ * Credentials attached to requests whose host matches a host pattern
+ */
+export type Credential =
+  | { Basic: { username: string; password: string } }
+  | { Bearer: { token: string } };
+
 /**
  * Completed download task (history record)
  */
@@ -108,6 +549,12 @@ export interface CompletedTask {
   duration: number;
   /** Average download speed in bytes/second */
   avg_speed: number;
+  /** Highest sampled download speed in bytes/second */
+  peak_speed?: number;
+  /** Download environment of the task */
+  provenance?: Provenance;
+  /** Status when the task last finished; defaults to Completed */
+  status?: TaskStatus;
 }
 
 /**
@@ -1,5 +1,11 @@
+use crate::ui::parse_resolve;
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
+use yushi_core::TaskPriority;
 
 #[derive(Parser)]
 #[command(name = "yushi")]
@@ -8,16 +14,28 @@ use std::path::PathBuf;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// 错误信息、文件大小等文本的语言，默认取自 LANG 环境变量
+    #[arg(long, global = true, value_parser = ["en", "zh"])]
+    pub lang: Option<String>,
+
+    /// 使用的配置档案，覆盖基础配置中的部分设置，也可以用 YUSHI_PROFILE 环境变量指定
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// 下载单个文件
     Download(DownloadArgs),
+    /// 查看远程文件信息
+    Info(InfoArgs),
     /// 管理下载队列
     Queue(QueueArgs),
     /// 配置管理
     Config(ConfigArgs),
+    /// 下载历史
+    History(HistoryArgs),
     /// 启动 TUI 界面
     #[cfg(feature = "tui")]
     Tui,
@@ -29,7 +47,7 @@ pub struct DownloadArgs {
     #[arg(value_name = "URL")]
     pub url: String,
 
-    /// 输出文件路径
+    /// 输出文件路径，`-` 表示写到标准输出（进度和提示写到标准错误）
     #[arg(short, long, value_name = "FILE")]
     pub output: Option<PathBuf>,
 
@@ -45,7 +63,7 @@ pub struct DownloadArgs {
     #[arg(short = 'A', long)]
     pub user_agent: Option<String>,
 
-    /// HTTP 代理
+    /// 代理 (http://、https://、socks5:// 或 socks5h://，例如 socks5h://127.0.0.1:1080)
     #[arg(short = 'x', long)]
     pub proxy: Option<String>,
 
@@ -53,6 +71,43 @@ pub struct DownloadArgs {
     #[arg(short = 'H', long)]
     pub header: Vec<String>,
 
+    /// 额外信任的 CA 证书 (PEM 文件)，可以指定多次，和配置中的 ca_cert 一起使用
+    #[arg(long, value_name = "FILE")]
+    pub cacert: Vec<PathBuf>,
+
+    /// 不验证服务器证书 (危险：连接可能被窃听或篡改)
+    #[arg(short = 'k', long)]
+    pub insecure: bool,
+
+    /// Netscape 格式的 Cookie 文件 (cookies.txt，浏览器导出的格式)
+    #[arg(long, value_name = "FILE")]
+    pub cookies: Option<PathBuf>,
+
+    /// HTTP Basic 认证 (格式: user:pass)，优先于保存的主机认证信息
+    #[arg(short = 'u', long, value_name = "USER:PASS", conflicts_with = "bearer")]
+    pub user: Option<String>,
+
+    /// Bearer 令牌认证，优先于保存的主机认证信息
+    #[arg(long, value_name = "TOKEN")]
+    pub bearer: Option<String>,
+
+    /// 把主机名解析为指定的地址 (格式: host:port:addr，例如 example.com:443:10.0.0.5)，
+    /// 可以指定多次
+    #[arg(long, value_name = "HOST:PORT:ADDR", value_parser = parse_resolve)]
+    pub resolve: Vec<(String, SocketAddr)>,
+
+    /// 连接时绑定的本地地址
+    #[arg(long, value_name = "IP")]
+    pub local_address: Option<IpAddr>,
+
+    /// 只使用 IPv4 连接
+    #[arg(short = '4', long, conflicts_with = "ipv6")]
+    pub ipv4: bool,
+
+    /// 只使用 IPv6 连接
+    #[arg(short = '6', long)]
+    pub ipv6: bool,
+
     /// MD5 校验和
     #[arg(long)]
     pub md5: Option<String>,
@@ -61,9 +116,45 @@ pub struct DownloadArgs {
     #[arg(long)]
     pub sha256: Option<String>,
 
+    /// 校验和，按长度判断算法 (MD5: 32, SHA256: 64, SHA512: 128 位十六进制)；
+    /// 同时指定 --md5 或 --sha256 时忽略
+    #[arg(long, value_name = "HEX")]
+    pub checksum: Option<String>,
+
+    /// 只下载指定的字节范围 (例如: 0-10485759, 1048576-)
+    #[arg(long, value_name = "START-END")]
+    pub range: Option<String>,
+
+    /// 输出文件已存在时，只在远程文件变化后重新下载
+    #[arg(long)]
+    pub if_changed: bool,
+
     /// 静默模式（不显示进度）
     #[arg(short = 'q', long)]
     pub quiet: bool,
+
+    /// 只探测并显示下载计划，不下载
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// 以 JSON 格式输出下载计划
+    #[arg(long, requires = "dry_run")]
+    pub json: bool,
+}
+
+#[derive(Parser)]
+pub struct InfoArgs {
+    /// 目标 URL
+    #[arg(value_name = "URL")]
+    pub url: String,
+
+    /// 测速时长 (例如: 10s, 1m)，测试不同并发连接数下的下载速度
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub measure: Option<Duration>,
+
+    /// 代理 (http://、https://、socks5:// 或 socks5h://，例如 socks5h://127.0.0.1:1080)
+    #[arg(short = 'x', long)]
+    pub proxy: Option<String>,
 }
 
 #[derive(Parser)]
@@ -75,32 +166,211 @@ pub struct QueueArgs {
 #[derive(Subcommand)]
 pub enum QueueCommands {
     /// 添加下载任务到队列
+    ///
+    /// 添加只把任务写入队列文件，不会开始下载：运行 `yushi queue start` 下载所有等待中的
+    /// 任务，或加上 --now 立即下载这个任务。
     Add {
         /// 下载 URL
         url: String,
         /// 输出文件路径
         #[arg(short, long)]
         output: PathBuf,
-        /// 优先级 (low, normal, high)
-        #[arg(short, long, default_value = "normal")]
-        priority: String,
+        /// 优先级 (low, normal, high 或 0, 1, 2)
+        #[arg(short, long, value_enum, ignore_case = true, default_value_t = TaskPriority::Normal)]
+        priority: TaskPriority,
         /// MD5 校验和
         #[arg(long)]
         md5: Option<String>,
         /// SHA256 校验和
         #[arg(long)]
         sha256: Option<String>,
+        /// 校验和，按长度判断算法 (MD5: 32, SHA256: 64, SHA512: 128 位十六进制)；
+        /// 同时指定 --md5 或 --sha256 时忽略
+        #[arg(long, value_name = "HEX")]
+        checksum: Option<String>,
+        /// 只下载指定的字节范围 (例如: 0-10485759, 1048576-)
+        #[arg(long, value_name = "START-END")]
+        range: Option<String>,
+        /// 显示名称（代替文件名显示）
+        #[arg(long)]
+        name: Option<String>,
+        /// 备注
+        #[arg(long)]
+        note: Option<String>,
+        /// 不探测文件大小，直接下载（适合大量小文件）
+        #[arg(long)]
+        skip_probe: bool,
+        /// 镜像地址，可以指定多次；分块会分散到这些地址下载，出错多的镜像自动停用
+        #[arg(long = "mirror", value_name = "URL")]
+        mirrors: Vec<String>,
+        /// 这个任务的速度限制，代替配置中的每任务限速 (例如: 500K，0 表示不限速)
+        #[arg(long, value_name = "RATE")]
+        speed_limit: Option<String>,
+        /// 时间限制 (例如: 2h)，开始后没有在这段时间内下载完成时任务失败，已下载的部分保留
+        #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+        deadline: Option<Duration>,
+        /// 输出文件已存在时，只在远程文件变化后重新下载（默认自动重命名）
+        #[arg(long)]
+        if_changed: bool,
+        /// 添加后立即下载这个任务并显示进度，不需要再运行 queue start
+        #[arg(long, conflicts_with = "dry_run")]
+        now: bool,
+        /// Netscape 格式的 Cookie 文件 (cookies.txt)，用于 --dry-run 的探测和 --now 的下载；
+        /// 之后用 queue start 下载时需要再次指定
+        #[arg(long, value_name = "FILE")]
+        cookies: Option<PathBuf>,
+        /// 只探测并显示下载计划，不添加任务
+        #[arg(long)]
+        dry_run: bool,
+        /// 以 JSON 格式输出下载计划
+        #[arg(long, requires = "dry_run")]
+        json: bool,
+    },
+    /// 添加只校验本地文件的任务，不下载
+    ///
+    /// 文件在 `yushi queue start` 时校验，结果和下载任务一样出现在任务列表和历史记录中。
+    AddVerify {
+        /// 要校验的文件
+        file: PathBuf,
+        /// 优先级 (low, normal, high 或 0, 1, 2)
+        #[arg(short, long, value_enum, ignore_case = true, default_value_t = TaskPriority::Normal)]
+        priority: TaskPriority,
+        /// MD5 校验和
+        #[arg(
+            long,
+            conflicts_with = "sha256",
+            required_unless_present_any = ["sha256", "checksum"]
+        )]
+        md5: Option<String>,
+        /// SHA256 校验和
+        #[arg(long)]
+        sha256: Option<String>,
+        /// 校验和，按长度判断算法 (MD5: 32, SHA256: 64, SHA512: 128 位十六进制)；
+        /// 同时指定 --md5 或 --sha256 时忽略
+        #[arg(long, value_name = "HEX")]
+        checksum: Option<String>,
+    },
+    /// 从 HTTP 目录索引页（nginx、Apache 等的自动索引）批量添加任务
+    ///
+    /// 文件保存到输出目录下与索引页相同的相对路径，不会开始下载。
+    AddIndex {
+        /// 目录索引页 URL
+        url: String,
+        /// 输出目录
+        #[arg(short, long)]
+        output: PathBuf,
+        /// 进入子目录的层数，0 表示只添加这一页列出的文件
+        #[arg(short, long, default_value_t = 0)]
+        depth: usize,
+        /// 只添加匹配的文件 (例如: "*.iso")；包含 / 时匹配相对路径
+        #[arg(long)]
+        include: Option<String>,
+        /// 不添加匹配的文件
+        #[arg(long)]
+        exclude: Option<String>,
+        /// 同时添加索引页中指向其他主机的文件
+        #[arg(long)]
+        any_host: bool,
     },
     /// 列出所有任务
-    List,
-    /// 启动队列处理
+    List {
+        /// 按任务组分组显示
+        #[arg(long)]
+        by_group: bool,
+        /// 只显示 URL、路径、名称或备注包含该文本的任务
+        #[arg(short, long)]
+        search: Option<String>,
+        /// 同时预估等待中和暂停的任务需要的磁盘空间
+        #[arg(long)]
+        forecast: bool,
+        /// 只显示统计：各优先级的剩余大小、运行中的任务数和预计完成时间
+        #[arg(long, conflicts_with_all = ["by_group", "search"])]
+        summary: bool,
+        /// 同时显示已移除（归档）的任务
+        #[arg(long, conflicts_with = "summary")]
+        archived: bool,
+        /// 输出格式：table 每个任务一行，wide 在 table 基础上显示 URL 和路径，
+        /// json 输出完整的任务信息，ids 每行一个任务 ID（便于配合 xargs 使用）
+        #[arg(
+            long,
+            value_parser = ["table", "wide", "json", "ids"],
+            conflicts_with_all = ["by_group", "forecast", "summary"]
+        )]
+        format: Option<String>,
+        /// table 和 wide 格式显示的列，逗号分隔
+        /// (可用: id, name, status, progress, size, speed, eta, url, dest)
+        #[arg(long, value_delimiter = ',', requires = "format")]
+        columns: Vec<String>,
+        /// 排序方式
+        #[arg(
+            long,
+            default_value = "created",
+            value_parser = ["created", "name", "status", "progress", "size", "speed", "priority"]
+        )]
+        sort: String,
+        /// 倒序排列
+        #[arg(long)]
+        reverse: bool,
+        /// 只显示这些状态的任务，逗号分隔 (例如: failed,paused)
+        #[arg(long, value_delimiter = ',', conflicts_with = "summary")]
+        status: Vec<String>,
+    },
+    /// 显示任务详情
+    Show {
+        /// 任务 ID
+        task_id: String,
+        /// 同时显示任务开始下载时记录的下载环境
+        #[arg(long)]
+        provenance: bool,
+    },
+    /// 输出任务的诊断信息（分块状态和生效的设置），用于问题报告
+    Diag {
+        /// 任务 ID
+        task_id: String,
+        /// 以 JSON 格式输出
+        #[arg(long)]
+        json: bool,
+        /// 保留 URL 中的查询字符串（可能包含签名等凭据）
+        #[arg(long)]
+        show_query: bool,
+        /// 把这些分块标记为未完成，恢复任务时重新下载 (例如: 3,7)；只能用于已暂停或失败的任务
+        #[arg(long, value_delimiter = ',')]
+        refetch_chunk: Vec<usize>,
+    },
+    /// 修改任务的显示名称和备注
+    Edit {
+        /// 任务 ID
+        task_id: String,
+        /// 显示名称，空字符串表示清除
+        #[arg(long)]
+        name: Option<String>,
+        /// 备注，空字符串表示清除
+        #[arg(long)]
+        note: Option<String>,
+    },
+    /// 启动队列处理，下载所有等待中的任务
     Start {
-        /// 最大并发任务数
-        #[arg(short = 'n', long, default_value = "2")]
-        max_tasks: usize,
-        /// 每个任务的并发连接数
-        #[arg(short = 'c', long, default_value = "4")]
-        connections: usize,
+        /// 最大并发任务数（默认使用配置中的 max_tasks）
+        #[arg(short = 'n', long)]
+        max_tasks: Option<usize>,
+        /// 每个任务的并发连接数（默认使用配置中的 connections）
+        #[arg(short = 'c', long)]
+        connections: Option<usize>,
+        /// 所有任务的总速度限制 (例如: 1M, 500K)
+        #[arg(short = 'l', long)]
+        speed_limit: Option<String>,
+        /// 每个任务的速度限制 (例如: 1M, 500K)
+        #[arg(long)]
+        per_task_limit: Option<String>,
+        /// Netscape 格式的 Cookie 文件 (cookies.txt)
+        #[arg(long, value_name = "FILE")]
+        cookies: Option<PathBuf>,
+        /// 只使用 IPv4 连接（默认使用配置中的 ip_policy）
+        #[arg(short = '4', long, conflicts_with = "ipv6")]
+        ipv4: bool,
+        /// 只使用 IPv6 连接
+        #[arg(short = '6', long)]
+        ipv6: bool,
     },
     /// 暂停任务
     Pause {
@@ -117,13 +387,91 @@ pub enum QueueCommands {
         /// 任务 ID
         task_id: String,
     },
+    /// 临时解除任务的速度限制，任务已在加速时延长加速时间
+    Boost {
+        /// 任务 ID
+        task_id: String,
+        /// 加速时长 (例如: 30s, 5m)
+        #[arg(value_name = "DURATION", value_parser = humantime::parse_duration)]
+        duration: Duration,
+    },
+    /// 重试失败、已取消或部分完成的任务（默认沿用上次的下载环境）
+    ///
+    /// 部分完成的任务只重新下载缺失的范围。
+    Retry {
+        /// 任务 ID
+        task_id: String,
+        /// 不沿用记录的下载环境，按当前配置重新下载
+        #[arg(long)]
+        fresh: bool,
+    },
     /// 移除任务
     Remove {
         /// 任务 ID
         task_id: String,
+        /// 同时删除下载的文件和完成后创建的链接
+        #[arg(long)]
+        delete_file: bool,
+    },
+    /// 预计等待中的任务依次在什么时候开始和完成（不下载）
+    Plan {
+        /// 假定的总下载速度 (例如: 10M)，默认使用总速度限制
+        #[arg(long)]
+        speed: Option<String>,
     },
     /// 清空已完成任务
     Clear,
+    /// 查找（并删除）没有任务引用的状态文件和未下载完的文件
+    Gc {
+        /// 要扫描的目录，默认为默认输出目录和所有任务所在的目录
+        dirs: Vec<PathBuf>,
+        /// 删除找到的文件，不指定时只列出
+        #[arg(long)]
+        delete: bool,
+    },
+    /// 重新校验所有已完成且设置了校验和的任务
+    VerifyAll {
+        /// 同时校验的任务数
+        #[arg(short, long, default_value = "2")]
+        concurrency: usize,
+        /// 只校验 URL、路径、名称或备注包含该文本的任务
+        #[arg(short, long)]
+        search: Option<String>,
+    },
+}
+
+#[derive(Parser)]
+pub struct HistoryArgs {
+    #[command(subcommand)]
+    pub command: HistoryCommands,
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommands {
+    /// 列出下载历史
+    List {
+        /// 最多显示的条数
+        #[arg(short = 'n', long, default_value = "20")]
+        limit: usize,
+    },
+    /// 从审计日志导入下载历史
+    ImportAudit {
+        /// 审计日志文件 (JSON Lines)
+        file: PathBuf,
+    },
+    /// 把历史记录中的下载重新加入队列（默认沿用原来的下载环境）
+    Redownload {
+        /// 任务 ID
+        task_id: String,
+        /// 输出文件路径，默认为原来的路径
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// 不沿用记录的下载环境，按当前配置下载
+        #[arg(long)]
+        fresh: bool,
+    },
+    /// 清空下载历史
+    Clear,
 }
 
 #[derive(Parser)]
@@ -134,9 +482,9 @@ pub struct ConfigArgs {
 
 #[derive(Subcommand)]
 pub enum ConfigCommands {
-    /// 显示当前配置
+    /// 显示当前配置，指定 --profile 时显示应用该配置档案后的结果
     Show,
-    /// 设置配置项
+    /// 设置配置项，指定 --profile 时写入该配置档案（不存在时创建）
     Set {
         /// 配置键
         key: String,
@@ -145,4 +493,20 @@ pub enum ConfigCommands {
     },
     /// 重置配置
     Reset,
+    /// 保存主机的认证信息，发往匹配主机的请求自动带上认证头
+    SetCredential {
+        /// 主机模式 (例如: example.com, example.com:8443, *.example.com)
+        host: String,
+        /// 使用 Bearer 令牌而不是用户名和密码
+        #[arg(long)]
+        bearer: bool,
+        /// 用户名，不指定时提示输入
+        #[arg(short, long, conflicts_with = "bearer")]
+        username: Option<String>,
+    },
+    /// 删除主机的认证信息
+    RemoveCredential {
+        /// 主机模式
+        host: String,
+    },
 }
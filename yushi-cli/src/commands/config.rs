@@ -1,23 +1,40 @@
 use crate::{
     cli::{ConfigArgs, ConfigCommands},
-    config::Config,
-    ui::{print_error, print_info, print_success},
+    config::{Config, Profile},
+    context::{load_config, profile},
+    ui::{print_error, print_info, print_success, print_warning},
 };
-use anyhow::Result;
-use console::style;
+use anyhow::{Result, anyhow};
+use console::{Term, style};
+use std::path::PathBuf;
+use yushi_core::{CaseInsensitiveFs, ChunkFailurePolicy, Credential, CredentialStore, IpPolicy};
 
 pub async fn execute(args: ConfigArgs) -> Result<()> {
     match args.command {
         ConfigCommands::Show => show_config().await,
         ConfigCommands::Set { key, value } => set_config(key, value).await,
         ConfigCommands::Reset => reset_config().await,
+        ConfigCommands::SetCredential {
+            host,
+            bearer,
+            username,
+        } => set_credential(host, bearer, username).await,
+        ConfigCommands::RemoveCredential { host } => remove_credential(host).await,
     }
 }
 
 async fn show_config() -> Result<()> {
-    let config = Config::load()?;
+    let config = load_config()?;
 
-    println!("\n{}", style("当前配置").bold().underlined());
+    match profile() {
+        Some(name) => println!(
+            "\n{}",
+            style(format!("当前配置 (配置档案: {})", name))
+                .bold()
+                .underlined()
+        ),
+        None => println!("\n{}", style("当前配置").bold().underlined()),
+    }
     println!();
     println!("  默认并发连接数: {}", config.default_connections);
     println!("  默认最大任务数: {}", config.default_max_tasks);
@@ -35,9 +52,65 @@ async fn show_config() -> Result<()> {
         println!("  速度限制: {}", limit);
     }
 
+    if let Some(path) = &config.audit_log {
+        println!("  审计日志: {}", path.display());
+    }
+
+    if let Some(path) = &config.temp_dir {
+        println!("  临时目录: {}", path.display());
+    }
+
+    println!("  文件名不区分大小写: {:?}", config.case_insensitive_fs);
+    println!("  IP 协议: {:?}", config.ip_policy);
+    println!("  分块重试用尽: {:?}", config.on_chunk_exhausted);
+    println!(
+        "  事件记录: {}",
+        if config.event_journal {
+            "开启"
+        } else {
+            "关闭"
+        }
+    );
+
+    if let Some(dir) = &config.completed_link_dir {
+        println!(
+            "  完成后链接到: {}{}",
+            dir.display(),
+            if config.completed_link_copy {
+                " (无法链接时复制)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    for (name, value) in &config.headers {
+        println!("  HTTP 头: {}: {}", name, value);
+    }
+
+    if let Some(path) = &config.ca_cert {
+        println!("  CA 证书: {}", path.display());
+    }
+
+    if config.insecure {
+        println!("  证书验证: {}", style("关闭").red());
+    }
+
+    if !config.profiles.is_empty() {
+        let names: Vec<_> = config.profiles.keys().map(String::as_str).collect();
+        println!("  配置档案: {}", names.join(", "));
+    }
+
+    let credentials = load_credentials().await?;
+    if !credentials.is_empty() {
+        println!("  已保存认证信息的主机: {}", credentials.hosts().join(", "));
+    }
+
     println!();
     println!("配置文件: {}", Config::config_path()?.display());
     println!("队列文件: {}", Config::queue_state_path()?.display());
+    println!("历史文件: {}", Config::history_path()?.display());
+    println!("认证文件: {}", Config::credentials_path()?.display());
 
     Ok(())
 }
@@ -45,47 +118,218 @@ async fn show_config() -> Result<()> {
 async fn set_config(key: String, value: String) -> Result<()> {
     let mut config = Config::load()?;
 
+    // 先把要修改的项记成一组设置，再写入基础配置或配置档案
+    let mut change = Profile::default();
     match key.as_str() {
         "connections" => {
-            config.default_connections = value.parse()?;
+            change.default_connections = Some(value.parse()?);
             print_success(&format!("默认并发连接数已设置为: {}", value));
         }
         "max_tasks" => {
-            config.default_max_tasks = value.parse()?;
+            change.default_max_tasks = Some(value.parse()?);
             print_success(&format!("默认最大任务数已设置为: {}", value));
         }
         "output_dir" => {
-            config.default_output_dir = value.into();
-            print_success(&format!(
-                "默认输出目录已设置为: {}",
-                config.default_output_dir.display()
-            ));
+            let dir = PathBuf::from(value);
+            print_success(&format!("默认输出目录已设置为: {}", dir.display()));
+            change.default_output_dir = Some(dir);
         }
         "user_agent" => {
-            config.user_agent = Some(value.clone());
+            change.user_agent = Some(value.clone());
             print_success(&format!("User-Agent 已设置为: {}", value));
         }
         "proxy" => {
-            config.proxy = Some(value.clone());
+            change.proxy = Some(value.clone());
             print_success(&format!("代理已设置为: {}", value));
         }
         "speed_limit" => {
-            config.speed_limit = Some(value.clone());
+            change.speed_limit = Some(value.clone());
             print_success(&format!("速度限制已设置为: {}", value));
         }
+        "audit_log" => {
+            let path = PathBuf::from(value);
+            print_success(&format!("审计日志已设置为: {}", path.display()));
+            change.audit_log = Some(path);
+        }
+        "temp_dir" => {
+            let path = PathBuf::from(value);
+            print_success(&format!("临时目录已设置为: {}", path.display()));
+            change.temp_dir = Some(path);
+        }
+        "case_insensitive_fs" => {
+            let mode = match value.to_lowercase().as_str() {
+                "auto" => CaseInsensitiveFs::Auto,
+                "on" => CaseInsensitiveFs::On,
+                "off" => CaseInsensitiveFs::Off,
+                _ => return Err(anyhow!("无效的值: {} (可选 auto, on, off)", value)),
+            };
+            print_success(&format!("文件名不区分大小写已设置为: {:?}", mode));
+            change.case_insensitive_fs = Some(mode);
+        }
+        "ip_policy" => {
+            let policy = match value.to_lowercase().as_str() {
+                "auto" => IpPolicy::Auto,
+                "prefer-ipv4" => IpPolicy::PreferIpv4,
+                "prefer-ipv6" => IpPolicy::PreferIpv6,
+                "ipv4-only" => IpPolicy::Ipv4Only,
+                "ipv6-only" => IpPolicy::Ipv6Only,
+                _ => {
+                    return Err(anyhow!(
+                        "无效的值: {} (可选 auto, prefer-ipv4, prefer-ipv6, ipv4-only, ipv6-only)",
+                        value
+                    ));
+                }
+            };
+            print_success(&format!("IP 协议已设置为: {:?}", policy));
+            change.ip_policy = Some(policy);
+        }
+        "on_chunk_exhausted" => {
+            let policy = match value.to_lowercase().as_str() {
+                "fail" => ChunkFailurePolicy::FailTask,
+                "holes" => ChunkFailurePolicy::CompleteWithHoles,
+                _ => return Err(anyhow!("无效的值: {} (可选 fail, holes)", value)),
+            };
+            print_success(&format!("分块重试用尽时的处理方式已设置为: {:?}", policy));
+            change.on_chunk_exhausted = Some(policy);
+        }
+        "event_journal" => {
+            let enabled = match value.to_lowercase().as_str() {
+                "true" | "on" => true,
+                "false" | "off" => false,
+                _ => return Err(anyhow!("无效的值: {} (可选 true, false)", value)),
+            };
+            print_success(&format!(
+                "事件记录已{}",
+                if enabled { "开启" } else { "关闭" }
+            ));
+            change.event_journal = Some(enabled);
+        }
+        "completed_link_dir" => {
+            let dir = PathBuf::from(value);
+            if !dir.is_dir() {
+                return Err(anyhow!("目录不存在: {}", dir.display()));
+            }
+            print_success(&format!("完成后链接到: {}", dir.display()));
+            change.completed_link_dir = Some(dir);
+        }
+        "completed_link_copy" => {
+            let copy = match value.to_lowercase().as_str() {
+                "true" | "on" => true,
+                "false" | "off" => false,
+                _ => return Err(anyhow!("无效的值: {} (可选 true, false)", value)),
+            };
+            print_success(&format!(
+                "无法链接时{}复制文件",
+                if copy { "" } else { "不" }
+            ));
+            change.completed_link_copy = Some(copy);
+        }
+        "header" => {
+            let (name, header_value) = value
+                .split_once(':')
+                .ok_or_else(|| anyhow!("无效的 HTTP 头: {} (格式: \"Key: Value\")", value))?;
+            let (name, header_value) = (name.trim().to_string(), header_value.trim().to_string());
+            if header_value.is_empty() {
+                print_success(&format!("已去掉 HTTP 头: {}", name));
+            } else {
+                print_success(&format!("HTTP 头已设置为: {}: {}", name, header_value));
+            }
+            change.headers.insert(name, header_value);
+        }
+        "ca_cert" => {
+            let path = PathBuf::from(value);
+            if !path.is_file() {
+                return Err(anyhow!("文件不存在: {}", path.display()));
+            }
+            print_success(&format!("CA 证书已设置为: {}", path.display()));
+            change.ca_cert = Some(path);
+        }
+        "insecure" => {
+            let insecure = match value.to_lowercase().as_str() {
+                "true" | "on" => true,
+                "false" | "off" => false,
+                _ => return Err(anyhow!("无效的值: {} (可选 true, false)", value)),
+            };
+            if insecure {
+                print_warning("已关闭证书验证，连接可能被窃听或篡改");
+            } else {
+                print_success("已开启证书验证");
+            }
+            change.insecure = Some(insecure);
+        }
         _ => {
             print_error(&format!("未知的配置项: {}", key));
             print_info(
-                "可用的配置项: connections, max_tasks, output_dir, user_agent, proxy, speed_limit",
+                "可用的配置项: connections, max_tasks, output_dir, user_agent, proxy, speed_limit, audit_log, temp_dir, case_insensitive_fs, ip_policy, on_chunk_exhausted, event_journal, completed_link_dir, completed_link_copy, header, ca_cert, insecure",
             );
             return Ok(());
         }
     }
 
+    match profile() {
+        Some(name) => {
+            config
+                .profiles
+                .entry(name.to_string())
+                .or_default()
+                .merge(change);
+            print_info(&format!("已写入配置档案: {}", name));
+        }
+        None => config.apply(change),
+    }
     config.save()?;
     Ok(())
 }
 
+/// 读取保存的认证信息，文件不存在时返回空的集合
+async fn load_credentials() -> Result<CredentialStore> {
+    let path = Config::credentials_path()?;
+    if !path.exists() {
+        return Ok(CredentialStore::default());
+    }
+    Ok(CredentialStore::load(&path).await?)
+}
+
+async fn set_credential(host: String, bearer: bool, username: Option<String>) -> Result<()> {
+    let term = Term::stderr();
+    let credential = if bearer {
+        term.write_str("令牌: ")?;
+        Credential::Bearer {
+            token: term.read_secure_line()?,
+        }
+    } else {
+        let username = match username {
+            Some(username) => username,
+            None => {
+                term.write_str("用户名: ")?;
+                term.read_line()?
+            }
+        };
+        term.write_str("密码: ")?;
+        Credential::Basic {
+            username,
+            password: term.read_secure_line()?,
+        }
+    };
+
+    let credentials = load_credentials().await?;
+    credentials.set(&host, credential);
+    credentials.save(&Config::credentials_path()?).await?;
+    print_success(&format!("已保存 {} 的认证信息", host));
+    Ok(())
+}
+
+async fn remove_credential(host: String) -> Result<()> {
+    let credentials = load_credentials().await?;
+    if credentials.remove(&host) {
+        credentials.save(&Config::credentials_path()?).await?;
+        print_success(&format!("已删除 {} 的认证信息", host));
+    } else {
+        print_error(&format!("没有 {} 的认证信息", host));
+    }
+    Ok(())
+}
+
 async fn reset_config() -> Result<()> {
     let config = Config::default();
     config.save()?;
@@ -1,12 +1,20 @@
 use crate::{
     cli::DownloadArgs,
-    ui::{format_size, parse_speed_limit, print_error, print_info, print_success},
+    context::{cookie_source, load_config, load_credentials},
+    ui::{
+        format_mirror_demotion, format_restart_reason, format_size, parse_byte_range,
+        parse_speed_limit, print_error, print_info, print_plan, print_success, print_warning,
+        reserve_stdout,
+    },
 };
 use anyhow::{Result, anyhow};
 use indicatif::{ProgressBar, ProgressStyle};
-use std::path::PathBuf;
-use tokio::sync::mpsc;
-use yushi_core::{ChecksumType, DownloadConfig, ProgressEvent, YuShi};
+use std::path::{Path, PathBuf};
+use tokio::{sync::mpsc, task::JoinHandle};
+use yushi_core::{
+    ChecksumType, ConflictPolicy, Credential, DownloadConfig, IpPolicy, ProgressEvent, RetryReason,
+    TaskOptions, YuShi, filename_from_url, resolve_destination,
+};
 
 pub async fn execute(args: DownloadArgs) -> Result<()> {
     // 确定输出路径
@@ -14,27 +22,122 @@ pub async fn execute(args: DownloadArgs) -> Result<()> {
         path
     } else {
         // 从 URL 提取文件名
-        let filename = args
-            .url
-            .split('/')
-            .next_back()
-            .and_then(|s| s.split('?').next())
-            .unwrap_or("download");
-        PathBuf::from(filename)
+        resolve_destination(
+            Path::new(""),
+            &filename_from_url(&args.url).unwrap_or_default(),
+        )
+    };
+
+    let byte_range = match &args.range {
+        Some(range) => {
+            Some(parse_byte_range(range).ok_or_else(|| anyhow!("无效的范围: {}", range))?)
+        }
+        None => None,
+    };
+    // --md5 和 --sha256 可以同时指定，下载完成后依次校验；都没有时才使用 --checksum
+    let mut checksums: Vec<ChecksumType> = args
+        .md5
+        .clone()
+        .map(ChecksumType::Md5)
+        .into_iter()
+        .chain(args.sha256.clone().map(ChecksumType::Sha256))
+        .collect();
+    if checksums.is_empty()
+        && let Some(hex) = &args.checksum
+    {
+        checksums.push(ChecksumType::parse_auto(hex)?);
+    }
+    let conflict = match args.if_changed {
+        true => ConflictPolicy::IfChanged,
+        false => ConflictPolicy::Overwrite,
+    };
+    // --user 和 --bearer 作为任务的认证信息，覆盖保存的主机认证信息
+    let auth = match (&args.user, &args.bearer) {
+        (Some(user), _) => {
+            let (username, password) = user
+                .split_once(':')
+                .ok_or_else(|| anyhow!("无效的认证信息: 格式应为 user:pass"))?;
+            Some(Credential::Basic {
+                username: username.to_string(),
+                password: password.to_string(),
+            })
+        }
+        (None, Some(token)) => Some(Credential::Bearer {
+            token: token.clone(),
+        }),
+        (None, None) => None,
+    };
+    let options = TaskOptions {
+        byte_range,
+        conflict,
+        auth,
+        ..Default::default()
     };
 
-    print_info(&format!("下载: {}", args.url));
-    print_info(&format!("保存到: {}", output.display()));
+    // 写到标准输出时，提示信息改为写到标准错误
+    let to_stdout = output == Path::new("-");
+    if to_stdout {
+        if args.if_changed {
+            return Err(anyhow!("--if-changed 不能用于标准输出"));
+        }
+        if checksums.len() > 1 {
+            return Err(anyhow!("写到标准输出时只能使用一种校验和"));
+        }
+        reserve_stdout();
+    }
+
+    if !args.json && (!to_stdout || !args.quiet) {
+        print_info(&format!("下载: {}", args.url));
+        print_info(&format!("保存到: {}", output.display()));
+        if let Some(range) = &args.range {
+            print_info(&format!("下载范围: {}", range));
+        }
+    }
 
-    // 构建配置
+    // 构建配置，命令行参数覆盖配置（和配置档案）中的设置
+    let cli_config = load_config()?;
+    let defaults = DownloadConfig::default();
     let mut config = DownloadConfig {
         max_concurrent: args.connections,
-        ..Default::default()
+        speed_limit: cli_config
+            .speed_limit
+            .as_deref()
+            .and_then(parse_speed_limit),
+        user_agent: cli_config.user_agent.or(defaults.user_agent.clone()),
+        proxy: cli_config.proxy,
+        headers: cli_config.headers.into_iter().collect(),
+        audit_log: cli_config.audit_log,
+        temp_dir: cli_config.temp_dir,
+        case_insensitive_fs: cli_config.case_insensitive_fs,
+        ip_policy: cli_config.ip_policy,
+        on_chunk_exhausted: cli_config.on_chunk_exhausted,
+        event_journal: cli_config.event_journal,
+        extra_root_certs: cli_config
+            .ca_cert
+            .into_iter()
+            .chain(args.cacert.clone())
+            .collect(),
+        danger_accept_invalid_certs: cli_config.insecure || args.insecure,
+        resolve: args.resolve.clone(),
+        local_address: args.local_address,
+        ..defaults
     };
 
+    if config.danger_accept_invalid_certs && !args.json {
+        print_warning("不验证服务器证书，连接可能被窃听或篡改");
+    }
+
+    if args.ipv4 {
+        config.ip_policy = IpPolicy::Ipv4Only;
+    } else if args.ipv6 {
+        config.ip_policy = IpPolicy::Ipv6Only;
+    }
+
     if let Some(limit_str) = &args.speed_limit {
         config.speed_limit = parse_speed_limit(limit_str);
-        if let Some(limit) = config.speed_limit {
+        if let Some(limit) = config.speed_limit
+            && !args.json
+        {
             print_info(&format!("速度限制: {}/s", format_size(limit)));
         }
     }
@@ -45,7 +148,13 @@ pub async fn execute(args: DownloadArgs) -> Result<()> {
 
     if let Some(proxy) = &args.proxy {
         config.proxy = Some(proxy.clone());
-        print_info(&format!("使用代理: {}", proxy));
+        if !args.json {
+            print_info(&format!("使用代理: {}", proxy));
+        }
+    }
+
+    if let Some(path) = &args.cookies {
+        config.cookies = Some(cookie_source(path)?);
     }
 
     // 解析自定义头
@@ -62,28 +171,139 @@ pub async fn execute(args: DownloadArgs) -> Result<()> {
     let queue_state_path = temp_dir.join(format!("yushi_temp_{}.json", std::process::id()));
 
     // 创建下载器
-    let (downloader, _) = YuShi::with_config(config, 1, queue_state_path.clone());
-    let (tx, mut rx) = mpsc::channel(1024);
+    let (downloader, _) = YuShi::with_config(config, 1, queue_state_path.clone())?;
+    load_credentials(&downloader).await?;
+
+    if args.dry_run {
+        let plan = match downloader.plan_task(&args.url, &output, &options).await {
+            Ok(plan) => plan,
+            Err(e) => {
+                print_error(&format!("探测失败: {}", e));
+                return Err(e.into());
+            }
+        };
+        return print_plan(&plan, args.json);
+    }
+
+    let (tx, rx) = mpsc::channel(1024);
 
     // 进度显示
-    let quiet = args.quiet;
-    let progress_handle = tokio::spawn(async move {
+    let progress_handle = show_progress(rx, output.clone(), args.quiet);
+
+    if to_stdout {
+        // 数据写出后无法撤回，校验和在写入的同时计算，全部写出后才知道结果
+        let options = TaskOptions {
+            checksum: checksums.pop(),
+            ..options
+        };
+        let result = downloader
+            .download_to_writer(&args.url, tokio::io::stdout(), options, Some(tx))
+            .await;
+        let _ = std::fs::remove_file(queue_state_path);
+        downloader.flush_audit_log().await;
+        progress_handle.await?;
+        return match result {
+            Ok(summary) => {
+                if !args.quiet {
+                    print_success(&format!(
+                        "已写到标准输出: {}",
+                        format_size(summary.total_size)
+                    ));
+                }
+                Ok(())
+            }
+            Err(e) => {
+                print_error(&format!("下载失败: {}", e));
+                Err(e.into())
+            }
+        };
+    }
+
+    // 执行下载
+    let result = downloader
+        .download_with(&args.url, &output, options, Some(tx))
+        .await;
+
+    // 清理临时队列状态文件
+    let _ = std::fs::remove_file(queue_state_path);
+    downloader.flush_audit_log().await;
+
+    progress_handle.await?;
+
+    match result {
+        Ok(summary) => {
+            if summary.skipped_unchanged && !args.quiet {
+                print_info("远程文件没有变化，跳过下载");
+            }
+
+            // 文件校验
+            for checksum in &checksums {
+                let algorithm = checksum.algorithm();
+                print_info(&format!("验证 {}...", algorithm));
+                match yushi_core::verify_file(&summary.dest, checksum).await {
+                    Ok(true) => print_success(&format!("{} 校验通过", algorithm)),
+                    Ok(false) => {
+                        print_error(&format!("{} 校验失败", algorithm));
+                        return Err(anyhow!("{} 校验失败", algorithm));
+                    }
+                    Err(e) => {
+                        print_error(&format!("{} 校验错误: {}", algorithm, e));
+                        return Err(e.into());
+                    }
+                }
+            }
+
+            print_success(&format!("文件已保存到: {}", summary.dest.display()));
+            Ok(())
+        }
+        Err(e) => {
+            print_error(&format!("下载失败: {}", e));
+            Err(e.into())
+        }
+    }
+}
+
+/// 显示单个下载任务的进度条，收到 [`ProgressEvent`] 直到发送端关闭
+///
+/// `output` 是请求的保存路径，实际路径不同（例如冲突时自动重命名）时会提示。
+pub fn show_progress(
+    mut rx: mpsc::Receiver<ProgressEvent>,
+    output: PathBuf,
+    quiet: bool,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
         let mut pb: Option<ProgressBar> = None;
         let mut downloaded = 0u64;
+        // 本次运行完成的分块数（不包括上次已完成的分块）
+        let mut chunks_finished = 0usize;
 
         while let Some(event) = rx.recv().await {
             match event {
-                ProgressEvent::Initialized { total_size, .. } => {
+                ProgressEvent::Initialized {
+                    total_size,
+                    resumed_from,
+                    final_path,
+                    ..
+                } => {
+                    downloaded = resumed_from;
+                    chunks_finished = 0;
                     if !quiet {
+                        if final_path != output {
+                            print_info(&format!("实际保存到: {}", final_path.display()));
+                        }
+                        if resumed_from > 0 {
+                            print_info(&format!("从 {} 处继续下载", format_size(resumed_from)));
+                        }
                         if let Some(size) = total_size {
                             // 分块下载，已知文件大小
                             let bar = ProgressBar::new(size);
                             bar.set_style(
                                 ProgressStyle::default_bar()
-                                    .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
+                                    .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}")
                                     .unwrap()
                                     .progress_chars("#>-"),
                             );
+                            bar.set_position(resumed_from);
                             pb = Some(bar);
                         } else {
                             // 流式下载，未知文件大小
@@ -139,61 +359,67 @@ pub async fn execute(args: DownloadArgs) -> Result<()> {
                         bar.finish_with_message(format!("下载失败: {}", error));
                     }
                 }
-            }
-        }
-    });
-
-    // 执行下载
-    let result = downloader
-        .download(&args.url, output.to_str().unwrap(), Some(tx))
-        .await;
-
-    // 清理临时队列状态文件
-    let _ = std::fs::remove_file(queue_state_path);
-
-    progress_handle.await?;
-
-    match result {
-        Ok(_) => {
-            // 文件校验
-            if let Some(md5) = args.md5 {
-                print_info("验证 MD5...");
-                let checksum = ChecksumType::Md5(md5);
-                match yushi_core::verify_file(&output, &checksum).await {
-                    Ok(true) => print_success("MD5 校验通过"),
-                    Ok(false) => {
-                        print_error("MD5 校验失败");
-                        return Err(anyhow!("MD5 校验失败"));
+                ProgressEvent::Retrying {
+                    attempt,
+                    reason:
+                        reason @ (RetryReason::ConnectionStalled { chunk_index }
+                        | RetryReason::ConnectionLost { chunk_index }),
+                    ..
+                } => {
+                    // 只重新请求这个分块，进度条保持不变
+                    let cause = match reason {
+                        RetryReason::ConnectionLost { .. } => "中断",
+                        _ => "停滞",
+                    };
+                    let text = format!(
+                        "分块 {} 的连接{}，重新连接 (第 {} 次)",
+                        chunk_index, cause, attempt
+                    );
+                    match &pb {
+                        Some(bar) if !quiet => bar.suspend(|| print_warning(&text)),
+                        None if !quiet => print_warning(&text),
+                        _ => {}
                     }
-                    Err(e) => {
-                        print_error(&format!("MD5 校验错误: {}", e));
-                        return Err(e.into());
+                }
+                ProgressEvent::Retrying { attempt, .. } => {
+                    // 重试时会重新发送 Initialized
+                    if let Some(bar) = pb.take() {
+                        bar.finish_and_clear();
+                    }
+                    if !quiet {
+                        print_warning(&format!("长时间没有收到数据，重新连接 (第 {} 次)", attempt));
                     }
                 }
-            }
-
-            if let Some(sha256) = args.sha256 {
-                print_info("验证 SHA256...");
-                let checksum = ChecksumType::Sha256(sha256);
-                match yushi_core::verify_file(&output, &checksum).await {
-                    Ok(true) => print_success("SHA256 校验通过"),
-                    Ok(false) => {
-                        print_error("SHA256 校验失败");
-                        return Err(anyhow!("SHA256 校验失败"));
+                ProgressEvent::Restarted { reason, .. } => {
+                    // 之后会重新发送 Initialized
+                    if let Some(bar) = pb.take() {
+                        bar.finish_and_clear();
                     }
-                    Err(e) => {
-                        print_error(&format!("SHA256 校验错误: {}", e));
-                        return Err(e.into());
+                    if !quiet {
+                        print_warning(format_restart_reason(reason));
                     }
                 }
+                ProgressEvent::MirrorDemoted { mirror, reason, .. } => {
+                    let text = format_mirror_demotion(&mirror, reason);
+                    match &pb {
+                        Some(bar) if !quiet => bar.suspend(|| print_warning(&text)),
+                        None if !quiet => print_warning(&text),
+                        _ => {}
+                    }
+                }
+                ProgressEvent::Finalizing { moved, .. } => {
+                    if moved == 0 && !quiet {
+                        print_info(&format!("正在移动到: {}", output.display()));
+                    }
+                }
+                ProgressEvent::ChunkFinished { .. } => {
+                    chunks_finished += 1;
+                    if let Some(ref bar) = pb {
+                        bar.set_message(format!("{} 个分块已完成", chunks_finished));
+                    }
+                }
+                ProgressEvent::PrefixFlushed { .. } => {}
             }
-
-            print_success(&format!("文件已保存到: {}", output.display()));
-            Ok(())
-        }
-        Err(e) => {
-            print_error(&format!("下载失败: {}", e));
-            Err(e.into())
         }
-    }
+    })
 }
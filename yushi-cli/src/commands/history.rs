@@ -0,0 +1,142 @@
+use crate::{
+    cli::{HistoryArgs, HistoryCommands},
+    config::Config,
+    context::{Overrides, build_queue},
+    ui::{format_size, print_info, print_success},
+};
+use anyhow::{Result, anyhow};
+use console::style;
+use std::path::PathBuf;
+use yushi_core::{AuditRecord, TaskStatus, read_audit_log};
+
+pub async fn execute(args: HistoryArgs) -> Result<()> {
+    match args.command {
+        HistoryCommands::List { limit } => list_history(limit).await,
+        HistoryCommands::ImportAudit { file } => import_audit(file).await,
+        HistoryCommands::Redownload {
+            task_id,
+            output,
+            fresh,
+        } => redownload(task_id, output, fresh).await,
+        HistoryCommands::Clear => clear_history().await,
+    }
+}
+
+fn load_history() -> Result<Vec<AuditRecord>> {
+    let path = Config::history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_history(history: &[AuditRecord]) -> Result<()> {
+    let path = Config::history_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(history)?)?;
+    Ok(())
+}
+
+async fn list_history(limit: usize) -> Result<()> {
+    let history = load_history()?;
+    if history.is_empty() {
+        print_info("下载历史为空");
+        return Ok(());
+    }
+
+    println!("\n{}", style("下载历史").bold().underlined());
+    println!();
+
+    for record in history.iter().take(limit) {
+        let status_str = match record.status {
+            TaskStatus::Completed => style("已完成").green(),
+            TaskStatus::Failed => style("失败").red(),
+            _ => style("已取消").red(),
+        };
+        println!("{} {}", style("●").bold(), status_str);
+        println!("  URL: {}", record.url);
+        println!("  输出: {}", record.dest.display());
+        println!(
+            "  大小: {}  耗时: {}",
+            format_size(record.bytes),
+            humantime::format_duration(std::time::Duration::from_secs(record.duration_ms / 1000))
+        );
+        if record.average_speed > 0 {
+            println!(
+                "  平均速度: {}/s  最高速度: {}/s",
+                format_size(record.average_speed),
+                format_size(record.peak_speed)
+            );
+        }
+        if let Some(error) = &record.error {
+            println!("  {}: {}", style("错误").red(), error);
+        }
+        println!();
+    }
+
+    if history.len() > limit {
+        print_info(&format!("还有 {} 条记录未显示", history.len() - limit));
+    }
+    Ok(())
+}
+
+async fn import_audit(file: PathBuf) -> Result<()> {
+    let records = read_audit_log(&file).await?;
+    let mut history = load_history()?;
+
+    // 同一任务的同一次结束只导入一次，重复导入同一个文件不会产生重复记录
+    let mut imported = 0;
+    for record in records {
+        let exists = history.iter().any(|r| {
+            r.task_id == record.task_id
+                && r.finished_at == record.finished_at
+                && r.status == record.status
+        });
+        if !exists {
+            history.push(record);
+            imported += 1;
+        }
+    }
+    history.sort_by_key(|r| std::cmp::Reverse(r.finished_at));
+    save_history(&history)?;
+
+    print_success(&format!("从 {} 导入了 {} 条记录", file.display(), imported));
+    Ok(())
+}
+
+/// 把历史记录中的下载重新加入队列
+async fn redownload(task_id: String, output: Option<PathBuf>, fresh: bool) -> Result<()> {
+    let history = load_history()?;
+    let record = history
+        .iter()
+        .find(|r| r.task_id == task_id)
+        .ok_or_else(|| anyhow!("历史记录中没有该任务: {}", task_id))?;
+    let provenance = record.provenance.as_ref().filter(|_| !fresh);
+
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+    // 只加入队列，由 queue start 开始下载
+    queue.pause_queue().await;
+    let new_id = queue
+        .redownload(
+            record.url.clone(),
+            output.unwrap_or_else(|| record.dest.clone()),
+            provenance,
+        )
+        .await?;
+
+    print_success(&format!("已加入队列: {}", new_id));
+    if provenance.is_some() {
+        print_info("沿用原来的下载环境");
+    }
+    Ok(())
+}
+
+async fn clear_history() -> Result<()> {
+    save_history(&[])?;
+    print_success("下载历史已清空");
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use crate::{
+    cli::InfoArgs,
+    ui::{format_size, print_error, print_info},
+};
+use anyhow::Result;
+use console::style;
+use yushi_core::{DownloadConfig, YuShi};
+
+pub async fn execute(args: InfoArgs) -> Result<()> {
+    let config = DownloadConfig {
+        proxy: args.proxy.clone(),
+        ..Default::default()
+    };
+
+    // 不使用队列，状态文件不会被写入
+    let queue_state_path =
+        std::env::temp_dir().join(format!("yushi_temp_{}.json", std::process::id()));
+    let (downloader, _) = YuShi::with_config(config, 1, queue_state_path)?;
+
+    let info = match downloader.probe(&args.url).await {
+        Ok(info) => info,
+        Err(e) => {
+            print_error(&format!("获取信息失败: {}", e));
+            return Err(e.into());
+        }
+    };
+
+    println!("\n{}", style("文件信息").bold().underlined());
+    println!();
+    println!("  URL: {}", args.url);
+    match info.total_size {
+        Some(size) => println!("  大小: {}", format_size(size)),
+        None => println!("  大小: 未知"),
+    }
+    println!(
+        "  断点续传/多连接: {}",
+        if info.supports_range {
+            style("支持").green()
+        } else {
+            style("不支持").yellow()
+        }
+    );
+
+    let Some(duration) = args.measure else {
+        println!();
+        return Ok(());
+    };
+
+    println!();
+    print_info(&format!(
+        "测速中 ({})...",
+        humantime::format_duration(duration)
+    ));
+
+    let report = match downloader.measure(&args.url, duration).await {
+        Ok(report) => report,
+        Err(e) => {
+            print_error(&format!("测速失败: {}", e));
+            return Err(e.into());
+        }
+    };
+
+    println!("\n{}", style("测速结果").bold().underlined());
+    println!();
+    for sample in &report.connections_tested {
+        println!(
+            "  {:>2} 个连接: {}/s ({})",
+            sample.connections,
+            format_size(sample.speed),
+            format_size(sample.bytes)
+        );
+    }
+    println!();
+    println!("  平均速度: {}/s", format_size(report.avg_speed));
+    println!("  已下载: {}", format_size(report.bytes));
+    println!("  首字节延迟 (P95): {} ms", report.p95_latency.as_millis());
+
+    let suggested = report.suggested_connections();
+    println!();
+    print_info(&format!(
+        "建议使用 {} 个连接: yushi download {} --connections {}",
+        suggested, args.url, suggested
+    ));
+    println!();
+
+    Ok(())
+}
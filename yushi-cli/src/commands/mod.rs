@@ -1,3 +1,5 @@
 pub mod config;
 pub mod download;
+pub mod history;
+pub mod info;
 pub mod queue;
@@ -1,15 +1,30 @@
 use crate::{
     cli::{QueueArgs, QueueCommands},
-    config::Config,
-    ui::{ProgressManager, format_size, print_info, print_success},
+    commands::download::show_progress,
+    context::{Overrides, build_queue, load_config, profile},
+    table::{self, TABLE_COLUMNS, WIDE_COLUMNS, parse_columns},
+    timeline,
+    ui::{
+        ProgressManager, format_byte_range, format_eta, format_mirror_demotion,
+        format_restart_reason, format_scheduler, format_size, parse_byte_range, parse_checksum,
+        parse_speed_limit, print_error, print_info, print_plan, print_provenance, print_success,
+        print_warning,
+    },
 };
 use anyhow::{Result, anyhow};
-use console::style;
-use std::path::PathBuf;
+use console::{Term, style};
+use std::{path::PathBuf, time::Duration};
+use tokio::sync::mpsc;
 use yushi_core::{
-    ChecksumType, DownloaderEvent, Priority, ProgressEvent, TaskEvent, VerificationEvent, YuShi,
+    ChecksumType, ChunkIssue, ChunkOrder, CompactEvent, CompactEventKind, ConflictPolicy,
+    DownloadTask, DownloaderEvent, Forecast, GlobalEvent, IndexOptions, IpPolicy, OrphanKind,
+    Priority, ProgressEvent, RemoveOptions, RetryReason, TaskEvent, TaskKind, TaskOptions,
+    TaskQuery, TaskSort, TaskStatus, VerificationEvent, YuShi, i18n,
 };
 
+/// 收到中断信号后等待下载保存进度的最长时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub async fn execute(args: QueueArgs) -> Result<()> {
     match args.command {
         QueueCommands::Add {
@@ -18,134 +33,1126 @@ pub async fn execute(args: QueueArgs) -> Result<()> {
             priority,
             md5,
             sha256,
-        } => add_task(url, output, priority, md5, sha256).await,
-        QueueCommands::List => list_tasks().await,
+            checksum,
+            range,
+            name,
+            note,
+            skip_probe,
+            mirrors,
+            speed_limit,
+            deadline,
+            if_changed,
+            now,
+            cookies,
+            dry_run,
+            json,
+        } => {
+            let checksum = parse_checksum(md5, sha256, checksum)?;
+            let speed_limit = match speed_limit {
+                Some(limit) => Some(
+                    parse_speed_limit(&limit)
+                        .ok_or_else(|| anyhow!("无效的速度限制: {}", limit))?,
+                ),
+                None => None,
+            };
+
+            let byte_range = match range {
+                Some(range) => {
+                    Some(parse_byte_range(&range).ok_or_else(|| anyhow!("无效的范围: {}", range))?)
+                }
+                None => None,
+            };
+
+            // 冲突时自动重命名，指定 --if-changed 时只在远程文件变化后覆盖
+            let conflict = match if_changed {
+                true => ConflictPolicy::IfChanged,
+                false => ConflictPolicy::Rename,
+            };
+            let options = TaskOptions {
+                priority,
+                checksum,
+                conflict,
+                byte_range,
+                display_name: name,
+                note,
+                skip_probe,
+                speed_limit,
+                deadline,
+                mirrors,
+                ..Default::default()
+            };
+            let overrides = Overrides {
+                cookies,
+                ..Default::default()
+            };
+            if dry_run {
+                plan_task(url, output, options, overrides, json).await
+            } else {
+                add_task(url, output, options, overrides, now).await
+            }
+        }
+        QueueCommands::AddVerify {
+            file,
+            priority,
+            md5,
+            sha256,
+            checksum,
+        } => {
+            let checksum =
+                parse_checksum(md5, sha256, checksum)?.ok_or_else(|| anyhow!("需要指定校验和"))?;
+            add_verify_task(file, checksum, priority).await
+        }
+        QueueCommands::AddIndex {
+            url,
+            output,
+            depth,
+            include,
+            exclude,
+            any_host,
+        } => {
+            let options = IndexOptions {
+                depth,
+                include_glob: include,
+                exclude_glob: exclude,
+                same_host_only: !any_host,
+            };
+            add_from_index(url, output, options).await
+        }
+        QueueCommands::List {
+            by_group,
+            search,
+            forecast,
+            summary,
+            archived,
+            format,
+            columns,
+            sort,
+            reverse,
+            status,
+        } => {
+            if summary {
+                return print_summary().await;
+            }
+            let query = TaskQuery {
+                statuses: status
+                    .iter()
+                    .map(|s| parse_status(s))
+                    .collect::<Result<_>>()?,
+                search,
+                include_archived: archived,
+                sort: parse_sort(&sort),
+                descending: reverse,
+            };
+            match format {
+                Some(format) => print_task_list(query, &format, &columns).await,
+                None => list_tasks(by_group, query, forecast).await,
+            }
+        }
+        QueueCommands::Show {
+            task_id,
+            provenance,
+        } => show_task(task_id, provenance).await,
+        QueueCommands::Diag {
+            task_id,
+            json,
+            show_query,
+            refetch_chunk,
+        } => {
+            if refetch_chunk.is_empty() {
+                diag_task(task_id, json, show_query).await
+            } else {
+                refetch_chunks(task_id, refetch_chunk).await
+            }
+        }
+        QueueCommands::Edit {
+            task_id,
+            name,
+            note,
+        } => edit_task(task_id, name, note).await,
         QueueCommands::Start {
             max_tasks,
             connections,
-        } => start_queue(max_tasks, connections).await,
+            speed_limit,
+            per_task_limit,
+            cookies,
+            ipv4,
+            ipv6,
+        } => {
+            let ip_policy = match (ipv4, ipv6) {
+                (true, _) => Some(IpPolicy::Ipv4Only),
+                (_, true) => Some(IpPolicy::Ipv6Only),
+                _ => None,
+            };
+            let overrides = Overrides {
+                max_tasks,
+                connections,
+                speed_limit: per_task_limit,
+                cookies,
+                ip_policy,
+                ..Default::default()
+            };
+            start_queue(overrides, speed_limit).await
+        }
         QueueCommands::Pause { task_id } => pause_task(task_id).await,
         QueueCommands::Resume { task_id } => resume_task(task_id).await,
         QueueCommands::Cancel { task_id } => cancel_task(task_id).await,
-        QueueCommands::Remove { task_id } => remove_task(task_id).await,
+        QueueCommands::Boost { task_id, duration } => boost_task(task_id, duration).await,
+        QueueCommands::Retry { task_id, fresh } => retry_task(task_id, fresh).await,
+        QueueCommands::Remove {
+            task_id,
+            delete_file,
+        } => remove_task(task_id, delete_file).await,
+        QueueCommands::Plan { speed } => plan_queue(speed).await,
         QueueCommands::Clear => clear_completed().await,
+        QueueCommands::Gc { dirs, delete } => gc(dirs, delete).await,
+        QueueCommands::VerifyAll {
+            concurrency,
+            search,
+        } => verify_all(concurrency, search).await,
     }
 }
 
 async fn add_task(
     url: String,
     output: PathBuf,
-    priority_str: String,
-    md5: Option<String>,
-    sha256: Option<String>,
+    options: TaskOptions,
+    overrides: Overrides,
+    now: bool,
 ) -> Result<()> {
-    let queue_path = Config::queue_state_path()?;
-    let (queue, _) = YuShi::new(4, 1, queue_path);
+    let (queue, _) = build_queue(overrides).await?;
 
     // 加载现有队列
     queue.load_queue_from_state().await?;
-
-    // 解析优先级
-    let priority = match priority_str.to_lowercase().as_str() {
-        "low" => Priority::Low,
-        "normal" => Priority::Normal,
-        "high" => Priority::High,
-        _ => return Err(anyhow!("无效的优先级: {}", priority_str)),
-    };
-
-    // 解析校验和
-    let checksum = if let Some(hash) = md5 {
-        Some(ChecksumType::Md5(hash))
-    } else {
-        sha256.map(ChecksumType::Sha256)
-    };
+    // 只记录任务，等待中的任务由 queue start 下载，避免进程退出时中断刚开始的下载
+    queue.pause_queue().await;
 
     // 添加任务
+    let priority = options.priority;
+    let byte_range = options.byte_range;
+    let speed_limit = options.speed_limit;
+    let deadline = options.deadline;
     let task_id = queue
-        .add_task_with_options(url.clone(), output.clone(), priority, checksum, true)
+        .add_task_with(url.clone(), output.clone(), options)
         .await?;
+    let task = queue.get_task(&task_id).await;
 
     print_success("任务已添加到队列");
     println!("  任务 ID: {}", style(&task_id).cyan());
+    if let Some(name) = task.as_ref().and_then(|t| t.display_name.as_ref()) {
+        println!("  名称: {}", name);
+    }
     println!("  URL: {}", url);
     println!("  输出: {}", output.display());
     println!("  优先级: {:?}", priority);
+    if let Some(range) = byte_range {
+        println!("  范围: {}", format_byte_range(range));
+    }
+    match speed_limit {
+        Some(0) => println!("  限速: 不限速"),
+        Some(limit) => println!("  限速: {}/s", format_size(limit)),
+        None => {}
+    }
+    if let Some(deadline) = deadline {
+        println!("  时限: {}", humantime::format_duration(deadline));
+    }
+    if let Some(note) = task.as_ref().and_then(|t| t.note.as_ref()) {
+        println!("  备注: {}", note);
+    }
+
+    if !now {
+        print_info("运行 yushi queue start 开始下载");
+        return Ok(());
+    }
+    println!();
+    download_now(&queue, &task_id, output).await
+}
+
+/// 解析 `--status` 中的任务状态
+fn parse_status(status: &str) -> Result<TaskStatus> {
+    match status.trim().to_lowercase().as_str() {
+        "pending" => Ok(TaskStatus::Pending),
+        "downloading" => Ok(TaskStatus::Downloading),
+        "verifying" => Ok(TaskStatus::Verifying),
+        "paused" => Ok(TaskStatus::Paused),
+        "completed" => Ok(TaskStatus::Completed),
+        "failed" => Ok(TaskStatus::Failed),
+        "cancelled" => Ok(TaskStatus::Cancelled),
+        "partial" | "partially_completed" => Ok(TaskStatus::PartiallyCompleted),
+        _ => Err(anyhow!(
+            "无效的状态: {} (可用: pending, downloading, verifying, paused, completed, failed, cancelled, partial)",
+            status
+        )),
+    }
+}
+
+/// 解析 `--sort`，取值已由 clap 限定
+fn parse_sort(sort: &str) -> TaskSort {
+    match sort {
+        "name" => TaskSort::Name,
+        "status" => TaskSort::Status,
+        "progress" => TaskSort::Progress,
+        "size" => TaskSort::Size,
+        "speed" => TaskSort::Speed,
+        "priority" => TaskSort::Priority,
+        _ => TaskSort::Created,
+    }
+}
+
+async fn add_verify_task(file: PathBuf, checksum: ChecksumType, priority: Priority) -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+    queue.pause_queue().await;
+
+    let task_id = queue.add_verify_task(file, checksum, priority).await?;
+    let task = queue.get_task(&task_id).await;
+
+    print_success("校验任务已添加到队列");
+    println!("  任务 ID: {}", style(&task_id).cyan());
+    if let Some(task) = &task {
+        println!("  文件: {}", task.dest.display());
+        println!("  大小: {}", format_size(task.total_size));
+    }
+    println!("  优先级: {:?}", priority);
+    print_info("运行 yushi queue start 开始校验");
+    Ok(())
+}
 
+async fn add_from_index(url: String, output: PathBuf, options: IndexOptions) -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+    queue.pause_queue().await;
+
+    let task_ids = queue.add_from_index(&url, &output, options).await?;
+    if task_ids.is_empty() {
+        print_warning("索引页中没有匹配的文件");
+        return Ok(());
+    }
+    print_success(&format!("已添加 {} 个任务", task_ids.len()));
+    for task_id in &task_ids {
+        if let Some(task) = queue.get_task(task_id).await {
+            println!("  {} {}", style(&task_id[..8]).cyan(), task.dest.display());
+        }
+    }
+    print_info("运行 yushi queue start 开始下载");
     Ok(())
 }
 
-async fn list_tasks() -> Result<()> {
-    let queue_path = Config::queue_state_path()?;
-    let (queue, _) = YuShi::new(4, 1, queue_path);
+/// 立即下载刚添加的任务，进度显示与 `yushi download` 相同
+///
+/// 任务的状态和进度写入队列文件，其他命令可以看到。
+async fn download_now(queue: &YuShi, task_id: &str, output: PathBuf) -> Result<()> {
+    let (tx, rx) = mpsc::channel(1024);
+    let progress = show_progress(rx, output, false);
+    let result = queue.run_task(task_id, Some(tx)).await;
+    progress.await?;
+    queue.flush_audit_log().await;
 
+    match result {
+        Ok(()) => {
+            if let Some(task) = queue.get_task(task_id).await {
+                print_success(&format!("文件已保存到: {}", task.dest.display()));
+            }
+            Ok(())
+        }
+        Err(e) => {
+            print_error(&format!("下载失败: {}", e));
+            Err(e.into())
+        }
+    }
+}
+
+async fn plan_task(
+    url: String,
+    output: PathBuf,
+    options: TaskOptions,
+    overrides: Overrides,
+    json: bool,
+) -> Result<()> {
+    let (queue, _) = build_queue(overrides).await?;
+
+    // 加载现有队列以检查路径冲突
     queue.load_queue_from_state().await?;
-    let tasks = queue.get_all_tasks().await;
+
+    let plan = match queue.plan_task(&url, &output, &options).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            print_error(&format!("探测失败: {}", e));
+            return Err(e.into());
+        }
+    };
+    print_plan(&plan, json)
+}
+
+/// 打印当前的运行参数
+fn print_settings(queue: &YuShi) {
+    print_info(&format!("最大并发任务: {}", queue.max_concurrent_tasks()));
+    print_info(&format!("每任务连接数: {}", queue.default_connections()));
+    match queue.global_speed_limit() {
+        Some(limit) => print_info(&format!("总速度限制: {}/s", format_size(limit))),
+        None => print_info("总速度限制: 不限速"),
+    }
+}
+
+fn print_commands_help() {
+    println!("  limit <速度|off>   设置总速度限制 (例如: limit 2M)");
+    println!("  tasks <数量>       设置最大并发任务数");
+    println!("  conn <数量>        设置每任务连接数");
+    println!("  pause              暂停所有运行中的任务，并停止启动等待中的任务");
+    println!("  resume             恢复所有暂停的任务和队列调度");
+    println!("  status             显示任务统计和当前参数");
+}
+
+/// 执行 `queue start` 运行中输入的命令
+async fn run_command(queue: &YuShi, line: &str) -> Result<()> {
+    let mut parts = line.split_whitespace();
+    let Some(command) = parts.next() else {
+        return Ok(());
+    };
+    let arg = parts.next();
+    let parse_count = |arg: Option<&str>| -> Result<usize> {
+        arg.and_then(|n| n.parse().ok())
+            .filter(|&n| n > 0)
+            .ok_or_else(|| anyhow!("需要一个正整数"))
+    };
+
+    match command {
+        "limit" => {
+            let limit = match arg {
+                Some("off" | "0") => None,
+                Some(rate) => {
+                    Some(parse_speed_limit(rate).ok_or_else(|| anyhow!("无效的速度: {}", rate))?)
+                }
+                None => return Err(anyhow!("用法: limit <速度|off>")),
+            };
+            queue.set_global_speed_limit(limit);
+            match limit {
+                Some(limit) => print_success(&format!("总速度限制: {}/s", format_size(limit))),
+                None => print_success("已取消总速度限制"),
+            }
+        }
+        "tasks" => {
+            let max = parse_count(arg)?;
+            queue.set_max_concurrent_tasks(max).await?;
+            print_success(&format!("最大并发任务: {}", max));
+        }
+        "conn" | "connections" => {
+            let connections = parse_count(arg)?;
+            queue.set_default_connections(connections).await;
+            print_success(&format!("每任务连接数: {}", connections));
+        }
+        "pause" => {
+            queue.pause_queue().await;
+            let mut count = 0;
+            for task in queue.get_all_tasks().await {
+                // 只校验本地文件的任务不能暂停
+                if matches!(task.status, TaskStatus::Downloading | TaskStatus::Verifying)
+                    && task.kind == TaskKind::Download
+                {
+                    queue.pause_task(&task.id).await?;
+                    count += 1;
+                }
+            }
+            print_success(&format!("已暂停 {} 个任务", count));
+        }
+        "resume" => {
+            let mut count = 0;
+            for task in queue.get_all_tasks().await {
+                if task.status == TaskStatus::Paused {
+                    queue.resume_task(&task.id).await?;
+                    count += 1;
+                }
+            }
+            queue.resume_queue().await?;
+            print_success(&format!("已恢复 {} 个任务", count));
+        }
+        "status" => {
+            let stats = queue.get_stats();
+            println!(
+                "  下载中: {}  校验中: {}  等待: {}  暂停: {}  完成: {}  失败: {}",
+                stats.downloading,
+                stats.verifying,
+                stats.pending,
+                stats.paused,
+                stats.completed,
+                stats.failed
+            );
+            println!(
+                "  总速度: {}/s  缓冲: {}",
+                format_size(stats.speed),
+                format_size(stats.buffered_bytes)
+            );
+            println!("  调度器: {}", format_scheduler(&stats.scheduler));
+            for snapshot in queue.progress_all() {
+                if let Some(secs) = snapshot.stalled_for {
+                    println!(
+                        "  {} {} 已 {}s 没有收到数据",
+                        style("停滞").yellow(),
+                        &snapshot.task_id[..8],
+                        secs
+                    );
+                }
+            }
+            print_settings(queue);
+        }
+        "help" => print_commands_help(),
+        _ => {
+            print_error(&format!("未知命令: {}", command));
+            print_commands_help();
+        }
+    }
+    Ok(())
+}
+
+/// 按 `--format` 输出任务列表，不带标题和统计，便于脚本处理
+async fn print_task_list(query: TaskQuery, format: &str, columns: &[String]) -> Result<()> {
+    let default_columns = if format == "wide" {
+        WIDE_COLUMNS
+    } else {
+        TABLE_COLUMNS
+    };
+    let columns = parse_columns(columns, default_columns)?;
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+    let tasks = queue.query_tasks(&query).await;
+
+    match format {
+        "json" => println!("{}", serde_json::to_string_pretty(&tasks)?),
+        "ids" => {
+            for task in &tasks {
+                println!("{}", task.id);
+            }
+        }
+        _ => {
+            // 输出到管道时不截断
+            let width = Term::stdout()
+                .is_term()
+                .then(|| Term::stdout().size().1 as usize);
+            print!(
+                "{}",
+                table::render(&tasks, &columns, width, &i18n::locale())
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn list_tasks(by_group: bool, query: TaskQuery, forecast: bool) -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
+
+    queue.load_queue_from_state().await?;
+    let tasks = queue.query_tasks(&query).await;
+
+    if let Some(search) = &query.search
+        && tasks.is_empty()
+    {
+        print_info(&format!("没有匹配 \"{}\" 的任务", search));
+        return Ok(());
+    }
 
     if tasks.is_empty() {
-        print_info("队列为空");
+        if query.statuses.is_empty() {
+            print_info("队列为空");
+        } else {
+            print_info("没有符合条件的任务");
+        }
         return Ok(());
     }
 
-    println!("\n{}", style("下载队列").bold().underlined());
+    match profile() {
+        Some(name) => println!(
+            "\n{}",
+            style(format!("下载队列 (配置档案: {})", name))
+                .bold()
+                .underlined()
+        ),
+        None => println!("\n{}", style("下载队列").bold().underlined()),
+    }
     println!();
 
-    for task in tasks {
-        let status_str = match task.status {
-            yushi_core::TaskStatus::Pending => style("等待中").yellow(),
-            yushi_core::TaskStatus::Downloading => style("下载中").green(),
-            yushi_core::TaskStatus::Paused => style("已暂停").blue(),
-            yushi_core::TaskStatus::Completed => style("已完成").green(),
-            yushi_core::TaskStatus::Failed => style("失败").red(),
-            yushi_core::TaskStatus::Cancelled => style("已取消").red(),
+    if !by_group {
+        for task in &tasks {
+            print_task(task);
+        }
+        print_stats(&queue);
+        if forecast {
+            print_forecast(&queue.forecast(None).await);
+        }
+        return Ok(());
+    }
+
+    let mut groups = queue.get_groups().await;
+    groups.sort_by_key(|g| g.created_at);
+    for group in groups {
+        let Some(progress) = queue.get_group_progress(&group.id).await else {
+            continue;
+        };
+        let percent = if progress.total_size > 0 {
+            progress.downloaded as f64 / progress.total_size as f64 * 100.0
+        } else {
+            0.0
         };
+        println!(
+            "{} {} ({}/{} 完成, {:.1}%, {} / {})",
+            style("▣").bold(),
+            style(&group.name).bold(),
+            progress.completed_tasks,
+            progress.total_tasks,
+            percent,
+            format_size(progress.downloaded),
+            format_size(progress.total_size)
+        );
+        println!("  组 ID: {}", style(&group.id[..16]).cyan());
+        if progress.failed_tasks > 0 {
+            println!("  {}: {}", style("失败").red(), progress.failed_tasks);
+        }
+        println!();
+        for task in tasks
+            .iter()
+            .filter(|t| t.group_id.as_deref() == Some(group.id.as_str()))
+        {
+            print_task(task);
+        }
+    }
+
+    let ungrouped: Vec<&DownloadTask> = tasks.iter().filter(|t| t.group_id.is_none()).collect();
+    if !ungrouped.is_empty() {
+        println!("{} {}", style("▣").bold(), style("未分组").bold());
+        println!();
+        for task in ungrouped {
+            print_task(task);
+        }
+    }
+
+    print_stats(&queue);
+    if forecast {
+        print_forecast(&queue.forecast(None).await);
+    }
+    Ok(())
+}
+
+/// `queue plan`：模拟调度并以时间线显示
+async fn plan_queue(speed: Option<String>) -> Result<()> {
+    let speed = match speed {
+        Some(rate) => {
+            Some(parse_speed_limit(&rate).ok_or_else(|| anyhow!("无效的速度: {}", rate))?)
+        }
+        None => None,
+    };
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+    // 探测等待中的任务的大小，结果缓存在任务上
+    queue.forecast(None).await;
+    let plan = queue.simulate_schedule(speed).await;
+    if plan.is_empty() {
+        print_info("没有待处理的任务");
+        return Ok(());
+    }
+
+    let tasks = queue.get_all_tasks().await;
+    let rows: Vec<_> = plan
+        .iter()
+        .filter_map(|slot| Some((tasks.iter().find(|t| t.id == slot.task_id)?, slot)))
+        .collect();
+    match speed.or(queue.global_speed_limit()) {
+        Some(speed) => println!("按 {}/s 的总速度估算:\n", format_size(speed)),
+        None => println!("总速度未知，只显示第一批任务:\n"),
+    }
+    print!("{}", timeline::render(&rows, 30, &i18n::locale()));
+    Ok(())
+}
+
+/// 打印磁盘占用预估
+fn print_forecast(forecast: &Forecast) {
+    println!();
+    println!("{}", style("磁盘空间预估").bold());
+    let unknown = match forecast.pending_unknown_count {
+        0 => String::new(),
+        n => format!("，另有 {} 个任务大小未知", n),
+    };
+    println!(
+        "  还需下载: {}{}",
+        format_size(forecast.pending_bytes_known),
+        unknown
+    );
+    for volume in &forecast.free_space_by_volume {
+        let line = format!(
+            "  {}: 需要 {} / 可用 {} ({} 个任务)",
+            volume.path.display(),
+            format_size(volume.required),
+            format_size(volume.free_space),
+            volume.task_count
+        );
+        match volume.headroom() {
+            Some(_) => println!("{}", line),
+            None => println!("{}", style(line).red()),
+        }
+    }
+    match forecast.will_fit {
+        Some(true) => print_success("磁盘空间足够"),
+        Some(false) => print_warning("磁盘空间不足，部分任务无法下载完成"),
+        None => print_info("有任务大小未知，无法确定磁盘空间是否足够"),
+    }
+}
+
+/// 打印队列统计和调度器状态
+/// `queue list --summary`：按优先级显示剩余的下载量
+async fn print_summary() -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+    // 等待中的任务需要探测才知道大小
+    let forecast = queue.forecast(None).await;
+    let stats = queue.get_stats();
+
+    println!("\n{}", style("队列统计").bold().underlined());
+    println!();
+    for priority in [Priority::High, Priority::Normal, Priority::Low] {
+        let remaining = stats
+            .remaining_bytes_by_priority
+            .get(&priority)
+            .copied()
+            .unwrap_or(0);
+        let unknown = stats
+            .unknown_size_by_priority
+            .get(&priority)
+            .copied()
+            .unwrap_or(0);
+        let active = stats
+            .active_by_priority
+            .get(&priority)
+            .copied()
+            .unwrap_or(0);
+        let mut line = format!(
+            "  {:?}: 剩余 {}  运行中 {}",
+            priority,
+            format_size(remaining),
+            active
+        );
+        if unknown > 0 {
+            line.push_str(&format!("  (另有 {} 个任务大小未知)", unknown));
+        }
+        println!("{}", line);
+    }
+    println!();
+    print_stats(&queue);
+    match stats.estimated_drain_time {
+        Some(secs) => println!("预计完成: {}", format_eta(secs)),
+        None => println!("预计完成: 未知（当前没有下载速度）"),
+    }
+    if forecast.pending_unknown_count > 0 {
+        print_info("大小未知的任务不计入剩余大小和预计完成时间");
+    }
+    Ok(())
+}
+
+fn print_stats(queue: &YuShi) {
+    let stats = queue.get_stats();
+    println!(
+        "共 {} 个任务  下载中: {}  校验中: {}  等待: {}  暂停: {}  完成: {}  失败: {}",
+        stats.total,
+        stats.downloading,
+        stats.verifying,
+        stats.pending,
+        stats.paused,
+        stats.completed,
+        stats.failed
+    );
+    if stats.partially_completed > 0 {
+        println!("部分完成: {}", stats.partially_completed);
+    }
+    println!("调度器: {}", format_scheduler(&stats.scheduler));
+}
+
+fn print_task(task: &DownloadTask) {
+    let status_str = match task.status {
+        TaskStatus::Pending => style("等待中").yellow(),
+        TaskStatus::Downloading => style("下载中").green(),
+        TaskStatus::Verifying => style("校验中").cyan(),
+        TaskStatus::Paused => style("已暂停").blue(),
+        TaskStatus::Completed => style("已完成").green(),
+        TaskStatus::Failed => style("失败").red(),
+        TaskStatus::Cancelled => style("已取消").red(),
+        TaskStatus::PartiallyCompleted => style("部分完成").yellow(),
+    };
+
+    let archived = if task.archived {
+        style(" (已归档)").dim().to_string()
+    } else {
+        String::new()
+    };
+    let kind = match task.kind {
+        TaskKind::Download => String::new(),
+        TaskKind::VerifyOnly => style(" [仅校验]").cyan().to_string(),
+    };
+    println!(
+        "{} {} {}{}{}",
+        style("●").bold(),
+        status_str,
+        style(task.label()).bold(),
+        kind,
+        archived
+    );
+    println!("  ID: {}", style(&task.id[..16]).cyan());
+    println!("  URL: {}", task.url);
+    if let Some(resolved) = &task.resolved_url {
+        println!("  重定向到: {}", resolved);
+    }
+    println!("  输出: {}", task.dest.display());
+    println!("  优先级: {:?}", task.priority);
+    if let Some(range) = task.byte_range {
+        println!("  范围: {}", format_byte_range(range));
+    }
+
+    if task.total_size > 0 {
+        let progress = (task.downloaded as f64 / task.total_size as f64) * 100.0;
+        println!(
+            "  进度: {:.1}% ({} / {})",
+            progress,
+            format_size(task.downloaded),
+            format_size(task.total_size)
+        );
+    } else {
+        // 流式下载，只显示已下载量
+        println!("  进度: {} (流式下载)", format_size(task.downloaded));
+    }
+
+    if !task.missing_ranges.is_empty() {
+        println!("  缺失范围: {} 个", task.missing_ranges.len());
+        for &(start, end) in &task.missing_ranges {
+            println!("    {}-{} ({})", start, end, format_size(end - start + 1));
+        }
+    }
+
+    if task.speed > 0 {
+        println!("  速度: {}/s", format_size(task.speed));
+    }
+
+    match task.speed_limit {
+        Some(0) => println!("  限速: 不限速"),
+        Some(limit) => println!("  限速: {}/s", format_size(limit)),
+        None => {}
+    }
+    if let Some(until) = task.boost_until {
+        let until = std::time::UNIX_EPOCH + Duration::from_millis(until);
+        println!("  加速至: {}", humantime::format_rfc3339_seconds(until));
+    }
+    if let Some(ms) = task.deadline_ms {
+        println!(
+            "  时限: {}",
+            humantime::format_duration(Duration::from_millis(ms))
+        );
+    }
+
+    if task.average_speed > 0 || task.peak_speed > 0 {
+        println!(
+            "  平均速度: {}/s  最高速度: {}/s  下载用时: {}",
+            format_size(task.average_speed),
+            format_size(task.peak_speed),
+            format_eta(task.active_duration_ms / 1000)
+        );
+    }
+
+    if let Some(eta) = task.eta {
+        println!("  剩余时间: {}", format_eta(eta));
+    }
+
+    if let Some(note) = &task.note {
+        println!("  备注: {}", note);
+    }
+
+    if let Some(error) = &task.error {
+        println!("  {}: {}", style("错误").red(), error);
+    }
+
+    println!();
+}
+
+async fn show_task(task_id: String, provenance: bool) -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+
+    let task = queue
+        .get_task(&task_id)
+        .await
+        .ok_or_else(|| anyhow!("任务不存在: {}", task_id))?;
+    print_task(&task);
+
+    if provenance {
+        match &task.provenance {
+            Some(provenance) => print_provenance(provenance),
+            None => print_info("任务还没有开始下载，没有下载环境记录"),
+        }
+    }
+    if !task.recent_events.is_empty() {
+        print_task_events(task.recent_events.iter());
+    }
+    Ok(())
+}
+
+/// 按时间先后打印任务的事件记录
+fn print_task_events<'a>(events: impl Iterator<Item = &'a CompactEvent>) {
+    println!("\n{}", style("事件记录").bold().underlined());
+    for event in events {
+        let time = humantime::format_rfc3339_seconds(
+            std::time::SystemTime::UNIX_EPOCH
+                + std::time::Duration::from_millis(event.timestamp_ms),
+        );
+        println!(
+            "  {}  {}",
+            style(time).dim(),
+            format_compact_event(&event.kind)
+        );
+    }
+}
+
+fn format_compact_event(kind: &CompactEventKind) -> String {
+    match kind {
+        CompactEventKind::Added => "添加到队列".to_string(),
+        CompactEventKind::Started => "开始下载".to_string(),
+        CompactEventKind::Paused => "暂停".to_string(),
+        CompactEventKind::Resumed => "恢复".to_string(),
+        CompactEventKind::Cancelled => "取消".to_string(),
+        CompactEventKind::Retried { fresh: true } => "重新下载（丢弃下载环境记录）".to_string(),
+        CompactEventKind::Retried { fresh: false } => "重试".to_string(),
+        CompactEventKind::Completed {
+            skipped_unchanged: true,
+        } => "完成（远程文件没有变化）".to_string(),
+        CompactEventKind::Completed { .. } => "完成".to_string(),
+        CompactEventKind::Failed { error } => format!("失败: {}", error),
+        CompactEventKind::PartiallyCompleted { missing_ranges } => {
+            format!("部分完成，缺失 {} 段", missing_ranges)
+        }
+        CompactEventKind::Retrying {
+            attempt,
+            reason: RetryReason::Stalled,
+        } => format!("停滞后重新连接 (第 {} 次)", attempt),
+        CompactEventKind::Retrying {
+            attempt,
+            reason: RetryReason::ConnectionStalled { chunk_index },
+        } => format!(
+            "分块 {} 的连接停滞，重新连接 (第 {} 次)",
+            chunk_index, attempt
+        ),
+        CompactEventKind::Retrying {
+            attempt,
+            reason: RetryReason::ConnectionLost { chunk_index },
+        } => format!(
+            "分块 {} 的连接中断，重新连接 (第 {} 次)",
+            chunk_index, attempt
+        ),
+        CompactEventKind::Restarted { reason } => format_restart_reason(*reason).to_string(),
+        CompactEventKind::MirrorDemoted { mirror, reason } => {
+            format_mirror_demotion(mirror, *reason)
+        }
+        CompactEventKind::VerifyStarted => "开始校验".to_string(),
+        CompactEventKind::VerifyCompleted {
+            success,
+            repaired_chunks,
+        } => match (success, repaired_chunks) {
+            (true, 0) => "校验通过".to_string(),
+            (true, n) => format!("校验通过（修复了 {} 个分块）", n),
+            (false, _) => "校验失败".to_string(),
+        },
+    }
+}
+
+async fn diag_task(task_id: String, json: bool, show_query: bool) -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+
+    let diagnostics = queue.diagnostics_with(&task_id, !show_query).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+        return Ok(());
+    }
 
-        println!("{} {}", style("●").bold(), status_str);
-        println!("  ID: {}", style(&task.id[..16]).cyan());
-        println!("  URL: {}", task.url);
-        println!("  输出: {}", task.dest.display());
-        println!("  优先级: {:?}", task.priority);
+    println!("\n{}", style("任务诊断").bold().underlined());
+    println!("  任务 ID: {}", style(&diagnostics.task_id).cyan());
+    println!("  状态: {:?}", diagnostics.status);
+    println!("  URL: {}", diagnostics.url);
+    println!("  输出: {}", diagnostics.dest.display());
+    println!("  数据文件: {}", diagnostics.work_path.display());
+    println!("  状态文件: {}", diagnostics.state_path.display());
+    if let Some(saved_at) = diagnostics.state_saved_at {
+        println!(
+            "  状态写入时间: {}",
+            humantime::format_rfc3339_seconds(
+                std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(saved_at)
+            )
+        );
+    }
 
-        if task.total_size > 0 {
-            let progress = (task.downloaded as f64 / task.total_size as f64) * 100.0;
+    println!();
+    if let Some(error) = &diagnostics.state_error {
+        print_error(&format!("状态文件无法解析: {}", error));
+    } else if let Some(state) = &diagnostics.state {
+        println!("  {}", style("分块状态").bold());
+        println!(
+            "    大小: {}  已下载: {}  流式: {}",
+            state.total_size.map_or("未知".to_string(), format_size),
+            format_size(state.downloaded),
+            if state.is_streaming { "是" } else { "否" }
+        );
+        for chunk in &state.chunks {
             println!(
-                "  进度: {:.1}% ({} / {})",
-                progress,
-                format_size(task.downloaded),
-                format_size(task.total_size)
+                "    #{:<4} {:>12} - {:<12} 写入位置 {:<12} {}{}",
+                chunk.index,
+                chunk.start,
+                chunk.end,
+                chunk.current,
+                if chunk.finished {
+                    style("完成").green()
+                } else {
+                    style("未完成").yellow()
+                },
+                if chunk.has_digest { "  有摘要" } else { "" }
             );
+        }
+        if state.issues.is_empty() {
+            print_success("状态正常");
         } else {
-            // 流式下载，只显示已下载量
-            println!("  进度: {} (流式下载)", format_size(task.downloaded));
+            for issue in &state.issues {
+                print_error(&format_chunk_issue(issue));
+            }
         }
-
-        if task.speed > 0 {
-            println!("  速度: {}/s", format_size(task.speed));
+        if matches!(diagnostics.status, TaskStatus::Paused | TaskStatus::Failed) {
+            let damaged = queue.damaged_chunks(&task_id).await?;
+            if !damaged.is_empty() {
+                print_warning(&format!(
+                    "分块 {} 与下载时记录的摘要不一致，可以使用 --refetch-chunk {} 重新下载",
+                    join_indices(&damaged),
+                    join_indices(&damaged)
+                ));
+            }
         }
+    } else {
+        print_info("没有分块状态文件");
+    }
 
-        if let Some(eta) = task.eta {
-            println!("  剩余时间: {}s", eta);
+    let config = &diagnostics.config;
+    println!();
+    println!("  {}", style("生效的设置").bold());
+    println!("    核心版本: {}", config.core_version);
+    println!(
+        "    连接数: {}  分块大小: {}  超时: {}s",
+        config.connections,
+        format_size(config.chunk_size),
+        config.timeout
+    );
+    if let Some(limit) = config.speed_limit {
+        println!("    任务速度限制: {}/s", format_size(limit));
+    }
+    if let Some(limit) = config.global_speed_limit {
+        println!("    全局速度限制: {}/s", format_size(limit));
+    }
+    if let Some(dir) = &config.temp_dir {
+        println!("    临时目录: {}", dir.display());
+    }
+    if let Some(suffix) = &config.part_suffix {
+        println!("    下载中文件后缀: {}", suffix);
+    }
+    if let Some(range) = config.byte_range {
+        println!("    范围: {}", format_byte_range(range));
+    }
+    if config.skip_probe {
+        println!("    跳过探测: 是");
+    }
+    match config.chunk_order {
+        ChunkOrder::Parallel => {}
+        ChunkOrder::Sequential => println!("    分块顺序: 逐个按顺序"),
+        ChunkOrder::SequentialFirst(prefix) => {
+            println!("    分块顺序: 前 {} 按顺序，其余并发", format_size(prefix))
         }
+        ChunkOrder::Random => println!("    分块顺序: 随机"),
+    }
+    if let Some(provenance) = &diagnostics.provenance {
+        println!();
+        print_provenance(provenance);
+    }
+    Ok(())
+}
+
+/// 把分块标记为未完成，恢复任务时只重新下载这些分块
+async fn refetch_chunks(task_id: String, indices: Vec<usize>) -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+    queue.invalidate_chunks(&task_id, &indices).await?;
+    print_success(&format!(
+        "分块 {} 已标记为重新下载，恢复任务后生效",
+        join_indices(&indices)
+    ));
+    Ok(())
+}
+
+fn join_indices(indices: &[usize]) -> String {
+    indices
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
 
-        if let Some(error) = &task.error {
-            println!("  {}: {}", style("错误").red(), error);
+fn format_chunk_issue(issue: &ChunkIssue) -> String {
+    match issue {
+        ChunkIssue::Gap { after, start, end } => {
+            format!("分块 #{} 之后有未覆盖的范围 {}-{}", after, start, end)
+        }
+        ChunkIssue::Overlap { first, second } => {
+            format!("分块 #{} 与 #{} 的范围重叠", first, second)
         }
+        ChunkIssue::InvalidRange { index } => format!("分块 #{} 的范围无效", index),
+        ChunkIssue::CurrentOutOfRange { index, current } => {
+            format!("分块 #{} 的写入位置 {} 超出范围", index, current)
+        }
+        ChunkIssue::FinishedIncomplete { index } => {
+            format!("分块 #{} 标记为完成但没有写完", index)
+        }
+        ChunkIssue::SizeMismatch { expected, covered } => {
+            format!("分块覆盖 {} 字节，文件大小为 {} 字节", covered, expected)
+        }
+    }
+}
 
-        println!();
+/// 修改任务的显示名称和备注，传入空字符串表示清除
+async fn edit_task(task_id: String, name: Option<String>, note: Option<String>) -> Result<()> {
+    if name.is_none() && note.is_none() {
+        return Err(anyhow!("请指定 --name 或 --note"));
+    }
+
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+
+    let non_empty = |value: String| (!value.is_empty()).then_some(value);
+    if let Some(name) = name {
+        queue
+            .set_task_display_name(&task_id, non_empty(name))
+            .await?;
+    }
+    if let Some(note) = note {
+        queue.set_task_note(&task_id, non_empty(note)).await?;
     }
+    queue.compact_queue_state().await?;
 
+    print_success(&format!("任务已更新: {}", task_id));
     Ok(())
 }
 
-async fn start_queue(max_tasks: usize, connections: usize) -> Result<()> {
-    let queue_path = Config::queue_state_path()?;
-    let (queue, mut event_rx) = YuShi::new(connections, max_tasks, queue_path);
+/// 开始下载队列，`speed_limit` 为所有任务的总速度限制
+async fn start_queue(overrides: Overrides, speed_limit: Option<String>) -> Result<()> {
+    let speed_limit = match speed_limit {
+        Some(limit) => {
+            Some(parse_speed_limit(&limit).ok_or_else(|| anyhow!("无效的速度限制: {}", limit))?)
+        }
+        None => None,
+    };
+    let (queue, mut event_rx) = build_queue(overrides).await?;
+    queue.set_global_speed_limit(speed_limit);
 
     queue.load_queue_from_state().await?;
 
     let tasks = queue.get_all_tasks().await;
     let pending_count = tasks
         .iter()
-        .filter(|t| t.status == yushi_core::TaskStatus::Pending)
+        .filter(|t| t.status == TaskStatus::Pending)
         .count();
 
     if pending_count == 0 {
@@ -154,16 +1161,17 @@ async fn start_queue(max_tasks: usize, connections: usize) -> Result<()> {
     }
 
     print_info(&format!("启动队列处理 ({} 个待处理任务)", pending_count));
-    print_info(&format!("最大并发任务: {}", max_tasks));
-    print_info(&format!("每任务连接数: {}", connections));
+    print_settings(&queue);
+    print_info("输入命令调整运行参数 (输入 help 查看全部命令):");
+    print_commands_help();
     println!();
 
     let progress_mgr = ProgressManager::new();
 
     // 事件处理
     let event_handle = tokio::spawn(async move {
-        while let Some(event) = event_rx.recv().await {
-            match event {
+        while let Some(envelope) = event_rx.recv().await {
+            match envelope.event {
                 DownloaderEvent::Task(TaskEvent::Started { task_id }) => {
                     println!("🚀 开始: {}", &task_id[..8]);
                 }
@@ -184,21 +1192,118 @@ async fn start_queue(max_tasks: usize, connections: usize) -> Result<()> {
                         progress_mgr.add_task(task_id, None).await;
                     }
                 }
-                DownloaderEvent::Task(TaskEvent::Completed { task_id }) => {
+                DownloaderEvent::Progress(ProgressEvent::Retrying {
+                    task_id,
+                    attempt,
+                    reason,
+                }) => match reason {
+                    RetryReason::Stalled => {
+                        println!("🔁 停滞后重新连接: {} (第 {} 次)", &task_id[..8], attempt);
+                    }
+                    RetryReason::ConnectionStalled { chunk_index } => {
+                        println!(
+                            "🔁 分块 {} 的连接停滞，重新连接: {} (第 {} 次)",
+                            chunk_index,
+                            &task_id[..8],
+                            attempt
+                        );
+                    }
+                    RetryReason::ConnectionLost { chunk_index } => {
+                        println!(
+                            "🔁 分块 {} 的连接中断，重新连接: {} (第 {} 次)",
+                            chunk_index,
+                            &task_id[..8],
+                            attempt
+                        );
+                    }
+                },
+                DownloaderEvent::Progress(ProgressEvent::Restarted { task_id, reason }) => {
+                    println!("🔄 {}: {}", format_restart_reason(reason), &task_id[..8]);
+                }
+                DownloaderEvent::Progress(ProgressEvent::MirrorDemoted {
+                    task_id,
+                    mirror,
+                    reason,
+                }) => {
+                    println!(
+                        "⚠️  {}: {}",
+                        format_mirror_demotion(&mirror, reason),
+                        &task_id[..8]
+                    );
+                }
+                DownloaderEvent::Task(TaskEvent::Completed { task_id, .. }) => {
                     progress_mgr.finish_task(&task_id, true).await;
                 }
                 DownloaderEvent::Task(TaskEvent::Failed { task_id, error }) => {
                     progress_mgr.finish_task(&task_id, false).await;
                     eprintln!("❌ 失败 {}: {}", &task_id[..8], error);
                 }
+                DownloaderEvent::Task(TaskEvent::PartiallyCompleted {
+                    task_id,
+                    missing_ranges,
+                }) => {
+                    progress_mgr.finish_task(&task_id, false).await;
+                    eprintln!(
+                        "⚠️ 部分完成 {}: {} 个范围缺失，运行 yushi queue retry 重新下载",
+                        &task_id[..8],
+                        missing_ranges.len()
+                    );
+                }
+                DownloaderEvent::Task(TaskEvent::LinkFailed { task_id, error }) => {
+                    eprintln!("⚠️ 创建链接失败 {}: {}", &task_id[..8], error);
+                }
+                DownloaderEvent::Task(TaskEvent::BoostEnded { task_id }) => {
+                    println!("🐢 加速结束: {}", &task_id[..8]);
+                }
+                DownloaderEvent::Task(TaskEvent::GroupCompleted { group_id, failed }) => {
+                    if failed.is_empty() {
+                        println!("📦 任务组完成: {}", &group_id[..8]);
+                    } else {
+                        println!(
+                            "📦 任务组结束: {} ({} 个失败)",
+                            &group_id[..8],
+                            failed.len()
+                        );
+                    }
+                }
                 DownloaderEvent::Verification(VerificationEvent::Started { task_id }) => {
                     println!("🔍 校验: {}", &task_id[..8]);
                 }
+                DownloaderEvent::Global(GlobalEvent::SchedulerStateChanged(state)) => {
+                    println!("⏯ 调度器: {}", format_scheduler(&state));
+                }
+                DownloaderEvent::Global(GlobalEvent::DiskPressure {
+                    latency_ms,
+                    throttled_to,
+                }) => match throttled_to {
+                    Some(rate) => eprintln!(
+                        "💽 磁盘写入缓慢 (平均 {} ms)，总速度限制为 {}/s",
+                        latency_ms,
+                        format_size(rate)
+                    ),
+                    None => eprintln!("💽 磁盘写入恢复正常，解除限速"),
+                },
+                DownloaderEvent::Verification(VerificationEvent::Progress {
+                    task_id,
+                    verified,
+                    total,
+                }) => {
+                    progress_mgr
+                        .update_verification(&task_id, verified, total)
+                        .await;
+                }
                 DownloaderEvent::Verification(VerificationEvent::Completed {
                     task_id,
                     success,
+                    repaired_chunks,
                 }) => {
-                    if success {
+                    if success && repaired_chunks > 0 {
+                        println!(
+                            "✅ 校验通过: {} (修复了 {} 个损坏分块)",
+                            &task_id[..8],
+                            repaired_chunks
+                        );
+                    } else if success {
                         println!("✅ 校验通过: {}", &task_id[..8]);
                     } else {
                         println!("❌ 校验失败: {}", &task_id[..8]);
@@ -209,10 +1314,34 @@ async fn start_queue(max_tasks: usize, connections: usize) -> Result<()> {
         }
     });
 
+    queue.start().await?;
+
+    // 交互命令：标准输入的读取会阻塞，放在单独的线程中
+    let (line_tx, mut line_rx) = tokio::sync::mpsc::channel::<String>(16);
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else { break };
+            if line_tx.blocking_send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let control = queue.clone();
+    let command_handle = tokio::spawn(async move {
+        while let Some(line) = line_rx.recv().await {
+            if let Err(e) = run_command(&control, line.trim()).await {
+                print_error(&e.to_string());
+            }
+        }
+    });
+
     // 等待中断信号
     tokio::signal::ctrl_c().await?;
     println!("\n\n收到中断信号，正在停止...");
 
+    command_handle.abort();
+    // 停止下载并保存进度，下次启动队列时继续
+    queue.shutdown(SHUTDOWN_TIMEOUT).await?;
     event_handle.abort();
     print_success("队列已停止");
 
@@ -220,8 +1349,7 @@ async fn start_queue(max_tasks: usize, connections: usize) -> Result<()> {
 }
 
 async fn pause_task(task_id: String) -> Result<()> {
-    let queue_path = Config::queue_state_path()?;
-    let (queue, _) = YuShi::new(4, 1, queue_path);
+    let (queue, _) = build_queue(Overrides::default()).await?;
 
     queue.load_queue_from_state().await?;
     queue.pause_task(&task_id).await?;
@@ -231,8 +1359,7 @@ async fn pause_task(task_id: String) -> Result<()> {
 }
 
 async fn resume_task(task_id: String) -> Result<()> {
-    let queue_path = Config::queue_state_path()?;
-    let (queue, _) = YuShi::new(4, 1, queue_path);
+    let (queue, _) = build_queue(Overrides::default()).await?;
 
     queue.load_queue_from_state().await?;
     queue.resume_task(&task_id).await?;
@@ -241,9 +1368,38 @@ async fn resume_task(task_id: String) -> Result<()> {
     Ok(())
 }
 
+async fn boost_task(task_id: String, duration: Duration) -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
+
+    queue.load_queue_from_state().await?;
+    queue.boost_task(&task_id, duration).await?;
+
+    print_success(&format!(
+        "任务已加速: {} ({} 内不限速)",
+        &task_id[..16],
+        humantime::format_duration(duration)
+    ));
+    Ok(())
+}
+
+async fn retry_task(task_id: String, fresh: bool) -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+
+    // 只放回队列，由 queue start 开始下载
+    queue.pause_queue().await;
+    queue.retry_task(&task_id, fresh).await?;
+
+    if fresh {
+        print_success(&format!("任务将按当前配置重新下载: {}", task_id));
+    } else {
+        print_success(&format!("任务将沿用上次的下载环境重试: {}", task_id));
+    }
+    Ok(())
+}
+
 async fn cancel_task(task_id: String) -> Result<()> {
-    let queue_path = Config::queue_state_path()?;
-    let (queue, _) = YuShi::new(4, 1, queue_path);
+    let (queue, _) = build_queue(Overrides::default()).await?;
 
     queue.load_queue_from_state().await?;
     queue.cancel_task(&task_id).await?;
@@ -252,20 +1408,20 @@ async fn cancel_task(task_id: String) -> Result<()> {
     Ok(())
 }
 
-async fn remove_task(task_id: String) -> Result<()> {
-    let queue_path = Config::queue_state_path()?;
-    let (queue, _) = YuShi::new(4, 1, queue_path);
+async fn remove_task(task_id: String, delete_file: bool) -> Result<()> {
+    let (queue, _) = build_queue(Overrides::default()).await?;
 
     queue.load_queue_from_state().await?;
-    queue.remove_task(&task_id).await?;
+    queue
+        .remove_task_with_options(&task_id, RemoveOptions { delete_file })
+        .await?;
 
     print_success(&format!("任务已移除: {}", &task_id[..16]));
     Ok(())
 }
 
 async fn clear_completed() -> Result<()> {
-    let queue_path = Config::queue_state_path()?;
-    let (queue, _) = YuShi::new(4, 1, queue_path);
+    let (queue, _) = build_queue(Overrides::default()).await?;
 
     queue.load_queue_from_state().await?;
     queue.clear_completed().await?;
@@ -273,3 +1429,95 @@ async fn clear_completed() -> Result<()> {
     print_success("已清空所有已完成任务");
     Ok(())
 }
+
+async fn gc(mut dirs: Vec<PathBuf>, delete: bool) -> Result<()> {
+    let cli_config = load_config()?;
+    // 临时目录中的文件按任务 ID 判断归属，需要与下载时使用同一个临时目录
+    let (queue, _) = build_queue(Overrides::default()).await?;
+    queue.load_queue_from_state().await?;
+
+    if dirs.is_empty() {
+        dirs.push(cli_config.default_output_dir);
+        dirs.extend(cli_config.temp_dir);
+        for task in queue.get_all_tasks().await {
+            if let Some(parent) = task.dest.parent() {
+                dirs.push(parent.to_path_buf());
+            }
+        }
+        dirs.sort();
+        dirs.dedup();
+    }
+
+    let reports = queue.scan_orphans(&dirs).await;
+    if reports.is_empty() {
+        print_info("没有找到孤立文件");
+        return Ok(());
+    }
+
+    for report in &reports {
+        let kind = match report.kind {
+            OrphanKind::StateFile => "状态文件",
+            OrphanKind::PartialFile => "未完成的文件",
+        };
+        println!(
+            "  {} {} ({})",
+            style(kind).yellow(),
+            report.path.display(),
+            format_size(report.size)
+        );
+    }
+
+    let reclaimed = queue.clean_orphans(&reports, delete).await?;
+    if delete {
+        print_success(&format!("已删除，释放了 {}", format_size(reclaimed)));
+    } else {
+        print_info(&format!(
+            "可以释放 {}，使用 --delete 删除这些文件",
+            format_size(reclaimed)
+        ));
+    }
+    Ok(())
+}
+
+async fn verify_all(concurrency: usize, search: Option<String>) -> Result<()> {
+    let (queue, _) = build_queue(Overrides {
+        verify_concurrency: Some(concurrency),
+        ..Default::default()
+    })
+    .await?;
+    queue.load_queue_from_state().await?;
+
+    print_info("正在校验已完成的任务...");
+    let results = queue
+        .verify_all(|task| search.as_deref().is_none_or(|q| task.matches(q)))
+        .await?;
+    if results.is_empty() {
+        print_info("没有需要校验的任务");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for (task_id, success) in &results {
+        let label = match queue.get_task(task_id).await {
+            Some(task) => task.label(),
+            None => task_id.clone(),
+        };
+        if *success {
+            println!("  {} {}", style("✓").green(), label);
+        } else {
+            failed += 1;
+            println!("  {} {}", style("✗").red(), label);
+        }
+    }
+
+    if failed == 0 {
+        print_success(&format!("{} 个任务全部通过校验", results.len()));
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} 个任务中有 {} 个未通过校验",
+            results.len(),
+            failed
+        ))
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
+use yushi_core::{CaseInsensitiveFs, ChunkFailurePolicy, IpPolicy};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
@@ -10,6 +11,110 @@ pub struct Config {
     pub user_agent: Option<String>,
     pub proxy: Option<String>,
     pub speed_limit: Option<String>,
+    /// 审计日志文件，设置后每个结束的下载都会追加一条记录
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+    /// 下载中的文件存放的目录，完成后移动到输出位置
+    #[serde(default)]
+    pub temp_dir: Option<PathBuf>,
+    /// 检查文件名冲突时是否不区分大小写
+    #[serde(default)]
+    pub case_insensitive_fs: CaseInsensitiveFs,
+    /// 连接服务器时使用的 IP 协议版本
+    #[serde(default)]
+    pub ip_policy: IpPolicy,
+    /// 分块重试次数用尽时让任务失败，还是留下空洞继续完成
+    #[serde(default)]
+    pub on_chunk_exhausted: ChunkFailurePolicy,
+    /// 是否在任务上记录最近的事件，`queue show` 中显示
+    #[serde(default = "default_event_journal")]
+    pub event_journal: bool,
+    /// 下载完成后在这个目录中创建指向文件的链接
+    #[serde(default)]
+    pub completed_link_dir: Option<PathBuf>,
+    /// 无法创建链接时复制文件到链接目录
+    #[serde(default)]
+    pub completed_link_copy: bool,
+    /// 每个请求都带上的 HTTP 头
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// 额外信任的 CA 证书（PEM 文件，可以包含多个证书）
+    #[serde(default)]
+    pub ca_cert: Option<PathBuf>,
+    /// 不验证服务器证书
+    #[serde(default)]
+    pub insecure: bool,
+    /// 命名的配置档案，用 `--profile` 或 `YUSHI_PROFILE` 选择
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// 配置档案：只包含要覆盖的设置项，没有设置的项沿用基础配置
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_connections: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_max_tasks: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_output_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_limit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temp_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub case_insensitive_fs: Option<CaseInsensitiveFs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip_policy: Option<IpPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_chunk_exhausted: Option<ChunkFailurePolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_journal: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_link_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_link_copy: Option<bool>,
+    /// 添加到基础配置中的 HTTP 头，值为空表示去掉基础配置中的同名头
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insecure: Option<bool>,
+}
+
+impl Profile {
+    /// 合并另一组设置，`other` 中设置了的项优先
+    pub fn merge(&mut self, other: Profile) {
+        self.default_connections = other.default_connections.or(self.default_connections);
+        self.default_max_tasks = other.default_max_tasks.or(self.default_max_tasks);
+        self.default_output_dir = other.default_output_dir.or(self.default_output_dir.take());
+        self.user_agent = other.user_agent.or(self.user_agent.take());
+        self.proxy = other.proxy.or(self.proxy.take());
+        self.speed_limit = other.speed_limit.or(self.speed_limit.take());
+        self.audit_log = other.audit_log.or(self.audit_log.take());
+        self.temp_dir = other.temp_dir.or(self.temp_dir.take());
+        self.case_insensitive_fs = other.case_insensitive_fs.or(self.case_insensitive_fs);
+        self.ip_policy = other.ip_policy.or(self.ip_policy);
+        self.on_chunk_exhausted = other.on_chunk_exhausted.or(self.on_chunk_exhausted);
+        self.event_journal = other.event_journal.or(self.event_journal);
+        self.completed_link_dir = other.completed_link_dir.or(self.completed_link_dir.take());
+        self.completed_link_copy = other.completed_link_copy.or(self.completed_link_copy);
+        self.headers.extend(other.headers);
+        self.ca_cert = other.ca_cert.or(self.ca_cert.take());
+        self.insecure = other.insecure.or(self.insecure);
+    }
+}
+
+fn default_event_journal() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -21,6 +126,18 @@ impl Default for Config {
             user_agent: Some("YuShi/1.0".to_string()),
             proxy: None,
             speed_limit: None,
+            audit_log: None,
+            temp_dir: None,
+            case_insensitive_fs: CaseInsensitiveFs::Auto,
+            ip_policy: IpPolicy::Auto,
+            on_chunk_exhausted: ChunkFailurePolicy::FailTask,
+            event_journal: true,
+            completed_link_dir: None,
+            completed_link_copy: false,
+            headers: BTreeMap::new(),
+            ca_cert: None,
+            insecure: false,
+            profiles: BTreeMap::new(),
         }
     }
 }
@@ -36,6 +153,63 @@ impl Config {
         }
     }
 
+    /// 用配置档案覆盖基础配置，档案不存在时返回错误
+    pub fn with_profile(mut self, name: &str) -> Result<Self> {
+        let profile = self.profiles.get(name).cloned().ok_or_else(|| {
+            let names: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+            anyhow!("未知的配置档案: {} (可用: {})", name, names.join(", "))
+        })?;
+        self.apply(profile);
+        Ok(self)
+    }
+
+    /// 把一组设置写入基础配置
+    pub fn apply(&mut self, profile: Profile) {
+        if let Some(connections) = profile.default_connections {
+            self.default_connections = connections;
+        }
+        if let Some(max_tasks) = profile.default_max_tasks {
+            self.default_max_tasks = max_tasks;
+        }
+        if let Some(dir) = profile.default_output_dir {
+            self.default_output_dir = dir;
+        }
+        self.user_agent = profile.user_agent.or(self.user_agent.take());
+        self.proxy = profile.proxy.or(self.proxy.take());
+        self.speed_limit = profile.speed_limit.or(self.speed_limit.take());
+        self.audit_log = profile.audit_log.or(self.audit_log.take());
+        self.temp_dir = profile.temp_dir.or(self.temp_dir.take());
+        if let Some(case_insensitive_fs) = profile.case_insensitive_fs {
+            self.case_insensitive_fs = case_insensitive_fs;
+        }
+        if let Some(ip_policy) = profile.ip_policy {
+            self.ip_policy = ip_policy;
+        }
+        if let Some(policy) = profile.on_chunk_exhausted {
+            self.on_chunk_exhausted = policy;
+        }
+        if let Some(enabled) = profile.event_journal {
+            self.event_journal = enabled;
+        }
+        self.completed_link_dir = profile
+            .completed_link_dir
+            .or(self.completed_link_dir.take());
+        if let Some(copy) = profile.completed_link_copy {
+            self.completed_link_copy = copy;
+        }
+        for (name, value) in profile.headers {
+            if value.is_empty() {
+                self.headers.remove(&name);
+            } else {
+                self.headers.insert(name, value);
+            }
+        }
+        self.ca_cert = profile.ca_cert.or(self.ca_cert.take());
+        if let Some(insecure) = profile.insecure {
+            self.insecure = insecure;
+        }
+    }
+
     pub fn save(&self) -> Result<()> {
         let config_path = Self::config_path()?;
         if let Some(parent) = config_path.parent() {
@@ -55,4 +229,15 @@ impl Config {
         let config_dir = dirs::config_dir().ok_or_else(|| anyhow!("无法获取配置目录"))?;
         Ok(config_dir.join("yushi").join("queue.json"))
     }
+
+    /// 主机认证信息文件，只有所有者可读写
+    pub fn credentials_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir().ok_or_else(|| anyhow!("无法获取配置目录"))?;
+        Ok(config_dir.join("yushi").join("credentials.json"))
+    }
+
+    pub fn history_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir().ok_or_else(|| anyhow!("无法获取配置目录"))?;
+        Ok(config_dir.join("yushi").join("history.json"))
+    }
 }
@@ -0,0 +1,303 @@
+//! 队列的构建
+//!
+//! 队列子命令和 TUI 都通过 [`build_queue`] 创建下载器，设置依次来自保存的配置、
+//! 选中的配置档案、环境变量和命令行参数，后者覆盖前者。保存的主机认证信息也在这里加载。
+
+use crate::{config::Config, ui::parse_speed_limit};
+use anyhow::{Result, anyhow};
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use tokio::sync::mpsc;
+use yushi_core::{CookieSource, DownloadConfig, EventEnvelope, IpPolicy, YuShi};
+
+/// 选择配置档案的环境变量
+const ENV_PROFILE: &str = "YUSHI_PROFILE";
+
+/// 覆盖最大并发任务数的环境变量
+const ENV_MAX_TASKS: &str = "YUSHI_MAX_TASKS";
+/// 覆盖每个任务连接数的环境变量
+const ENV_CONNECTIONS: &str = "YUSHI_CONNECTIONS";
+/// 覆盖代理的环境变量
+const ENV_PROXY: &str = "YUSHI_PROXY";
+
+/// 命令行参数对配置的覆盖，为 None 的项沿用环境变量或保存的配置
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    /// 最大并发任务数
+    pub max_tasks: Option<usize>,
+    /// 每个任务的并发连接数
+    pub connections: Option<usize>,
+    /// 每个任务的速度限制 (例如: 1M, 500K)
+    pub speed_limit: Option<String>,
+    pub proxy: Option<String>,
+    /// 同时校验的任务数
+    pub verify_concurrency: Option<usize>,
+    /// Netscape 格式的 Cookie 文件
+    pub cookies: Option<PathBuf>,
+    /// 连接服务器时使用的 IP 协议版本
+    pub ip_policy: Option<IpPolicy>,
+}
+
+impl Overrides {
+    /// 用环境变量补上没有通过命令行设置的项
+    fn with_env(self, var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let count = |name: &str| -> Result<Option<usize>> {
+            var(name)
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| anyhow!("环境变量 {} 的值无效: {}", name, value))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            max_tasks: self.max_tasks.or(count(ENV_MAX_TASKS)?),
+            connections: self.connections.or(count(ENV_CONNECTIONS)?),
+            proxy: self.proxy.or_else(|| var(ENV_PROXY)),
+            ..self
+        })
+    }
+}
+
+/// 当前使用的配置档案，启动时由 [`set_profile`] 设置
+static PROFILE: OnceLock<Option<String>> = OnceLock::new();
+
+/// 设置使用的配置档案：命令行的 `--profile` 优先，其次是 `YUSHI_PROFILE` 环境变量
+pub fn set_profile(flag: Option<String>) {
+    let profile = flag
+        .or_else(|| std::env::var(ENV_PROFILE).ok())
+        .filter(|name| !name.is_empty());
+    let _ = PROFILE.set(profile);
+}
+
+/// 当前使用的配置档案，None 表示只使用基础配置
+pub fn profile() -> Option<&'static str> {
+    PROFILE.get().and_then(|p| p.as_deref())
+}
+
+/// 加载配置并应用当前的配置档案
+pub fn load_config() -> Result<Config> {
+    let config = Config::load()?;
+    match profile() {
+        Some(name) => config.with_profile(name),
+        None => Ok(config),
+    }
+}
+
+/// 加载配置并创建队列，所有队列子命令和 TUI 都通过这里创建下载器
+pub async fn build_queue(overrides: Overrides) -> Result<(YuShi, mpsc::Receiver<EventEnvelope>)> {
+    let config = load_config()?;
+    let overrides = overrides.with_env(|name| std::env::var(name).ok())?;
+    if let Some(path) = &overrides.cookies {
+        cookie_source(path)?;
+    }
+    let (queue, rx) = build_queue_from(&config, &overrides, &Config::queue_state_path()?)?;
+    load_credentials(&queue).await?;
+    Ok((queue, rx))
+}
+
+/// `--cookies` 指定的 Cookie 文件，文件不存在时返回错误而不是不带 Cookie 下载
+pub fn cookie_source(path: &Path) -> Result<CookieSource> {
+    if !path.is_file() {
+        return Err(anyhow!("Cookie 文件不存在: {}", path.display()));
+    }
+    Ok(CookieSource::File(path.to_path_buf()))
+}
+
+/// 加载 `config set-credential` 保存的主机认证信息，文件不存在时什么也不做
+pub async fn load_credentials(queue: &YuShi) -> Result<()> {
+    let path = Config::credentials_path()?;
+    if path.exists() {
+        queue.load_credentials(&path).await?;
+    }
+    Ok(())
+}
+
+fn build_queue_from(
+    config: &Config,
+    overrides: &Overrides,
+    queue_path: &Path,
+) -> Result<(YuShi, mpsc::Receiver<EventEnvelope>)> {
+    let max_tasks = overrides.max_tasks.unwrap_or(config.default_max_tasks);
+    Ok(YuShi::with_config(
+        download_config(config, overrides)?,
+        max_tasks,
+        queue_path.to_path_buf(),
+    )?)
+}
+
+/// 合并配置和覆盖项得到下载设置，速度限制无效时返回错误而不是不限速
+fn download_config(config: &Config, overrides: &Overrides) -> Result<DownloadConfig> {
+    let speed_limit = match (&overrides.speed_limit, &config.speed_limit) {
+        (Some(limit), _) => {
+            Some(parse_speed_limit(limit).ok_or_else(|| anyhow!("无效的速度限制: {}", limit))?)
+        }
+        (None, Some(limit)) => Some(
+            parse_speed_limit(limit).ok_or_else(|| anyhow!("配置中的速度限制无效: {}", limit))?,
+        ),
+        (None, None) => None,
+    };
+    let defaults = DownloadConfig::default();
+    Ok(DownloadConfig {
+        max_concurrent: overrides.connections.unwrap_or(config.default_connections),
+        speed_limit,
+        user_agent: config.user_agent.clone().or(defaults.user_agent),
+        proxy: overrides.proxy.clone().or_else(|| config.proxy.clone()),
+        cookies: overrides.cookies.clone().map(CookieSource::File),
+        extra_root_certs: config.ca_cert.iter().cloned().collect(),
+        danger_accept_invalid_certs: config.insecure,
+        verify_concurrency: overrides
+            .verify_concurrency
+            .unwrap_or(defaults.verify_concurrency),
+        audit_log: config.audit_log.clone(),
+        temp_dir: config.temp_dir.clone(),
+        case_insensitive_fs: config.case_insensitive_fs,
+        ip_policy: overrides.ip_policy.unwrap_or(config.ip_policy),
+        on_chunk_exhausted: config.on_chunk_exhausted,
+        event_journal: config.event_journal,
+        completed_link_dir: config.completed_link_dir.clone(),
+        completed_link_copy: config.completed_link_copy,
+        headers: config
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+        // 多数命令修改队列后立即退出，来不及执行延后的写入
+        queue_save_interval: None,
+        ..defaults
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Profile;
+
+    fn queue_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("yushi-context-{}.json", std::process::id()))
+    }
+
+    #[test]
+    fn test_config_applies_to_every_entry_point() {
+        let config = Config {
+            default_max_tasks: 5,
+            default_connections: 3,
+            user_agent: Some("custom-agent/2.0".to_string()),
+            ..Default::default()
+        };
+
+        // queue add 使用的下载设置带上配置中的 User-Agent
+        let settings = download_config(&config, &Overrides::default()).unwrap();
+        assert_eq!(settings.user_agent.as_deref(), Some("custom-agent/2.0"));
+        assert_eq!(settings.max_concurrent, 3);
+
+        // TUI 不传覆盖项，并发数来自配置
+        let (queue, _rx) = build_queue_from(&config, &Overrides::default(), &queue_path()).unwrap();
+        assert_eq!(queue.max_concurrent_tasks(), 5);
+        assert_eq!(queue.default_connections(), 3);
+
+        let overrides = Overrides {
+            max_tasks: Some(1),
+            connections: Some(8),
+            ..Default::default()
+        };
+        let (queue, _rx) = build_queue_from(&config, &overrides, &queue_path()).unwrap();
+        assert_eq!(queue.max_concurrent_tasks(), 1);
+        assert_eq!(queue.default_connections(), 8);
+    }
+
+    #[test]
+    fn test_profile_overrides_subset_of_settings() {
+        let mut config = Config {
+            proxy: Some("http://home:8080".to_string()),
+            headers: [
+                ("X-Team".to_string(), "base".to_string()),
+                ("X-Debug".to_string(), "1".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        config.profiles.insert(
+            "work".to_string(),
+            Profile {
+                proxy: Some("http://corp:3128".to_string()),
+                default_max_tasks: Some(6),
+                headers: [
+                    ("Authorization".to_string(), "Bearer abc".to_string()),
+                    ("X-Debug".to_string(), String::new()),
+                ]
+                .into(),
+                ..Default::default()
+            },
+        );
+
+        let work = config.clone().with_profile("work").unwrap();
+        let settings = download_config(&work, &Overrides::default()).unwrap();
+        assert_eq!(settings.proxy.as_deref(), Some("http://corp:3128"));
+        assert_eq!(settings.user_agent.as_deref(), Some("YuShi/1.0"));
+        assert_eq!(settings.headers.get("Authorization").unwrap(), "Bearer abc");
+        assert_eq!(settings.headers.get("X-Team").unwrap(), "base");
+        assert!(!settings.headers.contains_key("X-Debug"));
+        let (queue, _rx) = build_queue_from(&work, &Overrides::default(), &queue_path()).unwrap();
+        assert_eq!(queue.max_concurrent_tasks(), 6);
+
+        // 命令行参数仍然优先于配置档案
+        let overrides = Overrides {
+            proxy: Some("http://cli:1080".to_string()),
+            ..Default::default()
+        };
+        let settings = download_config(&work, &overrides).unwrap();
+        assert_eq!(settings.proxy.as_deref(), Some("http://cli:1080"));
+
+        assert!(config.with_profile("missing").is_err());
+    }
+
+    #[test]
+    fn test_invalid_speed_limit_is_an_error() {
+        let config = Config {
+            speed_limit: Some("fast".to_string()),
+            ..Default::default()
+        };
+        let err = build_queue_from(&config, &Overrides::default(), &queue_path()).unwrap_err();
+        assert!(err.to_string().contains("fast"));
+
+        let overrides = Overrides {
+            speed_limit: Some("1X".to_string()),
+            ..Default::default()
+        };
+        let err = build_queue_from(&Config::default(), &overrides, &queue_path()).unwrap_err();
+        assert!(err.to_string().contains("1X"));
+
+        // 命令行参数有效时覆盖配置中的值
+        let overrides = Overrides {
+            speed_limit: Some("2M".to_string()),
+            ..Default::default()
+        };
+        let settings = download_config(&config, &overrides).unwrap();
+        assert_eq!(settings.speed_limit, Some(2 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let env = |name: &str| match name {
+            ENV_MAX_TASKS => Some("7".to_string()),
+            ENV_PROXY => Some("http://proxy:8080".to_string()),
+            _ => None,
+        };
+        let overrides = Overrides {
+            max_tasks: Some(2),
+            ..Default::default()
+        }
+        .with_env(env)
+        .unwrap();
+        // 命令行参数优先于环境变量
+        assert_eq!(overrides.max_tasks, Some(2));
+        assert_eq!(overrides.connections, None);
+        assert_eq!(overrides.proxy.as_deref(), Some("http://proxy:8080"));
+
+        let invalid = Overrides::default().with_env(|_| Some("many".to_string()));
+        assert!(invalid.is_err());
+    }
+}
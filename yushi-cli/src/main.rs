@@ -1,6 +1,9 @@
 mod cli;
 mod commands;
 mod config;
+mod context;
+mod table;
+mod timeline;
 #[cfg(feature = "tui")]
 mod tui;
 mod ui;
@@ -11,16 +14,19 @@ use clap::Parser;
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = cli::Cli::parse();
+    if let Some(lang) = &cli.lang {
+        yushi_core::i18n::set_locale(lang);
+    }
+    context::set_profile(cli.profile.clone());
 
     match cli.command {
         cli::Commands::Download(args) => commands::download::execute(args).await?,
+        cli::Commands::Info(args) => commands::info::execute(args).await?,
         cli::Commands::Queue(args) => commands::queue::execute(args).await?,
         cli::Commands::Config(args) => commands::config::execute(args).await?,
+        cli::Commands::History(args) => commands::history::execute(args).await?,
         #[cfg(feature = "tui")]
-        cli::Commands::Tui => {
-            let queue_path = config::Config::queue_state_path()?;
-            tui::run(queue_path).await?;
-        }
+        cli::Commands::Tui => tui::run().await?,
     }
 
     Ok(())
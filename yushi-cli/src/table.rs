@@ -0,0 +1,300 @@
+//! `queue list --format table|wide` 的表格输出
+//!
+//! 每个任务一行。列宽取表头和内容中最宽的一项，总宽度超过终端宽度时只压缩名称、URL
+//! 和路径这几列，过长的内容截断并以 `…` 结尾。
+
+use anyhow::{Result, anyhow};
+use console::{Alignment, measure_text_width, pad_str, truncate_str};
+use yushi_core::{
+    Task, TaskStatus,
+    i18n::{format_eta_with, format_size_with},
+};
+
+/// 列之间的间隔
+const GAP: &str = "  ";
+/// 可压缩的列至少保留的宽度
+const MIN_FLEX_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Name,
+    Status,
+    Progress,
+    Size,
+    Speed,
+    Eta,
+    Url,
+    Dest,
+}
+
+/// `--format table` 的默认列
+pub const TABLE_COLUMNS: &[Column] = &[
+    Column::Id,
+    Column::Name,
+    Column::Status,
+    Column::Progress,
+    Column::Size,
+    Column::Speed,
+    Column::Eta,
+];
+
+/// `--format wide` 的默认列
+pub const WIDE_COLUMNS: &[Column] = &[
+    Column::Id,
+    Column::Name,
+    Column::Status,
+    Column::Progress,
+    Column::Size,
+    Column::Speed,
+    Column::Eta,
+    Column::Url,
+    Column::Dest,
+];
+
+impl Column {
+    fn parse(name: &str) -> Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "id" => Ok(Self::Id),
+            "name" => Ok(Self::Name),
+            "status" => Ok(Self::Status),
+            "progress" => Ok(Self::Progress),
+            "size" => Ok(Self::Size),
+            "speed" => Ok(Self::Speed),
+            "eta" => Ok(Self::Eta),
+            "url" => Ok(Self::Url),
+            "dest" => Ok(Self::Dest),
+            _ => Err(anyhow!(
+                "无效的列: {} (可用: id, name, status, progress, size, speed, eta, url, dest)",
+                name
+            )),
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Self::Id => "ID",
+            Self::Name => "名称",
+            Self::Status => "状态",
+            Self::Progress => "进度",
+            Self::Size => "大小",
+            Self::Speed => "速度",
+            Self::Eta => "剩余",
+            Self::Url => "URL",
+            Self::Dest => "路径",
+        }
+    }
+
+    /// 终端宽度不够时是否可以压缩
+    fn flexible(self) -> bool {
+        matches!(self, Self::Name | Self::Url | Self::Dest)
+    }
+
+    fn alignment(self) -> Alignment {
+        match self {
+            Self::Progress | Self::Size | Self::Speed | Self::Eta => Alignment::Right,
+            _ => Alignment::Left,
+        }
+    }
+
+    fn cell(self, task: &Task, locale: &str) -> String {
+        match self {
+            Self::Id => task.id.chars().take(8).collect(),
+            Self::Name => task.label(),
+            Self::Status => status_label(task.status).to_string(),
+            Self::Progress if task.total_size > 0 => {
+                let progress = task.downloaded as f64 / task.total_size as f64 * 100.0;
+                format!("{:.1}%", progress.min(100.0))
+            }
+            Self::Size if task.total_size > 0 => format_size_with(locale, task.total_size),
+            Self::Speed if task.speed > 0 => format!("{}/s", format_size_with(locale, task.speed)),
+            Self::Eta => task
+                .eta
+                .map(|eta| format_eta_with(locale, eta))
+                .unwrap_or_else(|| "-".to_string()),
+            Self::Url => task.url.clone(),
+            Self::Dest => task.dest.display().to_string(),
+            Self::Progress | Self::Size | Self::Speed => "-".to_string(),
+        }
+    }
+}
+
+/// 解析 `--columns`，为空时返回 `default`
+pub fn parse_columns(names: &[String], default: &[Column]) -> Result<Vec<Column>> {
+    if names.is_empty() {
+        return Ok(default.to_vec());
+    }
+    names.iter().map(|name| Column::parse(name)).collect()
+}
+
+fn status_label(status: TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Pending => "等待中",
+        TaskStatus::Downloading => "下载中",
+        TaskStatus::Verifying => "校验中",
+        TaskStatus::Paused => "已暂停",
+        TaskStatus::Completed => "已完成",
+        TaskStatus::Failed => "失败",
+        TaskStatus::Cancelled => "已取消",
+        TaskStatus::PartiallyCompleted => "部分完成",
+    }
+}
+
+/// 把任务渲染为表格，`width` 为终端宽度，None 表示不限制（输出到管道时）
+///
+/// 大小和剩余时间按 `locale` 格式化
+pub fn render(tasks: &[Task], columns: &[Column], width: Option<usize>, locale: &str) -> String {
+    let rows: Vec<Vec<String>> = tasks
+        .iter()
+        .map(|task| {
+            columns
+                .iter()
+                .map(|column| column.cell(task, locale))
+                .collect()
+        })
+        .collect();
+    let natural: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| measure_text_width(&row[i]))
+                .fold(measure_text_width(column.header()), usize::max)
+        })
+        .collect();
+    let widths = match width {
+        Some(width) => fit_widths(columns, &natural, width),
+        None => natural,
+    };
+
+    let headers: Vec<String> = columns.iter().map(|c| c.header().to_string()).collect();
+    let mut out = String::new();
+    for row in std::iter::once(&headers).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(columns)
+            .zip(&widths)
+            .map(|((cell, column), &width)| {
+                let cell = truncate_str(cell, width, "…");
+                pad_str(&cell, width, column.alignment(), None).into_owned()
+            })
+            .collect();
+        out.push_str(line.join(GAP).trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// 在 `width` 内分配列宽
+///
+/// 固定列保持原宽，剩余宽度按从窄到宽的顺序平均分给可压缩的列：
+/// 较窄的列用不完的部分留给后面更宽的列。可压缩的列至少保留 [`MIN_FLEX_WIDTH`]，
+/// 因此列很多或终端很窄时表格仍可能超出 `width`。
+fn fit_widths(columns: &[Column], natural: &[usize], width: usize) -> Vec<usize> {
+    let total = natural.iter().sum::<usize>() + GAP.len() * columns.len().saturating_sub(1);
+    if total <= width {
+        return natural.to_vec();
+    }
+    let fixed: usize = columns
+        .iter()
+        .zip(natural)
+        .filter(|(column, _)| !column.flexible())
+        .map(|(_, &w)| w)
+        .sum();
+    let mut available = width.saturating_sub(fixed + GAP.len() * columns.len().saturating_sub(1));
+
+    let mut flexible: Vec<usize> = (0..columns.len())
+        .filter(|&i| columns[i].flexible())
+        .collect();
+    flexible.sort_by_key(|&i| natural[i]);
+
+    let mut widths = natural.to_vec();
+    for (n, &i) in flexible.iter().enumerate() {
+        let share = available / (flexible.len() - n);
+        let min = MIN_FLEX_WIDTH.min(natural[i]);
+        widths[i] = natural[i].min(share).max(min);
+        available = available.saturating_sub(widths[i]);
+    }
+    widths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn task(id: &str, dest: &str, status: &str, total: u64, downloaded: u64) -> Task {
+        serde_json::from_value(json!({
+            "id": id,
+            "url": format!("https://example.com/files/{}", dest),
+            "dest": format!("/downloads/{}", dest),
+            "status": status,
+            "total_size": total,
+            "downloaded": downloaded,
+            "created_at": 0,
+            "error": null,
+        }))
+        .unwrap()
+    }
+
+    fn tasks() -> Vec<Task> {
+        let mut downloading = task(
+            "3f2a9c1e7b5d4a60",
+            "ubuntu-24.04.1-desktop-amd64.iso",
+            "Downloading",
+            6_203_355_136,
+            1_550_838_784,
+        );
+        downloading.speed = 12_582_912;
+        downloading.eta = Some(370);
+        vec![
+            downloading,
+            task("a1b2c3d4e5f60718", "notes.txt", "Completed", 2048, 2048),
+            task("0c0ffee000000000", "stream.bin", "Pending", 0, 0),
+        ]
+    }
+
+    #[test]
+    fn test_render_table() {
+        let table = render(&tasks(), TABLE_COLUMNS, None, "en");
+        assert_eq!(
+            table,
+            "\
+ID        名称                              状态      进度     大小        速度    剩余
+3f2a9c1e  ubuntu-24.04.1-desktop-amd64.iso  下载中   25.0%  5.78 GB  12.00 MB/s  6m 10s
+a1b2c3d4  notes.txt                         已完成  100.0%  2.00 KB           -       -
+0c0ffee0  stream.bin                        等待中       -        -           -       -
+"
+        );
+    }
+
+    #[test]
+    fn test_render_truncates_flexible_columns() {
+        let columns = parse_columns(
+            &["name".to_string(), "status".to_string(), "url".to_string()],
+            TABLE_COLUMNS,
+        )
+        .unwrap();
+        let table = render(&tasks(), &columns, Some(48), "en");
+        assert_eq!(
+            table,
+            "\
+名称                 状态    URL
+ubuntu-24.04.1-des…  下载中  https://example.co…
+notes.txt            已完成  https://example.co…
+stream.bin           等待中  https://example.co…
+"
+        );
+        assert!(table.lines().all(|line| measure_text_width(line) <= 48));
+    }
+
+    #[test]
+    fn test_parse_columns() {
+        assert_eq!(parse_columns(&[], WIDE_COLUMNS).unwrap(), WIDE_COLUMNS);
+        assert_eq!(
+            parse_columns(&["ETA".to_string(), " id".to_string()], TABLE_COLUMNS).unwrap(),
+            [Column::Eta, Column::Id]
+        );
+        assert!(parse_columns(&["owner".to_string()], TABLE_COLUMNS).is_err());
+    }
+}
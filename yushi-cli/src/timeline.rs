@@ -0,0 +1,139 @@
+//! `queue plan` 的时间线输出
+//!
+//! 每个任务一行：ID、名称、预计开始和完成时间，最后是按最晚的完成时间缩放的横条。
+//! 完成时间未知的任务从开始位置画到结尾（`░`），开始时间未知的任务不画横条。
+
+use console::{Alignment, measure_text_width, pad_str, truncate_str};
+use yushi_core::{ScheduledSlot, Task, i18n::format_eta_with};
+
+/// 名称列的最大宽度
+const NAME_WIDTH: usize = 24;
+
+/// 渲染时间线，`bar_width` 为横条的宽度，时间按 `locale` 格式化
+pub fn render(rows: &[(&Task, &ScheduledSlot)], bar_width: usize, locale: &str) -> String {
+    let end = rows
+        .iter()
+        .filter_map(|(_, slot)| slot.est_finish.or(slot.est_start))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let time = |secs: Option<u64>| {
+        secs.map(|secs| format_eta_with(locale, secs))
+            .unwrap_or_else(|| "?".to_string())
+    };
+    let cells: Vec<[String; 4]> = rows
+        .iter()
+        .map(|(task, slot)| {
+            [
+                task.id.chars().take(8).collect(),
+                truncate_str(&task.label(), NAME_WIDTH, "…").into_owned(),
+                time(slot.est_start),
+                time(slot.est_finish),
+            ]
+        })
+        .collect();
+    let headers = ["ID", "名称", "开始", "完成"].map(str::to_string);
+    let widths: Vec<usize> = (0..4)
+        .map(|i| {
+            cells
+                .iter()
+                .map(|row| measure_text_width(&row[i]))
+                .fold(measure_text_width(&headers[i]), usize::max)
+        })
+        .collect();
+
+    let line = |row: &[String; 4]| -> String {
+        row.iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, &width))| {
+                let align = if i >= 2 {
+                    Alignment::Right
+                } else {
+                    Alignment::Left
+                };
+                pad_str(cell, width, align, None).into_owned()
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    let mut out = format!("{}\n", line(&headers).trim_end());
+    for ((_, slot), row) in rows.iter().zip(&cells) {
+        let bar = bar(slot, end, bar_width);
+        out.push_str(format!("{}  |{}|", line(row), bar).trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// 一个任务的横条，`end` 秒对应横条的末尾
+fn bar(slot: &ScheduledSlot, end: u64, width: usize) -> String {
+    let Some(start) = slot.est_start else {
+        return " ".repeat(width);
+    };
+    let column = |secs: u64| (secs.min(end) as f64 / end as f64 * width as f64).round() as usize;
+    let from = column(start).min(width.saturating_sub(1));
+    let (to, fill) = match slot.est_finish {
+        Some(finish) => (column(finish).max(from + 1), '█'),
+        None => (width, '░'),
+    };
+    let mut bar = " ".repeat(from);
+    bar.extend(std::iter::repeat_n(fill, to - from));
+    bar.push_str(&" ".repeat(width - to));
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn task(id: &str, dest: &str) -> Task {
+        serde_json::from_value(json!({
+            "id": id,
+            "url": format!("https://example.com/{}", dest),
+            "dest": format!("/downloads/{}", dest),
+            "status": "Pending",
+            "total_size": 0,
+            "downloaded": 0,
+            "created_at": 0,
+            "error": null,
+        }))
+        .unwrap()
+    }
+
+    fn slot(start: Option<u64>, finish: Option<u64>) -> ScheduledSlot {
+        ScheduledSlot {
+            task_id: String::new(),
+            est_start: start,
+            est_finish: finish,
+        }
+    }
+
+    #[test]
+    fn test_render_timeline() {
+        let tasks = [
+            task("3f2a9c1e7b5d4a60", "ubuntu.iso"),
+            task("a1b2c3d4e5f60718", "notes.txt"),
+            task("0c0ffee000000000", "stream.bin"),
+            task("deadbeef00000000", "later.bin"),
+        ];
+        let slots = [
+            slot(Some(0), Some(60)),
+            slot(Some(60), Some(120)),
+            slot(Some(120), None),
+            slot(None, None),
+        ];
+        let rows: Vec<_> = tasks.iter().zip(&slots).collect();
+        assert_eq!(
+            render(&rows, 10, "en"),
+            "\
+ID        名称         开始   完成
+3f2a9c1e  ubuntu.iso     0s  1m 0s  |█████     |
+a1b2c3d4  notes.txt   1m 0s  2m 0s  |     █████|
+0c0ffee0  stream.bin  2m 0s      ?  |         ░|
+deadbeef  later.bin       ?      ?  |          |
+"
+        );
+    }
+}
@@ -1,12 +1,24 @@
+use crate::{
+    context::{Overrides, build_queue},
+    ui::format_size,
+};
 use anyhow::Result;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio::sync::mpsc;
 use yushi_core::{
-    DownloadTask, DownloaderEvent, Priority, ProgressEvent, QueueEvent, TaskEvent, TaskStatus,
-    YuShi,
+    DownloadTask, DownloaderEvent, EventEnvelope, Forecast, GlobalEvent, Priority, ProgressEvent,
+    QueueStats, SchedulerState, TaskEvent, TaskStatus, YuShi, filename_from_url,
+    resolve_destination,
 };
 
+/// `b` 键每次加速的时长，已在加速时延长
+const BOOST_DURATION: Duration = Duration::from_secs(5 * 60);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InputMode {
     Normal,
@@ -27,14 +39,27 @@ pub struct App {
     pub input_buffer: String,
     pub selected_panel: SelectedPanel,
     pub status_message: String,
-    event_rx: mpsc::Receiver<QueueEvent>,
+    /// 停滞中的任务及其没有收到数据的秒数
+    pub stalled: HashMap<String, u64>,
+    /// 队列调度器状态
+    pub scheduler: SchedulerState,
+    /// 等待中和暂停任务的磁盘占用预估，任务列表变化时更新
+    pub forecast: Forecast,
+    /// 队列统计，每次刷新时更新
+    pub stats: QueueStats,
+    /// 下载中任务的分块是否完成，选中任务时从状态文件读取，之后按分块完成事件更新
+    pub chunk_maps: HashMap<String, Vec<bool>>,
+    event_rx: mpsc::Receiver<EventEnvelope>,
 }
 
 impl App {
-    pub async fn new(queue_path: PathBuf) -> Result<Self> {
-        let (queue, event_rx) = YuShi::new(4, 2, queue_path);
+    pub async fn new() -> Result<Self> {
+        let (queue, event_rx) = build_queue(Overrides::default()).await?;
         queue.load_queue_from_state().await?;
         let tasks = queue.get_all_tasks().await;
+        let scheduler = queue.scheduler_state();
+        let forecast = queue.forecast(None).await;
+        let stats = queue.get_stats();
 
         Ok(Self {
             queue,
@@ -44,6 +69,11 @@ impl App {
             input_buffer: String::new(),
             selected_panel: SelectedPanel::TaskList,
             status_message: "就绪".to_string(),
+            stalled: HashMap::new(),
+            scheduler,
+            forecast,
+            stats,
+            chunk_maps: HashMap::new(),
             event_rx,
         })
     }
@@ -63,15 +93,13 @@ impl App {
                 return Ok(false);
             }
             // 导航
-            (KeyCode::Up | KeyCode::Char('k'), KeyModifiers::NONE) => {
-                if self.selected_index > 0 {
-                    self.selected_index -= 1;
-                }
+            (KeyCode::Up | KeyCode::Char('k'), KeyModifiers::NONE) if self.selected_index > 0 => {
+                self.selected_index -= 1;
             }
-            (KeyCode::Down | KeyCode::Char('j'), KeyModifiers::NONE) => {
-                if self.selected_index < self.tasks.len().saturating_sub(1) {
-                    self.selected_index += 1;
-                }
+            (KeyCode::Down | KeyCode::Char('j'), KeyModifiers::NONE)
+                if self.selected_index < self.tasks.len().saturating_sub(1) =>
+            {
+                self.selected_index += 1;
             }
             (KeyCode::Home | KeyCode::Char('g'), KeyModifiers::NONE) => {
                 self.selected_index = 0;
@@ -96,9 +124,12 @@ impl App {
             (KeyCode::Char('p'), KeyModifiers::NONE) => {
                 if let Some(task) = self.tasks.get(self.selected_index) {
                     match task.status {
-                        TaskStatus::Downloading => {
-                            self.queue.pause_task(&task.id).await?;
-                            self.status_message = format!("已暂停任务: {}", &task.id[..8]);
+                        TaskStatus::Downloading | TaskStatus::Verifying => {
+                            // 只校验本地文件的任务不能暂停，显示原因而不是退出
+                            self.status_message = match self.queue.pause_task(&task.id).await {
+                                Ok(()) => format!("已暂停任务: {}", &task.id[..8]),
+                                Err(e) => e.to_string(),
+                            };
                         }
                         TaskStatus::Paused => {
                             self.queue.resume_task(&task.id).await?;
@@ -108,12 +139,21 @@ impl App {
                     }
                 }
             }
+            // 临时加速
+            (KeyCode::Char('b'), KeyModifiers::NONE) => {
+                if let Some(task) = self.tasks.get(self.selected_index) {
+                    self.queue.boost_task(&task.id, BOOST_DURATION).await?;
+                }
+            }
             // 取消任务
             (KeyCode::Char('c'), KeyModifiers::NONE) => {
                 if let Some(task) = self.tasks.get(self.selected_index)
                     && matches!(
                         task.status,
-                        TaskStatus::Pending | TaskStatus::Downloading | TaskStatus::Paused
+                        TaskStatus::Pending
+                            | TaskStatus::Downloading
+                            | TaskStatus::Verifying
+                            | TaskStatus::Paused
                     )
                 {
                     self.queue.cancel_task(&task.id).await?;
@@ -141,6 +181,17 @@ impl App {
                 self.status_message = "已清空已完成任务".to_string();
                 self.selected_index = 0;
             }
+            // 暂停/恢复队列调度
+            (KeyCode::Char('P'), KeyModifiers::SHIFT) => {
+                if self.scheduler.running {
+                    self.queue.pause_queue().await;
+                    self.status_message = "队列已暂停".to_string();
+                } else {
+                    self.queue.resume_queue().await?;
+                    self.status_message = "队列已恢复".to_string();
+                }
+                self.scheduler = self.queue.scheduler_state();
+            }
             // 刷新
             (KeyCode::Char('r'), KeyModifiers::NONE) | (KeyCode::F(5), KeyModifiers::NONE) => {
                 self.refresh_tasks().await?;
@@ -188,22 +239,18 @@ impl App {
             PathBuf::from(parts[1].trim())
         } else {
             // 从 URL 提取文件名
-            let filename = url
-                .split('/')
-                .next_back()
-                .and_then(|s| s.split('?').next())
-                .unwrap_or("download");
-            PathBuf::from(filename)
+            resolve_destination(Path::new(""), &filename_from_url(&url).unwrap_or_default())
         };
 
-        let priority = if parts.len() > 2 {
-            match parts[2].trim().to_lowercase().as_str() {
-                "high" | "h" | "高" => Priority::High,
-                "low" | "l" | "低" => Priority::Low,
-                _ => Priority::Normal,
-            }
-        } else {
-            Priority::Normal
+        let priority = match parts.get(2).map(|p| p.trim()) {
+            Some(p) if !p.is_empty() => match p.parse() {
+                Ok(priority) => priority,
+                Err(e) => {
+                    self.status_message = format!("错误: {}", e);
+                    return Ok(());
+                }
+            },
+            _ => Priority::Normal,
         };
 
         match self
@@ -225,33 +272,132 @@ impl App {
 
     pub async fn on_tick(&mut self) -> Result<()> {
         // 处理队列事件
-        while let Ok(event) = self.event_rx.try_recv() {
-            match event {
-                DownloaderEvent::Progress(ProgressEvent::Updated { task_id, .. }) => {
-                    // 更新任务进度
-                    if let Some(task) = self.queue.get_task(&task_id).await
-                        && let Some(idx) = self.tasks.iter().position(|t| t.id == task_id)
-                    {
-                        self.tasks[idx] = task;
-                    }
-                }
-                DownloaderEvent::Task(TaskEvent::Completed { task_id }) => {
+        while let Ok(envelope) = self.event_rx.try_recv() {
+            match envelope.event {
+                DownloaderEvent::Task(TaskEvent::Completed { task_id, .. }) => {
                     self.status_message = format!("任务完成: {}", &task_id[..8]);
+                    self.chunk_maps.remove(&task_id);
                     self.refresh_tasks().await?;
                 }
                 DownloaderEvent::Task(TaskEvent::Failed { task_id, error }) => {
                     self.status_message = format!("任务失败: {} - {}", &task_id[..8], error);
+                    self.chunk_maps.remove(&task_id);
+                    self.refresh_tasks().await?;
+                }
+                DownloaderEvent::Task(TaskEvent::PartiallyCompleted {
+                    task_id,
+                    missing_ranges,
+                }) => {
+                    self.status_message = format!(
+                        "任务部分完成: {} - {} 个范围缺失",
+                        &task_id[..8],
+                        missing_ranges.len()
+                    );
+                    self.chunk_maps.remove(&task_id);
                     self.refresh_tasks().await?;
                 }
+                DownloaderEvent::Task(TaskEvent::LinkFailed { task_id, error }) => {
+                    self.status_message = format!("创建链接失败: {} - {}", &task_id[..8], error);
+                }
+                DownloaderEvent::Task(TaskEvent::Boosted { task_id, until_ms }) => {
+                    let until = std::time::UNIX_EPOCH + Duration::from_millis(until_ms);
+                    let remaining = until
+                        .duration_since(std::time::SystemTime::now())
+                        .unwrap_or_default();
+                    self.status_message = format!(
+                        "已加速任务: {}，{} 内不限速",
+                        &task_id[..8],
+                        humantime::format_duration(Duration::from_secs(remaining.as_secs()))
+                    );
+                }
+                DownloaderEvent::Task(TaskEvent::BoostEnded { task_id }) => {
+                    self.status_message = format!("任务加速结束: {}", &task_id[..8]);
+                }
+                DownloaderEvent::Progress(ProgressEvent::ChunkFinished {
+                    task_id,
+                    chunk_index,
+                    ..
+                }) => {
+                    if let Some(finished) = self
+                        .chunk_maps
+                        .get_mut(&task_id)
+                        .and_then(|m| m.get_mut(chunk_index))
+                    {
+                        *finished = true;
+                    }
+                }
+                DownloaderEvent::Global(GlobalEvent::SchedulerStateChanged(state)) => {
+                    self.scheduler = state;
+                }
+                DownloaderEvent::Global(GlobalEvent::DiskPressure {
+                    latency_ms,
+                    throttled_to,
+                }) => {
+                    self.status_message = match throttled_to {
+                        Some(rate) => format!(
+                            "磁盘写入缓慢 (平均 {} ms)，总速度限制为 {}/s",
+                            latency_ms,
+                            format_size(rate)
+                        ),
+                        None => "磁盘写入恢复正常，解除限速".to_string(),
+                    };
+                }
                 _ => {}
             }
         }
 
+        self.stats = self.queue.get_stats();
+        self.load_chunk_map().await;
+
+        // 列表进度读取进度快照，避免与下载线程争用任务表的锁
+        let snapshots: HashMap<_, _> = self
+            .queue
+            .progress_all()
+            .into_iter()
+            .map(|s| (s.task_id.clone(), s))
+            .collect();
+        self.stalled = snapshots
+            .values()
+            .filter_map(|s| s.stalled_for.map(|secs| (s.task_id.clone(), secs)))
+            .collect();
+        for task in &mut self.tasks {
+            if let Some(snapshot) = snapshots.get(&task.id) {
+                task.status = snapshot.status;
+                task.downloaded = snapshot.downloaded;
+                task.total_size = snapshot.total;
+                task.speed = snapshot.speed;
+                task.eta = snapshot.eta;
+                task.verified = snapshot.verified;
+            }
+        }
+
         Ok(())
     }
 
+    /// 选中的任务正在下载且还没有分块信息时，从状态文件读取一次
+    async fn load_chunk_map(&mut self) {
+        let Some(task) = self.tasks.get(self.selected_index) else {
+            return;
+        };
+        if task.status != TaskStatus::Downloading || self.chunk_maps.contains_key(&task.id) {
+            return;
+        }
+        // 状态文件还没有创建时下次刷新再读取；流式下载没有分块，记为空
+        if let Ok(diagnostics) = self.queue.diagnostics(&task.id).await
+            && let Some(state) = diagnostics.state
+        {
+            let map = if state.is_streaming {
+                Vec::new()
+            } else {
+                state.chunks.iter().map(|c| c.finished).collect()
+            };
+            self.chunk_maps.insert(task.id.clone(), map);
+        }
+    }
+
     async fn refresh_tasks(&mut self) -> Result<()> {
         self.tasks = self.queue.get_all_tasks().await;
+        self.forecast = self.queue.forecast(None).await;
         if self.selected_index >= self.tasks.len() && !self.tasks.is_empty() {
             self.selected_index = self.tasks.len() - 1;
         }
@@ -261,4 +407,16 @@ impl App {
     pub fn get_selected_task(&self) -> Option<&DownloadTask> {
         self.tasks.get(self.selected_index)
     }
+
+    /// 磁盘空间不足或下载完后剩余不到空闲空间的 10% 时的提示
+    pub fn disk_warning(&self) -> Option<&'static str> {
+        if self.forecast.will_fit == Some(false) {
+            return Some("磁盘空间不足");
+        }
+        self.forecast
+            .free_space_by_volume
+            .iter()
+            .any(|v| v.headroom().is_some_and(|left| left < v.free_space / 10))
+            .then_some("磁盘空间紧张")
+    }
 }
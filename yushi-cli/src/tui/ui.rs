@@ -1,5 +1,8 @@
 use super::app::{App, InputMode, SelectedPanel};
-use crate::ui::format_size;
+use crate::{
+    context::profile,
+    ui::{format_eta, format_scheduler, format_size},
+};
 use ratatui::{
     Frame,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
@@ -7,7 +10,7 @@ use ratatui::{
     text::{Line, Span},
     widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Wrap},
 };
-use yushi_core::TaskStatus;
+use yushi_core::{DownloadTask, QueueStats, TaskPriority, TaskStatus};
 
 pub fn draw(f: &mut Frame, app: &App) {
     let chunks = Layout::default()
@@ -20,14 +23,30 @@ pub fn draw(f: &mut Frame, app: &App) {
         ])
         .split(f.area());
 
-    draw_header(f, chunks[0]);
+    draw_header(f, app, chunks[0]);
     draw_main_content(f, app, chunks[1]);
     draw_status_bar(f, app, chunks[2]);
     draw_help(f, app, chunks[3]);
 }
 
-fn draw_header(f: &mut Frame, area: Rect) {
-    let title = Paragraph::new("YuShi 下载管理器")
+fn draw_header(f: &mut Frame, app: &App, area: Rect) {
+    let mut spans = vec![Span::raw(format!(
+        "YuShi 下载管理器 · 调度: {}",
+        format_scheduler(&app.scheduler)
+    ))];
+    if let Some(profile) = profile() {
+        spans.push(Span::raw(format!(" · 配置档案: {}", profile)));
+    }
+    if let Some(remaining) = format_remaining_by_priority(&app.stats) {
+        spans.push(Span::raw(format!(" · {}", remaining)));
+    }
+    if let Some(warning) = app.disk_warning() {
+        spans.push(Span::styled(
+            format!(" · {}", warning),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    let title = Paragraph::new(Line::from(spans))
         .style(
             Style::default()
                 .fg(Color::Cyan)
@@ -57,34 +76,36 @@ fn draw_task_list(f: &mut Frame, app: &App, area: Rect) {
             let status_icon = match task.status {
                 TaskStatus::Pending => "⏸",
                 TaskStatus::Downloading => "⬇",
+                TaskStatus::Verifying => "🔍",
                 TaskStatus::Paused => "⏸",
                 TaskStatus::Completed => "✓",
                 TaskStatus::Failed => "✗",
                 TaskStatus::Cancelled => "⊗",
+                TaskStatus::PartiallyCompleted => "◐",
             };
 
             let status_color = match task.status {
                 TaskStatus::Pending => Color::Yellow,
                 TaskStatus::Downloading => Color::Blue,
+                TaskStatus::Verifying => Color::Cyan,
                 TaskStatus::Paused => Color::Magenta,
                 TaskStatus::Completed => Color::Green,
                 TaskStatus::Failed => Color::Red,
                 TaskStatus::Cancelled => Color::DarkGray,
+                TaskStatus::PartiallyCompleted => Color::LightRed,
             };
 
-            let progress = if task.total_size > 0 {
-                (task.downloaded as f64 / task.total_size as f64 * 100.0) as u16
-            } else {
-                0
-            };
+            let progress = task_progress(task);
 
-            let filename = task
-                .dest
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("unknown");
+            let filename = task.label();
 
-            let size_str = if task.total_size > 0 {
+            let size_str = if task.status == TaskStatus::Verifying {
+                format!(
+                    "校验 {} / {}",
+                    format_size(task.verified),
+                    format_size(task.total_size)
+                )
+            } else if task.total_size > 0 {
                 format!(
                     "{} / {}",
                     format_size(task.downloaded),
@@ -99,6 +120,11 @@ fn draw_task_list(f: &mut Frame, app: &App, area: Rect) {
             } else {
                 String::new()
             };
+            let stall_str = app
+                .stalled
+                .get(&task.id)
+                .map(|secs| format!("  停滞 {}s", secs))
+                .unwrap_or_default();
 
             let content = vec![
                 Line::from(vec![
@@ -114,6 +140,7 @@ fn draw_task_list(f: &mut Frame, app: &App, area: Rect) {
                     Span::raw(format!("  {}%  ", progress)),
                     Span::styled(size_str, Style::default().fg(Color::Gray)),
                     Span::styled(speed_str, Style::default().fg(Color::Cyan)),
+                    Span::styled(stall_str, Style::default().fg(Color::Yellow)),
                 ]),
             ];
 
@@ -177,6 +204,11 @@ fn draw_task_details(f: &mut Frame, app: &App, area: Rect) {
             )]),
             Line::from(format!("  {}", task.dest.display())),
             Line::from(""),
+            Line::from(vec![
+                Span::styled("名称: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(task.label()),
+            ]),
+            Line::from(""),
             Line::from(vec![
                 Span::styled("状态: ", Style::default().add_modifier(Modifier::BOLD)),
                 Span::styled(
@@ -185,6 +217,7 @@ fn draw_task_details(f: &mut Frame, app: &App, area: Rect) {
                         TaskStatus::Completed => Color::Green,
                         TaskStatus::Failed => Color::Red,
                         TaskStatus::Downloading => Color::Blue,
+                        TaskStatus::Verifying => Color::Cyan,
                         _ => Color::Yellow,
                     }),
                 ),
@@ -194,8 +227,48 @@ fn draw_task_details(f: &mut Frame, app: &App, area: Rect) {
                 Span::styled("优先级: ", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(format!("{:?}", task.priority)),
             ]),
+            Line::from(vec![
+                Span::styled("连接数: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw({
+                    let setting = task
+                        .connections
+                        .map_or_else(|| "默认".to_string(), |n| n.to_string());
+                    // 逐步增加连接数时显示当前已经允许的连接数
+                    if task.status == TaskStatus::Downloading && task.active_connections > 0 {
+                        format!("{} (当前 {})", setting, task.active_connections)
+                    } else {
+                        setting
+                    }
+                }),
+            ]),
         ];
 
+        if let Some(note) = &task.note {
+            lines.push(Line::from(vec![
+                Span::styled("备注: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(note.clone()),
+            ]));
+        }
+
+        if let Some((start, end)) = task.byte_range {
+            lines.push(Line::from(vec![
+                Span::styled("范围: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(match end {
+                    Some(end) => format!("{}-{}", start, end),
+                    None => format!("{}-", start),
+                }),
+            ]));
+        }
+
+        if let Some(map) = app.chunk_maps.get(&task.id).filter(|m| !m.is_empty()) {
+            let finished = map.iter().filter(|&&f| f).count();
+            lines.push(Line::from(vec![
+                Span::styled("分块: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(format_chunk_map(map), Style::default().fg(Color::Green)),
+                Span::raw(format!(" {}/{}", finished, map.len())),
+            ]));
+        }
+
         if let Some(error) = &task.error {
             lines.push(Line::from(""));
             lines.push(Line::from(vec![Span::styled(
@@ -209,7 +282,7 @@ fn draw_task_details(f: &mut Frame, app: &App, area: Rect) {
             lines.push(Line::from(""));
             lines.push(Line::from(vec![
                 Span::styled("预计剩余: ", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(format!("{}s", eta)),
+                Span::raw(format_eta(eta)),
             ]));
         }
 
@@ -224,18 +297,19 @@ fn draw_task_details(f: &mut Frame, app: &App, area: Rect) {
 
         f.render_widget(details, chunks[0]);
 
-        // 进度条
-        let progress = if task.total_size > 0 {
-            (task.downloaded as f64 / task.total_size as f64 * 100.0) as u16
+        // 进度条（校验阶段单独显示）
+        let progress = task_progress(task);
+        let (title, color) = if task.status == TaskStatus::Verifying {
+            ("校验进度", Color::Magenta)
         } else {
-            0
+            ("进度", Color::Cyan)
         };
 
         let gauge = Gauge::default()
-            .block(Block::default().borders(Borders::ALL).title("进度"))
+            .block(Block::default().borders(Borders::ALL).title(title))
             .gauge_style(
                 Style::default()
-                    .fg(Color::Cyan)
+                    .fg(color)
                     .bg(Color::Black)
                     .add_modifier(Modifier::BOLD),
             )
@@ -256,6 +330,53 @@ fn draw_task_details(f: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+/// 分块完成情况，分块多时每一格代表几个相邻的分块，全部完成才显示为完成
+fn format_chunk_map(map: &[bool]) -> String {
+    const MAX_CELLS: usize = 40;
+    let per_cell = map.len().div_ceil(MAX_CELLS).max(1);
+    map.chunks(per_cell)
+        .map(|cell| {
+            if cell.iter().all(|&f| f) {
+                '■'
+            } else {
+                '□'
+            }
+        })
+        .collect()
+}
+
+/// 各优先级剩余大小的简短形式，例如 `H: 1.2 GB • N: 14 GB • L: 80 GB`
+///
+/// 只列出还有剩余的优先级，都没有剩余时返回 None。
+fn format_remaining_by_priority(stats: &QueueStats) -> Option<String> {
+    let parts: Vec<_> = [
+        (TaskPriority::High, "H"),
+        (TaskPriority::Normal, "N"),
+        (TaskPriority::Low, "L"),
+    ]
+    .into_iter()
+    .filter_map(|(priority, label)| {
+        let remaining = stats.remaining_bytes_by_priority.get(&priority)?;
+        (*remaining > 0).then(|| format!("{}: {}", label, format_size(*remaining)))
+    })
+    .collect();
+    (!parts.is_empty()).then(|| parts.join(" • "))
+}
+
+/// 任务进度百分比，校验阶段显示校验进度
+fn task_progress(task: &DownloadTask) -> u16 {
+    let done = if task.status == TaskStatus::Verifying {
+        task.verified
+    } else {
+        task.downloaded
+    };
+    if task.total_size > 0 {
+        (done as f64 / task.total_size as f64 * 100.0) as u16
+    } else {
+        0
+    }
+}
+
 fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
     let status_text = if app.input_mode == InputMode::AddUrl {
         format!("输入: {}", app.input_buffer)
@@ -273,7 +394,7 @@ fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
 fn draw_help(f: &mut Frame, app: &App, area: Rect) {
     let help_text = match app.input_mode {
         InputMode::Normal => {
-            "q:退出 | ↑↓/jk:导航 | Tab:切换面板 | a:添加 | p:暂停/恢复 | c:取消 | d:删除 | C:清空 | r:刷新"
+            "q:退出 | ↑↓/jk:导航 | Tab:切换面板 | a:添加 | p:暂停/恢复 | P:暂停/恢复队列 | b:加速 | c:取消 | d:删除 | C:清空 | r:刷新"
         }
         InputMode::AddUrl => "Enter:确认 | Esc:取消 | 格式: URL|输出路径|优先级(high/normal/low)",
     };
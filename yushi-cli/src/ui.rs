@@ -1,8 +1,19 @@
 use console::style;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
 use tokio::sync::RwLock;
-use yushi_core::utils::XByte;
+use yushi_core::{
+    ChecksumType, MirrorDemotion, PausedReason, PlanConflict, Provenance, RestartReason,
+    SchedulerState, TaskPlan, i18n,
+};
 
 pub struct ProgressManager {
     multi: MultiProgress,
@@ -53,6 +64,15 @@ impl ProgressManager {
         }
     }
 
+    pub async fn update_verification(&self, task_id: &str, verified: u64, total: u64) {
+        let bars = self.bars.read().await;
+        if let Some(pb) = bars.get(task_id) {
+            pb.set_length(total);
+            pb.set_position(verified);
+            pb.set_message(format!("🔍 {} 校验中", &task_id[..8]));
+        }
+    }
+
     pub async fn finish_task(&self, task_id: &str, success: bool) {
         let mut bars = self.bars.write().await;
         if let Some(pb) = bars.remove(task_id) {
@@ -80,12 +100,122 @@ pub fn parse_speed_limit(limit: &str) -> Option<u64> {
     num_str.parse::<u64>().ok().map(|n| n * unit)
 }
 
+/// 解析校验和参数：`--md5` 和 `--sha256` 优先，都没有时按 `--checksum` 的长度判断算法
+pub fn parse_checksum(
+    md5: Option<String>,
+    sha256: Option<String>,
+    checksum: Option<String>,
+) -> anyhow::Result<Option<ChecksumType>> {
+    Ok(match (md5, sha256, checksum) {
+        (Some(md5), _, _) => Some(ChecksumType::Md5(md5)),
+        (_, Some(sha256), _) => Some(ChecksumType::Sha256(sha256)),
+        (_, _, Some(hex)) => Some(ChecksumType::parse_auto(&hex)?),
+        _ => None,
+    })
+}
+
+/// 解析字节范围 (例如: 0-10485759, 1048576-)
+pub fn parse_byte_range(range: &str) -> Option<(u64, Option<u64>)> {
+    let (start, end) = range.trim().split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let end = match end.trim() {
+        "" => None,
+        end => Some(end.parse().ok()?),
+    };
+    Some((start, end))
+}
+
+/// 解析 `--resolve` 的值 (例如: example.com:443:10.0.0.5, example.com:443:[::1])
+pub fn parse_resolve(value: &str) -> Result<(String, SocketAddr), String> {
+    let invalid = || format!("无效的解析条目: {} (格式: host:port:addr)", value);
+    let (host, rest) = value.split_once(':').ok_or_else(invalid)?;
+    let (port, addr) = rest.split_once(':').ok_or_else(invalid)?;
+    let port: u16 = port.parse().map_err(|_| invalid())?;
+    let addr = addr.trim_start_matches('[').trim_end_matches(']');
+    let ip: IpAddr = addr.parse().map_err(|_| invalid())?;
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), SocketAddr::new(ip, port)))
+}
+
+pub fn format_byte_range((start, end): (u64, Option<u64>)) -> String {
+    match end {
+        Some(end) => format!("{}-{}", start, end),
+        None => format!("{}-", start),
+    }
+}
+
+/// 调度器状态的简短描述
+pub fn format_scheduler(state: &SchedulerState) -> String {
+    let Some(reason) = state.reason else {
+        return "运行中".to_string();
+    };
+    let reason = match reason {
+        PausedReason::ManualPause => "手动暂停",
+        PausedReason::Shutdown => "已关闭",
+        _ => "已暂停",
+    };
+    match state
+        .next_wakeup
+        .and_then(|t| t.duration_since(SystemTime::now()).ok())
+    {
+        Some(wait) => format!(
+            "{}，{} 后恢复",
+            reason,
+            humantime::format_duration(Duration::from_secs(wait.as_secs()))
+        ),
+        None => reason.to_string(),
+    }
+}
+
+/// 从头重新下载的原因
+pub fn format_restart_reason(reason: RestartReason) -> &'static str {
+    match reason {
+        RestartReason::RemoteChanged => "远程文件已变化，从头重新下载",
+        RestartReason::RangeIgnored => "服务器忽略了 Range 请求，改为单连接从头下载",
+        RestartReason::ResumeRejected => "服务器无法从中断的位置继续，从头重新下载",
+    }
+}
+
+/// 镜像不再使用的原因
+pub fn format_mirror_demotion(mirror: &str, reason: MirrorDemotion) -> String {
+    let reason = match reason {
+        MirrorDemotion::ProbeFailed => "探测失败".to_string(),
+        MirrorDemotion::SizeMismatch => "文件大小不一致".to_string(),
+        MirrorDemotion::RangeIgnored => "不支持 Range 请求".to_string(),
+        MirrorDemotion::Rejected { status } => format!("返回了 {}", status),
+        MirrorDemotion::TooManyFailures => "失败次数过多".to_string(),
+    };
+    format!("停用镜像 {}: {}", mirror, reason)
+}
+
 pub fn format_size(bytes: u64) -> String {
-    XByte::from_bytes(bytes).to_string()
+    i18n::format_size(bytes)
+}
+
+pub fn format_eta(secs: u64) -> String {
+    i18n::format_eta(secs)
+}
+
+/// 标准输出是否用于输出下载的数据（`download -o -`）
+static STDOUT_IS_DATA: AtomicBool = AtomicBool::new(false);
+
+/// 标准输出只留给下载的数据，之后的提示信息都写到标准错误
+pub fn reserve_stdout() {
+    STDOUT_IS_DATA.store(true, Ordering::Relaxed);
+}
+
+fn print_message(line: String) {
+    if STDOUT_IS_DATA.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
 }
 
 pub fn print_success(msg: &str) {
-    println!("{} {}", style("✓").green().bold(), msg);
+    print_message(format!("{} {}", style("✓").green().bold(), msg));
 }
 
 pub fn print_error(msg: &str) {
@@ -93,10 +223,83 @@ pub fn print_error(msg: &str) {
 }
 
 pub fn print_info(msg: &str) {
-    println!("{} {}", style("ℹ").blue().bold(), msg);
+    print_message(format!("{} {}", style("ℹ").blue().bold(), msg));
 }
 
-#[allow(dead_code)]
 pub fn print_warning(msg: &str) {
-    println!("{} {}", style("⚠").yellow().bold(), msg);
+    print_message(format!("{} {}", style("⚠").yellow().bold(), msg));
+}
+
+/// 打印下载计划（`--dry-run`）
+pub fn print_plan(plan: &TaskPlan, json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(plan)?);
+        return Ok(());
+    }
+
+    println!("\n{}", style("下载计划").bold().underlined());
+    println!();
+    println!("  URL: {}", plan.url);
+    println!("  保存到: {}", plan.dest.display());
+    match plan.remote.total_size {
+        Some(size) => println!("  大小: {}", format_size(size)),
+        None => println!("  大小: 未知"),
+    }
+    if plan.chunked {
+        println!(
+            "  下载方式: {} ({} 个分块, {} 个连接)",
+            style("分块下载").green(),
+            plan.chunks,
+            plan.connections
+        );
+    } else {
+        println!(
+            "  下载方式: {} (单连接, 不支持断点续传)",
+            style("流式下载").yellow()
+        );
+    }
+    println!();
+    match &plan.conflict {
+        Some(PlanConflict::FileExists) => print_warning("目标文件已存在"),
+        Some(PlanConflict::QueuedTask { task_id }) => {
+            print_warning(&format!("队列中的任务 {} 已使用该路径", task_id))
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// 打印任务的下载环境记录
+pub fn print_provenance(provenance: &Provenance) {
+    println!("  {}", style("下载环境").bold());
+    println!("    核心版本: {}", provenance.core_version);
+    println!(
+        "    记录时间: {}",
+        humantime::format_rfc3339_seconds(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(provenance.captured_at)
+        )
+    );
+    println!("    URL: {}", provenance.url);
+    if let Some(ua) = &provenance.user_agent {
+        println!("    User-Agent: {}", ua);
+    }
+    for (name, value) in &provenance.headers {
+        println!("    请求头: {}: {}", name, value);
+    }
+    println!("    代理: {}", if provenance.proxy { "是" } else { "否" });
+    println!(
+        "    连接数: {}  分块大小: {}",
+        provenance.connections,
+        format_size(provenance.chunk_size)
+    );
+    if let Some(limit) = provenance.speed_limit {
+        println!("    速度限制: {}/s", format_size(limit));
+    }
+    if let Some(range) = provenance.byte_range {
+        println!("    范围: {}", format_byte_range(range));
+    }
+    if provenance.skip_probe {
+        println!("    跳过探测: 是");
+    }
+    println!();
 }
@@ -0,0 +1,78 @@
+//! `yushi download -o -`：把下载的数据写到标准输出
+
+use sha2::{Digest, Sha256};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+    process::{Command, Output},
+    thread,
+};
+
+/// 只返回一个文件的 HTTP 服务器，不支持 Range，每个请求后关闭连接
+fn serve(body: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                line.clear();
+            }
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(head.as_bytes());
+            if request_line.starts_with("GET") {
+                let _ = stream.write_all(&body);
+            }
+        }
+    });
+    format!("http://{}/data.bin", addr)
+}
+
+fn temp_home() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("yushi-cli-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn download_to_stdout(url: &str, sha256: &str) -> Output {
+    let home = temp_home();
+    Command::new(env!("CARGO_BIN_EXE_yushi"))
+        .args(["download", url, "-o", "-", "--quiet", "--sha256", sha256])
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .current_dir(&home)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_download_to_stdout_pipes_into_hasher() {
+    let body: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    let digest = hex::encode(Sha256::digest(&body));
+    let url = serve(body.clone());
+
+    let output = download_to_stdout(&url, &digest);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(hex::encode(Sha256::digest(&output.stdout)), digest);
+
+    // 校验和不匹配时数据已经写出，但退出码不为 0
+    let output = download_to_stdout(&url, &"0".repeat(64));
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, body);
+}
@@ -0,0 +1,230 @@
+//! 审计日志
+//!
+//! 任务进入终态时，[`AuditLog`] 把 [`AuditRecord`] 交给后台写入任务，下载流程不等待文件写入。
+//! 所有记录由同一个写入任务按接收顺序追加，并发产生的记录在文件中不会交错。
+
+use crate::{Result, types::AuditRecord};
+use fs_err::tokio as fs;
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{mpsc, oneshot},
+};
+
+enum AuditMessage {
+    Record(Box<AuditRecord>),
+    /// 之前的记录全部写入后回复
+    Flush(oneshot::Sender<()>),
+}
+
+/// 审计日志的写入端
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    /// 写入任务在第一次使用时启动，创建下载器时不要求处于 Tokio 运行时中
+    tx: OnceLock<mpsc::UnboundedSender<AuditMessage>>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf, max_size: u64) -> Self {
+        Self {
+            path,
+            max_size,
+            tx: OnceLock::new(),
+        }
+    }
+
+    fn sender(&self) -> &mpsc::UnboundedSender<AuditMessage> {
+        self.tx.get_or_init(|| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            tokio::spawn(run_writer(self.path.clone(), self.max_size, rx));
+            tx
+        })
+    }
+
+    /// 追加一条记录（不等待写入完成）
+    pub fn record(&self, record: AuditRecord) {
+        let _ = self.sender().send(AuditMessage::Record(Box::new(record)));
+    }
+
+    /// 等待已提交的记录全部写入文件
+    pub async fn flush(&self) {
+        let Some(tx) = self.tx.get() else {
+            return;
+        };
+        let (done_tx, done_rx) = oneshot::channel();
+        if tx.send(AuditMessage::Flush(done_tx)).is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+}
+
+async fn run_writer(path: PathBuf, max_size: u64, mut rx: mpsc::UnboundedReceiver<AuditMessage>) {
+    while let Some(message) = rx.recv().await {
+        match message {
+            // 审计日志写入失败不影响下载本身
+            AuditMessage::Record(record) => {
+                let _ = append(&path, max_size, &record).await;
+            }
+            AuditMessage::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+async fn append(path: &Path, max_size: u64, record: &AuditRecord) -> Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+
+    let size = match fs::metadata(path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    if size > 0 && size + line.len() as u64 > max_size {
+        rotate(path).await?;
+    }
+
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent).await?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// 把当前文件重命名为第一个未被占用的 `<文件名>.N`，旧记录不会被覆盖
+async fn rotate(path: &Path) -> Result<()> {
+    let mut index = 1;
+    loop {
+        let rotated = rotated_path(path, index);
+        if !rotated.exists() {
+            fs::rename(path, &rotated).await?;
+            return Ok(());
+        }
+        index += 1;
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+/// 读取审计日志文件
+///
+/// 无法解析的行（例如写到一半的最后一行）会被跳过。
+pub async fn read_audit_log(path: impl AsRef<Path>) -> Result<Vec<AuditRecord>> {
+    let content = fs::read(path.as_ref()).await?;
+    Ok(content
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock_server::temp_dir, types::TaskStatus};
+    use std::sync::Arc;
+
+    fn record(task_id: &str) -> AuditRecord {
+        AuditRecord {
+            task_id: task_id.to_string(),
+            url: format!("http://example.com/{task_id}"),
+            dest: PathBuf::from(format!("/tmp/{task_id}")),
+            status: TaskStatus::Completed,
+            bytes: 1024,
+            checksum: None,
+            duration_ms: 10,
+            error: None,
+            finished_at: 0,
+            provenance: None,
+            skipped_unchanged: false,
+            average_speed: 0,
+            peak_speed: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_every_record() {
+        let dir = temp_dir();
+        let path = dir.join("audit.jsonl");
+        let line_len = serde_json::to_string(&record("task-00")).unwrap().len() as u64 + 1;
+        // 每个文件最多放 3 行
+        let log = AuditLog::new(path.clone(), line_len * 3);
+
+        for i in 0..10 {
+            log.record(record(&format!("task-{i:02}")));
+        }
+        log.flush().await;
+
+        let mut files = vec![path.clone()];
+        files.extend((1..=3).map(|i| rotated_path(&path, i)));
+        let mut ids = Vec::new();
+        for file in &files {
+            let records = read_audit_log(file).await.unwrap();
+            assert!(records.len() <= 3);
+            ids.extend(records.into_iter().map(|r| r.task_id));
+        }
+        assert!(!rotated_path(&path, 4).exists());
+        ids.sort();
+        let expected: Vec<_> = (0..10).map(|i| format!("task-{i:02}")).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_records_do_not_interleave() {
+        let dir = temp_dir();
+        let path = dir.join("logs").join("audit.jsonl");
+        let log = Arc::new(AuditLog::new(path.clone(), u64::MAX));
+
+        let mut handles = Vec::new();
+        for i in 0..16 {
+            let log = Arc::clone(&log);
+            handles.push(tokio::spawn(async move {
+                for j in 0..20 {
+                    let mut r = record(&format!("{i}-{j}"));
+                    r.error = Some("x".repeat(j * 50));
+                    log.record(r);
+                    tokio::task::yield_now().await;
+                }
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        log.flush().await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines.len(), 16 * 20);
+        for line in lines {
+            serde_json::from_str::<AuditRecord>(line).unwrap();
+        }
+        // 同一个生产者的记录保持提交顺序
+        let records = read_audit_log(&path).await.unwrap();
+        for i in 0..16 {
+            let order: Vec<_> = records
+                .iter()
+                .filter(|r| r.task_id.starts_with(&format!("{i}-")))
+                .map(|r| r.task_id.clone())
+                .collect();
+            let expected: Vec<_> = (0..20).map(|j| format!("{i}-{j}")).collect();
+            assert_eq!(order, expected);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! 任务列表变更跟踪
+//!
+//! 界面有大量任务时，每次重新获取整个列表代价很高。[`ChangeTracker`] 记住上次发出的
+//! 任务视图，只把变化的部分作为 [`TaskChanges`] 发出，并保留最近的若干次变更，
+//! 供错过事件的一方补齐。
+
+use crate::types::{Task, TaskChanges, TaskView};
+use std::collections::{HashMap, VecDeque};
+
+/// 保留的历史变更数，更早的变更只能通过重新获取快照恢复
+const HISTORY_LIMIT: usize = 128;
+
+/// 合并多次变更时单个任务的最终变化
+enum Change {
+    Added(TaskView),
+    Updated(TaskView),
+    Removed,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ChangeTracker {
+    generation: u64,
+    /// 上次发出时的任务视图
+    views: HashMap<String, TaskView>,
+    history: VecDeque<TaskChanges>,
+}
+
+impl ChangeTracker {
+    /// 与当前任务比较，有变化时进入下一个代次并返回变更
+    pub fn update<'a>(&mut self, tasks: impl IntoIterator<Item = &'a Task>) -> Option<TaskChanges> {
+        let current: HashMap<String, TaskView> = tasks
+            .into_iter()
+            .map(|task| (task.id.clone(), task.to_view()))
+            .collect();
+
+        let mut changes = TaskChanges::default();
+        for (id, view) in &current {
+            match self.views.get(id) {
+                None => changes.added.push(view.clone()),
+                Some(old) if old != view => changes.updated.push(view.clone()),
+                Some(_) => {}
+            }
+        }
+        changes.removed = self
+            .views
+            .keys()
+            .filter(|id| !current.contains_key(*id))
+            .cloned()
+            .collect();
+        self.views = current;
+        if changes.is_empty() {
+            return None;
+        }
+
+        sort_changes(&mut changes);
+        self.generation += 1;
+        changes.generation = self.generation;
+        self.history.push_back(changes.clone());
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        Some(changes)
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// 当前代次和对应的完整任务视图（按 ID 排序）
+    pub fn snapshot(&self) -> (u64, Vec<TaskView>) {
+        let mut views: Vec<_> = self.views.values().cloned().collect();
+        views.sort_by(|a, b| a.id.cmp(&b.id));
+        (self.generation, views)
+    }
+
+    /// 把 `generation` 之后的变更合并为一个
+    ///
+    /// 历史中已经没有这么早的变更，或者 `generation` 比当前代次还新时返回 None，
+    /// 调用方需要重新获取快照。
+    pub fn changes_since(&self, generation: u64) -> Option<TaskChanges> {
+        if generation > self.generation {
+            return None;
+        }
+        let skip = match self.history.front() {
+            Some(first) if first.generation > generation + 1 => return None,
+            Some(first) => (generation + 1 - first.generation) as usize,
+            None => 0,
+        };
+
+        let mut merged: HashMap<String, Change> = HashMap::new();
+        for changes in self.history.iter().skip(skip) {
+            for view in &changes.added {
+                let change = match merged.remove(&view.id) {
+                    // 移除后又出现的任务对调用方来说只是内容变了
+                    Some(Change::Removed) => Change::Updated(view.clone()),
+                    _ => Change::Added(view.clone()),
+                };
+                merged.insert(view.id.clone(), change);
+            }
+            for view in &changes.updated {
+                let change = match merged.remove(&view.id) {
+                    Some(Change::Added(_)) => Change::Added(view.clone()),
+                    _ => Change::Updated(view.clone()),
+                };
+                merged.insert(view.id.clone(), change);
+            }
+            for id in &changes.removed {
+                // 区间内新增又移除的任务调用方从未见过
+                if !matches!(merged.remove(id), Some(Change::Added(_))) {
+                    merged.insert(id.clone(), Change::Removed);
+                }
+            }
+        }
+
+        let mut result = TaskChanges {
+            generation: self.generation,
+            ..Default::default()
+        };
+        for (id, change) in merged {
+            match change {
+                Change::Added(view) => result.added.push(view),
+                Change::Updated(view) => result.updated.push(view),
+                Change::Removed => result.removed.push(id),
+            }
+        }
+        sort_changes(&mut result);
+        Some(result)
+    }
+}
+
+fn sort_changes(changes: &mut TaskChanges) {
+    changes.added.sort_by(|a, b| a.id.cmp(&b.id));
+    changes.updated.sort_by(|a, b| a.id.cmp(&b.id));
+    changes.removed.sort();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TaskStatus;
+
+    fn task(id: &str) -> Task {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "url": format!("http://example.com/{}", id),
+            "dest": format!("/tmp/{}", id),
+            "status": "Pending",
+            "total_size": 100,
+            "downloaded": 0,
+            "created_at": 0,
+            "error": null,
+        }))
+        .unwrap()
+    }
+
+    fn ids(views: &[TaskView]) -> Vec<&str> {
+        views.iter().map(|v| v.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_update_reports_only_differences() {
+        let mut tracker = ChangeTracker::default();
+        let mut a = task("a");
+        let b = task("b");
+
+        let changes = tracker.update([&a, &b]).unwrap();
+        assert_eq!(changes.generation, 1);
+        assert_eq!(ids(&changes.added), ["a", "b"]);
+
+        // 没有变化时不进入新代次
+        assert!(tracker.update([&a, &b]).is_none());
+
+        a.status = TaskStatus::Downloading;
+        a.downloaded = 50;
+        let changes = tracker.update([&a]).unwrap();
+        assert_eq!(changes.generation, 2);
+        assert!(changes.added.is_empty());
+        assert_eq!(ids(&changes.updated), ["a"]);
+        assert_eq!(changes.updated[0].progress, Some(50.0));
+        assert_eq!(changes.removed, ["b"]);
+
+        let (generation, views) = tracker.snapshot();
+        assert_eq!(generation, 2);
+        assert_eq!(ids(&views), ["a"]);
+    }
+
+    #[test]
+    fn test_changes_since_merges_history() {
+        let mut tracker = ChangeTracker::default();
+        let mut a = task("a");
+        let b = task("b");
+        tracker.update([&a]).unwrap();
+
+        // 2: 新增 b，3: 更新 a 并移除 b，4: 再次更新 a
+        tracker.update([&a, &b]).unwrap();
+        a.downloaded = 10;
+        tracker.update([&a]).unwrap();
+        a.downloaded = 20;
+        tracker.update([&a]).unwrap();
+
+        let merged = tracker.changes_since(1).unwrap();
+        assert_eq!(merged.generation, 4);
+        assert!(merged.added.is_empty());
+        assert!(merged.removed.is_empty());
+        assert_eq!(ids(&merged.updated), ["a"]);
+        assert_eq!(merged.updated[0].progress, Some(20.0));
+
+        let merged = tracker.changes_since(0).unwrap();
+        assert_eq!(ids(&merged.added), ["a"]);
+        assert!(merged.updated.is_empty());
+
+        let merged = tracker.changes_since(2).unwrap();
+        assert_eq!(merged.removed, ["b"]);
+        assert!(tracker.changes_since(4).unwrap().is_empty());
+        assert!(tracker.changes_since(5).is_none());
+
+        // 超出保留的历史后需要重新获取快照
+        for i in 0..HISTORY_LIMIT as u64 {
+            a.downloaded = 21 + i;
+            tracker.update([&a]).unwrap();
+        }
+        assert!(tracker.changes_since(1).is_none());
+        assert!(tracker.changes_since(4).is_some());
+    }
+}
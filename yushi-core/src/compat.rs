@@ -0,0 +1,304 @@
+//! 旧版 API 的兼容层
+//!
+//! 早期版本的队列叫 `DownloadQueue`，事件是扁平的 [`QueueEvent`] 和 [`ProgressEvent`]。
+//! 现在的下载器是 [`YuShi`]，事件按类别分为 [`DownloaderEvent`] 的各个变体，并包装在
+//! 带序号的 [`EventEnvelope`] 中。这里的类型都可以从新事件转换得到，旧事件中没有
+//! 对应变体的新事件放在 `Other` 中，不会丢失。
+//!
+//! 兼容层由默认开启的 `compat` 特性提供，所有类型都已弃用，新代码请直接使用新 API。
+//!
+//! ```no_run
+//! # #![allow(deprecated)]
+//! use std::path::PathBuf;
+//! use yushi_core::{DownloadQueue, QueueEvent};
+//!
+//! # async fn run() -> yushi_core::Result<()> {
+//! let (queue, mut event_rx) = DownloadQueue::new(4, 2, PathBuf::from("queue.json"));
+//! queue
+//!     .add_task(
+//!         "https://example.com/file.zip".to_string(),
+//!         PathBuf::from("file.zip"),
+//!     )
+//!     .await?;
+//!
+//! while let Some(event) = event_rx.recv().await {
+//!     match event {
+//!         QueueEvent::TaskProgress { task_id, downloaded, total, .. } => {
+//!             println!("{}: {}/{}", task_id, downloaded, total);
+//!         }
+//!         QueueEvent::TaskCompleted { .. } => break,
+//!         _ => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#![allow(deprecated)]
+
+use crate::{DownloaderEvent, EventEnvelope, TaskEvent, VerificationEvent, YuShi, types};
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+
+/// 旧版队列的构造入口，队列本身就是 [`YuShi`]
+#[deprecated(note = "使用 YuShi::new，事件接收器改为接收 EventEnvelope")]
+pub struct DownloadQueue;
+
+impl DownloadQueue {
+    /// 创建队列，参数同 [`YuShi::new`]，返回的接收器收到旧版的 [`QueueEvent`]
+    ///
+    /// 事件转换在后台任务中进行，需要在 Tokio 运行时中调用。
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        max_concurrent_downloads: usize,
+        max_concurrent_tasks: usize,
+        queue_state_path: PathBuf,
+    ) -> (YuShi, mpsc::Receiver<QueueEvent>) {
+        let (queue, event_rx) = YuShi::new(
+            max_concurrent_downloads,
+            max_concurrent_tasks,
+            queue_state_path,
+        );
+        (queue, convert_receiver(event_rx))
+    }
+}
+
+/// 旧版队列事件
+#[deprecated(note = "使用 DownloaderEvent")]
+#[derive(Debug, Clone)]
+pub enum QueueEvent {
+    TaskAdded {
+        task_id: String,
+    },
+    TaskStarted {
+        task_id: String,
+    },
+    TaskProgress {
+        task_id: String,
+        downloaded: u64,
+        total: u64,
+        speed: u64,
+        eta: Option<u64>,
+    },
+    TaskCompleted {
+        task_id: String,
+    },
+    TaskFailed {
+        task_id: String,
+        error: String,
+    },
+    TaskPaused {
+        task_id: String,
+    },
+    TaskResumed {
+        task_id: String,
+    },
+    TaskCancelled {
+        task_id: String,
+    },
+    VerifyStarted {
+        task_id: String,
+    },
+    VerifyCompleted {
+        task_id: String,
+        success: bool,
+    },
+    /// 旧版没有对应变体的事件
+    Other(DownloaderEvent),
+}
+
+impl From<DownloaderEvent> for QueueEvent {
+    fn from(event: DownloaderEvent) -> Self {
+        match event {
+            DownloaderEvent::Task(TaskEvent::Added { task_id }) => Self::TaskAdded { task_id },
+            DownloaderEvent::Task(TaskEvent::Started { task_id }) => Self::TaskStarted { task_id },
+            DownloaderEvent::Task(TaskEvent::Completed { task_id, .. }) => {
+                Self::TaskCompleted { task_id }
+            }
+            DownloaderEvent::Task(TaskEvent::Failed { task_id, error }) => {
+                Self::TaskFailed { task_id, error }
+            }
+            DownloaderEvent::Task(TaskEvent::Paused { task_id }) => Self::TaskPaused { task_id },
+            DownloaderEvent::Task(TaskEvent::Resumed { task_id }) => Self::TaskResumed { task_id },
+            DownloaderEvent::Task(TaskEvent::Cancelled { task_id }) => {
+                Self::TaskCancelled { task_id }
+            }
+            DownloaderEvent::Progress(types::ProgressEvent::Updated {
+                task_id,
+                downloaded,
+                total,
+                speed,
+                eta,
+                ..
+            }) => Self::TaskProgress {
+                task_id,
+                downloaded,
+                total,
+                speed,
+                eta,
+            },
+            DownloaderEvent::Verification(VerificationEvent::Started { task_id }) => {
+                Self::VerifyStarted { task_id }
+            }
+            DownloaderEvent::Verification(VerificationEvent::Completed {
+                task_id,
+                success,
+                ..
+            }) => Self::VerifyCompleted { task_id, success },
+            event => Self::Other(event),
+        }
+    }
+}
+
+impl From<EventEnvelope> for QueueEvent {
+    fn from(envelope: EventEnvelope) -> Self {
+        envelope.event.into()
+    }
+}
+
+/// 旧版单文件下载的进度事件
+#[deprecated(note = "使用 yushi_core::ProgressEvent")]
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    Initialized {
+        task_id: String,
+        total_size: Option<u64>,
+    },
+    /// 分块下载进度，`delta` 为本次新增的字节数
+    ChunkUpdated {
+        task_id: String,
+        chunk_index: usize,
+        delta: u64,
+    },
+    /// 流式下载进度，`downloaded` 为累计字节数
+    StreamUpdated {
+        task_id: String,
+        downloaded: u64,
+    },
+    Finished {
+        task_id: String,
+    },
+    Failed {
+        task_id: String,
+        error: String,
+    },
+    /// 旧版没有对应变体的事件
+    Other(types::ProgressEvent),
+}
+
+impl From<types::ProgressEvent> for ProgressEvent {
+    fn from(event: types::ProgressEvent) -> Self {
+        use types::ProgressEvent as New;
+        match event {
+            New::Initialized {
+                task_id,
+                total_size,
+                ..
+            } => Self::Initialized {
+                task_id,
+                total_size,
+            },
+            New::ChunkProgress {
+                task_id,
+                chunk_index,
+                delta,
+            } => Self::ChunkUpdated {
+                task_id,
+                chunk_index,
+                delta,
+            },
+            New::StreamProgress {
+                task_id,
+                downloaded,
+            } => Self::StreamUpdated {
+                task_id,
+                downloaded,
+            },
+            New::Finished { task_id } => Self::Finished { task_id },
+            New::Failed { task_id, error } => Self::Failed { task_id, error },
+            event => Self::Other(event),
+        }
+    }
+}
+
+/// 把新事件的接收器转换为旧版事件的接收器
+///
+/// 在后台任务中逐个转换，原接收器关闭后返回的接收器也随之关闭。需要在 Tokio 运行时中调用。
+///
+/// ```no_run
+/// # #![allow(deprecated)]
+/// use tokio::sync::mpsc;
+/// use yushi_core::{YuShi, compat};
+///
+/// # async fn run(downloader: YuShi) -> yushi_core::Result<()> {
+/// let (tx, rx) = mpsc::channel(64);
+/// let mut legacy_rx = compat::convert_receiver::<_, compat::ProgressEvent>(rx);
+/// tokio::spawn(async move {
+///     while let Some(event) = legacy_rx.recv().await {
+///         if let compat::ProgressEvent::ChunkUpdated { delta, .. } = event {
+///             println!("+{} bytes", delta);
+///         }
+///     }
+/// });
+/// downloader
+///     .download("https://example.com/file.zip", "file.zip", Some(tx))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn convert_receiver<T, U>(mut rx: mpsc::Receiver<T>) -> mpsc::Receiver<U>
+where
+    T: Send + 'static,
+    U: From<T> + Send + 'static,
+{
+    let (tx, converted) = mpsc::channel(rx.max_capacity().max(1));
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            if tx.send(event.into()).await.is_err() {
+                break;
+            }
+        }
+    });
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockFile, MockServer, temp_dir};
+
+    #[tokio::test]
+    async fn test_legacy_queue_receives_flat_events() {
+        let server = MockServer::start().await;
+        server.add("/file", MockFile::new(vec![7u8; 64 * 1024]));
+        let dir = temp_dir();
+
+        let (queue, mut event_rx) = DownloadQueue::new(2, 1, dir.join("queue.json"));
+        let task_id = queue
+            .add_task(server.url("/file"), dir.join("file.bin"))
+            .await
+            .unwrap();
+
+        let mut seen = Vec::new();
+        while let Some(event) = event_rx.recv().await {
+            match event {
+                QueueEvent::TaskAdded { .. } => seen.push("added"),
+                QueueEvent::TaskStarted { .. } => seen.push("started"),
+                QueueEvent::TaskProgress {
+                    task_id: id, total, ..
+                } => {
+                    assert_eq!(id, task_id);
+                    assert_eq!(total, 64 * 1024);
+                }
+                QueueEvent::TaskCompleted { task_id: id } => {
+                    assert_eq!(id, task_id);
+                    seen.push("completed");
+                    break;
+                }
+                QueueEvent::TaskFailed { error, .. } => panic!("{}", error),
+                _ => {}
+            }
+        }
+        assert_eq!(seen, ["added", "started", "completed"]);
+    }
+}
@@ -0,0 +1,89 @@
+//! Cookie 支持（[`Config::cookies`](crate::types::Config::cookies)）
+//!
+//! 启用后客户端的所有请求（探测、分块和流式下载）共用一个 Cookie 存储：保存响应设置的
+//! Cookie，并在之后发往匹配主机的请求中发送。存储可以预先从浏览器导出的 Netscape 格式
+//! cookies.txt 加载。
+
+use crate::types::CookieSource;
+use reqwest::{Url, cookie::Jar};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// 按配置创建 Cookie 存储
+///
+/// cookies.txt 无法读取时返回空的存储，格式不正确的行和已经过期的 Cookie 会被忽略。
+pub(crate) fn cookie_jar(source: &CookieSource) -> Jar {
+    let jar = Jar::default();
+    if let CookieSource::File(path) = source {
+        load_cookie_file(&jar, path);
+    }
+    jar
+}
+
+fn load_cookie_file(jar: &Jar, path: &Path) {
+    let Ok(content) = fs_err::read_to_string(path) else {
+        return;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    for line in content.lines() {
+        if let Some((cookie, url)) = parse_netscape_line(line, now) {
+            jar.add_cookie_str(&cookie, &url);
+        }
+    }
+}
+
+/// 把 cookies.txt 的一行转换为 `Set-Cookie` 的值和设置它的地址
+///
+/// 每行用制表符分隔七列：域名、是否包括子域名、路径、是否只用于 HTTPS、过期时间
+/// （Unix 时间戳，0 表示会话 Cookie）、名称和值。`#HttpOnly_` 开头的域名表示
+/// HttpOnly Cookie，其他 `#` 开头的行是注释。
+fn parse_netscape_line(line: &str, now: u64) -> Option<(String, Url)> {
+    let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+        Some(rest) => (rest, true),
+        None if line.starts_with('#') => return None,
+        None => (line, false),
+    };
+    let fields: Vec<&str> = line.split('\t').collect();
+    let [
+        domain,
+        include_subdomains,
+        path,
+        secure,
+        expires,
+        name,
+        value,
+    ] = fields[..]
+    else {
+        return None;
+    };
+    let host = domain.trim_start_matches('.');
+    if host.is_empty() || name.is_empty() {
+        return None;
+    }
+    let secure = secure.eq_ignore_ascii_case("TRUE");
+    let expires: u64 = expires.parse().ok()?;
+
+    let mut cookie = format!("{}={}; Path={}", name, value, path);
+    if include_subdomains.eq_ignore_ascii_case("TRUE") {
+        cookie.push_str(&format!("; Domain={}", host));
+    }
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    if http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    if expires != 0 {
+        let max_age = expires.checked_sub(now).filter(|age| *age > 0)?;
+        cookie.push_str(&format!("; Max-Age={}", max_age));
+    }
+
+    let scheme = if secure { "https" } else { "http" };
+    let url = Url::parse(&format!("{}://{}{}", scheme, host, path)).ok()?;
+    Some((cookie, url))
+}
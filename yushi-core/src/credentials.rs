@@ -0,0 +1,277 @@
+//! 按主机保存的认证信息
+//!
+//! 认证信息只保存在内存中，也可以从只有所有者可读写的单独文件加载。请求的主机与
+//! 某个主机模式匹配且没有显式设置 `Authorization` 头时，自动附加对应的认证信息。
+//! 认证信息不会写入队列状态、事件或审计日志。
+
+use crate::{Error, Result};
+use fs_err::tokio as fs;
+use reqwest::{RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path, sync::RwLock};
+
+/// 认证信息
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Credential {
+    /// HTTP Basic 认证
+    Basic { username: String, password: String },
+    /// `Authorization: Bearer <token>`
+    Bearer { token: String },
+}
+
+impl std::fmt::Debug for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"***")
+                .finish(),
+            Self::Bearer { .. } => f.debug_struct("Bearer").field("token", &"***").finish(),
+        }
+    }
+}
+
+impl Credential {
+    /// 给请求附加认证头
+    pub(crate) fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Self::Basic { username, password } => request.basic_auth(username, Some(password)),
+            Self::Bearer { token } => request.bearer_auth(token),
+        }
+    }
+}
+
+/// 主机模式到认证信息的映射
+///
+/// 主机模式可以是：
+/// * `example.com`：只匹配该主机，不限端口
+/// * `example.com:8443`：只匹配该主机的指定端口
+/// * `*.example.com`：匹配所有子域名，不包括 `example.com` 本身
+///
+/// 多个模式匹配时，指定端口的优先于不指定的，精确主机优先于通配符，
+/// 通配符之间后缀更长的优先。
+#[derive(Debug, Default)]
+pub struct CredentialStore {
+    entries: RwLock<BTreeMap<String, Credential>>,
+}
+
+impl CredentialStore {
+    /// 设置主机模式的认证信息，替换已有的设置
+    pub fn set(&self, host_pattern: &str, credential: Credential) {
+        self.entries
+            .write()
+            .unwrap()
+            .insert(normalize_pattern(host_pattern), credential);
+    }
+
+    /// 删除主机模式的认证信息，不存在时返回 false
+    pub fn remove(&self, host_pattern: &str) -> bool {
+        self.entries
+            .write()
+            .unwrap()
+            .remove(&normalize_pattern(host_pattern))
+            .is_some()
+    }
+
+    /// 已设置认证信息的主机模式
+    pub fn hosts(&self) -> Vec<String> {
+        self.entries.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.read().unwrap().is_empty()
+    }
+
+    /// 查找 URL 对应的认证信息
+    pub fn lookup(&self, url: &str) -> Option<Credential> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+        let port = url.port_or_known_default();
+        let entries = self.entries.read().unwrap();
+        entries
+            .iter()
+            .filter_map(|(pattern, credential)| {
+                pattern_rank(pattern, &host, port).map(|rank| (rank, credential))
+            })
+            .max_by_key(|(rank, _)| *rank)
+            .map(|(_, credential)| credential.clone())
+    }
+
+    /// 从文件加载
+    ///
+    /// 文件内容为主机模式到认证信息的 JSON 对象。在 Unix 上文件不能被所有者以外的
+    /// 用户访问，否则返回 [`Error::InsecureCredentialFile`]。
+    pub async fn load(path: &Path) -> Result<Self> {
+        check_permissions(path).await?;
+        let content = fs::read_to_string(path).await?;
+        let entries: BTreeMap<String, Credential> = serde_json::from_str(&content)?;
+        let store = Self::default();
+        for (pattern, credential) in entries {
+            store.set(&pattern, credential);
+        }
+        Ok(store)
+    }
+
+    /// 保存到文件，在 Unix 上文件权限设置为只有所有者可读写
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(&*self.entries.read().unwrap())?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // 先创建空文件并限制权限，再写入内容
+        fs::write(path, "").await?;
+        restrict_permissions(path).await?;
+        fs::write(path, content).await?;
+        Ok(())
+    }
+
+    /// 复制所有认证信息
+    pub(crate) fn entries(&self) -> Vec<(String, Credential)> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(pattern, credential)| (pattern.clone(), credential.clone()))
+            .collect()
+    }
+}
+
+fn normalize_pattern(pattern: &str) -> String {
+    pattern.trim().to_ascii_lowercase()
+}
+
+/// 主机模式与主机的匹配程度，不匹配时返回 None，值越大越具体
+fn pattern_rank(pattern: &str, host: &str, port: Option<u16>) -> Option<(bool, bool, usize)> {
+    let (host_pattern, port_pattern) = match pattern.rsplit_once(':') {
+        Some((h, p)) => (h, Some(p.parse::<u16>().ok()?)),
+        None => (pattern, None),
+    };
+    if port_pattern.is_some() && port_pattern != port {
+        return None;
+    }
+    let exact = match host_pattern.strip_prefix("*.") {
+        Some(suffix) => {
+            let subdomain = host
+                .strip_suffix(suffix)
+                .is_some_and(|rest| rest.len() > 1 && rest.ends_with('.'));
+            if !subdomain {
+                return None;
+            }
+            false
+        }
+        None if host_pattern == host => true,
+        None => return None,
+    };
+    Some((port_pattern.is_some(), exact, host_pattern.len()))
+}
+
+#[cfg(unix)]
+async fn check_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path).await?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(Error::InsecureCredentialFile(path.display().to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn check_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn restrict_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).await?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn restrict_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bearer(token: &str) -> Credential {
+        Credential::Bearer {
+            token: token.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_lookup_prefers_most_specific_pattern() {
+        let store = CredentialStore::default();
+        store.set("*.example.com", bearer("wildcard"));
+        store.set("*.cdn.example.com", bearer("cdn"));
+        store.set("Files.Example.com", bearer("host"));
+        store.set("files.example.com:8443", bearer("port"));
+
+        let token = |url: &str| match store.lookup(url) {
+            Some(Credential::Bearer { token }) => Some(token),
+            _ => None,
+        };
+        assert_eq!(
+            token("https://files.example.com/a").as_deref(),
+            Some("host")
+        );
+        assert_eq!(
+            token("https://files.example.com:8443/a").as_deref(),
+            Some("port")
+        );
+        assert_eq!(token("https://a.cdn.example.com/").as_deref(), Some("cdn"));
+        assert_eq!(
+            token("https://www.example.com/").as_deref(),
+            Some("wildcard")
+        );
+        // 通配符不匹配根域名和只是后缀相同的主机
+        assert_eq!(token("https://example.com/"), None);
+        assert_eq!(token("https://badexample.com/"), None);
+        assert_eq!(token("not a url"), None);
+
+        assert!(store.remove("files.example.com"));
+        assert!(!store.remove("files.example.com"));
+        assert_eq!(
+            token("https://files.example.com/a").as_deref(),
+            Some("wildcard")
+        );
+
+        // 调试输出中不包含密钥
+        let debug = format!("{:?}", store);
+        assert!(!debug.contains("\"wildcard\""));
+        assert!(debug.contains("***"));
+    }
+
+    #[tokio::test]
+    async fn test_credential_file_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("yushi-credentials-{}.json", std::process::id()));
+        let store = CredentialStore::default();
+        store.set(
+            "example.com",
+            Credential::Basic {
+                username: "alice".to_string(),
+                password: "s3cret".to_string(),
+            },
+        );
+        store.save(&path).await.unwrap();
+
+        let loaded = CredentialStore::load(&path).await.unwrap();
+        assert_eq!(loaded.entries(), store.entries());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(matches!(
+                CredentialStore::load(&path).await,
+                Err(Error::InsecureCredentialFile(_))
+            ));
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+}
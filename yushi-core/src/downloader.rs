@@ -1,30 +1,73 @@
+#[cfg(feature = "checksums")]
+use crate::utils::{hash_file_range, verify_file_with};
 use crate::{
     Error, Result,
-    state::{ChunkState, DownloadState, QueueState, current_timestamp},
+    audit::AuditLog,
+    changes::ChangeTracker,
+    cookies::cookie_jar,
+    credentials::{Credential, CredentialStore},
+    error::RedirectError,
+    event::{EventSender, current_timestamp_ms},
+    hosts::{HostLimits, HostPermit},
+    i18n::{self, message},
+    index, orphan,
+    pressure::DiskPressure,
+    progress::ProgressRegistry,
+    resolver::PolicyResolver,
+    schedule,
+    sink::{FileSink, NullSink, SharedSink, StorageSink, WriterSink},
+    state::{ChunkState, DownloadState, DownloaderState, FlushSlot, QueueStore, current_timestamp},
     types::{
-        ChecksumType, CompletionCallback, Config, DownloaderEvent, ProgressEvent, Task, TaskEvent,
-        TaskPriority, TaskStatus, VerificationEvent,
+        AuditRecord, CaseInsensitiveFs, ChecksumType, ChunkDiagnostics, ChunkFailurePolicy,
+        ChunkOrder, CompactEvent, CompactEventKind, CompletionCallback, Config, ConflictPolicy,
+        ConnectionSample, DownloadSummary, DownloaderEvent, EffectiveConfig, EventEnvelope,
+        Forecast, GlobalEvent, GroupProgress, HostStats, IndexOptions, MeasureReport,
+        MirrorDemotion, NewTask, OrphanReport, PausedReason, PlanConflict, ProgressEvent,
+        ProgressSnapshot, Provenance, QueueStats, RemoteInfo, RemoveOptions, RestartReason,
+        RetryReason, ScheduledSlot, SchedulerState, StateDiagnostics, Task, TaskChanges,
+        TaskDiagnostics, TaskEvent, TaskGroup, TaskKind, TaskOptions, TaskPlan, TaskPriority,
+        TaskQuery, TaskStatus, TaskView, UrlRefresher, VerificationEvent, VolumeForecast,
+    },
+    utils::{
+        BufferBudget, ChunkDigest, ConnectionLimiter, READ_RESERVE, SharedSpeedLimiter,
+        SpeedCalculator, SpeedLimiter, auto_rename_avoiding, filename_from_content_disposition,
+        filename_from_url, new_id, parse_retry_after, path_taken, probe_case_insensitive,
+        redact_header, redact_url, resolve_destination, volume_of, with_affixes,
     },
-    utils::{SpeedCalculator, SpeedLimiter, auto_rename, verify_file},
 };
+use bytes::Bytes;
 use fs_err::tokio as fs;
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
 use reqwest::{
-    Client, Proxy,
-    header::{CONTENT_LENGTH, RANGE, USER_AGENT},
+    Certificate, Client, Proxy, RequestBuilder, Response, StatusCode, Url,
+    header::{
+        ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+        HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE, RETRY_AFTER,
+        USER_AGENT,
+    },
 };
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    any::Any,
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    net::SocketAddr,
+    ops::Range,
+    panic::AssertUnwindSafe,
     path::{Path, PathBuf},
-    sync::Arc,
-    time::Duration,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 use tokio::{
-    io::{AsyncSeekExt, AsyncWriteExt, SeekFrom},
-    sync::{RwLock, Semaphore, mpsc},
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{RwLock, Semaphore, SemaphorePermit, mpsc},
     task::JoinHandle,
 };
-use uuid::Uuid;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Clone)]
 pub struct YuShi {
@@ -32,9 +75,55 @@ pub struct YuShi {
     config: Config,
     tasks: Arc<RwLock<HashMap<String, Task>>>,
     active_downloads: Arc<RwLock<HashMap<String, JoinHandle<()>>>>,
-    max_concurrent_tasks: usize,
-    queue_state_path: PathBuf,
-    queue_event_tx: mpsc::Sender<DownloaderEvent>,
+    /// 运行中任务的取消令牌
+    cancel_tokens: Arc<RwLock<HashMap<String, CancellationToken>>>,
+    /// 额外接收任务原始进度事件的发送器（供 [`YuShi::download`] 使用）
+    progress_taps: Arc<RwLock<HashMap<String, mpsc::Sender<ProgressEvent>>>>,
+    /// 运行中任务的连接数限制
+    connection_limits: Arc<RwLock<HashMap<String, Arc<ConnectionLimiter>>>>,
+    /// 任务自定义的存储后端（仅保存在内存中）
+    sinks: Arc<RwLock<HashMap<String, SharedSink>>>,
+    /// 任务的下载地址刷新回调（仅保存在内存中）
+    url_refreshers: Arc<RwLock<HashMap<String, UrlRefresher>>>,
+    groups: Arc<RwLock<HashMap<String, TaskGroup>>>,
+    /// 供轮询使用的进度计数器
+    progress: Arc<ProgressRegistry>,
+    /// 队列中同时运行的最大任务数（可在运行中修改）
+    max_concurrent_tasks: Arc<AtomicUsize>,
+    /// 未单独设置连接数的任务使用的连接数（可在运行中修改）
+    default_connections: Arc<AtomicUsize>,
+    /// 所有任务共用的总速度限制
+    global_limiter: Arc<SharedSpeedLimiter>,
+    /// 按主机共用的连接数和速度限制
+    host_limits: Arc<HostLimits>,
+    /// 任务临时加速的截止时间（Unix 毫秒），0 表示没有加速
+    boosts: Arc<Mutex<HashMap<String, Arc<AtomicU64>>>>,
+    /// 磁盘写入压力检测，未配置时为 None
+    disk_pressure: Option<Arc<DiskPressure>>,
+    /// 审计日志，未配置时为 None
+    audit: Option<Arc<AuditLog>>,
+    /// 运行中任务本次开始运行的时间（用于审计日志）
+    run_started: Arc<Mutex<HashMap<String, Instant>>>,
+    /// 限制同时进行的文件校验数
+    verify_permits: Arc<Semaphore>,
+    /// 所有连接共用的内存缓冲预算
+    buffer_budget: Arc<BufferBudget>,
+    /// 调度器状态
+    scheduler: Arc<Mutex<SchedulerState>>,
+    /// 串行执行 `process_queue`，检查并发名额和启动任务之间不会穿插其他调用
+    queue_lock: Arc<tokio::sync::Mutex<()>>,
+    /// 各目录是否不区分文件名大小写的探测结果
+    case_probes: Arc<Mutex<HashMap<PathBuf, bool>>>,
+    /// 任务列表变更跟踪
+    changes: Arc<Mutex<ChangeTracker>>,
+    /// 是否已启动定期发送变更的后台任务
+    change_feed_started: Arc<AtomicBool>,
+    /// 按主机设置的认证信息（仅保存在内存中）
+    credentials: Arc<CredentialStore>,
+    /// 队列状态文件，None 表示队列只保存在内存中
+    queue_state_path: Option<PathBuf>,
+    store: Arc<QueueStore>,
+    queue_event_tx: EventSender,
     on_complete: Option<CompletionCallback>,
 }
 
@@ -42,7 +131,9 @@ impl std::fmt::Debug for YuShi {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("YuShi")
             .field("config", &self.config)
-            .field("max_concurrent_tasks", &self.max_concurrent_tasks)
+            .field("max_concurrent_tasks", &self.max_concurrent_tasks())
+            .field("default_connections", &self.default_connections())
+            .field("global_speed_limit", &self.global_speed_limit())
             .field("queue_state_path", &self.queue_state_path)
             .field("has_on_complete", &self.on_complete.is_some())
             .finish()
@@ -59,16 +150,18 @@ impl YuShi {
     ///
     /// # 返回
     /// 返回下载器实例和队列事件接收器
+    #[cfg(feature = "queue-persistence")]
     pub fn new(
         max_concurrent_downloads: usize,
         max_concurrent_tasks: usize,
         queue_state_path: PathBuf,
-    ) -> (Self, mpsc::Receiver<DownloaderEvent>) {
+    ) -> (Self, mpsc::Receiver<EventEnvelope>) {
         let config = Config {
             max_concurrent: max_concurrent_downloads,
             ..Default::default()
         };
         Self::with_config(config, max_concurrent_tasks, queue_state_path)
+            .expect("默认配置不使用代理")
     }
 
     /// 使用自定义配置创建下载器
@@ -79,38 +172,119 @@ impl YuShi {
     /// * `queue_state_path` - 队列状态持久化文件路径
     ///
     /// # 返回
-    /// 返回下载器实例和队列事件接收器
+    /// 返回下载器实例和队列事件接收器；代理地址无效时返回 [`Error::InvalidProxy`]
+    #[cfg(feature = "queue-persistence")]
     pub fn with_config(
         config: Config,
         max_concurrent_tasks: usize,
         queue_state_path: PathBuf,
-    ) -> (Self, mpsc::Receiver<DownloaderEvent>) {
+    ) -> Result<(Self, mpsc::Receiver<EventEnvelope>)> {
+        Self::build(config, max_concurrent_tasks, Some(queue_state_path))
+    }
+
+    /// 创建只在内存中保存队列的下载器
+    ///
+    /// 队列状态不写入文件，[`YuShi::load_queue_from_state`] 和 [`YuShi::compact_queue_state`]
+    /// 不读写文件。断点续传的状态文件仍然保存在下载文件旁边。代理地址无效时返回
+    /// [`Error::InvalidProxy`]。
+    pub fn in_memory(
+        config: Config,
+        max_concurrent_tasks: usize,
+    ) -> Result<(Self, mpsc::Receiver<EventEnvelope>)> {
+        Self::build(config, max_concurrent_tasks, None)
+    }
+
+    fn build(
+        config: Config,
+        max_concurrent_tasks: usize,
+        queue_state_path: Option<PathBuf>,
+    ) -> Result<(Self, mpsc::Receiver<EventEnvelope>)> {
+        let proxy = build_proxy(&config)?;
+        let root_certs = load_root_certs(&config.extra_root_certs)?;
         let (event_tx, event_rx) = mpsc::channel(1024);
+        let replay_capacity = config.event_replay_capacity;
+        if let Some(locale) = &config.locale {
+            i18n::set_locale(locale);
+        }
 
         let mut builder = Client::builder()
+            .redirect(redirect_policy(config.max_redirects))
             .tcp_keepalive(Duration::from_secs(60))
-            .timeout(Duration::from_secs(config.timeout));
+            .timeout(Duration::from_secs(config.timeout))
+            .pool_max_idle_per_host(config.pool.max_idle_per_host)
+            .pool_idle_timeout(config.pool.idle_timeout)
+            .tls_certs_merge(root_certs)
+            .tls_danger_accept_invalid_certs(config.danger_accept_invalid_certs)
+            .local_address(config.local_address)
+            .dns_resolver(Arc::new(PolicyResolver::new(
+                config.ip_policy,
+                resolve_overrides(&config.resolve),
+            )));
 
-        if let Some(proxy_url) = &config.proxy
-            && let Ok(proxy) = Proxy::all(proxy_url)
-        {
+        if let Some(source) = &config.cookies {
+            builder = builder.cookie_provider(Arc::new(cookie_jar(source)));
+        }
+
+        if let Some(proxy) = proxy {
             builder = builder.proxy(proxy);
         }
 
         let client = builder.build().unwrap();
+        let audit = config
+            .audit_log
+            .clone()
+            .map(|path| Arc::new(AuditLog::new(path, config.audit_log_max_size)));
+        let verify_permits = Arc::new(Semaphore::new(config.verify_concurrency.max(1)));
+        let buffer_budget = Arc::new(BufferBudget::new(config.max_buffered_bytes));
+        let global_limiter = Arc::new(SharedSpeedLimiter::new(config.global_speed_limit));
+        let host_limits = Arc::new(HostLimits::new(
+            config.per_host_max_connections,
+            config.per_host_speed_limit,
+        ));
+        let queue_event_tx = EventSender::new(event_tx, replay_capacity);
+        let disk_pressure = config.disk_pressure.map(|pressure| {
+            Arc::new(DiskPressure::new(
+                pressure,
+                Arc::clone(&global_limiter),
+                queue_event_tx.clone(),
+            ))
+        });
 
         let downloader = Self {
             client,
+            default_connections: Arc::new(AtomicUsize::new(config.max_concurrent)),
             config,
             tasks: Arc::new(RwLock::new(HashMap::new())),
             active_downloads: Arc::new(RwLock::new(HashMap::new())),
-            max_concurrent_tasks,
+            cancel_tokens: Arc::new(RwLock::new(HashMap::new())),
+            connection_limits: Arc::new(RwLock::new(HashMap::new())),
+            progress_taps: Arc::new(RwLock::new(HashMap::new())),
+            sinks: Arc::new(RwLock::new(HashMap::new())),
+            url_refreshers: Arc::new(RwLock::new(HashMap::new())),
+            groups: Arc::new(RwLock::new(HashMap::new())),
+            progress: Arc::new(ProgressRegistry::default()),
+            max_concurrent_tasks: Arc::new(AtomicUsize::new(max_concurrent_tasks)),
+            global_limiter,
+            host_limits,
+            boosts: Arc::new(Mutex::new(HashMap::new())),
+            disk_pressure,
+            audit,
+            run_started: Arc::new(Mutex::new(HashMap::new())),
+            verify_permits,
+            buffer_budget,
+            scheduler: Arc::new(Mutex::new(SchedulerState::default())),
+            queue_lock: Arc::new(tokio::sync::Mutex::new(())),
+            case_probes: Arc::new(Mutex::new(HashMap::new())),
+            changes: Arc::new(Mutex::new(ChangeTracker::default())),
+            change_feed_started: Arc::new(AtomicBool::new(false)),
+            credentials: Arc::new(CredentialStore::default()),
+            store: Arc::new(QueueStore::new(queue_state_path.clone())),
             queue_state_path,
-            queue_event_tx: event_tx,
+            queue_event_tx,
             on_complete: None,
         };
 
-        (downloader, event_rx)
+        Ok((downloader, event_rx))
     }
 
     /// 设置下载完成回调
@@ -124,56 +298,257 @@ impl YuShi {
         }));
     }
 
+    /// 设置队列状态写入回调
+    ///
+    /// 每次成功写入队列状态文件（追加增量日志或合并到主文件）后，回调收到此时磁盘上的
+    /// 完整状态，即此时重新加载会得到的状态。回调在后台任务中按写入顺序依次调用，
+    /// 不会推迟下一次写入。再次设置会替换之前的回调，之后的第一次写入总是合并到主文件。
+    pub fn on_persist(&self, callback: impl Fn(&DownloaderState) + Send + Sync + 'static) {
+        self.store.set_persist_hook(Arc::new(callback));
+    }
+
+    /// 订阅事件，先收到最近的事件（`replayed` 为 true），之后是新的事件
+    ///
+    /// 用于错过了一段事件的接收端（例如重新加载的界面）补齐状态：补发最近
+    /// [`Config::event_replay_capacity`] 个任务、校验和调度器状态事件，以及每个未结束任务
+    /// 最新的进度。补发的事件和之后的事件序号严格递增，没有重复。
+    ///
+    /// 订阅者收到的事件与 [`YuShi::with_config`] 返回的接收端相同；处理不过来时订阅被断开，
+    /// 接收端收到 None 后可以重新订阅。
+    pub fn subscribe_with_replay(&self) -> mpsc::Receiver<EventEnvelope> {
+        self.queue_event_tx.subscribe_with_replay()
+    }
+
+    /// 最近一次成功写入队列状态文件的时间，还没有写入过时返回 None
+    ///
+    /// 可用于健康检查：任务有变化但这个时间长期不更新，说明状态没有保存下来。
+    pub fn last_persisted_at(&self) -> Option<std::time::SystemTime> {
+        self.store.last_persisted_at()
+    }
+
     /// 简单下载文件（单文件下载的便捷方法）
     ///
     /// # 参数
     /// * `url` - 下载 URL
-    /// * `dest` - 目标文件路径
-    /// * `event_tx` - 进度事件发送器（可选）
+    /// * `dest` - 目标文件路径，也可以是已存在的目录
+    /// * `event_tx` - 进度事件发送器（可选），收到该任务的原始进度事件
+    ///
+    /// # 返回
+    /// 返回下载结果，其中的任务 ID 与进度事件中的 `task_id` 相同
     pub async fn download(
         &self,
         url: &str,
-        dest: &str,
+        dest: impl AsRef<Path>,
         event_tx: Option<mpsc::Sender<ProgressEvent>>,
-    ) -> Result<()> {
-        // 添加任务到队列
-        let task_id = self.add_task(url.to_string(), PathBuf::from(dest)).await?;
+    ) -> Result<DownloadSummary> {
+        self.download_with(url, dest, TaskOptions::default(), event_tx)
+            .await
+    }
+
+    /// 使用任务选项下载文件，参数同 [`YuShi::download`]
+    pub async fn download_with(
+        &self,
+        url: &str,
+        dest: impl AsRef<Path>,
+        options: TaskOptions,
+        event_tx: Option<mpsc::Sender<ProgressEvent>>,
+    ) -> Result<DownloadSummary> {
+        let task_id = self
+            .insert_task(url.to_string(), dest.as_ref().to_path_buf(), options, None)
+            .await;
+        self.download_inserted(task_id, event_tx).await
+    }
+
+    /// 只下载远程文件中 `range` 这一段（不包括 `range.end`），其他参数同 [`YuShi::download`]
+    ///
+    /// 等同于设置了 [`TaskOptions::byte_range`] 的 [`YuShi::download_with`]：目标文件从头
+    /// 写入这一段，进度、断点续传和校验都针对这一段，比一个分块小时只用一个连接。
+    /// `range.end` 超出文件末尾时截断；起点超出文件末尾时返回错误，服务器以 416 拒绝时
+    /// 返回 [`Error::RangeNotSatisfiable`]。
+    pub async fn download_range(
+        &self,
+        url: &str,
+        dest: impl AsRef<Path>,
+        range: Range<u64>,
+        event_tx: Option<mpsc::Sender<ProgressEvent>>,
+    ) -> Result<DownloadSummary> {
+        if range.is_empty() {
+            return Err(Error::TaskFailed(message(
+                "download.range_invalid",
+                &[&range.start, &range.end],
+            )));
+        }
+        let options = TaskOptions {
+            byte_range: Some((range.start, Some(range.end - 1))),
+            ..Default::default()
+        };
+        self.download_with(url, dest, options, event_tx).await
+    }
+
+    /// 启动刚添加的任务并等待它结束
+    async fn download_inserted(
+        &self,
+        task_id: String,
+        event_tx: Option<mpsc::Sender<ProgressEvent>>,
+    ) -> Result<DownloadSummary> {
+        // 在启动之前注册进度转发，避免漏掉 Initialized
+        if let Some(tx) = event_tx {
+            self.progress_taps.write().await.insert(task_id.clone(), tx);
+        }
+        self.save_queue_state().await?;
+        self.process_queue().await?;
+
+        let result = self.wait_task(&task_id).await;
+        self.progress_taps.write().await.remove(&task_id);
+        result?;
+
+        let task = self.get_task(&task_id).await.ok_or(Error::TaskNotFound)?;
+        Ok(DownloadSummary {
+            total_size: task.total_size.max(task.downloaded),
+            dest: task.dest,
+            skipped_unchanged: task.skipped_unchanged,
+            task_id,
+        })
+    }
+
+    /// 把文件按顺序写入 `writer`（例如标准输出），参数同 [`YuShi::download_with`]
+    ///
+    /// 总是使用单连接流式下载，不写入本地文件，也没有临时文件和断点续传；任务的目标
+    /// 路径记为 `-`。`options.checksum` 在写入的同时计算，不匹配时数据已经全部写出，
+    /// 但返回 [`Error::ChecksumMismatch`]。目标文件冲突策略不适用，总是按
+    /// [`ConflictPolicy::Overwrite`] 处理。
+    pub async fn download_to_writer<W>(
+        &self,
+        url: &str,
+        writer: W,
+        mut options: TaskOptions,
+        event_tx: Option<mpsc::Sender<ProgressEvent>>,
+    ) -> Result<DownloadSummary>
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let sink = match options.checksum.take() {
+            #[cfg(feature = "checksums")]
+            Some(checksum) => WriterSink::new(writer).with_checksum(checksum),
+            #[cfg(not(feature = "checksums"))]
+            Some(_) => return Err(Error::ChecksumsDisabled),
+            None => WriterSink::new(writer),
+        };
+        options.sink = Some(Arc::new(sink));
+        options.connections = Some(1);
+        options.auto_rename = false;
+        options.conflict = ConflictPolicy::Overwrite;
+        let task_id = self
+            .insert_task(url.to_string(), PathBuf::from("-"), options, None)
+            .await;
+        let result = self.download_inserted(task_id.clone(), event_tx).await;
+        // 释放 writer，读取端才能读到结尾
+        self.sinks.write().await.remove(&task_id);
+        result
+    }
 
-        // 等待任务完成
+    /// 把 `url` 的内容读入内存，适合清单、校验和文件等小文件
+    ///
+    /// 使用与任务相同的客户端、请求头、认证信息、代理和速度限制，连接失败和可重试的
+    /// 状态码按分块下载的规则重试；不创建任务，也不写入任何文件或队列状态。响应体超过
+    /// [`Config::max_memory_download`] 时返回 [`Error::ResponseTooLarge`]。
+    pub async fn download_bytes(&self, url: &str) -> Result<Bytes> {
+        let headers = HashMap::new();
+        let mut attempt = 0;
         loop {
-            let task = self.get_task(&task_id).await;
-            if let Some(task) = task {
-                match task.status {
-                    TaskStatus::Completed => return Ok(()),
-                    TaskStatus::Failed => {
-                        return Err(Error::TaskFailed(
-                            task.error.unwrap_or_else(|| "Unknown error".to_string()),
-                        ));
-                    }
-                    TaskStatus::Cancelled => {
-                        return Err(Error::TaskCancelled);
-                    }
-                    _ => {
-                        // 如果提供了进度事件发送器，发送进度更新
-                        if let Some(tx) = &event_tx {
-                            if task.total_size > 0 {
-                                tx.send(ProgressEvent::ChunkDownloading {
-                                    chunk_index: 0,
-                                    delta: 0, // 这里不发送增量，只是为了兼容
-                                })
-                                .await?;
-                            } else {
-                                tx.send(ProgressEvent::StreamDownloading {
-                                    downloaded: task.downloaded,
-                                })
-                                .await?;
-                            }
-                        }
-                        tokio::time::sleep(Duration::from_millis(100)).await;
+            let (error, delay) = match self.task_request(url, &headers, None).send().await {
+                Ok(response) if response.status().is_success() => {
+                    match self.read_bytes(response).await {
+                        Err(e) if e.is_retryable() => (e, RETRY_DELAY),
+                        result => return result,
                     }
                 }
-            } else {
+                Ok(response) => {
+                    let delay = retry_after(response.headers())
+                        .map_or(RETRY_DELAY, |delay| delay.min(MAX_RETRY_AFTER));
+                    (Error::HttpStatus(response.status().as_u16()), delay)
+                }
+                Err(e) => (Error::from(e), RETRY_DELAY),
+            };
+            attempt += 1;
+            if !error.is_retryable() || attempt > MAX_RETRIES {
+                return Err(error);
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// 读取 [`YuShi::download_bytes`] 的响应体，按速度限制等待
+    async fn read_bytes(&self, response: Response) -> Result<Bytes> {
+        let limit = self.config.max_memory_download;
+        if response.content_length().is_some_and(|len| len > limit) {
+            return Err(Error::ResponseTooLarge(limit));
+        }
+        let mut speed_limiter = self.config.speed_limit.map(SpeedLimiter::new);
+        let stall_timeout = self.config.connection_stall_timeout;
+        let mut body = Vec::new();
+        let mut stream = response.bytes_stream();
+        loop {
+            let Ok(item) = next_or_stall(&mut stream, stall_timeout).await else {
+                let secs = stall_timeout.unwrap_or_default().as_secs();
+                return Err(Error::ConnectionStalled(secs));
+            };
+            let Some(item) = item else {
+                break;
+            };
+            let data = item.map_err(|e| Error::StreamError(e.to_string()))?;
+            if (body.len() + data.len()) as u64 > limit {
+                return Err(Error::ResponseTooLarge(limit));
+            }
+            body.extend_from_slice(&data);
+            let len = data.len() as u64;
+            if let Some(speed_limiter) = &mut speed_limiter {
+                speed_limiter.wait(len).await;
+            }
+            self.global_limiter.wait(len).await;
+        }
+        Ok(Bytes::from(body))
+    }
+
+    /// 立即下载队列中的一个任务并等待它结束
+    ///
+    /// 不受调度器暂停和最大并发任务数的限制，任务需要处于等待或暂停状态；
+    /// 其他状态的任务不会启动，直接按当前状态返回。`event_tx` 的含义与
+    /// [`YuShi::download_with`] 相同。
+    pub async fn run_task(
+        &self,
+        task_id: &str,
+        event_tx: Option<mpsc::Sender<ProgressEvent>>,
+    ) -> Result<()> {
+        if let Some(tx) = event_tx {
+            self.progress_taps
+                .write()
+                .await
+                .insert(task_id.to_string(), tx);
+        }
+        let result = match self.start_queue_task(task_id).await {
+            Ok(()) => self.wait_task(task_id).await,
+            Err(e) => Err(e),
+        };
+        self.progress_taps.write().await.remove(task_id);
+        result
+    }
+
+    /// 等待任务进入终止状态，失败和取消时返回对应的错误
+    async fn wait_task(&self, task_id: &str) -> Result<()> {
+        loop {
+            let Some(task) = self.get_task(task_id).await else {
                 return Err(Error::TaskNotFound);
+            };
+            match task.status {
+                TaskStatus::Completed => return Ok(()),
+                TaskStatus::Failed | TaskStatus::PartiallyCompleted => {
+                    return Err(Error::TaskFailed(
+                        task.error.unwrap_or_else(|| message("error.unknown", &[])),
+                    ));
+                }
+                TaskStatus::Cancelled => return Err(Error::TaskCancelled),
+                _ => tokio::time::sleep(Duration::from_millis(100)).await,
             }
         }
     }
@@ -181,196 +556,762 @@ impl YuShi {
     /// 内部下载方法（由队列任务调用）
     ///
     /// # 参数
-    /// * `url` - 下载 URL
-    /// * `dest` - 目标文件路径
+    /// * `task` - 下载任务
+    /// * `sink` - 存储后端
     /// * `event_tx` - 进度事件发送器
     async fn download_internal(
         &self,
-        url: &str,
-        dest: &str,
+        task: &Task,
+        sink: SharedSink,
         event_tx: mpsc::Sender<ProgressEvent>,
     ) -> Result<()> {
-        let dest_path = PathBuf::from(dest);
-        let state_path = dest_path.with_extension("json");
+        let url = task.url.as_str();
+        if !self.sinks.read().await.contains_key(&task.id) {
+            self.migrate_legacy_partial(task).await;
+        }
+        let state_path = self.state_path(task);
+        if let Some(dir) = &self.config.temp_dir {
+            fs::create_dir_all(dir).await?;
+        }
+
+        if task.skip_probe && task.byte_range.is_none() && !state_path.exists() {
+            let probed = self
+                .tasks
+                .read()
+                .await
+                .get(&task.id)
+                .is_some_and(|t| t.remote.is_some());
+            if !probed {
+                return self.download_unprobed(task, sink, event_tx).await;
+            }
+        }
 
-        let state = self
-            .get_or_create_state(url, &dest_path, &state_path)
+        let (state, resumed) = self
+            .get_or_create_state(task, &state_path, sink.supports_random_access(), &event_tx)
             .await?;
         let state = Arc::new(RwLock::new(state));
 
-        let (total_size, is_streaming) = {
+        let (total_size, is_streaming, mut resumed_from, offset) = {
             let s = state.read().await;
-            (s.total_size, s.is_streaming)
+            let resumed_from = s.chunks.iter().map(|c| c.current - c.start).sum();
+            (s.total_size, s.is_streaming, resumed_from, s.offset)
         };
 
+        // 流式下载有保存的状态时，先尝试从已写入的文件末尾继续
+        let mut resumed_stream = None;
+        if is_streaming
+            && resumed
+            && task.byte_range.is_none()
+            && let Some(len) = sink.resumable_len().await?
+            && total_size.is_none_or(|total| len < total)
+        {
+            let host = self.host_permit(&task.id, url).await?;
+            let mut s = state.write().await;
+            let if_range = if_range_validator(s.etag.as_deref(), s.last_modified.as_deref());
+            let (response, from) = self
+                .resume_stream_request(
+                    url,
+                    &task.headers,
+                    task.auth.as_ref(),
+                    len,
+                    if_range.as_deref(),
+                )
+                .await?;
+            if from == 0 {
+                // 从头下载的是服务器现在的文件，记录它的校验值供下次继续时使用
+                (s.etag, s.last_modified) = validators(response.headers());
+                let _ = event_tx
+                    .send(ProgressEvent::Restarted {
+                        task_id: task.id.clone(),
+                        reason: RestartReason::ResumeRejected,
+                    })
+                    .await;
+            }
+            s.streamed = from;
+            s.save(&state_path).await?;
+            resumed_from = from;
+            resumed_stream = Some((response, host));
+        }
+
         event_tx
             .send(ProgressEvent::Initialized {
-                task_id: "internal".to_string(),
+                task_id: task.id.clone(),
                 total_size,
+                resumed_from,
+                final_path: task.dest.clone(),
+                supports_range: !is_streaming,
             })
             .await?;
 
-        if is_streaming {
-            // 流式下载
-            self.download_streaming(url, &dest_path, event_tx).await
+        if let Some((response, host)) = resumed_stream {
+            // 从已写入的位置继续流式下载，服务器返回整个文件时 `resumed_from` 为 0
+            sink.open(None, resumed_from > 0).await?;
+            let checkpoint = Some((state_path.as_path(), state.as_ref()));
+            self.write_stream(
+                &task.id,
+                response,
+                resumed_from,
+                sink,
+                checkpoint,
+                host,
+                event_tx,
+            )
+            .await
+        } else if is_streaming {
+            // 流式下载，不预先分配空间：文件长度就是已写入的数据，中断后从这里继续
+            sink.open(None, false).await?;
+            // 指定了下载范围时只请求这一段
+            let range = task
+                .byte_range
+                .zip(total_size)
+                .map(|(_, len)| (offset, offset + len - 1));
+            let checkpoint = sink
+                .supports_random_access()
+                .then_some((state_path.as_path(), state.as_ref()));
+            self.download_streaming(
+                &task.id,
+                url,
+                &task.headers,
+                task.auth.as_ref(),
+                range,
+                sink,
+                checkpoint,
+                event_tx,
+            )
+            .await
         } else {
             // 分块下载
-            self.download_chunked(state, &dest_path, &state_path, event_tx)
+            sink.open(total_size, resumed).await?;
+            let result = self
+                .download_chunked(
+                    &task.id,
+                    state,
+                    Arc::clone(&sink),
+                    &state_path,
+                    event_tx.clone(),
+                )
+                .await;
+            // 探测时服务器声称支持 Range，实际返回了整个文件：改为流式下载，从头重新开始
+            if !matches!(result, Err(Error::RangeIgnored)) || task.byte_range.is_some() {
+                return result;
+            }
+            if let Some(remote) = self
+                .tasks
+                .write()
                 .await
+                .get_mut(&task.id)
+                .and_then(|t| t.remote.as_mut())
+            {
+                remote.supports_range = false;
+            }
+            self.store.mark_task(&task.id);
+            let _ = fs::remove_file(&state_path).await;
+            let _ = event_tx
+                .send(ProgressEvent::Restarted {
+                    task_id: task.id.clone(),
+                    reason: RestartReason::RangeIgnored,
+                })
+                .await;
+            Box::pin(self.download_internal(task, sink, event_tx)).await
+        }
+    }
+
+    /// 下载任务，整个任务超过 [`Config::stall_timeout`] 没有收到数据时
+    /// 中断当前连接，从已下载的位置重新开始
+    async fn download_watched(
+        &self,
+        task: &Task,
+        sink: SharedSink,
+        event_tx: mpsc::Sender<ProgressEvent>,
+    ) -> Result<()> {
+        const MAX_STALL_RETRIES: u32 = 5;
+
+        let (Some(timeout), Some(counters)) =
+            (self.config.stall_timeout, self.progress.get(&task.id))
+        else {
+            return self.download_internal(task, sink, event_tx).await;
+        };
+        let poll_interval = (timeout / 4).min(Duration::from_secs(1));
+
+        let mut attempt = 0;
+        loop {
+            counters.touch();
+            let stalled = async {
+                while counters.idle() < timeout {
+                    tokio::time::sleep(poll_interval).await;
+                }
+            };
+            tokio::select! {
+                result = self.download_internal(task, Arc::clone(&sink), event_tx.clone()) => {
+                    // 流式下载的连接停滞和整个任务停滞一样，重新开始
+                    if !matches!(result, Err(Error::ConnectionStalled(_))) {
+                        return result;
+                    }
+                }
+                _ = stalled => {}
+            }
+
+            attempt += 1;
+            if attempt > MAX_STALL_RETRIES {
+                return Err(Error::TaskFailed(message(
+                    "download.stalled",
+                    &[&timeout.as_secs()],
+                )));
+            }
+            let _ = event_tx
+                .send(ProgressEvent::Retrying {
+                    task_id: task.id.clone(),
+                    attempt,
+                    reason: RetryReason::Stalled,
+                })
+                .await;
         }
     }
 
+    /// 不探测直接下载（[`TaskOptions::skip_probe`]）
+    ///
+    /// 用 GET 响应头中的大小和 Range 支持作为探测结果记录在任务上，
+    /// 这次下载按流式下载处理，重试时按探测结果正常下载。
+    async fn download_unprobed(
+        &self,
+        task: &Task,
+        sink: SharedSink,
+        event_tx: mpsc::Sender<ProgressEvent>,
+    ) -> Result<()> {
+        let host = self.host_permit(&task.id, &task.url).await?;
+        let response = self
+            .stream_request(&task.url, &task.headers, task.auth.as_ref(), None)
+            .await?;
+        let (etag, last_modified) = validators(response.headers());
+        let info = RemoteInfo {
+            total_size: response.content_length(),
+            supports_range: response
+                .headers()
+                .get(ACCEPT_RANGES)
+                .is_some_and(|v| v.to_str().unwrap_or("").contains("bytes")),
+            etag,
+            last_modified,
+            filename: suggested_filename(response.headers()),
+        };
+        let resolved = resolved_url(&task.url, response.url());
+        if let Some(task) = self.tasks.write().await.get_mut(&task.id) {
+            task.remote = Some(info.clone());
+            task.resolved_url = resolved.clone();
+        }
+        self.store.mark_task(&task.id);
+
+        // 和探测后的流式下载一样保存状态，重试时从已写入的位置继续
+        let state_path = self.state_path(task);
+        let state = RwLock::new(DownloadState {
+            url: resolved.unwrap_or_else(|| task.url.clone()),
+            total_size: info.total_size,
+            chunks: Vec::new(),
+            is_streaming: true,
+            offset: 0,
+            etag: info.etag.clone(),
+            last_modified: info.last_modified.clone(),
+            streamed: 0,
+            reported_prefix: 0,
+        });
+        let checkpoint = if sink.supports_random_access() {
+            state.read().await.save(&state_path).await?;
+            Some((state_path.as_path(), &state))
+        } else {
+            None
+        };
+
+        event_tx
+            .send(ProgressEvent::Initialized {
+                task_id: task.id.clone(),
+                total_size: info.total_size,
+                resumed_from: 0,
+                final_path: task.dest.clone(),
+                supports_range: false,
+            })
+            .await?;
+        sink.open(None, false).await?;
+        self.write_stream(&task.id, response, 0, sink, checkpoint, host, event_tx)
+            .await
+    }
+
     /// 流式下载（不需要 Content-Length）
+    ///
+    /// `range` 为包含两端的字节范围，指定时服务器必须返回 206。
+    #[allow(clippy::too_many_arguments)]
     async fn download_streaming(
         &self,
+        task_id: &str,
         url: &str,
-        dest: &std::path::PathBuf,
+        task_headers: &HashMap<String, String>,
+        auth: Option<&Credential>,
+        range: Option<(u64, u64)>,
+        sink: SharedSink,
+        checkpoint: Option<(&Path, &RwLock<DownloadState>)>,
         event_tx: mpsc::Sender<ProgressEvent>,
     ) -> Result<()> {
-        let mut request = self.client.get(url);
+        let host = self.host_permit(task_id, url).await?;
+        let response = self.stream_request(url, task_headers, auth, range).await?;
+        self.write_stream(task_id, response, 0, sink, checkpoint, host, event_tx)
+            .await
+    }
 
-        // 添加自定义头
-        for (key, value) in &self.config.headers {
-            request = request.header(key, value);
+    /// 请求流式下载从 `from` 开始的剩余部分
+    ///
+    /// `if_range` 为开始下载时记录的 `ETag` 或 `Last-Modified`，远程文件已经变化时服务器
+    /// 返回整个文件。返回响应和响应体在文件中的起始位置：206 时为 `from`，服务器忽略
+    /// Range 返回 200 或者无法满足 Range（416）时从头下载，为 0。
+    async fn resume_stream_request(
+        &self,
+        url: &str,
+        task_headers: &HashMap<String, String>,
+        auth: Option<&Credential>,
+        from: u64,
+        if_range: Option<&str>,
+    ) -> Result<(Response, u64)> {
+        let mut request = self
+            .task_request(url, task_headers, auth)
+            .header(RANGE, format!("bytes={}-", from));
+        if let Some(validator) = if_range {
+            request = request.header(IF_RANGE, validator);
         }
-
-        // 添加 User-Agent
-        if let Some(ua) = &self.config.user_agent {
-            request = request.header(USER_AGENT, ua);
+        let response = request.send().await?;
+        let status = response.status();
+        let starts_at_from = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| content_range_start(v.to_str().ok()?))
+            == Some(from);
+        if status == StatusCode::PARTIAL_CONTENT && starts_at_from {
+            Ok((response, from))
+        } else if status == StatusCode::OK {
+            Ok((response, 0))
+        } else if status == StatusCode::RANGE_NOT_SATISFIABLE
+            || status == StatusCode::PARTIAL_CONTENT
+        {
+            Ok((self.stream_request(url, task_headers, auth, None).await?, 0))
+        } else {
+            Err(Error::HttpStatus(status.as_u16()))
         }
+    }
 
+    /// 发送流式下载的 GET 请求并检查响应状态
+    async fn stream_request(
+        &self,
+        url: &str,
+        task_headers: &HashMap<String, String>,
+        auth: Option<&Credential>,
+        range: Option<(u64, u64)>,
+    ) -> Result<Response> {
+        let mut request = self.task_request(url, task_headers, auth);
+        if let Some((start, end)) = range {
+            request = request.header(RANGE, format!("bytes={}-{}", start, end));
+        }
         let response = request.send().await?;
+        if range.is_some() && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Err(range_not_satisfiable(response.headers()));
+        }
         if !response.status().is_success() {
-            return Err(Error::HttpError(response.status().to_string()));
+            return Err(Error::HttpStatus(response.status().as_u16()));
+        }
+        if range.is_some() && response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(Error::RangeIgnored);
         }
+        Ok(response)
+    }
 
-        let mut file = fs::File::create(dest).await?;
+    /// 把响应体依次写入存储后端，响应体从 `start` 处开始
+    ///
+    /// 指定了 `checkpoint` 时，每次落盘后把已写入的字节数保存到状态文件。
+    /// 任务被暂停或取消时落盘并保存后返回 [`Error::TaskCancelled`]。
+    /// `host` 为这个连接占用的主机许可，写完后释放。
+    #[allow(clippy::too_many_arguments)]
+    async fn write_stream(
+        &self,
+        task_id: &str,
+        response: Response,
+        start: u64,
+        sink: SharedSink,
+        checkpoint: Option<(&Path, &RwLock<DownloadState>)>,
+        host: HostPermit,
+        event_tx: mpsc::Sender<ProgressEvent>,
+    ) -> Result<()> {
         let mut stream = response.bytes_stream();
-        let mut downloaded = 0u64;
-        let speed_limiter = self
-            .config
-            .speed_limit
-            .map(|limit| Arc::new(RwLock::new(SpeedLimiter::new(limit))));
+        let mut downloaded = start;
+        let mut reported = start;
+        let speed_limiter = self.task_speed_limiter(task_id).await;
+        let boost = self.boost_handle(task_id);
+        let cancel = self.cancel_token(task_id).await;
+        let stall_timeout = self.config.connection_stall_timeout;
 
-        while let Some(item) = stream.next().await {
+        loop {
+            // 先预留缓冲预算再读取，预算用完时不再从连接读取数据
+            let mut permit = self.buffer_budget.reserve(READ_RESERVE).await?;
+            // 暂停或取消时停在两次写入之间，落盘后保存已写入的字节数
+            let Some(item) = cancel
+                .run_until_cancelled(next_or_stall(&mut stream, stall_timeout))
+                .await
+            else {
+                sink.flush().await?;
+                if let Some((path, state)) = checkpoint {
+                    let mut state = state.write().await;
+                    state.streamed = downloaded;
+                    state.save(path).await?;
+                }
+                return Err(Error::TaskCancelled);
+            };
+            let Ok(item) = item else {
+                let secs = stall_timeout.unwrap_or_default().as_secs();
+                return Err(Error::ConnectionStalled(secs));
+            };
+            let Some(item) = item else {
+                break;
+            };
             let chunk_data = item.map_err(|e| Error::StreamError(e.to_string()))?;
-            file.write_all(&chunk_data).await?;
+            permit.fit(chunk_data.len()).await?;
+            let write_started = Instant::now();
+            sink.write_at(downloaded, &chunk_data).await?;
+            drop(permit);
 
             let len = chunk_data.len() as u64;
+            if let Some(pressure) = &self.disk_pressure {
+                pressure.record(len, write_started.elapsed());
+            }
             downloaded += len;
 
-            if let Some(speed_limiter) = &speed_limiter {
-                speed_limiter.write().await.wait(len).await;
-            }
+            let limit = async {
+                if !boosted(&boost) {
+                    if let Some(speed_limiter) = &speed_limiter {
+                        speed_limiter.write().await.wait(len).await;
+                    }
+                    self.global_limiter.wait(len).await;
+                }
+                host.wait(len).await;
+            };
+            cancel.run_until_cancelled(limit).await;
 
             let _ = event_tx
-                .send(ProgressEvent::StreamDownloading { downloaded })
+                .send(ProgressEvent::StreamProgress {
+                    task_id: task_id.to_string(),
+                    downloaded,
+                })
                 .await;
+
+            // 按顺序写入，已落盘的数据就是连续前缀
+            if downloaded - reported >= PREFIX_REPORT_STEP {
+                sink.flush().await?;
+                reported = downloaded;
+                if let Some((path, state)) = checkpoint {
+                    let mut state = state.write().await;
+                    state.streamed = downloaded;
+                    state.save(path).await?;
+                }
+                let _ = event_tx
+                    .send(ProgressEvent::PrefixFlushed {
+                        task_id: task_id.to_string(),
+                        contiguous_prefix: reported,
+                    })
+                    .await;
+            }
         }
 
-        file.flush().await?;
-        event_tx
-            .send(ProgressEvent::Finished {
-                task_id: "internal".to_string(),
+        sink.finalize().await?;
+        let _ = event_tx
+            .send(ProgressEvent::PrefixFlushed {
+                task_id: task_id.to_string(),
+                contiguous_prefix: downloaded,
             })
-            .await?;
+            .await;
         Ok(())
     }
 
     /// 分块下载（需要 Content-Length）
+    ///
+    /// 并发连接数由任务的 [`ConnectionLimiter`] 控制，可在下载过程中调整。
     async fn download_chunked(
         &self,
+        task_id: &str,
         state: Arc<tokio::sync::RwLock<DownloadState>>,
-        dest_path: &Path,
+        sink: SharedSink,
         state_path: &Path,
         event_tx: mpsc::Sender<ProgressEvent>,
     ) -> Result<()> {
-        let semaphore = Arc::new(Semaphore::new(self.config.max_concurrent));
-        let speed_limiter = self
-            .config
-            .speed_limit
-            .map(|limit| Arc::new(RwLock::new(SpeedLimiter::new(limit))));
-        let mut workers = Vec::new();
+        let limiter = match self.connection_limits.read().await.get(task_id) {
+            Some(limiter) => Arc::clone(limiter),
+            None => Arc::new(ConnectionLimiter::new(self.default_connections())),
+        };
+        let counters = self.progress.get(task_id);
+        let speed_limiter = self.task_speed_limiter(task_id).await;
+        let boost = self.boost_handle(task_id);
+        let cancel = self.cancel_token(task_id).await;
+        let (connections, order, task_headers, auth) = self
+            .tasks
+            .read()
+            .await
+            .get(task_id)
+            .map(|t| {
+                (
+                    t.connections,
+                    t.chunk_order,
+                    t.headers.clone(),
+                    t.auth.clone(),
+                )
+            })
+            .unwrap_or_default();
+        let headers = self.request_headers(&task_headers);
+        let user_agent = self.user_agent_for(&task_headers);
+        let order = order.unwrap_or(self.default_chunk_order());
+        let target = match order {
+            ChunkOrder::Sequential => 1,
+            _ => connections.unwrap_or(self.default_connections()),
+        };
+        // 和分块连接一样，提前返回时随 JoinSet 一起停止
+        let mut ramp = tokio::task::JoinSet::new();
+        match self.config.ramp_up {
+            _ if order == ChunkOrder::Sequential => self.set_active_connections(task_id, 1).await,
+            Some(interval) if target > 1 => {
+                limiter.set_limit(1);
+                self.set_active_connections(task_id, 1).await;
+                let downloader = self.clone();
+                let limiter_c = Arc::clone(&limiter);
+                let task_id_c = task_id.to_string();
+                ramp.spawn(async move {
+                    downloader
+                        .ramp_up_connections(&task_id_c, limiter_c, target, interval)
+                        .await
+                });
+            }
+            _ => self.set_active_connections(task_id, limiter.limit()).await,
+        }
+        // 放弃下载（例如停滞重试）时丢弃 JoinSet 会中断所有分块连接
+        let mut workers = tokio::task::JoinSet::new();
+        let allow_holes = self.config.on_chunk_exhausted == ChunkFailurePolicy::CompleteWithHoles;
+        let mut holes = Vec::new();
 
-        let (chunks_count, url) = {
+        let (chunk_starts, url) = {
             let s = state.read().await;
-            (s.chunks.len(), s.url.clone())
+            if let Some(counters) = &counters {
+                let finished = s.chunks.iter().filter(|c| c.is_finished).count();
+                counters.set_chunks(s.chunks.len(), finished);
+            }
+            let starts: Vec<u64> = s.chunks.iter().map(|c| c.start).collect();
+            (starts, s.url.clone())
+        };
+        let refresh = self
+            .url_refreshers
+            .read()
+            .await
+            .get(task_id)
+            .cloned()
+            .map(|refresher| {
+                Arc::new(UrlRefresh {
+                    downloader: self.clone(),
+                    refresher,
+                    lock: tokio::sync::Mutex::new(()),
+                })
+            });
+        let fallback = Arc::new(UrlFallback {
+            downloader: self.clone(),
+        });
+        let mirrors = self.mirror_pool(task_id, &event_tx).await;
+        // 续传时先报告已有的连续前缀
+        report_prefix(task_id, &state, &sink, &event_tx, true).await?;
+        // 在这个位置之前开始的分块逐个按顺序下载
+        let sequential_prefix = match order {
+            ChunkOrder::Sequential => u64::MAX,
+            ChunkOrder::SequentialFirst(prefix) => prefix,
+            ChunkOrder::Parallel | ChunkOrder::Random => 0,
+        };
+        let indices = match order {
+            ChunkOrder::Random => shuffled_indices(chunk_starts.len(), task_id),
+            _ => (0..chunk_starts.len()).collect(),
         };
 
-        for i in 0..chunks_count {
-            let permit = semaphore.clone().acquire_owned().await?;
+        for i in indices {
+            let chunk_start = chunk_starts[i];
+            let Some(permit) = cancel.run_until_cancelled(limiter.acquire()).await else {
+                break;
+            };
+            let permit = permit?;
+            // 同一主机的连接数达到上限时，等其他任务的连接结束
+            let Some(host) = cancel
+                .run_until_cancelled(self.host_limits.acquire(&url))
+                .await
+            else {
+                break;
+            };
+            let host = host?;
             let state_c = Arc::clone(&state);
             let client_c = self.client.clone();
-            let url_c = url.clone();
-            let dest_c = dest_path.to_path_buf();
+            let refresh_c = refresh.clone();
+            let fallback_c = Arc::clone(&fallback);
+            let mirrors_c = mirrors.clone();
+            let sink_c = Arc::clone(&sink);
             let state_file_c = state_path.to_path_buf();
             let tx_c = event_tx.clone();
             let speed_limiter_c = speed_limiter.clone();
-            let headers = self.config.headers.clone();
-            let user_agent = self.config.user_agent.clone();
+            let global_limiter = Arc::clone(&self.global_limiter);
+            let boost_c = Arc::clone(&boost);
+            let disk_pressure = self.disk_pressure.clone();
+            let buffer_budget = Arc::clone(&self.buffer_budget);
+            let headers = headers.clone();
+            let user_agent = user_agent.clone();
+            let credential = self.credential_for(&url, &task_headers, auth.as_ref());
+            let stall_timeout = self.config.connection_stall_timeout;
+            let counters_c = counters.clone();
+            let limiter_c = Arc::clone(&limiter);
+            let cancel_c = cancel.clone();
+            let task_id_c = task_id.to_string();
 
-            workers.push(tokio::spawn(async move {
-                let res = Self::download_chunk(
+            let worker = async move {
+                let outcome = Self::download_chunk(
+                    &task_id_c,
                     i,
                     client_c,
-                    &url_c,
-                    &dest_c,
+                    refresh_c,
+                    fallback_c,
+                    mirrors_c,
+                    sink_c,
                     &state_file_c,
                     state_c,
                     tx_c,
                     speed_limiter_c,
+                    global_limiter,
+                    boost_c,
+                    disk_pressure,
+                    buffer_budget,
+                    limiter_c,
+                    &host,
                     headers,
                     user_agent,
+                    credential,
+                    stall_timeout,
+                    cancel_c,
                 )
                 .await;
+                drop(host);
                 drop(permit);
-                res
-            }));
+                // 返回留下空洞的分块
+                match outcome? {
+                    ChunkOutcome::Finished => {
+                        if let Some(counters) = counters_c {
+                            counters.chunk_finished();
+                        }
+                        Ok(None)
+                    }
+                    ChunkOutcome::AlreadyFinished => Ok(None),
+                    ChunkOutcome::Exhausted(_) if allow_holes => Ok(Some(i)),
+                    ChunkOutcome::Exhausted(e) => Err(e),
+                }
+            };
+            // 开头的分块下载完一个再开始下一个，之后才并发下载
+            if chunk_start < sequential_prefix {
+                holes.extend(worker.await?);
+            } else {
+                workers.spawn(worker);
+            }
+        }
+
+        // 暂停或取消时等所有分块都停在安全的位置后再返回
+        while let Some(result) = workers.join_next().await {
+            match result? {
+                Err(Error::TaskCancelled) => {}
+                result => holes.extend(result?),
+            }
+        }
+        if cancel.is_cancelled() {
+            return Err(Error::TaskCancelled);
         }
 
-        for worker in workers {
-            worker.await??;
+        holes.sort_unstable();
+        let missing_ranges = {
+            let s = state.read().await;
+            holes
+                .iter()
+                .map(|&i| (s.chunks[i].current, s.chunks[i].end))
+                .collect()
+        };
+        if let Some(task) = self.tasks.write().await.get_mut(task_id) {
+            task.missing_ranges = missing_ranges;
         }
+        self.store.mark_task(task_id);
 
-        fs::remove_file(state_path).await?;
-        event_tx
-            .send(ProgressEvent::Finished {
-                task_id: "internal".to_string(),
-            })
-            .await?;
+        // 状态文件保留到校验通过，以便校验失败时只修复损坏的分块
+        sink.finalize().await?;
         Ok(())
     }
 
     #[allow(clippy::too_many_arguments)]
     /// 下载单个分块
     async fn download_chunk(
+        task_id: &str,
         index: usize,
         client: reqwest::Client,
-        url: &str,
-        dest: &Path,
+        refresh: Option<Arc<UrlRefresh>>,
+        fallback: Arc<UrlFallback>,
+        mirrors: Option<Arc<MirrorPool>>,
+        sink: SharedSink,
         state_file: &Path,
         state_lock: Arc<tokio::sync::RwLock<DownloadState>>,
         tx: mpsc::Sender<ProgressEvent>,
         speed_limiter: Option<Arc<RwLock<SpeedLimiter>>>,
+        global_limiter: Arc<SharedSpeedLimiter>,
+        boost: Arc<AtomicU64>,
+        disk_pressure: Option<Arc<DiskPressure>>,
+        buffer_budget: Arc<BufferBudget>,
+        limiter: Arc<ConnectionLimiter>,
+        host: &HostPermit,
         headers: std::collections::HashMap<String, String>,
         user_agent: Option<String>,
-    ) -> Result<()> {
-        let (start_pos, end_pos) = {
+        credential: Option<Credential>,
+        stall_timeout: Option<Duration>,
+        cancel: CancellationToken,
+    ) -> Result<ChunkOutcome> {
+        let (end_pos, offset) = {
             let s = state_lock.read().await;
             let chunk = &s.chunks[index];
             if chunk.is_finished {
-                return Ok(());
+                return Ok(ChunkOutcome::AlreadyFinished);
             }
-            (chunk.current, chunk.end)
+            (chunk.end, s.offset)
         };
 
         let mut retry_count = 0;
-        const MAX_RETRIES: u32 = 5;
+        // 刷新地址后仍然被拒绝时直接失败，不再刷新
+        let mut refreshed = false;
+        let exhausted = || {
+            ChunkOutcome::Exhausted(Error::HttpError(message(
+                "download.chunk_retries_exhausted",
+                &[&index, &MAX_RETRIES],
+            )))
+        };
+        // 有镜像时从轮到的地址开始下载，None 表示任务自己的地址
+        let mut source = mirrors.as_ref().and_then(|pool| pool.pick());
+        // 在当前地址上连续失败的次数
+        let mut source_failures = 0;
 
         loop {
-            let mut request = client
-                .get(url)
-                .header(RANGE, format!("bytes={}-{}", start_pos, end_pos));
+            // 当前的镜像已被其他分块停用时换用下一个地址
+            if let (Some(pool), Some(m)) = (&mirrors, source)
+                && pool.is_demoted(m)
+            {
+                source = pool.after(source);
+                source_failures = 0;
+            }
+            let mirror = mirrors.as_deref().zip(source);
+            // 地址可能已被其他分块刷新，连接停滞后从已写入的位置继续
+            let (url, start_pos, from_start) = {
+                let s = state_lock.read().await;
+                let chunk = &s.chunks[index];
+                let url = match mirror {
+                    Some((pool, m)) => pool.url(m).to_string(),
+                    None => s.url.clone(),
+                };
+                (url, chunk.current, chunk.current == chunk.start)
+            };
+            let mut request = client.get(&url).header(
+                RANGE,
+                format!("bytes={}-{}", offset + start_pos, offset + end_pos),
+            );
 
             // 添加自定义头
             for (key, value) in &headers {
@@ -382,167 +1323,925 @@ impl YuShi {
                 request = request.header(USER_AGENT, ua);
             }
 
-            let res = request.send().await;
+            // 镜像使用它自己主机的认证信息
+            let credential = match mirror {
+                Some((pool, m)) => pool.credential(m),
+                None => credential.as_ref(),
+            };
+            if let Some(credential) = credential {
+                request = credential.apply(request);
+            }
+
+            let Some(res) = cancel.run_until_cancelled(request.send()).await else {
+                return Err(Error::TaskCancelled);
+            };
 
             match res {
+                // 服务器忽略了 Range，响应体是整个文件，不能写到分块的位置
+                Ok(resp)
+                    if resp.status().is_success()
+                        && resp.status() != StatusCode::PARTIAL_CONTENT =>
+                {
+                    // 镜像忽略了 Range 时只停用这个镜像
+                    if let Some((pool, m)) = mirror {
+                        pool.demote(m, MirrorDemotion::RangeIgnored).await;
+                        source = pool.after(source);
+                        source_failures = 0;
+                        continue;
+                    }
+                    return Err(Error::RangeIgnored);
+                }
                 Ok(resp) if resp.status().is_success() => {
-                    let mut file = fs::OpenOptions::new().write(true).open(&dest).await?;
-                    file.seek(SeekFrom::Start(start_pos)).await?;
-
                     let mut stream = resp.bytes_stream();
                     let mut current_idx = start_pos;
+                    // 只有从分块起点开始下载时才能得到完整的分块摘要
+                    let mut hasher = from_start.then(ChunkDigest::new);
+                    // 连接停滞或中断时重新请求剩余部分的原因
+                    let mut interrupted = None;
 
-                    while let Some(item) = stream.next().await {
-                        let chunk_data = item.map_err(|e| Error::StreamError(e.to_string()))?;
-                        file.write_all(&chunk_data).await?;
+                    loop {
+                        // 先预留缓冲预算再读取，预算用完时不再从连接读取数据
+                        let mut permit = buffer_budget.reserve(READ_RESERVE).await?;
+                        // 暂停或取消时停在两次写入之间，已写入的数据和分块状态都已保存
+                        let Some(item) = cancel
+                            .run_until_cancelled(next_or_stall(&mut stream, stall_timeout))
+                            .await
+                        else {
+                            sink.flush().await?;
+                            return Err(Error::TaskCancelled);
+                        };
+                        let Ok(item) = item else {
+                            interrupted =
+                                Some(RetryReason::ConnectionStalled { chunk_index: index });
+                            break;
+                        };
+                        let Some(item) = item else {
+                            break;
+                        };
+                        let Ok(chunk_data) = item else {
+                            interrupted = Some(RetryReason::ConnectionLost { chunk_index: index });
+                            break;
+                        };
+                        permit.fit(chunk_data.len()).await?;
+                        let write_started = Instant::now();
+                        sink.write_at(current_idx, &chunk_data).await?;
+                        let write_elapsed = write_started.elapsed();
+                        if let Some(hasher) = &mut hasher {
+                            hasher.update(&chunk_data);
+                        }
+                        drop(permit);
 
                         let len = chunk_data.len() as u64;
+                        if let Some(pressure) = &disk_pressure {
+                            pressure.record(len, write_elapsed);
+                        }
                         current_idx += len;
 
-                        if let Some(speed_limiter) = &speed_limiter {
-                            speed_limiter.write().await.wait(len).await;
-                        }
+                        let limit = async {
+                            if !boosted(&boost) {
+                                if let Some(speed_limiter) = &speed_limiter {
+                                    speed_limiter.write().await.wait(len).await;
+                                }
+                                global_limiter.wait(len).await;
+                            }
+                            host.wait(len).await;
+                        };
+                        cancel.run_until_cancelled(limit).await;
 
-                        // 更新内存状态
+                        // 更新并保存状态，之后再报告进度：中途停止时报告过的进度都已保存，
+                        // 续传时进度不会倒退
                         {
                             let mut s = state_lock.write().await;
                             s.chunks[index].current = current_idx;
                         }
+                        state_lock.read().await.save(state_file).await?;
+                        report_prefix(task_id, &state_lock, &sink, &tx, false).await?;
 
                         let _ = tx
-                            .send(ProgressEvent::ChunkDownloading {
+                            .send(ProgressEvent::ChunkProgress {
+                                task_id: task_id.to_string(),
                                 chunk_index: index,
                                 delta: len,
                             })
                             .await;
+                    }
 
-                        // 保存状态
-                        let state = state_lock.read().await;
-                        state.save(state_file).await?;
+                    if let Some(reason) = interrupted {
+                        // 丢弃停滞或中断的连接，立即重新请求剩余部分
+                        drop(stream);
+                        source_failures += 1;
+                        if let Some(pool) = &mirrors
+                            && let Some(next) = pool.failed(source, source_failures).await
+                        {
+                            source = next;
+                            source_failures = 0;
+                            retry_count = 0;
+                            continue;
+                        }
+                        retry_count += 1;
+                        if retry_count > MAX_RETRIES {
+                            return Ok(exhausted());
+                        }
+                        let _ = tx
+                            .send(ProgressEvent::Retrying {
+                                task_id: task_id.to_string(),
+                                attempt: retry_count,
+                                reason,
+                            })
+                            .await;
+                        continue;
                     }
 
                     let mut s = state_lock.write().await;
                     s.chunks[index].is_finished = true;
-                    return Ok(());
+                    s.chunks[index].digest = hasher.and_then(ChunkDigest::finish);
+                    s.save(state_file).await?;
+                    let bytes = s.chunks[index].end - s.chunks[index].start + 1;
+                    drop(s);
+                    report_prefix(task_id, &state_lock, &sink, &tx, true).await?;
+                    let _ = tx
+                        .send(ProgressEvent::ChunkFinished {
+                            task_id: task_id.to_string(),
+                            chunk_index: index,
+                            bytes,
+                        })
+                        .await;
+                    return Ok(ChunkOutcome::Finished);
                 }
-                _ => {
+                other => {
+                    if let Some((pool, m)) = mirror {
+                        // 镜像返回重试也不会成功的状态码时停用这个镜像
+                        if let Ok(resp) = &other
+                            && !Error::HttpStatus(resp.status().as_u16()).is_retryable()
+                        {
+                            let status = resp.status().as_u16();
+                            pool.demote(m, MirrorDemotion::Rejected { status }).await;
+                            source = pool.after(source);
+                            source_failures = 0;
+                            continue;
+                        }
+                    } else if fallback
+                        .fall_back(task_id, &state_lock, state_file, &url)
+                        .await?
+                    {
+                        // 重定向后的地址失败时改回原始地址，由服务器重新跳转
+                        continue;
+                    } else if let (Ok(resp), Some(refresh)) = (&other, &refresh)
+                        && matches!(
+                            resp.status(),
+                            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+                        )
+                        && !refreshed
+                    {
+                        refresh
+                            .refresh(task_id, &state_lock, state_file, &url)
+                            .await?;
+                        refreshed = true;
+                        continue;
+                    }
+                    let delay = match &other {
+                        // 远程文件在探测之后变小了
+                        Ok(resp) if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE => {
+                            return Err(range_not_satisfiable(resp.headers()));
+                        }
+                        Ok(resp) => {
+                            // 其他 4xx 重试也不会成功
+                            let error = Error::HttpStatus(resp.status().as_u16());
+                            if !error.is_retryable() {
+                                return Err(error);
+                            }
+                            if matches!(
+                                resp.status(),
+                                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+                            ) {
+                                limiter.mark_throttled();
+                            }
+                            retry_after(resp.headers())
+                                .map_or(RETRY_DELAY, |delay| delay.min(MAX_RETRY_AFTER))
+                        }
+                        Err(_) => RETRY_DELAY,
+                    };
+                    // 连续失败时立即换用下一个地址
+                    source_failures += 1;
+                    if let Some(pool) = &mirrors
+                        && let Some(next) = pool.failed(source, source_failures).await
+                    {
+                        source = next;
+                        source_failures = 0;
+                        retry_count = 0;
+                        continue;
+                    }
                     retry_count += 1;
                     if retry_count > MAX_RETRIES {
-                        return Err(Error::HttpError(format!(
-                            "Chunk {} failed after {} retries",
-                            index, MAX_RETRIES
-                        )));
+                        return Ok(exhausted());
                     }
-                    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+                    if cancel
+                        .run_until_cancelled(tokio::time::sleep(delay))
+                        .await
+                        .is_none()
+                    {
+                        return Err(Error::TaskCancelled);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 每隔 `interval` 增加一个连接，直到 `target`（见 [`Config::ramp_up`]）
+    ///
+    /// 服务器返回 429 或 503，或者连接数被 [`YuShi::set_task_connections`] 修改后停止增加。
+    async fn ramp_up_connections(
+        &self,
+        task_id: &str,
+        limiter: Arc<ConnectionLimiter>,
+        target: usize,
+        interval: Duration,
+    ) {
+        let mut current = limiter.limit();
+        while current < target {
+            tokio::time::sleep(interval).await;
+            if limiter.is_throttled() || limiter.limit() != current {
+                return;
+            }
+            current += 1;
+            limiter.set_limit(current);
+            self.set_active_connections(task_id, current).await;
+        }
+    }
+
+    /// 记录任务当前允许的并发连接数
+    async fn set_active_connections(&self, task_id: &str, connections: usize) {
+        if let Some(counters) = self.progress.get(task_id) {
+            counters.set_connections(connections);
+        }
+        if let Some(task) = self.tasks.write().await.get_mut(task_id) {
+            task.active_connections = connections;
+        }
+    }
+
+    /// 探测任务的镜像（[`TaskOptions::mirrors`]），没有镜像时返回 None
+    ///
+    /// 大小与任务地址不同或不支持 Range 的镜像在开始下载前就停用。
+    async fn mirror_pool(
+        &self,
+        task_id: &str,
+        event_tx: &mpsc::Sender<ProgressEvent>,
+    ) -> Option<Arc<MirrorPool>> {
+        let (urls, expected, headers) = self.tasks.read().await.get(task_id).map(|t| {
+            let expected = t.remote.as_ref().and_then(|r| r.total_size);
+            (t.mirrors.clone(), expected, t.headers.clone())
+        })?;
+        if urls.is_empty() {
+            return None;
+        }
+        let probes = futures::future::join_all(
+            urls.iter()
+                .map(|url| self.probe_with_headers(url, &headers, None)),
+        )
+        .await;
+        let pool = MirrorPool {
+            task_id: task_id.to_string(),
+            mirrors: urls
+                .iter()
+                .map(|url| Mirror {
+                    url: url.clone(),
+                    credential: self.credential_for(url, &headers, None),
+                    failures: AtomicU32::new(0),
+                    demoted: AtomicBool::new(false),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            event_tx: event_tx.clone(),
+        };
+        for (m, probe) in probes.into_iter().enumerate() {
+            let reason = match probe {
+                Err(_) => MirrorDemotion::ProbeFailed,
+                Ok((info, _)) if expected.is_none() || info.total_size != expected => {
+                    MirrorDemotion::SizeMismatch
                 }
+                Ok((info, _)) if !info.supports_range => MirrorDemotion::RangeIgnored,
+                Ok(_) => continue,
+            };
+            pool.demote(m, reason).await;
+        }
+        Some(Arc::new(pool))
+    }
+
+    /// 大小为 `total_size` 的文件是否不分块，见 [`Config::single_connection_threshold`]
+    fn single_connection(&self, total_size: u64) -> bool {
+        self.config
+            .single_connection_threshold
+            .is_some_and(|threshold| total_size < threshold)
+    }
+
+    /// 按配置把 `total_size` 字节划分为分块
+    ///
+    /// 分块大小为 [`Config::chunk_size`]，分块数超过 [`Config::max_chunks`] 时按上限增大；
+    /// 末尾不足 [`Config::min_chunk_size`] 的部分并入前一个分块。
+    fn chunk_layout(&self, total_size: u64) -> Vec<ChunkState> {
+        let max_chunks = self.config.max_chunks.max(1) as u64;
+        let chunk_size = self
+            .config
+            .chunk_size
+            .max(total_size.div_ceil(max_chunks))
+            .max(1);
+        let min_chunk_size = self.config.min_chunk_size.unwrap_or(0);
+
+        let mut chunks: Vec<ChunkState> = Vec::new();
+        let mut curr = 0;
+        while curr < total_size {
+            let end = (curr + chunk_size - 1).min(total_size - 1);
+            match chunks.last_mut() {
+                Some(last) if end - curr + 1 < min_chunk_size => last.end = end,
+                _ => chunks.push(ChunkState {
+                    index: chunks.len(),
+                    start: curr,
+                    end,
+                    current: curr,
+                    is_finished: false,
+                    digest: None,
+                }),
             }
+            curr += chunk_size;
         }
+        chunks
     }
 
     /// 获取或创建下载状态
+    ///
+    /// 返回下载状态以及是否从已有状态恢复。存储后端不支持随机写入时
+    /// （`random_access` 为 `false`）总是使用流式下载。
+    ///
+    /// 已有状态记录了 `ETag` 或 `Last-Modified` 时，续传前重新探测：远程文件已经变化时
+    /// 丢弃已下载的数据，发送 [`ProgressEvent::Restarted`] 后从头下载。
     async fn get_or_create_state(
         &self,
-        url: &str,
-        dest: &Path,
+        task: &Task,
         state_path: &Path,
-    ) -> Result<DownloadState> {
-        // 尝试加载已有状态
-        if let Some(state) = DownloadState::load(state_path).await?
-            && state.url == url
+        random_access: bool,
+        event_tx: &mpsc::Sender<ProgressEvent>,
+    ) -> Result<(DownloadState, bool)> {
+        // 尝试加载已有状态，分块状态不一致时重新下载
+        if random_access
+            && let Some(state) = DownloadState::load(state_path).await?
+            && task.owns_url(&state.url)
+            && state.issues().is_empty()
         {
-            return Ok(state);
+            if state.etag.is_none() && state.last_modified.is_none() {
+                return Ok((state, true));
+            }
+            let remote = self.refresh_probe(task).await?;
+            if !state.remote_changed(&remote) {
+                return Ok((state, true));
+            }
+            let _ = fs::remove_file(state_path).await;
+            let _ = event_tx
+                .send(ProgressEvent::Restarted {
+                    task_id: task.id.clone(),
+                    reason: RestartReason::RemoteChanged,
+                })
+                .await;
         }
 
         // 检查服务器是否支持 Range 请求和 Content-Length
-        let res = self.client.head(url).send().await?;
-        let total_size_opt = res
-            .headers()
-            .get(CONTENT_LENGTH)
-            .and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+        let RemoteInfo {
+            total_size: total_size_opt,
+            supports_range,
+            etag,
+            last_modified,
+            ..
+        } = self.probe_task(task).await?;
+        // 探测时记录了重定向后的地址，之后的请求直接发往那里
+        let url = self
+            .tasks
+            .read()
+            .await
+            .get(&task.id)
+            .and_then(|t| t.resolved_url.clone())
+            .unwrap_or_else(|| task.url.clone());
 
-        let supports_range = res
-            .headers()
-            .get("accept-ranges")
-            .map(|v| v.to_str().unwrap_or("").contains("bytes"))
-            .unwrap_or(false);
+        // 只下载一段时，后续的分块都在这一段内划分
+        let (offset, total_size_opt) = match task.byte_range {
+            Some(range) => {
+                let (offset, len) = byte_range_window(range, total_size_opt, supports_range)?;
+                (offset, Some(len))
+            }
+            None => (0, total_size_opt),
+        };
 
-        let use_streaming = total_size_opt.is_none() || !supports_range;
+        let single_connection = total_size_opt.is_some_and(|size| self.single_connection(size));
+        let use_streaming =
+            total_size_opt.is_none() || !supports_range || !random_access || single_connection;
 
         if use_streaming {
-            // 流式下载模式
-            return Ok(DownloadState {
+            // 流式下载模式，能随机写入的存储后端（本地文件）保存状态，暂停后从已写入的位置继续
+            let state = DownloadState {
                 url: url.to_string(),
                 total_size: total_size_opt,
                 chunks: Vec::new(),
                 is_streaming: true,
-            });
+                offset,
+                etag,
+                last_modified,
+                streamed: 0,
+                reported_prefix: 0,
+            };
+            // 小文件不保存状态，中断后从头下载
+            if random_access && !single_connection {
+                state.save(state_path).await?;
+            }
+            return Ok((state, false));
         }
 
         // 分块下载模式
         let total_size = total_size_opt.unwrap(); // 已经检查过存在
 
-        let file = fs::File::create(dest).await?;
-        file.set_len(total_size).await?;
-
-        let mut chunks = Vec::new();
-        let mut curr = 0;
-        let mut idx = 0;
-        while curr < total_size {
-            let end = (curr + self.config.chunk_size - 1).min(total_size - 1);
-            chunks.push(ChunkState {
-                index: idx,
-                start: curr,
-                end,
-                current: curr,
-                is_finished: false,
-            });
-            curr += self.config.chunk_size;
-            idx += 1;
-        }
-
         let state = DownloadState {
             url: url.to_string(),
             total_size: Some(total_size),
-            chunks,
+            chunks: self.chunk_layout(total_size),
             is_streaming: false,
+            offset,
+            etag,
+            last_modified,
+            streamed: 0,
+            reported_prefix: 0,
         };
         state.save(state_path).await?;
-        Ok(state)
+        Ok((state, false))
+    }
+
+    /// 任务的速度限制（字节/秒）：任务自己的限制，没有时使用 [`Config::speed_limit`]
+    fn speed_limit_for(&self, task: &Task) -> Option<u64> {
+        task.speed_limit
+            .or(self.config.speed_limit)
+            .filter(|&limit| limit > 0)
+    }
+
+    /// 按 [`YuShi::speed_limit_for`] 创建任务所有连接共用的限速器
+    async fn task_speed_limiter(&self, task_id: &str) -> Option<Arc<RwLock<SpeedLimiter>>> {
+        let limit = match self.tasks.read().await.get(task_id) {
+            Some(task) => self.speed_limit_for(task),
+            None => self.config.speed_limit,
+        };
+        limit.map(|limit| Arc::new(RwLock::new(SpeedLimiter::new(limit))))
+    }
+
+    /// 任务的加速截止时间，下载连接和 [`YuShi::boost_task`] 共用
+    fn boost_handle(&self, task_id: &str) -> Arc<AtomicU64> {
+        let mut boosts = self.boosts.lock().unwrap();
+        Arc::clone(boosts.entry(task_id.to_string()).or_default())
+    }
+
+    /// 创建带自定义头和 User-Agent 的 GET 请求
+    fn get_request(&self, url: &str) -> RequestBuilder {
+        self.task_request(url, &HashMap::new(), None)
+    }
+
+    /// 创建任务的 GET 请求，任务自己的请求头覆盖配置中的同名请求头
+    fn task_request(
+        &self,
+        url: &str,
+        task_headers: &HashMap<String, String>,
+        auth: Option<&Credential>,
+    ) -> RequestBuilder {
+        let mut request = self.client.get(url);
+
+        // 添加自定义头
+        for (key, value) in &self.request_headers(task_headers) {
+            request = request.header(key, value);
+        }
+
+        // 添加 User-Agent
+        if let Some(ua) = &self.user_agent_for(task_headers) {
+            request = request.header(USER_AGENT, ua);
+        }
+
+        self.authorize(request, url, task_headers, auth)
+    }
+
+    /// 配置中的请求头加上任务的请求头，名称相同（不区分大小写）时使用任务的
+    fn request_headers(&self, task_headers: &HashMap<String, String>) -> HashMap<String, String> {
+        let mut headers: HashMap<String, String> = self
+            .config
+            .headers
+            .iter()
+            .filter(|(key, _)| !has_header(task_headers, key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        headers.extend(task_headers.clone());
+        headers
+    }
+
+    /// 请求使用的 User-Agent，任务的请求头中有 `User-Agent` 时不再另外添加
+    fn user_agent_for(&self, task_headers: &HashMap<String, String>) -> Option<String> {
+        self.config
+            .user_agent
+            .clone()
+            .filter(|_| !has_header(task_headers, USER_AGENT.as_str()))
+    }
+
+    /// 请求 URL 时使用的认证信息，显式设置了 `Authorization` 头时不使用
+    ///
+    /// 依次使用任务自己的认证信息、按主机设置的认证信息和 [`Config::auth`]。
+    fn credential_for(
+        &self,
+        url: &str,
+        task_headers: &HashMap<String, String>,
+        auth: Option<&Credential>,
+    ) -> Option<Credential> {
+        let explicit = has_header(&self.config.headers, "authorization")
+            || has_header(task_headers, "authorization");
+        if explicit {
+            return None;
+        }
+        auth.cloned()
+            .or_else(|| self.credentials.lookup(url))
+            .or_else(|| self.config.auth.clone())
+    }
+
+    /// 给请求附加认证信息，选择规则见 `credential_for`
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        url: &str,
+        task_headers: &HashMap<String, String>,
+        auth: Option<&Credential>,
+    ) -> RequestBuilder {
+        match self.credential_for(url, task_headers, auth) {
+            Some(credential) => credential.apply(request),
+            None => request,
+        }
+    }
+
+    /// 探测远程资源的大小和 Range 支持情况
+    ///
+    /// HEAD 响应缺少 `Content-Length` 或 `Accept-Ranges` 时，再发送 `Range: bytes=0-0`
+    /// 的 GET 请求：206 响应的 `Content-Range` 同时给出总大小和 Range 支持；
+    /// 其他成功响应以 HEAD 的结果为准，错误响应返回 [`Error::HttpStatus`]。
+    pub async fn probe(&self, url: &str) -> Result<RemoteInfo> {
+        Ok(self.probe_with_headers(url, &HashMap::new(), None).await?.0)
+    }
+
+    /// 带任务请求头和认证信息的 [`YuShi::probe`]，同时返回跟随重定向后的地址
+    async fn probe_with_headers(
+        &self,
+        url: &str,
+        task_headers: &HashMap<String, String>,
+        auth: Option<&Credential>,
+    ) -> Result<(RemoteInfo, Url)> {
+        let mut request = self.client.head(url);
+        for (key, value) in &self.request_headers(task_headers) {
+            request = request.header(key, value);
+        }
+        if let Some(ua) = &self.user_agent_for(task_headers) {
+            request = request.header(USER_AGENT, ua);
+        }
+        let res = self
+            .authorize(request, url, task_headers, auth)
+            .send()
+            .await?;
+        let final_url = res.url().clone();
+        // HEAD 失败时不信任其中的头，完全依赖 Range 探测
+        let head_ok = res.status().is_success();
+        let total_size = res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok()?.parse::<u64>().ok())
+            .filter(|_| head_ok);
+
+        let supports_range = head_ok
+            && res
+                .headers()
+                .get("accept-ranges")
+                .map(|v| v.to_str().unwrap_or("").contains("bytes"))
+                .unwrap_or(false);
+
+        let (etag, last_modified) = if head_ok {
+            validators(res.headers())
+        } else {
+            (None, None)
+        };
+        let filename = suggested_filename(res.headers()).filter(|_| head_ok);
+
+        if (total_size.is_none() || !supports_range)
+            && let Some(mut info) = self.probe_range(url, task_headers, auth).await?
+        {
+            info.filename = info.filename.or(filename);
+            return Ok((info, final_url));
+        }
+
+        let info = RemoteInfo {
+            total_size,
+            supports_range,
+            etag,
+            last_modified,
+            filename,
+        };
+        Ok((info, final_url))
+    }
+
+    /// 用 `Range: bytes=0-0` 的 GET 请求探测，无法确定时返回 None
+    async fn probe_range(
+        &self,
+        url: &str,
+        task_headers: &HashMap<String, String>,
+        auth: Option<&Credential>,
+    ) -> Result<Option<RemoteInfo>> {
+        let res = self
+            .task_request(url, task_headers, auth)
+            .header(RANGE, "bytes=0-0")
+            .send()
+            .await?;
+        let status = res.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(Error::HttpStatus(status.as_u16()));
+        }
+        if status != StatusCode::PARTIAL_CONTENT {
+            return Ok(None);
+        }
+        let total_size = res
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| content_range_total(v.to_str().ok()?));
+        let (etag, last_modified) = validators(res.headers());
+        Ok(total_size.map(|total| RemoteInfo {
+            total_size: Some(total),
+            supports_range: true,
+            etag,
+            last_modified,
+            filename: suggested_filename(res.headers()),
+        }))
+    }
+
+    /// 探测任务的远程资源，结果缓存在任务上，重试时不再重复探测
+    async fn probe_task(&self, task: &Task) -> Result<RemoteInfo> {
+        if let Some(info) = self
+            .tasks
+            .read()
+            .await
+            .get(&task.id)
+            .and_then(|t| t.remote.clone())
+        {
+            return Ok(info);
+        }
+        self.refresh_probe(task).await
+    }
+
+    /// 重新探测任务的远程资源，替换任务上缓存的结果
+    async fn refresh_probe(&self, task: &Task) -> Result<RemoteInfo> {
+        let (info, final_url) = self
+            .probe_with_headers(&task.url, &task.headers, task.auth.as_ref())
+            .await?;
+        if let Some(task) = self.tasks.write().await.get_mut(&task.id) {
+            task.remote = Some(info.clone());
+            task.resolved_url = resolved_url(&task.url, &final_url);
+            // 尚未开始的任务也能在统计中按大小计算剩余字节
+            if let (0, Some(counters), Some(size)) = (
+                task.total_size,
+                self.progress.get(&task.id),
+                expected_size(task, Some(&info)),
+            ) {
+                counters.set_total(size);
+            }
+        }
+        self.store.mark_task(&task.id);
+        Ok(info)
+    }
+
+    // ==================== 测速 ====================
+
+    /// 测量到指定 URL 的下载带宽
+    ///
+    /// 在限定时间内下载数据并丢弃，不写入磁盘。服务器支持 Range 时依次测试
+    /// 1、4、8 个并发连接（平分测速时间），否则只测试单连接。
+    ///
+    /// # 参数
+    /// * `url` - 测速 URL
+    /// * `duration` - 测速总时长
+    pub async fn measure(&self, url: &str, duration: Duration) -> Result<MeasureReport> {
+        let info = self.probe(url).await?;
+        let levels: &[usize] = match info.total_size {
+            Some(size) if info.supports_range && size > 0 => &[1, 4, 8],
+            _ => &[1],
+        };
+        let slot = duration / levels.len() as u32;
+
+        let started = Instant::now();
+        let mut latencies = Vec::new();
+        let mut samples = Vec::new();
+        for &connections in levels {
+            let (sample, level_latencies) = self
+                .measure_connections(url, connections, info.total_size, slot)
+                .await?;
+            samples.push(sample);
+            latencies.extend(level_latencies);
+        }
+        let elapsed = started.elapsed();
+
+        let bytes = samples.iter().map(|s| s.bytes).sum();
+        latencies.sort();
+        let p95_latency = match latencies.len() {
+            0 => Duration::ZERO,
+            n => latencies[(n * 95).div_ceil(100) - 1],
+        };
+
+        Ok(MeasureReport {
+            bytes,
+            avg_speed: bytes_per_second(bytes, elapsed),
+            p95_latency,
+            supports_range: info.supports_range,
+            connections_tested: samples,
+        })
+    }
+
+    /// 使用指定数量的并发连接测速，返回结果和各请求的首字节延迟
+    ///
+    /// 多个连接时把文件平分为若干段，每个连接下载其中一段。
+    async fn measure_connections(
+        &self,
+        url: &str,
+        connections: usize,
+        total_size: Option<u64>,
+        slot: Duration,
+    ) -> Result<(ConnectionSample, Vec<Duration>)> {
+        let sink = Arc::new(NullSink::new());
+        let latencies = Mutex::new(Vec::new());
+
+        let workers = (0..connections).map(|i| {
+            let range = total_size.filter(|_| connections > 1).map(|size| {
+                let n = connections as u64;
+                let start = size * i as u64 / n;
+                let end = size * (i as u64 + 1) / n;
+                (start, end.saturating_sub(1).max(start))
+            });
+            let sink = Arc::clone(&sink);
+            let latencies = &latencies;
+            async move {
+                let mut request = self.get_request(url);
+                if let Some((start, end)) = range {
+                    request = request.header(RANGE, format!("bytes={}-{}", start, end));
+                }
+
+                let sent = Instant::now();
+                let response = request.send().await?;
+                latencies.lock().unwrap().push(sent.elapsed());
+                if !response.status().is_success() {
+                    return Err(Error::HttpStatus(response.status().as_u16()));
+                }
+
+                let mut offset = range.map(|(start, _)| start).unwrap_or(0);
+                let mut stream = response.bytes_stream();
+                while let Some(item) = stream.next().await {
+                    let data = item.map_err(|e| Error::StreamError(e.to_string()))?;
+                    sink.write_at(offset, &data).await?;
+                    offset += data.len() as u64;
+                }
+                Ok(())
+            }
+        });
+
+        let started = Instant::now();
+        // 超时即测速结束，未完成的连接随 Future 一起被丢弃
+        if let Ok(results) = tokio::time::timeout(slot, futures::future::join_all(workers)).await {
+            results.into_iter().collect::<Result<Vec<_>>>()?;
+        }
+        let elapsed = started.elapsed();
+
+        let bytes = sink.bytes();
+        let sample = ConnectionSample {
+            connections,
+            bytes,
+            speed: bytes_per_second(bytes, elapsed),
+        };
+        Ok((sample, latencies.into_inner().unwrap()))
     }
 
     // ==================== 队列管理方法 ====================
 
     /// 从持久化状态加载队列
+    ///
+    /// 读取主文件并应用增量日志，然后合并为新的主文件。
+    ///
+    /// 上次退出时校验没有完成的任务（`verification_pending` 为 true）不会直接视为完成：
+    /// [`Config::resume_interrupted_verification`] 为 true 时任务回到 `Pending`，
+    /// 启动后跳过下载、只重新校验；否则标记为失败。暂停的任务保持暂停。
+    ///
+    /// 归档超过 [`Config::archive_retention`] 的任务不再加载。
     pub async fn load_queue_from_state(&self) -> Result<()> {
-        if let Some(state) = QueueState::load(&self.queue_state_path).await? {
+        self.start_change_feed();
+        if let Some(state) = self.store.load().await? {
+            // 归档超过保留时间的任务不再加载，整理状态文件时一并删除
+            let cutoff = self
+                .config
+                .archive_retention
+                .map(|retention| current_timestamp().saturating_sub(retention.as_secs()));
             let mut tasks = self.tasks.write().await;
-            for task in state.tasks {
+            for mut task in state.tasks {
+                if task.archived
+                    && cutoff.is_some_and(|cutoff| task.archived_at.unwrap_or(0) <= cutoff)
+                {
+                    continue;
+                }
+                if task.verification_pending
+                    && matches!(
+                        task.status,
+                        TaskStatus::Downloading | TaskStatus::Verifying | TaskStatus::Completed
+                    )
+                {
+                    if self.config.resume_interrupted_verification {
+                        task.status = TaskStatus::Pending;
+                    } else {
+                        task.status = TaskStatus::Failed;
+                        task.verification_pending = false;
+                        task.error = Some(Error::VerificationCancelled.to_string());
+                    }
+                    task.verified = 0;
+                }
+                // 截止时间是绝对时间，没有到期的加速继续生效
+                match task.boost_until {
+                    Some(until) if until > current_timestamp_ms() => {
+                        self.arm_boost(&task.id, until);
+                    }
+                    _ => task.boost_until = None,
+                }
+                // 未完成任务的进度以断点续传状态文件为准
+                if !task.archived
+                    && task.status != TaskStatus::Completed
+                    && let Some(saved) = self.saved_progress(&task).await
+                {
+                    task.downloaded = saved;
+                }
+                if !task.archived {
+                    self.progress.sync(&task);
+                }
                 tasks.insert(task.id.clone(), task);
             }
+            let mut groups = self.groups.write().await;
+            for group in state.groups {
+                groups.insert(group.id.clone(), group);
+            }
         }
-        Ok(())
+        self.compact_queue_state().await
     }
 
     /// 保存队列状态
+    ///
+    /// 只把标记为已修改的任务和任务组追加到增量日志，没有修改时不写文件。距离上次写入
+    /// 不到 [`Config::queue_save_interval`] 时在后台等到间隔结束再写入，期间的修改一起写入，
+    /// 后台写入失败时保留修改标记，由下一次写入重试。
     async fn save_queue_state(&self) -> Result<()> {
+        if !self.store.is_persistent() {
+            return Ok(());
+        }
+        let Some(interval) = self.config.queue_save_interval else {
+            return self.flush_queue_state().await;
+        };
+        match self.store.schedule_flush(interval) {
+            FlushSlot::Now => self.flush_queue_state().await,
+            FlushSlot::After(delay) => {
+                let downloader = self.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    downloader.store.start_scheduled_flush();
+                    let _ = downloader.flush_queue_state().await;
+                });
+                Ok(())
+            }
+            FlushSlot::Scheduled => Ok(()),
+        }
+    }
+
+    /// 立即写入已标记的修改
+    async fn flush_queue_state(&self) -> Result<()> {
         let tasks = self.tasks.read().await;
-        let task_list: Vec<Task> = tasks.values().cloned().collect();
+        let groups = self.groups.read().await;
+        self.store.flush(&tasks, &groups).await
+    }
 
-        let state = QueueState {
-            version: "1.0".to_string(),
-            tasks: task_list,
-            created_at: current_timestamp(),
-            updated_at: current_timestamp(),
-        };
-        state.save(&self.queue_state_path).await?;
-        Ok(())
+    /// 等待已提交的审计日志记录全部写入文件
+    ///
+    /// 审计日志在后台写入，退出前调用可以避免丢失最后几条记录。
+    pub async fn flush_audit_log(&self) {
+        if let Some(audit) = &self.audit {
+            audit.flush().await;
+        }
+    }
+
+    /// 把增量日志合并到队列状态主文件
+    ///
+    /// 日志较长时会自动合并，退出前调用可以让下次启动时直接读取主文件。
+    pub async fn compact_queue_state(&self) -> Result<()> {
+        let tasks = self.tasks.read().await;
+        let groups = self.groups.read().await;
+        self.store.compact(&tasks, &groups).await
     }
 
     /// 添加下载任务到队列
     ///
     /// # 参数
     /// * `url` - 下载 URL
-    /// * `dest` - 目标文件路径
+    /// * `dest` - 目标文件路径，也可以是已存在的目录
     ///
     /// # 返回
     /// 返回任务 ID
@@ -555,7 +2254,7 @@ impl YuShi {
     ///
     /// # 参数
     /// * `url` - 下载 URL
-    /// * `dest` - 目标文件路径
+    /// * `dest` - 目标文件路径，也可以是已存在的目录
     /// * `priority` - 任务优先级
     /// * `checksum` - 文件校验（可选）
     /// * `auto_rename_on_conflict` - 是否自动重命名冲突文件
@@ -565,49 +2264,42 @@ impl YuShi {
     pub async fn add_task_with_options(
         &self,
         url: String,
-        mut dest: PathBuf,
+        dest: PathBuf,
         priority: TaskPriority,
         checksum: Option<ChecksumType>,
         auto_rename_on_conflict: bool,
     ) -> Result<String> {
-        // 自动重命名
-        if auto_rename_on_conflict && dest.exists() {
-            dest = auto_rename(&dest);
-        }
-
-        let task_id = Uuid::new_v4().to_string();
-
-        let task = Task {
-            id: task_id.clone(),
-            url,
-            dest,
-            status: TaskStatus::Pending,
-            total_size: 0,
-            downloaded: 0,
-            created_at: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            error: None,
+        let options = TaskOptions {
             priority,
-            speed: 0,
-            eta: None,
-            headers: HashMap::new(),
             checksum,
+            auto_rename: auto_rename_on_conflict,
+            ..Default::default()
         };
+        self.add_task_with(url, dest, options).await
+    }
 
-        {
-            let mut tasks = self.tasks.write().await;
-            tasks.insert(task_id.clone(), task);
-        }
-
-        self.save_queue_state().await?;
-        let _ = self
-            .queue_event_tx
-            .send(DownloaderEvent::Task(TaskEvent::Added {
-                task_id: task_id.clone(),
-            }))
-            .await;
+    /// 添加下载任务到队列（使用 [`TaskOptions`]）
+    ///
+    /// `dest` 是已存在的目录时，任务开始后先探测，文件名取 `Content-Disposition`
+    /// 建议的文件名或 URL 的最后一个路径段，同名文件已存在时自动重命名。写入第一个字节之前
+    /// 任务的 `dest` 就会更新为这个文件。
+    ///
+    /// # 参数
+    /// * `url` - 下载 URL
+    /// * `dest` - 目标文件路径，也可以是已存在的目录
+    /// * `options` - 任务选项
+    ///
+    /// # 返回
+    /// 返回任务 ID
+    pub async fn add_task_with(
+        &self,
+        url: String,
+        dest: PathBuf,
+        options: TaskOptions,
+    ) -> Result<String> {
+        let task_id = self.insert_task(url, dest, options, None).await;
+
+        self.save_queue_state().await?;
 
         // 尝试启动任务
         self.process_queue().await?;
@@ -615,28 +2307,371 @@ impl YuShi {
         Ok(task_id)
     }
 
+    /// 添加只校验本地文件的任务
+    ///
+    /// 任务不下载，开始后直接进入 `Verifying` 状态，与下载后的校验一样计算校验和并发送
+    /// [`VerificationEvent`]，结束后为 `Completed` 或 `Failed`。任务类型为
+    /// [`TaskKind::VerifyOnly`]，URL 是文件的 `file://` 地址。这类任务不能暂停；
+    /// 取消会中断校验，但不会删除文件。
+    ///
+    /// # 参数
+    /// * `path` - 要校验的文件，必须已存在
+    /// * `checksum` - 预期的校验和
+    /// * `priority` - 任务优先级
+    ///
+    /// # 返回
+    /// 返回任务 ID
+    pub async fn add_verify_task(
+        &self,
+        path: PathBuf,
+        checksum: ChecksumType,
+        priority: TaskPriority,
+    ) -> Result<String> {
+        if cfg!(not(feature = "checksums")) {
+            return Err(Error::ChecksumsDisabled);
+        }
+        let size = fs::metadata(&path).await?.len();
+        let path = std::path::absolute(&path)?;
+        let url = Url::from_file_path(&path)
+            .map(String::from)
+            .unwrap_or_else(|_| path.display().to_string());
+        let options = TaskOptions {
+            priority,
+            checksum: Some(checksum),
+            ..Default::default()
+        };
+        let task_id = self.insert_task(url, path, options, None).await;
+        if let Some(task) = self.tasks.write().await.get_mut(&task_id) {
+            task.kind = TaskKind::VerifyOnly;
+            task.total_size = size;
+            task.downloaded = size;
+            self.progress.sync(task);
+        }
+
+        self.save_queue_state().await?;
+        self.process_queue().await?;
+        Ok(task_id)
+    }
+
+    /// 从 HTTP 目录索引页（nginx、Apache 等生成的 autoindex）批量添加任务
+    ///
+    /// 读取 `url` 处的索引页，按 [`IndexOptions::depth`] 进入子目录，为每个满足包含和排除规则的
+    /// 文件添加一个任务，保存到 `dest_dir` 下相同的相对路径；其他主机上的文件保存在链接所在的目录。
+    /// 任何一页不是 HTML 或看起来是登录页时返回 [`Error::InvalidIndex`]，不会添加任何任务。
+    ///
+    /// # 返回
+    /// 返回添加的任务 ID，按索引页中出现的顺序排列
+    pub async fn add_from_index(
+        &self,
+        url: &str,
+        dest_dir: &Path,
+        options: IndexOptions,
+    ) -> Result<Vec<String>> {
+        let mut pages = VecDeque::from([(url.to_string(), 0)]);
+        let mut visited = HashSet::from([url.to_string()]);
+        let mut root: Option<Url> = None;
+        let mut seen = HashSet::new();
+        let mut files = Vec::new();
+        while let Some((page, depth)) = pages.pop_front() {
+            let (base, html) = self.fetch_index(&page).await?;
+            // 以重定向后的第一页所在目录为根，例如 `/pub` 会被重定向到 `/pub/`
+            let root = root.get_or_insert_with(|| base.join("./").unwrap_or_else(|_| base.clone()));
+            let Some(dir) = index::relative_segments(root, &base) else {
+                continue;
+            };
+            let links = index::parse_index(&html, &base);
+            for file in links.files {
+                let foreign = file.origin() != base.origin();
+                if foreign && options.same_host_only {
+                    continue;
+                }
+                let segments = if foreign {
+                    filename_from_url(file.as_str()).map(|name| {
+                        let mut segments = dir.clone();
+                        segments.push(name);
+                        segments
+                    })
+                } else {
+                    index::relative_segments(root, &file)
+                };
+                let Some(segments) = segments else {
+                    continue;
+                };
+                if index::selected(&options, &segments.join("/")) && seen.insert(file.clone()) {
+                    files.push((file, dest_dir.join(segments.iter().collect::<PathBuf>())));
+                }
+            }
+            if depth < options.depth {
+                for sub in links.dirs {
+                    if visited.insert(sub.to_string()) {
+                        pages.push_back((sub.to_string(), depth + 1));
+                    }
+                }
+            }
+        }
+
+        let mut task_ids = Vec::with_capacity(files.len());
+        for (file, dest) in files {
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            task_ids.push(
+                self.insert_task(file.to_string(), dest, TaskOptions::default(), None)
+                    .await,
+            );
+        }
+        self.save_queue_state().await?;
+        self.process_queue().await?;
+        Ok(task_ids)
+    }
+
+    /// 获取目录索引页，返回重定向后的地址和页面内容
+    async fn fetch_index(&self, url: &str) -> Result<(Url, String)> {
+        let response = self.get_request(url).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus(response.status().as_u16()));
+        }
+        let base = response.url().clone();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let mime = content_type.to_ascii_lowercase();
+        if !mime.starts_with("text/html") && !mime.starts_with("application/xhtml+xml") {
+            return Err(Error::InvalidIndex(message(
+                "index.not_html",
+                &[&redact_url(base.as_str()), &content_type],
+            )));
+        }
+        let html = response.text().await?;
+        if index::looks_like_login(&html) {
+            return Err(Error::InvalidIndex(message(
+                "index.login_page",
+                &[&redact_url(base.as_str())],
+            )));
+        }
+        Ok((base, html))
+    }
+
+    /// 生成任务计划，不创建任务也不写入文件
+    ///
+    /// 探测 URL（HEAD，必要时 Range 探测），按 `options` 处理目标路径冲突，
+    /// 并给出是否分块下载、分块数和连接数。探测失败时返回错误。
+    pub async fn plan_task(
+        &self,
+        url: &str,
+        dest: &Path,
+        options: &TaskOptions,
+    ) -> Result<TaskPlan> {
+        let case_insensitive = self.case_insensitive(dest);
+        let same_path = |path: &Path| {
+            if case_insensitive {
+                path.to_string_lossy().to_lowercase() == dest.to_string_lossy().to_lowercase()
+            } else {
+                path == dest
+            }
+        };
+        let queued = self
+            .tasks
+            .read()
+            .await
+            .values()
+            .find(|t| same_path(&t.dest))
+            .map(|t| t.id.clone());
+        let conflict = match queued {
+            Some(task_id) => Some(PlanConflict::QueuedTask { task_id }),
+            None => path_taken(dest, case_insensitive).then_some(PlanConflict::FileExists),
+        };
+
+        let (remote, _) = self
+            .probe_with_headers(url, &options.headers, options.auth.as_ref())
+            .await?;
+        let total_size = match options.byte_range {
+            Some(range) => {
+                Some(byte_range_window(range, remote.total_size, remote.supports_range)?.1)
+            }
+            None => remote.total_size,
+        };
+        let random_access = options
+            .sink
+            .as_ref()
+            .is_none_or(|sink| sink.supports_random_access());
+        let chunked = random_access
+            && remote.supports_range
+            && total_size.is_some_and(|size| !self.single_connection(size));
+        let chunks = match total_size {
+            Some(size) if chunked => self.chunk_layout(size).len(),
+            _ => 0,
+        };
+
+        Ok(TaskPlan {
+            url: url.to_string(),
+            dest: resolve_dest(
+                dest.to_path_buf(),
+                options.conflict_policy(),
+                case_insensitive,
+                self.part_suffix(),
+            ),
+            conflict,
+            remote,
+            chunked,
+            chunks,
+            connections: if chunked {
+                self.default_connections()
+            } else {
+                1
+            },
+        })
+    }
+
+    /// 创建任务并加入任务表（不保存状态、不启动）
+    async fn insert_task(
+        &self,
+        url: String,
+        dest: PathBuf,
+        options: TaskOptions,
+        group_id: Option<String>,
+    ) -> String {
+        let conflict = options.conflict_policy();
+        let TaskOptions {
+            priority,
+            checksum,
+            sink,
+            byte_range,
+            display_name,
+            note,
+            skip_probe,
+            connections,
+            chunk_order,
+            url_refresher,
+            headers,
+            auth,
+            speed_limit,
+            deadline,
+            mirrors,
+            ..
+        } = options;
+
+        // 目标是目录时，文件名在开始下载时才能确定，见 YuShi::resolve_directory_dest
+        let dest = if dest.is_dir() {
+            dest
+        } else {
+            let case_insensitive = self.case_insensitive(&dest);
+            resolve_dest(dest, conflict, case_insensitive, self.part_suffix())
+        };
+        self.start_change_feed();
+
+        let task_id = new_id();
+
+        let mut task = Task {
+            id: task_id.clone(),
+            url,
+            dest,
+            status: TaskStatus::Pending,
+            total_size: 0,
+            downloaded: 0,
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            error: None,
+            priority,
+            speed: 0,
+            eta: None,
+            active_connections: 0,
+            headers,
+            auth,
+            checksum,
+            group_id,
+            verified: 0,
+            verification_pending: false,
+            remote: None,
+            connections,
+            byte_range,
+            display_name,
+            note,
+            skip_probe,
+            chunk_order: Some(chunk_order.unwrap_or(self.default_chunk_order())),
+            conflict,
+            skipped_unchanged: false,
+            archived: false,
+            archived_at: None,
+            provenance: None,
+            contiguous_prefix: 0,
+            active_duration_ms: 0,
+            peak_speed: 0,
+            average_speed: 0,
+            kind: TaskKind::Download,
+            missing_ranges: Vec::new(),
+            recent_events: VecDeque::new(),
+            completed_link: None,
+            speed_limit,
+            boost_until: None,
+            deadline_ms: deadline.map(|deadline| deadline.as_millis() as u64),
+            resolved_url: None,
+            mirrors,
+        };
+        self.journal(&mut task, CompactEventKind::Added);
+
+        if let Some(sink) = sink {
+            self.sinks.write().await.insert(task_id.clone(), sink);
+        }
+        if let Some(refresher) = url_refresher {
+            self.url_refreshers
+                .write()
+                .await
+                .insert(task_id.clone(), refresher);
+        }
+
+        self.progress.sync(&task);
+        {
+            let mut tasks = self.tasks.write().await;
+            tasks.insert(task_id.clone(), task);
+        }
+        self.store.mark_task(&task_id);
+
+        let _ = self
+            .queue_event_tx
+            .send(DownloaderEvent::Task(TaskEvent::Added {
+                task_id: task_id.clone(),
+            }));
+
+        task_id
+    }
+
+    /// 装箱的 [`YuShi::process_queue`]，供任务结束时在后台任务中调用
+    ///
+    /// 后台任务由 `process_queue` 启动，直接调用会形成递归的 Future 类型。
+    fn process_queue_boxed(&self) -> Pin<Box<dyn Future<Output = Result<()>> + Send + '_>> {
+        Box::pin(self.process_queue())
+    }
+
     /// 处理队列，启动待处理的任务（按优先级排序）
     async fn process_queue(&self) -> Result<()> {
+        if !self.scheduler.lock().unwrap().running {
+            return Ok(());
+        }
+        // 任务先标记为下载中、之后才登记到 active_downloads，并发调用各自计数会超过上限
+        let _guard = self.queue_lock.lock().await;
         let active_count = self.active_downloads.read().await.len();
-        if active_count >= self.max_concurrent_tasks {
+        let max_concurrent_tasks = self.max_concurrent_tasks();
+        if active_count >= max_concurrent_tasks {
             return Ok(());
         }
 
-        let mut pending_tasks: Vec<(String, TaskPriority)> = {
+        let pending_tasks: Vec<String> = {
             let tasks = self.tasks.read().await;
-            tasks
-                .values()
-                .filter(|t| t.status == TaskStatus::Pending)
-                .map(|t| (t.id.clone(), t.priority))
+            schedule::start_order(tasks.values().filter(|t| t.status == TaskStatus::Pending))
+                .into_iter()
+                .map(|t| t.id.clone())
                 .collect()
         };
 
-        // 按优先级排序（高优先级在前）
-        pending_tasks.sort_by(|a, b| b.1.cmp(&a.1));
-
-        for (task_id, _) in pending_tasks
+        for task_id in pending_tasks
             .iter()
-            .take(self.max_concurrent_tasks - active_count)
+            .take(max_concurrent_tasks - active_count)
         {
             self.start_queue_task(task_id).await?;
         }
@@ -646,6 +2681,9 @@ impl YuShi {
 
     /// 启动单个队列任务
     async fn start_queue_task(&self, task_id: &str) -> Result<()> {
+        if self.scheduler.lock().unwrap().reason == Some(PausedReason::Shutdown) {
+            return Err(Error::ShutDown);
+        }
         let task = {
             let mut tasks = self.tasks.write().await;
             let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
@@ -655,233 +2693,639 @@ impl YuShi {
             }
 
             task.status = TaskStatus::Downloading;
+            // 由这次下载重新记录
+            task.missing_ranges.clear();
+            self.journal(task, CompactEventKind::Started);
+            if task.provenance.is_none() {
+                task.provenance = Some(self.capture_provenance(task));
+            }
+            self.progress.sync(task);
             task.clone()
         };
+        // 续传时从状态文件中的进度开始，探测期间界面不会显示为 0
+        let resumed = self.saved_progress(&task).await;
+        if let Some(saved) = resumed
+            && let Some(task) = self.tasks.write().await.get_mut(task_id)
+        {
+            task.downloaded = saved;
+            self.progress.sync(task);
+        }
+        let resumed = resumed.unwrap_or(task.downloaded);
+        self.run_started
+            .lock()
+            .unwrap()
+            .insert(task_id.to_string(), Instant::now());
 
+        self.store.mark_task(task_id);
         self.save_queue_state().await?;
         let _ = self
             .queue_event_tx
             .send(DownloaderEvent::Task(TaskEvent::Started {
                 task_id: task_id.to_string(),
-            }))
-            .await;
+            }));
 
         let downloader = self.clone();
         let tasks = Arc::clone(&self.tasks);
         let active_downloads = Arc::clone(&self.active_downloads);
         let queue_event_tx = self.queue_event_tx.clone();
         let task_id_owned = task_id.to_string();
-        let queue_state_path = self.queue_state_path.clone();
         let on_complete = self.on_complete.clone();
+        let cancel = CancellationToken::new();
+        self.cancel_tokens
+            .write()
+            .await
+            .insert(task_id.to_string(), cancel.clone());
+        let connections = task.connections.unwrap_or(self.default_connections());
+        self.connection_limits.write().await.insert(
+            task_id.to_string(),
+            Arc::new(ConnectionLimiter::new(connections)),
+        );
 
+        let supervisor = self.clone();
+        let supervised_id = task_id.to_string();
+        // 先持有写锁，任务很快结束时也要等登记之后才能把自己移除
+        let mut active = self.active_downloads.write().await;
         let handle = tokio::spawn(async move {
-            let (tx, mut rx) = mpsc::channel(1024);
-            let task_id_clone = task_id_owned.clone();
-            let queue_event_tx_clone = queue_event_tx.clone();
-            let tasks_clone = Arc::clone(&tasks);
+            let run = AssertUnwindSafe(async move {
+                let (tx, mut rx) = mpsc::channel::<ProgressEvent>(1024);
+                let task_id_clone = task_id_owned.clone();
+                let queue_event_tx_clone = queue_event_tx.clone();
+                let tasks_clone = Arc::clone(&tasks);
+                let counters_clone = downloader.progress.get(&task_id_owned);
+                let journal = downloader.config.event_journal;
+                let tap = downloader
+                    .progress_taps
+                    .read()
+                    .await
+                    .get(&task_id_owned)
+                    .cloned();
 
-            // 进度监听器
-            tokio::spawn(async move {
-                let mut total = 0u64;
-                let mut downloaded = 0u64;
-                let mut speed_calc = SpeedCalculator::new();
-
-                while let Some(event) = rx.recv().await {
-                    match event {
-                        ProgressEvent::Initialized { total_size, .. } => {
-                            if let Some(size) = total_size {
-                                total = size;
-                            }
-                            let mut tasks = tasks_clone.write().await;
-                            if let Some(task) = tasks.get_mut(&task_id_clone) {
-                                task.total_size = total_size.unwrap_or(0);
-                            }
-                        }
-                        ProgressEvent::ChunkDownloading { delta, .. } => {
-                            downloaded += delta;
-
-                            // 更新速度统计
-                            let speed = speed_calc.update(downloaded);
-                            let eta = if total > 0 {
-                                speed_calc.calculate_eta(downloaded, total)
-                            } else {
-                                None
-                            };
-
-                            let mut tasks = tasks_clone.write().await;
-                            if let Some(task) = tasks.get_mut(&task_id_clone) {
-                                task.downloaded = downloaded;
-                                task.speed = speed;
-                                task.eta = eta;
-                            }
+                // 进度监听器
+                let mut previous_active = Duration::from_millis(task.active_duration_ms);
+                let mut peak = task.peak_speed;
+                tokio::spawn(async move {
+                    let mut total = 0u64;
+                    let mut downloaded = resumed;
+                    let mut prefix = 0u64;
+                    // 已有的数据不计入速度
+                    let mut speed_calc = SpeedCalculator::starting_at(resumed);
+                    // 每次运行单独计时，暂停期间没有运行，不计入下载时长
+                    let mut run_started = Instant::now();
 
-                            let _ = queue_event_tx_clone
-                                .send(DownloaderEvent::Progress(ProgressEvent::Updated {
-                                    task_id: task_id_clone.clone(),
-                                    downloaded,
-                                    total,
-                                    speed,
-                                    eta,
-                                }))
-                                .await;
+                    while let Some(event) = rx.recv().await {
+                        if let Some(tap) = &tap {
+                            let _ = tap.send(event.clone()).await;
                         }
-                        ProgressEvent::StreamDownloading {
-                            downloaded: stream_downloaded,
-                        } => {
-                            downloaded = stream_downloaded;
-
-                            // 更新速度统计
-                            let speed = speed_calc.update(downloaded);
-
-                            let mut tasks = tasks_clone.write().await;
-                            if let Some(task) = tasks.get_mut(&task_id_clone) {
-                                task.downloaded = downloaded;
-                                task.speed = speed;
-                                task.eta = None; // 流式下载无法预估剩余时间
+                        match event {
+                            ProgressEvent::Initialized {
+                                total_size,
+                                resumed_from,
+                                ..
+                            } => {
+                                if let Some(size) = total_size {
+                                    total = size;
+                                }
+                                // 断点续传时从已下载的位置开始计数
+                                downloaded = resumed_from;
+                                prefix = 0;
+                                // 从头重新下载时，之前的下载时长和峰值不再对应已下载的数据
+                                if resumed_from == 0 {
+                                    previous_active = Duration::ZERO;
+                                    run_started = Instant::now();
+                                    peak = 0;
+                                }
+                                speed_calc = SpeedCalculator::starting_at(downloaded);
+                                if let Some(counters) = &counters_clone {
+                                    counters.set_total(total);
+                                    counters.set_downloaded(downloaded, 0, None);
+                                }
+                                let mut tasks = tasks_clone.write().await;
+                                if let Some(task) = tasks.get_mut(&task_id_clone) {
+                                    task.total_size = total_size.unwrap_or(0);
+                                    task.downloaded = downloaded;
+                                    task.contiguous_prefix = 0;
+                                }
                             }
+                            ProgressEvent::ChunkProgress { delta, .. }
+                            | ProgressEvent::ChunkDownloading { delta, .. } => {
+                                downloaded += delta;
 
-                            let _ = queue_event_tx_clone
-                                .send(DownloaderEvent::Progress(ProgressEvent::Updated {
-                                    task_id: task_id_clone.clone(),
-                                    downloaded,
-                                    total: 0, // 流式下载时 total 为 0
-                                    speed,
-                                    eta: None,
-                                }))
-                                .await;
-                        }
-                        ProgressEvent::Finished { .. } => {}
-                        ProgressEvent::Failed { .. } => {}
-                        ProgressEvent::Updated { .. } => {}
-                        ProgressEvent::ChunkProgress { .. } => {}
-                        ProgressEvent::StreamProgress { .. } => {}
-                    }
-                }
-            });
+                                // 更新速度统计
+                                let speed = speed_calc.update(downloaded);
+                                peak = peak.max(speed_calc.peak());
+                                let eta = if total > 0 {
+                                    speed_calc.calculate_eta(downloaded, total)
+                                } else {
+                                    None
+                                };
+                                if let Some(counters) = &counters_clone {
+                                    counters.set_downloaded(downloaded, speed, eta);
+                                }
 
-            // 执行下载
-            let result = downloader
-                .download_internal(&task.url, task.dest.to_str().unwrap(), tx)
-                .await;
+                                let mut tasks = tasks_clone.write().await;
+                                if let Some(task) = tasks.get_mut(&task_id_clone) {
+                                    task.downloaded = downloaded;
+                                    task.speed = speed;
+                                    task.eta = eta;
+                                    task.peak_speed = peak;
+                                    task.active_duration_ms =
+                                        (previous_active + run_started.elapsed()).as_millis()
+                                            as u64;
+                                }
 
-            // 文件校验
-            let checksum = task.checksum.clone();
-            let dest_path = task.dest.clone();
-            let verify_result = if result.is_ok() {
-                if let Some(checksum_value) = checksum {
-                    let _ = queue_event_tx
-                        .send(DownloaderEvent::Verification(VerificationEvent::Started {
-                            task_id: task_id_owned.clone(),
-                        }))
-                        .await;
+                                let _ = queue_event_tx_clone.send(DownloaderEvent::Progress(
+                                    ProgressEvent::Updated {
+                                        task_id: task_id_clone.clone(),
+                                        downloaded,
+                                        total,
+                                        speed,
+                                        eta,
+                                        contiguous_prefix: prefix,
+                                    },
+                                ));
+                            }
+                            ProgressEvent::StreamProgress {
+                                downloaded: stream_downloaded,
+                                ..
+                            }
+                            | ProgressEvent::StreamDownloading {
+                                downloaded: stream_downloaded,
+                            } => {
+                                downloaded = stream_downloaded;
+
+                                // 更新速度统计
+                                let speed = speed_calc.update(downloaded);
+                                peak = peak.max(speed_calc.peak());
+                                if let Some(counters) = &counters_clone {
+                                    counters.set_downloaded(downloaded, speed, None);
+                                }
 
-                    match verify_file(&dest_path, &checksum_value).await {
-                        Ok(success) => {
-                            let _ = queue_event_tx
-                                .send(DownloaderEvent::Verification(
-                                    VerificationEvent::Completed {
-                                        task_id: task_id_owned.clone(),
-                                        success,
+                                let mut tasks = tasks_clone.write().await;
+                                if let Some(task) = tasks.get_mut(&task_id_clone) {
+                                    task.downloaded = downloaded;
+                                    task.speed = speed;
+                                    task.eta = None; // 流式下载无法预估剩余时间
+                                    task.peak_speed = peak;
+                                    task.active_duration_ms =
+                                        (previous_active + run_started.elapsed()).as_millis()
+                                            as u64;
+                                }
+
+                                let _ = queue_event_tx_clone.send(DownloaderEvent::Progress(
+                                    ProgressEvent::Updated {
+                                        task_id: task_id_clone.clone(),
+                                        downloaded,
+                                        total: 0, // 流式下载时 total 为 0
+                                        speed,
+                                        eta: None,
+                                        contiguous_prefix: prefix,
                                     },
-                                ))
-                                .await;
-                            if success {
-                                Ok(())
-                            } else {
-                                Err(Error::ChecksumVerificationFailed)
+                                ));
+                            }
+                            ProgressEvent::PrefixFlushed {
+                                contiguous_prefix, ..
+                            } => {
+                                // 多个分块并发报告时可能乱序到达
+                                prefix = prefix.max(contiguous_prefix);
+                                let mut tasks = tasks_clone.write().await;
+                                if let Some(task) = tasks.get_mut(&task_id_clone) {
+                                    task.contiguous_prefix = prefix;
+                                }
+                            }
+                            ProgressEvent::Retrying {
+                                attempt, reason, ..
+                            } => {
+                                if journal {
+                                    let mut tasks = tasks_clone.write().await;
+                                    if let Some(task) = tasks.get_mut(&task_id_clone) {
+                                        task.record_event(CompactEventKind::Retrying {
+                                            attempt,
+                                            reason,
+                                        });
+                                    }
+                                }
+                                let _ = queue_event_tx_clone.send(DownloaderEvent::Progress(event));
+                            }
+                            ProgressEvent::Restarted { reason, .. } => {
+                                if journal {
+                                    let mut tasks = tasks_clone.write().await;
+                                    if let Some(task) = tasks.get_mut(&task_id_clone) {
+                                        task.record_event(CompactEventKind::Restarted { reason });
+                                    }
+                                }
+                                let _ = queue_event_tx_clone.send(DownloaderEvent::Progress(event));
                             }
+                            ProgressEvent::MirrorDemoted {
+                                ref mirror, reason, ..
+                            } => {
+                                if journal {
+                                    let mut tasks = tasks_clone.write().await;
+                                    if let Some(task) = tasks.get_mut(&task_id_clone) {
+                                        task.record_event(CompactEventKind::MirrorDemoted {
+                                            mirror: mirror.clone(),
+                                            reason,
+                                        });
+                                    }
+                                }
+                                let _ = queue_event_tx_clone.send(DownloaderEvent::Progress(event));
+                            }
+                            ProgressEvent::Finalizing { .. }
+                            | ProgressEvent::ChunkFinished { .. } => {
+                                let _ = queue_event_tx_clone.send(DownloaderEvent::Progress(event));
+                            }
+                            ProgressEvent::Finished { .. } => {}
+                            ProgressEvent::Failed { .. } => {}
+                            ProgressEvent::Updated { .. } => {}
                         }
-                        Err(e) => Err(e),
                     }
+                });
+
+                // 目标是目录时先确定文件名，之后的写入和状态文件都使用这个文件
+                let (task, resolved) = match downloader.resolve_directory_dest(&task).await {
+                    Ok(dest) => (Task { dest, ..task }, Ok(())),
+                    Err(e) => (task, Err(e)),
+                };
+                let sink = downloader.task_sink(&task).await;
+
+                let verify_only = task.kind == TaskKind::VerifyOnly;
+                // 远程文件没有变化时不下载，直接完成
+                let unchanged = match task.conflict {
+                    ConflictPolicy::IfChanged
+                        if resolved.is_ok() && !task.verification_pending && !verify_only =>
+                    {
+                        downloader.check_unchanged(&task).await
+                    }
+                    _ => None,
+                };
+                let skipped = unchanged.is_some();
+
+                // 执行下载（下载已完成、只差校验或只校验本地文件时跳过）
+                let result = if resolved.is_err() {
+                    resolved
+                } else if task.verification_pending || skipped || verify_only {
+                    Ok(())
                 } else {
+                    let download =
+                        downloader.download_watched(&task, Arc::clone(&sink), tx.clone());
+                    // 超时后丢弃下载，分块连接随之中断，状态文件保留以便续传
+                    let result = match task.deadline_ms {
+                        Some(ms) => tokio::time::timeout(Duration::from_millis(ms), download)
+                            .await
+                            .unwrap_or(Err(Error::TaskTimedOut)),
+                        None => download.await,
+                    };
+                    // 停滞重试会重新运行下载，完成事件在这里统一发送，保证只发送一次
+                    if result.is_ok() {
+                        let _ = tx
+                            .send(ProgressEvent::Finished {
+                                task_id: task.id.clone(),
+                            })
+                            .await;
+                    }
                     result
-                }
-            } else {
-                result
-            };
+                };
 
-            // 更新任务状态并调用回调
-            let callback_result = match &verify_result {
-                Ok(_) => Ok(()),
-                Err(e) => Err(e.to_string()),
-            };
+                // 有空洞的文件不校验也不移动，保留状态文件以便重试时只下载缺失的范围
+                let missing_ranges = match &result {
+                    Ok(()) => tasks
+                        .read()
+                        .await
+                        .get(&task_id_owned)
+                        .map(|t| t.missing_ranges.clone())
+                        .unwrap_or_default(),
+                    Err(_) => Vec::new(),
+                };
+                let complete = !skipped && missing_ranges.is_empty();
 
-            let mut tasks = tasks.write().await;
-            if let Some(task) = tasks.get_mut(&task_id_owned) {
-                match verify_result {
-                    Ok(_) => {
-                        task.status = TaskStatus::Completed;
-                        let _ = queue_event_tx
-                            .send(DownloaderEvent::Task(TaskEvent::Completed {
-                                task_id: task_id_owned.clone(),
-                            }))
-                            .await;
+                // 文件校验
+                let verify_result = match (result, &task.checksum) {
+                    (Ok(()), Some(checksum)) if complete => {
+                        downloader.verify_task(&task, sink, checksum, &cancel).await
                     }
-                    Err(e) => {
-                        task.status = TaskStatus::Failed;
-                        task.error = Some(e.to_string());
-                        let _ = queue_event_tx
-                            .send(DownloaderEvent::Task(TaskEvent::Failed {
+                    (result, _) => result,
+                };
+                // 从临时目录移动到目标位置
+                let verify_result = match verify_result {
+                    Ok(()) if complete => downloader.finalize_task_file(&task, &tx).await,
+                    result => result,
+                };
+                drop(tx);
+                let mut completed_link = None;
+                if verify_result.is_ok() && complete && !verify_only {
+                    let _ = fs::remove_file(downloader.state_path(&task)).await;
+                    if task.conflict == ConflictPolicy::IfChanged {
+                        downloader.save_validators(&task.id).await;
+                    }
+                    completed_link = downloader.link_completed(&task).await;
+                }
+                let size = match skipped {
+                    true => fs::metadata(&task.dest).await.map_or(0, |m| m.len()),
+                    false => 0,
+                };
+
+                // 下载或校验被暂停或取消打断，任务状态由 pause_task / cancel_task 设置
+                if matches!(
+                    verify_result,
+                    Err(Error::VerificationCancelled | Error::TaskCancelled)
+                ) {
+                    downloader
+                        .connection_limits
+                        .write()
+                        .await
+                        .remove(&task_id_owned);
+                    downloader
+                        .run_started
+                        .lock()
+                        .unwrap()
+                        .remove(&task_id_owned);
+                    return;
+                }
+
+                let incomplete = message("download.incomplete", &[&missing_ranges.len()]);
+                // 更新任务状态并调用回调
+                let callback_result = match &verify_result {
+                    Ok(_) if !missing_ranges.is_empty() => Err(incomplete.clone()),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e.to_string()),
+                };
+
+                let mut tasks_guard = tasks.write().await;
+                let group_id = tasks_guard
+                    .get(&task_id_owned)
+                    .and_then(|t| t.group_id.clone());
+                if let Some(task) = tasks_guard.get_mut(&task_id_owned) {
+                    match verify_result {
+                        Ok(_) if !missing_ranges.is_empty() => {
+                            task.status = TaskStatus::PartiallyCompleted;
+                            task.error = Some(incomplete);
+                            task.average_speed = task.compute_average_speed();
+                            downloader.journal(
+                                task,
+                                CompactEventKind::PartiallyCompleted {
+                                    missing_ranges: missing_ranges.len(),
+                                },
+                            );
+                            let _ = queue_event_tx.send(DownloaderEvent::Task(
+                                TaskEvent::PartiallyCompleted {
+                                    task_id: task_id_owned.clone(),
+                                    missing_ranges,
+                                },
+                            ));
+                        }
+                        Ok(_) => {
+                            task.status = TaskStatus::Completed;
+                            task.completed_link = completed_link;
+                            if let Some(previous) = unchanged {
+                                task.skipped_unchanged = true;
+                                task.remote = Some(previous);
+                                task.total_size = size;
+                                task.downloaded = size;
+                            }
+                            task.average_speed = task.compute_average_speed();
+                            downloader.journal(
+                                task,
+                                CompactEventKind::Completed {
+                                    skipped_unchanged: skipped,
+                                },
+                            );
+                            let _ =
+                                queue_event_tx.send(DownloaderEvent::Task(TaskEvent::Completed {
+                                    task_id: task_id_owned.clone(),
+                                    skipped_unchanged: skipped,
+                                    average_speed: task.average_speed,
+                                    peak_speed: task.peak_speed,
+                                }));
+                        }
+                        Err(e) => {
+                            task.status = TaskStatus::Failed;
+                            task.error = Some(e.to_string());
+                            task.average_speed = task.compute_average_speed();
+                            downloader.journal(
+                                task,
+                                CompactEventKind::Failed {
+                                    error: e.to_string(),
+                                },
+                            );
+                            let _ = queue_event_tx.send(DownloaderEvent::Task(TaskEvent::Failed {
                                 task_id: task_id_owned.clone(),
                                 error: e.to_string(),
-                            }))
-                            .await;
+                            }));
+                        }
                     }
+                    downloader.progress.sync(task);
+                    downloader.audit(task);
+                }
+
+                drop(tasks_guard);
+
+                // 保存状态
+                downloader.store.mark_task(&task_id_owned);
+                let _ = downloader.save_queue_state().await;
+
+                if let Some(group_id) = group_id {
+                    downloader.check_group_completed(&group_id).await;
                 }
+
+                // 调用完成回调
+                if let Some(callback) = on_complete {
+                    callback(task_id_owned.clone(), callback_result).await;
+                }
+
+                // 从活动下载中移除
+                active_downloads.write().await.remove(&task_id_owned);
+                downloader
+                    .cancel_tokens
+                    .write()
+                    .await
+                    .remove(&task_id_owned);
+                downloader
+                    .connection_limits
+                    .write()
+                    .await
+                    .remove(&task_id_owned);
+            });
+            // panic 时任务不会自行结束，在这里标记为失败并释放占用的并发名额
+            if let Err(panic) = run.catch_unwind().await {
+                supervisor.task_panicked(&supervised_id, panic).await;
             }
+            // 并发名额已经释放，启动下一个等待中的任务
+            let _ = supervisor.process_queue_boxed().await;
+        });
+        active.insert(task_id.to_string(), handle);
+
+        Ok(())
+    }
 
-            // 保存状态
-            let task_list: Vec<Task> = tasks.values().cloned().collect();
-            let state = QueueState {
-                version: "1.0".to_string(),
-                tasks: task_list,
-                created_at: current_timestamp(),
-                updated_at: current_timestamp(),
+    /// 任务的后台运行 panic 后的收尾：标记为失败并释放占用的并发名额
+    async fn task_panicked(&self, task_id: &str, panic: Box<dyn Any + Send>) {
+        let detail = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        let error = message("download.panicked", &[&detail]);
+        let group_id = {
+            let mut tasks = self.tasks.write().await;
+            let Some(task) = tasks.get_mut(task_id) else {
+                return;
             };
-            if let Ok(data) = serde_json::to_string_pretty(&state) {
-                let _ = fs::write(&queue_state_path, data).await;
-            }
+            task.status = TaskStatus::Failed;
+            task.error = Some(error.clone());
+            task.average_speed = task.compute_average_speed();
+            self.journal(
+                task,
+                CompactEventKind::Failed {
+                    error: error.clone(),
+                },
+            );
+            self.progress.sync(task);
+            self.audit(task);
+            task.group_id.clone()
+        };
+        let _ = self
+            .queue_event_tx
+            .send(DownloaderEvent::Task(TaskEvent::Failed {
+                task_id: task_id.to_string(),
+                error: error.clone(),
+            }));
+        self.store.mark_task(task_id);
+        let _ = self.save_queue_state().await;
+        if let Some(group_id) = group_id {
+            self.check_group_completed(&group_id).await;
+        }
+        if let Some(callback) = &self.on_complete {
+            callback(task_id.to_string(), Err(error)).await;
+        }
 
-            // 调用完成回调
-            if let Some(callback) = on_complete {
-                callback(task_id_owned.clone(), callback_result).await;
-            }
+        self.active_downloads.write().await.remove(task_id);
+        self.cancel_tokens.write().await.remove(task_id);
+        self.connection_limits.write().await.remove(task_id);
+    }
 
-            // 从活动下载中移除
-            active_downloads.write().await.remove(&task_id_owned);
-        });
+    /// 通知任务的下载停止并等待它退出
+    ///
+    /// 下载写完当前的数据、保存分块状态后退出，超过 [`STOP_TIMEOUT`] 仍未退出时直接中断。
+    /// 被中断的下载来不及收尾，由这里释放它占用的资源并启动下一个等待中的任务。
+    async fn stop_download(&self, task_id: &str) {
+        let handle = self.active_downloads.write().await.remove(task_id);
+        if let Some(token) = self.cancel_tokens.read().await.get(task_id) {
+            token.cancel();
+        }
+        if let Some(mut handle) = handle
+            && tokio::time::timeout(STOP_TIMEOUT, &mut handle)
+                .await
+                .is_err()
+        {
+            handle.abort();
+            self.connection_limits.write().await.remove(task_id);
+            self.run_started.lock().unwrap().remove(task_id);
+            let _ = self.process_queue_boxed().await;
+        }
+        // 下载退出后才移除，重新开始的下载（例如停滞重试）仍然能看到取消
+        self.cancel_tokens.write().await.remove(task_id);
+    }
 
-        self.active_downloads
-            .write()
+    /// 任务的取消令牌，不在队列中运行的下载不会被取消
+    async fn cancel_token(&self, task_id: &str) -> CancellationToken {
+        self.cancel_tokens
+            .read()
             .await
-            .insert(task_id.to_string(), handle);
+            .get(task_id)
+            .cloned()
+            .unwrap_or_default()
+    }
 
-        Ok(())
+    /// 取得 `url` 所在主机的连接许可，等待中任务被暂停或取消时返回 [`Error::TaskCancelled`]
+    async fn host_permit(&self, task_id: &str, url: &str) -> Result<HostPermit> {
+        self.cancel_token(task_id)
+            .await
+            .run_until_cancelled(self.host_limits.acquire(url))
+            .await
+            .ok_or(Error::TaskCancelled)?
     }
 
     /// 暂停任务
+    ///
+    /// 正在下载的任务写完当前的数据、保存分块状态后停止，之后才发送
+    /// [`TaskEvent::Paused`]，恢复时从保存的位置继续。
+    ///
+    /// 校验中的任务会立即中断校验并保留已下载的文件：
+    /// [`Config::resume_interrupted_verification`] 为 true 时任务回到 `Paused`，
+    /// 恢复后只重新校验；否则标记为失败。
+    ///
+    /// 只校验本地文件的任务（[`TaskKind::VerifyOnly`]）不能暂停，返回
+    /// [`Error::CannotPauseVerifyOnlyTask`]，需要停止时使用 [`YuShi::cancel_task`]。
     pub async fn pause_task(&self, task_id: &str) -> Result<()> {
         let mut tasks = self.tasks.write().await;
         let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
+        if task.kind == TaskKind::VerifyOnly {
+            return Err(Error::CannotPauseVerifyOnlyTask);
+        }
 
-        if task.status == TaskStatus::Downloading {
-            // 取消当前的下载任务
-            let mut active = self.active_downloads.write().await;
-            if let Some(handle) = active.remove(task_id) {
-                handle.abort();
+        match task.status {
+            TaskStatus::Downloading => {
+                // 等下载保存好进度后停止，再标记为暂停
+                drop(tasks);
+                self.stop_download(task_id).await;
+
+                let mut tasks = self.tasks.write().await;
+                let Some(task) = tasks.get_mut(task_id) else {
+                    return Ok(());
+                };
+                // 停止之前已经下载完成或失败
+                if task.status != TaskStatus::Downloading {
+                    return Ok(());
+                }
+                task.status = TaskStatus::Paused;
+                self.journal(task, CompactEventKind::Paused);
+                self.progress.sync(task);
+                drop(tasks);
+
+                self.store.mark_task(task_id);
+                self.save_queue_state().await?;
+                let _ = self
+                    .queue_event_tx
+                    .send(DownloaderEvent::Task(TaskEvent::Paused {
+                        task_id: task_id.to_string(),
+                    }));
             }
+            TaskStatus::Verifying => {
+                // 通知校验停止，任务自行退出
+                if let Some(token) = self.cancel_tokens.write().await.remove(task_id) {
+                    token.cancel();
+                }
+                self.active_downloads.write().await.remove(task_id);
 
-            task.status = TaskStatus::Paused;
-            drop(tasks);
-            drop(active);
+                let event = if self.config.resume_interrupted_verification {
+                    task.status = TaskStatus::Paused;
+                    self.journal(task, CompactEventKind::Paused);
+                    TaskEvent::Paused {
+                        task_id: task_id.to_string(),
+                    }
+                } else {
+                    let error = Error::VerificationCancelled.to_string();
+                    task.status = TaskStatus::Failed;
+                    task.verification_pending = false;
+                    task.error = Some(error.clone());
+                    self.journal(
+                        task,
+                        CompactEventKind::Failed {
+                            error: error.clone(),
+                        },
+                    );
+                    TaskEvent::Failed {
+                        task_id: task_id.to_string(),
+                        error,
+                    }
+                };
+                self.progress.sync(task);
+                drop(tasks);
 
-            self.save_queue_state().await?;
-            let _ = self
-                .queue_event_tx
-                .send(DownloaderEvent::Task(TaskEvent::Paused {
-                    task_id: task_id.to_string(),
-                }))
-                .await;
+                self.store.mark_task(task_id);
+                self.save_queue_state().await?;
+                let _ = self.queue_event_tx.send(DownloaderEvent::Task(event));
+            }
+            _ => {}
         }
 
         Ok(())
@@ -895,15 +3339,17 @@ impl YuShi {
 
             if task.status == TaskStatus::Paused {
                 task.status = TaskStatus::Pending;
+                self.journal(task, CompactEventKind::Resumed);
+                self.progress.sync(task);
                 drop(tasks);
 
+                self.store.mark_task(task_id);
                 self.save_queue_state().await?;
                 let _ = self
                     .queue_event_tx
                     .send(DownloaderEvent::Task(TaskEvent::Resumed {
                         task_id: task_id.to_string(),
-                    }))
-                    .await;
+                    }));
             }
         }
 
@@ -911,23 +3357,101 @@ impl YuShi {
         Ok(())
     }
 
+    /// 重试失败或已取消的任务
+    ///
+    /// 默认用任务记录的下载环境补上任务没有设置的项（目前只有连接数：没有设置时沿用
+    /// 记录的连接数而不是当前的默认值），之后修改过的地址和连接数保持不变。
+    /// `fresh` 为 true 时丢弃记录的环境和探测结果，按当前配置重新下载，
+    /// 并在下次开始下载时重新记录。
+    pub async fn retry_task(&self, task_id: &str, fresh: bool) -> Result<()> {
+        {
+            let mut tasks = self.tasks.write().await;
+            let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
+            if !matches!(
+                task.status,
+                TaskStatus::Failed | TaskStatus::Cancelled | TaskStatus::PartiallyCompleted
+            ) {
+                return Err(Error::CannotRetryTaskInCurrentStatus);
+            }
+
+            if fresh {
+                task.provenance = None;
+                task.remote = None;
+                task.resolved_url = None;
+            } else if task.connections.is_none()
+                && let Some(provenance) = &task.provenance
+            {
+                task.connections = Some(provenance.connections);
+            }
+            task.status = TaskStatus::Pending;
+            task.error = None;
+            task.verified = 0;
+            task.archived = false;
+            task.archived_at = None;
+            self.journal(task, CompactEventKind::Retried { fresh });
+            self.progress.sync(task);
+        }
+
+        self.store.mark_task(task_id);
+        self.save_queue_state().await?;
+        self.process_queue().await
+    }
+
+    /// 按历史记录重新下载，返回新任务的 ID
+    ///
+    /// 提供了下载环境记录时使用记录的 URL 和任务选项，否则按当前配置下载 `url`。
+    pub async fn redownload(
+        &self,
+        url: String,
+        dest: PathBuf,
+        provenance: Option<&Provenance>,
+    ) -> Result<String> {
+        match provenance {
+            Some(provenance) => {
+                self.add_task_with(provenance.url.clone(), dest, provenance.task_options())
+                    .await
+            }
+            None => self.add_task(url, dest).await,
+        }
+    }
+
     /// 取消任务
+    ///
+    /// 校验中的任务会立即中断校验，已下载的文件保留；只校验本地文件的任务不会删除文件。
     pub async fn cancel_task(&self, task_id: &str) -> Result<()> {
-        // 如果正在下载，先停止
-        let mut active = self.active_downloads.write().await;
-        if let Some(handle) = active.remove(task_id) {
-            handle.abort();
+        let verifying = self
+            .get_task(task_id)
+            .await
+            .is_some_and(|t| t.status == TaskStatus::Verifying);
+
+        // 如果正在下载，先等下载停止；校验中的任务收到取消信号后自行退出
+        if verifying {
+            if let Some(token) = self.cancel_tokens.write().await.remove(task_id) {
+                token.cancel();
+            }
+            self.active_downloads.write().await.remove(task_id);
+        } else {
+            self.stop_download(task_id).await;
         }
-        drop(active);
 
         let mut tasks = self.tasks.write().await;
+        let group_id = tasks.get(task_id).and_then(|t| t.group_id.clone());
         if let Some(task) = tasks.get_mut(task_id) {
             task.status = TaskStatus::Cancelled;
+            task.verification_pending = false;
+            task.average_speed = task.compute_average_speed();
+            self.journal(task, CompactEventKind::Cancelled);
 
-            // 删除下载文件和状态文件
-            let _ = fs::remove_file(&task.dest).await;
-            let state_path = task.dest.with_extension("json");
-            let _ = fs::remove_file(state_path).await;
+            // 删除已下载的数据和状态文件
+            if task.kind == TaskKind::Download {
+                if !verifying {
+                    let _ = self.task_sink(task).await.abort().await;
+                }
+                let _ = fs::remove_file(self.state_path(task)).await;
+            }
+            self.progress.sync(task);
+            self.audit(task);
+            self.store.mark_task(task_id);
         }
         drop(tasks);
 
@@ -936,8 +3460,11 @@ impl YuShi {
             .queue_event_tx
             .send(DownloaderEvent::Task(TaskEvent::Cancelled {
                 task_id: task_id.to_string(),
-            }))
-            .await;
+            }));
+
+        if let Some(group_id) = group_id {
+            self.check_group_completed(&group_id).await;
+        }
 
         // 处理队列中的下一个任务
         self.process_queue().await?;
@@ -945,26 +3472,136 @@ impl YuShi {
         Ok(())
     }
 
-    /// 移除已完成或已取消的任务
+    /// 移除已完成、失败或已取消的任务
+    ///
+    /// 任务只是归档，不再出现在任务列表和统计中，仍然可以用 [`YuShi::get_task`] 和
+    /// [`YuShi::get_tasks_filtered`] 查看。真正删除使用 [`YuShi::purge_task`]。
     pub async fn remove_task(&self, task_id: &str) -> Result<()> {
+        self.remove_task_with_options(task_id, RemoveOptions::default())
+            .await
+    }
+
+    /// 按 `options` 移除任务，见 [`YuShi::remove_task`] 和 [`RemoveOptions`]
+    pub async fn remove_task_with_options(
+        &self,
+        task_id: &str,
+        options: RemoveOptions,
+    ) -> Result<()> {
         let mut tasks = self.tasks.write().await;
-        if let Some(task) = tasks.get(task_id)
-            && (task.status == TaskStatus::Completed
-                || task.status == TaskStatus::Cancelled
-                || task.status == TaskStatus::Failed)
-        {
-            tasks.remove(task_id);
+        let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
+        if !matches!(
+            task.status,
+            TaskStatus::Completed
+                | TaskStatus::Cancelled
+                | TaskStatus::Failed
+                | TaskStatus::PartiallyCompleted
+        ) {
+            return Err(Error::CannotRemoveTaskInCurrentStatus);
+        }
+        if options.delete_file && task.kind == TaskKind::Download {
+            let mut paths = vec![
+                self.work_path(task),
+                task.dest.clone(),
+                self.state_path(task),
+            ];
+            paths.extend(task.completed_link.take());
+            paths.dedup();
+            for path in paths {
+                let _ = fs::remove_file(path).await;
+            }
+            self.store.mark_task(task_id);
+        }
+        if task.archived {
             drop(tasks);
+            return match options.delete_file {
+                true => self.save_queue_state().await,
+                false => Ok(()),
+            };
+        }
+        archive(task);
+        drop(tasks);
+        self.sinks.write().await.remove(task_id);
+        self.url_refreshers.write().await.remove(task_id);
+        self.boosts.lock().unwrap().remove(task_id);
+        self.progress.remove(task_id);
+        self.store.mark_task(task_id);
+        self.save_queue_state().await
+    }
+
+    /// 删除任务记录，只能删除已归档或已完成、失败、取消的任务
+    pub async fn purge_task(&self, task_id: &str) -> Result<()> {
+        let mut tasks = self.tasks.write().await;
+        let task = tasks.get(task_id).ok_or(Error::TaskNotFound)?;
+        if !task.archived
+            && !matches!(
+                task.status,
+                TaskStatus::Completed
+                    | TaskStatus::Cancelled
+                    | TaskStatus::Failed
+                    | TaskStatus::PartiallyCompleted
+            )
+        {
+            return Err(Error::CannotRemoveTaskInCurrentStatus);
+        }
+        tasks.remove(task_id);
+        drop(tasks);
+        self.sinks.write().await.remove(task_id);
+        self.url_refreshers.write().await.remove(task_id);
+        self.boosts.lock().unwrap().remove(task_id);
+        self.progress.remove(task_id);
+        self.store.mark_task_removed(task_id);
+        self.save_queue_state().await
+    }
+
+    /// 删除归档超过 `older_than` 的任务，返回删除的任务数
+    pub async fn purge_archived(&self, older_than: Duration) -> Result<usize> {
+        let cutoff = current_timestamp().saturating_sub(older_than.as_secs());
+        let removed = {
+            let mut tasks = self.tasks.write().await;
+            let removed: Vec<_> = tasks
+                .values()
+                .filter(|t| t.archived && t.archived_at.unwrap_or(0) <= cutoff)
+                .map(|t| t.id.clone())
+                .collect();
+            for id in &removed {
+                tasks.remove(id);
+                self.store.mark_task_removed(id);
+            }
+            removed
+        };
+        if !removed.is_empty() {
             self.save_queue_state().await?;
-            return Ok(());
         }
-        Err(Error::CannotRemoveTaskInCurrentStatus)
+        Ok(removed.len())
     }
 
-    /// 获取所有任务
+    /// 获取所有任务（不包括已归档的任务）
     pub async fn get_all_tasks(&self) -> Vec<Task> {
+        self.get_tasks_filtered(false).await
+    }
+
+    /// 获取任务，`include_archived` 为 true 时包括已归档的任务
+    pub async fn get_tasks_filtered(&self, include_archived: bool) -> Vec<Task> {
         let tasks = self.tasks.read().await;
-        tasks.values().cloned().collect()
+        tasks
+            .values()
+            .filter(|t| include_archived || !t.archived)
+            .cloned()
+            .collect()
+    }
+
+    /// 按条件过滤并排序任务
+    pub async fn query_tasks(&self, query: &TaskQuery) -> Vec<Task> {
+        let mut tasks: Vec<Task> = {
+            let tasks = self.tasks.read().await;
+            tasks
+                .values()
+                .filter(|t| query.accepts(t))
+                .cloned()
+                .collect()
+        };
+        tasks.sort_by(|a, b| query.compare(a, b));
+        tasks
     }
 
     /// 获取单个任务
@@ -973,12 +3610,7900 @@ impl YuShi {
         tasks.get(task_id).cloned()
     }
 
-    /// 清空所有已完成的任务
-    pub async fn clear_completed(&self) -> Result<()> {
-        let mut tasks = self.tasks.write().await;
-        tasks.retain(|_, task| task.status != TaskStatus::Completed);
-        drop(tasks);
+    /// 获取任务最近的事件记录，按时间先后排列（见 [`Task::recent_events`]）
+    pub async fn get_task_events(&self, task_id: &str) -> Result<Vec<CompactEvent>> {
+        let tasks = self.tasks.read().await;
+        let task = tasks.get(task_id).ok_or(Error::TaskNotFound)?;
+        Ok(task.recent_events.iter().cloned().collect())
+    }
+
+    /// 调整任务的并发连接数
+    ///
+    /// 设置保存在任务上，之后的下载都会使用。任务正在下载时立即生效：
+    /// 调大时马上启动更多分块；调小时进行中的分块会下载完，
+    /// 在连接数降到新上限以下之前不再启动新的分块。`connections` 至少为 1。
+    pub async fn set_task_connections(&self, task_id: &str, connections: usize) -> Result<()> {
+        let connections = connections.max(1);
+        {
+            let mut tasks = self.tasks.write().await;
+            let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
+            task.connections = Some(connections);
+        }
+        let limiter = self.connection_limits.read().await.get(task_id).cloned();
+        if let Some(limiter) = limiter {
+            limiter.set_limit(connections);
+            self.set_active_connections(task_id, connections).await;
+        }
+        self.store.mark_task(task_id);
+        self.save_queue_state().await
+    }
+
+    /// 设置任务的显示名称，None 表示使用文件名
+    pub async fn set_task_display_name(
+        &self,
+        task_id: &str,
+        display_name: Option<String>,
+    ) -> Result<()> {
+        {
+            let mut tasks = self.tasks.write().await;
+            let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
+            task.display_name = display_name;
+        }
+        self.store.mark_task(task_id);
+        self.save_queue_state().await
+    }
+
+    /// 设置任务备注，None 表示清除备注
+    pub async fn set_task_note(&self, task_id: &str, note: Option<String>) -> Result<()> {
+        {
+            let mut tasks = self.tasks.write().await;
+            let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
+            task.note = note;
+        }
+        self.store.mark_task(task_id);
+        self.save_queue_state().await
+    }
+
+    /// 修改任务的下载地址，例如换用新签发的预签名 URL
+    ///
+    /// 下载中和校验中的任务不能修改。已经下载过一部分或探测过远程资源时，先探测新地址，
+    /// 大小或 `ETag` 与原来不一致时返回 [`Error::UrlMismatch`]；一致时断点续传状态跟随
+    /// 新地址，之后恢复任务会从原来的进度继续。
+    pub async fn set_task_url(&self, task_id: &str, url: String) -> Result<()> {
+        let task = self.get_task(task_id).await.ok_or(Error::TaskNotFound)?;
+        if matches!(task.status, TaskStatus::Downloading | TaskStatus::Verifying) {
+            return Err(Error::CannotModifyTaskInCurrentStatus);
+        }
+
+        let state_path = self.state_path(&task);
+        let state = DownloadState::load(&state_path)
+            .await?
+            .filter(|s| task.owns_url(&s.url));
+        let mut remote = task.remote.clone();
+        let mut resolved = None;
+        if state.is_some() || remote.is_some() {
+            let (info, final_url) = self
+                .probe_with_headers(&url, &task.headers, task.auth.as_ref())
+                .await?;
+            resolved = resolved_url(&url, &final_url);
+            if let Some(old) = &remote {
+                if old.total_size != info.total_size {
+                    return Err(Error::UrlMismatch(format!(
+                        "Content-Length {:?} != {:?}",
+                        info.total_size, old.total_size
+                    )));
+                }
+                if let (Some(old), Some(new)) = (&old.etag, &info.etag)
+                    && old != new
+                {
+                    return Err(Error::UrlMismatch(format!("ETag {} != {}", new, old)));
+                }
+            } else if let Some(state) = &state
+                && task.byte_range.is_none()
+                && state.total_size != info.total_size
+            {
+                return Err(Error::UrlMismatch(format!(
+                    "Content-Length {:?} != {:?}",
+                    info.total_size, state.total_size
+                )));
+            }
+            remote = Some(info);
+        }
+
+        if let Some(mut state) = state {
+            state.url = resolved.clone().unwrap_or_else(|| url.clone());
+            state.save(&state_path).await?;
+        }
+        {
+            let mut tasks = self.tasks.write().await;
+            let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
+            task.replace_url(url);
+            task.remote = remote;
+            task.resolved_url = resolved;
+        }
+        self.store.mark_task(task_id);
+        self.save_queue_state().await
+    }
+
+    /// 重新校验已完成且设置了校验和的任务
+    ///
+    /// 只处理满足 `filter` 的任务。同时校验的任务数受 [`Config::verify_concurrency`] 限制，
+    /// 与下载完成后的校验共用同一个上限。每个任务都会发送 [`VerificationEvent`]，
+    /// 校验结果不改变任务状态。
+    ///
+    /// 返回每个任务的 ID 和是否通过校验（按创建时间排序），文件不存在视为未通过。
+    pub async fn verify_all(&self, filter: impl Fn(&Task) -> bool) -> Result<Vec<(String, bool)>> {
+        let mut tasks: Vec<Task> = self
+            .tasks
+            .read()
+            .await
+            .values()
+            .filter(|t| t.status == TaskStatus::Completed && t.checksum.is_some() && filter(t))
+            .cloned()
+            .collect();
+        tasks.sort_by_key(|t| t.created_at);
+
+        let results = futures::future::join_all(tasks.iter().map(|task| async move {
+            let success = self.reverify_task(task).await?;
+            Ok((task.id.clone(), success))
+        }))
+        .await;
+        results.into_iter().collect()
+    }
+
+    /// 扫描下载目录中没有任务引用的状态文件和未下载完的数据文件
+    ///
+    /// 只扫描给定目录本身，不递归子目录。任务的目标文件（任何状态）和
+    /// 未完成、未取消任务的状态文件都不会被报告。
+    pub async fn scan_orphans(&self, download_dirs: &[PathBuf]) -> Vec<OrphanReport> {
+        let claimed = self.claimed_paths().await;
+        let mut reports = Vec::new();
+        for dir in download_dirs {
+            reports.extend(orphan::scan_dir(dir, &claimed).await);
+        }
+        reports
+    }
+
+    /// 清理 [`YuShi::scan_orphans`] 报告的文件，返回释放的字节数
+    ///
+    /// `delete` 为 false 时只计算可以释放的字节数。清理前会重新检查任务引用，
+    /// 扫描之后被任务引用的文件和已经不存在的文件会被跳过。
+    pub async fn clean_orphans(&self, reports: &[OrphanReport], delete: bool) -> Result<u64> {
+        let claimed = self.claimed_paths().await;
+        let mut reclaimed = 0;
+        for report in reports {
+            if claimed.contains(&orphan::normalize(&report.path)) {
+                continue;
+            }
+            let Ok(metadata) = fs::metadata(&report.path).await else {
+                continue;
+            };
+            if delete {
+                fs::remove_file(&report.path).await?;
+            }
+            reclaimed += metadata.len();
+        }
+        Ok(reclaimed)
+    }
+
+    /// 仍被任务引用的路径
+    ///
+    /// 所有任务的目标文件，以及可能继续下载的任务（未完成且未取消）的状态文件。
+    async fn claimed_paths(&self) -> HashSet<PathBuf> {
+        let tasks = self.tasks.read().await;
+        let mut claimed = HashSet::new();
+        for task in tasks.values() {
+            claimed.insert(orphan::normalize(&task.dest));
+            if !matches!(task.status, TaskStatus::Completed | TaskStatus::Cancelled) {
+                claimed.insert(orphan::normalize(&self.work_path(task)));
+                claimed.insert(orphan::normalize(&self.state_path(task)));
+            }
+        }
+        claimed
+    }
+
+    /// 启动队列中等待的任务（不超过最大并发任务数）
+    pub async fn start(&self) -> Result<()> {
+        self.process_queue().await
+    }
+
+    /// 最大并发任务数
+    pub fn max_concurrent_tasks(&self) -> usize {
+        self.max_concurrent_tasks.load(Ordering::Relaxed)
+    }
+
+    /// 修改最大并发任务数（至少为 1）
+    ///
+    /// 调大时立即启动等待中的任务；调小时运行中的任务不受影响，
+    /// 直到运行数降到新上限以下才启动新的任务。
+    pub async fn set_max_concurrent_tasks(&self, max: usize) -> Result<()> {
+        self.max_concurrent_tasks
+            .store(max.max(1), Ordering::Relaxed);
+        self.process_queue().await
+    }
+
+    /// 未单独设置连接数的任务使用的连接数
+    pub fn default_connections(&self) -> usize {
+        self.default_connections.load(Ordering::Relaxed)
+    }
+
+    /// 未单独设置分块顺序的任务使用的顺序，兼容旧的 `sequential_first` 设置
+    fn default_chunk_order(&self) -> ChunkOrder {
+        match self.config.chunk_order {
+            ChunkOrder::Parallel if self.config.sequential_first => {
+                ChunkOrder::SequentialFirst(self.config.sequential_prefix)
+            }
+            order => order,
+        }
+    }
+
+    /// 修改默认连接数（至少为 1）
+    ///
+    /// 立即应用到运行中且没有通过 [`YuShi::set_task_connections`] 单独设置的任务。
+    pub async fn set_default_connections(&self, connections: usize) {
+        let connections = connections.max(1);
+        self.default_connections
+            .store(connections, Ordering::Relaxed);
+        let tasks = self.tasks.read().await;
+        for (task_id, limiter) in self.connection_limits.read().await.iter() {
+            if tasks.get(task_id).is_some_and(|t| t.connections.is_none()) {
+                limiter.set_limit(connections);
+            }
+        }
+    }
+
+    /// 所有任务共用的总速度限制（字节/秒）
+    pub fn global_speed_limit(&self) -> Option<u64> {
+        self.global_limiter.limit()
+    }
+
+    /// 修改总速度限制（初始值为 [`Config::global_speed_limit`]），None 表示不限速，
+    /// 立即对所有下载生效
+    ///
+    /// 与 [`Config::speed_limit`]（每个任务单独计算）同时生效。
+    pub fn set_global_speed_limit(&self, limit: Option<u64>) {
+        self.global_limiter.set_limit(limit);
+    }
+
+    /// 各主机当前的连接数，只包括有连接或等待中的连接的主机
+    ///
+    /// 连接按下载地址的主机名统计，上限见 [`Config::per_host_max_connections`]。
+    pub fn host_stats(&self) -> Vec<HostStats> {
+        self.host_limits.stats()
+    }
+
+    /// 临时解除任务的速度限制，返回加速的截止时间（Unix 毫秒）
+    ///
+    /// `duration` 之内任务的下载不受任务自己的限速和总速度限制，之后自动恢复。任务正在
+    /// 加速时从原来的截止时间起延长，而不是重新计时。开始和结束时分别发送
+    /// [`TaskEvent::Boosted`] 和 [`TaskEvent::BoostEnded`]，截止时间见 [`Task::boost_until`]。
+    /// 等待中的任务也可以加速，开始下载后生效。截止时间随队列状态保存，重新加载时
+    /// 没有到期的加速继续生效。
+    pub async fn boost_task(&self, task_id: &str, duration: Duration) -> Result<u64> {
+        let now = current_timestamp_ms();
+        let until = {
+            let mut tasks = self.tasks.write().await;
+            let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
+            let from = task.boost_until.filter(|&until| until > now).unwrap_or(now);
+            let until = from.saturating_add(duration.as_millis() as u64);
+            task.boost_until = Some(until);
+            until
+        };
+        self.arm_boost(task_id, until);
+        self.store.mark_task(task_id);
         self.save_queue_state().await?;
-        Ok(())
+        let _ = self
+            .queue_event_tx
+            .send(DownloaderEvent::Task(TaskEvent::Boosted {
+                task_id: task_id.to_string(),
+                until_ms: until,
+            }));
+        Ok(until)
+    }
+
+    /// 让下载连接看到新的截止时间，并在到期时结束加速
+    fn arm_boost(&self, task_id: &str, until: u64) {
+        self.boost_handle(task_id).store(until, Ordering::Relaxed);
+        let downloader = self.clone();
+        let task_id = task_id.to_string();
+        let remaining = until.saturating_sub(current_timestamp_ms());
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(remaining)).await;
+            downloader.end_boost(&task_id, until).await;
+        });
+    }
+
+    /// 加速到期，截止时间仍是 `until` 时恢复限速（被延长时由之后的计时器处理）
+    async fn end_boost(&self, task_id: &str, until: u64) {
+        {
+            let mut tasks = self.tasks.write().await;
+            let Some(task) = tasks.get_mut(task_id) else {
+                return;
+            };
+            if task.boost_until != Some(until) {
+                return;
+            }
+            task.boost_until = None;
+        }
+        if let Some(boost) = self.boosts.lock().unwrap().get(task_id) {
+            let _ = boost.compare_exchange(until, 0, Ordering::Relaxed, Ordering::Relaxed);
+        }
+        self.store.mark_task(task_id);
+        let _ = self.save_queue_state().await;
+        let _ = self
+            .queue_event_tx
+            .send(DownloaderEvent::Task(TaskEvent::BoostEnded {
+                task_id: task_id.to_string(),
+            }));
+    }
+
+    /// 设置主机的认证信息，之后发往匹配主机的请求自动带上认证头
+    ///
+    /// 主机模式的写法见 [`CredentialStore`]。配置中显式设置了 `Authorization` 头时不使用。
+    /// 认证信息只保存在内存中，不会写入队列状态或事件。
+    pub fn set_host_credentials(&self, host_pattern: &str, credential: Credential) {
+        self.credentials.set(host_pattern, credential);
+    }
+
+    /// 删除主机的认证信息，不存在时返回 false
+    pub fn remove_host_credentials(&self, host_pattern: &str) -> bool {
+        self.credentials.remove(host_pattern)
+    }
+
+    /// 从认证信息文件加载，与已有的设置合并，返回加载的条目数
+    ///
+    /// 文件格式和权限要求见 [`CredentialStore::load`]。
+    pub async fn load_credentials(&self, path: &Path) -> Result<usize> {
+        let entries = CredentialStore::load(path).await?.entries();
+        let count = entries.len();
+        for (pattern, credential) in entries {
+            self.credentials.set(&pattern, credential);
+        }
+        Ok(count)
+    }
+
+    /// 获取任务进度快照
+    ///
+    /// 读取下载线程维护的原子计数器，不获取任务表的锁，适合高频轮询。
+    /// 进度至少每个进度间隔更新一次，状态在变化时立即更新。
+    pub fn progress(&self, task_id: &str) -> Option<ProgressSnapshot> {
+        self.progress.snapshot(task_id)
+    }
+
+    /// 获取所有任务的进度快照（顺序不固定）
+    pub fn progress_all(&self) -> Vec<ProgressSnapshot> {
+        self.progress.snapshot_all()
+    }
+
+    /// 队列统计
+    ///
+    /// 与 [`YuShi::progress_all`] 一样读取进度计数器，不获取任务表的锁。
+    pub fn get_stats(&self) -> QueueStats {
+        let snapshots = self.progress.snapshot_all();
+        let count = |status: TaskStatus| snapshots.iter().filter(|s| s.status == status).count();
+        let speed = snapshots
+            .iter()
+            .filter(|s| s.status == TaskStatus::Downloading)
+            .map(|s| s.speed)
+            .sum();
+
+        let mut remaining_bytes_by_priority = HashMap::new();
+        let mut unknown_size_by_priority = HashMap::new();
+        let mut active_by_priority = HashMap::new();
+        for s in &snapshots {
+            match s.status {
+                TaskStatus::Pending | TaskStatus::Downloading | TaskStatus::Paused => {
+                    if s.total > 0 {
+                        *remaining_bytes_by_priority.entry(s.priority).or_default() +=
+                            s.total.saturating_sub(s.downloaded);
+                    } else {
+                        *unknown_size_by_priority.entry(s.priority).or_default() += 1;
+                    }
+                }
+                _ => {}
+            }
+            if matches!(s.status, TaskStatus::Downloading | TaskStatus::Verifying) {
+                *active_by_priority.entry(s.priority).or_default() += 1;
+            }
+        }
+        let remaining: u64 = remaining_bytes_by_priority.values().sum();
+        let estimated_drain_time = match (remaining, speed) {
+            (0, _) => Some(0),
+            (_, 0) => None,
+            (remaining, speed) => Some(remaining.div_ceil(speed)),
+        };
+
+        QueueStats {
+            total: snapshots.len(),
+            pending: count(TaskStatus::Pending),
+            downloading: count(TaskStatus::Downloading),
+            verifying: count(TaskStatus::Verifying),
+            paused: count(TaskStatus::Paused),
+            completed: count(TaskStatus::Completed),
+            failed: count(TaskStatus::Failed),
+            cancelled: count(TaskStatus::Cancelled),
+            partially_completed: count(TaskStatus::PartiallyCompleted),
+            speed,
+            buffered_bytes: self.buffer_budget.in_use(),
+            scheduler: self.scheduler_state(),
+            remaining_bytes_by_priority,
+            unknown_size_by_priority,
+            active_by_priority,
+            estimated_drain_time,
+        }
+    }
+
+    /// 模拟调度：按当前设置预计等待中的任务依次在什么时候开始和完成，不下载任何数据
+    ///
+    /// 启动顺序与调度器使用同一个排序函数，同时运行的任务数取
+    /// [`YuShi::max_concurrent_tasks`]，调度器暂停时也按运行中计算。同时运行的任务平分
+    /// `assumed_speed`（字节/秒）；为 None 时依次使用总速度限制和当前的总速度。
+    /// 任务大小取已知的大小（不探测），大小未知的任务预计不会结束，一直占用名额。
+    /// 结果按预计的启动顺序排列。
+    pub async fn simulate_schedule(&self, assumed_speed: Option<u64>) -> Vec<ScheduledSlot> {
+        let speed = assumed_speed
+            .or(self.global_speed_limit())
+            .or(Some(self.get_stats().speed))
+            .filter(|&speed| speed > 0);
+        let remaining = |task: &Task| {
+            expected_size(task, task.remote.as_ref())
+                .map(|size| size.saturating_sub(task.downloaded))
+        };
+        let sim = |task: &Task| schedule::SimTask {
+            id: task.id.clone(),
+            remaining: remaining(task),
+        };
+
+        let tasks = self.tasks.read().await;
+        let active = self.active_downloads.read().await;
+        let running = tasks
+            .values()
+            .filter(|t| active.contains_key(&t.id))
+            .map(sim)
+            .collect();
+        let pending =
+            schedule::start_order(tasks.values().filter(|t| t.status == TaskStatus::Pending))
+                .into_iter()
+                .map(sim)
+                .collect();
+        schedule::simulate(running, pending, self.max_concurrent_tasks(), speed)
+    }
+
+    // ==================== 磁盘空间 ====================
+
+    /// 预估等待中和暂停的任务下载完还需要的磁盘空间，并与各目标卷的空闲空间比较
+    ///
+    /// `dest_root_filter` 指定时只统计目标位于该目录下的任务。大小未知的任务最多探测
+    /// [`Config::forecast_probe_limit`] 个（设置了 `skip_probe` 的任务不探测），结果缓存在任务上。
+    /// 空间按最终目标位置计算，不包括 [`Config::temp_dir`] 中的临时占用。
+    pub async fn forecast(&self, dest_root_filter: Option<&Path>) -> Forecast {
+        // 与调度器的顺序相同，先探测会先开始的任务
+        let tasks: Vec<Task> = schedule::start_order(
+            self.tasks
+                .read()
+                .await
+                .values()
+                .filter(|t| matches!(t.status, TaskStatus::Pending | TaskStatus::Paused))
+                .filter(|t| dest_root_filter.is_none_or(|root| t.dest.starts_with(root))),
+        )
+        .into_iter()
+        .cloned()
+        .collect();
+
+        let mut probes_left = self.config.forecast_probe_limit;
+        let mut volumes: Vec<(String, VolumeForecast)> = Vec::new();
+        let mut forecast = Forecast {
+            pending_bytes_known: 0,
+            pending_unknown_count: 0,
+            free_space_by_volume: Vec::new(),
+            will_fit: Some(true),
+        };
+
+        for task in tasks {
+            let mut size = expected_size(&task, task.remote.as_ref());
+            if size.is_none() && !task.skip_probe && probes_left > 0 {
+                probes_left -= 1;
+                if let Ok(remote) = self.probe_task(&task).await {
+                    size = expected_size(&task, Some(&remote));
+                }
+            }
+            let remaining = size.map(|s| s.saturating_sub(task.downloaded));
+
+            let Some((id, dir)) = volume_of(&task.dest) else {
+                // 目标位置不可用，无法判断能否放下
+                forecast.will_fit = None;
+                continue;
+            };
+            let index = match volumes.iter().position(|(v, _)| *v == id) {
+                Some(index) => index,
+                None => {
+                    volumes.push((
+                        id,
+                        VolumeForecast {
+                            free_space: fs4::available_space(&dir).unwrap_or(0),
+                            path: dir,
+                            required: 0,
+                            task_count: 0,
+                            unknown_count: 0,
+                        },
+                    ));
+                    volumes.len() - 1
+                }
+            };
+            let volume = &mut volumes[index].1;
+            volume.task_count += 1;
+            match remaining {
+                Some(bytes) => {
+                    volume.required += bytes;
+                    forecast.pending_bytes_known += bytes;
+                }
+                None => {
+                    volume.unknown_count += 1;
+                    forecast.pending_unknown_count += 1;
+                }
+            }
+        }
+
+        forecast.free_space_by_volume = volumes.into_iter().map(|(_, v)| v).collect();
+        if forecast
+            .free_space_by_volume
+            .iter()
+            .any(|v| v.headroom().is_none())
+        {
+            forecast.will_fit = Some(false);
+        } else if forecast.pending_unknown_count > 0 {
+            forecast.will_fit = None;
+        }
+        forecast
+    }
+
+    // ==================== 诊断 ====================
+
+    /// 任务的诊断信息：分块状态、其中的不一致以及生效的下载设置
+    ///
+    /// URL 中的查询字符串会被去掉，需要完整 URL 时使用 [`YuShi::diagnostics_with`]。
+    pub async fn diagnostics(&self, task_id: &str) -> Result<TaskDiagnostics> {
+        self.diagnostics_with(task_id, true).await
+    }
+
+    /// 任务的诊断信息，`redact_query` 为 false 时保留 URL 中的查询字符串
+    pub async fn diagnostics_with(
+        &self,
+        task_id: &str,
+        redact_query: bool,
+    ) -> Result<TaskDiagnostics> {
+        let task = self.get_task(task_id).await.ok_or(Error::TaskNotFound)?;
+        let redact = |url: &str| {
+            if redact_query {
+                redact_url(url)
+            } else {
+                url.to_string()
+            }
+        };
+
+        let state_path = self.state_path(&task);
+        let state_saved_at = fs::metadata(&state_path)
+            .await
+            .ok()
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        let (state, state_error) = match DownloadState::load(&state_path).await {
+            Ok(Some(state)) => {
+                let diagnostics = StateDiagnostics {
+                    url: redact(&state.url),
+                    total_size: state.total_size,
+                    is_streaming: state.is_streaming,
+                    offset: state.offset,
+                    downloaded: state
+                        .chunks
+                        .iter()
+                        .map(|c| c.current.saturating_sub(c.start))
+                        .sum(),
+                    chunks: state
+                        .chunks
+                        .iter()
+                        .map(|c| ChunkDiagnostics {
+                            index: c.index,
+                            start: c.start,
+                            end: c.end,
+                            current: c.current,
+                            finished: c.is_finished,
+                            has_digest: c.digest.is_some(),
+                        })
+                        .collect(),
+                    issues: state.issues(),
+                };
+                (Some(diagnostics), None)
+            }
+            Ok(None) => (None, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        let config = EffectiveConfig {
+            core_version: env!("CARGO_PKG_VERSION").to_string(),
+            connections: task.connections.unwrap_or(self.default_connections()),
+            chunk_size: self.config.chunk_size,
+            speed_limit: self.speed_limit_for(&task),
+            global_speed_limit: self.global_speed_limit(),
+            timeout: self.config.timeout,
+            user_agent: self.config.user_agent.clone(),
+            proxy: self.config.proxy.is_some(),
+            temp_dir: self.config.temp_dir.clone(),
+            part_suffix: self.part_suffix().map(str::to_string),
+            stall_timeout: self.config.stall_timeout.map(|t| t.as_secs()),
+            connection_stall_timeout: self.config.connection_stall_timeout.map(|t| t.as_secs()),
+            sequential_first: self.config.sequential_first,
+            chunk_order: task.chunk_order.unwrap_or(self.default_chunk_order()),
+            ramp_up_ms: self.config.ramp_up.map(|t| t.as_millis() as u64),
+            skip_probe: task.skip_probe,
+            byte_range: task.byte_range,
+        };
+        let provenance = task.provenance.clone().map(|mut p| {
+            p.url = redact(&p.url);
+            p
+        });
+
+        Ok(TaskDiagnostics {
+            task_id: task.id.clone(),
+            status: task.status,
+            url: redact(&task.url),
+            work_path: self.work_path(&task),
+            dest: task.dest,
+            state_path,
+            state_saved_at,
+            state,
+            state_error,
+            config,
+            provenance,
+        })
+    }
+
+    /// 把指定分块标记为未完成，下次恢复或重试时只重新下载这些分块
+    ///
+    /// 只能用于已暂停或失败的任务。`indices` 是分块序号（[`ChunkDiagnostics::index`]），
+    /// 有任何一个序号不存在时不做修改。
+    pub async fn invalidate_chunks(&self, task_id: &str, indices: &[usize]) -> Result<()> {
+        // 持有写锁直到状态文件写完，避免任务在修改期间开始下载
+        let mut tasks = self.tasks.write().await;
+        let task = tasks.get_mut(task_id).ok_or(Error::TaskNotFound)?;
+        if !matches!(task.status, TaskStatus::Paused | TaskStatus::Failed) {
+            return Err(Error::CannotInvalidateChunksInCurrentStatus);
+        }
+
+        let state_path = self.state_path(task);
+        let mut state = match DownloadState::load(&state_path).await? {
+            Some(state) if !state.is_streaming => state,
+            _ => {
+                return Err(Error::TaskFailed(message("chunks.no_state", &[])));
+            }
+        };
+        if let Some(missing) = indices
+            .iter()
+            .find(|&&i| !state.chunks.iter().any(|c| c.index == i))
+        {
+            return Err(Error::TaskFailed(message("chunks.not_found", &[missing])));
+        }
+        for chunk in &mut state.chunks {
+            if indices.contains(&chunk.index) {
+                chunk.current = chunk.start;
+                chunk.is_finished = false;
+                chunk.digest = None;
+            }
+        }
+        state.save(&state_path).await?;
+
+        task.downloaded = state.chunks.iter().map(|c| c.current - c.start).sum();
+        task.contiguous_prefix = task.contiguous_prefix.min(state.contiguous_prefix());
+        self.progress.sync(task);
+        drop(tasks);
+
+        self.store.mark_task(task_id);
+        self.save_queue_state().await
+    }
+
+    /// 与下载时记录的摘要不一致的已完成分块，可传给 [`YuShi::invalidate_chunks`]
+    ///
+    /// 没有记录摘要的分块无法判断，不会包含在结果中。下载中的任务返回错误。
+    pub async fn damaged_chunks(&self, task_id: &str) -> Result<Vec<usize>> {
+        let task = self.get_task(task_id).await.ok_or(Error::TaskNotFound)?;
+        if matches!(task.status, TaskStatus::Downloading | TaskStatus::Verifying) {
+            return Err(Error::CannotInvalidateChunksInCurrentStatus);
+        }
+        let Some(state) = DownloadState::load(&self.state_path(&task)).await? else {
+            return Ok(Vec::new());
+        };
+
+        let work_path = self.work_path(&task);
+        let mut damaged = Vec::new();
+        for chunk in state
+            .chunks
+            .iter()
+            .filter(|c| c.is_finished && c.digest.is_some())
+        {
+            if !chunk_intact(&work_path, chunk).await? {
+                damaged.push(chunk.index);
+            }
+        }
+        Ok(damaged)
+    }
+
+    // ==================== 任务列表同步 ====================
+
+    /// 任务列表的完整视图（按 ID 排序）和对应的代次
+    ///
+    /// 界面初始化时调用，之后只需应用 [`GlobalEvent::TasksChanged`] 中代次更大的增量。
+    pub async fn get_tasks_snapshot(&self) -> (u64, Vec<TaskView>) {
+        self.start_change_feed();
+        self.publish_task_changes().await;
+        self.changes.lock().unwrap().snapshot()
+    }
+
+    /// 最近一次发出的任务列表变更的代次，不触发新的比较
+    ///
+    /// 与本地记录的代次不同说明错过了 [`GlobalEvent::TasksChanged`] 事件。
+    pub fn tasks_generation(&self) -> u64 {
+        self.changes.lock().unwrap().generation()
+    }
+
+    /// 把 `generation` 之后的变更合并为一个
+    ///
+    /// 收到的 [`GlobalEvent::TasksChanged`] 代次不连续（事件被丢弃）时调用；
+    /// 返回 None 表示历史中已经没有这么早的变更，需要重新调用
+    /// [`YuShi::get_tasks_snapshot`]。
+    pub fn get_changes_since(&self, generation: u64) -> Option<TaskChanges> {
+        self.changes.lock().unwrap().changes_since(generation)
+    }
+
+    /// 与上次发出的任务列表比较，有变化时发送 [`GlobalEvent::TasksChanged`]
+    ///
+    /// 事件通道已满时直接丢弃，接收方会发现代次不连续并补齐。
+    async fn publish_task_changes(&self) {
+        let tasks = self.tasks.read().await;
+        let mut tracker = self.changes.lock().unwrap();
+        if let Some(changes) = tracker.update(tasks.values().filter(|t| !t.archived)) {
+            let _ = self
+                .queue_event_tx
+                .try_send(DownloaderEvent::Global(GlobalEvent::TasksChanged(changes)));
+        }
+    }
+
+    /// 按 [`Config::tasks_changed_interval`] 定期发送任务列表变更，按
+    /// [`Config::stats_interval`] 定期发送队列统计
+    ///
+    /// 第一次添加或加载任务时启动，事件接收端关闭后停止。统计事件与任务列表变更一样
+    /// 在事件通道已满时直接丢弃，下一次发送的是最新的统计。
+    fn start_change_feed(&self) {
+        if self.change_feed_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if let Some(interval) = self.config.tasks_changed_interval {
+            let downloader = self.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                while !downloader.queue_event_tx.is_closed() {
+                    ticker.tick().await;
+                    downloader.publish_task_changes().await;
+                }
+            });
+        }
+        if let Some(interval) = self.config.stats_interval {
+            let downloader = self.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                while !downloader.queue_event_tx.is_closed() {
+                    ticker.tick().await;
+                    let stats = downloader.get_stats();
+                    let _ = downloader
+                        .queue_event_tx
+                        .try_send(DownloaderEvent::Global(GlobalEvent::Stats(Box::new(stats))));
+                }
+            });
+        }
+    }
+
+    // ==================== 调度器 ====================
+
+    /// 调度器状态：是否会启动等待中的任务，以及暂停的原因
+    pub fn scheduler_state(&self) -> SchedulerState {
+        self.scheduler.lock().unwrap().clone()
+    }
+
+    /// 暂停调度：不再启动等待中的任务，运行中的任务不受影响
+    pub async fn pause_queue(&self) {
+        self.set_scheduler_state(SchedulerState {
+            running: false,
+            reason: Some(PausedReason::ManualPause),
+            next_wakeup: None,
+        })
+        .await;
+    }
+
+    /// 恢复被 [`YuShi::pause_queue`] 暂停的调度，并立即启动等待中的任务
+    pub async fn resume_queue(&self) -> Result<()> {
+        let manual = self.scheduler.lock().unwrap().reason == Some(PausedReason::ManualPause);
+        if manual {
+            self.set_scheduler_state(SchedulerState::default()).await;
+        }
+        self.process_queue().await
+    }
+
+    /// 关闭下载器
+    ///
+    /// 不再启动任何任务（包括 [`YuShi::run_task`]，返回 [`Error::ShutDown`]），通知所有运行中的
+    /// 下载在安全的位置停止并保存分块状态，然后把它们标记为 `Paused`，并把队列状态
+    /// 合并到主文件（见 [`YuShi::compact_queue_state`]）。
+    /// 下载全部停止或超过 `timeout` 后返回，超时仍未停止的下载直接中断。
+    ///
+    /// 下次启动后用 [`YuShi::load_queue_from_state`] 加载队列，恢复暂停的任务即可继续下载。
+    pub async fn shutdown(&self, timeout: Duration) -> Result<()> {
+        self.set_scheduler_state(SchedulerState {
+            running: false,
+            reason: Some(PausedReason::Shutdown),
+            next_wakeup: None,
+        })
+        .await;
+
+        let mut handles: Vec<_> = self.active_downloads.write().await.drain().collect();
+        for token in self.cancel_tokens.read().await.values() {
+            token.cancel();
+        }
+        let stopped = futures::future::join_all(handles.iter_mut().map(|(_, handle)| handle));
+        if tokio::time::timeout(timeout, stopped).await.is_err() {
+            for (_, handle) in &handles {
+                handle.abort();
+            }
+        }
+        self.cancel_tokens.write().await.clear();
+
+        // 停止前没有结束的任务下次继续
+        let mut paused = Vec::new();
+        {
+            let mut tasks = self.tasks.write().await;
+            for (task_id, _) in &handles {
+                let Some(task) = tasks.get_mut(task_id) else {
+                    continue;
+                };
+                if matches!(task.status, TaskStatus::Downloading | TaskStatus::Verifying) {
+                    task.status = TaskStatus::Paused;
+                    self.journal(task, CompactEventKind::Paused);
+                    self.progress.sync(task);
+                    self.store.mark_task(task_id);
+                    paused.push(task_id.clone());
+                }
+            }
+        }
+        self.compact_queue_state().await?;
+        for task_id in paused {
+            let _ = self
+                .queue_event_tx
+                .send(DownloaderEvent::Task(TaskEvent::Paused { task_id }));
+        }
+        self.flush_audit_log().await;
+        Ok(())
+    }
+
+    /// 更新调度器状态，发生变化时发送 [`GlobalEvent::SchedulerStateChanged`]
+    async fn set_scheduler_state(&self, state: SchedulerState) {
+        {
+            let mut current = self.scheduler.lock().unwrap();
+            if *current == state {
+                return;
+            }
+            *current = state.clone();
+        }
+        let _ =
+            self.queue_event_tx
+                .send(DownloaderEvent::Global(GlobalEvent::SchedulerStateChanged(
+                    state,
+                )));
+    }
+
+    /// 清空所有已完成的任务（归档，见 [`YuShi::remove_task`]）
+    pub async fn clear_completed(&self) -> Result<()> {
+        let mut tasks = self.tasks.write().await;
+        let mut sinks = self.sinks.write().await;
+        let mut url_refreshers = self.url_refreshers.write().await;
+        for (id, task) in tasks.iter_mut() {
+            if task.status == TaskStatus::Completed && !task.archived {
+                archive(task);
+                sinks.remove(id);
+                url_refreshers.remove(id);
+                self.progress.remove(id);
+                self.store.mark_task(id);
+            }
+        }
+        drop(url_refreshers);
+        drop(sinks);
+        drop(tasks);
+        self.save_queue_state().await?;
+        Ok(())
+    }
+
+    // ==================== 任务组方法 ====================
+
+    /// 添加一组任务，作为整体进行暂停、取消和进度跟踪
+    ///
+    /// # 参数
+    /// * `name` - 任务组名称
+    /// * `tasks` - 成员任务
+    ///
+    /// # 返回
+    /// 返回任务组 ID
+    pub async fn add_group(&self, name: String, tasks: Vec<NewTask>) -> Result<String> {
+        let group_id = new_id();
+        let group = TaskGroup {
+            id: group_id.clone(),
+            name,
+            created_at: current_timestamp(),
+            completed_at: None,
+        };
+        self.groups.write().await.insert(group_id.clone(), group);
+        self.store.mark_group(&group_id);
+
+        for new_task in tasks {
+            self.insert_task(
+                new_task.url,
+                new_task.dest,
+                new_task.options,
+                Some(group_id.clone()),
+            )
+            .await;
+        }
+
+        self.save_queue_state().await?;
+        self.process_queue().await?;
+
+        Ok(group_id)
+    }
+
+    /// 获取所有任务组
+    pub async fn get_groups(&self) -> Vec<TaskGroup> {
+        self.groups.read().await.values().cloned().collect()
+    }
+
+    /// 获取任务组的成员任务 ID
+    async fn group_members(&self, group_id: &str) -> Result<Vec<String>> {
+        if !self.groups.read().await.contains_key(group_id) {
+            return Err(Error::GroupNotFound);
+        }
+        let tasks = self.tasks.read().await;
+        Ok(tasks
+            .values()
+            .filter(|t| t.group_id.as_deref() == Some(group_id))
+            .map(|t| t.id.clone())
+            .collect())
+    }
+
+    /// 暂停任务组中所有正在下载的任务，只校验本地文件的任务不受影响
+    pub async fn pause_group(&self, group_id: &str) -> Result<()> {
+        for task_id in self.group_members(group_id).await? {
+            match self.pause_task(&task_id).await {
+                Ok(()) | Err(Error::CannotPauseVerifyOnlyTask) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// 恢复任务组中所有已暂停的任务
+    pub async fn resume_group(&self, group_id: &str) -> Result<()> {
+        for task_id in self.group_members(group_id).await? {
+            self.resume_task(&task_id).await?;
+        }
+        Ok(())
+    }
+
+    /// 取消任务组中所有未结束的任务
+    pub async fn cancel_group(&self, group_id: &str) -> Result<()> {
+        for task_id in self.group_members(group_id).await? {
+            let unfinished = self.get_task(&task_id).await.is_some_and(|t| {
+                matches!(
+                    t.status,
+                    TaskStatus::Pending
+                        | TaskStatus::Downloading
+                        | TaskStatus::Verifying
+                        | TaskStatus::Paused
+                )
+            });
+            if unfinished {
+                self.cancel_task(&task_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 获取任务组的汇总进度
+    pub async fn get_group_progress(&self, group_id: &str) -> Option<GroupProgress> {
+        let name = self.groups.read().await.get(group_id)?.name.clone();
+        let tasks = self.tasks.read().await;
+        let members: Vec<&Task> = tasks
+            .values()
+            .filter(|t| t.group_id.as_deref() == Some(group_id))
+            .collect();
+
+        let mut progress = GroupProgress {
+            group_id: group_id.to_string(),
+            name,
+            total_tasks: members.len(),
+            completed_tasks: 0,
+            failed_tasks: 0,
+            downloaded: 0,
+            total_size: 0,
+            speed: 0,
+            eta: None,
+        };
+        let mut size_unknown = false;
+        for task in &members {
+            match task.status {
+                TaskStatus::Completed => progress.completed_tasks += 1,
+                TaskStatus::Failed | TaskStatus::Cancelled | TaskStatus::PartiallyCompleted => {
+                    progress.failed_tasks += 1
+                }
+                _ => {}
+            }
+            progress.downloaded += task.downloaded;
+            progress.total_size += task.total_size;
+            progress.speed += task.speed;
+            if task.total_size == 0 && task.status != TaskStatus::Completed {
+                size_unknown = true;
+            }
+        }
+        if !size_unknown && progress.speed > 0 {
+            let remaining = progress.total_size.saturating_sub(progress.downloaded);
+            progress.eta = Some(remaining / progress.speed);
+        }
+        Some(progress)
+    }
+
+    /// 任务组的所有成员都结束时发送 `GroupCompleted` 事件
+    async fn check_group_completed(&self, group_id: &str) {
+        let failed = {
+            let tasks = self.tasks.read().await;
+            let mut failed = Vec::new();
+            for task in tasks
+                .values()
+                .filter(|t| t.group_id.as_deref() == Some(group_id))
+            {
+                match task.status {
+                    TaskStatus::Completed => {}
+                    TaskStatus::Failed | TaskStatus::Cancelled | TaskStatus::PartiallyCompleted => {
+                        failed.push(task.id.clone())
+                    }
+                    _ => return,
+                }
+            }
+            failed
+        };
+
+        // 只发送一次
+        {
+            let mut groups = self.groups.write().await;
+            match groups.get_mut(group_id) {
+                Some(group) if group.completed_at.is_none() => {
+                    group.completed_at = Some(current_timestamp());
+                }
+                _ => return,
+            }
+        }
+        self.store.mark_group(group_id);
+        let _ = self.save_queue_state().await;
+
+        let _ = self
+            .queue_event_tx
+            .send(DownloaderEvent::Task(TaskEvent::GroupCompleted {
+                group_id: group_id.to_string(),
+                failed,
+            }));
+    }
+
+    /// 校验任务文件，校验期间任务处于 `Verifying` 状态
+    ///
+    /// 校验进度通过 [`VerificationEvent::Progress`] 发送；`cancel` 被取消时返回
+    /// [`Error::VerificationCancelled`]，此时任务状态由调用取消的一方负责设置。
+    ///
+    /// 分块下载的文件校验失败时，会用下载时记录的分块摘要找出损坏的分块，
+    /// 只重新下载这些分块后再校验一次。
+    async fn verify_task(
+        &self,
+        task: &Task,
+        sink: SharedSink,
+        checksum: &ChecksumType,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        {
+            let mut tasks = self.tasks.write().await;
+            if let Some(task) = tasks.get_mut(&task.id) {
+                task.status = TaskStatus::Verifying;
+                task.verification_pending = true;
+                task.verified = 0;
+                self.journal(task, CompactEventKind::VerifyStarted);
+                self.progress.sync(task);
+            }
+        }
+        self.store.mark_task(&task.id);
+        let _ = self.save_queue_state().await;
+        let _ =
+            self.queue_event_tx
+                .send(DownloaderEvent::Verification(VerificationEvent::Started {
+                    task_id: task.id.clone(),
+                }));
+
+        let mut result = self.verify_file_limited(task, checksum, cancel).await;
+        let mut repaired_chunks = 0;
+        if matches!(result, Ok(false)) {
+            result = match self.repair_chunks(task, sink, cancel).await {
+                Ok(Some(0)) => Err(Error::ChecksumMismatch(message(
+                    "verify.chunks_intact",
+                    &[],
+                ))),
+                Ok(Some(repaired)) => {
+                    repaired_chunks = repaired;
+                    self.verify_file_limited(task, checksum, cancel).await
+                }
+                Ok(None) => Ok(false),
+                Err(e) => Err(e),
+            };
+        }
+
+        if matches!(result, Err(Error::VerificationCancelled)) {
+            return Err(Error::VerificationCancelled);
+        }
+        if let Some(task) = self.tasks.write().await.get_mut(&task.id) {
+            task.verification_pending = false;
+            if let Ok(&success) = result.as_ref() {
+                self.journal(
+                    task,
+                    CompactEventKind::VerifyCompleted {
+                        success,
+                        repaired_chunks,
+                    },
+                );
+            }
+        }
+
+        let success = result?;
+        let _ = self.queue_event_tx.send(DownloaderEvent::Verification(
+            VerificationEvent::Completed {
+                task_id: task.id.clone(),
+                success,
+                repaired_chunks,
+            },
+        ));
+        if success {
+            Ok(())
+        } else {
+            Err(Error::ChecksumVerificationFailed)
+        }
+    }
+
+    /// 重新校验一个已完成的任务，不修改任务状态
+    async fn reverify_task(&self, task: &Task) -> Result<bool> {
+        let Some(checksum) = &task.checksum else {
+            return Ok(true);
+        };
+        let cancel = CancellationToken::new();
+        let _permit = self.verify_permit(&cancel).await?;
+        let _ =
+            self.queue_event_tx
+                .send(DownloaderEvent::Verification(VerificationEvent::Started {
+                    task_id: task.id.clone(),
+                }));
+        let success = match self
+            .verify_file_progress(task, &task.dest, checksum, &cancel)
+            .await
+        {
+            Ok(success) => success,
+            Err(Error::IOError(_)) => false,
+            Err(e) => return Err(e),
+        };
+        let _ = self.queue_event_tx.send(DownloaderEvent::Verification(
+            VerificationEvent::Completed {
+                task_id: task.id.clone(),
+                success,
+                repaired_chunks: 0,
+            },
+        ));
+        Ok(success)
+    }
+
+    /// 等待校验名额，`cancel` 被取消时返回 [`Error::VerificationCancelled`]
+    async fn verify_permit(&self, cancel: &CancellationToken) -> Result<SemaphorePermit<'_>> {
+        tokio::select! {
+            permit = self.verify_permits.acquire() => Ok(permit?),
+            _ = cancel.cancelled() => Err(Error::VerificationCancelled),
+        }
+    }
+
+    /// 取得校验名额后校验整个文件
+    async fn verify_file_limited(
+        &self,
+        task: &Task,
+        checksum: &ChecksumType,
+        cancel: &CancellationToken,
+    ) -> Result<bool> {
+        let _permit = self.verify_permit(cancel).await?;
+        self.verify_file_progress(task, &self.work_path(task), checksum, cancel)
+            .await
+    }
+
+    /// 校验 `path` 处的整个文件并发送校验进度
+    #[cfg(feature = "checksums")]
+    async fn verify_file_progress(
+        &self,
+        task: &Task,
+        path: &Path,
+        checksum: &ChecksumType,
+        cancel: &CancellationToken,
+    ) -> Result<bool> {
+        let counters = self.progress.get(&task.id);
+        verify_file_with(path, checksum, cancel, |verified, total| {
+            if let Some(counters) = &counters {
+                counters.set_verified(verified);
+            }
+            if let Ok(mut tasks) = self.tasks.try_write()
+                && let Some(task) = tasks.get_mut(&task.id)
+            {
+                task.verified = verified;
+            }
+            let _ = self.queue_event_tx.send(DownloaderEvent::Verification(
+                VerificationEvent::Progress {
+                    task_id: task.id.clone(),
+                    verified,
+                    total,
+                },
+            ));
+        })
+        .await
+    }
+
+    /// 没有启用 `checksums` 特性时无法校验
+    #[cfg(not(feature = "checksums"))]
+    async fn verify_file_progress(
+        &self,
+        _task: &Task,
+        _path: &Path,
+        _checksum: &ChecksumType,
+        _cancel: &CancellationToken,
+    ) -> Result<bool> {
+        Err(Error::ChecksumsDisabled)
+    }
+
+    /// 重新下载与记录摘要不一致的分块
+    ///
+    /// 返回重新下载的分块数；没有分块状态（流式下载或自定义存储后端）时返回 None。
+    /// 没有记录摘要的分块无法确认是否完好，也会重新下载。
+    async fn repair_chunks(
+        &self,
+        task: &Task,
+        sink: SharedSink,
+        cancel: &CancellationToken,
+    ) -> Result<Option<usize>> {
+        if self.sinks.read().await.contains_key(&task.id) {
+            return Ok(None);
+        }
+        let state_path = self.state_path(task);
+        let work_path = self.work_path(task);
+        let Some(mut state) = DownloadState::load(&state_path).await? else {
+            return Ok(None);
+        };
+        if state.is_streaming || !task.owns_url(&state.url) {
+            return Ok(None);
+        }
+
+        let mut repaired = 0;
+        for chunk in &mut state.chunks {
+            if cancel.is_cancelled() {
+                return Err(Error::VerificationCancelled);
+            }
+            if !chunk_intact(&work_path, chunk).await? {
+                chunk.current = chunk.start;
+                chunk.is_finished = false;
+                chunk.digest = None;
+                repaired += 1;
+            }
+        }
+        if repaired == 0 {
+            return Ok(Some(0));
+        }
+        state.save(&state_path).await?;
+
+        // 修复下载的进度不计入任务进度
+        let (tx, mut rx) = mpsc::channel(1024);
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        sink.open(state.total_size, true).await?;
+        let state = Arc::new(RwLock::new(state));
+        tokio::select! {
+            _ = cancel.cancelled() => return Err(Error::VerificationCancelled),
+            result = self.download_chunked(&task.id, state, sink, &state_path, tx) => result?,
+        }
+        Ok(Some(repaired))
+    }
+
+    /// 任务进入终态时追加审计日志记录
+    fn audit(&self, task: &Task) {
+        let started = self.run_started.lock().unwrap().remove(&task.id);
+        let Some(audit) = &self.audit else {
+            return;
+        };
+        let checksum = match task.status {
+            TaskStatus::Completed => task.checksum.clone(),
+            _ => None,
+        };
+        audit.record(AuditRecord {
+            task_id: task.id.clone(),
+            url: task.url.clone(),
+            dest: task.dest.clone(),
+            status: task.status,
+            bytes: task.downloaded,
+            checksum,
+            duration_ms: started.map_or(0, |s| s.elapsed().as_millis() as u64),
+            error: task.error.clone(),
+            finished_at: current_timestamp(),
+            provenance: task.provenance.clone(),
+            skipped_unchanged: task.skipped_unchanged,
+            average_speed: task.average_speed,
+            peak_speed: task.peak_speed,
+        });
+    }
+
+    /// `dest` 所在目录是否按不区分大小写比较文件名（见 [`Config::case_insensitive_fs`]）
+    fn case_insensitive(&self, dest: &Path) -> bool {
+        match self.config.case_insensitive_fs {
+            CaseInsensitiveFs::On => true,
+            CaseInsensitiveFs::Off => false,
+            CaseInsensitiveFs::Auto => {
+                let dir = dest.parent().unwrap_or_else(|| Path::new(""));
+                let mut probes = self.case_probes.lock().unwrap();
+                *probes.entry(dir.to_path_buf()).or_insert_with(|| {
+                    probe_case_insensitive(dir)
+                        .unwrap_or(cfg!(any(target_os = "macos", target_os = "windows")))
+                })
+            }
+        }
+    }
+
+    /// 在任务的事件记录中追加一条，[`Config::event_journal`] 为 false 时不记录
+    fn journal(&self, task: &mut Task, kind: CompactEventKind) {
+        if self.config.event_journal {
+            task.record_event(kind);
+        }
+    }
+
+    /// 记录任务当前的下载环境，任务自己的请求头覆盖配置中的同名请求头
+    fn capture_provenance(&self, task: &Task) -> Provenance {
+        let headers = self
+            .config
+            .headers
+            .iter()
+            .chain(&task.headers)
+            .map(|(name, value)| (name.to_ascii_lowercase(), redact_header(name, value)))
+            .collect();
+        Provenance {
+            core_version: env!("CARGO_PKG_VERSION").to_string(),
+            captured_at: current_timestamp(),
+            url: task.url.clone(),
+            user_agent: self.config.user_agent.clone(),
+            headers,
+            proxy: self.config.proxy.is_some(),
+            connections: task.connections.unwrap_or(self.default_connections()),
+            chunk_size: self.config.chunk_size,
+            speed_limit: self.speed_limit_for(task),
+            byte_range: task.byte_range,
+            skip_probe: task.skip_probe,
+        }
+    }
+
+    /// 获取任务的存储后端，未指定时使用写入 [`YuShi::work_path`] 的本地文件
+    async fn task_sink(&self, task: &Task) -> SharedSink {
+        match self.sinks.read().await.get(&task.id) {
+            Some(sink) => Arc::clone(sink),
+            None => Arc::new(
+                FileSink::new(self.work_path(task)).with_preallocate(self.config.preallocate),
+            ),
+        }
+    }
+
+    /// 目标是已存在的目录时确定目录中的文件，并把任务的 `dest` 更新为这个文件
+    ///
+    /// 文件名依次取探测时 `Content-Disposition` 建议的文件名、重定向后的 URL 的最后一个
+    /// 路径段和 `download`。文件已存在时自动重命名，[`ConflictPolicy::IfChanged`] 的任务除外。
+    /// 返回任务的目标文件，目标不是目录时原样返回。
+    async fn resolve_directory_dest(&self, task: &Task) -> Result<PathBuf> {
+        if task.kind != TaskKind::Download
+            || !fs::metadata(&task.dest).await.is_ok_and(|m| m.is_dir())
+        {
+            return Ok(task.dest.clone());
+        }
+        let info = self.probe_task(task).await?;
+        let url = self
+            .tasks
+            .read()
+            .await
+            .get(&task.id)
+            .and_then(|t| t.resolved_url.clone())
+            .unwrap_or_else(|| task.url.clone());
+        let name = info
+            .filename
+            .or_else(|| filename_from_url(&url))
+            .unwrap_or_default();
+        let mut dest = resolve_destination(&task.dest, &name);
+        if task.conflict != ConflictPolicy::IfChanged {
+            dest = auto_rename_avoiding(&dest, self.case_insensitive(&dest), self.part_suffix());
+        }
+
+        if let Some(task) = self.tasks.write().await.get_mut(&task.id) {
+            task.dest = dest.clone();
+        }
+        self.store.mark_task(&task.id);
+        self.save_queue_state().await?;
+        Ok(dest)
+    }
+
+    /// 下载过程中写入的文件
+    ///
+    /// 设置了 [`Config::temp_dir`] 时位于临时目录中、以任务 ID 命名，否则是 `dest` 加上
+    /// [`Config::part_suffix`]，没有后缀时就是 `dest`。
+    fn work_path(&self, task: &Task) -> PathBuf {
+        if task.kind == TaskKind::VerifyOnly {
+            return task.dest.clone();
+        }
+        match &self.config.temp_dir {
+            Some(dir) => dir.join(&task.id),
+            // 远程文件变化时才下载的任务先写入隐藏文件，完成后再替换已有文件
+            None if task.conflict == ConflictPolicy::IfChanged => {
+                with_affixes(&task.dest, ".", &format!(".{}.part", task.id))
+            }
+            None => match &self.config.part_suffix {
+                Some(suffix) => with_affixes(&task.dest, "", suffix),
+                None => task.dest.clone(),
+            },
+        }
+    }
+
+    /// 自动重命名时还要避开的下载中文件的后缀
+    fn part_suffix(&self) -> Option<&str> {
+        match self.config.temp_dir {
+            Some(_) => None,
+            None => self.config.part_suffix.as_deref(),
+        }
+    }
+
+    /// 把旧版本直接写入 `dest` 的未完成下载移动到 [`YuShi::work_path`]
+    ///
+    /// 旧版本的状态文件是 `dest` 换成 `.json` 扩展名，只有其中记录的 URL 与任务相同时才移动。
+    async fn migrate_legacy_partial(&self, task: &Task) {
+        let work_path = self.work_path(task);
+        if work_path == task.dest
+            || self.config.temp_dir.is_some()
+            || task.conflict == ConflictPolicy::IfChanged
+            || fs::try_exists(&work_path).await.unwrap_or(true)
+        {
+            return;
+        }
+        let legacy_state = task.dest.with_extension("json");
+        let Ok(Some(state)) = DownloadState::load(&legacy_state).await else {
+            return;
+        };
+        if state.url != task.url || !fs::try_exists(&task.dest).await.unwrap_or(false) {
+            return;
+        }
+        if fs::rename(&task.dest, &work_path).await.is_ok() {
+            let _ = fs::rename(&legacy_state, self.state_path(task)).await;
+        }
+    }
+
+    /// 断点续传状态文件，与 [`YuShi::work_path`] 放在一起
+    fn state_path(&self, task: &Task) -> PathBuf {
+        self.work_path(task).with_extension("json")
+    }
+
+    /// 断点续传状态文件中记录的已下载字节数，没有属于任务的状态文件时返回 None
+    async fn saved_progress(&self, task: &Task) -> Option<u64> {
+        let state = DownloadState::load(&self.state_path(task)).await.ok()??;
+        if !task.owns_url(&state.url) {
+            return None;
+        }
+        Some(match state.is_streaming {
+            true => state.streamed,
+            false => state.chunks.iter().map(|c| c.current - c.start).sum(),
+        })
+    }
+
+    /// 上次下载同一目标文件时记录的 `ETag` / `Last-Modified`
+    ///
+    /// 优先使用同一 URL、同一目标路径的已完成任务上的探测结果，没有时读取
+    /// 目标文件旁的记录文件（见 [`validators_path`]）。
+    async fn previous_validators(&self, task: &Task) -> Option<RemoteInfo> {
+        let usable = |info: &RemoteInfo| info.etag.is_some() || info.last_modified.is_some();
+        let from_task = self
+            .tasks
+            .read()
+            .await
+            .values()
+            .filter(|t| {
+                t.id != task.id
+                    && t.status == TaskStatus::Completed
+                    && t.url == task.url
+                    && t.dest == task.dest
+            })
+            .max_by_key(|t| t.created_at)
+            .and_then(|t| t.remote.clone())
+            .filter(usable);
+        if from_task.is_some() {
+            return from_task;
+        }
+        let content = fs::read_to_string(validators_path(&task.dest)).await.ok()?;
+        let stored: StoredValidators = serde_json::from_str(&content).ok()?;
+        (stored.url == task.url)
+            .then_some(stored.remote)
+            .filter(usable)
+    }
+
+    /// 目标文件存在且远程文件自上次下载后没有变化时，返回上次的探测结果
+    ///
+    /// 用上次的 `ETag` / `Last-Modified` 发送条件 GET 请求，只有 304 视为没有变化；
+    /// 其他响应（包括请求失败）都按正常下载处理，响应体不读取。
+    async fn check_unchanged(&self, task: &Task) -> Option<RemoteInfo> {
+        if !fs::try_exists(&task.dest).await.unwrap_or(false) {
+            return None;
+        }
+        let previous = self.previous_validators(task).await?;
+        let mut request = self.task_request(&task.url, &task.headers, task.auth.as_ref());
+        if let Some(etag) = &previous.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &previous.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send().await.ok()?;
+        (response.status() == StatusCode::NOT_MODIFIED).then_some(previous)
+    }
+
+    /// 把任务的 `ETag` / `Last-Modified` 写入目标文件旁的记录文件，供下次条件请求使用
+    async fn save_validators(&self, task_id: &str) {
+        let Some(task) = self.tasks.read().await.get(task_id).cloned() else {
+            return;
+        };
+        let Some(remote) = task.remote else {
+            return;
+        };
+        if remote.etag.is_none() && remote.last_modified.is_none() {
+            return;
+        }
+        let stored = StoredValidators {
+            url: task.url,
+            remote,
+        };
+        if let Ok(content) = serde_json::to_string(&stored) {
+            let _ = fs::write(validators_path(&task.dest), content).await;
+        }
+    }
+
+    /// 把临时目录中下载完成的文件移动到 `dest`
+    ///
+    /// 同一文件系统内直接重命名，否则复制后删除临时文件。
+    async fn finalize_task_file(
+        &self,
+        task: &Task,
+        event_tx: &mpsc::Sender<ProgressEvent>,
+    ) -> Result<()> {
+        let work_path = self.work_path(task);
+        if work_path == task.dest || self.sinks.read().await.contains_key(&task.id) {
+            return Ok(());
+        }
+        if let Some(parent) = task.dest.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        match fs::rename(&work_path, &task.dest).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                move_by_copy(&task.id, &work_path, &task.dest, event_tx).await
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 在 [`Config::completed_link_dir`] 中创建指向完成文件的链接，返回链接路径
+    ///
+    /// 没有设置链接目录、数据写入自定义存储后端或文件本来就在链接目录中时返回 None。
+    /// 创建失败时发送 [`TaskEvent::LinkFailed`] 并返回 None，不影响任务状态。
+    async fn link_completed(&self, task: &Task) -> Option<PathBuf> {
+        let dir = self.config.completed_link_dir.as_ref()?;
+        let name = task.dest.file_name()?;
+        if self.sinks.read().await.contains_key(&task.id)
+            || task
+                .dest
+                .parent()
+                .is_some_and(|parent| same_dir(parent, dir))
+        {
+            return None;
+        }
+        let link = dir.join(name);
+        let link = resolve_dest(
+            link.clone(),
+            task.conflict,
+            self.case_insensitive(&link),
+            None,
+        );
+        match link_file(&task.dest, &link, self.config.completed_link_copy).await {
+            Ok(()) => Some(link),
+            Err(e) => {
+                let _ = self
+                    .queue_event_tx
+                    .send(DownloaderEvent::Task(TaskEvent::LinkFailed {
+                        task_id: task.id.clone(),
+                        error: e.to_string(),
+                    }));
+                None
+            }
+        }
+    }
+}
+
+/// 两个路径是否指向同一目录
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// 在 `link` 处创建指向 `target` 的链接
+///
+/// 先尝试符号链接，失败时尝试硬链接（只能在同一文件系统内），`copy` 为 true 时最后
+/// 复制文件。`link` 处已有的文件先删除，名称冲突应该已经按任务的冲突处理方式解决。
+async fn link_file(target: &Path, link: &Path, copy: bool) -> std::io::Result<()> {
+    let target = std::path::absolute(target)?;
+    if fs::symlink_metadata(link).await.is_ok() {
+        fs::remove_file(link).await?;
+    }
+    #[cfg(unix)]
+    let symlink = fs::symlink(&target, link).await;
+    #[cfg(windows)]
+    let symlink = fs::symlink_file(&target, link).await;
+    #[cfg(not(any(unix, windows)))]
+    let symlink: std::io::Result<()> = Err(std::io::ErrorKind::Unsupported.into());
+    if symlink.is_ok() {
+        return Ok(());
+    }
+    match fs::hard_link(&target, link).await {
+        Ok(()) => Ok(()),
+        Err(_) if copy => fs::copy(&target, link).await.map(|_| ()),
+        Err(e) => Err(e),
+    }
+}
+
+/// 跨文件系统移动文件
+///
+/// 先复制到目标目录中的隐藏文件，复制过程中发送 [`ProgressEvent::Finalizing`]，
+/// 完成后重命名为 `dest` 并删除 `src`，其他程序不会看到复制了一半的文件。
+async fn move_by_copy(
+    task_id: &str,
+    src: &Path,
+    dest: &Path,
+    event_tx: &mpsc::Sender<ProgressEvent>,
+) -> Result<()> {
+    const BUFFER_SIZE: usize = 1024 * 1024;
+
+    let partial = with_affixes(dest, ".", ".yushi-move");
+    let total = fs::metadata(src).await?.len();
+    let mut reader = fs::File::open(src).await?;
+    let mut writer = fs::File::create(&partial).await?;
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut moved = 0u64;
+
+    let copied: Result<()> = async {
+        loop {
+            let _ = event_tx
+                .send(ProgressEvent::Finalizing {
+                    task_id: task_id.to_string(),
+                    moved,
+                    total,
+                })
+                .await;
+            let n = reader.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..n]).await?;
+            moved += n as u64;
+        }
+        writer.sync_all().await?;
+        Ok(())
+    }
+    .await;
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&partial).await;
+        return Err(e);
+    }
+
+    fs::rename(&partial, dest).await?;
+    fs::remove_file(src).await?;
+    Ok(())
+}
+
+/// 磁盘上的分块数据是否与下载时记录的摘要一致，没有摘要时返回 false
+#[cfg(feature = "checksums")]
+async fn chunk_intact(path: &Path, chunk: &ChunkState) -> Result<bool> {
+    match &chunk.digest {
+        Some(digest) => {
+            let len = chunk.end - chunk.start + 1;
+            Ok(hash_file_range(path, chunk.start, len).await? == *digest)
+        }
+        None => Ok(false),
+    }
+}
+
+/// 没有启用 `checksums` 特性时无法计算摘要，所有分块都视为需要重新下载
+#[cfg(not(feature = "checksums"))]
+async fn chunk_intact(_path: &Path, _chunk: &ChunkState) -> Result<bool> {
+    Ok(false)
+}
+
+/// 归档任务，见 [`YuShi::remove_task`]
+fn archive(task: &mut Task) {
+    task.archived = true;
+    task.archived_at = Some(current_timestamp());
+}
+
+/// 目标文件已存在且允许自动重命名时返回不冲突的新路径
+fn resolve_dest(
+    dest: PathBuf,
+    conflict: ConflictPolicy,
+    case_insensitive: bool,
+    part_suffix: Option<&str>,
+) -> PathBuf {
+    if conflict == ConflictPolicy::Rename {
+        auto_rename_avoiding(&dest, case_insensitive, part_suffix)
+    } else {
+        dest
+    }
+}
+
+/// 请求头中是否有这个名称（不区分大小写）
+fn has_header(headers: &HashMap<String, String>, name: &str) -> bool {
+    headers.keys().any(|key| key.eq_ignore_ascii_case(name))
+}
+
+/// 任务是否处于临时加速中，见 [`YuShi::boost_task`]
+fn boosted(boost: &AtomicU64) -> bool {
+    boost.load(Ordering::Relaxed) > current_timestamp_ms()
+}
+
+/// 读取响应体的下一段，超过 `timeout` 没有收到数据时返回 Err
+async fn next_or_stall<S: futures::Stream + Unpin>(
+    stream: &mut S,
+    timeout: Option<Duration>,
+) -> std::result::Result<Option<S::Item>, tokio::time::error::Elapsed> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, stream.next()).await,
+        None => Ok(stream.next().await),
+    }
+}
+
+/// 跟随重定向的策略：回到访问过的地址或超过 `max` 次时停止，见 [`Config::max_redirects`]
+fn redirect_policy(max: usize) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().contains(attempt.url()) {
+            let url = redact_url(attempt.url().as_str());
+            attempt.error(RedirectError::Loop(url))
+        } else if attempt.previous().len() > max {
+            attempt.error(RedirectError::TooMany(max))
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// 把 [`Config::resolve`] 按小写的主机名分组，同一主机的地址保持配置中的顺序
+fn resolve_overrides(entries: &[(String, SocketAddr)]) -> HashMap<String, Vec<SocketAddr>> {
+    let mut overrides: HashMap<String, Vec<SocketAddr>> = HashMap::new();
+    for (host, addr) in entries {
+        overrides
+            .entry(host.to_ascii_lowercase())
+            .or_default()
+            .push(*addr);
+    }
+    overrides
+}
+
+/// 代理 URL 支持的协议，没有写协议时按 `http` 处理
+const PROXY_SCHEMES: [&str; 4] = ["http", "https", "socks5", "socks5h"];
+
+/// 按配置创建代理，地址无法解析或协议不支持时返回 [`Error::InvalidProxy`]
+fn build_proxy(config: &Config) -> Result<Option<Proxy>> {
+    let Some(proxy_url) = &config.proxy else {
+        return Ok(None);
+    };
+    let invalid = || Error::InvalidProxy(redact_url(proxy_url));
+    let url = match proxy_url.contains("://") {
+        true => Url::parse(proxy_url),
+        false => Url::parse(&format!("http://{}", proxy_url)),
+    }
+    .map_err(|_| invalid())?;
+    if !PROXY_SCHEMES.contains(&url.scheme()) || url.host_str().is_none() {
+        return Err(invalid());
+    }
+    let mut proxy = Proxy::all(url.as_str()).map_err(|_| invalid())?;
+    if let Some(user) = &config.proxy_user {
+        proxy = proxy.basic_auth(user, config.proxy_password.as_deref().unwrap_or_default());
+    }
+    Ok(Some(proxy))
+}
+
+/// 读取 [`Config::extra_root_certs`] 中的证书
+///
+/// 每个文件单独检查，无法读取、没有证书或证书无法解析时返回
+/// [`Error::InvalidCertificate`]，而不是等到第一次请求时才失败。
+fn load_root_certs(paths: &[PathBuf]) -> Result<Vec<Certificate>> {
+    let mut certs = Vec::new();
+    for path in paths {
+        let invalid =
+            |reason: String| Error::InvalidCertificate(path.display().to_string(), reason);
+        let pem = std::fs::read(path).map_err(|e| invalid(e.to_string()))?;
+        let bundle = Certificate::from_pem_bundle(&pem).map_err(|e| invalid(error_reason(&e)))?;
+        if bundle.is_empty() {
+            return Err(invalid(message("error.no_certificates", &[])));
+        }
+        // 证书内容在创建客户端时才解析，只用这些证书创建一次以便报告是哪个文件有问题
+        Client::builder()
+            .tls_certs_only(bundle.clone())
+            .build()
+            .map_err(|e| invalid(error_reason(&e)))?;
+        certs.extend(bundle);
+    }
+    Ok(certs)
+}
+
+/// reqwest 错误的具体原因，外层只是笼统的“builder error”
+fn error_reason(error: &reqwest::Error) -> String {
+    std::error::Error::source(error)
+        .map(ToString::to_string)
+        .unwrap_or_else(|| error.to_string())
+}
+
+/// 跟随重定向后的地址，与原始地址相同时为 None
+fn resolved_url(original: &str, final_url: &Url) -> Option<String> {
+    (Url::parse(original).ok().as_ref() != Some(final_url)).then(|| final_url.to_string())
+}
+
+/// 服务器在 `Retry-After` 中要求的等待时间
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    parse_retry_after(
+        headers.get(RETRY_AFTER)?.to_str().ok()?,
+        current_timestamp(),
+    )
+}
+
+/// 从响应头中取出 `ETag` 和 `Last-Modified`
+fn validators(headers: &HeaderMap) -> (Option<String>, Option<String>) {
+    let get = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    (get(ETAG), get(LAST_MODIFIED))
+}
+
+/// `Content-Disposition` 响应头中建议的文件名
+///
+/// 有些服务器直接在 `filename` 中写 UTF-8，按 UTF-8 读取头的值。
+fn suggested_filename(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(CONTENT_DISPOSITION)?;
+    filename_from_content_disposition(&String::from_utf8_lossy(value.as_bytes()))
+}
+
+/// 单个分块的下载结果
+enum ChunkOutcome {
+    /// 之前已经下载完成
+    AlreadyFinished,
+    /// 这次下载完成
+    Finished,
+    /// 重试次数用尽，是否让任务失败由 [`Config::on_chunk_exhausted`] 决定
+    Exhausted(Error),
+}
+
+/// 分块请求被拒绝时刷新任务的下载地址（见 [`TaskOptions::url_refresher`]）
+struct UrlRefresh {
+    downloader: YuShi,
+    refresher: UrlRefresher,
+    /// 多个分块同时被拒绝时只刷新一次
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl UrlRefresh {
+    /// 用 `failed_url` 请求被拒绝后刷新地址，其他分块已经刷新过时直接返回
+    async fn refresh(
+        &self,
+        task_id: &str,
+        state: &RwLock<DownloadState>,
+        state_file: &Path,
+        failed_url: &str,
+    ) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        if state.read().await.url != failed_url {
+            return Ok(());
+        }
+        let url = (self.refresher)(task_id.to_string(), failed_url.to_string())
+            .await
+            .map_err(|e| Error::HttpError(message("download.url_refresh_failed", &[&e])))?;
+        {
+            let mut s = state.write().await;
+            s.url = url.clone();
+            s.save(state_file).await?;
+        }
+        // 任务和下载状态中的地址保持一致，之后续传时不会当作新下载
+        if let Some(task) = self.downloader.tasks.write().await.get_mut(task_id) {
+            task.replace_url(url);
+        }
+        self.downloader.store.mark_task(task_id);
+        Ok(())
+    }
+}
+
+/// 重定向后的地址（[`Task::resolved_url`]）请求失败时改回任务的原始地址
+struct UrlFallback {
+    downloader: YuShi,
+}
+
+impl UrlFallback {
+    /// `failed_url` 不是任务的原始地址时改用原始地址，返回是否切换了地址
+    async fn fall_back(
+        &self,
+        task_id: &str,
+        state: &RwLock<DownloadState>,
+        state_file: &Path,
+        failed_url: &str,
+    ) -> Result<bool> {
+        let original = match self.downloader.tasks.read().await.get(task_id) {
+            Some(task) if task.url != failed_url => task.url.clone(),
+            _ => return Ok(false),
+        };
+        {
+            let mut s = state.write().await;
+            // 其他分块已经切换过时不再修改
+            if s.url == failed_url {
+                s.url = original;
+                s.save(state_file).await?;
+            }
+        }
+        if let Some(task) = self.downloader.tasks.write().await.get_mut(task_id) {
+            task.resolved_url = None;
+        }
+        self.downloader.store.mark_task(task_id);
+        Ok(true)
+    }
+}
+
+/// 分块下载使用的镜像（[`TaskOptions::mirrors`]）
+///
+/// 分块用 `Option<usize>` 表示地址：None 是任务自己的地址（下载状态中的 `url`），从不停用；
+/// `Some(m)` 是第 m 个镜像，累计失败 [`MIRROR_MAX_FAILURES`] 次后在这次下载中不再使用。
+struct MirrorPool {
+    task_id: String,
+    mirrors: Vec<Mirror>,
+    /// 轮流分配地址的计数
+    next: AtomicUsize,
+    event_tx: mpsc::Sender<ProgressEvent>,
+}
+
+struct Mirror {
+    url: String,
+    credential: Option<Credential>,
+    failures: AtomicU32,
+    demoted: AtomicBool,
+}
+
+impl MirrorPool {
+    /// 轮流选择下一个地址，跳过已停用的镜像
+    fn pick(&self) -> Option<usize> {
+        loop {
+            let i = self.next.fetch_add(1, Ordering::Relaxed) % (self.mirrors.len() + 1);
+            match i.checked_sub(1) {
+                Some(m) if self.is_demoted(m) => continue,
+                source => return source,
+            }
+        }
+    }
+
+    /// `source` 之后的下一个可用地址，最后一个镜像之后回到任务自己的地址
+    fn after(&self, source: Option<usize>) -> Option<usize> {
+        let start = source.map_or(0, |m| m + 1);
+        (start..self.mirrors.len()).find(|&m| !self.is_demoted(m))
+    }
+
+    fn url(&self, m: usize) -> &str {
+        &self.mirrors[m].url
+    }
+
+    fn credential(&self, m: usize) -> Option<&Credential> {
+        self.mirrors[m].credential.as_ref()
+    }
+
+    fn is_demoted(&self, m: usize) -> bool {
+        self.mirrors[m].demoted.load(Ordering::Relaxed)
+    }
+
+    /// 停用镜像，每个镜像只发送一次 [`ProgressEvent::MirrorDemoted`]
+    async fn demote(&self, m: usize, reason: MirrorDemotion) {
+        if !self.mirrors[m].demoted.swap(true, Ordering::Relaxed) {
+            let _ = self
+                .event_tx
+                .send(ProgressEvent::MirrorDemoted {
+                    task_id: self.task_id.clone(),
+                    mirror: redact_url(&self.mirrors[m].url),
+                    reason,
+                })
+                .await;
+        }
+    }
+
+    /// 分块在 `source` 上又失败了一次，`failures` 为连续失败的次数
+    ///
+    /// 需要换用其他地址时返回新的地址，没有其他可用的地址时返回 None。
+    async fn failed(&self, source: Option<usize>, failures: u32) -> Option<Option<usize>> {
+        let demoted = match source {
+            Some(m) => {
+                let total = self.mirrors[m].failures.fetch_add(1, Ordering::Relaxed) + 1;
+                if total >= MIRROR_MAX_FAILURES {
+                    self.demote(m, MirrorDemotion::TooManyFailures).await;
+                }
+                self.is_demoted(m)
+            }
+            None => false,
+        };
+        if !demoted && failures < MIRROR_SWITCH_AFTER {
+            return None;
+        }
+        let next = self.after(source);
+        (next != source).then_some(next)
+    }
+}
+
+/// 目标文件旁记录的上次下载的校验信息
+#[derive(Serialize, Deserialize)]
+struct StoredValidators {
+    url: String,
+    #[serde(flatten)]
+    remote: RemoteInfo,
+}
+
+/// 保存上次下载的 `ETag` / `Last-Modified` 的文件，放在目标文件旁边
+fn validators_path(dest: &Path) -> PathBuf {
+    with_affixes(dest, ".", ".validators.json")
+}
+
+/// 任务要下载的总字节数，任务上和探测结果中都没有大小时返回 None
+fn expected_size(task: &Task, remote: Option<&RemoteInfo>) -> Option<u64> {
+    if task.total_size > 0 {
+        return Some(task.total_size);
+    }
+    let remote = remote?;
+    match task.byte_range {
+        Some(range) => byte_range_window(range, remote.total_size, remote.supports_range)
+            .ok()
+            .map(|(_, len)| len),
+        None => remote.total_size,
+    }
+}
+
+/// 计算下载范围在远程文件中的起始位置和长度
+///
+/// 结束位置超出文件末尾时截断到文件末尾。
+fn byte_range_window(
+    (start, end): (u64, Option<u64>),
+    remote_total: Option<u64>,
+    supports_range: bool,
+) -> Result<(u64, u64)> {
+    if !supports_range {
+        return Err(Error::TaskFailed(message(
+            "download.range_unsupported",
+            &[],
+        )));
+    }
+    if let Some(end) = end
+        && end < start
+    {
+        return Err(Error::TaskFailed(message(
+            "download.range_invalid",
+            &[&start, &end],
+        )));
+    }
+    let last = match (end, remote_total) {
+        (Some(end), Some(total)) => end.min(total.saturating_sub(1)),
+        (Some(end), None) => end,
+        (None, Some(total)) => total.saturating_sub(1),
+        (None, None) => {
+            return Err(Error::TaskFailed(message("download.range_needs_size", &[])));
+        }
+    };
+    if remote_total.is_some_and(|total| start >= total) {
+        return Err(Error::TaskFailed(message(
+            "download.range_beyond_end",
+            &[&start],
+        )));
+    }
+    Ok((start, last - start + 1))
+}
+
+/// 连续前缀至少增加这么多时才报告（分块完成时总是报告）
+const PREFIX_REPORT_STEP: u64 = 256 * 1024;
+
+/// 暂停或取消任务时等待下载停止的最长时间
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 请求失败后的最大重试次数
+const MAX_RETRIES: u32 = 5;
+
+/// 重试前的默认等待时间
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// 服务器要求的等待时间（`Retry-After`）过长时按这个上限等待
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// 分块在同一个地址上连续失败这么多次后换用下一个镜像
+const MIRROR_SWITCH_AFTER: u32 = 2;
+
+/// 镜像累计失败这么多次后在这次下载中不再使用
+const MIRROR_MAX_FAILURES: u32 = 5;
+
+/// 报告分块下载的连续前缀
+///
+/// 先让存储后端落盘再发送，报告的值不会超过其他进程能读到的数据。
+async fn report_prefix(
+    task_id: &str,
+    state: &RwLock<DownloadState>,
+    sink: &SharedSink,
+    tx: &mpsc::Sender<ProgressEvent>,
+    force: bool,
+) -> Result<()> {
+    let prefix = {
+        let mut s = state.write().await;
+        let prefix = s.contiguous_prefix();
+        let step = prefix.saturating_sub(s.reported_prefix);
+        if step == 0 || (!force && step < PREFIX_REPORT_STEP) {
+            return Ok(());
+        }
+        s.reported_prefix = prefix;
+        prefix
+    };
+    sink.flush().await?;
+    let _ = tx
+        .send(ProgressEvent::PrefixFlushed {
+            task_id: task_id.to_string(),
+            contiguous_prefix: prefix,
+        })
+        .await;
+    Ok(())
+}
+
+/// 416 响应对应的错误，`Content-Range: bytes */12345` 给出文件现在的大小
+fn range_not_satisfiable(headers: &HeaderMap) -> Error {
+    Error::RangeNotSatisfiable(
+        headers
+            .get(CONTENT_RANGE)
+            .and_then(|v| content_range_total(v.to_str().ok()?)),
+    )
+}
+
+/// 从 `Content-Range: bytes 0-0/12345` 中取出总大小，总大小未知（`*`）时返回 None
+fn content_range_total(value: &str) -> Option<u64> {
+    let (_, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    total.trim().parse().ok()
+}
+
+/// `Content-Range` 中响应体的起始位置
+fn content_range_start(value: &str) -> Option<u64> {
+    let (start, _) = value.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+/// `If-Range` 使用的校验值：优先使用强 `ETag`，弱 `ETag` 不能用于 `If-Range`
+fn if_range_validator(etag: Option<&str>, last_modified: Option<&str>) -> Option<String> {
+    etag.filter(|etag| !etag.starts_with("W/"))
+        .or(last_modified)
+        .map(str::to_string)
+}
+
+/// 打乱的分块下标，同一个任务每次得到相同的顺序
+///
+/// 用任务 ID 的 FNV-1a 哈希作为 SplitMix64 的种子，做 Fisher-Yates 洗牌。
+fn shuffled_indices(len: usize, task_id: &str) -> Vec<usize> {
+    let mut seed = task_id
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    let mut next = || {
+        seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut indices: Vec<usize> = (0..len).collect();
+    for i in (1..len).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        indices.swap(i, j);
+    }
+    indices
+}
+
+/// 计算平均速度（字节/秒）
+fn bytes_per_second(bytes: u64, elapsed: Duration) -> u64 {
+    let millis = elapsed.as_millis().max(1) as u64;
+    bytes.saturating_mul(1000) / millis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock_server::{MockFile, MockServer, MockSocksProxy, temp_dir, test_body},
+        sink::MemorySink,
+        types::{
+            ChunkIssue, ChunkSummary, CookieSource, DiskPressureConfig, PoolConfig,
+            PreallocateMode, TaskSort,
+        },
+    };
+    #[cfg(feature = "checksums")]
+    use sha2::{Digest, Sha256};
+
+    /// 等待任务进入终止状态
+    async fn wait_for_terminal(yushi: &YuShi, task_id: &str) -> Task {
+        for _ in 0..200 {
+            let task = yushi.get_task(task_id).await.unwrap();
+            if matches!(
+                task.status,
+                TaskStatus::Completed | TaskStatus::Failed | TaskStatus::Cancelled
+            ) {
+                return task;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("task {} did not finish", task_id);
+    }
+
+    /// 创建把队列状态保存到 `queue_path` 的下载器
+    ///
+    /// 与 [`YuShi::with_config`] 相同，但不需要 `queue-persistence` 特性，
+    /// 关闭该特性时这些测试也能运行。
+    fn with_queue_file(
+        config: Config,
+        max_concurrent_tasks: usize,
+        queue_path: PathBuf,
+    ) -> Result<(YuShi, mpsc::Receiver<EventEnvelope>)> {
+        YuShi::build(config, max_concurrent_tasks, Some(queue_path))
+    }
+
+    fn test_downloader(dir: &Path, chunk_size: u64) -> YuShi {
+        let config = Config {
+            chunk_size,
+            ..Default::default()
+        };
+        with_queue_file(config, 2, dir.join("queue.json"))
+            .unwrap()
+            .0
+    }
+
+    /// 使用默认设置下载到 `dest` 时，下载中的文件和状态文件
+    fn partial_paths(dest: &Path) -> (PathBuf, PathBuf) {
+        let part = with_affixes(dest, "", ".part");
+        let state_path = part.with_extension("json");
+        (part, state_path)
+    }
+
+    #[tokio::test]
+    async fn test_memory_sink_chunked() {
+        let server = MockServer::start().await;
+        let body = test_body(100_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        let sink = Arc::new(MemorySink::new());
+        let options = TaskOptions {
+            sink: Some(sink.clone()),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert!(sink.is_finalized());
+        assert_eq!(sink.data(), body);
+        assert!(!dir.join("file.bin").exists());
+        assert!(
+            server
+                .requests()
+                .iter()
+                .any(|r| r.headers.contains_key("range"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sequential_sink_falls_back_to_streaming() {
+        let server = MockServer::start().await;
+        let body = test_body(100_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        let sink = Arc::new(MemorySink::sequential());
+        let options = TaskOptions {
+            sink: Some(sink.clone()),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(sink.data(), body);
+        assert!(
+            server
+                .requests()
+                .iter()
+                .all(|r| !r.headers.contains_key("range"))
+        );
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_download_to_writer_streams_and_checks_inline() {
+        let server = MockServer::start().await;
+        let body = test_body(100_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+        let sha256 = hex::encode(Sha256::digest(&body));
+
+        for (checksum, ok) in [(sha256, true), ("0".repeat(64), false)] {
+            let (writer, mut reader) = tokio::io::duplex(8 * 1024);
+            let received = tokio::spawn(async move {
+                let mut received = Vec::new();
+                reader.read_to_end(&mut received).await.unwrap();
+                received
+            });
+            let options = TaskOptions {
+                checksum: Some(ChecksumType::Sha256(checksum)),
+                ..Default::default()
+            };
+            let result = yushi
+                .download_to_writer(&server.url("/file.bin"), writer, options, None)
+                .await;
+
+            // 校验失败时数据也已经全部写出
+            assert_eq!(received.await.unwrap(), body);
+            match result {
+                Ok(summary) => {
+                    assert!(ok);
+                    assert_eq!(summary.dest, PathBuf::from("-"));
+                    assert_eq!(summary.total_size, body.len() as u64);
+                }
+                Err(e) => assert!(!ok, "{e}"),
+            }
+        }
+        assert!(
+            server
+                .requests()
+                .iter()
+                .all(|r| !r.headers.contains_key("range"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download_bytes_reads_into_memory() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        server.add("/manifest.json", MockFile::new(body.clone()));
+        server.add("/big.bin", MockFile::new(test_body(100_000)));
+        let dir = temp_dir();
+        let config = Config {
+            headers: HashMap::from([("X-Client".to_string(), "yushi".to_string())]),
+            max_memory_download: 50_000,
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        // 可重试的状态码按 Retry-After 重试
+        server.fail_gets(1, "503 Service Unavailable", Some("0"));
+        let bytes = yushi
+            .download_bytes(&server.url("/manifest.json"))
+            .await
+            .unwrap();
+        assert_eq!(bytes.as_ref(), body.as_slice());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(
+            requests
+                .iter()
+                .all(|r| r.headers.get("x-client").map(String::as_str) == Some("yushi"))
+        );
+
+        let error = yushi
+            .download_bytes(&server.url("/big.bin"))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::ResponseTooLarge(50_000)));
+        let error = yushi
+            .download_bytes(&server.url("/missing.json"))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::HttpStatus(404)));
+
+        // 不创建任务，也不写入任何文件
+        assert!(yushi.get_all_tasks().await.is_empty());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_group_completion_and_persistence() {
+        let server = MockServer::start().await;
+        server.add("/a.bin", MockFile::new(test_body(20_000)));
+        let dir = temp_dir();
+        let queue_path = dir.join("queue.json");
+        let (yushi, mut rx) = with_queue_file(Config::default(), 2, queue_path.clone()).unwrap();
+
+        let group_id = yushi
+            .add_group(
+                "season".to_string(),
+                vec![
+                    NewTask::new(server.url("/a.bin"), dir.join("a.bin")),
+                    NewTask::new(server.url("/nope.bin"), dir.join("nope.bin")),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let failed = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(DownloaderEvent::Task(TaskEvent::GroupCompleted {
+                    group_id: g,
+                    failed,
+                })) = rx.recv().await.map(|e| e.event)
+                {
+                    assert_eq!(g, group_id);
+                    return failed;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(failed.len(), 1);
+
+        let progress = yushi.get_group_progress(&group_id).await.unwrap();
+        assert_eq!(progress.total_tasks, 2);
+        assert_eq!(progress.completed_tasks, 1);
+        assert_eq!(progress.failed_tasks, 1);
+
+        let (reloaded, _) = with_queue_file(Config::default(), 2, queue_path).unwrap();
+        reloaded.load_queue_from_state().await.unwrap();
+        assert_eq!(reloaded.get_groups().await.len(), 1);
+        let members = reloaded
+            .get_all_tasks()
+            .await
+            .into_iter()
+            .filter(|t| t.group_id.as_deref() == Some(group_id.as_str()))
+            .count();
+        assert_eq!(members, 2);
+    }
+
+    #[tokio::test]
+    async fn test_measure() {
+        let server = MockServer::start().await;
+        let body = test_body(200_000);
+        server.add("/ranged.bin", MockFile::new(body.clone()));
+        server.add("/plain.bin", MockFile::new(body).without_ranges());
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        let report = yushi
+            .measure(&server.url("/ranged.bin"), Duration::from_secs(3))
+            .await
+            .unwrap();
+        assert!(report.supports_range);
+        let levels: Vec<usize> = report
+            .connections_tested
+            .iter()
+            .map(|s| s.connections)
+            .collect();
+        assert_eq!(levels, vec![1, 4, 8]);
+        assert_eq!(report.bytes, 3 * 200_000);
+        assert!((1..=8).contains(&report.suggested_connections()));
+
+        let report = yushi
+            .measure(&server.url("/plain.bin"), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(!report.supports_range);
+        assert_eq!(report.connections_tested.len(), 1);
+        assert_eq!(report.bytes, 200_000);
+
+        // 测速不写入磁盘
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_verify_all_respects_concurrency() {
+        let dir = temp_dir();
+        let config = Config {
+            verify_concurrency: 1,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let mut ids = Vec::new();
+        for i in 0..4 {
+            let body = test_body(200_000 + i);
+            let dest = dir.join(format!("file-{i}.bin"));
+            std::fs::write(&dest, &body).unwrap();
+            let options = TaskOptions {
+                checksum: Some(ChecksumType::Sha256(hex::encode(Sha256::digest(&body)))),
+                ..Default::default()
+            };
+            let id = yushi
+                .insert_task(format!("http://example.com/{i}"), dest, options, None)
+                .await;
+            if let Some(task) = yushi.tasks.write().await.get_mut(&id) {
+                task.status = TaskStatus::Completed;
+                task.created_at = i as u64;
+            }
+            ids.push(id);
+        }
+        // 损坏一个文件，删除一个文件
+        std::fs::write(dir.join("file-1.bin"), b"corrupted").unwrap();
+        std::fs::remove_file(dir.join("file-2.bin")).unwrap();
+        while rx.try_recv().is_ok() {}
+
+        let results = yushi.verify_all(|_| true).await.unwrap();
+        let expected: Vec<_> = ids
+            .iter()
+            .cloned()
+            .zip([true, false, false, true])
+            .collect();
+        assert_eq!(results, expected);
+
+        // 同一时间只有一个任务在校验
+        let mut running = None;
+        while let Ok(envelope) = rx.try_recv() {
+            match envelope.event {
+                DownloaderEvent::Verification(VerificationEvent::Started { task_id }) => {
+                    assert!(running.is_none());
+                    running = Some(task_id);
+                }
+                DownloaderEvent::Verification(VerificationEvent::Completed { task_id, .. }) => {
+                    assert_eq!(running.take(), Some(task_id));
+                }
+                _ => {}
+            }
+        }
+        assert!(running.is_none());
+
+        let results = yushi.verify_all(|t| t.id == ids[3]).await.unwrap();
+        assert_eq!(results, vec![(ids[3].clone(), true)]);
+        let task = yushi.get_task(&ids[1]).await.unwrap();
+        assert_eq!(task.status, TaskStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_orphans_never_include_claimed_files() {
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        let write_partial = |name: &str| {
+            let dest = dir.join(format!("{name}.bin"));
+            let (part, state_path) = partial_paths(&dest);
+            std::fs::write(part, vec![0u8; 1000]).unwrap();
+            let state = format!(
+                r#"{{"url":"http://example.com/{name}","total_size":1000,"chunks":[{{"index":0,"start":0,"end":999,"current":0,"is_finished":false}}],"is_streaming":false}}"#
+            );
+            std::fs::write(state_path, state).unwrap();
+            dest
+        };
+        let paused = write_partial("paused");
+        let failed = write_partial("failed");
+        let removed = write_partial("removed");
+        let late = write_partial("late");
+
+        for (dest, status) in [(&paused, TaskStatus::Paused), (&failed, TaskStatus::Failed)] {
+            let id = yushi
+                .insert_task(
+                    "http://example.com".to_string(),
+                    dest.clone(),
+                    TaskOptions::default(),
+                    None,
+                )
+                .await;
+            yushi.tasks.write().await.get_mut(&id).unwrap().status = status;
+        }
+
+        let reports = yushi.scan_orphans(std::slice::from_ref(&dir)).await;
+        let mut paths: Vec<_> = reports.iter().map(|r| r.path.clone()).collect();
+        paths.sort();
+        let (late_part, late_state) = partial_paths(&late);
+        let (removed_part, removed_state) = partial_paths(&removed);
+        let mut expected = vec![
+            late_part.clone(),
+            late_state.clone(),
+            removed_part.clone(),
+            removed_state.clone(),
+        ];
+        expected.sort();
+        assert_eq!(paths, expected);
+
+        // 扫描后新添加的任务引用的文件不会被删除
+        yushi
+            .insert_task(
+                "http://example.com".to_string(),
+                late.clone(),
+                TaskOptions::default(),
+                None,
+            )
+            .await;
+        let expected = 1000 + std::fs::metadata(&removed_state).unwrap().len();
+        assert_eq!(
+            yushi.clean_orphans(&reports, false).await.unwrap(),
+            expected
+        );
+        assert!(removed_part.exists());
+        assert_eq!(yushi.clean_orphans(&reports, true).await.unwrap(), expected);
+        assert!(!removed_part.exists() && !removed_state.exists());
+        assert!(late_part.exists() && late_state.exists());
+        for dest in [&paused, &failed] {
+            let (part, state_path) = partial_paths(dest);
+            assert!(part.exists() && state_path.exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_many_small_files_skip_probe() {
+        let server = MockServer::start().await;
+        server.keep_alive();
+        let dir = temp_dir();
+        let config = Config {
+            pool: PoolConfig {
+                max_idle_per_host: 8,
+                idle_timeout: Some(Duration::from_secs(30)),
+            },
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 8, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        let mut ids = Vec::new();
+        for i in 0..500 {
+            let path = format!("/tile-{i}.bin");
+            server.add(&path, MockFile::new(test_body(1024 + i)));
+            let options = TaskOptions {
+                skip_probe: true,
+                ..Default::default()
+            };
+            let id = yushi
+                .add_task_with(
+                    server.url(&path),
+                    dir.join(format!("tile-{i}.bin")),
+                    options,
+                )
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+
+        // 完成的任务释放名额后自动启动下一个
+        let deadline = Instant::now() + Duration::from_secs(60);
+        loop {
+            let tasks = yushi.get_all_tasks().await;
+            if tasks.iter().all(|t| t.status == TaskStatus::Completed) {
+                break;
+            }
+            assert!(Instant::now() < deadline, "downloads did not finish");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        for (i, id) in ids.iter().enumerate() {
+            let task = yushi.get_task(id).await.unwrap();
+            assert_eq!(task.total_size, 1024 + i as u64);
+            assert!(task.remote.is_some());
+            assert_eq!(std::fs::read(&task.dest).unwrap(), test_body(1024 + i));
+        }
+        // 每个文件只有一个 GET，连接数不超过同时运行的任务数加上空闲连接数
+        let requests = server.requests();
+        assert_eq!(requests.len(), 500);
+        assert!(requests.iter().all(|r| r.method == "GET"));
+        assert!(
+            server.connections() <= 16,
+            "{} connections",
+            server.connections()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_finished_task_starts_next_pending() {
+        let server = MockServer::start().await;
+        let dir = temp_dir();
+        let (yushi, _rx) = with_queue_file(Config::default(), 1, dir.join("queue.json")).unwrap();
+
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let path = format!("/{}.bin", i);
+            server.add(&path, MockFile::new(test_body(4096)));
+            let id = yushi
+                .add_task(server.url(&path), dir.join(format!("{}.bin", i)))
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        for id in &ids {
+            let task = wait_for_terminal(&yushi, id).await;
+            assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_queue_holds_pending_tasks() {
+        let server = MockServer::start().await;
+        server.add("/file.bin", MockFile::new(test_body(10_000)));
+        let dir = temp_dir();
+        let (yushi, mut rx) =
+            with_queue_file(Config::default(), 2, dir.join("queue.json")).unwrap();
+
+        yushi.pause_queue().await;
+        yushi.pause_queue().await;
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let stats = yushi.get_stats();
+        assert_eq!((stats.total, stats.pending), (1, 1));
+        assert!(!stats.scheduler.running);
+        assert_eq!(stats.scheduler.reason, Some(PausedReason::ManualPause));
+        assert!(server.requests().is_empty());
+
+        yushi.resume_queue().await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(yushi.scheduler_state(), SchedulerState::default());
+
+        // 每次状态变化只发送一次事件
+        let mut changes = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Global(GlobalEvent::SchedulerStateChanged(state)) =
+                envelope.event
+            {
+                changes.push(state.running);
+            }
+        }
+        assert_eq!(changes, vec![false, true]);
+    }
+
+    #[tokio::test]
+    async fn test_run_task_ignores_paused_scheduler() {
+        let server = MockServer::start().await;
+        server.add("/a.bin", MockFile::new(test_body(10_000)));
+        server.add("/b.bin", MockFile::new(test_body(10_000)));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 4 * 1024);
+
+        yushi.pause_queue().await;
+        let other = yushi
+            .add_task(server.url("/a.bin"), dir.join("a.bin"))
+            .await
+            .unwrap();
+        let id = yushi
+            .add_task(server.url("/b.bin"), dir.join("b.bin"))
+            .await
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(1024);
+        yushi.run_task(&id, Some(tx)).await.unwrap();
+        assert_eq!(std::fs::read(dir.join("b.bin")).unwrap(), test_body(10_000));
+        let mut initialized = false;
+        while let Ok(event) = rx.try_recv() {
+            initialized |= matches!(event, ProgressEvent::Initialized { .. });
+        }
+        assert!(initialized);
+
+        // 只下载指定的任务，其他任务仍在等待
+        let other = yushi.get_task(&other).await.unwrap();
+        assert_eq!(other.status, TaskStatus::Pending);
+        assert!(server.requests().iter().all(|r| r.path == "/b.bin"));
+
+        // 已经结束的任务直接返回
+        yushi.run_task(&id, None).await.unwrap();
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_pause_during_verification_resumes_verify_only() {
+        let server = MockServer::start().await;
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        // 模拟下载已完成、正在校验的任务
+        let body = test_body(10_000);
+        let dest = dir.join("file.bin");
+        let (part, _) = partial_paths(&dest);
+        std::fs::write(&part, &body).unwrap();
+        let options = TaskOptions {
+            checksum: Some(ChecksumType::Sha256(hex::encode(Sha256::digest(&body)))),
+            ..Default::default()
+        };
+        let id = yushi
+            .insert_task(server.url("/file.bin"), dest.clone(), options, None)
+            .await;
+        let cancel = CancellationToken::new();
+        yushi
+            .cancel_tokens
+            .write()
+            .await
+            .insert(id.clone(), cancel.clone());
+        if let Some(task) = yushi.tasks.write().await.get_mut(&id) {
+            task.status = TaskStatus::Verifying;
+            task.verification_pending = true;
+        }
+
+        yushi.pause_task(&id).await.unwrap();
+        assert!(cancel.is_cancelled());
+        let task = yushi.get_task(&id).await.unwrap();
+        assert_eq!(task.status, TaskStatus::Paused);
+        assert!(task.verification_pending);
+        assert_eq!(std::fs::read(&part).unwrap(), body);
+
+        // 恢复后只重新校验，不再发起下载请求
+        yushi.resume_task(&id).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert!(!task.verification_pending);
+        assert!(server.requests().is_empty());
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_interrupted_verification_reruns_after_reload() {
+        let server = MockServer::start().await;
+        let dir = temp_dir();
+        let body = test_body(10_000);
+        let dest = dir.join("file.bin");
+        let (part, _) = partial_paths(&dest);
+        std::fs::write(&part, &body).unwrap();
+
+        // 模拟校验进行到一半时进程退出：任务停在 Verifying 状态
+        let id = {
+            let yushi = test_downloader(&dir, 16 * 1024);
+            let options = TaskOptions {
+                checksum: Some(ChecksumType::Sha256(hex::encode(Sha256::digest(&body)))),
+                ..Default::default()
+            };
+            let id = yushi
+                .insert_task(server.url("/file.bin"), dest.clone(), options, None)
+                .await;
+            if let Some(task) = yushi.tasks.write().await.get_mut(&id) {
+                task.status = TaskStatus::Verifying;
+                task.verification_pending = true;
+                task.verified = 4096;
+            }
+            yushi.store.mark_task(&id);
+            yushi.save_queue_state().await.unwrap();
+            id
+        };
+
+        let (yushi, mut rx) = with_queue_file(
+            Config {
+                chunk_size: 16 * 1024,
+                ..Default::default()
+            },
+            2,
+            dir.join("queue.json"),
+        )
+        .unwrap();
+        yushi.load_queue_from_state().await.unwrap();
+        let task = yushi.get_task(&id).await.unwrap();
+        assert_eq!(task.status, TaskStatus::Pending);
+        assert!(task.verification_pending);
+
+        // 启动后重新校验再完成，不重新下载
+        yushi.start().await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert!(!task.verification_pending);
+        assert!(server.requests().is_empty());
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+
+        let mut started = false;
+        let mut completed = None;
+        while let Ok(envelope) = rx.try_recv() {
+            match envelope.event {
+                DownloaderEvent::Verification(VerificationEvent::Started { .. }) => started = true,
+                DownloaderEvent::Verification(VerificationEvent::Completed { success, .. }) => {
+                    completed = Some(success)
+                }
+                _ => {}
+            }
+        }
+        assert!(started);
+        assert_eq!(completed, Some(true));
+
+        // 不恢复中断的校验时，重新加载后任务失败
+        yushi.tasks.write().await.get_mut(&id).unwrap().status = TaskStatus::Verifying;
+        yushi
+            .tasks
+            .write()
+            .await
+            .get_mut(&id)
+            .unwrap()
+            .verification_pending = true;
+        yushi.store.mark_task(&id);
+        yushi.save_queue_state().await.unwrap();
+        let (yushi, _rx) = with_queue_file(
+            Config {
+                resume_interrupted_verification: false,
+                ..Default::default()
+            },
+            2,
+            dir.join("queue.json"),
+        )
+        .unwrap();
+        yushi.load_queue_from_state().await.unwrap();
+        let task = yushi.get_task(&id).await.unwrap();
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert!(!task.verification_pending);
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_checksum_mismatch_repairs_corrupted_chunks() {
+        let server = MockServer::start().await;
+        let body = test_body(40_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let (yushi, mut rx) =
+            with_queue_file(Config::default(), 2, dir.join("queue.json")).unwrap();
+
+        // 已下载完成的分块状态，第 2 个分块在磁盘上损坏
+        let dest = dir.join("file.bin");
+        let (part, state_path) = partial_paths(&dest);
+        let chunk_size = 10_000u64;
+        let chunks = (0..4u64)
+            .map(|i| {
+                let (start, end) = (i * chunk_size, (i + 1) * chunk_size - 1);
+                ChunkState {
+                    index: i as usize,
+                    start,
+                    end,
+                    current: end + 1,
+                    is_finished: true,
+                    digest: Some(hex::encode(Sha256::digest(
+                        &body[start as usize..=end as usize],
+                    ))),
+                }
+            })
+            .collect();
+        let state = DownloadState {
+            url: server.url("/file.bin"),
+            total_size: Some(body.len() as u64),
+            chunks,
+            is_streaming: false,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 0,
+            reported_prefix: 0,
+        };
+        state.save(&state_path).await.unwrap();
+        let mut corrupted = body.clone();
+        corrupted[15_000] ^= 0xff;
+        std::fs::write(&part, &corrupted).unwrap();
+
+        let checksum = ChecksumType::Sha256(hex::encode(Sha256::digest(&body)));
+        let options = TaskOptions {
+            checksum: Some(checksum.clone()),
+            ..Default::default()
+        };
+        let id = yushi
+            .insert_task(state.url.clone(), dest.clone(), options, None)
+            .await;
+        let task = yushi.get_task(&id).await.unwrap();
+        let sink = yushi.task_sink(&task).await;
+
+        yushi
+            .verify_task(&task, sink, &checksum, &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&part).unwrap(), body);
+
+        let ranges: Vec<String> = server
+            .requests()
+            .iter()
+            .filter_map(|r| r.headers.get("range").cloned())
+            .collect();
+        assert_eq!(ranges, vec!["bytes=10000-19999".to_string()]);
+
+        let repaired = loop {
+            if let Some(DownloaderEvent::Verification(VerificationEvent::Completed {
+                success,
+                repaired_chunks,
+                ..
+            })) = rx.recv().await.map(|e| e.event)
+            {
+                assert!(success);
+                break repaired_chunks;
+            }
+        };
+        assert_eq!(repaired, 1);
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_wrong_expected_checksum_reports_diagnostics() {
+        let server = MockServer::start().await;
+        server.add("/file.bin", MockFile::new(test_body(30_000)));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        let options = TaskOptions {
+            checksum: Some(ChecksumType::Sha256("0".repeat(64))),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert!(
+            task.error
+                .unwrap()
+                .contains(&message("verify.chunks_intact", &[]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_sink_chunked() {
+        let server = MockServer::start().await;
+        let body = test_body(50_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        let dest = dir.join("file.bin");
+        let id = yushi
+            .add_task(server.url("/file.bin"), dest.clone())
+            .await
+            .unwrap();
+
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        let (part, state_path) = partial_paths(&dest);
+        assert!(!part.exists() && !state_path.exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_file_sink_concurrent_writes() {
+        let dest = temp_dir().join("file.bin");
+        let body = test_body(64 * 1024);
+        let sink = Arc::new(FileSink::new(&dest));
+        sink.open(Some(body.len() as u64), false).await.unwrap();
+
+        // 各分块同时按偏移写入，交错的小块写入不会互相覆盖
+        let mut writers = Vec::new();
+        for (index, chunk) in body.chunks(16 * 1024).enumerate() {
+            let sink = Arc::clone(&sink);
+            let chunk = chunk.to_vec();
+            writers.push(tokio::spawn(async move {
+                let start = (index * 16 * 1024) as u64;
+                for (i, piece) in chunk.chunks(1000).enumerate() {
+                    sink.write_at(start + (i * 1000) as u64, piece)
+                        .await
+                        .unwrap();
+                }
+            }));
+        }
+        for writer in writers {
+            writer.await.unwrap();
+        }
+        sink.finalize().await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_limits_and_single_connection() {
+        let server = MockServer::start().await;
+        let small = test_body(20_000);
+        let body = test_body(40_000);
+        server.add("/small.bin", MockFile::new(small.clone()));
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 4096,
+            max_chunks: 4,
+            min_chunk_size: Some(3000),
+            single_connection_threshold: Some(30_000),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        // 分块数超过上限时增大分块，末尾很小的部分并入前一个分块
+        let ranges = |size| {
+            yushi
+                .chunk_layout(size)
+                .iter()
+                .map(|c| (c.start, c.end))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ranges(100_000),
+            [
+                (0, 24_999),
+                (25_000, 49_999),
+                (50_000, 74_999),
+                (75_000, 99_999)
+            ]
+        );
+        assert_eq!(ranges(10_000), [(0, 4095), (4096, 9999)]);
+
+        // 小文件用一个 GET 流式下载，不保存状态
+        let plan = yushi
+            .plan_task(
+                &server.url("/small.bin"),
+                &dir.join("small.bin"),
+                &TaskOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(plan.chunks, 0);
+        yushi
+            .download(&server.url("/small.bin"), dir.join("small.bin"), None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(dir.join("small.bin")).unwrap(), small);
+        assert!(
+            server
+                .requests()
+                .iter()
+                .filter(|r| r.method == "GET")
+                .all(|r| !r.headers.contains_key("range"))
+        );
+
+        // 按旧的分块大小保存的状态按其中的分块继续下载
+        let dest = dir.join("file.bin");
+        let (part, state_path) = partial_paths(&dest);
+        let chunks = (0..8u64)
+            .map(|i| {
+                let (start, end) = (i * 5000, (i + 1) * 5000 - 1);
+                let finished = i < 4;
+                ChunkState {
+                    index: i as usize,
+                    start,
+                    end,
+                    current: if finished { end + 1 } else { start },
+                    is_finished: finished,
+                    digest: None,
+                }
+            })
+            .collect();
+        let url = server.url("/file.bin");
+        DownloadState {
+            url: url.clone(),
+            total_size: Some(body.len() as u64),
+            chunks,
+            is_streaming: false,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 0,
+            reported_prefix: 0,
+        }
+        .save(&state_path)
+        .await
+        .unwrap();
+        let mut partial = body[..20_000].to_vec();
+        partial.resize(body.len(), 0);
+        std::fs::write(&part, &partial).unwrap();
+
+        yushi.download(&url, &dest, None).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        let mut fetched: Vec<String> = server
+            .requests()
+            .iter()
+            .filter(|r| r.path == "/file.bin" && r.method == "GET")
+            .filter_map(|r| r.headers.get("range").cloned())
+            .collect();
+        fetched.sort();
+        assert_eq!(
+            fetched,
+            [
+                "bytes=20000-24999",
+                "bytes=25000-29999",
+                "bytes=30000-34999",
+                "bytes=35000-39999"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preallocate_none_out_of_order() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 4 * 1024,
+            preallocate: PreallocateMode::None,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        // 打乱顺序后后面的分块先写入，文件在中间留下空洞
+        let dest = dir.join("file.bin");
+        let options = TaskOptions {
+            chunk_order: Some(ChunkOrder::Random),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dest.clone(), options)
+            .await
+            .unwrap();
+
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_range_probe_personalities() {
+        let server = MockServer::start().await;
+        let body = test_body(40_000);
+        server.add("/full.bin", MockFile::new(body.clone()));
+        server.add(
+            "/bare-head.bin",
+            MockFile::new(body.clone()).without_head_headers(),
+        );
+        server.add(
+            "/no-range.bin",
+            MockFile::new(body.clone())
+                .without_head_headers()
+                .without_ranges(),
+        );
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+        let chunked = RemoteInfo {
+            total_size: Some(40_000),
+            supports_range: true,
+            ..Default::default()
+        };
+
+        // HEAD 信息完整时不发送探测请求
+        assert_eq!(
+            yushi.probe(&server.url("/full.bin")).await.unwrap(),
+            chunked
+        );
+        // HEAD 缺少信息但支持 Range：通过 206 得到大小
+        assert_eq!(
+            yushi.probe(&server.url("/bare-head.bin")).await.unwrap(),
+            chunked
+        );
+        // 不支持 Range：保持流式下载
+        assert_eq!(
+            yushi.probe(&server.url("/no-range.bin")).await.unwrap(),
+            RemoteInfo {
+                total_size: None,
+                supports_range: false,
+                ..Default::default()
+            }
+        );
+        let probes = |path: &str| {
+            server
+                .requests()
+                .iter()
+                .filter(|r| {
+                    r.path == path
+                        && r.method == "GET"
+                        && r.headers.get("range").map(String::as_str) == Some("bytes=0-0")
+                })
+                .count()
+        };
+        assert_eq!(probes("/full.bin"), 0);
+        assert_eq!(probes("/bare-head.bin"), 1);
+        assert_eq!(probes("/no-range.bin"), 1);
+
+        for (path, chunked) in [("/bare-head.bin", true), ("/no-range.bin", false)] {
+            let dest = dir.join(&path[1..]);
+            let id = yushi
+                .add_task(server.url(path), dest.clone())
+                .await
+                .unwrap();
+            let task = wait_for_terminal(&yushi, &id).await;
+            assert_eq!(task.status, TaskStatus::Completed);
+            assert_eq!(std::fs::read(&dest).unwrap(), body);
+            assert!(task.remote.is_some());
+            let ranged_gets = server
+                .requests()
+                .iter()
+                .filter(|r| r.path == path && r.method == "GET" && r.headers.contains_key("range"))
+                .count();
+            // 两次探测之外的 Range 请求来自分块下载
+            assert_eq!(ranged_gets > 2, chunked, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_when_head_is_not_allowed() {
+        let server = MockServer::start().await;
+        let body = test_body(40_000);
+        server.add(
+            "/no-head.bin",
+            MockFile::new(body.clone()).with_head_status("405 Method Not Allowed"),
+        );
+        server.add(
+            "/silent-ranges.bin",
+            MockFile::new(body.clone()).without_accept_ranges(),
+        );
+        server.add(
+            "/opaque.bin",
+            MockFile::new(body.clone())
+                .with_head_status("405 Method Not Allowed")
+                .without_ranges(),
+        );
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+        let chunked = RemoteInfo {
+            total_size: Some(40_000),
+            supports_range: true,
+            ..Default::default()
+        };
+
+        // 405 响应中的 Content-Length 和 Accept-Ranges 不可信，大小来自 206 的 Content-Range
+        assert_eq!(
+            yushi.probe(&server.url("/no-head.bin")).await.unwrap(),
+            chunked
+        );
+        // 没有 Accept-Ranges，但 Range 请求得到了 206
+        assert_eq!(
+            yushi
+                .probe(&server.url("/silent-ranges.bin"))
+                .await
+                .unwrap(),
+            chunked
+        );
+        // 两种探测都没有结果时才使用流式下载
+        assert_eq!(
+            yushi.probe(&server.url("/opaque.bin")).await.unwrap(),
+            RemoteInfo::default()
+        );
+
+        for (path, chunked) in [
+            ("/no-head.bin", true),
+            ("/silent-ranges.bin", true),
+            ("/opaque.bin", false),
+        ] {
+            let dest = dir.join(&path[1..]);
+            let id = yushi
+                .add_task(server.url(path), dest.clone())
+                .await
+                .unwrap();
+            let task = wait_for_terminal(&yushi, &id).await;
+            assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+            assert_eq!(std::fs::read(&dest).unwrap(), body);
+            let chunk_gets = server
+                .requests()
+                .iter()
+                .filter(|r| {
+                    r.path == path
+                        && r.method == "GET"
+                        && r.headers
+                            .get("range")
+                            .is_some_and(|range| range != "bytes=0-0")
+                })
+                .count();
+            assert_eq!(chunk_gets, if chunked { 5 } else { 0 }, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_task_connections() {
+        let server = MockServer::start().await;
+        // 16 个 4 KiB 分块，每个分块约 150ms
+        let body = test_body(64 * 1024);
+        server.add(
+            "/slow.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_millis(50)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 4 * 1024,
+            max_concurrent: 8,
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let dest = dir.join("slow.bin");
+        let id = yushi
+            .add_task(server.url("/slow.bin"), dest.clone())
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            if server.active_gets() >= 4 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(server.active_gets() >= 4);
+
+        yushi.set_task_connections(&id, 2).await.unwrap();
+        // 进行中的分块下载完之后，连接数不再超过新的上限
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let mut max_active = 0;
+        while yushi.get_task(&id).await.unwrap().status == TaskStatus::Downloading {
+            max_active = max_active.max(server.active_gets());
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(max_active <= 2, "{} connections still open", max_active);
+
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.connections, Some(2));
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_per_host_connection_limit_shared_across_tasks() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        for name in ["a.bin", "b.bin", "c.bin"] {
+            server.add(
+                &format!("/{}", name),
+                MockFile::new(body.clone()).with_delay(Duration::from_millis(50)),
+            );
+        }
+        server.add(
+            "/stream.bin",
+            MockFile::new(body.clone())
+                .without_ranges()
+                .with_delay(Duration::from_millis(50)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 4 * 1024,
+            max_concurrent: 4,
+            per_host_max_connections: Some(3),
+            ..Default::default()
+        };
+        let (yushi, _rx) = YuShi::in_memory(config, 4).unwrap();
+
+        let mut ids = Vec::new();
+        for name in ["a.bin", "b.bin", "stream.bin"] {
+            let id = yushi
+                .add_task(server.url(&format!("/{}", name)), dir.join(name))
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        // 同一个服务器的另一个主机名不受 127.0.0.1 的上限影响
+        let other = format!("http://localhost:{}/c.bin", server.addr().port());
+        ids.push(yushi.add_task(other, dir.join("c.bin")).await.unwrap());
+
+        let (mut max_active, mut waited, mut parallel) = (0, false, false);
+        for _ in 0..1000 {
+            let stats = yushi.host_stats();
+            let local = stats.iter().find(|s| s.host == "127.0.0.1");
+            if let Some(local) = local {
+                max_active = max_active.max(local.active_connections);
+                waited |= local.waiting_connections > 0;
+                parallel |= local.active_connections == 3
+                    && stats
+                        .iter()
+                        .any(|s| s.host == "localhost" && s.active_connections > 0);
+            }
+            let stats = yushi.get_stats();
+            if stats.completed + stats.failed == ids.len() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(max_active, 3);
+        assert!(waited);
+        assert!(parallel);
+        for (id, name) in ids.iter().zip(["a.bin", "b.bin", "stream.bin", "c.bin"]) {
+            let task = yushi.get_task(id).await.unwrap();
+            assert_eq!(task.status, TaskStatus::Completed, "{}", name);
+            assert_eq!(std::fs::read(dir.join(name)).unwrap(), body);
+        }
+        assert!(yushi.host_stats().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_progress_events_carry_task_id() {
+        let server = MockServer::start().await;
+        server.add("/chunked.bin", MockFile::new(test_body(40_000)));
+        server.add(
+            "/stream.bin",
+            MockFile::new(test_body(40_000)).without_ranges(),
+        );
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        for name in ["chunked.bin", "stream.bin"] {
+            let (tx, mut rx) = mpsc::channel(4096);
+            let dest = dir.join(name);
+            let summary = yushi
+                .download(&server.url(&format!("/{name}")), &dest, Some(tx))
+                .await
+                .unwrap();
+            assert_eq!(summary.dest, dest);
+            assert_eq!(summary.total_size, 40_000);
+            assert!(yushi.get_task(&summary.task_id).await.is_some());
+
+            let mut events = Vec::new();
+            while let Some(event) = rx.recv().await {
+                events.push(event);
+            }
+            assert!(
+                events
+                    .iter()
+                    .any(|e| matches!(e, ProgressEvent::Finished { .. }))
+            );
+            for event in &events {
+                assert_eq!(event.task_id(), Some(summary.task_id.as_str()), "{event:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initialized_reports_resume() {
+        let server = MockServer::start().await;
+        let body = test_body(32 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+        let url = server.url("/file.bin");
+        let dest = dir.join("file.bin");
+
+        // 上次运行下载完了第一个分块
+        let mut partial = body[..8 * 1024].to_vec();
+        partial.resize(body.len(), 0);
+        std::fs::write(&dest, partial).unwrap();
+        let chunks = (0..4)
+            .map(|i| {
+                let start = i * 8 * 1024;
+                ChunkState {
+                    index: i as usize,
+                    start,
+                    end: start + 8 * 1024 - 1,
+                    current: if i == 0 { start + 8 * 1024 } else { start },
+                    is_finished: i == 0,
+                    digest: None,
+                }
+            })
+            .collect();
+        DownloadState {
+            url: url.clone(),
+            total_size: Some(body.len() as u64),
+            chunks,
+            is_streaming: false,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 0,
+            reported_prefix: 0,
+        }
+        .save(&dest.with_extension("json"))
+        .await
+        .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(1024);
+        yushi.download(&url, &dest, Some(tx)).await.unwrap();
+        match rx.recv().await.unwrap() {
+            ProgressEvent::Initialized {
+                total_size,
+                resumed_from,
+                final_path,
+                supports_range,
+                ..
+            } => {
+                assert_eq!(total_size, Some(body.len() as u64));
+                assert_eq!(resumed_from, 8 * 1024);
+                assert_eq!(final_path, dest);
+                assert!(supports_range);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // 只有这次下载的分块发送完成事件，全部完成后发送一次 Finished，之后不再有事件
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        let mut finished_chunks: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                ProgressEvent::ChunkFinished {
+                    chunk_index, bytes, ..
+                } => {
+                    assert_eq!(*bytes, 8 * 1024);
+                    Some(*chunk_index)
+                }
+                _ => None,
+            })
+            .collect();
+        finished_chunks.sort();
+        assert_eq!(finished_chunks, [1, 2, 3]);
+        let finished: Vec<_> = events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| matches!(e, ProgressEvent::Finished { .. }))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(finished, [events.len() - 1]);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_plan_task() {
+        let server = MockServer::start().await;
+        server.add("/file.bin", MockFile::new(test_body(20_000)));
+        server.add(
+            "/stream.bin",
+            MockFile::new(test_body(20_000)).without_ranges(),
+        );
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        let dest = dir.join("file.bin");
+        std::fs::write(&dest, b"old").unwrap();
+        let options = TaskOptions {
+            auto_rename: true,
+            ..Default::default()
+        };
+        let plan = yushi
+            .plan_task(&server.url("/file.bin"), &dest, &options)
+            .await
+            .unwrap();
+        assert_eq!(plan.conflict, Some(PlanConflict::FileExists));
+        assert_eq!(plan.dest, dir.join("file (1).bin"));
+        assert!(plan.chunked);
+        assert_eq!(plan.chunks, 3);
+        assert_eq!(plan.remote.total_size, Some(20_000));
+
+        let plan = yushi
+            .plan_task(
+                &server.url("/stream.bin"),
+                &dir.join("stream.bin"),
+                &TaskOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(plan.conflict, None);
+        assert!(!plan.chunked);
+        assert_eq!(plan.connections, 1);
+
+        assert!(
+            yushi
+                .plan_task(&server.url("/missing"), &dir.join("x"), &options)
+                .await
+                .is_err()
+        );
+        // 不创建任务或文件
+        assert!(yushi.get_all_tasks().await.is_empty());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_conflicts() {
+        let server = MockServer::start().await;
+        server.add("/report.pdf", MockFile::new(test_body(1000)));
+        let dir = temp_dir();
+        std::fs::write(dir.join("Report.PDF"), b"old").unwrap();
+        let config = Config {
+            case_insensitive_fs: CaseInsensitiveFs::On,
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        yushi.pause_queue().await;
+        let options = TaskOptions {
+            auto_rename: true,
+            ..Default::default()
+        };
+
+        let url = server.url("/report.pdf");
+        let plan = yushi
+            .plan_task(&url, &dir.join("report.pdf"), &options)
+            .await
+            .unwrap();
+        assert_eq!(plan.conflict, Some(PlanConflict::FileExists));
+        assert_eq!(plan.dest, dir.join("report (1).pdf"));
+
+        let id = yushi
+            .add_task_with(url.clone(), dir.join("report.pdf"), options.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            yushi.get_task(&id).await.unwrap().dest,
+            dir.join("report (1).pdf")
+        );
+        let plan = yushi
+            .plan_task(&url, &dir.join("REPORT (1).pdf"), &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            plan.conflict,
+            Some(PlanConflict::QueuedTask { task_id: id })
+        );
+
+        // 关闭后按区分大小写比较（文件系统本身不区分大小写时除外）
+        if !dir.join("REPORT.pdf").exists() {
+            let (yushi, _rx) = with_queue_file(Config::default(), 1, dir.join("q2.json")).unwrap();
+            let config = Config {
+                case_insensitive_fs: CaseInsensitiveFs::Off,
+                ..Default::default()
+            };
+            let (strict, _rx) = with_queue_file(config, 1, dir.join("q3.json")).unwrap();
+            for yushi in [yushi, strict] {
+                let plan = yushi
+                    .plan_task(&url, &dir.join("report.pdf"), &options)
+                    .await
+                    .unwrap();
+                assert_eq!(plan.conflict, None);
+                assert_eq!(plan.dest, dir.join("report.pdf"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_if_changed_skips_unchanged_files() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        server.add("/file.bin", MockFile::new(body.clone()).with_etag("\"v1\""));
+        let dir = temp_dir();
+        let dest = dir.join("file.bin");
+        let options = || TaskOptions {
+            conflict: ConflictPolicy::IfChanged,
+            ..Default::default()
+        };
+
+        // 第一次下载记录校验信息
+        let yushi = test_downloader(&dir, 8 * 1024);
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dest.clone(), options())
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert!(!task.skipped_unchanged);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert!(validators_path(&dest).exists());
+
+        // 新的队列只能从记录文件中读取校验信息，服务器返回 304 后直接完成
+        let (yushi, mut rx) =
+            with_queue_file(Config::default(), 2, dir.join("other.json")).unwrap();
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dest.clone(), options())
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert!(task.skipped_unchanged);
+        assert_eq!(task.downloaded, body.len() as u64);
+        let mut skipped = false;
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Task(TaskEvent::Completed {
+                skipped_unchanged, ..
+            }) = envelope.event
+            {
+                skipped = skipped_unchanged;
+            }
+        }
+        assert!(skipped);
+        let conditional = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.headers.get("if-none-match").map(String::as_str) == Some("\"v1\""))
+            .count();
+        assert_eq!(conditional, 1);
+
+        // 远程文件变化后下载到临时文件，再替换已有文件
+        let changed = test_body(30_000);
+        server.add(
+            "/file.bin",
+            MockFile::new(changed.clone()).with_etag("\"v2\""),
+        );
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dest.clone(), options())
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert!(!task.skipped_unchanged);
+        assert_eq!(task.dest, dest);
+        assert_eq!(std::fs::read(&dest).unwrap(), changed);
+        assert!(
+            !dest
+                .with_file_name(format!(".file.bin.{}.part", id))
+                .exists()
+        );
+        let stored = std::fs::read_to_string(validators_path(&dest)).unwrap();
+        assert!(stored.contains("v2"));
+    }
+
+    #[tokio::test]
+    async fn test_slow_event_consumer_does_not_slow_downloads() {
+        let server = MockServer::start().await;
+        for i in 0..3 {
+            server.add(&format!("/{}.bin", i), MockFile::new(test_body(200_000)));
+        }
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 16 * 1024,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 3, dir.join("queue.json")).unwrap();
+
+        // 事件通道已经塞满，接收端每个事件要处理 2 毫秒
+        let filler = 1500;
+        for _ in 0..filler {
+            let _ = yushi.queue_event_tx.send(DownloaderEvent::Global(
+                GlobalEvent::SchedulerStateChanged(SchedulerState::default()),
+            ));
+        }
+        let consumed = Arc::new(AtomicUsize::new(0));
+        let consumed_c = Arc::clone(&consumed);
+        let consumer = tokio::spawn(async move {
+            let mut envelopes = Vec::new();
+            let mut completed = 0;
+            while completed < 3
+                && let Some(envelope) = rx.recv().await
+            {
+                tokio::time::sleep(Duration::from_millis(2)).await;
+                consumed_c.fetch_add(1, Ordering::SeqCst);
+                if let DownloaderEvent::Task(TaskEvent::Completed { .. }) = envelope.event {
+                    completed += 1;
+                }
+                envelopes.push(envelope);
+            }
+            envelopes
+        });
+
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let id = yushi
+                .add_task(
+                    server.url(&format!("/{}.bin", i)),
+                    dir.join(format!("{}.bin", i)),
+                )
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        for id in &ids {
+            assert_eq!(
+                wait_for_terminal(&yushi, id).await.status,
+                TaskStatus::Completed
+            );
+        }
+        // 下载在接收端处理完积压的事件之前就已完成
+        assert!(consumed.load(Ordering::SeqCst) < filler);
+
+        let envelopes = consumer.await.unwrap();
+        assert!(envelopes.windows(2).all(|w| w[0].seq < w[1].seq));
+        for id in &ids {
+            let position = |matches: &dyn Fn(&TaskEvent) -> bool| {
+                envelopes.iter().position(|e| match &e.event {
+                    DownloaderEvent::Task(event) => matches(event),
+                    _ => false,
+                })
+            };
+            let added = position(&|e| matches!(e, TaskEvent::Added { task_id } if task_id == id));
+            let started =
+                position(&|e| matches!(e, TaskEvent::Started { task_id } if task_id == id));
+            let completed =
+                position(&|e| matches!(e, TaskEvent::Completed { task_id, .. } if task_id == id));
+            assert!(added.is_some() && added < started && started < completed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_global_speed_limit_and_max_tasks() {
+        let server = MockServer::start().await;
+        let body = test_body(100 * 1024);
+        server.add("/a.bin", MockFile::new(body.clone()));
+        server.add("/b.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let (yushi, _rx) = with_queue_file(Config::default(), 1, dir.join("queue.json")).unwrap();
+        yushi.set_global_speed_limit(Some(100 * 1024));
+        assert_eq!(yushi.global_speed_limit(), Some(100 * 1024));
+
+        let start = Instant::now();
+        let a = yushi
+            .add_task(server.url("/a.bin"), dir.join("a.bin"))
+            .await
+            .unwrap();
+        let b = yushi
+            .add_task(server.url("/b.bin"), dir.join("b.bin"))
+            .await
+            .unwrap();
+        // 只允许一个任务运行，调大后第二个任务立即开始
+        assert_eq!(
+            yushi.get_task(&b).await.unwrap().status,
+            TaskStatus::Pending
+        );
+        yushi.set_max_concurrent_tasks(2).await.unwrap();
+        assert_ne!(
+            yushi.get_task(&b).await.unwrap().status,
+            TaskStatus::Pending
+        );
+
+        for id in [&a, &b] {
+            assert_eq!(
+                wait_for_terminal(&yushi, id).await.status,
+                TaskStatus::Completed
+            );
+        }
+        // 两个任务共 200 KiB，总速度 100 KiB/s
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }
+
+    #[tokio::test]
+    async fn test_global_speed_limit_shared_fairly() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        let names = ["a.bin", "b.bin", "c.bin"];
+        for name in names {
+            server.add(&format!("/{}", name), MockFile::new(body.clone()));
+        }
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 16 * 1024,
+            max_concurrent: 2,
+            global_speed_limit: Some(128 * 1024),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 3, dir.join("queue.json")).unwrap();
+        assert_eq!(yushi.global_speed_limit(), Some(128 * 1024));
+
+        let start = Instant::now();
+        let mut ids = Vec::new();
+        for name in names {
+            let id = yushi
+                .add_task(server.url(&format!("/{}", name)), dir.join(name))
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        let mut finished = Vec::new();
+        for id in &ids {
+            let task = wait_for_terminal(&yushi, id).await;
+            assert_eq!(task.status, TaskStatus::Completed);
+            finished.push(start.elapsed());
+        }
+
+        // 三个任务共 192 KiB，总速度 128 KiB/s，而不是每个任务各 128 KiB/s
+        let total = *finished.iter().max().unwrap();
+        assert!(total >= Duration::from_millis(1300), "{:?}", total);
+        assert!(total < Duration::from_millis(3000), "{:?}", total);
+        // 各任务轮流获得额度，没有任务远远早于其他任务完成
+        let first = *finished.iter().min().unwrap();
+        assert!(first >= Duration::from_millis(900), "{:?}", finished);
+    }
+
+    #[tokio::test]
+    async fn test_global_speed_limit_change_applies_immediately() {
+        let server = MockServer::start().await;
+        let body = test_body(256 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 64 * 1024,
+            global_speed_limit: Some(16 * 1024),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+
+        let start = Instant::now();
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        // 按 16 KiB/s 需要 16 秒，解除限制后等待中的连接立即继续
+        yushi.set_global_speed_limit(None);
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert!(
+            start.elapsed() < Duration::from_secs(3),
+            "{:?}",
+            start.elapsed()
+        );
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_remove_task_archives_until_purged() {
+        let server = MockServer::start().await;
+        server.add("/a.bin", MockFile::new(test_body(10_000)));
+        server.add("/b.bin", MockFile::new(test_body(10_000)));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        let a = yushi
+            .add_task(server.url("/a.bin"), dir.join("a.bin"))
+            .await
+            .unwrap();
+        let b = yushi
+            .add_task(server.url("/b.bin"), dir.join("b.bin"))
+            .await
+            .unwrap();
+        wait_for_terminal(&yushi, &a).await;
+        wait_for_terminal(&yushi, &b).await;
+
+        yushi.remove_task(&a).await.unwrap();
+        yushi.remove_task(&b).await.unwrap();
+        assert!(yushi.get_all_tasks().await.is_empty());
+        assert!(yushi.progress_all().is_empty());
+        assert_eq!(yushi.get_tasks_filtered(true).await.len(), 2);
+        assert!(yushi.get_task(&a).await.unwrap().archived);
+
+        yushi.purge_task(&a).await.unwrap();
+        assert!(yushi.get_task(&a).await.is_none());
+        yushi.compact_queue_state().await.unwrap();
+        drop(yushi);
+
+        // 归档状态写入状态文件，保留时间内重新加载后仍然可以查看
+        let reloaded = test_downloader(&dir, 8 * 1024);
+        reloaded.load_queue_from_state().await.unwrap();
+        assert!(reloaded.get_all_tasks().await.is_empty());
+        assert!(reloaded.get_task(&b).await.unwrap().archived);
+        assert_eq!(
+            reloaded
+                .purge_archived(Duration::from_secs(3600))
+                .await
+                .unwrap(),
+            0
+        );
+        drop(reloaded);
+
+        let config = Config {
+            archive_retention: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let pruned = with_queue_file(config, 2, dir.join("queue.json"))
+            .unwrap()
+            .0;
+        pruned.load_queue_from_state().await.unwrap();
+        assert!(pruned.get_tasks_filtered(true).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_progress_snapshot() {
+        let server = MockServer::start().await;
+        let body = test_body(50_000);
+        server.add("/file.bin", MockFile::new(body));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        assert!(yushi.progress("missing").is_none());
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        wait_for_terminal(&yushi, &id).await;
+
+        let snapshot = yushi.progress(&id).unwrap();
+        assert_eq!(snapshot.status, TaskStatus::Completed);
+        assert_eq!(snapshot.total, 50_000);
+        assert_eq!(
+            snapshot.chunk_summary,
+            ChunkSummary {
+                total: 7,
+                finished: 7
+            }
+        );
+        assert_eq!(yushi.progress_all(), vec![snapshot]);
+
+        yushi.remove_task(&id).await.unwrap();
+        assert!(yushi.progress_all().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stats_breakdown_by_priority() {
+        let server = MockServer::start().await;
+        server.add("/high.bin", MockFile::new(test_body(30_000)));
+        server.add("/normal.bin", MockFile::new(test_body(20_000)));
+        server.add("/low.bin", MockFile::new(test_body(10_000)));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+        yushi.pause_queue().await;
+
+        for (name, priority) in [
+            ("high", TaskPriority::High),
+            ("normal", TaskPriority::Normal),
+            ("low", TaskPriority::Low),
+        ] {
+            let options = TaskOptions {
+                priority,
+                ..Default::default()
+            };
+            yushi
+                .add_task_with(
+                    server.url(&format!("/{}.bin", name)),
+                    dir.join(name),
+                    options,
+                )
+                .await
+                .unwrap();
+        }
+        let unprobed = TaskOptions {
+            priority: TaskPriority::Low,
+            skip_probe: true,
+            ..Default::default()
+        };
+        yushi
+            .add_task_with(server.url("/low.bin"), dir.join("other"), unprobed)
+            .await
+            .unwrap();
+
+        // 探测之后才知道等待中任务的大小
+        let stats = yushi.get_stats();
+        assert!(stats.remaining_bytes_by_priority.is_empty());
+        assert_eq!(stats.unknown_size_by_priority[&TaskPriority::High], 1);
+        yushi.forecast(None).await;
+
+        let stats = yushi.get_stats();
+        let expected: HashMap<_, _> = [
+            (TaskPriority::High, 30_000),
+            (TaskPriority::Normal, 20_000),
+            (TaskPriority::Low, 10_000),
+        ]
+        .into();
+        assert_eq!(stats.remaining_bytes_by_priority, expected);
+        assert_eq!(
+            stats.unknown_size_by_priority,
+            [(TaskPriority::Low, 1)].into()
+        );
+        assert!(stats.active_by_priority.is_empty());
+        assert_eq!(stats.estimated_drain_time, None);
+
+        // 全部完成后没有剩余
+        yushi.set_max_concurrent_tasks(4).await.unwrap();
+        yushi.resume_queue().await.unwrap();
+        for task in yushi.get_all_tasks().await {
+            wait_for_terminal(&yushi, &task.id).await;
+        }
+        let stats = yushi.get_stats();
+        assert_eq!(stats.remaining_bytes_by_priority.values().sum::<u64>(), 0);
+        assert!(stats.unknown_size_by_priority.is_empty());
+        assert_eq!(stats.estimated_drain_time, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_records_terminal_tasks() {
+        let server = MockServer::start().await;
+        server.add("/file.bin", MockFile::new(test_body(20_000)));
+        let dir = temp_dir();
+        let audit_path = dir.join("audit.jsonl");
+        let config = Config {
+            chunk_size: 8 * 1024,
+            audit_log: Some(audit_path.clone()),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let ok = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let failed = yushi
+            .add_task(server.url("/missing.bin"), dir.join("missing.bin"))
+            .await
+            .unwrap();
+        wait_for_terminal(&yushi, &ok).await;
+        wait_for_terminal(&yushi, &failed).await;
+        yushi.flush_audit_log().await;
+
+        let records = crate::read_audit_log(&audit_path).await.unwrap();
+        assert_eq!(records.len(), 2);
+        let ok_record = records.iter().find(|r| r.task_id == ok).unwrap();
+        assert_eq!(ok_record.status, TaskStatus::Completed);
+        assert_eq!(ok_record.bytes, 20_000);
+        assert!(ok_record.error.is_none());
+        let failed_record = records.iter().find(|r| r.task_id == failed).unwrap();
+        assert_eq!(failed_record.status, TaskStatus::Failed);
+        assert!(failed_record.error.is_some());
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_byte_range_task() {
+        let server = MockServer::start().await;
+        let body = test_body(100_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        server.add("/plain.bin", MockFile::new(body.clone()).without_ranges());
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        // 校验只针对下载的这一段
+        let slice = &body[1000..31_000];
+        let options = TaskOptions {
+            byte_range: Some((1000, Some(30_999))),
+            checksum: Some(ChecksumType::Sha256(hex::encode(Sha256::digest(slice)))),
+            ..Default::default()
+        };
+        let plan = yushi
+            .plan_task(&server.url("/file.bin"), &dir.join("slice.bin"), &options)
+            .await
+            .unwrap();
+        assert_eq!(plan.chunks, 4);
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("slice.bin"), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(task.total_size, 30_000);
+        assert_eq!(std::fs::read(dir.join("slice.bin")).unwrap(), slice);
+
+        // 没有结束位置时下载到文件末尾，超出文件末尾的结束位置被截断
+        for (range, name) in [
+            ((90_000, None), "tail.bin"),
+            ((90_000, Some(500_000)), "tail2.bin"),
+        ] {
+            let options = TaskOptions {
+                byte_range: Some(range),
+                ..Default::default()
+            };
+            let id = yushi
+                .add_task_with(server.url("/file.bin"), dir.join(name), options)
+                .await
+                .unwrap();
+            let task = wait_for_terminal(&yushi, &id).await;
+            assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+            assert_eq!(std::fs::read(dir.join(name)).unwrap(), &body[90_000..]);
+        }
+
+        // 服务器不支持 Range 时任务失败，不会下载整个文件
+        let options = TaskOptions {
+            byte_range: Some((0, Some(9999))),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/plain.bin"), dir.join("plain.bin"), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Failed);
+        // 按消息键比较，不依赖运行测试时的语言
+        assert!(
+            task.error
+                .unwrap()
+                .contains(&message("download.range_unsupported", &[]))
+        );
+        assert!(!dir.join("plain.bin").exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_range() {
+        let server = MockServer::start().await;
+        let body = test_body(100_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        // 进度按这一段的长度计算
+        let (tx, mut rx) = mpsc::channel(1024);
+        let summary = yushi
+            .download_range(
+                &server.url("/file.bin"),
+                dir.join("head.bin"),
+                0..10_000,
+                Some(tx),
+            )
+            .await
+            .unwrap();
+        assert_eq!(summary.total_size, 10_000);
+        assert_eq!(
+            std::fs::read(dir.join("head.bin")).unwrap(),
+            &body[..10_000]
+        );
+        let mut initialized = None;
+        while let Ok(event) = rx.try_recv() {
+            if let ProgressEvent::Initialized { total_size, .. } = event {
+                initialized = Some(total_size);
+            }
+        }
+        assert_eq!(initialized, Some(Some(10_000)));
+
+        let error = yushi
+            .download_range(&server.url("/file.bin"), dir.join("empty.bin"), 5..5, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::TaskFailed(_)));
+
+        // HEAD 报告的大小过时，服务器以 416 拒绝时报告文件实际的大小
+        server.add(
+            "/stale.bin",
+            MockFile::new(test_body(50_000)).with_head_length(100_000),
+        );
+        let error = yushi
+            .download_range(
+                &server.url("/stale.bin"),
+                dir.join("stale.bin"),
+                60_000..70_000,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            Error::RangeNotSatisfiable(Some(50_000)).to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stalled_download_retries() {
+        let server = MockServer::start().await;
+        let body = test_body(2 * 1024 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).stall_after(1024 * 1024),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 4 * 1024 * 1024,
+            stall_timeout: Some(Duration::from_millis(500)),
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+
+        let mut retries = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Progress(ProgressEvent::Retrying {
+                attempt, reason, ..
+            }) = envelope.event
+            {
+                retries.push((attempt, reason));
+            }
+        }
+        assert_eq!(retries, vec![(1, RetryReason::Stalled)]);
+
+        // 重试从停滞的位置继续，而不是重新下载整个文件
+        let ranges: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "GET")
+            .filter_map(|r| r.headers.get("range").cloned())
+            .collect();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(
+            ranges[1],
+            format!("bytes={}-{}", 1024 * 1024, body.len() - 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_display_name_and_note() {
+        let server = MockServer::start().await;
+        server.add("/data.csv", MockFile::new(test_body(1000)));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        let options = TaskOptions {
+            display_name: Some("Q3 dataset".to_string()),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/data.csv"), dir.join("data.csv"), options)
+            .await
+            .unwrap();
+        yushi
+            .set_task_note(&id, Some("rerun".to_string()))
+            .await
+            .unwrap();
+        wait_for_terminal(&yushi, &id).await;
+
+        let task = yushi.get_task(&id).await.unwrap();
+        assert_eq!(task.label(), "Q3 dataset");
+        assert_eq!(task.to_view().note.as_deref(), Some("rerun"));
+        assert!(task.matches("q3 DATA"));
+        assert!(task.matches("RERUN"));
+        assert!(task.matches("data.csv"));
+        assert!(!task.matches("q4"));
+
+        // 显示名称和备注会持久化
+        yushi.compact_queue_state().await.unwrap();
+        let (reloaded, _) = with_queue_file(Config::default(), 2, dir.join("queue.json")).unwrap();
+        reloaded.load_queue_from_state().await.unwrap();
+        let task = reloaded.get_task(&id).await.unwrap();
+        assert_eq!(task.display_name.as_deref(), Some("Q3 dataset"));
+        assert_eq!(task.note.as_deref(), Some("rerun"));
+
+        yushi.set_task_display_name(&id, None).await.unwrap();
+        assert_eq!(yushi.get_task(&id).await.unwrap().label(), "data.csv");
+        assert!(matches!(
+            yushi.set_task_note("missing", None).await,
+            Err(Error::TaskNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_provenance_recorded_and_reused() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 8 * 1024,
+            headers: HashMap::from([
+                ("Authorization".to_string(), "Bearer hunter2".to_string()),
+                ("Accept".to_string(), "*/*".to_string()),
+            ]),
+            audit_log: Some(dir.join("audit.jsonl")),
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        // 第一次下载时文件还不存在
+        let options = TaskOptions {
+            connections: Some(3),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Failed);
+        let provenance = task.provenance.unwrap();
+        assert_eq!(provenance.url, server.url("/file.bin"));
+        assert_eq!(provenance.connections, 3);
+        assert_eq!(provenance.chunk_size, 8 * 1024);
+        assert_eq!(provenance.core_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(provenance.headers["accept"], "*/*");
+        assert_eq!(
+            provenance.headers["authorization"],
+            redact_header("Authorization", "Bearer hunter2")
+        );
+
+        // 敏感请求头不以明文写入任何文件
+        yushi.flush_audit_log().await;
+        let audit = crate::read_audit_log(&dir.join("audit.jsonl"))
+            .await
+            .unwrap();
+        assert_eq!(audit[0].provenance.as_ref(), Some(&provenance));
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let content = std::fs::read(entry.unwrap().path()).unwrap();
+            assert!(!String::from_utf8_lossy(&content).contains("hunter2"));
+        }
+
+        // 之后修改的连接数不会被记录的环境覆盖
+        server.add("/file.bin", MockFile::new(body.clone()));
+        yushi.set_task_connections(&id, 1).await.unwrap();
+        yushi.retry_task(&id, false).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.connections, Some(1));
+        assert_eq!(task.provenance.as_ref(), Some(&provenance));
+        assert!(matches!(
+            yushi.retry_task(&id, false).await,
+            Err(Error::CannotRetryTaskInCurrentStatus)
+        ));
+
+        // 按历史记录重新下载
+        let copy = yushi
+            .redownload(String::new(), dir.join("copy.bin"), Some(&provenance))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &copy).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.connections, Some(3));
+        assert_eq!(std::fs::read(dir.join("copy.bin")).unwrap(), body);
+
+        // --fresh 按当前设置重新记录
+        let gone = yushi
+            .add_task(server.url("/gone.bin"), dir.join("gone.bin"))
+            .await
+            .unwrap();
+        wait_for_terminal(&yushi, &gone).await;
+        yushi.set_task_connections(&gone, 2).await.unwrap();
+        yushi.retry_task(&gone, true).await.unwrap();
+        let task = wait_for_terminal(&yushi, &gone).await;
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert_eq!(task.provenance.unwrap().connections, 2);
+    }
+
+    #[tokio::test]
+    async fn test_host_credentials_attached_to_matching_requests() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        server.add("/private.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 8 * 1024,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+        let events = tokio::spawn(async move {
+            let mut seen = Vec::new();
+            while let Some(envelope) = rx.recv().await {
+                seen.push(format!("{:?}", envelope));
+            }
+            seen
+        });
+        yushi.set_host_credentials(
+            "localhost",
+            Credential::Bearer {
+                token: "wrong-host".to_string(),
+            },
+        );
+        yushi.set_host_credentials(
+            "127.0.0.1",
+            Credential::Basic {
+                username: "alice".to_string(),
+                password: "hunter2".to_string(),
+            },
+        );
+
+        let id = yushi
+            .add_task(server.url("/private.bin"), dir.join("private.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(std::fs::read(dir.join("private.bin")).unwrap(), body);
+
+        // 探测和分块请求都带上了匹配主机的认证信息
+        let requests = server.requests();
+        assert!(requests.len() > 1);
+        for request in &requests {
+            assert_eq!(
+                request.headers.get("authorization").map(String::as_str),
+                Some("Basic YWxpY2U6aHVudGVyMg==")
+            );
+        }
+
+        // 认证信息不出现在队列状态和事件中
+        let queue = std::fs::read_to_string(dir.join("queue.json")).unwrap();
+        assert!(!queue.contains("hunter2") && !queue.contains("YWxpY2U6aHVudGVyMg"));
+        drop(yushi);
+        for event in events.await.unwrap() {
+            assert!(!event.contains("hunter2"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_config_and_task_auth_applied_to_requests() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        server.add("/chunked.bin", MockFile::new(body.clone()));
+        server.add(
+            "/stream.bin",
+            MockFile::new(body.clone()).without_accept_ranges(),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 8 * 1024,
+            auth: Some(Credential::Bearer {
+                token: "config-token".to_string(),
+            }),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let chunked = yushi
+            .add_task(server.url("/chunked.bin"), dir.join("chunked.bin"))
+            .await
+            .unwrap();
+        let options = TaskOptions {
+            auth: Some(Credential::Basic {
+                username: "alice".to_string(),
+                password: "hunter2".to_string(),
+            }),
+            ..Default::default()
+        };
+        let streamed = yushi
+            .add_task_with(server.url("/stream.bin"), dir.join("stream.bin"), options)
+            .await
+            .unwrap();
+        for id in [&chunked, &streamed] {
+            let task = wait_for_terminal(&yushi, id).await;
+            assert_eq!(task.status, TaskStatus::Completed);
+        }
+
+        // 没有任务认证信息时使用配置中的，任务自己的优先
+        let requests = server.requests();
+        for (path, expected) in [
+            ("/chunked.bin", "Bearer config-token"),
+            ("/stream.bin", "Basic YWxpY2U6aHVudGVyMg=="),
+        ] {
+            let matching: Vec<_> = requests.iter().filter(|r| r.path == path).collect();
+            assert!(matching.iter().any(|r| r.method == "HEAD"));
+            assert!(matching.iter().any(|r| r.method == "GET"));
+            for request in matching {
+                assert_eq!(
+                    request.headers.get("authorization").map(String::as_str),
+                    Some(expected)
+                );
+            }
+        }
+
+        // 任务的认证信息不写入队列状态
+        let queue = std::fs::read_to_string(dir.join("queue.json")).unwrap();
+        assert!(!queue.contains("hunter2") && !queue.contains("YWxpY2U6aHVudGVyMg"));
+    }
+
+    #[tokio::test]
+    async fn test_cookies_file_sent_with_all_requests() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        server.add("/chunked.bin", MockFile::new(body.clone()));
+        server.add(
+            "/stream.bin",
+            MockFile::new(body.clone()).without_accept_ranges(),
+        );
+        let dir = temp_dir();
+        let cookies = dir.join("cookies.txt");
+        std::fs::write(
+            &cookies,
+            [
+                "# Netscape HTTP Cookie File",
+                "127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\tabc",
+                "#HttpOnly_127.0.0.1\tFALSE\t/\tFALSE\t4102444800\ttoken\txyz",
+                // 已过期、其他主机、路径不匹配和格式不正确的行
+                "127.0.0.1\tFALSE\t/\tFALSE\t1\texpired\told",
+                ".example.com\tTRUE\t/\tFALSE\t0\tother\thost",
+                "127.0.0.1\tFALSE\t/private\tFALSE\t0\tscoped\tpath",
+                "127.0.0.1\tFALSE\t/",
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+        let config = Config {
+            chunk_size: 8 * 1024,
+            cookies: Some(CookieSource::File(cookies)),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        for name in ["chunked.bin", "stream.bin"] {
+            let id = yushi
+                .add_task(server.url(&format!("/{}", name)), dir.join(name))
+                .await
+                .unwrap();
+            let task = wait_for_terminal(&yushi, &id).await;
+            assert_eq!(task.status, TaskStatus::Completed);
+        }
+
+        // 探测、分块和流式下载请求都带上了 cookies.txt 中匹配的 Cookie
+        let requests = server.requests();
+        assert!(requests.iter().any(|r| r.method == "HEAD"));
+        for request in &requests {
+            let mut sent: Vec<&str> = request
+                .headers
+                .get("cookie")
+                .map(|v| v.split("; ").collect())
+                .unwrap_or_default();
+            sent.sort();
+            assert_eq!(sent, ["session=abc", "token=xyz"], "{}", request.path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_routed_through_socks_proxy() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        server.add("/proxied.bin", MockFile::new(body.clone()));
+        let proxy = MockSocksProxy::start("alice", "s3cret").await;
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 8 * 1024,
+            proxy: Some(proxy.url("socks5h")),
+            proxy_user: Some("alice".to_string()),
+            proxy_password: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let id = yushi
+            .add_task(server.url("/proxied.bin"), dir.join("proxied.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(std::fs::read(dir.join("proxied.bin")).unwrap(), body);
+
+        // 每个连接都经过代理，目标是下载服务器
+        let targets = proxy.targets();
+        assert_eq!(targets.len(), server.connections());
+        assert!(
+            targets
+                .iter()
+                .all(|target| *target == server.addr().to_string())
+        );
+
+        // 无法解析或不支持的代理地址直接报错，而不是不使用代理
+        for url in ["socks4://127.0.0.1:1080", "http://[::1", "ftp://proxy"] {
+            let config = Config {
+                proxy: Some(url.to_string()),
+                ..Default::default()
+            };
+            assert!(matches!(
+                YuShi::in_memory(config, 1),
+                Err(Error::InvalidProxy(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_extra_root_certs_checked_at_construction() {
+        const TEST_CA: &str = "-----BEGIN CERTIFICATE-----
+MIIBhjCCAS2gAwIBAgIUYIr4qoE8HGEOTNban+ysZMqQQgwwCgYIKoZIzj0EAwIw
+GDEWMBQGA1UEAwwNWXVTaGkgVGVzdCBDQTAgFw0yNjEwMTYyMzQxMTBaGA8yMTI2
+MDkyMjIzNDExMFowGDEWMBQGA1UEAwwNWXVTaGkgVGVzdCBDQTBZMBMGByqGSM49
+AgEGCCqGSM49AwEHA0IABF0BgR2gPsk2DwJoPa8iyKjdMailbqKkmzLs5k1PemAR
+FF/CRozadfCv71uVSGRMAEBq4katWKiwZN3DUaJ5EFyjUzBRMB0GA1UdDgQWBBSv
+3iCrS6vHI3ZNvPsT4o6iHuA+bTAfBgNVHSMEGDAWgBSv3iCrS6vHI3ZNvPsT4o6i
+HuA+bTAPBgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0cAMEQCIEYg2TegNW6/
+wq5FVHKooP3zCoMUBcGp8q8vj+LgleiNAiBvalPdnKa5z5BpADD74sWD3Md7t9I2
+xFSXR/+Wkl4Ifw==
+-----END CERTIFICATE-----
+";
+        let dir = temp_dir();
+        let valid = dir.join("ca.pem");
+        std::fs::write(&valid, TEST_CA).unwrap();
+        let config = Config {
+            extra_root_certs: vec![valid.clone()],
+            danger_accept_invalid_certs: true,
+            ..Default::default()
+        };
+        assert!(YuShi::in_memory(config, 1).is_ok());
+
+        // 不存在的文件、不是 PEM 的文件和内容损坏的证书都在创建时报错，并指出是哪个文件
+        let not_pem = dir.join("not-pem.txt");
+        std::fs::write(&not_pem, "hello").unwrap();
+        let corrupt = dir.join("corrupt.pem");
+        std::fs::write(&corrupt, TEST_CA.replace("MIIB", "AAAA")).unwrap();
+        for path in [dir.join("missing.pem"), not_pem, corrupt] {
+            let config = Config {
+                extra_root_certs: vec![valid.clone(), path.clone()],
+                ..Default::default()
+            };
+            match YuShi::in_memory(config, 1) {
+                Err(Error::InvalidCertificate(file, _)) => {
+                    assert_eq!(file, path.display().to_string())
+                }
+                other => panic!("{:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_overrides_hostname() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        server.add("/cdn.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 8 * 1024,
+            resolve: vec![(
+                "Files.Staging.Test".to_string(),
+                "127.0.0.1:443".parse().unwrap(),
+            )],
+            local_address: Some("127.0.0.1".parse().unwrap()),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        // URL 使用真实的主机名，连接到本地服务器
+        let host = format!("files.staging.test:{}", server.addr().port());
+        let id = yushi
+            .add_task(format!("http://{}/cdn.bin", host), dir.join("cdn.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(std::fs::read(dir.join("cdn.bin")).unwrap(), body);
+
+        let requests = server.requests();
+        assert!(requests.len() > 1);
+        for request in &requests {
+            assert_eq!(request.headers.get("host"), Some(&host));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forecast_probes_unknown_sizes_lazily() {
+        let server = MockServer::start().await;
+        server.add("/a.bin", MockFile::new(test_body(10_000)));
+        server.add("/b.bin", MockFile::new(test_body(4_000)));
+        let dir = temp_dir();
+        let config = Config {
+            forecast_probe_limit: 1,
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+        yushi.pause_queue().await;
+        yushi
+            .add_task(server.url("/a.bin"), dir.join("a.bin"))
+            .await
+            .unwrap();
+        let options = TaskOptions {
+            priority: TaskPriority::High,
+            ..Default::default()
+        };
+        yushi
+            .add_task_with(server.url("/b.bin"), dir.join("sub").join("b.bin"), options)
+            .await
+            .unwrap();
+
+        // 每次只探测一个大小未知的任务（高优先级的先探测），还有未知大小时无法确定能否放下
+        let forecast = yushi.forecast(None).await;
+        assert_eq!(forecast.pending_bytes_known, 4_000);
+        assert_eq!(forecast.pending_unknown_count, 1);
+        assert_eq!(forecast.will_fit, None);
+        assert_eq!(forecast.free_space_by_volume.len(), 1);
+        let volume = &forecast.free_space_by_volume[0];
+        assert_eq!(volume.path, dir);
+        assert_eq!((volume.task_count, volume.unknown_count), (2, 1));
+
+        // 探测结果已缓存，第二次只需要探测剩下的任务
+        let forecast = yushi.forecast(None).await;
+        assert_eq!(forecast.pending_bytes_known, 14_000);
+        assert_eq!(forecast.pending_unknown_count, 0);
+        assert_eq!(forecast.will_fit, Some(true));
+        let heads = server
+            .requests()
+            .iter()
+            .filter(|r| r.method == "HEAD")
+            .count();
+        assert_eq!(heads, 2);
+
+        // 只统计指定目录下的任务
+        let forecast = yushi.forecast(Some(&dir.join("sub"))).await;
+        assert_eq!(forecast.pending_bytes_known, 4_000);
+        let forecast = yushi.forecast(Some(&dir.join("elsewhere"))).await;
+        assert!(forecast.free_space_by_volume.is_empty());
+        assert_eq!(forecast.will_fit, Some(true));
+    }
+
+    /// 每次写入前等待 `delay` 的存储后端，模拟很慢的磁盘，下载中可以修改延迟
+    struct SlowSink {
+        inner: MemorySink,
+        delay: Mutex<Duration>,
+    }
+
+    impl StorageSink for SlowSink {
+        fn supports_random_access(&self) -> bool {
+            true
+        }
+
+        fn open(&self, total_size: Option<u64>, resume: bool) -> crate::sink::SinkFuture<'_, ()> {
+            self.inner.open(total_size, resume)
+        }
+
+        fn write_at<'a>(&'a self, offset: u64, data: &'a [u8]) -> crate::sink::SinkFuture<'a, ()> {
+            Box::pin(async move {
+                let delay = *self.delay.lock().unwrap();
+                tokio::time::sleep(delay).await;
+                self.inner.write_at(offset, data).await
+            })
+        }
+
+        fn flush(&self) -> crate::sink::SinkFuture<'_, ()> {
+            self.inner.flush()
+        }
+
+        fn finalize(&self) -> crate::sink::SinkFuture<'_, ()> {
+            self.inner.finalize()
+        }
+
+        fn abort(&self) -> crate::sink::SinkFuture<'_, ()> {
+            self.inner.abort()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_buffered_bytes_with_slow_writer() {
+        const LIMIT: u64 = 256 * 1024;
+
+        let server = MockServer::start().await;
+        let body = test_body(1024 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 64 * 1024,
+            max_concurrent: 8,
+            max_buffered_bytes: Some(LIMIT),
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 4, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        let mut tasks = Vec::new();
+        for i in 0..4 {
+            let sink = Arc::new(SlowSink {
+                inner: MemorySink::new(),
+                delay: Mutex::new(Duration::from_millis(2)),
+            });
+            let options = TaskOptions {
+                sink: Some(sink.clone()),
+                ..Default::default()
+            };
+            let id = yushi
+                .add_task_with(
+                    server.url("/file.bin"),
+                    dir.join(format!("{}.bin", i)),
+                    options,
+                )
+                .await
+                .unwrap();
+            tasks.push((id, sink));
+        }
+
+        let monitor = {
+            let yushi = yushi.clone();
+            tokio::spawn(async move {
+                let mut peak = 0;
+                loop {
+                    let stats = yushi.get_stats();
+                    peak = peak.max(stats.buffered_bytes);
+                    if stats.completed + stats.failed == 4 {
+                        return peak;
+                    }
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            })
+        };
+        for (id, sink) in &tasks {
+            let task = wait_for_terminal(&yushi, id).await;
+            assert_eq!(task.status, TaskStatus::Completed);
+            assert_eq!(sink.inner.data(), body);
+        }
+
+        let peak = monitor.await.unwrap();
+        assert!(peak > 0);
+        assert!(peak <= LIMIT, "buffered {} bytes", peak);
+        assert_eq!(yushi.get_stats().buffered_bytes, 0);
+    }
+
+    fn temp_dir_downloader(dir: &Path) -> YuShi {
+        let config = Config {
+            chunk_size: 8 * 1024,
+            temp_dir: Some(dir.join("scratch")),
+            ..Default::default()
+        };
+        with_queue_file(config, 2, dir.join("queue.json"))
+            .unwrap()
+            .0
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_temp_dir_moves_on_completion_and_cleans_on_cancel() {
+        let server = MockServer::start().await;
+        let body = test_body(40_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        server.add(
+            "/slow.bin",
+            MockFile::new(test_body(200_000)).with_delay(Duration::from_millis(20)),
+        );
+        let dir = temp_dir();
+        let scratch = dir.join("scratch");
+        let yushi = temp_dir_downloader(&dir);
+
+        let dest = dir.join("out").join("file.bin");
+        let id = yushi
+            .add_task_with(
+                server.url("/file.bin"),
+                dest.clone(),
+                TaskOptions {
+                    checksum: Some(ChecksumType::Sha256(hex::encode(Sha256::digest(&body)))),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert!(!dest.with_extension("json").exists());
+        assert_eq!(std::fs::read_dir(&scratch).unwrap().count(), 0);
+
+        // 下载中的数据只出现在临时目录，取消后删除
+        let slow = dir.join("slow.bin");
+        let id = yushi
+            .add_task(server.url("/slow.bin"), slow.clone())
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            if scratch.join(&id).exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(scratch.join(&id).exists());
+        assert!(!slow.exists());
+        yushi.cancel_task(&id).await.unwrap();
+        assert!(!scratch.join(&id).exists());
+        assert!(!scratch.join(format!("{}.json", id)).exists());
+        assert!(!slow.exists());
+    }
+
+    #[tokio::test]
+    async fn test_temp_dir_resumes_after_restart() {
+        let server = MockServer::start().await;
+        let body = test_body(32 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let scratch = dir.join("scratch");
+        let url = server.url("/file.bin");
+        let dest = dir.join("file.bin");
+
+        // 上次运行只添加了任务，临时目录中留下了第一个分块
+        let id = {
+            let yushi = temp_dir_downloader(&dir);
+            yushi.pause_queue().await;
+            yushi.add_task(url.clone(), dest.clone()).await.unwrap()
+        };
+        std::fs::create_dir_all(&scratch).unwrap();
+        let mut partial = body[..8 * 1024].to_vec();
+        partial.resize(body.len(), 0);
+        std::fs::write(scratch.join(&id), partial).unwrap();
+        let chunks = (0..4)
+            .map(|i| {
+                let start = i * 8 * 1024;
+                ChunkState {
+                    index: i as usize,
+                    start,
+                    end: start + 8 * 1024 - 1,
+                    current: if i == 0 { start + 8 * 1024 } else { start },
+                    is_finished: i == 0,
+                    digest: None,
+                }
+            })
+            .collect();
+        DownloadState {
+            url: url.clone(),
+            total_size: Some(body.len() as u64),
+            chunks,
+            is_streaming: false,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 0,
+            reported_prefix: 0,
+        }
+        .save(&scratch.join(format!("{}.json", id)))
+        .await
+        .unwrap();
+
+        let yushi = temp_dir_downloader(&dir);
+        yushi.load_queue_from_state().await.unwrap();
+        yushi.start().await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert_eq!(std::fs::read_dir(&scratch).unwrap().count(), 0);
+
+        // 第一个分块没有重新下载
+        let ranges: Vec<_> = server
+            .requests()
+            .iter()
+            .filter_map(|r| r.headers.get("range").cloned())
+            .collect();
+        assert!(!ranges.is_empty());
+        assert!(!ranges.iter().any(|r| r.starts_with("bytes=0-")));
+    }
+
+    #[tokio::test]
+    async fn test_move_by_copy() {
+        let dir = temp_dir();
+        let src = dir.join("scratch.bin");
+        let dest = dir.join("final.bin");
+        let body = test_body(3 * 1024 * 1024 + 17);
+        std::fs::write(&src, &body).unwrap();
+
+        let (tx, mut rx) = mpsc::channel(1024);
+        move_by_copy("task", &src, &dest, &tx).await.unwrap();
+        drop(tx);
+
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert!(!src.exists());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let mut last = None;
+        while let Some(event) = rx.recv().await {
+            match event {
+                ProgressEvent::Finalizing { moved, total, .. } => {
+                    assert_eq!(total, body.len() as u64);
+                    assert!(last.is_none_or(|last| moved >= last));
+                    last = Some(moved);
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+        assert_eq!(last, Some(body.len() as u64));
+
+        // 复制失败时不留下半个文件，源文件保留
+        let missing = dir.join("missing.bin");
+        assert!(
+            move_by_copy(
+                "task",
+                &missing,
+                &dir.join("other.bin"),
+                &mpsc::channel(1).0
+            )
+            .await
+            .is_err()
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    /// 记录每次写入偏移的存储后端
+    #[derive(Default)]
+    struct OrderSink {
+        inner: MemorySink,
+        writes: Mutex<Vec<u64>>,
+    }
+
+    impl StorageSink for OrderSink {
+        fn supports_random_access(&self) -> bool {
+            true
+        }
+
+        fn open(&self, total_size: Option<u64>, resume: bool) -> crate::sink::SinkFuture<'_, ()> {
+            self.inner.open(total_size, resume)
+        }
+
+        fn write_at<'a>(&'a self, offset: u64, data: &'a [u8]) -> crate::sink::SinkFuture<'a, ()> {
+            self.writes.lock().unwrap().push(offset);
+            self.inner.write_at(offset, data)
+        }
+
+        fn flush(&self) -> crate::sink::SinkFuture<'_, ()> {
+            self.inner.flush()
+        }
+
+        fn finalize(&self) -> crate::sink::SinkFuture<'_, ()> {
+            self.inner.finalize()
+        }
+
+        fn abort(&self) -> crate::sink::SinkFuture<'_, ()> {
+            self.inner.abort()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sequential_first_downloads_prefix_in_order() {
+        const PREFIX: u64 = 64 * 1024;
+
+        let server = MockServer::start().await;
+        let body = test_body(256 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_micros(200)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 16 * 1024,
+            max_concurrent: 4,
+            sequential_first: true,
+            sequential_prefix: PREFIX,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        let sink = Arc::new(OrderSink::default());
+        let options = TaskOptions {
+            sink: Some(sink.clone()),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(sink.inner.data(), body);
+        assert_eq!(task.contiguous_prefix, body.len() as u64);
+
+        // 前缀内的数据按顺序写入，并且都在其余分块之前
+        let writes = sink.writes.lock().unwrap().clone();
+        let split = writes.iter().position(|&o| o >= PREFIX).unwrap();
+        assert!(writes[..split].is_sorted());
+        assert!(writes[split..].iter().all(|&o| o >= PREFIX));
+        // 之后的分块并发下载
+        assert!(!writes[split..].is_sorted());
+    }
+
+    const ORDER_CHUNK: u64 = 16 * 1024;
+
+    /// 按给定的分块顺序下载，返回任务 ID 和每次写入的偏移
+    async fn chunk_write_order(order: ChunkOrder, connections: usize) -> (String, Vec<u64>) {
+        let server = MockServer::start().await;
+        let body = test_body(16 * ORDER_CHUNK as usize);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_micros(200)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: ORDER_CHUNK,
+            max_concurrent: connections,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        let sink = Arc::new(OrderSink::default());
+        let options = TaskOptions {
+            sink: Some(sink.clone()),
+            chunk_order: Some(order),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.chunk_order, Some(order));
+        assert_eq!(sink.inner.data(), body);
+        let diagnostics = yushi.diagnostics(&id).await.unwrap();
+        assert_eq!(diagnostics.config.chunk_order, order);
+
+        let writes = sink.writes.lock().unwrap().clone();
+        (id, writes)
+    }
+
+    /// 各分块第一次写入的先后顺序
+    fn started_chunks(writes: &[u64]) -> Vec<usize> {
+        let mut started = Vec::new();
+        for offset in writes {
+            let index = (offset / ORDER_CHUNK) as usize;
+            if !started.contains(&index) {
+                started.push(index);
+            }
+        }
+        started
+    }
+
+    #[tokio::test]
+    async fn test_chunk_order_policies() {
+        // 严格顺序：即使有 4 个连接，所有写入也按偏移递增
+        let (_, writes) = chunk_write_order(ChunkOrder::Sequential, 4).await;
+        assert!(writes.is_sorted());
+
+        // 并发下载时分块交错写入
+        let (_, writes) = chunk_write_order(ChunkOrder::Parallel, 4).await;
+        assert!(!writes.is_sorted());
+
+        // 打乱的顺序由任务 ID 决定
+        let (id, writes) = chunk_write_order(ChunkOrder::Random, 1).await;
+        let started = started_chunks(&writes);
+        assert_eq!(started, shuffled_indices(16, &id));
+        assert_ne!(started, (0..16).collect::<Vec<_>>());
+        assert_eq!(shuffled_indices(16, &id), shuffled_indices(16, &id));
+        let mut sorted = started.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..16).collect::<Vec<_>>());
+
+        // 开头的分块逐个按顺序下载，其余并发
+        let prefix = 4 * ORDER_CHUNK;
+        let (_, writes) = chunk_write_order(ChunkOrder::SequentialFirst(prefix), 4).await;
+        let split = writes.iter().position(|&o| o >= prefix).unwrap();
+        assert!(writes[..split].is_sorted());
+        assert_eq!(started_chunks(&writes[..split]), [0, 1, 2, 3]);
+    }
+
+    /// 写入的数据先缓冲，`flush` 之后才可读的存储后端，模拟操作系统之前的写缓冲
+    #[derive(Default)]
+    struct BufferedSink {
+        /// 已经可以读到的数据
+        visible: MemorySink,
+        pending: Mutex<Vec<(u64, Vec<u8>)>>,
+    }
+
+    impl BufferedSink {
+        async fn apply_pending(&self) -> Result<()> {
+            let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+            for (offset, data) in pending {
+                self.visible.write_at(offset, &data).await?;
+            }
+            Ok(())
+        }
+    }
+
+    impl StorageSink for BufferedSink {
+        fn supports_random_access(&self) -> bool {
+            true
+        }
+
+        fn open(&self, total_size: Option<u64>, resume: bool) -> crate::sink::SinkFuture<'_, ()> {
+            self.visible.open(total_size, resume)
+        }
+
+        fn write_at<'a>(&'a self, offset: u64, data: &'a [u8]) -> crate::sink::SinkFuture<'a, ()> {
+            self.pending.lock().unwrap().push((offset, data.to_vec()));
+            Box::pin(async { Ok(()) })
+        }
+
+        fn flush(&self) -> crate::sink::SinkFuture<'_, ()> {
+            Box::pin(self.apply_pending())
+        }
+
+        fn finalize(&self) -> crate::sink::SinkFuture<'_, ()> {
+            Box::pin(async move {
+                self.apply_pending().await?;
+                self.visible.finalize().await
+            })
+        }
+
+        fn abort(&self) -> crate::sink::SinkFuture<'_, ()> {
+            self.visible.abort()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_contiguous_prefix_never_exceeds_flushed_data() {
+        let server = MockServer::start().await;
+        let body = test_body(1024 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_micros(200)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 64 * 1024,
+            max_concurrent: 4,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        let sink = Arc::new(BufferedSink::default());
+        let options = TaskOptions {
+            sink: Some(sink.clone()),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+
+        // 每收到一次进度就读取已落盘的数据，报告的前缀必须已经可读
+        let mut reported = 0;
+        let mut checks = 0;
+        while let Some(envelope) = rx.recv().await {
+            match envelope.event {
+                DownloaderEvent::Progress(ProgressEvent::Updated {
+                    contiguous_prefix, ..
+                }) => {
+                    assert!(contiguous_prefix >= reported);
+                    reported = contiguous_prefix;
+                    let len = contiguous_prefix as usize;
+                    if len > 0 && len < body.len() {
+                        let data = sink.visible.data();
+                        assert_eq!(data[..len], body[..len], "prefix {}", len);
+                        checks += 1;
+                    }
+                }
+                DownloaderEvent::Task(TaskEvent::Completed { .. }) => break,
+                DownloaderEvent::Task(TaskEvent::Failed { error, .. }) => panic!("{}", error),
+                _ => {}
+            }
+        }
+        assert!(checks > 0);
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.contiguous_prefix, body.len() as u64);
+        assert_eq!(sink.visible.data(), body);
+    }
+
+    #[tokio::test]
+    async fn test_tasks_changed_events_rebuild_snapshot() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add("/a.bin", MockFile::new(body.clone()));
+        server.add("/b.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 16 * 1024,
+            tasks_changed_interval: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+        let a = yushi
+            .add_task(server.url("/a.bin"), dir.join("a.bin"))
+            .await
+            .unwrap();
+        let b = yushi
+            .add_task(server.url("/b.bin"), dir.join("b.bin"))
+            .await
+            .unwrap();
+
+        // 只应用增量，得到的列表应与快照一致
+        let mut generation = 0;
+        let mut views: HashMap<String, TaskView> = HashMap::new();
+        let mut apply = |changes: TaskChanges, views: &mut HashMap<String, TaskView>| {
+            assert_eq!(changes.generation, generation + 1);
+            generation = changes.generation;
+            for view in changes.added.into_iter().chain(changes.updated) {
+                views.insert(view.id.clone(), view);
+            }
+            for id in changes.removed {
+                views.remove(&id);
+            }
+        };
+        let done = |views: &HashMap<String, TaskView>| {
+            views.len() == 2 && views.values().all(|v| v.status == TaskStatus::Completed)
+        };
+        while !done(&views) {
+            let envelope = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let DownloaderEvent::Global(GlobalEvent::TasksChanged(changes)) = envelope.event {
+                apply(changes, &mut views);
+            }
+        }
+        let (snapshot_generation, snapshot) = yushi.get_tasks_snapshot().await;
+        assert_eq!(snapshot_generation, generation);
+        assert_eq!(yushi.tasks_generation(), generation);
+        let mut rebuilt: Vec<_> = views.values().cloned().collect();
+        rebuilt.sort_by(|x, y| x.id.cmp(&y.id));
+        assert_eq!(rebuilt, snapshot);
+
+        // 错过的变更可以一次补齐
+        yushi.remove_task(&a).await.unwrap();
+        let (_, snapshot) = yushi.get_tasks_snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].id, b);
+        let missed = yushi.get_changes_since(generation).unwrap();
+        assert_eq!(missed.removed, [a]);
+        assert!(missed.added.is_empty());
+        let from_start = yushi.get_changes_since(0).unwrap();
+        assert_eq!(from_start.added, snapshot);
+        assert!(from_start.removed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ramp_up_spaces_connections() {
+        const INTERVAL: Duration = Duration::from_millis(150);
+
+        let server = MockServer::start().await;
+        let body = test_body(128 * 1024);
+        // 每个分块约需 320ms，逐步增加连接期间第一个分块还没有下载完
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_millis(20)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 16 * 1024,
+            max_concurrent: 3,
+            ramp_up: Some(INTERVAL),
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+
+        let mut observed = Vec::new();
+        loop {
+            let snapshot = yushi.progress(&id).unwrap();
+            if observed.last() != Some(&snapshot.connections) {
+                observed.push(snapshot.connections);
+            }
+            if snapshot.status == TaskStatus::Completed {
+                break;
+            }
+            assert_ne!(snapshot.status, TaskStatus::Failed);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+        assert!(observed.starts_with(&[0, 1, 2, 3]) || observed.starts_with(&[1, 2, 3]));
+
+        let arrivals: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "GET")
+            .filter(|r| r.headers.get("range").is_some_and(|v| v != "bytes=0-0"))
+            .map(|r| r.received_at)
+            .take(3)
+            .collect();
+        for pair in arrivals.windows(2) {
+            let gap = pair[1] - pair[0];
+            assert!(gap >= INTERVAL - Duration::from_millis(20), "gap {:?}", gap);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_reports_inconsistent_state() {
+        let server = MockServer::start().await;
+        let body = test_body(32 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+        let url = server.url("/file.bin?signature=secret");
+        let dest = dir.join("file.bin");
+
+        yushi.pause_queue().await;
+        let id = yushi.add_task(url.clone(), dest.clone()).await.unwrap();
+        let diagnostics = yushi.diagnostics(&id).await.unwrap();
+        assert!(diagnostics.state.is_none());
+        assert!(diagnostics.state_saved_at.is_none());
+        assert_eq!(diagnostics.config.chunk_size, 8 * 1024);
+
+        // 第二个分块的写入位置越过了分块末尾，第三个分块缺失
+        let chunk = |index: usize, current: u64, finished: bool| ChunkState {
+            index,
+            start: index as u64 * 8 * 1024,
+            end: (index as u64 + 1) * 8 * 1024 - 1,
+            current,
+            is_finished: finished,
+            digest: None,
+        };
+        DownloadState {
+            url: url.clone(),
+            total_size: Some(body.len() as u64),
+            chunks: vec![
+                chunk(0, 8 * 1024, true),
+                chunk(1, 20_000, false),
+                chunk(3, 24 * 1024, false),
+            ],
+            is_streaming: false,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 0,
+            reported_prefix: 0,
+        }
+        .save(&partial_paths(&dest).1)
+        .await
+        .unwrap();
+
+        let diagnostics = yushi.diagnostics(&id).await.unwrap();
+        assert!(!diagnostics.url.contains("secret"));
+        assert!(diagnostics.url.ends_with("/file.bin?redacted"));
+        assert!(diagnostics.state_saved_at.is_some());
+        let state = diagnostics.state.unwrap();
+        assert!(!state.url.contains("secret"));
+        assert_eq!(state.chunks.len(), 3);
+        assert_eq!(
+            state.issues,
+            [
+                ChunkIssue::CurrentOutOfRange {
+                    index: 1,
+                    current: 20_000
+                },
+                ChunkIssue::Gap {
+                    after: 1,
+                    start: 16 * 1024,
+                    end: 24 * 1024 - 1
+                },
+                ChunkIssue::SizeMismatch {
+                    expected: body.len() as u64,
+                    covered: 24 * 1024
+                },
+            ]
+        );
+        let unredacted = yushi.diagnostics_with(&id, false).await.unwrap();
+        assert_eq!(unredacted.url, url);
+        assert!(serde_json::to_string(&diagnostics.config).is_ok());
+
+        // 不一致的状态不会用于续传
+        yushi.resume_queue().await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_invalidate_chunks_refetches_only_those_ranges() {
+        let server = MockServer::start().await;
+        let body = test_body(40_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let (yushi, mut rx) =
+            with_queue_file(Config::default(), 2, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        // 前三个分块已完成，第 2 个分块在磁盘上损坏，最后一个分块还没有下载
+        let dest = dir.join("file.bin");
+        let (part, state_path) = partial_paths(&dest);
+        let chunk_size = 10_000u64;
+        let chunks = (0..4u64)
+            .map(|i| {
+                let (start, end) = (i * chunk_size, (i + 1) * chunk_size - 1);
+                let finished = i < 3;
+                ChunkState {
+                    index: i as usize,
+                    start,
+                    end,
+                    current: if finished { end + 1 } else { start },
+                    is_finished: finished,
+                    digest: finished
+                        .then(|| hex::encode(Sha256::digest(&body[start as usize..=end as usize]))),
+                }
+            })
+            .collect();
+        let url = server.url("/file.bin");
+        DownloadState {
+            url: url.clone(),
+            total_size: Some(body.len() as u64),
+            chunks,
+            is_streaming: false,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 0,
+            reported_prefix: 0,
+        }
+        .save(&state_path)
+        .await
+        .unwrap();
+        let mut partial = body[..30_000].to_vec();
+        partial[15_000] ^= 0xff;
+        partial.resize(body.len(), 0);
+        std::fs::write(&part, &partial).unwrap();
+
+        let id = yushi
+            .insert_task(url, dest.clone(), TaskOptions::default(), None)
+            .await;
+        let set_status = |status| {
+            let mut tasks = yushi.tasks.try_write().unwrap();
+            tasks.get_mut(&id).unwrap().status = status;
+        };
+
+        set_status(TaskStatus::Downloading);
+        assert!(matches!(
+            yushi.invalidate_chunks(&id, &[1]).await,
+            Err(Error::CannotInvalidateChunksInCurrentStatus)
+        ));
+        set_status(TaskStatus::Paused);
+
+        assert_eq!(yushi.damaged_chunks(&id).await.unwrap(), [1]);
+        assert!(yushi.invalidate_chunks(&id, &[1, 9]).await.is_err());
+        yushi.invalidate_chunks(&id, &[1]).await.unwrap();
+        let state = yushi.diagnostics(&id).await.unwrap().state.unwrap();
+        assert!(!state.chunks[1].finished);
+        assert_eq!(state.chunks[1].current, chunk_size);
+        assert_eq!(state.downloaded, 2 * chunk_size);
+        assert_eq!(
+            yushi.get_task(&id).await.unwrap().downloaded,
+            2 * chunk_size
+        );
+
+        yushi.resume_task(&id).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+
+        let mut ranges: Vec<String> = server
+            .requests()
+            .iter()
+            .filter_map(|r| r.headers.get("range").cloned())
+            .filter(|r| r != "bytes=0-0")
+            .collect();
+        ranges.sort();
+        assert_eq!(ranges, ["bytes=10000-19999", "bytes=30000-39999"]);
+    }
+
+    #[tokio::test]
+    async fn test_expired_url_is_refreshed() {
+        let server = MockServer::start().await;
+        let body = test_body(256 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_micros(500)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 16 * 1024,
+            max_concurrent: 2,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let refresher: UrlRefresher = {
+            let calls = calls.clone();
+            let url = server.url("/file.bin?token=new");
+            Arc::new(move |_, _| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let url = url.clone();
+                Box::pin(async move { Ok(url) })
+            })
+        };
+        let sink = Arc::new(OrderSink::default());
+        let options = TaskOptions {
+            sink: Some(sink.clone()),
+            url_refresher: Some(refresher),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(
+                server.url("/file.bin?token=old"),
+                dir.join("file.bin"),
+                options,
+            )
+            .await
+            .unwrap();
+
+        // 下载到一半时旧地址过期
+        while yushi.progress(&id).is_none_or(|p| p.downloaded < 64 * 1024) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        server.reject_query("token=old");
+
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(task.url.ends_with("token=new"));
+        assert_eq!(task.provenance.unwrap().url, task.url);
+        assert_eq!(sink.inner.data(), body);
+        // 已经下载的数据没有重新下载
+        let writes = sink.writes.lock().unwrap().clone();
+        let unique: HashSet<_> = writes.iter().collect();
+        assert_eq!(unique.len(), writes.len());
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_set_task_url_uses_new_url() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        server.reject_query("token=old");
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 8 * 1024);
+
+        let id = yushi
+            .add_task(server.url("/file.bin?token=old"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        assert_eq!(
+            wait_for_terminal(&yushi, &id).await.status,
+            TaskStatus::Failed
+        );
+
+        // 换成新地址后按记录的环境重试，不会退回已经过期的旧地址
+        let new_url = server.url("/file.bin?token=new");
+        yushi.set_task_url(&id, new_url.clone()).await.unwrap();
+        yushi.retry_task(&id, false).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(task.url, new_url);
+        assert_eq!(task.provenance.unwrap().url, new_url);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_set_task_url_rejects_different_file() {
+        let server = MockServer::start().await;
+        server.add("/a.bin", MockFile::new(test_body(4096)));
+        server.add("/b.bin", MockFile::new(test_body(8192)));
+        server.add("/mirror.bin", MockFile::new(test_body(4096)));
+        let dir = temp_dir();
+        let config = Config {
+            max_concurrent: 2,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        let id = yushi
+            .add_task(server.url("/a.bin"), dir.join("a.bin"))
+            .await
+            .unwrap();
+        assert_eq!(
+            wait_for_terminal(&yushi, &id).await.status,
+            TaskStatus::Completed
+        );
+
+        let result = yushi.set_task_url(&id, server.url("/b.bin")).await;
+        assert!(matches!(result, Err(Error::UrlMismatch(_))));
+        assert_eq!(yushi.get_task(&id).await.unwrap().url, server.url("/a.bin"));
+
+        yushi
+            .set_task_url(&id, server.url("/mirror.bin"))
+            .await
+            .unwrap();
+        assert_eq!(
+            yushi.get_task(&id).await.unwrap().url,
+            server.url("/mirror.bin")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_queue_writes_no_state_file() {
+        let server = MockServer::start().await;
+        server.add("/file.bin", MockFile::new(test_body(64 * 1024)));
+        let dir = temp_dir();
+        let (yushi, mut rx) = YuShi::in_memory(Config::default(), 1).unwrap();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        yushi.load_queue_from_state().await.unwrap();
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        assert_eq!(
+            wait_for_terminal(&yushi, &id).await.status,
+            TaskStatus::Completed
+        );
+        yushi.compact_queue_state().await.unwrap();
+
+        // 目录中只有下载的文件
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["file.bin"]);
+    }
+
+    #[tokio::test]
+    async fn test_speed_stats_exclude_paused_time() {
+        let server = MockServer::start().await;
+        let body = test_body(384 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body).with_delay(Duration::from_millis(4)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            max_concurrent: 1,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+
+        let started = Instant::now();
+        tokio::time::sleep(Duration::from_millis(1200)).await;
+        yushi.pause_task(&id).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        yushi.resume_task(&id).await.unwrap();
+
+        let (average, peak) = loop {
+            let envelope = rx.recv().await.unwrap();
+            if let DownloaderEvent::Task(TaskEvent::Completed {
+                average_speed,
+                peak_speed,
+                ..
+            }) = envelope.event
+            {
+                break (average_speed, peak_speed);
+            }
+        };
+        let wall = started.elapsed().as_millis() as u64;
+        let task = yushi.get_task(&id).await.unwrap();
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!((task.average_speed, task.peak_speed), (average, peak));
+
+        // 暂停的一秒不计入下载时长
+        assert!(task.active_duration_ms > 0);
+        assert!(
+            task.active_duration_ms + 800 < wall,
+            "{} / {}",
+            task.active_duration_ms,
+            wall
+        );
+        assert_eq!(average, task.compute_average_speed());
+        assert!(peak > 0);
+        assert!(average > 0);
+    }
+
+    #[tokio::test]
+    async fn test_add_from_index() {
+        let server = MockServer::start().await;
+        server.add(
+            "/pub/",
+            MockFile::html(
+                r#"<a href="../">../</a><a href="sub/">sub/</a><a href="a.iso">a.iso</a>
+                <a href="notes.txt">notes.txt</a><a href="http://other.invalid/x.iso">x.iso</a>"#,
+            ),
+        );
+        server.add(
+            "/pub/sub/",
+            MockFile::html(r#"<a href="b.iso">b.iso</a><a href="deeper/">deeper/</a>"#),
+        );
+        server.add(
+            "/pub/sub/deeper/",
+            MockFile::html(r#"<a href="c.iso">c.iso</a>"#),
+        );
+        for path in ["/pub/a.iso", "/pub/notes.txt", "/pub/sub/b.iso"] {
+            server.add(path, MockFile::new(test_body(8 * 1024)));
+        }
+        server.add(
+            "/login/",
+            MockFile::html(r#"<form><input type="password" name="pass"></form>"#),
+        );
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 4 * 1024);
+
+        // 不是 HTML 或者是登录页时不添加任何任务
+        let err = yushi
+            .add_from_index(&server.url("/pub/a.iso"), &dir, IndexOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidIndex(_)), "{err:?}");
+        let err = yushi
+            .add_from_index(&server.url("/login/"), &dir, IndexOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidIndex(_)), "{err:?}");
+        assert!(yushi.get_all_tasks().await.is_empty());
+
+        let options = IndexOptions {
+            depth: 1,
+            include_glob: Some("*.iso".to_string()),
+            ..Default::default()
+        };
+        let ids = yushi
+            .add_from_index(&server.url("/pub/"), &dir, options)
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 2);
+        for id in &ids {
+            assert_eq!(
+                wait_for_terminal(&yushi, id).await.status,
+                TaskStatus::Completed
+            );
+        }
+        assert!(dir.join("a.iso").exists());
+        assert!(dir.join("sub").join("b.iso").exists());
+        assert!(!dir.join("notes.txt").exists());
+        assert!(!dir.join("sub").join("deeper").exists());
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_verify_only_task() {
+        let dir = temp_dir();
+        let path = dir.join("local.bin");
+        let body = test_body(64 * 1024);
+        std::fs::write(&path, &body).unwrap();
+        let (yushi, mut rx) =
+            with_queue_file(Config::default(), 2, dir.join("queue.json")).unwrap();
+        let events = tokio::spawn(async move {
+            let mut events = Vec::new();
+            while let Some(event) = rx.recv().await {
+                events.push(event);
+            }
+            events
+        });
+
+        let hash = hex::encode(Sha256::digest(&body));
+        let id = yushi
+            .add_verify_task(
+                path.clone(),
+                ChecksumType::Sha256(hash),
+                TaskPriority::Normal,
+            )
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.kind, TaskKind::VerifyOnly);
+        assert_eq!(task.total_size, body.len() as u64);
+        assert!(matches!(
+            yushi.pause_task(&id).await,
+            Err(Error::CannotPauseVerifyOnlyTask)
+        ));
+
+        // 校验失败时文件保留
+        let bad = ChecksumType::Sha256("0".repeat(64));
+        let failed = yushi
+            .add_verify_task(path.clone(), bad.clone(), TaskPriority::Normal)
+            .await
+            .unwrap();
+        assert_eq!(
+            wait_for_terminal(&yushi, &failed).await.status,
+            TaskStatus::Failed
+        );
+        assert!(path.exists());
+
+        // 取消等待中的任务也不删除文件
+        yushi.pause_queue().await;
+        let cancelled = yushi
+            .add_verify_task(path.clone(), bad, TaskPriority::Normal)
+            .await
+            .unwrap();
+        yushi.cancel_task(&cancelled).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+
+        drop(yushi);
+        let events = events.await.unwrap();
+        let verified: Vec<_> = events
+            .iter()
+            .filter_map(|envelope| match &envelope.event {
+                DownloaderEvent::Verification(VerificationEvent::Completed {
+                    task_id,
+                    success,
+                    ..
+                }) => Some((task_id.clone(), *success)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(verified, [(id, true), (failed, false)]);
+        assert!(!events.iter().any(|envelope| matches!(
+            envelope.event,
+            DownloaderEvent::Progress(ProgressEvent::Updated { .. })
+        )));
+    }
+
+    #[cfg(feature = "checksums")]
+    #[tokio::test]
+    async fn test_complete_with_holes_then_retry() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        server.fail_range(16 * 1024);
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 16 * 1024,
+            on_chunk_exhausted: ChunkFailurePolicy::CompleteWithHoles,
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+        let dest = dir.join("file.bin");
+        let options = TaskOptions {
+            checksum: Some(ChecksumType::Sha256(hex::encode(Sha256::digest(&body)))),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dest.clone(), options)
+            .await
+            .unwrap();
+
+        // 失败的分块要用完全部重试，每次间隔 2 秒
+        let mut task = yushi.get_task(&id).await.unwrap();
+        for _ in 0..300 {
+            if task.status == TaskStatus::PartiallyCompleted {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            task = yushi.get_task(&id).await.unwrap();
+        }
+        assert_eq!(task.status, TaskStatus::PartiallyCompleted);
+        assert_eq!(task.missing_ranges, [(16 * 1024, 32 * 1024 - 1)]);
+        // 有缺失范围的文件不校验
+        assert_eq!(task.verified, 0);
+        assert_eq!(yushi.get_stats().partially_completed, 1);
+
+        // 重试时只请求缺失的范围
+        server.clear_failing_ranges();
+        let before = server.requests().len();
+        yushi.retry_task(&id, false).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert!(task.missing_ranges.is_empty());
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        let ranges: Vec<_> = server.requests()[before..]
+            .iter()
+            .filter(|r| r.method == "GET")
+            .filter_map(|r| r.headers.get("range").cloned())
+            .collect();
+        assert_eq!(ranges, ["bytes=16384-32767"]);
+    }
+
+    #[tokio::test]
+    async fn test_query_tasks() {
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+        yushi.pause_queue().await;
+        let mut ids = Vec::new();
+        for (i, (name, status, size)) in [
+            ("b.iso", TaskStatus::Failed, 300),
+            ("a.iso", TaskStatus::Pending, 100),
+            ("c.txt", TaskStatus::Failed, 200),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let id = yushi
+                .insert_task(
+                    format!("http://example.com/{name}"),
+                    dir.join(name),
+                    TaskOptions::default(),
+                    None,
+                )
+                .await;
+            if let Some(task) = yushi.tasks.write().await.get_mut(&id) {
+                task.status = status;
+                task.total_size = size;
+                task.created_at = i as u64;
+                task.archived = name == "c.txt";
+            }
+            ids.push(id);
+        }
+        let names = |tasks: Vec<Task>| tasks.iter().map(Task::label).collect::<Vec<_>>();
+
+        assert_eq!(
+            names(yushi.query_tasks(&TaskQuery::default()).await),
+            ["b.iso", "a.iso"]
+        );
+        let query = TaskQuery {
+            statuses: vec![TaskStatus::Failed],
+            include_archived: true,
+            sort: TaskSort::Size,
+            descending: true,
+            ..Default::default()
+        };
+        assert_eq!(names(yushi.query_tasks(&query).await), ["b.iso", "c.txt"]);
+        let query = TaskQuery {
+            search: Some(".ISO".to_string()),
+            sort: TaskSort::Name,
+            ..Default::default()
+        };
+        assert_eq!(names(yushi.query_tasks(&query).await), ["a.iso", "b.iso"]);
+    }
+
+    #[tokio::test]
+    async fn test_event_journal_persisted() {
+        let server = MockServer::start().await;
+        server.add("/file.bin", MockFile::new(test_body(20_000)));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        let task_id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &task_id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        let kinds: Vec<_> = yushi
+            .get_task_events(&task_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                CompactEventKind::Added,
+                CompactEventKind::Started,
+                CompactEventKind::Completed {
+                    skipped_unchanged: false
+                },
+            ]
+        );
+
+        // 事件记录随队列状态保存，重启后仍然保留
+        yushi.compact_queue_state().await.unwrap();
+        let reloaded = test_downloader(&dir, 16 * 1024);
+        reloaded.load_queue_from_state().await.unwrap();
+        let events = reloaded.get_task_events(&task_id).await.unwrap();
+        assert_eq!(
+            events,
+            task.recent_events.iter().cloned().collect::<Vec<_>>()
+        );
+
+        let config = Config {
+            event_journal: false,
+            ..Default::default()
+        };
+        let (quiet, _) = with_queue_file(config, 2, dir.join("quiet-queue.json")).unwrap();
+        let task_id = quiet
+            .add_task(server.url("/file.bin"), dir.join("quiet.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&quiet, &task_id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert!(task.recent_events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_completed_link_dir() {
+        let server = MockServer::start().await;
+        let body = test_body(20_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let links = dir.join("links");
+        std::fs::create_dir_all(&links).unwrap();
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        let config = Config {
+            completed_link_dir: Some(links.clone()),
+            ..Default::default()
+        };
+        let (yushi, _) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let first = yushi
+            .add_task(server.url("/file.bin"), dir.join("a/file.bin"))
+            .await
+            .unwrap();
+        let first = wait_for_terminal(&yushi, &first).await;
+        assert_eq!(first.status, TaskStatus::Completed);
+        assert_eq!(first.completed_link, Some(links.join("file.bin")));
+        assert_eq!(std::fs::read(links.join("file.bin")).unwrap(), body);
+
+        // 链接名称冲突时按任务的冲突处理方式自动重命名
+        let options = TaskOptions {
+            conflict: ConflictPolicy::Rename,
+            ..Default::default()
+        };
+        let second = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("b/file.bin"), options)
+            .await
+            .unwrap();
+        let second = wait_for_terminal(&yushi, &second).await;
+        assert_eq!(second.completed_link, Some(links.join("file (1).bin")));
+
+        yushi
+            .remove_task_with_options(&second.id, RemoveOptions { delete_file: true })
+            .await
+            .unwrap();
+        assert!(!second.dest.exists());
+        assert!(!links.join("file (1).bin").exists());
+        assert!(links.join("file.bin").exists());
+        assert!(yushi.get_task(&second.id).await.unwrap().archived);
+
+        // 链接目录不存在时只发送警告，任务仍然完成
+        let config = Config {
+            completed_link_dir: Some(dir.join("missing")),
+            ..Default::default()
+        };
+        let (broken, mut broken_rx) = with_queue_file(config, 2, dir.join("broken.json")).unwrap();
+        let task_id = broken
+            .add_task(server.url("/file.bin"), dir.join("c.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&broken, &task_id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.completed_link, None);
+        let mut warned = false;
+        while let Ok(envelope) = broken_rx.try_recv() {
+            warned |= matches!(
+                envelope.event,
+                DownloaderEvent::Task(TaskEvent::LinkFailed { .. })
+            );
+        }
+        assert!(warned);
+    }
+
+    #[tokio::test]
+    async fn test_task_headers_sent_with_every_request() {
+        let server = MockServer::start().await;
+        let body = test_body(100_000);
+        server.add("/signed.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 16 * 1024,
+            headers: HashMap::from([
+                ("x-token".to_string(), "from-config".to_string()),
+                ("X-Client".to_string(), "yushi".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let options = TaskOptions {
+            headers: HashMap::from([("X-Token".to_string(), "signed-123".to_string())]),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/signed.bin"), dir.join("signed.bin"), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.headers["X-Token"], "signed-123");
+        assert_eq!(std::fs::read(dir.join("signed.bin")).unwrap(), body);
+
+        // 探测和每个分块请求都带有任务的请求头，配置中的同名请求头被覆盖
+        let requests = server.requests();
+        let ranged = requests
+            .iter()
+            .filter(|r| r.method == "GET" && r.headers.contains_key("range"))
+            .count();
+        assert!(ranged >= 7, "{} range requests", ranged);
+        for request in &requests {
+            assert_eq!(
+                request.headers.get("x-token").map(String::as_str),
+                Some("signed-123"),
+                "{} {:?}",
+                request.method,
+                request.headers.get("range")
+            );
+            assert_eq!(request.headers["x-client"], "yushi");
+        }
+
+        // 请求头随任务保存
+        yushi.save_queue_state().await.unwrap();
+        let (reloaded, _rx) =
+            with_queue_file(Config::default(), 2, dir.join("queue.json")).unwrap();
+        reloaded.load_queue_from_state().await.unwrap();
+        let task = reloaded.get_task(&id).await.unwrap();
+        assert_eq!(task.headers["X-Token"], "signed-123");
+    }
+
+    #[tokio::test]
+    async fn test_disk_pressure_throttles_and_recovers() {
+        let server = MockServer::start().await;
+        let body = test_body(16 * 1024 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 1024 * 1024,
+            disk_pressure: Some(DiskPressureConfig {
+                latency_threshold: Duration::from_millis(20),
+                min_rate: 256 * 1024,
+                adjust_interval: Duration::from_millis(100),
+            }),
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        let sink = Arc::new(SlowSink {
+            inner: MemorySink::new(),
+            delay: Mutex::new(Duration::from_millis(50)),
+        });
+        let options = TaskOptions {
+            sink: Some(sink.clone()),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+
+        // 写入变慢后开始限速，每次调整都不低于下限
+        let mut throttles = Vec::new();
+        let released = tokio::time::timeout(Duration::from_secs(30), async {
+            while let Some(envelope) = rx.recv().await {
+                let DownloaderEvent::Global(GlobalEvent::DiskPressure {
+                    latency_ms,
+                    throttled_to,
+                }) = envelope.event
+                else {
+                    continue;
+                };
+                match throttled_to {
+                    Some(rate) => {
+                        assert!(rate >= 256 * 1024);
+                        if throttles.is_empty() {
+                            assert!(latency_ms > 20, "{} ms", latency_ms);
+                            // 磁盘恢复正常
+                            *sink.delay.lock().unwrap() = Duration::ZERO;
+                        }
+                        throttles.push(rate);
+                    }
+                    None => return true,
+                }
+            }
+            false
+        })
+        .await
+        .unwrap();
+        assert!(released, "throttles: {:?}", throttles);
+        assert!(!throttles.is_empty());
+        // 恢复时逐步提高
+        let recovering = throttles.windows(2).filter(|w| w[1] > w[0]).count();
+        assert!(recovering >= 1, "throttles: {:?}", throttles);
+
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(sink.inner.data(), body);
+    }
+
+    #[tokio::test]
+    async fn test_per_task_speed_limit() {
+        let server = MockServer::start().await;
+        let body = test_body(512 * 1024);
+        server.add("/background.bin", MockFile::new(body.clone()));
+        server.add("/urgent.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 128 * 1024,
+            speed_limit: Some(64 * 1024),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let background = yushi
+            .add_task_with(
+                server.url("/background.bin"),
+                dir.join("background.bin"),
+                TaskOptions {
+                    speed_limit: Some(256 * 1024),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        // 0 表示不受配置中的限速影响
+        let urgent = yushi
+            .add_task_with(
+                server.url("/urgent.bin"),
+                dir.join("urgent.bin"),
+                TaskOptions {
+                    speed_limit: Some(0),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let started = Instant::now();
+        let task = wait_for_terminal(&yushi, &urgent).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        let urgent_elapsed = started.elapsed();
+        let task = wait_for_terminal(&yushi, &background).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        let background_elapsed = started.elapsed();
+        assert_eq!(std::fs::read(dir.join("background.bin")).unwrap(), body);
+
+        // 512 KB 限速 256 KB/s：第一秒的份额用完后要等到下一秒
+        assert!(
+            urgent_elapsed < Duration::from_millis(500),
+            "{:?}",
+            urgent_elapsed
+        );
+        assert!(
+            background_elapsed >= Duration::from_millis(900),
+            "{:?}",
+            background_elapsed
+        );
+        // 远低于配置中的 64 KB/s 需要的 8 秒
+        assert!(
+            background_elapsed < Duration::from_secs(4),
+            "{:?}",
+            background_elapsed
+        );
+
+        // 限速随任务保存
+        yushi.save_queue_state().await.unwrap();
+        let (reloaded, _rx) =
+            with_queue_file(Config::default(), 2, dir.join("queue.json")).unwrap();
+        reloaded.load_queue_from_state().await.unwrap();
+        let task = reloaded.get_task(&background).await.unwrap();
+        assert_eq!(task.speed_limit, Some(256 * 1024));
+    }
+
+    #[tokio::test]
+    async fn test_boost_task_lifts_limits_and_extends() {
+        let server = MockServer::start().await;
+        let body = test_body(512 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 128 * 1024,
+            speed_limit: Some(64 * 1024),
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+        yushi.set_global_speed_limit(Some(64 * 1024));
+        yushi.pause_queue().await;
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+
+        // 第二次加速从第一次的截止时间起延长
+        let first = yushi.boost_task(&id, Duration::from_secs(1)).await.unwrap();
+        let second = yushi.boost_task(&id, Duration::from_secs(1)).await.unwrap();
+        assert_eq!(second, first + 1000);
+        assert_eq!(yushi.get_task(&id).await.unwrap().boost_until, Some(second));
+        assert!(matches!(
+            yushi.boost_task("missing", Duration::from_secs(1)).await,
+            Err(Error::TaskNotFound)
+        ));
+
+        // 不加速时 64 KB/s 需要 8 秒
+        let started = Instant::now();
+        yushi.resume_queue().await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert!(
+            started.elapsed() < Duration::from_secs(1),
+            "{:?}",
+            started.elapsed()
+        );
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+
+        let mut boosted = Vec::new();
+        let ended = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(envelope) = rx.recv().await {
+                match envelope.event {
+                    DownloaderEvent::Task(TaskEvent::Boosted { until_ms, .. }) => {
+                        boosted.push(until_ms)
+                    }
+                    DownloaderEvent::Task(TaskEvent::BoostEnded { task_id }) => return task_id,
+                    _ => {}
+                }
+            }
+            panic!("event channel closed");
+        })
+        .await
+        .unwrap();
+        assert_eq!(ended, id);
+        assert_eq!(boosted, [first, second]);
+        assert_eq!(yushi.get_task(&id).await.unwrap().boost_until, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_non_utf8_destination() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+        // Latin-1 编码的 "café.bin"，不是合法的 UTF-8
+        let dest = dir.join(OsStr::from_bytes(b"caf\xe9.bin"));
+
+        let id = yushi
+            .add_task(server.url("/file.bin"), dest.clone())
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(task.dest, dest);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert!(task.matches("caf"));
+
+        // 队列状态中保存的是原始字节
+        let reloaded = test_downloader(&dir, 16 * 1024);
+        reloaded.load_queue_from_state().await.unwrap();
+        assert_eq!(reloaded.get_task(&id).await.unwrap().dest, dest);
+
+        // 重命名时保留原来的字节
+        assert_eq!(
+            crate::utils::auto_rename_with(&dest, false),
+            dir.join(OsStr::from_bytes(b"caf\xe9 (1).bin"))
+        );
+    }
+
+    /// 打开时 panic 的存储后端
+    struct PanickingSink;
+
+    impl StorageSink for PanickingSink {
+        fn supports_random_access(&self) -> bool {
+            true
+        }
+
+        fn open(&self, _: Option<u64>, _: bool) -> crate::sink::SinkFuture<'_, ()> {
+            panic!("sink exploded")
+        }
+
+        fn write_at<'a>(&'a self, _: u64, _: &'a [u8]) -> crate::sink::SinkFuture<'a, ()> {
+            unreachable!()
+        }
+
+        fn flush(&self) -> crate::sink::SinkFuture<'_, ()> {
+            unreachable!()
+        }
+
+        fn finalize(&self) -> crate::sink::SinkFuture<'_, ()> {
+            unreachable!()
+        }
+
+        fn abort(&self) -> crate::sink::SinkFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panicking_task_fails_and_frees_slot() {
+        let server = MockServer::start().await;
+        server.add("/a.bin", MockFile::new(test_body(16 * 1024)));
+        server.add("/b.bin", MockFile::new(test_body(16 * 1024)));
+        let dir = temp_dir();
+        let (yushi, _rx) = with_queue_file(Config::default(), 1, dir.join("queue.json")).unwrap();
+
+        let crashed = yushi
+            .add_task_with(
+                server.url("/a.bin"),
+                dir.join("a.bin"),
+                TaskOptions {
+                    sink: Some(Arc::new(PanickingSink)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let next = yushi
+            .add_task(server.url("/b.bin"), dir.join("b.bin"))
+            .await
+            .unwrap();
+
+        let task = wait_for_terminal(&yushi, &crashed).await;
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert!(task.error.unwrap().contains("sink exploded"));
+        // 并发名额已释放，下一个任务自动开始
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!yushi.active_downloads.read().await.contains_key(&crashed));
+        let task = wait_for_terminal(&yushi, &next).await;
+        assert_eq!(task.status, TaskStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_process_queue_respects_limit() {
+        let server = MockServer::start().await;
+        server.add(
+            "/file.bin",
+            MockFile::new(test_body(64 * 1024)).with_delay(Duration::from_millis(5)),
+        );
+        let dir = temp_dir();
+        let (yushi, _rx) = with_queue_file(Config::default(), 1, dir.join("queue.json")).unwrap();
+        yushi.pause_queue().await;
+        for i in 0..8 {
+            yushi
+                .add_task(server.url("/file.bin"), dir.join(format!("{}.bin", i)))
+                .await
+                .unwrap();
+        }
+
+        // 占住取消令牌表，启动中的任务已标记为下载中、但还没有登记到 active_downloads
+        let tokens = yushi.cancel_tokens.read().await;
+        let resumes: Vec<_> = (0..8)
+            .map(|_| {
+                let yushi = yushi.clone();
+                tokio::spawn(async move { yushi.resume_queue().await })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(tokens);
+        for resume in resumes {
+            resume.await.unwrap().unwrap();
+        }
+
+        // 同时处理队列也只启动一个任务
+        let downloading = yushi
+            .get_all_tasks()
+            .await
+            .iter()
+            .filter(|t| t.status == TaskStatus::Downloading)
+            .count();
+        assert_eq!(downloading, 1);
+        assert_eq!(yushi.active_downloads.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_simulated_schedule_matches_real_order() {
+        let server = MockServer::start().await;
+        let dir = temp_dir();
+        let (yushi, mut rx) =
+            with_queue_file(Config::default(), 1, dir.join("queue.json")).unwrap();
+        yushi.pause_queue().await;
+
+        let priorities = [
+            TaskPriority::Low,
+            TaskPriority::High,
+            TaskPriority::Normal,
+            TaskPriority::High,
+            TaskPriority::Normal,
+        ];
+        for (i, priority) in priorities.into_iter().enumerate() {
+            let path = format!("/{}.bin", i);
+            server.add(&path, MockFile::new(test_body(4096)));
+            yushi
+                .add_task_with_options(
+                    server.url(&path),
+                    dir.join(format!("{}.bin", i)),
+                    priority,
+                    None,
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+
+        let plan = yushi.simulate_schedule(Some(1024 * 1024)).await;
+        assert_eq!(plan.len(), priorities.len());
+        assert_eq!(plan[0].est_start, Some(0));
+        let simulated: Vec<String> = plan.into_iter().map(|slot| slot.task_id).collect();
+
+        yushi.resume_queue().await.unwrap();
+        let mut started = Vec::new();
+        while started.len() < simulated.len() {
+            let envelope = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let DownloaderEvent::Task(TaskEvent::Started { task_id }) = envelope.event {
+                started.push(task_id);
+            }
+        }
+        assert_eq!(started, simulated);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_retry_honors_retry_after() {
+        let server = MockServer::start().await;
+        let body = test_body(32 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        server.fail_gets(1, "429 Too Many Requests", Some("1"));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 64 * 1024);
+
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+
+        // 按 Retry-After 等待 1 秒，而不是默认的 2 秒
+        let gets: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "GET")
+            .map(|r| r.received_at)
+            .collect();
+        assert_eq!(gets.len(), 2);
+        let waited = gets[1] - gets[0];
+        assert!(
+            waited >= Duration::from_secs(1) && waited < Duration::from_millis(1900),
+            "{:?}",
+            waited
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_error_fails_without_retry() {
+        assert!(!Error::HttpStatus(404).is_retryable());
+        assert!(!Error::HttpStatus(410).is_retryable());
+        assert!(Error::HttpStatus(408).is_retryable());
+        assert!(Error::HttpStatus(429).is_retryable());
+        assert!(Error::HttpStatus(503).is_retryable());
+        assert!(Error::StreamError(String::new()).is_retryable());
+
+        let server = MockServer::start().await;
+        server.add("/file.bin", MockFile::new(test_body(32 * 1024)));
+        server.fail_gets(10, "404 Not Found", None);
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 64 * 1024);
+
+        let started = Instant::now();
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert!(task.error.unwrap().contains("404"));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let gets = server
+            .requests()
+            .iter()
+            .filter(|r| r.method == "GET")
+            .count();
+        assert_eq!(gets, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_connection_retries_chunk_from_offset() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).stall_after(40 * 1024),
+        );
+        let dir = temp_dir();
+        // 关闭整个任务的停滞检测，确认是分块自己恢复的
+        let config = Config {
+            chunk_size: 16 * 1024,
+            stall_timeout: None,
+            connection_stall_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+
+        let mut retries = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Progress(ProgressEvent::Retrying {
+                attempt, reason, ..
+            }) = envelope.event
+            {
+                retries.push((attempt, reason));
+            }
+        }
+        assert_eq!(
+            retries,
+            vec![(1, RetryReason::ConnectionStalled { chunk_index: 2 })]
+        );
+        // 只重新请求分块中还没有收到的部分
+        let ranges: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "GET")
+            .filter_map(|r| r.headers.get("range").cloned())
+            .collect();
+        assert!(
+            ranges.contains(&"bytes=40960-49151".to_string()),
+            "{:?}",
+            ranges
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_connection_retries_chunk_from_offset() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).drop_after(40 * 1024),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 16 * 1024,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+
+        let mut retries = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Progress(ProgressEvent::Retrying {
+                attempt, reason, ..
+            }) = envelope.event
+            {
+                retries.push((attempt, reason));
+            }
+        }
+        assert_eq!(
+            retries,
+            vec![(1, RetryReason::ConnectionLost { chunk_index: 2 })]
+        );
+        let ranges: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "GET")
+            .filter_map(|r| r.headers.get("range").cloned())
+            .collect();
+        assert!(
+            ranges.contains(&"bytes=40960-49151".to_string()),
+            "{:?}",
+            ranges
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stalled_stream_connection_restarts() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone())
+                .without_ranges()
+                .stall_after(32 * 1024),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            stall_timeout: Some(Duration::from_secs(30)),
+            connection_stall_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let started = Instant::now();
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+        // 没有等到整个任务的停滞超时
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    /// 不探测地流式下载 `file`，第一次连接在 32 KiB 处停滞，返回完成的任务、
+    /// 重新请求时的 Range 和转发的进度
+    async fn stalled_stream_download(file: MockFile) -> (Task, Vec<String>, Vec<u64>) {
+        let server = MockServer::start().await;
+        let body = file.body.clone();
+        server.add("/file.bin", file.stall_after(32 * 1024));
+        let dir = temp_dir();
+        let config = Config {
+            stall_timeout: Some(Duration::from_secs(30)),
+            connection_stall_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let options = TaskOptions {
+            skip_probe: true,
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+
+        let ranges = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "GET")
+            .filter_map(|r| r.headers.get("range").cloned())
+            .collect();
+        let mut progress = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Progress(ProgressEvent::Updated { downloaded, .. }) =
+                envelope.event
+            {
+                progress.push(downloaded);
+            }
+        }
+        (task, ranges, progress)
+    }
+
+    #[tokio::test]
+    async fn test_stalled_stream_resumes_from_file_length() {
+        // 响应中没有 `Accept-Ranges`，不探测时按流式下载，但实际支持 Range
+        let file = MockFile::new(test_body(64 * 1024)).without_accept_ranges();
+        let (task, ranges, progress) = stalled_stream_download(file).await;
+        assert_eq!(task.downloaded, 64 * 1024);
+        assert_eq!(ranges, vec!["bytes=32768-".to_string()]);
+        // 继续下载时进度从已写入的位置开始，不会回到 0
+        assert!(progress.is_sorted(), "{:?}", progress);
+        assert_eq!(progress.last(), Some(&(64 * 1024)));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_stream_restarts_when_range_ignored() {
+        let file = MockFile::new(test_body(64 * 1024)).without_ranges();
+        let (task, ranges, _) = stalled_stream_download(file).await;
+        assert_eq!(task.downloaded, 64 * 1024);
+        // 先尝试继续，服务器返回整个文件后从头写入
+        assert_eq!(ranges, vec!["bytes=32768-".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_progress_never_goes_back() {
+        let server = MockServer::start().await;
+        let body = test_body(512 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_millis(2)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 64 * 1024,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        while yushi.get_task(&id).await.unwrap().downloaded < 128 * 1024 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        yushi.pause_task(&id).await.unwrap();
+        // 等暂停前已发出的进度处理完
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let paused_at = yushi.get_task(&id).await.unwrap().downloaded;
+
+        yushi.resume_task(&id).await.unwrap();
+        // 探测完成之前进度就从状态文件中的位置开始
+        assert!(yushi.get_task(&id).await.unwrap().downloaded >= paused_at);
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(task.downloaded, body.len() as u64);
+
+        let mut progress = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Progress(ProgressEvent::Updated { downloaded, .. }) =
+                envelope.event
+            {
+                progress.push(downloaded);
+            }
+        }
+        assert!(progress.contains(&paused_at));
+        assert!(progress.is_sorted(), "{:?}", progress);
+        assert_eq!(progress.last(), Some(&(body.len() as u64)));
+    }
+
+    #[tokio::test]
+    async fn test_pause_waits_for_chunks_to_save_state() {
+        let server = MockServer::start().await;
+        let body = test_body(512 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_millis(2)),
+        );
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 64 * 1024,
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let dest = dir.join("file.bin");
+        let id = yushi
+            .add_task(server.url("/file.bin"), dest.clone())
+            .await
+            .unwrap();
+        while yushi.get_task(&id).await.unwrap().downloaded < 128 * 1024 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        yushi.pause_task(&id).await.unwrap();
+        assert_eq!(
+            yushi.get_task(&id).await.unwrap().status,
+            TaskStatus::Paused
+        );
+        assert!(yushi.cancel_tokens.read().await.is_empty());
+        assert!(yushi.connection_limits.read().await.is_empty());
+        assert!(yushi.run_started.lock().unwrap().is_empty());
+
+        // 暂停返回时分块已经停止写入，状态文件记录的位置之前都是正确的数据
+        let (part, state_path) = partial_paths(&dest);
+        let state = DownloadState::load(&state_path).await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let later = DownloadState::load(&state_path).await.unwrap().unwrap();
+        let progress = |s: &DownloadState| {
+            s.chunks
+                .iter()
+                .map(|c| (c.current, c.is_finished))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(progress(&state), progress(&later));
+        let data = std::fs::read(&part).unwrap();
+        for chunk in &state.chunks {
+            let range = chunk.start as usize..chunk.current as usize;
+            assert_eq!(data[range.clone()], body[range]);
+        }
+
+        yushi.resume_task(&id).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_pause_aborts_stuck_download_and_frees_slot() {
+        let server = MockServer::start().await;
+        server.add("/stuck.bin", MockFile::new(test_body(512 * 1024)));
+        server.add("/next.bin", MockFile::new(test_body(16 * 1024)));
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 64 * 1024,
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 1, dir.join("queue.json")).unwrap();
+
+        let sink = Arc::new(SlowSink {
+            inner: MemorySink::new(),
+            delay: Mutex::new(Duration::from_millis(2)),
+        });
+        let stuck = yushi
+            .add_task_with(
+                server.url("/stuck.bin"),
+                dir.join("stuck.bin"),
+                TaskOptions {
+                    sink: Some(sink.clone()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let next = yushi
+            .add_task(server.url("/next.bin"), dir.join("next.bin"))
+            .await
+            .unwrap();
+        while yushi.get_task(&stuck).await.unwrap().downloaded == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // 写入卡住，下载在 STOP_TIMEOUT 内不会退出，只能被中断
+        *sink.delay.lock().unwrap() = Duration::from_secs(600);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        yushi.pause_task(&stuck).await.unwrap();
+        assert_eq!(
+            yushi.get_task(&stuck).await.unwrap().status,
+            TaskStatus::Paused
+        );
+        assert!(!yushi.connection_limits.read().await.contains_key(&stuck));
+        assert!(!yushi.run_started.lock().unwrap().contains_key(&stuck));
+
+        // 被中断的下载空出的并发名额交给下一个任务
+        let task = wait_for_terminal(&yushi, &next).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_pauses_running_tasks_and_saves_queue() {
+        let server = MockServer::start().await;
+        let body = test_body(512 * 1024);
+        for path in ["/a.bin", "/b.bin"] {
+            server.add(
+                path,
+                MockFile::new(body.clone()).with_delay(Duration::from_millis(2)),
+            );
+        }
+        let dir = temp_dir();
+        let queue_path = dir.join("queue.json");
+        let config = Config {
+            chunk_size: 64 * 1024,
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config.clone(), 2, queue_path.clone()).unwrap();
+
+        let mut ids = Vec::new();
+        for name in ["a.bin", "b.bin"] {
+            let id = yushi
+                .add_task(server.url(&format!("/{}", name)), dir.join(name))
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        for id in &ids {
+            while yushi.get_task(id).await.unwrap().downloaded == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        yushi.shutdown(Duration::from_secs(5)).await.unwrap();
+
+        assert!(yushi.active_downloads.read().await.is_empty());
+        // 队列状态已合并到主文件
+        assert!(!crate::state::journal_path(&queue_path).exists());
+        assert_eq!(yushi.scheduler_state().reason, Some(PausedReason::Shutdown));
+        for id in &ids {
+            assert_eq!(yushi.get_task(id).await.unwrap().status, TaskStatus::Paused);
+        }
+        // 关闭后不再启动任务
+        yushi.resume_queue().await.unwrap();
+        assert!(matches!(
+            yushi.run_task(&ids[0], None).await,
+            Err(Error::ShutDown)
+        ));
+        assert_eq!(
+            yushi.get_task(&ids[0]).await.unwrap().status,
+            TaskStatus::Paused
+        );
+
+        // 重新加载队列后从保存的位置继续
+        let (reloaded, _rx) = with_queue_file(config, 2, queue_path).unwrap();
+        reloaded.load_queue_from_state().await.unwrap();
+        for id in &ids {
+            let task = reloaded.get_task(id).await.unwrap();
+            assert_eq!(task.status, TaskStatus::Paused);
+            assert!(task.downloaded > 0);
+            reloaded.resume_task(id).await.unwrap();
+        }
+        for (id, name) in ids.iter().zip(["a.bin", "b.bin"]) {
+            let task = wait_for_terminal(&reloaded, id).await;
+            assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+            assert_eq!(std::fs::read(dir.join(name)).unwrap(), body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_streaming_task() {
+        let server = MockServer::start().await;
+        let body = test_body(1024 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone())
+                .without_accept_ranges()
+                .with_delay(Duration::from_millis(1)),
+        );
+        let dir = temp_dir();
+        let queue_path = dir.join("queue.json");
+        let (yushi, _rx) = with_queue_file(Config::default(), 2, queue_path.clone()).unwrap();
+
+        let dest = dir.join("file.bin");
+        let options = TaskOptions {
+            skip_probe: true,
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dest.clone(), options)
+            .await
+            .unwrap();
+        while yushi.get_task(&id).await.unwrap().downloaded < 2 * PREFIX_REPORT_STEP {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        yushi.pause_task(&id).await.unwrap();
+        let (_, state_path) = partial_paths(&dest);
+        let state = DownloadState::load(&state_path).await.unwrap().unwrap();
+        assert!(state.is_streaming);
+        assert!(state.streamed >= PREFIX_REPORT_STEP);
+
+        // 重新加载队列后进度来自状态文件，而不是 0
+        let (reloaded, mut rx) = with_queue_file(Config::default(), 2, queue_path).unwrap();
+        reloaded.load_queue_from_state().await.unwrap();
+        let task = reloaded.get_task(&id).await.unwrap();
+        assert_eq!(task.status, TaskStatus::Paused);
+        assert_eq!(task.downloaded, state.streamed);
+
+        reloaded.resume_task(&id).await.unwrap();
+        let task = wait_for_terminal(&reloaded, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert!(!state_path.exists());
+        let ranges: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter_map(|r| r.headers.get("range").cloned())
+            .collect();
+        // 从暂停时文件的长度继续，不会少于状态文件中记录的字节数
+        assert_eq!(ranges.len(), 1, "{:?}", ranges);
+        let from: u64 = ranges[0]
+            .strip_prefix("bytes=")
+            .and_then(|r| r.strip_suffix('-'))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(from >= state.streamed && from < body.len() as u64);
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Progress(ProgressEvent::Updated { downloaded, .. }) =
+                envelope.event
+            {
+                assert!(downloaded >= state.streamed);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resumed_stream_restarts_when_range_ignored() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add("/file.bin", MockFile::new(body.clone()).without_ranges());
+        let dir = temp_dir();
+        let (yushi, mut rx) =
+            with_queue_file(Config::default(), 2, dir.join("queue.json")).unwrap();
+
+        // 上次运行以流式下载写入了一部分
+        let dest = dir.join("file.bin");
+        let (part, state_path) = partial_paths(&dest);
+        std::fs::write(&part, &body[..1000]).unwrap();
+        DownloadState {
+            url: server.url("/file.bin"),
+            total_size: None,
+            chunks: Vec::new(),
+            is_streaming: true,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 1000,
+            reported_prefix: 0,
+        }
+        .save(&state_path)
+        .await
+        .unwrap();
+
+        let id = yushi
+            .add_task(server.url("/file.bin"), dest.clone())
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+
+        let mut restarts = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Progress(ProgressEvent::Restarted { reason, .. }) =
+                envelope.event
+            {
+                restarts.push(reason);
+            }
+        }
+        assert_eq!(restarts, vec![RestartReason::ResumeRejected]);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_fails_task_and_keeps_state() {
+        let server = MockServer::start().await;
+        let body = test_body(256 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body).with_delay(Duration::from_millis(20)),
+        );
+        let dir = temp_dir();
+        let (yushi, mut rx) = with_queue_file(
+            Config {
+                chunk_size: 64 * 1024,
+                ..Default::default()
+            },
+            2,
+            dir.join("queue.json"),
+        )
+        .unwrap();
+
+        let options = TaskOptions {
+            deadline: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let id = yushi
+            .add_task_with(server.url("/file.bin"), dir.join("file.bin"), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert_eq!(task.error, Some(Error::TaskTimedOut.to_string()));
+        assert_eq!(task.deadline_ms, Some(300));
+        // 保留下载状态，之后可以续传
+        assert!(yushi.state_path(&task).exists());
+
+        let mut failed = false;
+        while let Ok(envelope) = rx.try_recv() {
+            failed |= matches!(
+                envelope.event,
+                DownloaderEvent::Task(TaskEvent::Failed { ref task_id, .. }) if *task_id == id
+            );
+        }
+        assert!(failed);
+
+        // 所有分块连接都已断开
+        for _ in 0..50 {
+            if server.active_gets() == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(server.active_gets(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_use_resolved_url() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add("/files/file.bin", MockFile::new(body.clone()));
+        server.redirect("/dl", "/files/file.bin");
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        let id = yushi
+            .add_task(server.url("/dl"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+        assert_eq!(task.url, server.url("/dl"));
+        assert_eq!(task.resolved_url, Some(server.url("/files/file.bin")));
+
+        // 只有探测经过跳转链接，分块请求直接发往重定向后的地址
+        let gets: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "GET")
+            .map(|r| r.path)
+            .collect();
+        assert_eq!(gets.len(), 4);
+        assert!(gets.iter().all(|path| path == "/files/file.bin"));
+
+        yushi.compact_queue_state().await.unwrap();
+        let reloaded = test_downloader(&dir, 16 * 1024);
+        reloaded.load_queue_from_state().await.unwrap();
+        let task = reloaded.get_task(&id).await.unwrap();
+        assert_eq!(task.resolved_url, Some(server.url("/files/file.bin")));
+    }
+
+    #[tokio::test]
+    async fn test_resolved_url_falls_back_to_original() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add(
+            "/old.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_millis(30)),
+        );
+        server.redirect("/dl", "/old.bin");
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        let id = yushi
+            .add_task(server.url("/dl"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        yushi.pause_task(&id).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let task = yushi.get_task(&id).await.unwrap();
+        assert_eq!(task.resolved_url, Some(server.url("/old.bin")));
+
+        // 重定向后的地址失效，跳转链接指向新的位置
+        server.remove("/old.bin");
+        server.add("/new.bin", MockFile::new(body.clone()));
+        server.redirect("/dl", "/new.bin");
+        yushi.resume_task(&id).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+        assert_eq!(task.resolved_url, None);
+        assert!(
+            server
+                .requests()
+                .iter()
+                .any(|r| r.method == "GET" && r.path == "/new.bin")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redirect_loop_and_limit() {
+        let server = MockServer::start().await;
+        server.redirect("/loop1", "/loop2");
+        server.redirect("/loop2", "/loop1");
+        server.add("/file.bin", MockFile::new(test_body(1024)));
+        server.redirect("/a", "/b");
+        server.redirect("/b", "/file.bin");
+        let dir = temp_dir();
+        let config = Config {
+            max_redirects: 1,
+            ..Default::default()
+        };
+        let (yushi, _rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+
+        let id = yushi
+            .add_task(server.url("/loop1"), dir.join("loop.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert_eq!(
+            task.error,
+            Some(Error::RedirectLoop(server.url("/loop1")).to_string())
+        );
+
+        let id = yushi
+            .add_task(server.url("/a"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert_eq!(task.error, Some(Error::TooManyRedirects(1).to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_directory_dest_uses_suggested_filename() {
+        let server = MockServer::start().await;
+        let body = test_body(40 * 1024);
+        server.add(
+            "/get",
+            MockFile::new(body.clone())
+                .with_content_disposition("attachment; filename*=UTF-8''%E6%8A%A5%E5%91%8A.bin"),
+        );
+        server.add("/files/plain.bin", MockFile::new(body.clone()));
+        server.redirect("/latest", "/files/plain.bin");
+        let dir = temp_dir();
+        let downloads = dir.join("downloads");
+        std::fs::create_dir_all(&downloads).unwrap();
+        std::fs::write(downloads.join("plain.bin"), b"existing").unwrap();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        let id = yushi
+            .add_task(server.url("/get"), downloads.clone())
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(task.dest, downloads.join("报告.bin"));
+        assert_eq!(std::fs::read(&task.dest).unwrap(), body);
+
+        // 没有 Content-Disposition 时取重定向后的地址的文件名，已存在的文件不被覆盖
+        let id = yushi
+            .add_task(server.url("/latest"), downloads.clone())
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(task.dest, downloads.join("plain (1).bin"));
+        assert_eq!(std::fs::read(&task.dest).unwrap(), body);
+        assert_eq!(
+            std::fs::read(downloads.join("plain.bin")).unwrap(),
+            b"existing"
+        );
+    }
+
+    /// 开始下载，下载一部分后暂停，返回任务 ID
+    async fn start_and_pause(yushi: &YuShi, url: String, dest: PathBuf) -> String {
+        let id = yushi.add_task(url, dest).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        yushi.pause_task(&id).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let task = yushi.get_task(&id).await.unwrap();
+        assert!(task.downloaded > 0 && task.downloaded < task.total_size);
+        id
+    }
+
+    /// 从文件开头请求的 GET 数
+    fn gets_from_start(server: &MockServer) -> usize {
+        server
+            .requests()
+            .iter()
+            .filter(|r| {
+                r.method == "GET"
+                    && r.headers
+                        .get("range")
+                        .is_some_and(|range| range.starts_with("bytes=0-"))
+            })
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_resume_with_same_etag_continues() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone())
+                .with_etag("\"v1\"")
+                .with_delay(Duration::from_millis(30)),
+        );
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+        let id = start_and_pause(&yushi, server.url("/file.bin"), dir.join("file.bin")).await;
+        let state = DownloadState::load(&partial_paths(&dir.join("file.bin")).1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.etag.as_deref(), Some("\"v1\""));
+
+        server.add("/file.bin", MockFile::new(body.clone()).with_etag("\"v1\""));
+        yushi.resume_task(&id).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+        // 续传前重新探测，第一个分块从暂停的位置继续
+        let heads = server
+            .requests()
+            .iter()
+            .filter(|r| r.method == "HEAD")
+            .count();
+        assert_eq!(heads, 2);
+        assert_eq!(gets_from_start(&server), 1);
+        assert!(
+            !task
+                .recent_events
+                .iter()
+                .any(|e| matches!(e.kind, CompactEventKind::Restarted { .. }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resume_with_changed_etag_restarts() {
+        let server = MockServer::start().await;
+        server.add(
+            "/file.bin",
+            MockFile::new(test_body(64 * 1024))
+                .with_etag("\"v1\"")
+                .with_delay(Duration::from_millis(30)),
+        );
+        let dir = temp_dir();
+        let (yushi, mut rx) = with_queue_file(
+            Config {
+                chunk_size: 16 * 1024,
+                ..Default::default()
+            },
+            2,
+            dir.join("queue.json"),
+        )
+        .unwrap();
+        let id = start_and_pause(&yushi, server.url("/file.bin"), dir.join("file.bin")).await;
+
+        // 远程文件被替换为内容和大小都不同的新版本
+        let body: Vec<u8> = test_body(48 * 1024).into_iter().rev().collect();
+        server.add("/file.bin", MockFile::new(body.clone()).with_etag("\"v2\""));
+        yushi.resume_task(&id).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), body);
+        assert_eq!(task.total_size, body.len() as u64);
+        assert_eq!(gets_from_start(&server), 2);
+        assert!(task.recent_events.iter().any(|e| e.kind
+            == CompactEventKind::Restarted {
+                reason: RestartReason::RemoteChanged,
+            }));
+
+        let mut restarted = false;
+        while let Ok(envelope) = rx.try_recv() {
+            restarted |= matches!(
+                envelope.event,
+                DownloaderEvent::Progress(ProgressEvent::Restarted { ref task_id, .. })
+                    if *task_id == id
+            );
+        }
+        assert!(restarted);
+    }
+
+    #[tokio::test]
+    async fn test_range_ignored_falls_back_to_streaming() {
+        let server = MockServer::start().await;
+        let body = test_body(100_000);
+        server.add("/file.bin", MockFile::new(body.clone()).ignoring_ranges());
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+
+        let id = yushi
+            .add_task(server.url("/file.bin"), dir.join("file.bin"))
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        // 分块位置写入整个文件会损坏数据，改为流式下载后内容正确
+        assert!(std::fs::read(dir.join("file.bin")).unwrap() == body);
+        assert!(!task.remote.unwrap().supports_range);
+        assert!(!partial_paths(&dir.join("file.bin")).1.exists());
+        assert!(task.recent_events.iter().any(|e| e.kind
+            == CompactEventKind::Restarted {
+                reason: RestartReason::RangeIgnored,
+            }));
+        // 流式下载只发送一个不带 Range 的请求
+        let plain_gets = server
+            .requests()
+            .iter()
+            .filter(|r| r.method == "GET" && !r.headers.contains_key("range"))
+            .count();
+        assert_eq!(plain_gets, 1);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_to_part_file() {
+        let server = MockServer::start().await;
+        let body = test_body(64 * 1024);
+        server.add(
+            "/file.bin",
+            MockFile::new(body.clone()).with_delay(Duration::from_millis(30)),
+        );
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 16 * 1024);
+        let dest = dir.join("file.bin");
+        let (part, state_path) = partial_paths(&dest);
+
+        // 下载中只写入 .part 文件，任务的目标路径不变
+        let id = start_and_pause(&yushi, server.url("/file.bin"), dest.clone()).await;
+        assert!(part.exists() && state_path.exists());
+        assert!(!dest.exists());
+        assert_eq!(yushi.get_task(&id).await.unwrap().dest, dest);
+
+        // 自动重命名时已有 .part 文件的名称也算被占用
+        let options = TaskOptions {
+            auto_rename: true,
+            ..Default::default()
+        };
+        let other = yushi
+            .insert_task(server.url("/file.bin"), dest.clone(), options, None)
+            .await;
+        let other = yushi.get_task(&other).await.unwrap();
+        assert_eq!(other.dest, dir.join("file (1).bin"));
+
+        // 取消后删除 .part 文件和状态文件
+        yushi.cancel_task(&id).await.unwrap();
+        assert!(!part.exists() && !state_path.exists());
+
+        // 完成后才重命名为目标路径
+        let id = yushi
+            .add_task(server.url("/file.bin"), dest.clone())
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert!(!part.exists() && !state_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_resume_moves_legacy_partial_file() {
+        let server = MockServer::start().await;
+        let body = test_body(40_000);
+        server.add("/file.bin", MockFile::new(body.clone()));
+        let dir = temp_dir();
+        let yushi = test_downloader(&dir, 10_000);
+
+        // 旧版本直接写入目标路径，状态文件是 file.json
+        let dest = dir.join("file.bin");
+        let url = server.url("/file.bin");
+        let chunks = (0..4u64)
+            .map(|i| {
+                let (start, end) = (i * 10_000, (i + 1) * 10_000 - 1);
+                ChunkState {
+                    index: i as usize,
+                    start,
+                    end,
+                    current: if i < 2 { end + 1 } else { start },
+                    is_finished: i < 2,
+                    digest: None,
+                }
+            })
+            .collect();
+        DownloadState {
+            url: url.clone(),
+            total_size: Some(body.len() as u64),
+            chunks,
+            is_streaming: false,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 0,
+            reported_prefix: 0,
+        }
+        .save(&dest.with_extension("json"))
+        .await
+        .unwrap();
+        let mut partial = body[..20_000].to_vec();
+        partial.resize(body.len(), 0);
+        std::fs::write(&dest, &partial).unwrap();
+
+        let id = yushi
+            .insert_task(url, dest.clone(), TaskOptions::default(), None)
+            .await;
+        yushi.tasks.write().await.get_mut(&id).unwrap().status = TaskStatus::Paused;
+        yushi.resume_task(&id).await.unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert!(!dest.with_extension("json").exists());
+        // 已完成的分块没有重新下载
+        assert_eq!(gets_from_start(&server), 0);
+    }
+
+    #[tokio::test]
+    async fn test_mirrors_share_chunks_and_demote_bad_ones() {
+        let body = test_body(512 * 1024);
+        let primary = MockServer::start().await;
+        primary.add("/file.bin", MockFile::new(body.clone()));
+        let good = MockServer::start().await;
+        good.add("/file.bin", MockFile::new(body.clone()));
+        // 大小不同：开始下载前就停用
+        let other = MockServer::start().await;
+        other.add("/file.bin", MockFile::new(test_body(1000)));
+        // 探测正常，分块请求返回 404：第一次失败后停用，分块换用其他地址
+        let broken = MockServer::start().await;
+        broken.add("/file.bin", MockFile::new(body.clone()));
+        broken.fail_gets(100, "404 Not Found", None);
+
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 64 * 1024,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 4, dir.join("queue.json")).unwrap();
+        let dest = dir.join("file.bin");
+        let options = TaskOptions {
+            mirrors: vec![
+                good.url("/file.bin"),
+                other.url("/file.bin"),
+                broken.url("/file.bin"),
+            ],
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(primary.url("/file.bin"), dest.clone(), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+
+        let gets = |server: &MockServer| {
+            server
+                .requests()
+                .iter()
+                .filter(|r| r.method == "GET")
+                .count()
+        };
+        assert!(gets(&primary) > 0);
+        assert!(gets(&good) > 0);
+        assert_eq!(gets(&other), 0);
+        assert!(gets(&broken) > 0);
+
+        let mut demoted = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Progress(ProgressEvent::MirrorDemoted {
+                mirror, reason, ..
+            }) = envelope.event
+            {
+                demoted.push((mirror, reason));
+            }
+        }
+        assert_eq!(
+            demoted,
+            vec![
+                (other.url("/file.bin"), MirrorDemotion::SizeMismatch),
+                (
+                    broken.url("/file.bin"),
+                    MirrorDemotion::Rejected { status: 404 }
+                ),
+            ]
+        );
+        assert!(task.recent_events.iter().any(|e| matches!(
+            e.kind,
+            CompactEventKind::MirrorDemoted {
+                reason: MirrorDemotion::SizeMismatch,
+                ..
+            }
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_switches_mirror_after_repeated_failures() {
+        let body = test_body(256 * 1024);
+        let primary = MockServer::start().await;
+        primary.add("/file.bin", MockFile::new(body.clone()));
+        // 分块请求一直返回 503，每个分块失败两次后换用任务地址，累计失败过多后停用
+        let flaky = MockServer::start().await;
+        flaky.add("/file.bin", MockFile::new(body.clone()));
+        flaky.fail_gets(100, "503 Service Unavailable", Some("0"));
+
+        let dir = temp_dir();
+        let config = Config {
+            chunk_size: 32 * 1024,
+            ..Default::default()
+        };
+        let (yushi, mut rx) = with_queue_file(config, 2, dir.join("queue.json")).unwrap();
+        let dest = dir.join("file.bin");
+        let options = TaskOptions {
+            mirrors: vec![flaky.url("/file.bin")],
+            connections: Some(2),
+            ..Default::default()
+        };
+        let id = yushi
+            .add_task_with(primary.url("/file.bin"), dest.clone(), options)
+            .await
+            .unwrap();
+        let task = wait_for_terminal(&yushi, &id).await;
+        assert_eq!(task.status, TaskStatus::Completed, "{:?}", task.error);
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+
+        let flaky_gets = flaky
+            .requests()
+            .iter()
+            .filter(|r| r.method == "GET")
+            .count();
+        assert!(flaky_gets >= MIRROR_MAX_FAILURES as usize);
+        // 停用之后不再请求这个镜像
+        assert!(flaky_gets < MIRROR_MAX_FAILURES as usize + 2);
+        let mut reasons = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            if let DownloaderEvent::Progress(ProgressEvent::MirrorDemoted { reason, .. }) =
+                envelope.event
+            {
+                reasons.push(reason);
+            }
+        }
+        assert_eq!(reasons, vec![MirrorDemotion::TooManyFailures]);
     }
 }
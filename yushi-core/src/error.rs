@@ -1,31 +1,136 @@
-#[derive(Debug, Clone, thiserror::Error)]
+use crate::i18n::message;
+
+/// 错误
+///
+/// 错误信息使用当前语言，见 [`crate::i18n`]。
+#[derive(Debug, Clone)]
 pub enum Error {
-    #[error("{0}")]
     IOError(String),
-    #[error("{0}")]
     TaskFailed(String),
-    #[error("Task was cancelled")]
     TaskCancelled,
-    #[error("Task not found")]
     TaskNotFound,
-    #[error("Internal error: {0}")]
+    GroupNotFound,
     InternalError(String),
-    #[error("{0}")]
     ReqwestError(String),
-    #[error("HTTP ERROR: {0}")]
     HttpError(String),
-    #[error("Stream Error: {0}")]
+    /// 服务器返回了错误状态码
+    HttpStatus(u16),
     StreamError(String),
-    #[error("JSON Error: {0}")]
     JsonError(String),
-    #[error("Checksum verification failed")]
     ChecksumVerificationFailed,
-    #[error("Cannot remove task in current status")]
+    ChecksumMismatch(String),
+    VerificationCancelled,
+    /// 设置了校验和，但没有启用 `checksums` 特性
+    ChecksumsDisabled,
     CannotRemoveTaskInCurrentStatus,
-    #[error("Unknown error")]
+    CannotRetryTaskInCurrentStatus,
+    CannotInvalidateChunksInCurrentStatus,
+    InsecureCredentialFile(String),
+    CannotModifyTaskInCurrentStatus,
+    /// 只校验本地文件的任务不能暂停
+    CannotPauseVerifyOnlyTask,
+    /// 新的下载地址与原来的文件大小或 `ETag` 不一致
+    UrlMismatch(String),
+    /// 页面不是可以读取的目录索引，例如不是 HTML 或者是登录页
+    InvalidIndex(String),
+    /// 无法识别的校验和，见 [`ChecksumType::parse_auto`](crate::ChecksumType::parse_auto)
+    InvalidChecksum(String),
+    /// 无法识别的任务优先级
+    InvalidPriority(String),
+    /// 连接超过 [`Config::connection_stall_timeout`](crate::Config::connection_stall_timeout)
+    /// 秒没有收到数据
+    ConnectionStalled(u64),
+    /// 任务没有在 [`TaskOptions::deadline`](crate::TaskOptions::deadline) 内下载完成
+    TaskTimedOut,
+    /// 重定向回到了已经访问过的地址
+    RedirectLoop(String),
+    /// 重定向次数超过 [`Config::max_redirects`](crate::Config::max_redirects)
+    TooManyRedirects(usize),
+    /// 带 Range 的请求得到了 200 和整个文件
+    RangeIgnored,
+    /// 下载器已经关闭（[`YuShi::shutdown`](crate::YuShi::shutdown)），不再启动任务
+    ShutDown,
+    /// 无法解析的代理地址，见 [`Config::proxy`](crate::Config::proxy)
+    InvalidProxy(String),
+    /// 无法使用的 CA 证书文件和原因，见 [`Config::extra_root_certs`](crate::Config::extra_root_certs)
+    InvalidCertificate(String, String),
+    /// 服务器无法满足请求的字节范围（416），附带服务器报告的文件大小
+    RangeNotSatisfiable(Option<u64>),
+    /// 响应体超过了 [`Config::max_memory_download`](crate::Config::max_memory_download) 字节
+    ResponseTooLarge(u64),
     Unknown,
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::IOError(e) | Self::TaskFailed(e) | Self::ReqwestError(e) => e.clone(),
+            Self::TaskCancelled => message("error.task_cancelled", &[]),
+            Self::TaskNotFound => message("error.task_not_found", &[]),
+            Self::GroupNotFound => message("error.group_not_found", &[]),
+            Self::InternalError(e) => message("error.internal", &[e]),
+            Self::HttpError(e) => message("error.http", &[e]),
+            Self::HttpStatus(code) => {
+                let status = reqwest::StatusCode::from_u16(*code)
+                    .map(|status| status.to_string())
+                    .unwrap_or_else(|_| code.to_string());
+                message("error.http", &[&status])
+            }
+            Self::StreamError(e) => message("error.stream", &[e]),
+            Self::JsonError(e) => message("error.json", &[e]),
+            Self::ChecksumVerificationFailed => message("error.checksum_failed", &[]),
+            Self::ChecksumMismatch(e) => message("error.checksum_mismatch", &[e]),
+            Self::VerificationCancelled => message("error.verification_cancelled", &[]),
+            Self::ChecksumsDisabled => message("error.checksums_disabled", &[]),
+            Self::CannotRemoveTaskInCurrentStatus => message("error.cannot_remove", &[]),
+            Self::CannotRetryTaskInCurrentStatus => message("error.cannot_retry", &[]),
+            Self::CannotInvalidateChunksInCurrentStatus => message("error.cannot_invalidate", &[]),
+            Self::InsecureCredentialFile(path) => {
+                message("error.insecure_credential_file", &[path])
+            }
+            Self::CannotModifyTaskInCurrentStatus => message("error.cannot_modify", &[]),
+            Self::CannotPauseVerifyOnlyTask => message("error.cannot_pause_verify_only", &[]),
+            Self::UrlMismatch(e) => message("error.url_mismatch", &[e]),
+            Self::InvalidIndex(e) => message("error.invalid_index", &[e]),
+            Self::InvalidChecksum(e) => message("error.invalid_checksum", &[e]),
+            Self::InvalidPriority(e) => message("error.invalid_priority", &[e]),
+            Self::ConnectionStalled(secs) => message("error.connection_stalled", &[secs]),
+            Self::TaskTimedOut => message("error.task_timed_out", &[]),
+            Self::RedirectLoop(url) => message("error.redirect_loop", &[url]),
+            Self::TooManyRedirects(max) => message("error.too_many_redirects", &[max]),
+            Self::RangeIgnored => message("download.range_ignored", &[]),
+            Self::ShutDown => message("error.shut_down", &[]),
+            Self::InvalidProxy(url) => message("error.invalid_proxy", &[url]),
+            Self::InvalidCertificate(path, reason) => {
+                message("error.invalid_certificate", &[path, reason])
+            }
+            Self::RangeNotSatisfiable(Some(total)) => {
+                message("error.range_not_satisfiable", &[total])
+            }
+            Self::RangeNotSatisfiable(None) => message("error.range_not_satisfiable_unknown", &[]),
+            Self::ResponseTooLarge(limit) => message("error.response_too_large", &[limit]),
+            Self::Unknown => message("error.unknown", &[]),
+        };
+        f.write_str(&text)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// 重试是否可能成功
+    ///
+    /// 连接和传输错误、5xx、408 和 429 可以重试；其他 4xx 说明请求本身有问题，
+    /// 重试也会得到同样的结果。
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::ReqwestError(_) | Self::StreamError(_) | Self::ConnectionStalled(_) => true,
+            Self::HttpStatus(code) => matches!(code, 408 | 429 | 500..=599),
+            _ => false,
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
         Self::IOError(value.to_string())
@@ -34,10 +139,34 @@ impl From<std::io::Error> for Error {
 
 impl From<reqwest::Error> for Error {
     fn from(value: reqwest::Error) -> Self {
-        Self::ReqwestError(value.to_string())
+        let redirect = std::error::Error::source(&value)
+            .and_then(|source| source.downcast_ref::<RedirectError>());
+        match redirect {
+            Some(RedirectError::Loop(url)) => Self::RedirectLoop(url.clone()),
+            Some(RedirectError::TooMany(max)) => Self::TooManyRedirects(*max),
+            None => Self::ReqwestError(value.to_string()),
+        }
     }
 }
 
+/// 重定向策略拒绝继续跟随的原因，作为 reqwest 错误的来源转换为对应的 [`Error`]
+#[derive(Debug)]
+pub(crate) enum RedirectError {
+    Loop(String),
+    TooMany(usize),
+}
+
+impl std::fmt::Display for RedirectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Loop(url) => write!(f, "redirect loop at {}", url),
+            Self::TooMany(max) => write!(f, "more than {} redirects", max),
+        }
+    }
+}
+
+impl std::error::Error for RedirectError {}
+
 impl<T> From<tokio::sync::mpsc::error::SendError<T>> for Error {
     fn from(value: tokio::sync::mpsc::error::SendError<T>) -> Self {
         Self::InternalError(value.to_string())
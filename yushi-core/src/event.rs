@@ -0,0 +1,645 @@
+//! 事件发送
+//!
+//! 所有 [`DownloaderEvent`] 都经由 [`EventSender`] 发出，发送时包装为带有时间戳和
+//! 序号的 [`EventEnvelope`]。序号在写入通道的同时分配，因此接收端看到的序号严格递增。
+//!
+//! 发送从不等待接收端，接收端处理得慢也不会拖慢下载：
+//! - 进度事件（[`ProgressEvent::Updated`]、[`VerificationEvent::Progress`]）每个任务只保留
+//!   最新的一个，通道已满时新的进度覆盖尚未写入通道的旧进度；
+//! - 其他事件在通道已满时放入不限长度的溢出队列，由后台任务在通道有空位时按顺序写入，
+//!   不会丢失。
+//!
+//! 同一任务的事件保持发送的顺序，不同任务之间的进度事件可能晚于之后发送的其他事件。
+//!
+//! 写入通道的事件同时转发给 [`EventSender::subscribe_with_replay`] 的订阅者，并记入重放
+//! 缓冲区：最近的任务、校验和调度器状态事件，以及每个任务最新的进度。新的订阅者先收到
+//! 缓冲区中的事件，之后是新的事件，序号严格递增且不会重复。
+
+use crate::types::{
+    DownloaderEvent, EventEnvelope, GlobalEvent, ProgressEvent, TaskEvent, VerificationEvent,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+use tokio::{
+    runtime::Handle,
+    sync::{
+        Notify,
+        mpsc::{
+            self,
+            error::{SendError, TrySendError},
+        },
+    },
+};
+
+/// 为事件分配序号和时间戳的发送器
+#[derive(Debug, Clone)]
+pub(crate) struct EventSender {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    tx: mpsc::Sender<EventEnvelope>,
+    pending: Arc<Mutex<Pending>>,
+    /// 有事件等待写入通道时唤醒后台发送任务
+    notify: Arc<Notify>,
+    /// 后台发送任务是否已经启动
+    flusher_started: AtomicBool,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // 让后台发送任务写完剩余的事件后退出
+        self.notify.notify_one();
+    }
+}
+
+/// 进度事件的种类，同一任务同一种类只保留最新的一个
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ProgressKind {
+    Download,
+    Verify,
+}
+
+/// 订阅者通道的容量（不含订阅时补发的事件）
+const SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// 等待写入通道的事件
+#[derive(Debug, Default)]
+struct Pending {
+    /// 上一个事件的序号
+    seq: u64,
+    /// 溢出队列：事件及其产生时间，按顺序写入
+    queue: VecDeque<(u64, DownloaderEvent)>,
+    /// 每个任务最新的进度及其产生时间，在溢出队列之后写入
+    latest: HashMap<(String, ProgressKind), (u64, DownloaderEvent)>,
+    replay: Replay,
+    /// 订阅者，处理不过来（通道已满）时被断开
+    subscribers: Vec<mpsc::Sender<EventEnvelope>>,
+}
+
+/// 为后来的订阅者保留的事件
+#[derive(Debug, Default)]
+struct Replay {
+    /// 最多保留的非进度事件数
+    capacity: usize,
+    /// 最近的任务、校验和调度器状态事件
+    events: VecDeque<EventEnvelope>,
+    /// 每个任务最新的进度，任务结束时移除
+    progress: HashMap<(String, ProgressKind), EventEnvelope>,
+}
+
+impl Replay {
+    fn record(&mut self, envelope: &EventEnvelope) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(key) = progress_key(&envelope.event) {
+            self.progress.insert(key, envelope.clone());
+            return;
+        }
+        if let Some(key) = finished_key(&envelope.event) {
+            self.progress.remove(&key);
+        }
+        if replayable(&envelope.event) {
+            if self.events.len() == self.capacity {
+                self.events.pop_front();
+            }
+            self.events.push_back(envelope.clone());
+        }
+    }
+
+    /// 按序号排列的缓冲区内容，均标记为重放的事件
+    fn snapshot(&self) -> Vec<EventEnvelope> {
+        let mut envelopes: Vec<_> = self
+            .events
+            .iter()
+            .chain(self.progress.values())
+            .map(|envelope| EventEnvelope {
+                replayed: true,
+                ..envelope.clone()
+            })
+            .collect();
+        envelopes.sort_by_key(|envelope| envelope.seq);
+        envelopes
+    }
+}
+
+impl Pending {
+    /// 为事件分配序号，记入重放缓冲区并转发给订阅者
+    ///
+    /// 必须在写入通道的同时调用，保证通道中的顺序与序号一致。
+    fn envelope(&mut self, timestamp_ms: u64, event: DownloaderEvent) -> EventEnvelope {
+        self.seq += 1;
+        let envelope = EventEnvelope {
+            seq: self.seq,
+            timestamp_ms,
+            replayed: false,
+            event,
+        };
+        self.replay.record(&envelope);
+        self.subscribers
+            .retain(|subscriber| subscriber.try_send(envelope.clone()).is_ok());
+        envelope
+    }
+
+    /// 取出下一个要写入通道的事件
+    fn pop(&mut self) -> Option<(u64, DownloaderEvent)> {
+        if let Some(item) = self.queue.pop_front() {
+            return Some(item);
+        }
+        let key = self
+            .latest
+            .iter()
+            .min_by_key(|(_, (timestamp, _))| *timestamp)
+            .map(|(key, _)| key.clone())?;
+        self.latest.remove(&key)
+    }
+
+    /// 把等待中的进度按产生顺序移到溢出队列末尾
+    fn queue_latest(&mut self) {
+        let mut latest: Vec<_> = self.latest.drain().map(|(_, item)| item).collect();
+        latest.sort_by_key(|(timestamp, _)| *timestamp);
+        self.queue.extend(latest);
+    }
+
+    /// 通道有空位时直接写入，否则返回事件
+    fn write(
+        &mut self,
+        tx: &mpsc::Sender<EventEnvelope>,
+        timestamp_ms: u64,
+        event: DownloaderEvent,
+    ) -> Result<(), DownloaderEvent> {
+        match tx.try_reserve() {
+            Ok(permit) => {
+                // 持有锁期间写入通道，保证通道中的顺序与序号一致
+                permit.send(self.envelope(timestamp_ms, event));
+                Ok(())
+            }
+            Err(_) => Err(event),
+        }
+    }
+}
+
+impl EventSender {
+    /// `replay_capacity` 为重放缓冲区保留的非进度事件数，0 表示不保留
+    pub fn new(tx: mpsc::Sender<EventEnvelope>, replay_capacity: usize) -> Self {
+        let pending = Pending {
+            replay: Replay {
+                capacity: replay_capacity,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        Self {
+            inner: Arc::new(Inner {
+                tx,
+                pending: Arc::new(Mutex::new(pending)),
+                notify: Arc::new(Notify::new()),
+                flusher_started: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// 发送事件，不等待接收端
+    ///
+    /// 通道已满时进度事件覆盖同一任务尚未写入的旧进度，其他事件放入溢出队列稍后写入。
+    /// 只有接收端已经关闭时返回错误。
+    pub fn send(&self, event: DownloaderEvent) -> Result<(), SendError<DownloaderEvent>> {
+        if self.is_closed() {
+            return Err(SendError(event));
+        }
+        let timestamp_ms = current_timestamp_ms();
+        let mut pending = self.inner.pending.lock().unwrap();
+        match progress_key(&event) {
+            Some(key) => {
+                let event = match pending.queue.is_empty() {
+                    true => match pending.write(&self.inner.tx, timestamp_ms, event) {
+                        Ok(()) => {
+                            // 已经写入更新的进度，旧的不再需要
+                            pending.latest.remove(&key);
+                            return Ok(());
+                        }
+                        Err(event) => event,
+                    },
+                    false => event,
+                };
+                pending.latest.insert(key, (timestamp_ms, event));
+            }
+            None => {
+                // 先排入尚未写入的进度，进度不会出现在同一任务之后的事件后面
+                pending.queue_latest();
+                let event = match pending.queue.is_empty() {
+                    true => match pending.write(&self.inner.tx, timestamp_ms, event) {
+                        Ok(()) => return Ok(()),
+                        Err(event) => event,
+                    },
+                    false => event,
+                };
+                pending.queue.push_back((timestamp_ms, event));
+            }
+        }
+        drop(pending);
+        self.wake_flusher();
+        Ok(())
+    }
+
+    /// 尝试发送事件，通道已满或溢出队列中还有事件时直接丢弃
+    pub fn try_send(&self, event: DownloaderEvent) -> Result<(), TrySendError<DownloaderEvent>> {
+        if self.is_closed() {
+            return Err(TrySendError::Closed(event));
+        }
+        let mut pending = self.inner.pending.lock().unwrap();
+        if !pending.queue.is_empty() {
+            return Err(TrySendError::Full(event));
+        }
+        pending
+            .write(&self.inner.tx, current_timestamp_ms(), event)
+            .map_err(TrySendError::Full)
+    }
+
+    /// 订阅之后的事件，先收到重放缓冲区中的事件（`replayed` 为 true）
+    ///
+    /// 补发的事件和之后的事件序号严格递增，没有重复。订阅者收到的事件与主接收端相同，
+    /// 处理不过来导致通道已满时订阅被断开，接收端收到 None 后可以重新订阅。
+    pub fn subscribe_with_replay(&self) -> mpsc::Receiver<EventEnvelope> {
+        let mut pending = self.inner.pending.lock().unwrap();
+        let replayed = pending.replay.snapshot();
+        let (tx, rx) = mpsc::channel(replayed.len() + SUBSCRIBER_CAPACITY);
+        for envelope in replayed {
+            // 容量足够，不会失败
+            let _ = tx.try_send(envelope);
+        }
+        pending.subscribers.push(tx);
+        rx
+    }
+
+    /// 接收端是否已经关闭
+    pub fn is_closed(&self) -> bool {
+        self.inner.tx.is_closed()
+    }
+
+    /// 唤醒后台发送任务，第一次调用时启动它
+    fn wake_flusher(&self) {
+        if !self.inner.flusher_started.swap(true, Ordering::SeqCst) {
+            match Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn(flush_pending(
+                        self.inner.tx.clone(),
+                        Arc::clone(&self.inner.pending),
+                        Arc::clone(&self.inner.notify),
+                        Arc::downgrade(&self.inner),
+                    ));
+                }
+                // 不在 Tokio 运行时中：事件留在队列里，下次在运行时中发送时再启动
+                Err(_) => self.inner.flusher_started.store(false, Ordering::SeqCst),
+            }
+        }
+        self.inner.notify.notify_one();
+    }
+}
+
+/// 后台发送任务：通道有空位时写入等待中的事件
+///
+/// 所有发送器都释放后写完剩余的事件再退出；接收端关闭后立即退出。
+async fn flush_pending(
+    tx: mpsc::Sender<EventEnvelope>,
+    pending: Arc<Mutex<Pending>>,
+    notify: Arc<Notify>,
+    senders: Weak<Inner>,
+) {
+    loop {
+        loop {
+            let Ok(permit) = tx.reserve().await else {
+                return;
+            };
+            let mut pending = pending.lock().unwrap();
+            let Some((timestamp_ms, event)) = pending.pop() else {
+                break;
+            };
+            permit.send(pending.envelope(timestamp_ms, event));
+        }
+        if senders.strong_count() == 0 {
+            return;
+        }
+        notify.notified().await;
+    }
+}
+
+/// 只保留最新值的进度事件所属的任务和种类
+fn progress_key(event: &DownloaderEvent) -> Option<(String, ProgressKind)> {
+    match event {
+        DownloaderEvent::Progress(ProgressEvent::Updated { task_id, .. }) => {
+            Some((task_id.clone(), ProgressKind::Download))
+        }
+        DownloaderEvent::Verification(VerificationEvent::Progress { task_id, .. }) => {
+            Some((task_id.clone(), ProgressKind::Verify))
+        }
+        _ => None,
+    }
+}
+
+/// 任务结束后不再需要保留进度的事件所属的任务和种类
+fn finished_key(event: &DownloaderEvent) -> Option<(String, ProgressKind)> {
+    match event {
+        DownloaderEvent::Task(
+            TaskEvent::Completed { task_id, .. }
+            | TaskEvent::Failed { task_id, .. }
+            | TaskEvent::PartiallyCompleted { task_id, .. }
+            | TaskEvent::Cancelled { task_id },
+        ) => Some((task_id.clone(), ProgressKind::Download)),
+        DownloaderEvent::Verification(VerificationEvent::Completed { task_id, .. }) => {
+            Some((task_id.clone(), ProgressKind::Verify))
+        }
+        _ => None,
+    }
+}
+
+/// 是否记入重放缓冲区：分块进度和定期汇总的全局事件在订阅后很快会有新的，不保留
+fn replayable(event: &DownloaderEvent) -> bool {
+    matches!(
+        event,
+        DownloaderEvent::Task(_)
+            | DownloaderEvent::Verification(_)
+            | DownloaderEvent::Global(GlobalEvent::SchedulerStateChanged(_))
+    )
+}
+
+/// 当前 Unix 时间（毫秒）
+pub(crate) fn current_timestamp_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TaskEvent;
+
+    #[tokio::test]
+    async fn test_seq_strictly_increases_across_concurrent_senders() {
+        let (tx, mut rx) = mpsc::channel(64);
+        let sender = EventSender::new(tx, 0);
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    for _ in 0..500 {
+                        let event = DownloaderEvent::Task(TaskEvent::Started {
+                            task_id: i.to_string(),
+                        });
+                        if i % 2 == 0 {
+                            sender.send(event).unwrap();
+                        } else {
+                            // 通道满时重试
+                            let mut event = event;
+                            while let Err(TrySendError::Full(e)) = sender.try_send(event) {
+                                event = e;
+                                tokio::task::yield_now().await;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let receiver = tokio::spawn(async move {
+            let mut seqs = Vec::new();
+            while let Some(envelope) = rx.recv().await {
+                seqs.push(envelope.seq);
+            }
+            seqs
+        });
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let seqs = receiver.await.unwrap();
+        assert_eq!(seqs.len(), 4000);
+        assert!(seqs.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(seqs[0], 1);
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_never_blocks_and_keeps_lifecycle_events() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let sender = EventSender::new(tx, 0);
+
+        // 没有接收端消费时发送不等待：进度只保留最新的，其他事件全部排队
+        for i in 0..1000u64 {
+            let task_id = (i % 2).to_string();
+            sender
+                .send(DownloaderEvent::Progress(ProgressEvent::Updated {
+                    task_id: task_id.clone(),
+                    downloaded: i,
+                    total: 1000,
+                    speed: 0,
+                    eta: None,
+                    contiguous_prefix: 0,
+                }))
+                .unwrap();
+            if i % 100 == 99 {
+                sender
+                    .send(DownloaderEvent::Task(TaskEvent::Completed {
+                        task_id,
+                        skipped_unchanged: false,
+                        average_speed: 0,
+                        peak_speed: 0,
+                    }))
+                    .unwrap();
+            }
+        }
+        assert!(matches!(
+            sender.try_send(DownloaderEvent::Task(TaskEvent::Added {
+                task_id: "x".to_string()
+            })),
+            Err(TrySendError::Full(_))
+        ));
+        drop(sender);
+
+        let mut envelopes = Vec::new();
+        while let Some(envelope) = rx.recv().await {
+            envelopes.push(envelope);
+        }
+        assert!(envelopes.windows(2).all(|w| w[0].seq < w[1].seq));
+        let completed = envelopes
+            .iter()
+            .filter(|e| matches!(e.event, DownloaderEvent::Task(TaskEvent::Completed { .. })))
+            .count();
+        assert_eq!(completed, 10);
+        assert!(envelopes.len() < 100);
+        // 最后的进度没有丢失
+        let last = |task: &str| {
+            envelopes.iter().rev().find_map(|e| match &e.event {
+                DownloaderEvent::Progress(ProgressEvent::Updated {
+                    task_id,
+                    downloaded,
+                    ..
+                }) if task_id == task => Some(*downloaded),
+                _ => None,
+            })
+        };
+        assert_eq!(last("0"), Some(998));
+        assert_eq!(last("1"), Some(999));
+    }
+
+    fn updated(task_id: &str, downloaded: u64) -> DownloaderEvent {
+        DownloaderEvent::Progress(ProgressEvent::Updated {
+            task_id: task_id.to_string(),
+            downloaded,
+            total: 100,
+            speed: 0,
+            eta: None,
+            contiguous_prefix: 0,
+        })
+    }
+
+    fn task_event(event: fn(String) -> TaskEvent, task_id: &str) -> DownloaderEvent {
+        DownloaderEvent::Task(event(task_id.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_replay_then_live_in_order() {
+        let (tx, mut rx) = mpsc::channel(1024);
+        let sender = EventSender::new(tx, 3);
+        let started = |task_id| TaskEvent::Started { task_id };
+        let paused = |task_id| TaskEvent::Paused { task_id };
+        let cancelled = |task_id| TaskEvent::Cancelled { task_id };
+
+        sender.send(task_event(started, "a")).unwrap(); // 1，超出容量被丢弃
+        sender.send(updated("a", 10)).unwrap(); // 2，被 4 覆盖
+        sender.send(task_event(started, "b")).unwrap(); // 3
+        sender.send(updated("a", 20)).unwrap(); // 4
+        sender.send(updated("b", 5)).unwrap(); // 5，任务已结束
+        sender.send(task_event(cancelled, "b")).unwrap(); // 6
+        sender
+            .send(DownloaderEvent::Progress(ProgressEvent::ChunkProgress {
+                task_id: "a".to_string(),
+                chunk_index: 0,
+                delta: 1,
+            }))
+            .unwrap(); // 7，分块进度不保留
+        sender.send(task_event(paused, "a")).unwrap(); // 8
+
+        let mut subscriber = sender.subscribe_with_replay();
+        sender.send(updated("a", 30)).unwrap(); // 9
+        sender.send(task_event(started, "c")).unwrap(); // 10
+        drop(sender);
+
+        let mut received = Vec::new();
+        while let Some(envelope) = subscriber.recv().await {
+            received.push((envelope.seq, envelope.replayed));
+        }
+        assert_eq!(
+            received,
+            [
+                (3, true),
+                (4, true),
+                (6, true),
+                (8, true),
+                (9, false),
+                (10, false)
+            ]
+        );
+
+        // 主接收端不受影响
+        let mut seqs = Vec::new();
+        while let Some(envelope) = rx.recv().await {
+            assert!(!envelope.replayed);
+            seqs.push(envelope.seq);
+        }
+        assert_eq!(seqs, (1..=10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_during_concurrent_sends_has_no_gaps_or_duplicates() {
+        let (tx, _rx) = mpsc::channel(8192);
+        let sender = EventSender::new(tx, 8192);
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    for _ in 0..500 {
+                        sender
+                            .send(task_event(
+                                |task_id| TaskEvent::Started { task_id },
+                                &i.to_string(),
+                            ))
+                            .unwrap();
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        let mut subscriber = sender.subscribe_with_replay();
+        drop(sender);
+        let receiver = tokio::spawn(async move {
+            let mut seqs = Vec::new();
+            let mut live = false;
+            while let Some(envelope) = subscriber.recv().await {
+                // 补发的事件都在新的事件之前
+                assert!(!(live && envelope.replayed));
+                live |= !envelope.replayed;
+                seqs.push(envelope.seq);
+            }
+            seqs
+        });
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        // 缓冲区足够大时补发的事件和之后的事件正好是全部事件，每个只出现一次
+        let seqs = receiver.await.unwrap();
+        assert_eq!(seqs, (1..=2000).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_is_disconnected() {
+        let (tx, _rx) = mpsc::channel(4096);
+        let sender = EventSender::new(tx, 0);
+        let mut subscriber = sender.subscribe_with_replay();
+        for i in 0..=SUBSCRIBER_CAPACITY as u64 {
+            sender.send(updated("a", i)).unwrap();
+        }
+        let mut count = 0;
+        while subscriber.recv().await.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, SUBSCRIBER_CAPACITY);
+    }
+
+    #[test]
+    fn test_envelope_serialization_is_additive() {
+        let envelope = EventEnvelope {
+            seq: 7,
+            timestamp_ms: 1_700_000_000_000,
+            replayed: false,
+            event: DownloaderEvent::Task(TaskEvent::Added {
+                task_id: "abc".to_string(),
+            }),
+        };
+        let value = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(value["type"], "Task");
+        assert_eq!(value["data"]["Added"]["task_id"], "abc");
+        assert_eq!(value["seq"], 7);
+        assert_eq!(value["timestamp_ms"], 1_700_000_000_000u64);
+        assert!(value.get("replayed").is_none());
+
+        let back: EventEnvelope = serde_json::from_value(value).unwrap();
+        assert_eq!(back.seq, 7);
+        assert!(matches!(
+            back.event,
+            DownloaderEvent::Task(TaskEvent::Added { .. })
+        ));
+    }
+}
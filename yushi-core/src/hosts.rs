@@ -0,0 +1,130 @@
+//! 按主机限制连接数和速度
+//!
+//! 所有任务的下载连接按下载地址的主机名共用一组限制（[`Config::per_host_max_connections`]
+//! 和 [`Config::per_host_speed_limit`]）：分块连接和流式下载连接都先取得所在主机的许可，
+//! 不同主机之间互不影响。没有设置限制时只统计各主机的连接数，见 [`YuShi::host_stats`]。
+//!
+//! [`Config::per_host_max_connections`]: crate::Config::per_host_max_connections
+//! [`Config::per_host_speed_limit`]: crate::Config::per_host_speed_limit
+//! [`YuShi::host_stats`]: crate::YuShi::host_stats
+
+use crate::{Result, types::HostStats, utils::SharedSpeedLimiter};
+use reqwest::Url;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug)]
+pub(crate) struct HostLimits {
+    max_connections: Option<usize>,
+    speed_limit: Option<u64>,
+    /// 按小写的主机名查找
+    hosts: Mutex<HashMap<String, Arc<HostLimit>>>,
+}
+
+/// 一个主机的限制和连接统计
+#[derive(Debug)]
+struct HostLimit {
+    /// 不限制连接数时为 None
+    connections: Option<Arc<Semaphore>>,
+    speed: SharedSpeedLimiter,
+    active: AtomicUsize,
+    waiting: AtomicUsize,
+}
+
+impl HostLimits {
+    pub fn new(max_connections: Option<usize>, speed_limit: Option<u64>) -> Self {
+        Self {
+            max_connections: max_connections.map(|limit| limit.max(1)),
+            speed_limit,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 取得 `url` 所在主机的一个连接许可，达到连接数上限时等待其他连接结束
+    pub async fn acquire(&self, url: &str) -> Result<HostPermit> {
+        let host = self.host(url);
+        let permit = match &host.connections {
+            Some(semaphore) => {
+                host.waiting.fetch_add(1, Ordering::Relaxed);
+                // 等待中被取消时也要减去
+                let _waiting = Decrement(&host.waiting);
+                Some(Arc::clone(semaphore).acquire_owned().await?)
+            }
+            None => None,
+        };
+        host.active.fetch_add(1, Ordering::Relaxed);
+        Ok(HostPermit {
+            host,
+            _permit: permit,
+        })
+    }
+
+    /// 有连接或等待中的连接的主机，按主机名排序
+    pub fn stats(&self) -> Vec<HostStats> {
+        let mut stats: Vec<HostStats> = self
+            .hosts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(host, limit)| HostStats {
+                host: host.clone(),
+                active_connections: limit.active.load(Ordering::Relaxed),
+                waiting_connections: limit.waiting.load(Ordering::Relaxed),
+            })
+            .filter(|s| s.active_connections > 0 || s.waiting_connections > 0)
+            .collect();
+        stats.sort_by(|a, b| a.host.cmp(&b.host));
+        stats
+    }
+
+    fn host(&self, url: &str) -> Arc<HostLimit> {
+        let key = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+            .unwrap_or_default();
+        let mut hosts = self.hosts.lock().unwrap();
+        let limit = hosts.entry(key).or_insert_with(|| {
+            Arc::new(HostLimit {
+                connections: self.max_connections.map(|n| Arc::new(Semaphore::new(n))),
+                speed: SharedSpeedLimiter::new(self.speed_limit),
+                active: AtomicUsize::new(0),
+                waiting: AtomicUsize::new(0),
+            })
+        });
+        Arc::clone(limit)
+    }
+}
+
+/// [`HostLimits`] 的连接许可，释放时归还
+#[derive(Debug)]
+pub(crate) struct HostPermit {
+    host: Arc<HostLimit>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl HostPermit {
+    /// 按主机的速度限制等待写入 `bytes` 字节
+    pub async fn wait(&self, bytes: u64) {
+        self.host.speed.wait(bytes).await;
+    }
+}
+
+impl Drop for HostPermit {
+    fn drop(&mut self) {
+        self.host.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+struct Decrement<'a>(&'a AtomicUsize);
+
+impl Drop for Decrement<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,597 @@
+//! 核心库产生的文本的本地化
+//!
+//! 面向用户的文本（错误信息、校验结果等）都通过消息键查找。内置英文和中文两套消息，
+//! 前端可以用 [`register_bundle`] 注册其他语言，或者覆盖内置消息中的一部分。
+//!
+//! 当前语言在进程内共享，默认取自 `LC_ALL`、`LC_MESSAGES` 或 `LANG` 环境变量，
+//! 可以通过 [`Config::locale`](crate::Config::locale) 或 [`set_locale`] 修改。
+//! 找不到消息时依次回退到内置的同语言消息、英文消息，最后返回消息键本身。
+
+use crate::utils::XByte;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::{LazyLock, RwLock},
+};
+
+/// 默认语言，也是找不到消息时的回退语言
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// 数字的小数点，注册的消息包可以用这个键设置为其他字符
+pub const DECIMAL_SEPARATOR: &str = "number.decimal_separator";
+
+/// 所有消息键，内置的每个消息包都必须包含全部的键
+pub const KEYS: &[&str] = &[
+    DECIMAL_SEPARATOR,
+    "duration.seconds",
+    "duration.minutes",
+    "duration.hours",
+    "error.task_cancelled",
+    "error.task_not_found",
+    "error.group_not_found",
+    "error.internal",
+    "error.http",
+    "error.stream",
+    "error.json",
+    "error.checksum_failed",
+    "error.checksum_mismatch",
+    "error.verification_cancelled",
+    "error.checksums_disabled",
+    "error.cannot_remove",
+    "error.cannot_retry",
+    "error.cannot_invalidate",
+    "error.insecure_credential_file",
+    "error.cannot_modify",
+    "error.cannot_pause_verify_only",
+    "error.url_mismatch",
+    "error.invalid_index",
+    "error.invalid_checksum",
+    "error.invalid_priority",
+    "error.connection_stalled",
+    "error.task_timed_out",
+    "error.redirect_loop",
+    "error.too_many_redirects",
+    "error.shut_down",
+    "error.invalid_proxy",
+    "error.invalid_certificate",
+    "error.no_certificates",
+    "error.range_not_satisfiable",
+    "error.range_not_satisfiable_unknown",
+    "error.response_too_large",
+    "error.unknown",
+    "download.stalled",
+    "download.chunk_retries_exhausted",
+    "download.incomplete",
+    "download.panicked",
+    "download.url_refresh_failed",
+    "download.range_ignored",
+    "download.range_unsupported",
+    "download.range_needs_size",
+    "download.range_invalid",
+    "download.range_beyond_end",
+    "chunks.no_state",
+    "chunks.not_found",
+    "verify.chunks_intact",
+    "verify.stream_mismatch",
+    "dns.no_address",
+    "index.not_html",
+    "index.login_page",
+    "checksum.not_hex",
+    "checksum.unknown_length",
+    "checksum.unsupported",
+];
+
+const EN: &[(&str, &str)] = &[
+    (DECIMAL_SEPARATOR, "."),
+    ("duration.seconds", "{0}s"),
+    ("duration.minutes", "{0}m {1}s"),
+    ("duration.hours", "{0}h {1}m"),
+    ("error.task_cancelled", "Task was cancelled"),
+    ("error.task_not_found", "Task not found"),
+    ("error.group_not_found", "Group not found"),
+    ("error.internal", "Internal error: {0}"),
+    ("error.http", "HTTP ERROR: {0}"),
+    ("error.stream", "Stream Error: {0}"),
+    ("error.json", "JSON Error: {0}"),
+    ("error.checksum_failed", "Checksum verification failed"),
+    (
+        "error.checksum_mismatch",
+        "Checksum verification failed: {0}",
+    ),
+    (
+        "error.verification_cancelled",
+        "Checksum verification was interrupted",
+    ),
+    (
+        "error.checksums_disabled",
+        "Checksum verification requires the `checksums` feature",
+    ),
+    (
+        "error.cannot_remove",
+        "Cannot remove task in current status",
+    ),
+    (
+        "error.cannot_retry",
+        "Only failed or cancelled tasks can be retried",
+    ),
+    (
+        "error.cannot_invalidate",
+        "Chunks can only be invalidated for paused or failed tasks",
+    ),
+    (
+        "error.insecure_credential_file",
+        "Credential file {0} must only be accessible by its owner",
+    ),
+    (
+        "error.cannot_modify",
+        "The task cannot be modified while it is downloading or verifying",
+    ),
+    (
+        "error.cannot_pause_verify_only",
+        "Verification-only tasks cannot be paused, cancel them instead",
+    ),
+    (
+        "error.url_mismatch",
+        "The new URL serves a different file: {0}",
+    ),
+    ("error.invalid_index", "Cannot read directory index: {0}"),
+    ("error.invalid_checksum", "Invalid checksum: {0}"),
+    (
+        "error.invalid_priority",
+        "Invalid priority: {0} (expected low, normal, high, 0, 1 or 2)",
+    ),
+    (
+        "error.connection_stalled",
+        "Connection stalled: no data received for {0}s",
+    ),
+    (
+        "error.task_timed_out",
+        "Task did not finish within its deadline",
+    ),
+    ("error.redirect_loop", "Redirect loop at {0}"),
+    (
+        "error.too_many_redirects",
+        "Too many redirects (more than {0})",
+    ),
+    ("error.shut_down", "Downloader has been shut down"),
+    ("error.invalid_proxy", "Invalid proxy URL: {0}"),
+    (
+        "error.invalid_certificate",
+        "Invalid CA certificate {0}: {1}",
+    ),
+    ("error.no_certificates", "no PEM certificates found"),
+    (
+        "error.range_not_satisfiable",
+        "Requested byte range is beyond the end of the file ({0} bytes)",
+    ),
+    (
+        "error.range_not_satisfiable_unknown",
+        "Requested byte range is beyond the end of the file",
+    ),
+    (
+        "error.response_too_large",
+        "Response body exceeds the {0}-byte limit",
+    ),
+    ("error.unknown", "Unknown error"),
+    (
+        "download.stalled",
+        "Download stalled: no data received for {0}s",
+    ),
+    (
+        "download.chunk_retries_exhausted",
+        "Chunk {0} failed after {1} retries",
+    ),
+    (
+        "download.incomplete",
+        "Download finished with {0} missing byte range(s)",
+    ),
+    ("download.panicked", "Download task crashed: {0}"),
+    (
+        "download.url_refresh_failed",
+        "Failed to refresh the URL: {0}",
+    ),
+    (
+        "download.range_ignored",
+        "Server ignored the Range request for a byte-range download",
+    ),
+    (
+        "download.range_unsupported",
+        "Server does not support range requests, cannot download a byte range",
+    ),
+    (
+        "download.range_needs_size",
+        "Byte range without an end requires the server to report the file size",
+    ),
+    ("download.range_invalid", "Invalid byte range: {0}-{1}"),
+    (
+        "download.range_beyond_end",
+        "Byte range starts at {0} beyond the end of the file",
+    ),
+    ("chunks.no_state", "task has no chunk state to invalidate"),
+    ("chunks.not_found", "chunk {0} does not exist"),
+    (
+        "verify.chunks_intact",
+        "all chunks match the digests recorded during download, \
+         the expected checksum is probably wrong",
+    ),
+    (
+        "verify.stream_mismatch",
+        "the {0} bytes written do not match the expected checksum",
+    ),
+    ("dns.no_address", "{0} has no {1} address"),
+    (
+        "index.not_html",
+        "{0} is not an HTML page (Content-Type: {1})",
+    ),
+    ("index.login_page", "{0} looks like a login page"),
+    ("checksum.not_hex", "\"{0}\" is not a hexadecimal digest"),
+    (
+        "checksum.unknown_length",
+        "cannot tell the algorithm of a {0}-character digest; \
+         supported: MD5 (32), SHA-256 (64), SHA-512 (128)",
+    ),
+    (
+        "checksum.unsupported",
+        "{0} digests are not supported; supported: MD5 (32), SHA-256 (64), SHA-512 (128)",
+    ),
+];
+
+const ZH: &[(&str, &str)] = &[
+    (DECIMAL_SEPARATOR, "."),
+    ("duration.seconds", "{0} 秒"),
+    ("duration.minutes", "{0} 分 {1} 秒"),
+    ("duration.hours", "{0} 小时 {1} 分"),
+    ("error.task_cancelled", "任务已取消"),
+    ("error.task_not_found", "任务不存在"),
+    ("error.group_not_found", "任务组不存在"),
+    ("error.internal", "内部错误: {0}"),
+    ("error.http", "HTTP 错误: {0}"),
+    ("error.stream", "数据流错误: {0}"),
+    ("error.json", "JSON 错误: {0}"),
+    ("error.checksum_failed", "校验和验证失败"),
+    ("error.checksum_mismatch", "校验和验证失败: {0}"),
+    ("error.verification_cancelled", "校验被中断"),
+    (
+        "error.checksums_disabled",
+        "校验文件需要启用 `checksums` 特性",
+    ),
+    ("error.cannot_remove", "当前状态的任务不能删除"),
+    ("error.cannot_retry", "只有失败或已取消的任务可以重试"),
+    (
+        "error.cannot_invalidate",
+        "只有暂停或失败的任务可以重新下载分块",
+    ),
+    (
+        "error.insecure_credential_file",
+        "认证信息文件 {0} 只能允许所有者访问",
+    ),
+    ("error.cannot_modify", "下载或校验中的任务不能修改"),
+    (
+        "error.cannot_pause_verify_only",
+        "只校验本地文件的任务不能暂停，可以取消",
+    ),
+    ("error.url_mismatch", "新地址对应的不是同一个文件: {0}"),
+    ("error.invalid_index", "无法读取目录索引: {0}"),
+    ("error.invalid_checksum", "无效的校验和: {0}"),
+    (
+        "error.invalid_priority",
+        "无效的优先级: {0} (可选 low, normal, high, 0, 1, 2)",
+    ),
+    ("error.connection_stalled", "连接停滞: {0} 秒内没有收到数据"),
+    ("error.task_timed_out", "任务没有在时限内完成"),
+    ("error.redirect_loop", "重定向循环: {0}"),
+    ("error.too_many_redirects", "重定向次数过多（超过 {0} 次）"),
+    ("error.shut_down", "下载器已关闭"),
+    ("error.invalid_proxy", "无效的代理地址: {0}"),
+    ("error.invalid_certificate", "无效的 CA 证书 {0}: {1}"),
+    ("error.no_certificates", "没有找到 PEM 格式的证书"),
+    (
+        "error.range_not_satisfiable",
+        "请求的字节范围超出了文件末尾（文件大小 {0} 字节）",
+    ),
+    (
+        "error.range_not_satisfiable_unknown",
+        "请求的字节范围超出了文件末尾",
+    ),
+    ("error.response_too_large", "响应体超过了 {0} 字节的上限"),
+    ("error.unknown", "未知错误"),
+    ("download.stalled", "下载停滞: {0} 秒内没有收到数据"),
+    (
+        "download.chunk_retries_exhausted",
+        "分块 {0} 重试 {1} 次后仍然失败",
+    ),
+    (
+        "download.incomplete",
+        "下载结束，但有 {0} 个字节范围没有下载",
+    ),
+    ("download.panicked", "下载任务异常退出: {0}"),
+    ("download.url_refresh_failed", "刷新下载地址失败: {0}"),
+    (
+        "download.range_ignored",
+        "服务器忽略了字节范围下载的 Range 请求",
+    ),
+    (
+        "download.range_unsupported",
+        "服务器不支持范围请求，无法下载字节范围",
+    ),
+    (
+        "download.range_needs_size",
+        "没有结束位置的字节范围需要服务器报告文件大小",
+    ),
+    ("download.range_invalid", "无效的字节范围: {0}-{1}"),
+    (
+        "download.range_beyond_end",
+        "字节范围的起点 {0} 超出了文件末尾",
+    ),
+    ("chunks.no_state", "任务没有可以重新下载的分块"),
+    ("chunks.not_found", "分块 {0} 不存在"),
+    (
+        "verify.chunks_intact",
+        "所有分块都与下载时记录的摘要一致，预期的校验和可能有误",
+    ),
+    (
+        "verify.stream_mismatch",
+        "写出的 {0} 字节与预期的校验和不一致",
+    ),
+    ("dns.no_address", "{0} 没有 {1} 地址"),
+    ("index.not_html", "{0} 不是 HTML 页面（Content-Type: {1}）"),
+    ("index.login_page", "{0} 看起来是登录页面"),
+    ("checksum.not_hex", "\"{0}\" 不是十六进制摘要"),
+    (
+        "checksum.unknown_length",
+        "无法根据 {0} 位的摘要判断算法，支持 MD5（32 位）、SHA-256（64 位）和 SHA-512（128 位）",
+    ),
+    (
+        "checksum.unsupported",
+        "不支持 {0} 摘要，支持 MD5（32 位）、SHA-256（64 位）和 SHA-512（128 位）",
+    ),
+];
+
+/// 当前语言
+static LOCALE: LazyLock<RwLock<String>> = LazyLock::new(|| RwLock::new(locale_from_env()));
+
+/// 前端注册的消息包，键为语言
+static BUNDLES: RwLock<BTreeMap<String, BTreeMap<String, String>>> = RwLock::new(BTreeMap::new());
+
+/// 把 `zh_CN.UTF-8`、`en-US` 这样的语言标签规范为 `zh`、`en`
+pub fn normalize_locale(tag: &str) -> String {
+    tag.split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// 从环境变量判断语言，没有设置或为 `C`、`POSIX` 时返回 [`DEFAULT_LOCALE`]
+pub fn locale_from_env() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .map(|value| normalize_locale(&value))
+        .find(|locale| !locale.is_empty())
+        .filter(|locale| locale != "c" && locale != "posix")
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+}
+
+/// 当前语言
+pub fn locale() -> String {
+    LOCALE.read().unwrap().clone()
+}
+
+/// 设置当前语言，之后产生的文本使用新的语言
+pub fn set_locale(tag: &str) {
+    *LOCALE.write().unwrap() = normalize_locale(tag);
+}
+
+/// 可用的语言，包括内置的和注册的
+pub fn available_locales() -> Vec<String> {
+    let mut locales = vec![DEFAULT_LOCALE.to_string(), "zh".to_string()];
+    for locale in BUNDLES.read().unwrap().keys() {
+        if !locales.contains(locale) {
+            locales.push(locale.clone());
+        }
+    }
+    locales
+}
+
+/// 注册消息包
+///
+/// 同一语言多次注册时合并，后注册的消息覆盖之前的；包中没有的键回退到内置消息。
+pub fn register_bundle<K, V>(locale: &str, messages: impl IntoIterator<Item = (K, V)>)
+where
+    K: Into<String>,
+    V: Into<String>,
+{
+    BUNDLES
+        .write()
+        .unwrap()
+        .entry(normalize_locale(locale))
+        .or_default()
+        .extend(messages.into_iter().map(|(k, v)| (k.into(), v.into())));
+}
+
+/// 用当前语言查找消息，`{0}`、`{1}` 等占位符依次替换为 `args`
+pub fn message(key: &str, args: &[&dyn Display]) -> String {
+    message_with(&locale(), key, args)
+}
+
+/// 用指定语言查找消息
+pub fn message_with(locale: &str, key: &str, args: &[&dyn Display]) -> String {
+    let locale = normalize_locale(locale);
+    let registered = BUNDLES
+        .read()
+        .unwrap()
+        .get(&locale)
+        .and_then(|bundle| bundle.get(key).cloned());
+    let template = registered
+        .as_deref()
+        .or_else(|| builtin(&locale, key))
+        .or_else(|| builtin(DEFAULT_LOCALE, key))
+        .unwrap_or(key);
+    fill(template, args)
+}
+
+fn builtin(locale: &str, key: &str) -> Option<&'static str> {
+    let bundle = match locale {
+        "en" => EN,
+        "zh" => ZH,
+        _ => return None,
+    };
+    bundle.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// 替换占位符，没有对应参数的占位符原样保留
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let placeholder = rest[start + 1..].find('}').and_then(|end| {
+            let index: usize = rest[start + 1..start + 1 + end].parse().ok()?;
+            Some((index, start + end + 2))
+        });
+        match placeholder.and_then(|(index, next)| args.get(index).map(|arg| (arg, next))) {
+            Some((arg, next)) => {
+                out.push_str(&arg.to_string());
+                rest = &rest[next..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 按当前语言格式化文件大小，如 `1.50 MB`
+pub fn format_size(bytes: u64) -> String {
+    format_size_with(&locale(), bytes)
+}
+
+/// 按指定语言格式化文件大小
+pub fn format_size_with(locale: &str, bytes: u64) -> String {
+    let size = XByte::from_bytes(bytes);
+    let value = format!("{:.2}", size.to_float());
+    let separator = message_with(locale, DECIMAL_SEPARATOR, &[]);
+    format!("{} {}", value.replace('.', &separator), size.unit())
+}
+
+/// 按当前语言格式化剩余时间，如 `2m 5s`
+pub fn format_eta(secs: u64) -> String {
+    format_eta_with(&locale(), secs)
+}
+
+/// 按指定语言格式化剩余时间
+pub fn format_eta_with(locale: &str, secs: u64) -> String {
+    if secs >= 3600 {
+        message_with(
+            locale,
+            "duration.hours",
+            &[&(secs / 3600), &(secs % 3600 / 60)],
+        )
+    } else if secs >= 60 {
+        message_with(locale, "duration.minutes", &[&(secs / 60), &(secs % 60)])
+    } else {
+        message_with(locale, "duration.seconds", &[&secs])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(template: &str) -> Vec<&str> {
+        let mut found: Vec<_> = template
+            .match_indices('{')
+            .filter_map(|(i, _)| {
+                let end = template[i..].find('}')?;
+                Some(&template[i..i + end + 1])
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn test_builtin_bundles_cover_every_key() {
+        for (name, bundle) in [("en", EN), ("zh", ZH)] {
+            for key in KEYS {
+                assert!(
+                    bundle.iter().any(|(k, _)| k == key),
+                    "{} bundle is missing {}",
+                    name,
+                    key
+                );
+            }
+            for (key, _) in bundle {
+                assert!(
+                    KEYS.contains(key),
+                    "{} bundle has unknown key {}",
+                    name,
+                    key
+                );
+            }
+            assert_eq!(
+                bundle.len(),
+                KEYS.len(),
+                "{} bundle has duplicate keys",
+                name
+            );
+        }
+        // 两种语言的占位符一致
+        for (key, en) in EN {
+            let zh = builtin("zh", key).unwrap();
+            assert_eq!(placeholders(en), placeholders(zh), "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_lookup_and_fallback() {
+        assert_eq!(
+            message_with("en_US.UTF-8", "download.range_invalid", &[&5, &2]),
+            "Invalid byte range: 5-2"
+        );
+        assert_eq!(
+            message_with("zh-CN", "chunks.not_found", &[&3]),
+            "分块 3 不存在"
+        );
+        // 未知语言回退到英文，未知键原样返回
+        assert_eq!(message_with("xx", "error.unknown", &[]), "Unknown error");
+        assert_eq!(message_with("en", "no.such.key", &[]), "no.such.key");
+        // 参数中的占位符不会再被替换，缺少的参数保留占位符
+        assert_eq!(
+            message_with("en", "download.range_invalid", &[&"{1}"]),
+            "Invalid byte range: {1}-{1}"
+        );
+
+        // 注册的消息包覆盖内置消息，缺少的键回退，也可以改变小数点
+        register_bundle(
+            "de_DE",
+            [
+                ("error.unknown", "Unbekannter Fehler"),
+                (DECIMAL_SEPARATOR, ","),
+            ],
+        );
+        assert!(available_locales().contains(&"de".to_string()));
+        assert_eq!(
+            message_with("de", "error.unknown", &[]),
+            "Unbekannter Fehler"
+        );
+        assert_eq!(
+            message_with("de", "error.task_not_found", &[]),
+            "Task not found"
+        );
+        assert_eq!(format_size_with("de", 1536), "1,50 KB");
+        assert_eq!(format_size_with("zh", 1536), "1.50 KB");
+    }
+
+    #[test]
+    fn test_format_eta() {
+        assert_eq!(format_eta_with("en", 42), "42s");
+        assert_eq!(format_eta_with("en", 125), "2m 5s");
+        assert_eq!(format_eta_with("zh", 3 * 3600 + 120), "3 小时 2 分");
+        assert_eq!(normalize_locale("zh_CN.UTF-8"), "zh");
+    }
+}
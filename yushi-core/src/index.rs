@@ -0,0 +1,254 @@
+//! 目录索引页解析
+//!
+//! 读取 nginx、Apache 等服务器自动生成的目录索引页（autoindex）中的 `<a href>` 链接：
+//! 以 `/` 结尾的是子目录，其余是文件。上级目录、排序链接和索引页自身都会被忽略，
+//! 子目录只保留索引页所在目录之下的。这里只做索引页需要的最少解析，不是通用的 HTML 解析器。
+
+use crate::{
+    types::IndexOptions,
+    utils::{percent_decode, sanitize_filename},
+};
+use reqwest::Url;
+use std::collections::HashSet;
+
+/// 索引页中的链接，按出现顺序排列且没有重复
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct IndexLinks {
+    /// 索引页所在目录之下的子目录
+    pub dirs: Vec<Url>,
+    /// 文件，可能位于其他主机
+    pub files: Vec<Url>,
+}
+
+/// 解析 `base` 处的索引页
+pub(crate) fn parse_index(html: &str, base: &Url) -> IndexLinks {
+    let dir = base.join("./").unwrap_or_else(|_| base.clone());
+    let mut seen = HashSet::new();
+    let mut links = IndexLinks::default();
+    for href in hrefs(html) {
+        let Ok(mut url) = base.join(&href) else {
+            continue;
+        };
+        if !matches!(url.scheme(), "http" | "https") {
+            continue;
+        }
+        url.set_fragment(None);
+        let local = url.origin() == base.origin();
+        // 排序链接（`?C=N;O=D`）、索引页自身和上级目录
+        if local && (url.path() == base.path() || !url.path().starts_with(dir.path())) {
+            continue;
+        }
+        if !seen.insert(url.clone()) {
+            continue;
+        }
+        if url.path().ends_with('/') {
+            if local {
+                links.dirs.push(url);
+            }
+        } else {
+            links.files.push(url);
+        }
+    }
+    links
+}
+
+/// 页面中是否有密码输入框，用于识别重定向到的登录页
+pub(crate) fn looks_like_login(html: &str) -> bool {
+    let lower = html.to_ascii_lowercase();
+    ["type=\"password\"", "type='password'", "type=password"]
+        .iter()
+        .any(|pattern| lower.contains(pattern))
+}
+
+/// `url` 相对于目录 `root` 的路径段，每段都经过 [`sanitize_filename`] 处理
+///
+/// `url` 不在 `root` 之下，或者某一段得不到可用的名称时返回 None。
+pub(crate) fn relative_segments(root: &Url, url: &Url) -> Option<Vec<String>> {
+    if url.origin() != root.origin() {
+        return None;
+    }
+    url.path()
+        .strip_prefix(root.path())?
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| sanitize_filename(&percent_decode(segment)))
+        .collect()
+}
+
+/// 相对路径（以 `/` 分隔）是否满足 `options` 中的包含和排除规则
+pub(crate) fn selected(options: &IndexOptions, path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let check =
+        |pattern: &str| glob_match(pattern, if pattern.contains('/') { path } else { name });
+    options.include_glob.as_deref().is_none_or(check)
+        && !options.exclude_glob.as_deref().is_some_and(check)
+}
+
+/// 通配符匹配，`*` 匹配任意多个字符（包括 `/`），`?` 匹配一个字符
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 最近一个 `*` 在模式中的位置，以及它目前匹配到的文本结尾
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// 所有 `<a>` 标签的 `href` 属性值，已解码常见的 HTML 实体
+fn hrefs(html: &str) -> Vec<String> {
+    // ASCII 小写不改变字节长度，两边的下标可以通用
+    let lower = html.to_ascii_lowercase();
+    let mut values = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = lower[pos..].find("<a") {
+        let start = pos + offset;
+        let Some(len) = lower[start..].find('>') else {
+            break;
+        };
+        let end = start + len;
+        pos = end + 1;
+        // `<abbr>`、`<address>` 等其他标签
+        if !lower[start + 2..end].starts_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        if let Some(value) = attribute(&html[start..end], &lower[start..end], "href") {
+            values.push(decode_entities(value));
+        }
+    }
+    values
+}
+
+/// 标签中的属性值，支持单引号、双引号和不带引号的写法
+fn attribute<'a>(tag: &'a str, lower: &str, name: &str) -> Option<&'a str> {
+    let mut from = 0;
+    while let Some(offset) = lower[from..].find(name) {
+        let start = from + offset;
+        from = start + name.len();
+        if !lower[..start].ends_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let Some(rest) = lower[from..].trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let value = &tag[tag.len() - rest.trim_start().len()..];
+        return match value.chars().next()? {
+            quote @ ('"' | '\'') => value[1..].split(quote).next(),
+            _ => value.split(|c: char| c.is_ascii_whitespace()).next(),
+        };
+    }
+    None
+}
+
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NGINX: &str = include_str!("../tests/fixtures/nginx_autoindex.html");
+    const APACHE: &str = include_str!("../tests/fixtures/apache_autoindex.html");
+
+    fn paths(urls: &[Url]) -> Vec<&str> {
+        urls.iter().map(|url| url.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_nginx_autoindex() {
+        let base = Url::parse("http://example.com/pub/releases/").unwrap();
+        let links = parse_index(NGINX, &base);
+        assert_eq!(
+            paths(&links.dirs),
+            [
+                "http://example.com/pub/releases/v1.0/",
+                "http://example.com/pub/releases/v1.1/",
+            ]
+        );
+        assert_eq!(
+            paths(&links.files),
+            [
+                "http://example.com/pub/releases/SHA256SUMS",
+                "http://example.com/pub/releases/yushi-1.1-x86_64.iso",
+                "http://example.com/pub/releases/yushi%201.1%20notes.txt",
+                "http://example.com/pub/releases/R%26D.tar.gz",
+            ]
+        );
+        assert!(!looks_like_login(NGINX));
+    }
+
+    #[test]
+    fn test_parse_apache_autoindex() {
+        let base = Url::parse("https://mirror.example.org/mirror/iso/").unwrap();
+        let links = parse_index(APACHE, &base);
+        // 排序链接、上级目录和重复的链接都被忽略，大写标签和不带引号的属性也能识别
+        assert_eq!(
+            paths(&links.dirs),
+            ["https://mirror.example.org/mirror/iso/daily/"]
+        );
+        assert_eq!(
+            paths(&links.files),
+            [
+                "https://mirror.example.org/mirror/iso/debian-12.5.0-amd64-netinst.iso",
+                "https://mirror.example.org/mirror/iso/SHA512SUMS",
+                "https://cdn.example.org/iso/README.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relative_segments_and_globs() {
+        let root = Url::parse("http://example.com/pub/").unwrap();
+        let url = Url::parse("http://example.com/pub/v1.1/R%26D%20notes.txt").unwrap();
+        assert_eq!(
+            relative_segments(&root, &url).unwrap(),
+            ["v1.1", "R&D notes.txt"]
+        );
+        let other = Url::parse("http://other.example.com/pub/a.iso").unwrap();
+        assert_eq!(relative_segments(&root, &other), None);
+
+        let options = IndexOptions {
+            include_glob: Some("*.iso".to_string()),
+            exclude_glob: Some("beta/*".to_string()),
+            ..Default::default()
+        };
+        assert!(selected(&options, "v1.1/yushi.iso"));
+        assert!(!selected(&options, "beta/yushi.iso"));
+        assert!(!selected(&options, "v1.1/SHA256SUMS"));
+        assert!(glob_match("yushi-?.?-*.iso", "yushi-1.1-x86_64.iso"));
+        assert!(!glob_match("*.iso", "yushi.iso.sig"));
+    }
+
+    #[test]
+    fn test_looks_like_login() {
+        let html =
+            r#"<form action="/login"><input name=user><INPUT TYPE="password" name=pass></form>"#;
+        assert!(looks_like_login(html));
+    }
+}
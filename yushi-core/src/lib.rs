@@ -1,9 +1,67 @@
 //! YuShi - 高性能异步下载库
 //!
 //! 提供统一的下载和队列管理功能，支持断点续传、并发下载等特性。
+//!
+//! ```no_run
+//! use std::path::PathBuf;
+//! use yushi_core::{DownloaderEvent, ProgressEvent, TaskEvent, YuShi};
+//!
+//! # #[cfg(feature = "queue-persistence")]
+//! # async fn run() -> yushi_core::Result<()> {
+//! let (queue, mut event_rx) = YuShi::new(4, 2, PathBuf::from("queue.json"));
+//! queue
+//!     .add_task(
+//!         "https://example.com/file.zip".to_string(),
+//!         PathBuf::from("file.zip"),
+//!     )
+//!     .await?;
+//!
+//! while let Some(envelope) = event_rx.recv().await {
+//!     match envelope.event {
+//!         DownloaderEvent::Progress(ProgressEvent::Updated {
+//!             task_id, downloaded, total, ..
+//!         }) => println!("{}: {}/{}", task_id, downloaded, total),
+//!         DownloaderEvent::Task(TaskEvent::Completed { .. }) => break,
+//!         _ => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! 旧版的 `DownloadQueue` 和扁平的 `QueueEvent` 由默认开启的 `compat` 特性提供，
+//! 见 `compat` 模块。
+//!
+//! # 特性
+//!
+//! 默认开启全部特性，只需要基本下载功能时可以关闭以减少依赖：
+//!
+//! - `checksums`：MD5 / SHA-256 校验、分块摘要和 [`verify_file`]
+//! - `queue-persistence`：把队列状态保存到文件，[`YuShi::new`] 和 [`YuShi::with_config`]
+//!   需要此特性；关闭后用 [`YuShi::in_memory`] 创建只在内存中的队列
+//! - `uuid-ids`：任务 ID 使用 UUID v4，关闭后使用不依赖 `uuid` 的轻量 ID
+//! - `compat`：旧版 API，依赖 `queue-persistence`
 
+mod audit;
+mod changes;
+#[cfg(feature = "compat")]
+pub mod compat;
+mod cookies;
+mod credentials;
 pub mod downloader;
 pub mod error;
+mod event;
+mod hosts;
+pub mod i18n;
+mod index;
+#[cfg(test)]
+mod mock_server;
+mod orphan;
+mod pressure;
+mod progress;
+mod resolver;
+mod schedule;
+pub mod sink;
 pub mod state;
 pub mod types;
 pub mod utils;
@@ -11,28 +69,95 @@ pub mod utils;
 pub use error::*;
 
 // 重新导出公共 API
+pub use audit::read_audit_log;
+pub use bytes::Bytes;
+#[cfg(feature = "compat")]
+#[allow(deprecated)]
+pub use compat::{DownloadQueue, QueueEvent};
+pub use credentials::{Credential, CredentialStore};
 pub use downloader::YuShi;
+pub use sink::{FileSink, MemorySink, NullSink, StorageSink, WriterSink};
+pub use state::DownloaderState;
+pub use tokio_util::sync::CancellationToken;
 pub use types::{
+    AuditRecord,
+    CaseInsensitiveFs,
     ChecksumType,
+    ChunkDiagnostics,
+    ChunkFailurePolicy,
+    ChunkIssue,
+    ChunkOrder,
+    ChunkSummary,
+    CompactEvent,
+    CompactEventKind,
     // 回调类型
     CompletionCallback,
 
     Config,
+    ConflictPolicy,
+    ConnectionSample,
+    CookieSource,
+    DiskPressureConfig,
     DownloadCallback,
     DownloadConfig,
+    DownloadSummary,
     // 向后兼容别名
     DownloadTask,
     // 事件类型
     DownloaderEvent,
+    EffectiveConfig,
+    EventEnvelope,
+    Forecast,
+    GlobalEvent,
+    GroupProgress,
+    HostStats,
+    IndexOptions,
+    IpPolicy,
+    MAX_RECENT_EVENTS,
+    MeasureReport,
+    MirrorDemotion,
+    NewTask,
+    OrphanKind,
+    OrphanReport,
+    PausedReason,
+    PersistCallback,
+    PlanConflict,
+    PoolConfig,
+    PreallocateMode,
     Priority,
     ProgressEvent,
-    QueueEvent,
+    ProgressSnapshot,
+    Provenance,
+    QueueStats,
+    RemoteInfo,
+    RemoveOptions,
+    RestartReason,
+    RetryReason,
+    ScheduledSlot,
+    SchedulerState,
+    StateDiagnostics,
     // 主要类型
     Task,
+    TaskChanges,
+    TaskDiagnostics,
     TaskEvent,
+    TaskGroup,
+    TaskKind,
+    TaskOptions,
+    TaskPlan,
     TaskPriority,
+    TaskQuery,
+    TaskSort,
     // 枚举类型
     TaskStatus,
+    TaskView,
+    UrlRefresher,
     VerificationEvent,
+    VolumeForecast,
+};
+pub use utils::{
+    SpeedCalculator, auto_rename, auto_rename_with, filename_from_content_disposition,
+    filename_from_url, resolve_destination, sanitize_filename,
 };
-pub use utils::{SpeedCalculator, auto_rename, verify_file};
+#[cfg(feature = "checksums")]
+pub use utils::{verify_file, verify_file_with};
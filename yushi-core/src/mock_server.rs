@@ -0,0 +1,693 @@
+//! 测试用的本地 HTTP 服务器
+//!
+//! 默认每个连接只处理一个请求（响应带 `Connection: close`），支持 HEAD、Range
+//! 以及若干可配置的服务器行为，并记录收到的所有请求以便断言。
+
+#![allow(dead_code)]
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// 服务器上的一个文件及其行为
+#[derive(Debug, Clone)]
+pub(crate) struct MockFile {
+    pub body: Vec<u8>,
+    /// 是否返回 `Accept-Ranges: bytes`
+    pub accept_ranges: bool,
+    /// 是否按 Range 请求返回 206
+    pub honor_range: bool,
+    /// 是否返回 `Content-Length`
+    pub content_length: bool,
+    /// HEAD 响应是否包含 `Content-Length` 和 `Accept-Ranges`
+    pub head_headers: bool,
+    /// 每发送 1 KiB 响应体后的等待时间，用于模拟慢速服务器
+    pub delay_per_kib: Option<Duration>,
+    /// 第一个经过该位置的响应发送到这里后不再发送数据（连接保持打开）
+    pub stall_after: Option<u64>,
+    /// 第一个经过该位置的响应发送到这里后关闭连接
+    pub drop_after: Option<u64>,
+    /// 响应中的 `ETag`，请求的 `If-None-Match` 与之相同时返回 304
+    pub etag: Option<String>,
+    /// 响应中的 `Content-Type`
+    pub content_type: Option<String>,
+    /// 响应中的 `Content-Disposition`
+    pub content_disposition: Option<String>,
+    /// HEAD 请求返回这个状态（以及与文件无关的 `Content-Length`），而不是文件信息
+    pub head_status: Option<String>,
+    /// HEAD 响应中的 `Content-Length`，None 表示使用文件的实际大小
+    pub head_length: Option<u64>,
+}
+
+impl MockFile {
+    pub fn new(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            body: body.into(),
+            accept_ranges: true,
+            honor_range: true,
+            content_length: true,
+            head_headers: true,
+            delay_per_kib: None,
+            stall_after: None,
+            drop_after: None,
+            etag: None,
+            content_type: None,
+            content_disposition: None,
+            head_status: None,
+            head_length: None,
+        }
+    }
+
+    /// HTML 页面
+    pub fn html(body: &str) -> Self {
+        Self::new(body).with_content_type("text/html; charset=utf-8")
+    }
+
+    /// 响应带上 `Content-Type`
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.to_string());
+        self
+    }
+
+    /// 响应带上 `Content-Disposition`
+    pub fn with_content_disposition(mut self, value: &str) -> Self {
+        self.content_disposition = Some(value.to_string());
+        self
+    }
+
+    /// 不支持 Range 的服务器
+    pub fn without_ranges(mut self) -> Self {
+        self.accept_ranges = false;
+        self.honor_range = false;
+        self
+    }
+
+    /// 声称支持 Range（`Accept-Ranges: bytes`），实际总是返回 200 和整个文件的服务器
+    pub fn ignoring_ranges(mut self) -> Self {
+        self.honor_range = false;
+        self
+    }
+
+    /// 支持 Range 但不返回 `Accept-Ranges` 的服务器
+    pub fn without_accept_ranges(mut self) -> Self {
+        self.accept_ranges = false;
+        self
+    }
+
+    /// HEAD 请求返回 `status`（例如 `405 Method Not Allowed`）的服务器
+    pub fn with_head_status(mut self, status: &str) -> Self {
+        self.head_status = Some(status.to_string());
+        self
+    }
+
+    /// HEAD 响应报告的大小与实际文件不同的服务器（例如缓存过期的 CDN）
+    pub fn with_head_length(mut self, len: u64) -> Self {
+        self.head_length = Some(len);
+        self
+    }
+
+    /// HEAD 响应不带大小和 Range 信息的服务器
+    pub fn without_head_headers(mut self) -> Self {
+        self.head_headers = false;
+        self
+    }
+
+    /// 每发送 1 KiB 等待 `delay`
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay_per_kib = Some(delay);
+        self
+    }
+
+    /// 响应带上 `ETag`
+    pub fn with_etag(mut self, etag: &str) -> Self {
+        self.etag = Some(etag.to_string());
+        self
+    }
+
+    /// 第一次发送到文件的 `offset` 位置后停止发送，之后的请求正常响应
+    pub fn stall_after(mut self, offset: u64) -> Self {
+        self.stall_after = Some(offset);
+        self
+    }
+
+    /// 第一次发送到文件的 `offset` 位置后关闭连接，之后的请求正常响应
+    pub fn drop_after(mut self, offset: u64) -> Self {
+        self.drop_after = Some(offset);
+        self
+    }
+}
+
+/// 收到的请求
+#[derive(Debug, Clone)]
+pub(crate) struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// 头名称均为小写
+    pub headers: HashMap<String, String>,
+    /// 收到请求的时间
+    pub received_at: Instant,
+}
+
+#[derive(Default)]
+struct Shared {
+    files: HashMap<String, MockFile>,
+    requests: Vec<RecordedRequest>,
+    /// 正在发送响应体的 GET 连接数
+    active_gets: usize,
+    /// 是否在一个连接上处理多个请求
+    keep_alive: bool,
+    /// 接受的 TCP 连接数
+    connections: usize,
+    /// 带这些查询字符串的请求返回 403，模拟过期的预签名 URL
+    rejected_queries: Vec<String>,
+    /// 从这些位置开始的 Range 请求返回 500，模拟损坏的对象存储分片
+    failing_ranges: Vec<u64>,
+    /// 之后的 GET 请求依次返回的错误响应：状态和 `Retry-After` 的值
+    failing_gets: VecDeque<(String, Option<String>)>,
+    /// 这些路径返回 302，重定向到对应的路径
+    redirects: HashMap<String, String>,
+}
+
+pub(crate) struct MockServer {
+    addr: SocketAddr,
+    shared: Arc<Mutex<Shared>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let shared_c = Arc::clone(&shared);
+
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                shared_c.lock().unwrap().connections += 1;
+                let shared = Arc::clone(&shared_c);
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, shared).await;
+                });
+            }
+        });
+
+        Self {
+            addr,
+            shared,
+            handle,
+        }
+    }
+
+    pub fn add(&self, path: &str, file: MockFile) {
+        self.shared
+            .lock()
+            .unwrap()
+            .files
+            .insert(path.to_string(), file);
+    }
+
+    pub fn remove(&self, path: &str) {
+        self.shared.lock().unwrap().files.remove(path);
+    }
+
+    /// 之后对 `from` 的请求返回 302，重定向到同一服务器上的 `to`
+    pub fn redirect(&self, from: &str, to: &str) {
+        self.shared
+            .lock()
+            .unwrap()
+            .redirects
+            .insert(from.to_string(), to.to_string());
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.shared.lock().unwrap().requests.clone()
+    }
+
+    /// 当前正在发送响应体的 GET 连接数
+    pub fn active_gets(&self) -> usize {
+        self.shared.lock().unwrap().active_gets
+    }
+
+    /// 之后的响应使用 `Connection: keep-alive`，允许客户端复用连接
+    pub fn keep_alive(&self) {
+        self.shared.lock().unwrap().keep_alive = true;
+    }
+
+    /// 已接受的 TCP 连接数
+    pub fn connections(&self) -> usize {
+        self.shared.lock().unwrap().connections
+    }
+
+    /// 之后带这个查询字符串（`?` 之后的部分）的请求返回 403
+    pub fn reject_query(&self, query: &str) {
+        self.shared
+            .lock()
+            .unwrap()
+            .rejected_queries
+            .push(query.to_string());
+    }
+
+    /// 之后从 `start` 开始的 Range 请求返回 500，直到调用 [`Self::clear_failing_ranges`]
+    pub fn fail_range(&self, start: u64) {
+        self.shared.lock().unwrap().failing_ranges.push(start);
+    }
+
+    pub fn clear_failing_ranges(&self) {
+        self.shared.lock().unwrap().failing_ranges.clear();
+    }
+
+    /// 之后的 `count` 个 GET 请求返回 `status`（例如 `429 Too Many Requests`），
+    /// `retry_after` 为响应中 `Retry-After` 头的值
+    pub fn fail_gets(&self, count: usize, status: &str, retry_after: Option<&str>) {
+        let response = (status.to_string(), retry_after.map(str::to_string));
+        self.shared
+            .lock()
+            .unwrap()
+            .failing_gets
+            .extend(std::iter::repeat_n(response, count));
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    shared: Arc<Mutex<Shared>>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut tmp = [0u8; 1024];
+    loop {
+        let end = loop {
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            let n = stream.read(&mut tmp).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            buf.extend_from_slice(&tmp[..n]);
+        };
+        let request: Vec<u8> = buf.drain(..end).collect();
+        if !handle_request(&mut stream, &request, &shared).await? {
+            return stream.shutdown().await;
+        }
+    }
+}
+
+/// 处理一个请求，返回是否保持连接
+async fn handle_request(
+    stream: &mut TcpStream,
+    request: &[u8],
+    shared: &Mutex<Shared>,
+) -> std::io::Result<bool> {
+    let text = String::from_utf8_lossy(request).to_string();
+    let mut lines = text.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = path.to_string();
+    let headers: HashMap<String, String> = lines
+        .take_while(|l| !l.is_empty())
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+
+    let range_start = headers
+        .get("range")
+        .and_then(|r| r.strip_prefix("bytes="))
+        .and_then(|r| r.split('-').next())
+        .and_then(|start| start.parse::<u64>().ok());
+    let (file, keep_alive, rejected, failing, failed_get, redirect) = {
+        let mut shared = shared.lock().unwrap();
+        shared.requests.push(RecordedRequest {
+            method: method.clone(),
+            path: path.clone(),
+            headers: headers.clone(),
+            received_at: Instant::now(),
+        });
+        let rejected = shared.rejected_queries.iter().any(|q| q == query);
+        let failing = range_start.is_some_and(|start| shared.failing_ranges.contains(&start));
+        let failed_get = if method == "GET" {
+            shared.failing_gets.pop_front()
+        } else {
+            None
+        };
+        (
+            shared.files.get(&path).cloned(),
+            shared.keep_alive,
+            rejected,
+            failing,
+            failed_get,
+            shared.redirects.get(&path).cloned(),
+        )
+    };
+
+    if let Some(location) = redirect {
+        let head = format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            location
+        );
+        stream.write_all(head.as_bytes()).await?;
+        return Ok(false);
+    }
+
+    if let Some((status, retry_after)) = failed_get {
+        let mut head = format!(
+            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n",
+            status
+        );
+        if let Some(retry_after) = retry_after {
+            head.push_str(&format!("Retry-After: {}\r\n", retry_after));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).await?;
+        return Ok(false);
+    }
+
+    if failing {
+        stream
+            .write_all(
+                b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )
+            .await?;
+        return Ok(false);
+    }
+
+    if rejected {
+        stream
+            .write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(false);
+    }
+
+    let Some(file) = file else {
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(false);
+    };
+    // 没有 Content-Length 时只能靠关闭连接表示响应结束
+    let keep_alive = keep_alive && file.content_length;
+
+    if method == "HEAD"
+        && let Some(status) = &file.head_status
+    {
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Length: 7\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n",
+            status
+        );
+        stream.write_all(head.as_bytes()).await?;
+        return Ok(false);
+    }
+
+    if let Some(etag) = &file.etag
+        && headers.get("if-none-match") == Some(etag)
+    {
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        let head = format!(
+            "HTTP/1.1 304 Not Modified\r\nConnection: {}\r\nETag: {}\r\nContent-Length: 0\r\n\r\n",
+            connection, etag
+        );
+        stream.write_all(head.as_bytes()).await?;
+        return Ok(keep_alive);
+    }
+
+    let total = file.body.len() as u64;
+    let unsatisfiable = headers
+        .get("range")
+        .filter(|_| file.honor_range && method != "HEAD")
+        .and_then(|r| {
+            r.strip_prefix("bytes=")?
+                .split_once('-')?
+                .0
+                .parse::<u64>()
+                .ok()
+        })
+        .is_some_and(|start| start >= total);
+    if unsatisfiable {
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        let head = format!(
+            "HTTP/1.1 416 Range Not Satisfiable\r\nConnection: {}\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\n\r\n",
+            connection, total
+        );
+        stream.write_all(head.as_bytes()).await?;
+        return Ok(keep_alive);
+    }
+    let range = headers
+        .get("range")
+        .filter(|_| file.honor_range)
+        .and_then(|r| parse_range(r, total));
+
+    let (status, body): (&str, &[u8]) = match range {
+        Some((start, end)) => (
+            "206 Partial Content",
+            &file.body[start as usize..=end as usize],
+        ),
+        None => ("200 OK", &file.body),
+    };
+
+    // 响应体中停止发送的位置，只生效一次
+    let body_start = range.map_or(0, |(start, _)| start);
+    let stall_at = file
+        .stall_after
+        .filter(|&at| method != "HEAD" && at >= body_start && at < body_start + body.len() as u64)
+        .map(|at| {
+            if let Some(f) = shared.lock().unwrap().files.get_mut(&path) {
+                f.stall_after = None;
+            }
+            (at - body_start) as usize
+        });
+    let drop_at = file
+        .drop_after
+        .filter(|&at| method != "HEAD" && at >= body_start && at < body_start + body.len() as u64)
+        .map(|at| {
+            if let Some(f) = shared.lock().unwrap().files.get_mut(&path) {
+                f.drop_after = None;
+            }
+            (at - body_start) as usize
+        });
+
+    let bare_head = method == "HEAD" && !file.head_headers;
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    let mut head = format!("HTTP/1.1 {}\r\nConnection: {}\r\n", status, connection);
+    if file.accept_ranges && !bare_head {
+        head.push_str("Accept-Ranges: bytes\r\n");
+    }
+    if let Some((start, end)) = range {
+        head.push_str(&format!(
+            "Content-Range: bytes {}-{}/{}\r\n",
+            start, end, total
+        ));
+    }
+    if file.content_length && !bare_head {
+        let len = match file.head_length {
+            Some(len) if method == "HEAD" => len,
+            _ => body.len() as u64,
+        };
+        head.push_str(&format!("Content-Length: {}\r\n", len));
+    }
+    if let Some(etag) = &file.etag {
+        head.push_str(&format!("ETag: {}\r\n", etag));
+    }
+    if let Some(content_type) = &file.content_type {
+        head.push_str(&format!("Content-Type: {}\r\n", content_type));
+    }
+    if let Some(disposition) = &file.content_disposition {
+        head.push_str(&format!("Content-Disposition: {}\r\n", disposition));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    if method != "HEAD" {
+        shared.lock().unwrap().active_gets += 1;
+        let result = match (stall_at, drop_at) {
+            (Some(at), _) => {
+                let result = write_body(stream, &body[..at], file.delay_per_kib).await;
+                if result.is_ok() {
+                    tokio::time::sleep(Duration::from_secs(3600)).await;
+                }
+                result
+            }
+            (None, Some(at)) => write_body(stream, &body[..at], file.delay_per_kib).await,
+            (None, None) => write_body(stream, body, file.delay_per_kib).await,
+        };
+        shared.lock().unwrap().active_gets -= 1;
+        result?;
+        if drop_at.is_some() {
+            return Ok(false);
+        }
+    }
+    Ok(keep_alive)
+}
+
+async fn write_body(
+    stream: &mut TcpStream,
+    body: &[u8],
+    delay_per_kib: Option<Duration>,
+) -> std::io::Result<()> {
+    let Some(delay) = delay_per_kib else {
+        return stream.write_all(body).await;
+    };
+    for (i, piece) in body.chunks(1024).enumerate() {
+        if i > 0 {
+            tokio::time::sleep(delay).await;
+        }
+        stream.write_all(piece).await?;
+    }
+    Ok(())
+}
+
+fn parse_range(value: &str, total: u64) -> Option<(u64, u64)> {
+    let spec = value.strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+    let start: u64 = start.parse().ok()?;
+    let end: u64 = if end.is_empty() {
+        total.checked_sub(1)?
+    } else {
+        end.parse::<u64>().ok()?.min(total.checked_sub(1)?)
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// 测试用的 SOCKS5 代理，只接受用户名和密码认证，记录每个 CONNECT 请求的目标地址
+pub(crate) struct MockSocksProxy {
+    addr: SocketAddr,
+    targets: Arc<Mutex<Vec<String>>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockSocksProxy {
+    pub async fn start(user: &str, password: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let targets_c = Arc::clone(&targets);
+        let credentials = (user.to_string(), password.to_string());
+
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let targets = Arc::clone(&targets_c);
+                let credentials = credentials.clone();
+                tokio::spawn(async move {
+                    let _ = handle_socks(stream, credentials, targets).await;
+                });
+            }
+        });
+
+        Self {
+            addr,
+            targets,
+            handle,
+        }
+    }
+
+    /// 代理地址，`scheme` 为 `socks5` 或 `socks5h`
+    pub fn url(&self, scheme: &str) -> String {
+        format!("{}://{}", scheme, self.addr)
+    }
+
+    /// 通过认证的连接请求的目标地址（`host:port`）
+    pub fn targets(&self) -> Vec<String> {
+        self.targets.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockSocksProxy {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_socks(
+    mut stream: TcpStream,
+    (user, password): (String, String),
+    targets: Arc<Mutex<Vec<String>>>,
+) -> std::io::Result<()> {
+    // 协商认证方法，只接受用户名和密码（0x02）
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).await?;
+    let mut methods = vec![0u8; head[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if head[0] != 5 || !methods.contains(&2) {
+        return stream.write_all(&[5, 0xff]).await;
+    }
+    stream.write_all(&[5, 2]).await?;
+
+    // 用户名和密码认证（RFC 1929）
+    let mut version = [0u8; 1];
+    stream.read_exact(&mut version).await?;
+    let (got_user, got_password) = (
+        read_socks_field(&mut stream).await?,
+        read_socks_field(&mut stream).await?,
+    );
+    if got_user != user || got_password != password {
+        return stream.write_all(&[1, 1]).await;
+    }
+    stream.write_all(&[1, 0]).await?;
+
+    // CONNECT 请求
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    let host = match request[3] {
+        1 => {
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip).await?;
+            std::net::Ipv4Addr::from(ip).to_string()
+        }
+        3 => read_socks_field(&mut stream).await?,
+        _ => return stream.write_all(&[5, 8, 0, 1, 0, 0, 0, 0, 0, 0]).await,
+    };
+    let port = stream.read_u16().await?;
+    let target = format!("{}:{}", host, port);
+    let mut upstream = match TcpStream::connect(&target).await {
+        Ok(upstream) => upstream,
+        Err(_) => return stream.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).await,
+    };
+    targets.lock().unwrap().push(target);
+    stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+    tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+    Ok(())
+}
+
+/// 读取一个以长度字节开头的字段
+async fn read_socks_field(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut len = [0u8; 1];
+    stream.read_exact(&mut len).await?;
+    let mut field = vec![0u8; len[0] as usize];
+    stream.read_exact(&mut field).await?;
+    Ok(String::from_utf8_lossy(&field).into_owned())
+}
+
+/// 生成测试数据
+pub(crate) fn test_body(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// 为测试创建独立的临时目录
+pub(crate) fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("yushi-test-{}", crate::utils::new_id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,186 @@
+//! 孤立文件扫描
+//!
+//! 进程崩溃或任务被移除后，下载目录中可能留下没有任务引用的状态文件（`<dest>.json`）
+//! 和未下载完的数据文件。这里只识别能解析为下载状态的 `.json` 文件，以及与之对应、
+//! 且状态显示尚未下载完成的数据文件；任何被任务引用的路径都不会被报告。
+
+use crate::{
+    state::DownloadState,
+    types::{OrphanKind, OrphanReport},
+};
+use fs_err::tokio as fs;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+/// 转换为绝对路径，用于比较任务路径和扫描到的路径
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// 扫描目录（不递归）中的孤立文件
+///
+/// `claimed` 是仍被任务引用的路径（已通过 [`normalize`] 转换）。
+pub(crate) async fn scan_dir(dir: &Path, claimed: &HashSet<PathBuf>) -> Vec<OrphanReport> {
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_type().await.is_ok_and(|t| t.is_file()) {
+            files.push(normalize(&entry.path()));
+        }
+    }
+    files.sort();
+
+    let mut reports = Vec::new();
+    for state_path in files
+        .iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+    {
+        if claimed.contains(state_path) {
+            continue;
+        }
+        // 不是下载状态的 JSON 文件属于用户数据
+        let Ok(Some(state)) = DownloadState::load(state_path).await else {
+            continue;
+        };
+        let Ok(metadata) = fs::metadata(state_path).await else {
+            continue;
+        };
+        reports.push(OrphanReport {
+            path: state_path.clone(),
+            kind: OrphanKind::StateFile,
+            size: metadata.len(),
+            url: state.url.clone(),
+        });
+
+        // 所有分块都已完成时数据文件可能是完整的，只清理状态文件
+        let finished = !state.is_streaming && state.chunks.iter().all(|c| c.is_finished);
+        if finished {
+            continue;
+        }
+        let candidates: Vec<_> = files
+            .iter()
+            .filter(|p| *p != state_path && p.with_extension("json") == *state_path)
+            .collect();
+        // 多个文件对应同一个状态文件时无法确定是哪一个，全部保留
+        let [data_path] = candidates[..] else {
+            continue;
+        };
+        if claimed.contains(data_path) {
+            continue;
+        }
+        let Ok(metadata) = fs::metadata(data_path).await else {
+            continue;
+        };
+        // 比状态记录的大小还大的文件不是这次下载写入的
+        if state.total_size.is_some_and(|total| metadata.len() > total) {
+            continue;
+        }
+        reports.push(OrphanReport {
+            path: data_path.clone(),
+            kind: OrphanKind::PartialFile,
+            size: metadata.len(),
+            url: state.url,
+        });
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock_server::temp_dir, state::ChunkState};
+
+    async fn write_state(path: &Path, total: u64, finished: bool) {
+        let state = DownloadState {
+            url: "http://example.com/file.bin".to_string(),
+            total_size: Some(total),
+            chunks: vec![ChunkState {
+                index: 0,
+                start: 0,
+                end: total - 1,
+                current: if finished { total } else { 0 },
+                is_finished: finished,
+                digest: None,
+            }],
+            is_streaming: false,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 0,
+            reported_prefix: 0,
+        };
+        state.save(path).await.unwrap();
+    }
+
+    fn kinds(reports: &[OrphanReport]) -> Vec<(String, OrphanKind)> {
+        reports
+            .iter()
+            .map(|r| {
+                let name = r.path.file_name().unwrap().to_string_lossy().to_string();
+                (name, r.kind)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_scan_matches_only_download_state() {
+        let dir = temp_dir();
+        // 未完成的下载：状态文件和预分配的数据文件
+        write_state(&dir.join("partial.json"), 1000, false).await;
+        std::fs::write(dir.join("partial.bin"), vec![0u8; 1000]).unwrap();
+        // 已全部完成的状态：只报告状态文件
+        write_state(&dir.join("done.json"), 10, true).await;
+        std::fs::write(dir.join("done.bin"), b"0123456789").unwrap();
+        // 用户自己的 JSON 文件和普通文件
+        std::fs::write(dir.join("config.json"), r#"{"url": "x"}"#).unwrap();
+        std::fs::write(dir.join("config.txt"), b"hello").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"hello").unwrap();
+        // 比状态记录的大小还大的文件
+        write_state(&dir.join("big.json"), 10, false).await;
+        std::fs::write(dir.join("big.iso"), vec![1u8; 100]).unwrap();
+        // 多个文件对应同一个状态文件
+        write_state(&dir.join("twin.json"), 10, false).await;
+        std::fs::write(dir.join("twin.a"), b"a").unwrap();
+        std::fs::write(dir.join("twin.b"), b"b").unwrap();
+
+        let reports = scan_dir(&dir, &HashSet::new()).await;
+        assert_eq!(
+            kinds(&reports),
+            vec![
+                ("big.json".to_string(), OrphanKind::StateFile),
+                ("done.json".to_string(), OrphanKind::StateFile),
+                ("partial.json".to_string(), OrphanKind::StateFile),
+                ("partial.bin".to_string(), OrphanKind::PartialFile),
+                ("twin.json".to_string(), OrphanKind::StateFile),
+            ]
+        );
+        assert_eq!(reports[3].size, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_scan_skips_claimed_paths() {
+        let dir = temp_dir();
+        write_state(&dir.join("a.json"), 100, false).await;
+        std::fs::write(dir.join("a.bin"), vec![0u8; 100]).unwrap();
+        write_state(&dir.join("b.json"), 100, false).await;
+        std::fs::write(dir.join("b.bin"), vec![0u8; 100]).unwrap();
+
+        // a 的状态文件和数据文件都被引用；b 只有数据文件被引用（例如已完成的任务）
+        let claimed: HashSet<_> = [
+            normalize(&dir.join("a.json")),
+            normalize(&dir.join("a.bin")),
+            normalize(&dir.join("b.bin")),
+        ]
+        .into_iter()
+        .collect();
+        let reports = scan_dir(&dir, &claimed).await;
+        assert_eq!(
+            kinds(&reports),
+            vec![("b.json".to_string(), OrphanKind::StateFile)]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! 磁盘写入压力检测
+//!
+//! 记录每次写入存储后端的耗时，平均耗时过高时通过总速度限制器临时降低下载速度，
+//! 让下载不再比磁盘写得快，并发送 [`GlobalEvent::DiskPressure`] 说明变慢的原因。
+//! 调整规则见 [`DiskPressureConfig`]。
+
+use crate::{
+    event::EventSender,
+    types::{DiskPressureConfig, DownloaderEvent, GlobalEvent},
+    utils::SharedSpeedLimiter,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// 指数移动平均中新样本的权重
+const SMOOTHING: f64 = 0.2;
+/// 恢复时每次提高的倍数
+const RECOVERY_FACTOR: f64 = 1.5;
+
+#[derive(Debug)]
+pub(crate) struct DiskPressure {
+    config: DiskPressureConfig,
+    limiter: Arc<SharedSpeedLimiter>,
+    events: EventSender,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// 写入耗时的指数移动平均（毫秒）
+    latency_ms: f64,
+    /// 当前的限速，None 表示没有限速
+    throttled_to: Option<u64>,
+    /// 开始限速前的写入速度，恢复到这个速度时解除限速
+    baseline: u64,
+    /// 本轮统计以来写入的字节数
+    bytes: u64,
+    /// 本轮统计开始的时间，第一次写入前为 None
+    since: Option<Instant>,
+}
+
+impl DiskPressure {
+    pub fn new(
+        config: DiskPressureConfig,
+        limiter: Arc<SharedSpeedLimiter>,
+        events: EventSender,
+    ) -> Self {
+        Self {
+            config,
+            limiter,
+            events,
+            state: Mutex::new(State {
+                latency_ms: 0.0,
+                throttled_to: None,
+                baseline: 0,
+                bytes: 0,
+                since: None,
+            }),
+        }
+    }
+
+    /// 记录一次写入，必要时调整限速
+    pub fn record(&self, bytes: u64, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let sample = elapsed.as_secs_f64() * 1000.0;
+        state.latency_ms += (sample - state.latency_ms) * SMOOTHING;
+        state.bytes += bytes;
+        let now = Instant::now();
+        let since = *state
+            .since
+            .get_or_insert_with(|| now.checked_sub(elapsed).unwrap_or(now));
+        let window = now - since;
+        if window < self.config.adjust_interval {
+            return;
+        }
+        let rate = (state.bytes as f64 / window.as_secs_f64()) as u64;
+        state.bytes = 0;
+        state.since = Some(now);
+
+        let threshold = self.config.latency_threshold.as_secs_f64() * 1000.0;
+        let next = if state.latency_ms > threshold {
+            let current = match state.throttled_to {
+                Some(throttle) => throttle.min(rate),
+                None => {
+                    state.baseline = rate;
+                    rate
+                }
+            };
+            Some((current / 2).max(self.config.min_rate))
+        } else if state.latency_ms < threshold / 2.0 {
+            state.throttled_to.and_then(|throttle| {
+                let raised = (throttle as f64 * RECOVERY_FACTOR) as u64;
+                (raised < state.baseline).then_some(raised)
+            })
+        } else {
+            state.throttled_to
+        };
+        if next == state.throttled_to {
+            return;
+        }
+
+        state.throttled_to = next;
+        self.limiter.set_throttle(next);
+        let _ = self
+            .events
+            .send(DownloaderEvent::Global(GlobalEvent::DiskPressure {
+                latency_ms: state.latency_ms.round() as u64,
+                throttled_to: next,
+            }));
+    }
+}
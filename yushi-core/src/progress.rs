@@ -0,0 +1,219 @@
+//! 任务进度计数器
+//!
+//! 每个任务对应一组原子计数器，由进度监听器和分块下载线程直接写入，
+//! 轮询方通过 [`ProgressRegistry::snapshot`] 读取，不会与任务表的写锁竞争。
+
+use crate::types::{ChunkSummary, ProgressSnapshot, Task, TaskPriority, TaskStatus};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// `eta` 未知时的取值
+const ETA_UNKNOWN: u64 = u64::MAX;
+
+/// 没有收到数据超过该时间后，快照中开始报告停滞
+const STALL_HINT: Duration = Duration::from_secs(5);
+
+/// 单个任务的进度计数器
+#[derive(Debug)]
+pub(crate) struct ProgressCounters {
+    downloaded: AtomicU64,
+    total: AtomicU64,
+    speed: AtomicU64,
+    eta: AtomicU64,
+    verified: AtomicU64,
+    status: AtomicU8,
+    chunks_total: AtomicUsize,
+    chunks_finished: AtomicUsize,
+    connections: AtomicUsize,
+    /// 任务优先级，创建后不再变化
+    priority: TaskPriority,
+    /// 计算活动时间的起点
+    created: Instant,
+    /// 最近一次收到数据时距 `created` 的毫秒数
+    last_activity_ms: AtomicU64,
+}
+
+impl ProgressCounters {
+    fn new(task: &Task) -> Self {
+        let counters = Self {
+            downloaded: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            speed: AtomicU64::new(0),
+            eta: AtomicU64::new(ETA_UNKNOWN),
+            verified: AtomicU64::new(0),
+            status: AtomicU8::new(0),
+            chunks_total: AtomicUsize::new(0),
+            chunks_finished: AtomicUsize::new(0),
+            connections: AtomicUsize::new(0),
+            priority: task.priority,
+            created: Instant::now(),
+            last_activity_ms: AtomicU64::new(0),
+        };
+        counters.sync(task);
+        counters
+    }
+
+    /// 用任务记录覆盖计数器（分块信息除外）
+    fn sync(&self, task: &Task) {
+        self.downloaded.store(task.downloaded, Ordering::Relaxed);
+        self.total.store(task.total_size, Ordering::Relaxed);
+        self.speed.store(task.speed, Ordering::Relaxed);
+        self.set_eta(task.eta);
+        self.verified.store(task.verified, Ordering::Relaxed);
+        self.set_status(task.status);
+        self.connections
+            .store(task.active_connections, Ordering::Relaxed);
+        self.touch();
+    }
+
+    fn set_status(&self, status: TaskStatus) {
+        self.status.store(status_to_u8(status), Ordering::Relaxed);
+    }
+
+    pub fn set_total(&self, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    /// 更新下载进度，同时记录一次活动
+    pub fn set_downloaded(&self, downloaded: u64, speed: u64, eta: Option<u64>) {
+        self.downloaded.store(downloaded, Ordering::Relaxed);
+        self.speed.store(speed, Ordering::Relaxed);
+        self.set_eta(eta);
+        self.touch();
+    }
+
+    /// 记录一次活动（收到数据或开始新的连接）
+    pub fn touch(&self) {
+        let elapsed = self.created.elapsed().as_millis() as u64;
+        self.last_activity_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// 距最近一次活动的时间
+    pub fn idle(&self) -> Duration {
+        let last = Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed));
+        self.created.elapsed().saturating_sub(last)
+    }
+
+    #[cfg(feature = "checksums")]
+    pub fn set_verified(&self, verified: u64) {
+        self.verified.store(verified, Ordering::Relaxed);
+    }
+
+    /// 设置分块总数和已完成的分块数
+    pub fn set_chunks(&self, total: usize, finished: usize) {
+        self.chunks_total.store(total, Ordering::Relaxed);
+        self.chunks_finished.store(finished, Ordering::Relaxed);
+    }
+
+    pub fn set_connections(&self, connections: usize) {
+        self.connections.store(connections, Ordering::Relaxed);
+    }
+
+    pub fn chunk_finished(&self) {
+        self.chunks_finished.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn set_eta(&self, eta: Option<u64>) {
+        self.eta
+            .store(eta.unwrap_or(ETA_UNKNOWN), Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, task_id: &str) -> ProgressSnapshot {
+        let eta = self.eta.load(Ordering::Relaxed);
+        let status = status_from_u8(self.status.load(Ordering::Relaxed));
+        let idle = self.idle();
+        ProgressSnapshot {
+            task_id: task_id.to_string(),
+            downloaded: self.downloaded.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            speed: self.speed.load(Ordering::Relaxed),
+            eta: (eta != ETA_UNKNOWN).then_some(eta),
+            verified: self.verified.load(Ordering::Relaxed),
+            status,
+            chunk_summary: ChunkSummary {
+                total: self.chunks_total.load(Ordering::Relaxed),
+                finished: self.chunks_finished.load(Ordering::Relaxed),
+            },
+            stalled_for: (status == TaskStatus::Downloading && idle >= STALL_HINT)
+                .then_some(idle.as_secs()),
+            connections: self.connections.load(Ordering::Relaxed),
+            priority: self.priority,
+        }
+    }
+}
+
+/// 所有任务的进度计数器
+///
+/// 只在添加或移除任务时获取写锁，更新计数器本身只需要读锁。
+#[derive(Debug, Default)]
+pub(crate) struct ProgressRegistry {
+    entries: RwLock<HashMap<String, Arc<ProgressCounters>>>,
+}
+
+impl ProgressRegistry {
+    /// 获取任务的计数器
+    pub fn get(&self, task_id: &str) -> Option<Arc<ProgressCounters>> {
+        self.entries.read().unwrap().get(task_id).cloned()
+    }
+
+    /// 用任务记录更新计数器，任务没有计数器时创建
+    pub fn sync(&self, task: &Task) {
+        if let Some(counters) = self.get(&task.id) {
+            counters.sync(task);
+            return;
+        }
+        self.entries
+            .write()
+            .unwrap()
+            .insert(task.id.clone(), Arc::new(ProgressCounters::new(task)));
+    }
+
+    pub fn remove(&self, task_id: &str) {
+        self.entries.write().unwrap().remove(task_id);
+    }
+
+    pub fn snapshot(&self, task_id: &str) -> Option<ProgressSnapshot> {
+        self.get(task_id).map(|c| c.snapshot(task_id))
+    }
+
+    pub fn snapshot_all(&self) -> Vec<ProgressSnapshot> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, c)| c.snapshot(id))
+            .collect()
+    }
+}
+
+fn status_to_u8(status: TaskStatus) -> u8 {
+    match status {
+        TaskStatus::Pending => 0,
+        TaskStatus::Downloading => 1,
+        TaskStatus::Verifying => 2,
+        TaskStatus::Paused => 3,
+        TaskStatus::Completed => 4,
+        TaskStatus::Failed => 5,
+        TaskStatus::Cancelled => 6,
+        TaskStatus::PartiallyCompleted => 7,
+    }
+}
+
+fn status_from_u8(value: u8) -> TaskStatus {
+    match value {
+        1 => TaskStatus::Downloading,
+        2 => TaskStatus::Verifying,
+        3 => TaskStatus::Paused,
+        4 => TaskStatus::Completed,
+        5 => TaskStatus::Failed,
+        6 => TaskStatus::Cancelled,
+        7 => TaskStatus::PartiallyCompleted,
+        _ => TaskStatus::Pending,
+    }
+}
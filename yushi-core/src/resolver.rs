@@ -0,0 +1,144 @@
+//! 按 IP 协议版本筛选和排序的域名解析
+//!
+//! 解析结果按 [`IpPolicy`] 排序或过滤后交给 HTTP 客户端。客户端依次连接返回的地址，
+//! 两种协议的地址都有时使用 Happy Eyeballs：排在前面的一种连接失败或迟迟连不上时，
+//! 同一次连接中改用另一种，不会计入下载的重试次数。
+
+use crate::{IpPolicy, i18n::message};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::{collections::HashMap, io, net::SocketAddr, sync::Arc};
+
+pub(crate) struct PolicyResolver {
+    policy: IpPolicy,
+    /// 固定的解析结果，按小写的主机名查找，不经过系统解析
+    overrides: Arc<HashMap<String, Vec<SocketAddr>>>,
+}
+
+impl PolicyResolver {
+    pub(crate) fn new(policy: IpPolicy, overrides: HashMap<String, Vec<SocketAddr>>) -> Self {
+        Self {
+            policy,
+            overrides: Arc::new(overrides),
+        }
+    }
+}
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy;
+        let overrides = Arc::clone(&self.overrides);
+        Box::pin(async move {
+            let host = name.as_str().to_ascii_lowercase();
+            let addrs = match overrides.get(&host) {
+                Some(addrs) => addrs.clone(),
+                None => tokio::net::lookup_host((host.as_str(), 0)).await?.collect(),
+            };
+            let addrs = apply_policy(policy, addrs);
+            if addrs.is_empty() {
+                let family = match policy {
+                    IpPolicy::Ipv6Only => "IPv6",
+                    _ => "IPv4",
+                };
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    message("dns.no_address", &[&host, &family]),
+                )
+                .into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// 按策略排序或过滤地址，排序是稳定的，同一协议的地址保持系统返回的顺序
+fn apply_policy(policy: IpPolicy, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    match policy {
+        IpPolicy::Auto => {}
+        IpPolicy::PreferIpv4 => addrs.sort_by_key(|addr| addr.is_ipv6()),
+        IpPolicy::PreferIpv6 => addrs.sort_by_key(|addr| addr.is_ipv4()),
+        IpPolicy::Ipv4Only => addrs.retain(|addr| addr.is_ipv4()),
+        IpPolicy::Ipv6Only => addrs.retain(|addr| addr.is_ipv6()),
+    }
+    addrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockFile, MockServer};
+    use reqwest::Client;
+    use std::time::{Duration, Instant};
+
+    /// 先返回一个不可达的 IPv6 地址，再返回本地服务器的 IPv4 地址
+    fn dual_stack(server: &MockServer) -> HashMap<String, Vec<SocketAddr>> {
+        let port = server.addr().port();
+        let blackhole = SocketAddr::new("100::1".parse().unwrap(), port);
+        [("dual.test".to_string(), vec![blackhole, server.addr()])].into()
+    }
+
+    fn client(policy: IpPolicy, server: &MockServer) -> Client {
+        Client::builder()
+            .dns_resolver(Arc::new(PolicyResolver::new(policy, dual_stack(server))))
+            .connect_timeout(Duration::from_secs(2))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_apply_policy_orders_and_filters() {
+        let v4: SocketAddr = "10.0.0.1:80".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:80".parse().unwrap();
+        let v6b: SocketAddr = "[2001:db8::2]:80".parse().unwrap();
+        let addrs = vec![v6, v4, v6b];
+
+        assert_eq!(apply_policy(IpPolicy::Auto, addrs.clone()), [v6, v4, v6b]);
+        assert_eq!(
+            apply_policy(IpPolicy::PreferIpv4, addrs.clone()),
+            [v4, v6, v6b]
+        );
+        assert_eq!(
+            apply_policy(IpPolicy::PreferIpv6, addrs.clone()),
+            [v6, v6b, v4]
+        );
+        assert_eq!(apply_policy(IpPolicy::Ipv4Only, addrs.clone()), [v4]);
+        assert_eq!(apply_policy(IpPolicy::Ipv6Only, addrs), [v6, v6b]);
+    }
+
+    #[tokio::test]
+    async fn test_broken_ipv6_falls_back_to_ipv4() {
+        let server = MockServer::start().await;
+        server.add("/file", MockFile::new(b"hello".to_vec()));
+        let url = format!("http://dual.test:{}/file", server.addr().port());
+
+        // IPv6 地址不可达时在同一次连接中改用 IPv4，不需要等到连接超时
+        for policy in [IpPolicy::Auto, IpPolicy::PreferIpv6, IpPolicy::Ipv4Only] {
+            let start = Instant::now();
+            let body = client(policy, &server)
+                .get(&url)
+                .send()
+                .await
+                .unwrap()
+                .bytes()
+                .await
+                .unwrap();
+            assert_eq!(&body[..], b"hello");
+            assert!(start.elapsed() < Duration::from_secs(2), "{policy:?}");
+        }
+
+        // 只使用 IPv6 时不会退回 IPv4
+        let result = client(IpPolicy::Ipv6Only, &server).get(&url).send().await;
+        assert!(result.unwrap_err().is_connect());
+
+        // 没有对应协议的地址时解析失败
+        let resolver = PolicyResolver::new(
+            IpPolicy::Ipv6Only,
+            [("dual.test".to_string(), vec![server.addr()])].into(),
+        );
+        let client = Client::builder()
+            .dns_resolver(Arc::new(resolver))
+            .build()
+            .unwrap();
+        let error = client.get(&url).send().await.unwrap_err();
+        assert!(error.is_connect());
+    }
+}
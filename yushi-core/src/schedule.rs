@@ -0,0 +1,138 @@
+//! 调度顺序和调度模拟
+//!
+//! 调度器和 [`YuShi::simulate_schedule`](crate::YuShi::simulate_schedule) 都按
+//! [`start_order`] 决定等待中的任务的启动顺序，模拟结果不会与实际调度不一致。
+
+use crate::types::{ScheduledSlot, Task};
+use std::cmp::Reverse;
+
+/// 剩余字节数低于这个值时认为已经下载完，避免浮点误差留下极小的余量
+const EPSILON: f64 = 0.5;
+
+/// 等待中的任务的启动顺序：优先级高的在前，同优先级先添加的在前，最后按 ID
+pub(crate) fn start_order<'a>(tasks: impl IntoIterator<Item = &'a Task>) -> Vec<&'a Task> {
+    let mut tasks: Vec<&Task> = tasks.into_iter().collect();
+    tasks.sort_by(|a, b| {
+        (Reverse(a.priority), a.created_at, &a.id).cmp(&(Reverse(b.priority), b.created_at, &b.id))
+    });
+    tasks
+}
+
+/// 模拟中的任务
+pub(crate) struct SimTask {
+    pub id: String,
+    /// 剩余的字节数，None 表示大小未知
+    pub remaining: Option<u64>,
+}
+
+/// 模拟等待中的任务的启动和完成时间
+///
+/// `running` 是已经在运行、占用名额的任务，`pending` 已按 [`start_order`] 排好。
+/// 同时运行的任务平分 `speed`（字节/秒），任务完成后立即启动下一个。大小未知的任务
+/// 一直占用名额；`speed` 为 None 时只有第一批任务的启动时间是确定的。
+/// 结果只包含 `pending` 中的任务，顺序与 `pending` 相同。
+pub(crate) fn simulate(
+    running: Vec<SimTask>,
+    pending: Vec<SimTask>,
+    slots: usize,
+    speed: Option<u64>,
+) -> Vec<ScheduledSlot> {
+    let slots = slots.max(1);
+    let mut result: Vec<ScheduledSlot> = pending
+        .iter()
+        .map(|task| ScheduledSlot {
+            task_id: task.id.clone(),
+            est_start: None,
+            est_finish: None,
+        })
+        .collect();
+    // (剩余字节数, 在 result 中的位置)
+    let mut active: Vec<(Option<f64>, Option<usize>)> = running
+        .into_iter()
+        .map(|task| (task.remaining.map(|r| r as f64), None))
+        .collect();
+    let mut queue = pending.into_iter().enumerate();
+    let mut now = 0.0_f64;
+
+    loop {
+        while active.len() < slots {
+            let Some((index, task)) = queue.next() else {
+                break;
+            };
+            result[index].est_start = Some(now.round() as u64);
+            active.push((task.remaining.map(|r| r as f64), Some(index)));
+        }
+        let Some(speed) = speed.filter(|&speed| speed > 0) else {
+            break;
+        };
+        let Some(step) = active
+            .iter()
+            .filter_map(|(remaining, _)| *remaining)
+            .min_by(f64::total_cmp)
+        else {
+            break;
+        };
+        let share = speed as f64 / active.len() as f64;
+        now += step / share;
+        active.retain_mut(|(remaining, index)| {
+            let Some(left) = remaining else {
+                return true;
+            };
+            *left -= step;
+            if *left > EPSILON {
+                return true;
+            }
+            if let Some(index) = index {
+                result[*index].est_finish = Some(now.round() as u64);
+            }
+            false
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sim(id: &str, remaining: Option<u64>) -> SimTask {
+        SimTask {
+            id: id.to_string(),
+            remaining,
+        }
+    }
+
+    fn times(slots: &[ScheduledSlot]) -> Vec<(Option<u64>, Option<u64>)> {
+        slots.iter().map(|s| (s.est_start, s.est_finish)).collect()
+    }
+
+    #[test]
+    fn test_simulate_shares_speed_between_slots() {
+        // 两个名额，100 B/s：a 和 b 各 50 B/s，a 在 2 秒时完成，c 接着开始
+        let result = simulate(
+            vec![],
+            vec![
+                sim("a", Some(100)),
+                sim("b", Some(300)),
+                sim("c", Some(100)),
+            ],
+            2,
+            Some(100),
+        );
+        assert_eq!(
+            times(&result),
+            [(Some(0), Some(2)), (Some(0), Some(5)), (Some(2), Some(4))]
+        );
+    }
+
+    #[test]
+    fn test_simulate_unknown_sizes_and_speed() {
+        // 运行中的任务大小未知，一直占用唯一的名额
+        let result = simulate(vec![sim("r", None)], vec![sim("a", Some(10))], 1, Some(100));
+        assert_eq!(times(&result), [(None, None)]);
+
+        // 不知道速度时只能确定第一批任务的启动时间
+        let result = simulate(vec![], vec![sim("a", Some(10)), sim("b", None)], 1, None);
+        assert_eq!(times(&result), [(Some(0), None), (None, None)]);
+    }
+}
@@ -0,0 +1,497 @@
+//! 存储后端
+//!
+//! 下载得到的字节通过 [`StorageSink`] 写入目标存储。默认实现为写入本地文件的
+//! [`FileSink`]，另外提供保存在内存中的 [`MemorySink`]（主要用于测试）、只统计字节数的
+//! [`NullSink`]（用于测速）和按顺序写入任意 `AsyncWrite`（例如标准输出）的 [`WriterSink`]。
+//!
+//! # 接入对象存储（S3 / MinIO）
+//!
+//! 对象存储一般不支持随机位置写入，实现时让 `supports_random_access` 返回 `false`，
+//! 核心会自动退回单连接流式下载，数据按偏移递增的顺序到达 `write_at`：
+//!
+//! - `open`：发起 multipart upload
+//! - `write_at`：缓冲数据，每满一个 part（例如 8 MB）上传一次
+//! - `flush`：可以为空操作
+//! - `finalize`：上传剩余数据并完成 multipart upload
+//! - `abort`：中止 multipart upload，清理已上传的 part
+//!
+//! 然后通过 [`TaskOptions::sink`](crate::TaskOptions::sink) 为任务指定该后端。
+
+#[cfg(feature = "checksums")]
+use crate::{ChecksumType, i18n::message, utils::Hasher};
+use crate::{Error, PreallocateMode, Result};
+use fs_err::tokio as fs;
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::Mutex,
+};
+
+/// 存储后端操作返回的 Future
+pub type SinkFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// 下载数据的存储后端
+pub trait StorageSink: Send + Sync {
+    /// 是否支持随机位置写入（分块并发下载需要）
+    fn supports_random_access(&self) -> bool;
+
+    /// 打开存储
+    ///
+    /// * `total_size` - 已知的文件总大小
+    /// * `resume` - 是否为断点续传（为 `true` 时必须保留已写入的数据）
+    fn open(&self, total_size: Option<u64>, resume: bool) -> SinkFuture<'_, ()>;
+
+    /// 在指定偏移处写入数据
+    fn write_at<'a>(&'a self, offset: u64, data: &'a [u8]) -> SinkFuture<'a, ()>;
+
+    /// 将缓冲的数据落盘
+    fn flush(&self) -> SinkFuture<'_, ()>;
+
+    /// 下载完成，提交数据
+    fn finalize(&self) -> SinkFuture<'_, ()>;
+
+    /// 放弃下载，清理已写入的数据
+    fn abort(&self) -> SinkFuture<'_, ()>;
+
+    /// 上次写入留下的连续数据长度，用于从中间继续流式下载
+    ///
+    /// 返回 `Some(len)` 时，核心可能以 `resume = true` 打开存储，从 `len` 处继续写入。
+    /// 默认返回 None，每次都从头下载。
+    fn resumable_len(&self) -> SinkFuture<'_, Option<u64>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// 本地文件存储（默认）
+///
+/// 各连接按偏移直接写入同一个文件句柄（`pwrite`），互不等待，也不需要移动文件位置。
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    preallocate: PreallocateMode,
+    /// 只在打开和关闭文件时短暂持有
+    file: Mutex<Option<Arc<fs_err::File>>>,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            preallocate: PreallocateMode::default(),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// 设置新建文件时预先分配空间的方式，默认为 [`PreallocateMode::Sparse`]
+    pub fn with_preallocate(mut self, mode: PreallocateMode) -> Self {
+        self.preallocate = mode;
+        self
+    }
+
+    /// 目标文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 打开的文件，没有调用 `open` 时打开已有文件（不存在时创建）
+    async fn handle(&self) -> Result<Arc<fs_err::File>> {
+        let mut guard = self.file.lock().await;
+        if let Some(file) = guard.as_ref() {
+            return Ok(Arc::clone(file));
+        }
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)
+            .await?;
+        let file = Arc::new(file.into_std().await);
+        *guard = Some(Arc::clone(&file));
+        Ok(file)
+    }
+}
+
+impl StorageSink for FileSink {
+    fn supports_random_access(&self) -> bool {
+        true
+    }
+
+    fn open(&self, total_size: Option<u64>, resume: bool) -> SinkFuture<'_, ()> {
+        Box::pin(async move {
+            let mut guard = self.file.lock().await;
+            let file = if resume && self.path.exists() {
+                fs::OpenOptions::new().write(true).open(&self.path).await?
+            } else {
+                let file = fs::File::create(&self.path).await?;
+                if let Some(size) = total_size {
+                    preallocate(&file, size, self.preallocate).await?;
+                }
+                file
+            };
+            *guard = Some(Arc::new(file.into_std().await));
+            Ok(())
+        })
+    }
+
+    fn write_at<'a>(&'a self, offset: u64, data: &'a [u8]) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            let file = self.handle().await?;
+            let data = data.to_vec();
+            tokio::task::spawn_blocking(move || write_all_at(&file, &data, offset))
+                .await
+                .map_err(std::io::Error::other)??;
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> SinkFuture<'_, ()> {
+        // 按偏移写入直接交给系统，没有自己的缓冲
+        Box::pin(async { Ok(()) })
+    }
+
+    fn finalize(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async move {
+            self.file.lock().await.take();
+            Ok(())
+        })
+    }
+
+    fn abort(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async move {
+            self.file.lock().await.take();
+            match fs::remove_file(&self.path).await {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn resumable_len(&self) -> SinkFuture<'_, Option<u64>> {
+        Box::pin(async move {
+            match fs::metadata(&self.path).await {
+                Ok(meta) if meta.len() > 0 => Ok(Some(meta.len())),
+                Ok(_) => Ok(None),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+}
+
+/// 在 `offset` 处写入全部数据
+fn write_all_at(file: &fs_err::File, mut data: &[u8], mut offset: u64) -> std::io::Result<()> {
+    #[cfg(unix)]
+    use fs_err::os::unix::fs::FileExt;
+    #[cfg(windows)]
+    use fs_err::os::windows::fs::FileExt;
+
+    while !data.is_empty() {
+        #[cfg(unix)]
+        let written = file.write_at(data, offset)?;
+        #[cfg(windows)]
+        let written = file.seek_write(data, offset)?;
+        if written == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        data = &data[written..];
+        offset += written as u64;
+    }
+    Ok(())
+}
+
+/// 按 `mode` 为新建的文件分配 `size` 字节
+async fn preallocate(file: &fs::File, size: u64, mode: PreallocateMode) -> Result<()> {
+    match mode {
+        PreallocateMode::Full => {
+            // 文件系统不支持时（例如一些网络文件系统）退回只设置长度
+            if allocate(file, size).await.is_err() {
+                file.set_len(size).await?;
+            }
+        }
+        PreallocateMode::Sparse => file.set_len(size).await?,
+        PreallocateMode::None => {}
+    }
+    Ok(())
+}
+
+/// 使用 `fallocate` 分配磁盘空间
+#[cfg(target_os = "linux")]
+async fn allocate(file: &fs::File, size: u64) -> std::io::Result<()> {
+    let file = file.try_clone().await?.into_std().await;
+    tokio::task::spawn_blocking(move || fs4::FileExt::allocate(file.file(), size))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// 其他平台没有统一的分配接口，只设置长度
+#[cfg(not(target_os = "linux"))]
+async fn allocate(file: &fs::File, size: u64) -> std::io::Result<()> {
+    file.set_len(size).await
+}
+
+/// 内存存储
+///
+/// 数据保存在内存中，主要用于测试；也可以通过 [`MemorySink::sequential`]
+/// 模拟不支持随机写入的后端。
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    data: std::sync::Mutex<Vec<u8>>,
+    sequential: bool,
+    finalized: AtomicBool,
+}
+
+impl MemorySink {
+    /// 创建支持随机写入的内存存储
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 创建只支持顺序写入的内存存储
+    pub fn sequential() -> Self {
+        Self {
+            sequential: true,
+            ..Self::default()
+        }
+    }
+
+    /// 获取已写入数据的副本
+    pub fn data(&self) -> Vec<u8> {
+        self.data.lock().unwrap().clone()
+    }
+
+    /// 是否已提交
+    pub fn is_finalized(&self) -> bool {
+        self.finalized.load(Ordering::SeqCst)
+    }
+}
+
+impl StorageSink for MemorySink {
+    fn supports_random_access(&self) -> bool {
+        !self.sequential
+    }
+
+    fn open(&self, total_size: Option<u64>, resume: bool) -> SinkFuture<'_, ()> {
+        Box::pin(async move {
+            let mut data = self.data.lock().unwrap();
+            if !resume {
+                data.clear();
+            }
+            if let Some(size) = total_size
+                && !self.sequential
+            {
+                data.resize(size as usize, 0);
+            }
+            self.finalized.store(false, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+
+    fn write_at<'a>(&'a self, offset: u64, bytes: &'a [u8]) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            let mut data = self.data.lock().unwrap();
+            let offset = offset as usize;
+            if self.sequential && offset != data.len() {
+                return Err(Error::IOError(format!(
+                    "sequential sink expected offset {}, got {}",
+                    data.len(),
+                    offset
+                )));
+            }
+            let end = offset + bytes.len();
+            if data.len() < end {
+                data.resize(end, 0);
+            }
+            data[offset..end].copy_from_slice(bytes);
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn finalize(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async move {
+            self.finalized.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+
+    fn abort(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async move {
+            self.data.lock().unwrap().clear();
+            Ok(())
+        })
+    }
+}
+
+/// 丢弃数据、只统计字节数的存储后端
+#[derive(Debug, Default)]
+pub struct NullSink {
+    bytes: AtomicU64,
+}
+
+impl NullSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 已写入的字节数
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+impl StorageSink for NullSink {
+    fn supports_random_access(&self) -> bool {
+        true
+    }
+
+    fn open(&self, _total_size: Option<u64>, _resume: bool) -> SinkFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn write_at<'a>(&'a self, _offset: u64, data: &'a [u8]) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            self.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn finalize(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn abort(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// 按顺序写入任意 [`AsyncWrite`] 的存储后端，例如标准输出或管道
+///
+/// 只支持顺序写入，核心会使用单连接流式下载。写出的数据无法撤回，因此不能断点续传
+/// 或从头重试：已经写出数据后再次打开会失败，`abort` 只是停止写入。
+///
+/// 设置了校验时边写边计算哈希，`finalize` 时不匹配返回 [`Error::ChecksumMismatch`]。
+pub struct WriterSink<W> {
+    state: Mutex<WriterState<W>>,
+    #[cfg(feature = "checksums")]
+    checksum: Option<ChecksumType>,
+}
+
+struct WriterState<W> {
+    writer: W,
+    written: u64,
+    #[cfg(feature = "checksums")]
+    hasher: Option<Hasher>,
+}
+
+impl<W: AsyncWrite + Unpin + Send> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            state: Mutex::new(WriterState {
+                writer,
+                written: 0,
+                #[cfg(feature = "checksums")]
+                hasher: None,
+            }),
+            #[cfg(feature = "checksums")]
+            checksum: None,
+        }
+    }
+
+    /// 写入的同时校验数据
+    #[cfg(feature = "checksums")]
+    pub fn with_checksum(mut self, checksum: ChecksumType) -> Self {
+        self.state.get_mut().hasher = Some(Hasher::new(&checksum));
+        self.checksum = Some(checksum);
+        self
+    }
+
+    /// 已写出的字节数
+    pub async fn written(&self) -> u64 {
+        self.state.lock().await.written
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> StorageSink for WriterSink<W> {
+    fn supports_random_access(&self) -> bool {
+        false
+    }
+
+    fn open(&self, _total_size: Option<u64>, _resume: bool) -> SinkFuture<'_, ()> {
+        Box::pin(async move {
+            let written = self.state.lock().await.written;
+            if written > 0 {
+                return Err(Error::IOError(format!(
+                    "writer sink cannot restart after {} bytes were written",
+                    written
+                )));
+            }
+            Ok(())
+        })
+    }
+
+    fn write_at<'a>(&'a self, offset: u64, data: &'a [u8]) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+            if offset != state.written {
+                return Err(Error::IOError(format!(
+                    "writer sink expected offset {}, got {}",
+                    state.written, offset
+                )));
+            }
+            state.writer.write_all(data).await?;
+            #[cfg(feature = "checksums")]
+            if let Some(hasher) = &mut state.hasher {
+                hasher.update(data);
+            }
+            state.written += data.len() as u64;
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async move {
+            self.state.lock().await.writer.flush().await?;
+            Ok(())
+        })
+    }
+
+    fn finalize(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+            state.writer.flush().await?;
+            #[cfg(feature = "checksums")]
+            if let (Some(hasher), Some(checksum)) = (state.hasher.take(), &self.checksum)
+                && !hasher.matches(checksum)
+            {
+                return Err(Error::ChecksumMismatch(message(
+                    "verify.stream_mismatch",
+                    &[&state.written],
+                )));
+            }
+            Ok(())
+        })
+    }
+
+    fn abort(&self) -> SinkFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// 共享的存储后端
+pub type SharedSink = Arc<dyn StorageSink>;
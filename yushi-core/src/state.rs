@@ -1,7 +1,20 @@
-use crate::{Result, types::Task};
+use crate::{
+    Result,
+    event::current_timestamp_ms,
+    types::{ChunkIssue, PersistCallback, RemoteInfo, Task, TaskGroup},
+};
 use fs_err::tokio as fs;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{io::AsyncWriteExt, sync::mpsc};
 
 // ==================== 内部状态类型 ====================
 
@@ -13,6 +26,9 @@ pub(crate) struct ChunkState {
     pub end: u64,
     pub current: u64,
     pub is_finished: bool,
+    /// 分块数据的 SHA-256（下载过程中计算，从分块中间续传的分块没有摘要）
+    #[serde(default)]
+    pub digest: Option<String>,
 }
 
 /// 单文件下载状态
@@ -24,9 +40,94 @@ pub(crate) struct FileDownloadState {
     pub chunks: Vec<ChunkState>,
     /// 是否为流式下载模式
     pub is_streaming: bool,
+    /// 下载范围在远程文件中的起始位置，分块位置都相对于它
+    #[serde(default)]
+    pub offset: u64,
+    /// 开始下载时远程文件的 `ETag`，续传前用来判断远程文件是否变化
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// 开始下载时远程文件的 `Last-Modified`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// 流式下载已写入存储后端的字节数，定期保存，可能比实际写入的少
+    #[serde(default)]
+    pub streamed: u64,
+    /// 最近一次报告的连续前缀
+    #[serde(skip)]
+    pub reported_prefix: u64,
 }
 
 impl FileDownloadState {
+    /// 从文件开头起连续下载完成的字节数（按已交给存储后端的数据计算）
+    pub fn contiguous_prefix(&self) -> u64 {
+        match self.chunks.iter().find(|c| !c.is_finished) {
+            Some(chunk) => chunk.current,
+            None => self.chunks.last().map_or(0, |c| c.end + 1),
+        }
+    }
+
+    /// 远程文件与开始下载时是否不同
+    ///
+    /// 两边都有 `ETag` 时只比较 `ETag`，否则两边都有 `Last-Modified` 时比较
+    /// `Last-Modified`；没有可比较的信息时认为没有变化。
+    pub fn remote_changed(&self, remote: &RemoteInfo) -> bool {
+        match (&self.etag, &remote.etag) {
+            (Some(saved), Some(current)) => saved != current,
+            _ => matches!(
+                (&self.last_modified, &remote.last_modified),
+                (Some(saved), Some(current)) if saved != current
+            ),
+        }
+    }
+
+    /// 检查分块状态是否自洽：分块范围连续且不重叠、覆盖整个文件，写入位置在分块范围内
+    pub fn issues(&self) -> Vec<ChunkIssue> {
+        let mut issues = Vec::new();
+        if self.is_streaming {
+            return issues;
+        }
+
+        let mut covered = 0;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if chunk.start > chunk.end {
+                issues.push(ChunkIssue::InvalidRange { index: chunk.index });
+                continue;
+            }
+            covered += chunk.end - chunk.start + 1;
+            if chunk.current < chunk.start || chunk.current > chunk.end + 1 {
+                issues.push(ChunkIssue::CurrentOutOfRange {
+                    index: chunk.index,
+                    current: chunk.current,
+                });
+            } else if chunk.is_finished && chunk.current != chunk.end + 1 {
+                issues.push(ChunkIssue::FinishedIncomplete { index: chunk.index });
+            }
+
+            let Some(next) = self.chunks.get(i + 1) else {
+                continue;
+            };
+            if next.start > chunk.end + 1 {
+                issues.push(ChunkIssue::Gap {
+                    after: chunk.index,
+                    start: chunk.end + 1,
+                    end: next.start - 1,
+                });
+            } else if next.start <= chunk.end {
+                issues.push(ChunkIssue::Overlap {
+                    first: chunk.index,
+                    second: next.index,
+                });
+            }
+        }
+
+        if let Some(expected) = self.total_size
+            && expected != covered
+        {
+            issues.push(ChunkIssue::SizeMismatch { expected, covered });
+        }
+        issues
+    }
+
     /// 保存状态到文件
     pub async fn save(&self, path: &Path) -> Result<()> {
         let data = serde_json::to_string(self)?;
@@ -46,14 +147,19 @@ impl FileDownloadState {
     }
 }
 
-/// 下载器状态（队列状态）
+/// 下载器状态（队列状态），即队列状态文件的内容
+///
+/// 任务和任务组的顺序没有意义。
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct DownloaderState {
+pub struct DownloaderState {
     /// 版本号（用于状态文件兼容性）
     #[serde(default = "default_version")]
     pub version: String,
     /// 任务列表
     pub tasks: Vec<Task>,
+    /// 任务组列表
+    #[serde(default)]
+    pub groups: Vec<TaskGroup>,
     /// 创建时间戳
     #[serde(default = "current_timestamp")]
     pub created_at: u64,
@@ -64,18 +170,19 @@ pub(crate) struct DownloaderState {
 
 impl DownloaderState {
     /// 创建新的下载器状态
-    pub fn new() -> Self {
+    pub(crate) fn new() -> Self {
         let now = current_timestamp();
         Self {
             version: default_version(),
             tasks: Vec::new(),
+            groups: Vec::new(),
             created_at: now,
             updated_at: now,
         }
     }
 
     /// 保存下载器状态到文件
-    pub async fn save(&self, path: &Path) -> Result<()> {
+    pub(crate) async fn save(&self, path: &Path) -> Result<()> {
         let mut state = self.clone();
         state.updated_at = current_timestamp();
 
@@ -85,7 +192,7 @@ impl DownloaderState {
     }
 
     /// 从文件加载下载器状态
-    pub async fn load(path: &Path) -> Result<Option<Self>> {
+    pub(crate) async fn load(path: &Path) -> Result<Option<Self>> {
         if !path.exists() {
             return Ok(None);
         }
@@ -102,15 +209,458 @@ impl Default for DownloaderState {
     }
 }
 
+// ==================== 队列持久化 ====================
+
+/// 日志条目数达到该值时合并到主文件
+const JOURNAL_COMPACT_THRESHOLD: usize = 1000;
+
+/// 队列状态文件对应的增量日志路径（`queue.json` -> `queue.journal.jsonl`）
+pub fn journal_path(queue_state_path: &Path) -> PathBuf {
+    queue_state_path.with_extension("journal.jsonl")
+}
+
+/// 增量日志条目
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op")]
+enum JournalEntry {
+    UpsertTask { task: Box<Task> },
+    RemoveTask { id: String },
+    UpsertGroup { group: TaskGroup },
+}
+
+/// 待写入的变更
+#[derive(Debug, Default)]
+struct DirtySet {
+    tasks: HashSet<String>,
+    removed_tasks: HashSet<String>,
+    groups: HashSet<String>,
+}
+
+impl DirtySet {
+    fn is_empty(&self) -> bool {
+        self.tasks.is_empty() && self.removed_tasks.is_empty() && self.groups.is_empty()
+    }
+
+    /// 放回写入失败的变更，之后的标记优先
+    fn restore(&mut self, older: DirtySet) {
+        for id in older.tasks {
+            if !self.removed_tasks.contains(&id) {
+                self.tasks.insert(id);
+            }
+        }
+        for id in older.removed_tasks {
+            if !self.tasks.contains(&id) {
+                self.removed_tasks.insert(id);
+            }
+        }
+        self.groups.extend(older.groups);
+    }
+}
+
+/// 写入频率限制的状态
+#[derive(Debug, Default)]
+struct FlushSchedule {
+    /// 最近一次开始写入的时间
+    last: Option<Instant>,
+    /// 是否已经安排了延后的写入
+    pending: bool,
+}
+
+/// [`QueueStore::schedule_flush`] 的结果
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum FlushSlot {
+    /// 立即写入
+    Now,
+    /// 等待这么久后写入，期间的变更一起写入
+    After(Duration),
+    /// 已经安排了延后的写入，这次的变更会一起写入
+    Scheduled,
+}
+
+/// 写入日志的状态
+#[derive(Debug, Default)]
+struct JournalState {
+    /// 日志中的条目数
+    entries: usize,
+    /// 本进程是否已经合并过（第一次保存总是完整写入主文件）
+    compacted: bool,
+    /// 已写入磁盘的状态，只在设置了写入回调时维护
+    persisted: Option<Persisted>,
+}
+
+/// 已写入磁盘的状态（主文件加上日志），与此时从磁盘加载得到的状态相同
+#[derive(Debug, Default)]
+struct Persisted {
+    tasks: HashMap<String, Task>,
+    groups: HashMap<String, TaskGroup>,
+}
+
+impl Persisted {
+    fn apply(&mut self, entry: &JournalEntry) {
+        match entry {
+            JournalEntry::UpsertTask { task } => {
+                self.tasks.insert(task.id.clone(), (**task).clone());
+            }
+            JournalEntry::RemoveTask { id } => {
+                self.tasks.remove(id);
+            }
+            JournalEntry::UpsertGroup { group } => {
+                self.groups.insert(group.id.clone(), group.clone());
+            }
+        }
+    }
+
+    fn snapshot(&self) -> DownloaderState {
+        let mut state = DownloaderState::new();
+        state.tasks = self.tasks.values().cloned().collect();
+        state.groups = self.groups.values().cloned().collect();
+        state
+    }
+}
+
+/// 写入回调，以及按顺序把写入的状态交给回调的后台任务
+struct PersistObserver {
+    callback: PersistCallback,
+    /// 第一次写入时创建后台任务
+    tx: Option<mpsc::UnboundedSender<DownloaderState>>,
+}
+
+impl std::fmt::Debug for PersistObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PersistObserver").finish_non_exhaustive()
+    }
+}
+
+/// 队列状态存储
+///
+/// 主文件 `queue.json` 保存完整状态，两次合并之间的变更以 JSON Lines 追加到
+/// `queue.journal.jsonl`，每次保存只序列化标记为已修改的任务和任务组。
+/// 日志条目达到阈值时合并到主文件；加载时先读主文件再按顺序应用日志，
+/// 遇到损坏（例如写到一半被中断）的行时忽略该行及之后的内容。
+///
+/// 没有路径时队列只保存在内存中，保存和加载都不做任何事。
+#[derive(Debug)]
+pub(crate) struct QueueStore {
+    /// 主文件和增量日志的路径
+    paths: Option<(PathBuf, PathBuf)>,
+    dirty: Mutex<DirtySet>,
+    schedule: Mutex<FlushSchedule>,
+    journal: tokio::sync::Mutex<JournalState>,
+    observer: Mutex<Option<PersistObserver>>,
+    /// 最近一次成功写入的时间（Unix 时间，毫秒），0 表示还没有写入过
+    last_persisted_ms: AtomicU64,
+}
+
+impl QueueStore {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            paths: path.map(|path| {
+                let journal = journal_path(&path);
+                (path, journal)
+            }),
+            dirty: Mutex::new(DirtySet::default()),
+            schedule: Mutex::new(FlushSchedule::default()),
+            journal: tokio::sync::Mutex::new(JournalState::default()),
+            observer: Mutex::new(None),
+            last_persisted_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// 设置写入回调，之后的第一次写入会完整写入主文件，以便得到磁盘上的完整状态
+    pub fn set_persist_hook(&self, callback: PersistCallback) {
+        *self.observer.lock().unwrap() = Some(PersistObserver { callback, tx: None });
+    }
+
+    /// 最近一次成功写入的时间
+    pub fn last_persisted_at(&self) -> Option<SystemTime> {
+        match self.last_persisted_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(SystemTime::UNIX_EPOCH + Duration::from_millis(ms)),
+        }
+    }
+
+    /// 是否保存到文件
+    pub fn is_persistent(&self) -> bool {
+        self.paths.is_some()
+    }
+
+    /// 按最短间隔 `interval` 安排一次写入
+    ///
+    /// 距离上次写入已超过间隔时立即写入，否则在间隔结束时写入一次，
+    /// 短时间内的多次修改只写一次。
+    pub fn schedule_flush(&self, interval: Duration) -> FlushSlot {
+        let mut schedule = self.schedule.lock().unwrap();
+        if schedule.pending {
+            return FlushSlot::Scheduled;
+        }
+        let now = Instant::now();
+        match schedule.last {
+            Some(last) if now.duration_since(last) < interval => {
+                schedule.pending = true;
+                FlushSlot::After(interval - now.duration_since(last))
+            }
+            _ => {
+                schedule.last = Some(now);
+                FlushSlot::Now
+            }
+        }
+    }
+
+    /// 开始执行延后的写入，之后的修改重新按间隔安排
+    pub fn start_scheduled_flush(&self) {
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.pending = false;
+        schedule.last = Some(Instant::now());
+    }
+
+    fn observed(&self) -> bool {
+        self.observer.lock().unwrap().is_some()
+    }
+
+    /// 记录写入时间，设置了写入回调时把写入后的状态交给回调
+    ///
+    /// 在持有日志锁时调用，回调收到状态的顺序与写入顺序相同。
+    fn persisted(&self, journal: &JournalState) {
+        self.last_persisted_ms
+            .store(current_timestamp_ms(), Ordering::Relaxed);
+        let mut observer = self.observer.lock().unwrap();
+        let (Some(observer), Some(persisted)) = (observer.as_mut(), &journal.persisted) else {
+            return;
+        };
+        let callback = &observer.callback;
+        let tx = observer.tx.get_or_insert_with(|| {
+            let (tx, mut rx) = mpsc::unbounded_channel::<DownloaderState>();
+            let callback = Arc::clone(callback);
+            tokio::spawn(async move {
+                while let Some(state) = rx.recv().await {
+                    callback(&state);
+                }
+            });
+            tx
+        });
+        let _ = tx.send(persisted.snapshot());
+    }
+
+    /// 标记任务已修改
+    pub fn mark_task(&self, id: &str) {
+        let mut dirty = self.dirty.lock().unwrap();
+        dirty.removed_tasks.remove(id);
+        dirty.tasks.insert(id.to_string());
+    }
+
+    /// 标记任务已移除
+    pub fn mark_task_removed(&self, id: &str) {
+        let mut dirty = self.dirty.lock().unwrap();
+        dirty.tasks.remove(id);
+        dirty.removed_tasks.insert(id.to_string());
+    }
+
+    /// 标记任务组已修改
+    pub fn mark_group(&self, id: &str) {
+        self.dirty.lock().unwrap().groups.insert(id.to_string());
+    }
+
+    /// 写入已标记的变更，没有变更时不做任何事
+    ///
+    /// 写入失败时保留这些标记，下次写入时重试。
+    pub async fn flush(
+        &self,
+        tasks: &HashMap<String, Task>,
+        groups: &HashMap<String, TaskGroup>,
+    ) -> Result<()> {
+        let mut journal = self.journal.lock().await;
+        let dirty = std::mem::take(&mut *self.dirty.lock().unwrap());
+        let result = self.flush_locked(&mut journal, &dirty, tasks, groups).await;
+        if result.is_err() {
+            self.dirty.lock().unwrap().restore(dirty);
+        }
+        result
+    }
+
+    async fn flush_locked(
+        &self,
+        journal: &mut JournalState,
+        dirty: &DirtySet,
+        tasks: &HashMap<String, Task>,
+        groups: &HashMap<String, TaskGroup>,
+    ) -> Result<()> {
+        let Some((_, journal_path)) = &self.paths else {
+            return Ok(());
+        };
+        let observed = self.observed();
+        if !observed {
+            journal.persisted = None;
+        }
+        if !journal.compacted || (observed && journal.persisted.is_none()) {
+            return self.compact_locked(journal, tasks, groups).await;
+        }
+        if dirty.is_empty() {
+            return Ok(());
+        }
+
+        let mut entries = Vec::new();
+        for id in &dirty.tasks {
+            if let Some(task) = tasks.get(id) {
+                entries.push(JournalEntry::UpsertTask {
+                    task: Box::new(task.clone()),
+                });
+            }
+        }
+        for id in &dirty.removed_tasks {
+            entries.push(JournalEntry::RemoveTask { id: id.clone() });
+        }
+        for id in &dirty.groups {
+            if let Some(group) = groups.get(id) {
+                entries.push(JournalEntry::UpsertGroup {
+                    group: group.clone(),
+                });
+            }
+        }
+        let mut lines = String::new();
+        for entry in &entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+
+        if let Err(e) = append(journal_path, &lines).await {
+            // 日志末尾可能留下写到一半的行，加载时会忽略它之后的内容，下次改为完整写入主文件
+            journal.compacted = false;
+            return Err(e);
+        }
+        journal.entries += entries.len();
+        if let Some(persisted) = &mut journal.persisted {
+            for entry in &entries {
+                persisted.apply(entry);
+            }
+        }
+        self.persisted(journal);
+
+        if journal.entries >= JOURNAL_COMPACT_THRESHOLD {
+            self.compact_locked(journal, tasks, groups).await?;
+        }
+        Ok(())
+    }
+
+    /// 把完整状态写入主文件并清空日志
+    pub async fn compact(
+        &self,
+        tasks: &HashMap<String, Task>,
+        groups: &HashMap<String, TaskGroup>,
+    ) -> Result<()> {
+        let mut journal = self.journal.lock().await;
+        *self.dirty.lock().unwrap() = DirtySet::default();
+        self.compact_locked(&mut journal, tasks, groups).await
+    }
+
+    async fn compact_locked(
+        &self,
+        journal: &mut JournalState,
+        tasks: &HashMap<String, Task>,
+        groups: &HashMap<String, TaskGroup>,
+    ) -> Result<()> {
+        let Some((path, journal_path)) = &self.paths else {
+            return Ok(());
+        };
+        if let Err(e) = write_full(path, journal_path, tasks, groups).await {
+            // 已清空的标记不会再写入，下次改为完整写入主文件
+            journal.compacted = false;
+            return Err(e);
+        }
+
+        journal.entries = 0;
+        journal.compacted = true;
+        journal.persisted = self.observed().then(|| Persisted {
+            tasks: tasks.clone(),
+            groups: groups.clone(),
+        });
+        self.persisted(journal);
+        Ok(())
+    }
+
+    /// 加载主文件并应用日志
+    pub async fn load(&self) -> Result<Option<DownloaderState>> {
+        let Some((path, journal_path)) = &self.paths else {
+            return Ok(None);
+        };
+        let base = DownloaderState::load(path).await?;
+        if !journal_path.exists() {
+            return Ok(base);
+        }
+
+        let mut state = base.unwrap_or_default();
+        let mut tasks: HashMap<String, Task> =
+            state.tasks.drain(..).map(|t| (t.id.clone(), t)).collect();
+        let mut groups: HashMap<String, TaskGroup> =
+            state.groups.drain(..).map(|g| (g.id.clone(), g)).collect();
+
+        let content = fs::read(journal_path).await?;
+        for line in content.split(|b| *b == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            let Ok(entry) = serde_json::from_slice::<JournalEntry>(line) else {
+                break;
+            };
+            match entry {
+                JournalEntry::UpsertTask { task } => {
+                    tasks.insert(task.id.clone(), *task);
+                }
+                JournalEntry::RemoveTask { id } => {
+                    tasks.remove(&id);
+                }
+                JournalEntry::UpsertGroup { group } => {
+                    groups.insert(group.id.clone(), group);
+                }
+            }
+        }
+
+        state.tasks = tasks.into_values().collect();
+        state.groups = groups.into_values().collect();
+        Ok(Some(state))
+    }
+}
+
 // ==================== 兼容性别名 ====================
 
 /// 下载状态（向后兼容）
 pub(crate) type DownloadState = FileDownloadState;
-/// 队列状态（向后兼容）
-pub(crate) type QueueState = DownloaderState;
 
 // ==================== 辅助函数 ====================
 
+/// 把变更追加到增量日志
+async fn append(journal_path: &Path, lines: &str) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path)
+        .await?;
+    file.write_all(lines.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// 把完整状态写入主文件并删除增量日志
+async fn write_full(
+    path: &Path,
+    journal_path: &Path,
+    tasks: &HashMap<String, Task>,
+    groups: &HashMap<String, TaskGroup>,
+) -> Result<()> {
+    let mut state = DownloaderState::new();
+    state.tasks = tasks.values().cloned().collect();
+    state.groups = groups.values().cloned().collect();
+
+    // 先写临时文件再重命名，避免主文件写到一半
+    let tmp_path = path.with_extension("json.tmp");
+    state.save(&tmp_path).await?;
+    fs::rename(&tmp_path, path).await?;
+    match fs::remove_file(journal_path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
 fn default_version() -> String {
     "1.0".to_string()
 }
@@ -121,3 +671,377 @@ pub(crate) fn current_timestamp() -> u64 {
         .unwrap_or_default()
         .as_secs()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock_server::temp_dir,
+        types::{Priority, TaskKind, TaskStatus},
+    };
+
+    fn test_task(id: &str) -> Task {
+        Task {
+            id: id.to_string(),
+            url: format!("http://example.com/{}", id),
+            dest: PathBuf::from(format!("/tmp/{}", id)),
+            status: TaskStatus::Pending,
+            total_size: 0,
+            downloaded: 0,
+            created_at: current_timestamp(),
+            error: None,
+            priority: Priority::Normal,
+            speed: 0,
+            eta: None,
+            active_connections: 0,
+            headers: HashMap::new(),
+            checksum: None,
+            group_id: None,
+            verified: 0,
+            verification_pending: false,
+            remote: None,
+            connections: None,
+            byte_range: None,
+            display_name: None,
+            note: None,
+            skip_probe: false,
+            chunk_order: None,
+            conflict: Default::default(),
+            skipped_unchanged: false,
+            archived: false,
+            archived_at: None,
+            provenance: None,
+            contiguous_prefix: 0,
+            active_duration_ms: 0,
+            peak_speed: 0,
+            average_speed: 0,
+            kind: TaskKind::Download,
+            missing_ranges: Vec::new(),
+            recent_events: Default::default(),
+            completed_link: None,
+            speed_limit: None,
+            boost_until: None,
+            deadline_ms: None,
+            resolved_url: None,
+            mirrors: Vec::new(),
+            auth: None,
+        }
+    }
+
+    fn sorted_ids(state: &DownloaderState) -> Vec<String> {
+        let mut ids: Vec<_> = state.tasks.iter().map(|t| t.id.clone()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_journal_roundtrip() {
+        let dir = temp_dir();
+        let store = QueueStore::new(Some(dir.join("queue.json")));
+        let journal_file = journal_path(&dir.join("queue.json"));
+        let groups = HashMap::new();
+        let mut tasks = HashMap::new();
+        tasks.insert("a".to_string(), test_task("a"));
+
+        // 第一次保存写入主文件
+        store.mark_task("a");
+        store.flush(&tasks, &groups).await.unwrap();
+        assert!(!journal_file.exists());
+
+        // 之后的变更只追加到日志
+        tasks.insert("b".to_string(), test_task("b"));
+        store.mark_task("b");
+        tasks.get_mut("a").unwrap().status = TaskStatus::Paused;
+        store.mark_task("a");
+        store.flush(&tasks, &groups).await.unwrap();
+        tasks.remove("b");
+        store.mark_task_removed("b");
+        store.flush(&tasks, &groups).await.unwrap();
+        let journal = std::fs::read_to_string(&journal_file).unwrap();
+        assert_eq!(journal.lines().count(), 3);
+
+        // 没有变更时不写入
+        store.flush(&tasks, &groups).await.unwrap();
+        let journal = std::fs::read_to_string(&journal_file).unwrap();
+        assert_eq!(journal.lines().count(), 3);
+
+        let state = QueueStore::new(Some(dir.join("queue.json")))
+            .load()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(sorted_ids(&state), vec!["a"]);
+        assert_eq!(state.tasks[0].status, TaskStatus::Paused);
+
+        // 合并后日志被删除，主文件包含完整状态
+        store.compact(&tasks, &groups).await.unwrap();
+        assert!(!journal_file.exists());
+        let state = DownloaderState::load(&dir.join("queue.json"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.tasks[0].status, TaskStatus::Paused);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_journal_is_ignored() {
+        let dir = temp_dir();
+        let store = QueueStore::new(Some(dir.join("queue.json")));
+        let journal_file = journal_path(&dir.join("queue.json"));
+        let groups = HashMap::new();
+        let mut tasks = HashMap::new();
+        tasks.insert("a".to_string(), test_task("a"));
+        store.flush(&tasks, &groups).await.unwrap();
+
+        tasks.insert("b".to_string(), test_task("b"));
+        store.mark_task("b");
+        store.flush(&tasks, &groups).await.unwrap();
+
+        // 模拟写到一半时进程被杀死
+        let line = serde_json::to_string(&JournalEntry::UpsertTask {
+            task: Box::new(test_task("c")),
+        })
+        .unwrap();
+        let mut journal = std::fs::read_to_string(&journal_file).unwrap();
+        journal.push_str(&line[..line.len() / 2]);
+        std::fs::write(&journal_file, journal).unwrap();
+
+        let state = QueueStore::new(Some(dir.join("queue.json")))
+            .load()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(sorted_ids(&state), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_keeps_changes() {
+        let dir = temp_dir();
+        let store = QueueStore::new(Some(dir.join("queue.json")));
+        let journal_file = journal_path(&dir.join("queue.json"));
+        let groups = HashMap::new();
+        let mut tasks = HashMap::new();
+        tasks.insert("a".to_string(), test_task("a"));
+        store.flush(&tasks, &groups).await.unwrap();
+
+        // 日志路径被目录占用，追加失败
+        std::fs::create_dir(&journal_file).unwrap();
+        tasks.insert("b".to_string(), test_task("b"));
+        store.mark_task("b");
+        assert!(store.flush(&tasks, &groups).await.is_err());
+        assert!(store.dirty.lock().unwrap().tasks.contains("b"));
+
+        // 失败期间之后的标记优先
+        store.mark_task_removed("b");
+        store.dirty.lock().unwrap().restore(DirtySet {
+            tasks: HashSet::from(["b".to_string()]),
+            ..Default::default()
+        });
+        assert!(!store.dirty.lock().unwrap().tasks.contains("b"));
+        store.mark_task("b");
+
+        std::fs::remove_dir(&journal_file).unwrap();
+        store.flush(&tasks, &groups).await.unwrap();
+        let state = QueueStore::new(Some(dir.join("queue.json")))
+            .load()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(sorted_ids(&state), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_schedule_flush_coalesces() {
+        let store = QueueStore::new(Some(temp_dir().join("queue.json")));
+        let interval = Duration::from_secs(60);
+        assert_eq!(store.schedule_flush(interval), FlushSlot::Now);
+        let FlushSlot::After(delay) = store.schedule_flush(interval) else {
+            panic!("间隔内的写入应该延后");
+        };
+        assert!(delay <= interval);
+        assert_eq!(store.schedule_flush(interval), FlushSlot::Scheduled);
+
+        // 延后的写入开始后，新的修改重新安排
+        store.start_scheduled_flush();
+        assert!(matches!(
+            store.schedule_flush(interval),
+            FlushSlot::After(_)
+        ));
+        assert_eq!(store.schedule_flush(Duration::ZERO), FlushSlot::Scheduled);
+    }
+
+    #[tokio::test]
+    async fn test_persist_hook_sees_written_states() {
+        let dir = temp_dir();
+        let path = dir.join("queue.json");
+        let store = QueueStore::new(Some(path.clone()));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        store.set_persist_hook(Arc::new(move |state: &DownloaderState| {
+            sink.lock().unwrap().push(state.clone());
+        }));
+        assert!(store.last_persisted_at().is_none());
+
+        // 任务按 ID 排序后比较，状态中的顺序没有意义
+        let normalize = |state: &DownloaderState| {
+            let mut tasks = state.tasks.clone();
+            tasks.sort_by(|a, b| a.id.cmp(&b.id));
+            serde_json::to_value(&tasks).unwrap()
+        };
+        // 每次写入后回调收到的状态与此时从磁盘加载的状态相同
+        let expect_written = async |count: usize| {
+            for _ in 0..100 {
+                if seen.lock().unwrap().len() >= count {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            let on_disk = QueueStore::new(Some(path.clone()))
+                .load()
+                .await
+                .unwrap()
+                .unwrap();
+            let seen = seen.lock().unwrap();
+            assert_eq!(seen.len(), count);
+            assert_eq!(normalize(&seen[count - 1]), normalize(&on_disk));
+        };
+
+        let groups = HashMap::new();
+        let mut tasks = HashMap::new();
+        tasks.insert("a".to_string(), test_task("a"));
+        store.mark_task("a");
+        store.flush(&tasks, &groups).await.unwrap();
+        expect_written(1).await;
+        assert!(store.last_persisted_at().is_some());
+
+        tasks.insert("b".to_string(), test_task("b"));
+        store.mark_task("b");
+        tasks.get_mut("a").unwrap().status = TaskStatus::Paused;
+        store.mark_task("a");
+        store.flush(&tasks, &groups).await.unwrap();
+        expect_written(2).await;
+
+        // 没有变更时不写入，也不调用回调
+        store.flush(&tasks, &groups).await.unwrap();
+        tasks.remove("a");
+        store.mark_task_removed("a");
+        store.flush(&tasks, &groups).await.unwrap();
+        expect_written(3).await;
+        assert_eq!(sorted_ids(&seen.lock().unwrap()[2]), vec!["b"]);
+
+        tasks.get_mut("b").unwrap().status = TaskStatus::Completed;
+        store.compact(&tasks, &groups).await.unwrap();
+        expect_written(4).await;
+
+        let seen = seen.lock().unwrap();
+        let ids: Vec<_> = seen.iter().map(sorted_ids).collect();
+        assert_eq!(ids, [vec!["a"], vec!["a", "b"], vec!["b"], vec!["b"]]);
+        assert_eq!(seen[1].tasks.len(), 2);
+        assert_eq!(seen[3].tasks[0].status, TaskStatus::Completed);
+    }
+
+    #[test]
+    fn test_state_file_skips_volatile_fields() {
+        let mut state = DownloaderState::new();
+        for i in 0..1000 {
+            let mut task = test_task(&format!("task-{i:04}"));
+            task.status = TaskStatus::Downloading;
+            task.total_size = 10 * 1024 * 1024;
+            task.downloaded = 5 * 1024 * 1024;
+            task.speed = 1_234_567;
+            task.eta = Some(42);
+            state.tasks.push(task);
+        }
+        let compact = serde_json::to_string(&state).unwrap();
+
+        // 旧版本写入的状态文件包含这些字段
+        let mut legacy: serde_json::Value = serde_json::from_str(&compact).unwrap();
+        for task in legacy["tasks"].as_array_mut().unwrap() {
+            let task = task.as_object_mut().unwrap();
+            task.insert("speed".into(), 1_234_567.into());
+            task.insert("eta".into(), 42.into());
+            task.insert("headers".into(), serde_json::json!({}));
+        }
+        let legacy = serde_json::to_string(&legacy).unwrap();
+        let saved = legacy.len() - compact.len();
+        assert!(
+            saved >= 1000 * r#","speed":1234567,"eta":42,"headers":{}"#.len(),
+            "saved {} of {} bytes",
+            saved,
+            legacy.len()
+        );
+
+        // 旧状态文件仍然可以读取，速度和剩余时间在重新加载后归零
+        let loaded: DownloaderState = serde_json::from_str(&legacy).unwrap();
+        assert_eq!(loaded.tasks.len(), 1000);
+        assert_eq!(loaded.tasks[0].speed, 0);
+        assert_eq!(loaded.tasks[0].eta, None);
+        assert_eq!(loaded.tasks[0].downloaded, 5 * 1024 * 1024);
+
+        // 列表视图仍然包含运行时的速度
+        let view = state.tasks[0].to_view();
+        assert_eq!(view.name, "task-0000");
+        assert_eq!(view.progress, Some(50.0));
+        assert_eq!(view.speed, 1_234_567);
+        assert_eq!(view.total_size.as_deref(), Some("10.00 MB"));
+    }
+
+    #[test]
+    fn test_state_issues() {
+        let chunk = |index: usize, start: u64, end: u64, current: u64, finished: bool| ChunkState {
+            index,
+            start,
+            end,
+            current,
+            is_finished: finished,
+            digest: None,
+        };
+        let mut state = FileDownloadState {
+            url: "http://example.com/a.bin".to_string(),
+            total_size: Some(300),
+            chunks: vec![
+                chunk(0, 0, 99, 100, true),
+                chunk(1, 100, 199, 150, false),
+                chunk(2, 200, 299, 200, false),
+            ],
+            is_streaming: false,
+            offset: 0,
+            etag: None,
+            last_modified: None,
+            streamed: 0,
+            reported_prefix: 0,
+        };
+        assert!(state.issues().is_empty());
+
+        state.chunks[0].current = 90;
+        state.chunks[1].current = 250;
+        state.chunks[2].start = 210;
+        state.chunks[2].current = 210;
+        assert_eq!(
+            state.issues(),
+            [
+                ChunkIssue::FinishedIncomplete { index: 0 },
+                ChunkIssue::CurrentOutOfRange {
+                    index: 1,
+                    current: 250
+                },
+                ChunkIssue::Gap {
+                    after: 1,
+                    start: 200,
+                    end: 209
+                },
+                ChunkIssue::SizeMismatch {
+                    expected: 300,
+                    covered: 290
+                },
+            ]
+        );
+
+        state.chunks[2].start = 150;
+        state.chunks[2].current = 150;
+        assert!(state.issues().contains(&ChunkIssue::Overlap {
+            first: 1,
+            second: 2
+        }));
+    }
+}
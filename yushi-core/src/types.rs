@@ -1,6 +1,20 @@
-use crate::utils::{Unit, XByte};
+use crate::{
+    credentials::Credential,
+    i18n,
+    sink::SharedSink,
+    utils::{Unit, XByte},
+};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// 队列状态写入磁盘后的回调类型，见 [`YuShi::on_persist`](crate::YuShi::on_persist)
+pub type PersistCallback = Arc<dyn Fn(&crate::state::DownloaderState) + Send + Sync>;
 
 /// 下载完成回调类型
 pub type CompletionCallback = Arc<
@@ -12,20 +26,67 @@ pub type CompletionCallback = Arc<
         + Sync,
 >;
 
+/// 刷新下载地址的回调类型，参数为任务 ID 和失效的 URL，返回新的 URL
+pub type UrlRefresher = Arc<
+    dyn Fn(
+            String,
+            String,
+        )
+            -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, String>> + Send>>
+        + Send
+        + Sync,
+>;
+
 // ==================== 枚举类型 ====================
 
 /// 任务优先级
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Default)]
+///
+/// 序列化为变体名称（`"Low"`、`"Normal"`、`"High"`）。从文本解析见 [`FromStr`] 实现，
+/// 启用 `clap` 特性时还实现了 `clap::ValueEnum`，可以直接用作命令行参数。
+///
+/// [`FromStr`]: std::str::FromStr
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Default,
+)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
 pub enum TaskPriority {
     /// 低优先级
+    #[cfg_attr(feature = "clap", value(alias = "0"))]
     Low = 0,
     #[default]
     /// 普通优先级
+    #[cfg_attr(feature = "clap", value(alias = "1"))]
     Normal = 1,
     /// 高优先级
+    #[cfg_attr(feature = "clap", value(alias = "2"))]
     High = 2,
 }
 
+impl std::str::FromStr for TaskPriority {
+    type Err = crate::Error;
+
+    /// 接受 `low`、`normal`、`high`（不区分大小写）和对应的数字 `0`、`1`、`2`
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" | "0" => Ok(Self::Low),
+            "normal" | "1" => Ok(Self::Normal),
+            "high" | "2" => Ok(Self::High),
+            _ => Err(crate::Error::InvalidPriority(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for TaskPriority {
+    /// 小写的名称，可以再由 [`str::parse`] 解析回来
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+        })
+    }
+}
+
 /// 任务状态
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskStatus {
@@ -33,6 +94,8 @@ pub enum TaskStatus {
     Pending,
     /// 正在下载
     Downloading,
+    /// 正在校验文件
+    Verifying,
     /// 已暂停
     Paused,
     /// 已完成
@@ -41,6 +104,23 @@ pub enum TaskStatus {
     Failed,
     /// 已取消
     Cancelled,
+    /// 下载结束，但有分块重试次数用尽，文件中留有空洞（见 [`Task::missing_ranges`]）
+    ///
+    /// 只在 [`Config::on_chunk_exhausted`] 为 [`ChunkFailurePolicy::CompleteWithHoles`] 时出现。
+    /// 不进行文件校验，文件保留在下载位置，重试时只重新下载缺失的范围。
+    PartiallyCompleted,
+}
+
+/// 任务类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskKind {
+    /// 下载文件，设置了校验和时下载后校验
+    #[default]
+    Download,
+    /// 只校验已有的本地文件，不下载，见 [`YuShi::add_verify_task`]
+    ///
+    /// [`YuShi::add_verify_task`]: crate::YuShi::add_verify_task
+    VerifyOnly,
 }
 
 /// 文件校验类型
@@ -50,6 +130,55 @@ pub enum ChecksumType {
     Md5(String),
     /// SHA256 校验
     Sha256(String),
+    /// SHA512 校验
+    Sha512(String),
+}
+
+impl ChecksumType {
+    /// 根据十六进制摘要的长度判断算法：32 位为 MD5，64 位为 SHA-256，128 位为 SHA-512
+    ///
+    /// 忽略前后的空白。包含非十六进制字符，或者长度不对应支持的算法
+    /// （包括 40 位的 SHA-1）时返回 [`Error::InvalidChecksum`](crate::Error::InvalidChecksum)，
+    /// 信息中列出支持的算法。
+    pub fn parse_auto(digest: &str) -> crate::Result<Self> {
+        let digest = digest.trim();
+        if digest.is_empty() || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(crate::Error::InvalidChecksum(i18n::message(
+                "checksum.not_hex",
+                &[&digest],
+            )));
+        }
+        let hex = digest.to_string();
+        match hex.len() {
+            32 => Ok(Self::Md5(hex)),
+            64 => Ok(Self::Sha256(hex)),
+            128 => Ok(Self::Sha512(hex)),
+            40 => Err(crate::Error::InvalidChecksum(i18n::message(
+                "checksum.unsupported",
+                &[&"SHA-1"],
+            ))),
+            len => Err(crate::Error::InvalidChecksum(i18n::message(
+                "checksum.unknown_length",
+                &[&len],
+            ))),
+        }
+    }
+
+    /// 算法名称
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            Self::Md5(_) => "MD5",
+            Self::Sha256(_) => "SHA256",
+            Self::Sha512(_) => "SHA512",
+        }
+    }
+
+    /// 期望的十六进制摘要
+    pub fn expected(&self) -> &str {
+        match self {
+            Self::Md5(hex) | Self::Sha256(hex) | Self::Sha512(hex) => hex,
+        }
+    }
 }
 
 // ==================== 事件类型 ====================
@@ -64,6 +193,25 @@ pub enum DownloaderEvent {
     Progress(ProgressEvent),
     /// 校验相关事件
     Verification(VerificationEvent),
+    /// 与单个任务无关的全局事件
+    Global(GlobalEvent),
+}
+
+/// 带序号和时间戳的事件
+///
+/// 序列化时事件本身的字段被展开，只是额外增加了 `seq`、`timestamp_ms` 和 `replayed`。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    /// 同一下载器内单调递增的序号，从 1 开始
+    pub seq: u64,
+    /// 发出事件时的 Unix 时间（毫秒）
+    pub timestamp_ms: u64,
+    /// 是否是订阅时补发的历史事件，见 [`YuShi::subscribe_with_replay`](crate::YuShi::subscribe_with_replay)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replayed: bool,
+    /// 事件内容
+    #[serde(flatten)]
+    pub event: DownloaderEvent,
 }
 
 /// 任务事件
@@ -74,15 +222,48 @@ pub enum TaskEvent {
     /// 任务开始下载
     Started { task_id: String },
     /// 任务完成
-    Completed { task_id: String },
+    Completed {
+        task_id: String,
+        /// 远程文件没有变化，没有重新下载（见 [`ConflictPolicy::IfChanged`]）
+        #[serde(default)]
+        skipped_unchanged: bool,
+        /// 平均下载速度（字节/秒），见 [`Task::average_speed`]
+        #[serde(default)]
+        average_speed: u64,
+        /// 最高下载速度（字节/秒）
+        #[serde(default)]
+        peak_speed: u64,
+    },
     /// 任务失败
     Failed { task_id: String, error: String },
+    /// 任务下载结束但有缺失的字节范围，见 [`TaskStatus::PartiallyCompleted`]
+    PartiallyCompleted {
+        task_id: String,
+        /// 缺失的字节范围（包含两端）
+        missing_ranges: Vec<(u64, u64)>,
+    },
     /// 任务暂停
     Paused { task_id: String },
     /// 任务恢复
     Resumed { task_id: String },
     /// 任务取消
     Cancelled { task_id: String },
+    /// 没能在 [`Config::completed_link_dir`] 中创建链接，任务仍然是完成状态
+    LinkFailed { task_id: String, error: String },
+    /// 任务开始临时加速，或者加速被延长
+    Boosted {
+        task_id: String,
+        /// 加速的截止时间（Unix 毫秒）
+        until_ms: u64,
+    },
+    /// 临时加速结束，恢复原来的速度限制
+    BoostEnded { task_id: String },
+    /// 任务组的所有成员都已结束
+    GroupCompleted {
+        group_id: String,
+        /// 未成功完成（失败或取消）的成员任务 ID
+        failed: Vec<String>,
+    },
 }
 
 /// 进度事件
@@ -92,6 +273,15 @@ pub enum ProgressEvent {
     Initialized {
         task_id: String,
         total_size: Option<u64>,
+        /// 断点续传时已下载的字节数，全新下载时为 0
+        #[serde(default)]
+        resumed_from: u64,
+        /// 最终写入的文件路径
+        #[serde(default, with = "crate::utils::path_serde")]
+        final_path: PathBuf,
+        /// 是否使用 Range 分块下载
+        #[serde(default)]
+        supports_range: bool,
     },
     /// 进度更新
     Updated {
@@ -100,6 +290,9 @@ pub enum ProgressEvent {
         total: u64,
         speed: u64,
         eta: Option<u64>,
+        /// 从文件开头起连续下载完成的字节数（见 [`Task::contiguous_prefix`]）
+        #[serde(default)]
+        contiguous_prefix: u64,
     },
     /// 分块下载进度更新（内部使用）
     ChunkProgress {
@@ -109,31 +302,233 @@ pub enum ProgressEvent {
     },
     /// 流式下载进度更新（内部使用）
     StreamProgress { task_id: String, downloaded: u64 },
-    /// 下载完成（内部使用）
+    /// 一个分块下载完成，上次运行已完成的分块不再发送
+    ChunkFinished {
+        task_id: String,
+        chunk_index: usize,
+        /// 分块大小（字节）
+        bytes: u64,
+    },
+    /// 连续前缀已落盘（内部使用）
+    PrefixFlushed {
+        task_id: String,
+        contiguous_prefix: u64,
+    },
+    /// 下载完成（内部使用），每次下载只发送一次，在所有分块完成之后
     Finished { task_id: String },
     /// 下载失败（内部使用）
     Failed { task_id: String, error: String },
+    /// 中断当前连接后重新开始下载（从已下载的位置继续）
+    Retrying {
+        task_id: String,
+        /// 第几次重试，从 1 开始
+        attempt: u32,
+        reason: RetryReason,
+    },
+    /// 丢弃已下载的数据从头重新下载，之后会重新发送 `Initialized`
+    Restarted {
+        task_id: String,
+        reason: RestartReason,
+    },
+    /// 镜像（[`TaskOptions::mirrors`]）在这次下载中不再使用
+    MirrorDemoted {
+        task_id: String,
+        mirror: String,
+        reason: MirrorDemotion,
+    },
+    /// 把临时目录中下载完成的文件复制到目标位置（只在跨文件系统时发送）
+    Finalizing {
+        task_id: String,
+        /// 已复制的字节数
+        moved: u64,
+        total: u64,
+    },
 
-    // 向后兼容的变体
+    // 向后兼容的变体，下载器不再发送，改为发送 `ChunkProgress` 和 `StreamProgress`
     /// 分块下载进度更新（向后兼容）
     ChunkDownloading { chunk_index: usize, delta: u64 },
     /// 流式下载进度更新（向后兼容）
     StreamDownloading { downloaded: u64 },
 }
 
+impl ProgressEvent {
+    /// 事件所属的任务，向后兼容的变体没有任务 ID，返回 None
+    pub fn task_id(&self) -> Option<&str> {
+        match self {
+            Self::Initialized { task_id, .. }
+            | Self::Updated { task_id, .. }
+            | Self::ChunkProgress { task_id, .. }
+            | Self::StreamProgress { task_id, .. }
+            | Self::ChunkFinished { task_id, .. }
+            | Self::PrefixFlushed { task_id, .. }
+            | Self::Finished { task_id }
+            | Self::Failed { task_id, .. }
+            | Self::Retrying { task_id, .. }
+            | Self::Restarted { task_id, .. }
+            | Self::MirrorDemoted { task_id, .. }
+            | Self::Finalizing { task_id, .. } => Some(task_id),
+            Self::ChunkDownloading { .. } | Self::StreamDownloading { .. } => None,
+        }
+    }
+}
+
+/// 全局事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GlobalEvent {
+    /// 调度器状态发生变化
+    SchedulerStateChanged(SchedulerState),
+    /// 任务列表发生变化（按 [`Config::tasks_changed_interval`] 定期汇总发送）
+    TasksChanged(TaskChanges),
+    /// 队列统计（按 [`Config::stats_interval`] 定期发送）
+    Stats(Box<QueueStats>),
+    /// 磁盘写入延迟过高，总速度被临时限制（见 [`Config::disk_pressure`]）
+    DiskPressure {
+        /// 平均写入耗时（毫秒）
+        latency_ms: u64,
+        /// 当前的限速（字节/秒），None 表示已解除
+        throttled_to: Option<u64>,
+    },
+}
+
+/// 任务列表的增量变更
+///
+/// 每次变更对应一个新的代次，代次从 1 开始连续递增。界面先通过
+/// [`YuShi::get_tasks_snapshot`](crate::YuShi::get_tasks_snapshot) 获取完整列表，
+/// 之后依次应用增量；发现代次不连续时调用
+/// [`YuShi::get_changes_since`](crate::YuShi::get_changes_since) 补齐。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaskChanges {
+    /// 应用这些变更后的代次
+    pub generation: u64,
+    /// 新增的任务
+    pub added: Vec<TaskView>,
+    /// 显示内容有变化的任务
+    pub updated: Vec<TaskView>,
+    /// 已移除的任务 ID
+    pub removed: Vec<String>,
+}
+
+impl TaskChanges {
+    /// 是否没有任何变更
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// 重试原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RetryReason {
+    /// 超过 [`Config::stall_timeout`] 没有收到任何数据，或者流式下载的连接停滞
+    Stalled,
+    /// 分块的连接超过 [`Config::connection_stall_timeout`] 没有收到数据，从分块的当前位置重新请求
+    ConnectionStalled { chunk_index: usize },
+    /// 分块的连接在传输中断开或出错，从分块的当前位置重新请求
+    ConnectionLost { chunk_index: usize },
+}
+
+/// 从头重新下载的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RestartReason {
+    /// 续传前重新探测，远程文件的 `ETag` 或 `Last-Modified` 与开始下载时不同
+    RemoteChanged,
+    /// 服务器对分块的 Range 请求返回了整个文件，改为单连接流式下载
+    RangeIgnored,
+    /// 继续流式下载时服务器没有从已写入的位置返回数据（不支持 Range，或者远程文件
+    /// 已经变化），从头下载
+    ResumeRejected,
+}
+
+/// 镜像不再使用的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MirrorDemotion {
+    /// 探测失败
+    ProbeFailed,
+    /// 文件大小与任务地址不同
+    SizeMismatch,
+    /// 不支持 Range 请求，或者对分块的 Range 请求返回了整个文件
+    RangeIgnored,
+    /// 返回了重试也不会成功的状态码（例如 404）
+    Rejected { status: u16 },
+    /// 分块请求失败的次数过多
+    TooManyFailures,
+}
+
 /// 校验事件
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum VerificationEvent {
     /// 校验开始
     Started { task_id: String },
+    /// 校验进度
+    Progress {
+        task_id: String,
+        /// 已校验字节数
+        verified: u64,
+        /// 文件大小
+        total: u64,
+    },
+    /// 校验完成
+    Completed {
+        task_id: String,
+        success: bool,
+        /// 校验失败后重新下载的损坏分块数
+        #[serde(default)]
+        repaired_chunks: usize,
+    },
+}
+
+/// 任务事件记录最多保留的条数，更早的记录被丢弃
+pub const MAX_RECENT_EVENTS: usize = 50;
+
+/// 任务事件记录中的一条，见 [`Task::recent_events`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactEvent {
+    /// 发生时间（Unix 时间，毫秒）
+    pub timestamp_ms: u64,
+    /// 事件内容
+    pub kind: CompactEventKind,
+}
+
+/// 任务事件记录的内容，只包含生命周期、重试和校验事件，不包含进度
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompactEventKind {
+    /// 任务被添加到队列
+    Added,
+    /// 开始（或重新开始）下载
+    Started,
+    /// 暂停
+    Paused,
+    /// 恢复
+    Resumed,
+    /// 取消
+    Cancelled,
+    /// 手动重试，`fresh` 表示丢弃已下载的数据从头开始
+    Retried { fresh: bool },
+    /// 下载完成
+    Completed { skipped_unchanged: bool },
+    /// 下载失败
+    Failed { error: String },
+    /// 下载结束但留有空洞
+    PartiallyCompleted { missing_ranges: usize },
+    /// 自动重试
+    Retrying { attempt: u32, reason: RetryReason },
+    /// 丢弃已下载的数据从头重新下载
+    Restarted { reason: RestartReason },
+    /// 镜像不再使用
+    MirrorDemoted {
+        mirror: String,
+        reason: MirrorDemotion,
+    },
+    /// 校验开始
+    VerifyStarted,
     /// 校验完成
-    Completed { task_id: String, success: bool },
+    VerifyCompleted {
+        success: bool,
+        repaired_chunks: usize,
+    },
 }
 
 // ==================== 兼容性别名 ====================
 
-/// 队列事件（向后兼容）
-pub type QueueEvent = DownloaderEvent;
 /// 任务优先级（向后兼容）
 pub type Priority = TaskPriority;
 /// 下载完成回调（向后兼容）
@@ -149,6 +544,7 @@ pub struct Task {
     /// 下载 URL
     pub url: String,
     /// 目标文件路径
+    #[serde(with = "crate::utils::path_serde")]
     pub dest: PathBuf,
     /// 当前状态
     pub status: TaskStatus,
@@ -164,39 +560,1319 @@ pub struct Task {
     #[serde(default)]
     pub priority: TaskPriority,
     /// 当前下载速度（字节/秒）
-    #[serde(default)]
+    ///
+    /// 只在运行时有意义，不写入状态文件，也不包含在序列化结果中，
+    /// 旧状态文件中的值在加载时忽略。需要显示速度时使用 [`Task::to_view`]。
+    #[serde(skip)]
     pub speed: u64,
-    /// 预计剩余时间（秒）
-    #[serde(default)]
+    /// 预计剩余时间（秒），与 `speed` 一样不序列化
+    #[serde(skip)]
     pub eta: Option<u64>,
-    /// 自定义 HTTP 头
-    #[serde(default)]
+    /// 分块下载当前允许的并发连接数（逐步增加连接数时会逐渐变大），与 `speed` 一样不序列化
+    #[serde(skip)]
+    pub active_connections: usize,
+    /// 任务自己的 HTTP 头，与 [`Config::headers`] 同名（不区分大小写）时覆盖后者
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub headers: HashMap<String, String>,
+    /// 任务自己的认证信息，见 [`TaskOptions::auth`]；只保存在内存中，不写入队列状态
+    #[serde(skip)]
+    pub auth: Option<Credential>,
     /// 文件校验
     #[serde(default)]
     pub checksum: Option<ChecksumType>,
+    /// 所属任务组 ID
+    #[serde(default)]
+    pub group_id: Option<String>,
+    /// 已校验大小（字节）
+    #[serde(default)]
+    pub verified: u64,
+    /// 下载已完成但校验尚未完成（恢复时只需重新校验）
+    #[serde(default)]
+    pub verification_pending: bool,
+    /// 探测到的远程资源信息（重试时不再重复探测）
+    #[serde(default)]
+    pub remote: Option<RemoteInfo>,
+    /// 并发连接数，None 时使用配置中的 `max_concurrent`
+    #[serde(default)]
+    pub connections: Option<usize>,
+    /// 只下载的字节范围（见 [`TaskOptions::byte_range`]）
+    #[serde(default)]
+    pub byte_range: Option<(u64, Option<u64>)>,
+    /// 显示名称，设置后在列表中代替文件名显示
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// 备注
+    #[serde(default)]
+    pub note: Option<String>,
+    /// 跳过探测，直接发起下载请求（见 [`TaskOptions::skip_probe`]）
+    #[serde(default)]
+    pub skip_probe: bool,
+    /// 分块的下载顺序，添加任务时确定，之后续传保持不变；旧状态文件中没有时按当前配置
+    #[serde(default)]
+    pub chunk_order: Option<ChunkOrder>,
+    /// 目标文件已存在时的处理方式
+    #[serde(default)]
+    pub conflict: ConflictPolicy,
+    /// 远程文件没有变化，任务没有重新下载就完成了
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped_unchanged: bool,
+    /// 第一次开始下载时的下载环境
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+    /// 已归档：用户从列表中移除了任务，记录仍然保留（见 [`YuShi::remove_task`]）
+    ///
+    /// [`YuShi::remove_task`]: crate::YuShi::remove_task
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    /// 归档时间戳（Unix 时间）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<u64>,
+    /// 从文件开头起连续下载完成的字节数
+    ///
+    /// 只在数据已写入存储后端（对本地文件而言已交给操作系统，其他进程可以读到）后
+    /// 才增加，播放器可以放心读取这一段。
+    #[serde(default)]
+    pub contiguous_prefix: u64,
+    /// 下载时长（毫秒），不含暂停、排队和校验的时间，多次运行累加
+    #[serde(default)]
+    pub active_duration_ms: u64,
+    /// 最高下载速度（字节/秒），每秒采样一次
+    #[serde(default)]
+    pub peak_speed: u64,
+    /// 平均下载速度（字节/秒），任务结束时由 [`Task::compute_average_speed`] 计算
+    #[serde(default)]
+    pub average_speed: u64,
+    /// 任务类型
+    #[serde(default)]
+    pub kind: TaskKind,
+    /// 重试次数用尽、没有下载到的字节范围（包含两端），见 [`TaskStatus::PartiallyCompleted`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_ranges: Vec<(u64, u64)>,
+    /// 最近的生命周期、重试和校验事件，最多 [`MAX_RECENT_EVENTS`] 条，用于事后排查问题
+    ///
+    /// 随队列状态一起保存，重启后仍然保留。[`Config::event_journal`] 为 false 时不记录。
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    pub recent_events: VecDeque<CompactEvent>,
+    /// 完成时在 [`Config::completed_link_dir`] 中创建的链接（或副本）
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::utils::path_serde::option"
+    )]
+    pub completed_link: Option<PathBuf>,
+    /// 任务的速度限制（字节/秒），代替 [`Config::speed_limit`]，0 表示不限速
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed_limit: Option<u64>,
+    /// 临时加速的截止时间（Unix 毫秒），之前不受任务和总速度限制，见
+    /// [`YuShi::boost_task`](crate::YuShi::boost_task)。重启后没有到期的加速继续生效。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boost_until: Option<u64>,
+    /// 每次运行的时间限制（毫秒），见 [`TaskOptions::deadline`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_ms: Option<u64>,
+    /// 探测时跟随重定向后得到的地址，与 `url` 相同时为 None
+    ///
+    /// 分块下载和续传直接请求这个地址，不再经过可能已经过期的跳转链接；
+    /// 这个地址请求失败时改回 `url`。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_url: Option<String>,
+    /// 镜像地址，见 [`TaskOptions::mirrors`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+}
+
+impl Task {
+    /// 断点续传状态中记录的地址是否属于这个任务：原始地址或重定向后的地址
+    pub(crate) fn owns_url(&self, url: &str) -> bool {
+        self.url == url || self.resolved_url.as_deref() == Some(url)
+    }
+
+    /// 更换下载地址，记录的下载环境也改用新地址，按记录重试时不会退回旧地址
+    pub(crate) fn replace_url(&mut self, url: String) {
+        if let Some(provenance) = &mut self.provenance {
+            provenance.url = url.clone();
+        }
+        self.url = url;
+    }
+
+    /// 追加一条事件记录，超过 [`MAX_RECENT_EVENTS`] 条时丢弃最早的
+    pub(crate) fn record_event(&mut self, kind: CompactEventKind) {
+        if self.recent_events.len() >= MAX_RECENT_EVENTS {
+            self.recent_events.pop_front();
+        }
+        self.recent_events.push_back(CompactEvent {
+            timestamp_ms: crate::event::current_timestamp_ms(),
+            kind,
+        });
+    }
+
+    /// 已下载字节数除以下载时长，得到不含暂停时间的平均速度；没有下载时长时为 0
+    pub fn compute_average_speed(&self) -> u64 {
+        match self.active_duration_ms {
+            0 => 0,
+            ms => self.downloaded.saturating_mul(1000) / ms,
+        }
+    }
+
+    /// 列表中显示的名称：优先使用显示名称，否则为目标文件名
+    pub fn label(&self) -> String {
+        if let Some(name) = &self.display_name {
+            return name.clone();
+        }
+        self.dest
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.url.clone())
+    }
+
+    /// 任务是否匹配搜索词（不区分大小写，匹配 URL、目标路径、显示名称和备注）
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let dest = self.dest.to_string_lossy();
+        [
+            Some(self.url.as_str()),
+            Some(&*dest),
+            self.display_name.as_deref(),
+            self.note.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(&query))
+    }
+
+    /// 生成任务列表使用的精简视图
+    pub fn to_view(&self) -> TaskView {
+        let name = self.label();
+        let done = match self.status {
+            TaskStatus::Verifying => self.verified,
+            _ => self.downloaded,
+        };
+        let progress = (self.total_size > 0)
+            .then(|| (done as f64 / self.total_size as f64 * 100.0).min(100.0));
+        TaskView {
+            id: self.id.clone(),
+            name,
+            note: self.note.clone(),
+            status: self.status,
+            progress,
+            speed: self.speed,
+            eta: self.eta,
+            downloaded: i18n::format_size(self.downloaded),
+            total_size: (self.total_size > 0).then(|| i18n::format_size(self.total_size)),
+        }
+    }
+}
+
+/// 任务列表使用的精简视图
+///
+/// 只包含显示列表所需的字段，由 [`Task::to_view`] 生成。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskView {
+    /// 任务 ID
+    pub id: String,
+    /// 显示名称（未设置时为目标文件名）
+    pub name: String,
+    /// 备注
+    pub note: Option<String>,
+    /// 任务状态
+    pub status: TaskStatus,
+    /// 进度百分比（0-100），校验中时为校验进度，大小未知时为 None
+    pub progress: Option<f64>,
+    /// 当前下载速度（字节/秒）
+    pub speed: u64,
+    /// 预计剩余时间（秒）
+    pub eta: Option<u64>,
+    /// 已下载大小（如 `1.50 MB`）
+    pub downloaded: String,
+    /// 文件总大小，未知时为 None
+    pub total_size: Option<String>,
 }
 
 /// 下载任务（向后兼容）
 pub type DownloadTask = Task;
 
+/// [`YuShi::download`](crate::YuShi::download) 的结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadSummary {
+    /// 下载使用的任务 ID，与进度事件中的 `task_id` 相同
+    pub task_id: String,
+    /// 实际写入的文件路径（自动重命名时与请求的路径不同）
+    #[serde(with = "crate::utils::path_serde")]
+    pub dest: PathBuf,
+    /// 文件大小（字节），大小未知时为已下载的字节数
+    pub total_size: u64,
+    /// 远程文件没有变化，没有重新下载（见 [`ConflictPolicy::IfChanged`]）
+    pub skipped_unchanged: bool,
+}
+
+/// 移除任务时的选项，见 [`YuShi::remove_task_with_options`]
+///
+/// [`YuShi::remove_task_with_options`]: crate::YuShi::remove_task_with_options
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoveOptions {
+    /// 同时删除下载的文件、状态文件和 [`Task::completed_link`]
+    ///
+    /// 只校验本地文件的任务不会删除文件。
+    pub delete_file: bool,
+}
+
+/// 添加任务时的选项
+#[derive(Clone, Default)]
+pub struct TaskOptions {
+    /// 任务优先级
+    pub priority: TaskPriority,
+    /// 文件校验
+    pub checksum: Option<ChecksumType>,
+    /// 目标文件已存在时是否自动重命名，等同于 [`ConflictPolicy::Rename`]
+    pub auto_rename: bool,
+    /// 目标文件已存在时的处理方式，`auto_rename` 为 true 时忽略
+    pub conflict: ConflictPolicy,
+    /// 存储后端，None 表示写入 `dest` 指向的本地文件
+    pub sink: Option<SharedSink>,
+    /// 只下载远程文件的一部分：`(起始位置, 结束位置)`，均为包含在内的字节偏移，
+    /// 结束位置为 None 表示到文件末尾
+    ///
+    /// 目标文件只包含这一段数据，`total_size` 为这一段的长度，校验也只针对这一段。
+    /// 服务器不支持 Range 请求时任务失败。
+    pub byte_range: Option<(u64, Option<u64>)>,
+    /// 显示名称
+    pub display_name: Option<String>,
+    /// 备注
+    pub note: Option<String>,
+    /// 不发送 HEAD 探测，直接用一个 GET 请求流式下载，大小从响应头获得
+    ///
+    /// 适合大量小文件：每个文件省去一次请求往返，但不使用分块并发下载，
+    /// 也不支持断点续传（重试时会按探测到的信息正常下载）。指定了
+    /// [`TaskOptions::byte_range`] 时忽略。
+    pub skip_probe: bool,
+    /// 并发连接数，None 表示使用默认连接数
+    pub connections: Option<usize>,
+    /// 分块的下载顺序，None 表示使用 [`Config::chunk_order`]
+    pub chunk_order: Option<ChunkOrder>,
+    /// 分块请求返回 401 或 403 时调用，换成新的下载地址后从原来的位置继续下载
+    ///
+    /// 用于会过期的预签名 URL。新地址同时写入任务和下载状态，回调本身只保存在内存中。
+    pub url_refresher: Option<UrlRefresher>,
+    /// 任务自己的请求头，与 [`Config::headers`] 同名（不区分大小写）时覆盖后者
+    ///
+    /// 用于探测和所有下载请求，随任务保存在队列状态中。
+    pub headers: HashMap<String, String>,
+    /// 任务自己的认证信息，优先于按主机设置的认证信息和 [`Config::auth`]
+    ///
+    /// 用于探测和所有下载请求（镜像除外），设置了 `Authorization` 请求头时不使用。
+    /// 认证信息不写入队列状态：重新加载队列后需要按主机设置
+    /// （[`YuShi::set_host_credentials`](crate::YuShi::set_host_credentials)）或使用 [`Config::auth`]。
+    pub auth: Option<Credential>,
+    /// 任务的速度限制（字节/秒），None 表示使用 [`Config::speed_limit`]，0 表示不限速
+    pub speed_limit: Option<u64>,
+    /// 时间限制：任务开始后没有在这段时间内下载完成时中断所有连接，任务失败
+    /// （[`Error::TaskTimedOut`](crate::Error::TaskTimedOut)）
+    ///
+    /// 下载状态文件会保留，重试时从已下载的位置继续。每次开始运行（包括恢复和重试）时重新计时，
+    /// 下载完成后的校验不计入。
+    pub deadline: Option<Duration>,
+    /// 内容与 `url` 相同的其他下载地址
+    ///
+    /// 分块下载时各个分块轮流使用任务地址和镜像；分块在一个镜像上连续失败时换用下一个地址，
+    /// 失败次数过多的镜像在这次下载中不再使用（发送 [`ProgressEvent::MirrorDemoted`]）。
+    /// 开始下载前探测每个镜像，大小与任务地址不同或不支持 Range 的镜像不会使用。
+    /// 流式下载只使用任务地址。
+    pub mirrors: Vec<String>,
+}
+
+/// 目标文件已存在时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// 覆盖已有文件
+    #[default]
+    Overwrite,
+    /// 写入自动重命名后的新路径
+    Rename,
+    /// 只在远程文件变化时重新下载
+    ///
+    /// 用上次下载记录的 `ETag` / `Last-Modified`（来自同一目标路径的已完成任务，
+    /// 或目标文件旁的 `.<文件名>.validators.json`）发送条件请求。服务器返回 304
+    /// 时任务直接完成；否则下载到临时文件，完成后替换已有文件。
+    IfChanged,
+}
+
+/// 任务组中的单个任务
+#[derive(Debug, Clone)]
+pub struct NewTask {
+    /// 下载 URL
+    pub url: String,
+    /// 目标文件路径
+    pub dest: PathBuf,
+    /// 任务选项
+    pub options: TaskOptions,
+}
+
+impl NewTask {
+    pub fn new(url: impl Into<String>, dest: impl Into<PathBuf>) -> Self {
+        Self {
+            url: url.into(),
+            dest: dest.into(),
+            options: TaskOptions::default(),
+        }
+    }
+}
+
+/// 任务组
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskGroup {
+    /// 任务组唯一标识符
+    pub id: String,
+    /// 任务组名称
+    pub name: String,
+    /// 创建时间戳（Unix 时间）
+    pub created_at: u64,
+    /// 所有成员结束的时间戳
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+}
+
+/// 任务组汇总进度
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupProgress {
+    /// 任务组 ID
+    pub group_id: String,
+    /// 任务组名称
+    pub name: String,
+    /// 成员任务数
+    pub total_tasks: usize,
+    /// 已完成的成员数
+    pub completed_tasks: usize,
+    /// 失败或取消的成员数
+    pub failed_tasks: usize,
+    /// 已下载大小（字节）
+    pub downloaded: u64,
+    /// 已知的总大小（字节），尚未获取大小的成员不计入
+    pub total_size: u64,
+    /// 当前总下载速度（字节/秒）
+    pub speed: u64,
+    /// 预计剩余时间（秒），存在未知大小的成员时为 None
+    pub eta: Option<u64>,
+}
+
+/// 任务进度快照
+///
+/// 由 [`YuShi::progress`](crate::YuShi::progress) 返回，读取下载线程维护的原子计数器，
+/// 不需要获取任务表的锁。下载中的计数器在每次收到数据时更新，至少每个进度间隔更新一次；
+/// 状态在每次状态变化时立即更新。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgressSnapshot {
+    /// 任务 ID
+    pub task_id: String,
+    /// 已下载大小（字节）
+    pub downloaded: u64,
+    /// 文件总大小（字节），未知时为 0
+    pub total: u64,
+    /// 当前下载速度（字节/秒）
+    pub speed: u64,
+    /// 预计剩余时间（秒）
+    pub eta: Option<u64>,
+    /// 已校验大小（字节），仅在校验时有意义
+    pub verified: u64,
+    /// 任务状态
+    pub status: TaskStatus,
+    /// 分块完成情况
+    pub chunk_summary: ChunkSummary,
+    /// 下载中且已有一段时间没有收到数据时，为没有数据的秒数
+    pub stalled_for: Option<u64>,
+    /// 分块下载当前允许的并发连接数（见 [`Task::active_connections`]）
+    pub connections: usize,
+    /// 任务优先级
+    pub priority: TaskPriority,
+}
+
+/// 调度器暂停启动新任务的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum PausedReason {
+    /// 调用了 [`YuShi::pause_queue`](crate::YuShi::pause_queue)
+    ManualPause,
+    /// 调用了 [`YuShi::shutdown`](crate::YuShi::shutdown)，不会再恢复
+    Shutdown,
+}
+
+/// 调度器状态，由 [`YuShi::scheduler_state`](crate::YuShi::scheduler_state) 返回
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulerState {
+    /// 是否会启动等待中的任务
+    pub running: bool,
+    /// 暂停的原因，运行中时为 None
+    pub reason: Option<PausedReason>,
+    /// 预计自动恢复的时间，不会自动恢复时为 None
+    pub next_wakeup: Option<SystemTime>,
+}
+
+impl Default for SchedulerState {
+    fn default() -> Self {
+        Self {
+            running: true,
+            reason: None,
+            next_wakeup: None,
+        }
+    }
+}
+
+/// 一个主机当前的连接数，由 [`YuShi::host_stats`](crate::YuShi::host_stats) 返回
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostStats {
+    /// 小写的主机名
+    pub host: String,
+    /// 正在下载的连接数
+    pub active_connections: usize,
+    /// 等待 [`Config::per_host_max_connections`] 许可的连接数
+    pub waiting_connections: usize,
+}
+
+/// 队列统计，由 [`YuShi::get_stats`](crate::YuShi::get_stats) 返回
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueStats {
+    /// 任务总数
+    pub total: usize,
+    pub pending: usize,
+    pub downloading: usize,
+    pub verifying: usize,
+    pub paused: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    #[serde(default)]
+    pub partially_completed: usize,
+    /// 所有任务的总速度（字节/秒）
+    pub speed: u64,
+    /// 已收到、尚未写入存储后端的字节数，见 [`Config::max_buffered_bytes`]
+    pub buffered_bytes: u64,
+    /// 调度器状态
+    pub scheduler: SchedulerState,
+    /// 等待中、下载中和暂停的任务按优先级统计的剩余字节数，不包括大小未知的任务
+    #[serde(default)]
+    pub remaining_bytes_by_priority: HashMap<TaskPriority, u64>,
+    /// 等待中、下载中和暂停的任务中大小未知的任务数，按优先级统计
+    #[serde(default)]
+    pub unknown_size_by_priority: HashMap<TaskPriority, usize>,
+    /// 下载中和校验中的任务数，按优先级统计
+    #[serde(default)]
+    pub active_by_priority: HashMap<TaskPriority, usize>,
+    /// 按当前总速度下载完所有已知剩余字节需要的秒数，速度为 0 时为 None
+    #[serde(default)]
+    pub estimated_drain_time: Option<u64>,
+}
+
+/// 模拟调度中一个任务的预计启动和完成时间，见
+/// [`YuShi::simulate_schedule`](crate::YuShi::simulate_schedule)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledSlot {
+    pub task_id: String,
+    /// 距现在多少秒后开始，无法预计（前面有大小未知的任务或不知道速度）时为 None
+    pub est_start: Option<u64>,
+    /// 距现在多少秒后下载完成，大小未知或无法预计时为 None
+    pub est_finish: Option<u64>,
+}
+
+/// 队列的磁盘占用预估，由 [`YuShi::forecast`](crate::YuShi::forecast) 返回
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Forecast {
+    /// 大小已知的等待中和暂停任务还需要下载的字节数
+    pub pending_bytes_known: u64,
+    /// 大小未知的等待中和暂停任务数
+    pub pending_unknown_count: usize,
+    /// 按目标卷分组的需求和空闲空间
+    pub free_space_by_volume: Vec<VolumeForecast>,
+    /// 所有卷都放得下时为 true，有卷放不下时为 false，
+    /// 因为有大小未知的任务而无法确定时为 None
+    pub will_fit: Option<bool>,
+}
+
+/// 单个卷的磁盘占用预估
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeForecast {
+    /// 卷上的一个已存在的目录（第一个任务的目标目录或其上级）
+    pub path: PathBuf,
+    /// 当前用户可用的空闲空间（字节）
+    pub free_space: u64,
+    /// 大小已知的任务还需要的字节数
+    pub required: u64,
+    /// 目标在该卷上的任务数
+    pub task_count: usize,
+    /// 大小未知的任务数
+    pub unknown_count: usize,
+}
+
+impl VolumeForecast {
+    /// 大小已知的任务下载完后剩余的空间，放不下时为 None
+    pub fn headroom(&self) -> Option<u64> {
+        self.free_space.checked_sub(self.required)
+    }
+}
+
+/// 分块完成情况，流式下载时均为 0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkSummary {
+    /// 分块总数
+    pub total: usize,
+    /// 已完成的分块数
+    pub finished: usize,
+}
+
+/// 任务计划
+///
+/// 由 [`YuShi::plan_task`](crate::YuShi::plan_task) 生成，描述添加任务后会怎样下载，
+/// 生成时不会创建任务或文件。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskPlan {
+    /// 下载 URL
+    pub url: String,
+    /// 实际写入的路径（已应用冲突处理）
+    #[serde(with = "crate::utils::path_serde")]
+    pub dest: PathBuf,
+    /// 请求的目标路径上存在的冲突
+    pub conflict: Option<PlanConflict>,
+    /// 远程资源信息
+    pub remote: RemoteInfo,
+    /// 是否使用多连接分块下载
+    pub chunked: bool,
+    /// 分块数，流式下载时为 0
+    pub chunks: usize,
+    /// 并发连接数
+    pub connections: usize,
+}
+
+/// 目标路径冲突
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlanConflict {
+    /// 文件已存在：启用自动重命名时写入新路径，否则覆盖
+    FileExists,
+    /// 队列中已有写入同一路径的任务
+    QueuedTask { task_id: String },
+}
+
+/// 远程资源信息（来自 HEAD 或 Range 探测）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteInfo {
+    /// 文件总大小，None 表示服务器未提供
+    pub total_size: Option<u64>,
+    /// 服务器是否支持 Range 请求
+    pub supports_range: bool,
+    /// `ETag` 响应头
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// `Last-Modified` 响应头
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// `Content-Disposition` 响应头中建议的文件名，已经过
+    /// [`sanitize_filename`](crate::sanitize_filename) 处理
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+/// 测速中某一并发连接数的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionSample {
+    /// 并发连接数
+    pub connections: usize,
+    /// 下载的字节数
+    pub bytes: u64,
+    /// 平均速度（字节/秒）
+    pub speed: u64,
+}
+
+/// 测速报告
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeasureReport {
+    /// 测速期间下载的总字节数
+    pub bytes: u64,
+    /// 整体平均速度（字节/秒）
+    pub avg_speed: u64,
+    /// 首字节延迟的 P95
+    pub p95_latency: Duration,
+    /// 服务器是否支持 Range 请求
+    pub supports_range: bool,
+    /// 各并发连接数的测试结果
+    pub connections_tested: Vec<ConnectionSample>,
+}
+
+impl MeasureReport {
+    /// 建议的并发连接数
+    ///
+    /// 取速度达到最佳结果 90% 的最小连接数。
+    pub fn suggested_connections(&self) -> usize {
+        let best = self
+            .connections_tested
+            .iter()
+            .map(|s| s.speed)
+            .max()
+            .unwrap_or(0);
+        self.connections_tested
+            .iter()
+            .filter(|s| s.speed * 10 >= best * 9)
+            .map(|s| s.connections)
+            .min()
+            .unwrap_or(1)
+    }
+}
+
+/// 孤立文件的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrphanKind {
+    /// 没有任务引用的下载状态文件（`<dest>.json`）
+    StateFile,
+    /// 状态文件对应的未下载完的数据文件（可能是预分配的全零文件）
+    PartialFile,
+}
+
+/// 扫描到的孤立文件，见 [`YuShi::scan_orphans`]
+///
+/// [`YuShi::scan_orphans`]: crate::YuShi::scan_orphans
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrphanReport {
+    /// 文件路径
+    #[serde(with = "crate::utils::path_serde")]
+    pub path: PathBuf,
+    /// 文件类型
+    pub kind: OrphanKind,
+    /// 文件大小（字节）
+    pub size: u64,
+    /// 状态文件中记录的下载 URL
+    pub url: String,
+}
+
+/// 任务的诊断信息，由 [`YuShi::diagnostics`](crate::YuShi::diagnostics) 返回
+///
+/// 用于附加到问题报告中，URL 默认去掉查询字符串（签名 URL 常在其中携带凭据）。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskDiagnostics {
+    pub task_id: String,
+    pub status: TaskStatus,
+    pub url: String,
+    #[serde(with = "crate::utils::path_serde")]
+    pub dest: PathBuf,
+    /// 下载中的数据文件路径（配置了临时目录时位于临时目录中）
+    #[serde(with = "crate::utils::path_serde")]
+    pub work_path: PathBuf,
+    /// 分块状态文件路径
+    #[serde(with = "crate::utils::path_serde")]
+    pub state_path: PathBuf,
+    /// 状态文件最后写入的时间（Unix 时间），没有状态文件时为 None
+    pub state_saved_at: Option<u64>,
+    /// 解析后的分块状态，没有状态文件时为 None
+    pub state: Option<StateDiagnostics>,
+    /// 状态文件存在但无法解析时的错误
+    pub state_error: Option<String>,
+    /// 当前生效的下载设置
+    pub config: EffectiveConfig,
+    /// 第一次开始下载时记录的下载环境
+    pub provenance: Option<Provenance>,
+}
+
+/// 分块状态文件的内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateDiagnostics {
+    /// 状态文件中记录的 URL
+    pub url: String,
+    pub total_size: Option<u64>,
+    pub is_streaming: bool,
+    /// 下载范围在远程文件中的起始位置
+    pub offset: u64,
+    /// 各分块已下载的字节数之和
+    pub downloaded: u64,
+    pub chunks: Vec<ChunkDiagnostics>,
+    /// 发现的不一致，为空表示状态正常
+    pub issues: Vec<ChunkIssue>,
+}
+
+/// 单个分块的状态
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkDiagnostics {
+    pub index: usize,
+    /// 起始位置（包含）
+    pub start: u64,
+    /// 结束位置（包含）
+    pub end: u64,
+    /// 下一个要写入的位置
+    pub current: u64,
+    pub finished: bool,
+    /// 是否记录了分块摘要
+    pub has_digest: bool,
+}
+
+/// 分块状态中的不一致
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChunkIssue {
+    /// 两个相邻分块之间有没有被任何分块覆盖的字节
+    Gap { after: usize, start: u64, end: u64 },
+    /// 两个相邻分块的范围重叠
+    Overlap { first: usize, second: usize },
+    /// 分块的起始位置大于结束位置
+    InvalidRange { index: usize },
+    /// 写入位置不在分块范围内
+    CurrentOutOfRange { index: usize, current: u64 },
+    /// 标记为已完成，但写入位置没有到达分块末尾
+    FinishedIncomplete { index: usize },
+    /// 分块覆盖的范围与文件大小不一致
+    SizeMismatch { expected: u64, covered: u64 },
+}
+
+/// 诊断时生效的下载设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectiveConfig {
+    pub core_version: String,
+    /// 任务使用的连接数
+    pub connections: usize,
+    pub chunk_size: u64,
+    pub speed_limit: Option<u64>,
+    pub global_speed_limit: Option<u64>,
+    /// 请求超时（秒）
+    pub timeout: u64,
+    pub user_agent: Option<String>,
+    /// 是否使用代理
+    pub proxy: bool,
+    pub temp_dir: Option<PathBuf>,
+    /// 下载中的文件的后缀
+    pub part_suffix: Option<String>,
+    /// 停滞超时（秒）
+    pub stall_timeout: Option<u64>,
+    /// 单个连接的停滞超时（秒）
+    pub connection_stall_timeout: Option<u64>,
+    pub sequential_first: bool,
+    /// 任务的分块下载顺序
+    pub chunk_order: ChunkOrder,
+    /// 逐步增加连接数的间隔（毫秒）
+    pub ramp_up_ms: Option<u64>,
+    pub skip_probe: bool,
+    pub byte_range: Option<(u64, Option<u64>)>,
+}
+
+/// 审计日志中的一条记录
+///
+/// 任务每次进入终态（完成、失败或取消）时追加一行，见 [`Config::audit_log`]。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// 任务 ID
+    pub task_id: String,
+    /// 下载 URL
+    pub url: String,
+    /// 目标文件路径
+    #[serde(with = "crate::utils::path_serde")]
+    pub dest: PathBuf,
+    /// 终态：`Completed`、`Failed` 或 `Cancelled`
+    pub status: TaskStatus,
+    /// 已下载的字节数
+    pub bytes: u64,
+    /// 文件校验和（设置了校验且校验通过时才有值）
+    pub checksum: Option<ChecksumType>,
+    /// 本次运行耗时（毫秒）
+    pub duration_ms: u64,
+    /// 失败原因
+    pub error: Option<String>,
+    /// 记录时间戳（Unix 秒）
+    pub finished_at: u64,
+    /// 任务的下载环境
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// 远程文件没有变化，没有重新下载（见 [`ConflictPolicy::IfChanged`]）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped_unchanged: bool,
+    /// 平均下载速度（字节/秒），不含暂停时间
+    #[serde(default)]
+    pub average_speed: u64,
+    /// 最高下载速度（字节/秒）
+    #[serde(default)]
+    pub peak_speed: u64,
+}
+
+/// 任务的下载环境
+///
+/// 任务第一次开始下载时记录并随任务持久化，用于事后追查文件是用什么选项下载的，
+/// 以及重试或重新下载时沿用相同的选项（见 [`Provenance::task_options`]）。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// yushi-core 版本
+    pub core_version: String,
+    /// 记录时间（Unix 秒）
+    pub captured_at: u64,
+    /// 实际请求的 URL
+    pub url: String,
+    /// User-Agent
+    pub user_agent: Option<String>,
+    /// 生效的自定义请求头（键为小写）
+    ///
+    /// 敏感请求头（认证、Cookie、令牌等）只记录值的摘要 `sha256:<前 16 位>`，不保存明文。
+    pub headers: BTreeMap<String, String>,
+    /// 是否使用了代理（代理地址可能包含凭据，不记录）
+    pub proxy: bool,
+    /// 并发连接数
+    pub connections: usize,
+    /// 分块大小（字节）
+    pub chunk_size: u64,
+    /// 每个任务的速度限制（字节/秒）
+    pub speed_limit: Option<u64>,
+    /// 下载的字节范围
+    pub byte_range: Option<(u64, Option<u64>)>,
+    /// 是否跳过探测
+    pub skip_probe: bool,
+}
+
+impl Provenance {
+    /// 按记录的环境重新下载时使用的任务选项
+    ///
+    /// 只包含能按任务设置的选项（连接数、字节范围、是否跳过探测）；
+    /// 请求头、User-Agent 和分块大小由下载器配置决定，只作记录。
+    pub fn task_options(&self) -> TaskOptions {
+        TaskOptions {
+            byte_range: self.byte_range,
+            skip_probe: self.skip_probe,
+            connections: Some(self.connections),
+            ..Default::default()
+        }
+    }
+}
+
+impl TaskOptions {
+    /// 生效的冲突处理方式，`auto_rename` 优先
+    pub(crate) fn conflict_policy(&self) -> ConflictPolicy {
+        if self.auto_rename {
+            ConflictPolicy::Rename
+        } else {
+            self.conflict
+        }
+    }
+}
+
+impl std::fmt::Debug for TaskOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskOptions")
+            .field("priority", &self.priority)
+            .field("checksum", &self.checksum)
+            .field("auto_rename", &self.auto_rename)
+            .field("conflict", &self.conflict)
+            .field("byte_range", &self.byte_range)
+            .field("display_name", &self.display_name)
+            .field("note", &self.note)
+            .field("skip_probe", &self.skip_probe)
+            .field("connections", &self.connections)
+            .field("chunk_order", &self.chunk_order)
+            .field("mirrors", &self.mirrors)
+            .field("auth", &self.auth)
+            .field("has_sink", &self.sink.is_some())
+            .field("has_url_refresher", &self.url_refresher.is_some())
+            .finish()
+    }
+}
+
+/// 任务列表的排序方式，见 [`TaskQuery`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskSort {
+    /// 按添加时间，先添加的在前
+    #[default]
+    Created,
+    /// 按显示名称（不区分大小写）
+    Name,
+    /// 按状态，顺序同 [`TaskStatus`] 的定义
+    Status,
+    /// 按进度，大小未知的任务在最前
+    Progress,
+    /// 按文件大小
+    Size,
+    /// 按当前下载速度
+    Speed,
+    /// 按优先级，低优先级在前
+    Priority,
+}
+
+/// 查询任务时的过滤和排序条件，见 [`YuShi::query_tasks`]
+///
+/// [`YuShi::query_tasks`]: crate::YuShi::query_tasks
+#[derive(Debug, Clone, Default)]
+pub struct TaskQuery {
+    /// 只返回这些状态的任务，为空时不按状态过滤
+    pub statuses: Vec<TaskStatus>,
+    /// 只返回匹配搜索词的任务，规则见 [`Task::matches`]
+    pub search: Option<String>,
+    /// 是否包括已归档的任务
+    pub include_archived: bool,
+    /// 排序方式，相同时按添加时间和 ID 排序
+    pub sort: TaskSort,
+    /// 是否倒序
+    pub descending: bool,
+}
+
+impl TaskQuery {
+    /// 任务是否满足过滤条件
+    pub fn accepts(&self, task: &Task) -> bool {
+        (self.include_archived || !task.archived)
+            && (self.statuses.is_empty() || self.statuses.contains(&task.status))
+            && self
+                .search
+                .as_deref()
+                .is_none_or(|query| task.matches(query))
+    }
+
+    /// 按排序方式比较两个任务
+    pub fn compare(&self, a: &Task, b: &Task) -> std::cmp::Ordering {
+        // 大小未知的任务排在最前
+        let progress = |t: &Task| match t.total_size {
+            0 => -1.0,
+            total => t.downloaded as f64 / total as f64,
+        };
+        let order = match self.sort {
+            TaskSort::Created => std::cmp::Ordering::Equal,
+            TaskSort::Name => a.label().to_lowercase().cmp(&b.label().to_lowercase()),
+            TaskSort::Status => (a.status as u8).cmp(&(b.status as u8)),
+            TaskSort::Progress => progress(a).total_cmp(&progress(b)),
+            TaskSort::Size => a.total_size.cmp(&b.total_size),
+            TaskSort::Speed => a.speed.cmp(&b.speed),
+            TaskSort::Priority => a.priority.cmp(&b.priority),
+        }
+        .then_with(|| a.created_at.cmp(&b.created_at))
+        .then_with(|| a.id.cmp(&b.id));
+        if self.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    }
+}
+
+/// 从目录索引页批量添加任务时的选项，见 [`YuShi::add_from_index`]
+///
+/// [`YuShi::add_from_index`]: crate::YuShi::add_from_index
+#[derive(Debug, Clone)]
+pub struct IndexOptions {
+    /// 进入子目录的层数，0 表示只添加这一页列出的文件
+    pub depth: usize,
+    /// 只添加匹配的文件，支持 `*` 和 `?`；模式中有 `/` 时匹配相对路径，否则匹配文件名
+    pub include_glob: Option<String>,
+    /// 不添加匹配的文件，规则同 `include_glob`
+    pub exclude_glob: Option<String>,
+    /// 只添加与索引页同一主机的文件（子目录总是只进入同一主机上索引页之下的目录）
+    pub same_host_only: bool,
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        Self {
+            depth: 0,
+            include_glob: None,
+            exclude_glob: None,
+            same_host_only: true,
+        }
+    }
+}
+
 /// 下载器配置
 #[derive(Debug, Clone)]
 pub struct Config {
     /// 最大并发连接数
     pub max_concurrent: usize,
-    /// 分块大小（字节）
+    /// 分块大小（字节），分块数超过 [`Config::max_chunks`] 时自动增大
     pub chunk_size: u64,
-    /// 速度限制（字节/秒），None 表示不限速
+    /// 分块的最小大小（字节），None 表示不限制
+    ///
+    /// 文件末尾剩下的部分小于这个大小时并入前一个分块，而不是单独作为一个很小的分块。
+    pub min_chunk_size: Option<u64>,
+    /// 一个文件最多划分的分块数，至少为 1
+    ///
+    /// 实际的分块大小为 `max(chunk_size, 文件大小 / max_chunks)`，避免很大的文件产生
+    /// 大量分块和频繁的状态保存。已有的状态文件按其中记录的分块继续下载。
+    pub max_chunks: usize,
+    /// 小于这个大小（字节）的文件用单个连接流式下载，不创建状态文件，None 表示不使用
+    ///
+    /// 省去预分配空间和保存分块状态，适合大量小文件；中断后从头重新下载。
+    pub single_connection_threshold: Option<u64>,
+    /// 每个任务的速度限制（字节/秒），None 表示不限速，可以用 [`TaskOptions::speed_limit`]
+    /// 为单个任务另外设置
+    ///
+    /// 总速度限制见 [`Config::global_speed_limit`]。
     pub speed_limit: Option<u64>,
+    /// 所有任务共用的总速度限制（字节/秒），None 表示不限速
+    ///
+    /// 与 [`Config::speed_limit`] 同时生效，可以用
+    /// [`YuShi::set_global_speed_limit`](crate::YuShi::set_global_speed_limit) 在运行中修改。
+    pub global_speed_limit: Option<u64>,
+    /// 同一主机同时使用的最大连接数，None 表示不限制
+    ///
+    /// 所有任务的分块连接和流式下载连接按下载地址的主机名共用这个上限，用于避免
+    /// 同一服务器的大量任务触发 429 或封禁。不同主机之间互不影响，每个任务仍受
+    /// [`Config::max_concurrent`] 限制。各主机当前的连接数见
+    /// [`YuShi::host_stats`](crate::YuShi::host_stats)。
+    pub per_host_max_connections: Option<usize>,
+    /// 同一主机所有连接的总速度限制（字节/秒），None 表示不限速
+    ///
+    /// 与其他速度限制同时生效，临时加速（[`YuShi::boost_task`](crate::YuShi::boost_task)）
+    /// 不跳过这个限制。
+    pub per_host_speed_limit: Option<u64>,
     /// 自定义 HTTP 头
     pub headers: HashMap<String, String>,
-    /// 代理 URL
+    /// 所有请求使用的认证信息（HTTP Basic 或 Bearer 令牌）
+    ///
+    /// 按主机设置的认证信息和任务自己的认证信息（[`TaskOptions::auth`]）优先；
+    /// 设置了 `Authorization` 请求头时不使用。重定向到其他主机时不发送。
+    pub auth: Option<Credential>,
+    /// Cookie 支持，None 表示不保存也不发送 Cookie
+    ///
+    /// 启用后探测和所有下载请求共用一个 Cookie 存储，适用于需要登录后才能下载的链接。
+    pub cookies: Option<CookieSource>,
+    /// 代理 URL，支持 `http://`、`https://`、`socks5://` 和 `socks5h://`
+    ///
+    /// `socks5h://` 由代理服务器解析域名，`socks5://` 在本地解析。无法解析的地址让
+    /// [`YuShi::with_config`](crate::YuShi::with_config) 返回
+    /// [`Error::InvalidProxy`](crate::Error::InvalidProxy)。
     pub proxy: Option<String>,
+    /// 代理的用户名，和 `proxy_password` 一起使用，代替代理 URL 中的认证信息
+    pub proxy_user: Option<String>,
+    /// 代理的密码
+    pub proxy_password: Option<String>,
+    /// 额外信任的 CA 证书（PEM 文件，可以包含多个证书），和内置的根证书一起使用
+    ///
+    /// 用于自签名证书或有 TLS 拦截的内部网络。文件无法读取或不包含有效证书时
+    /// [`YuShi::with_config`](crate::YuShi::with_config) 返回
+    /// [`Error::InvalidCertificate`](crate::Error::InvalidCertificate)。
+    pub extra_root_certs: Vec<PathBuf>,
+    /// 不验证服务器证书和主机名
+    ///
+    /// 危险：连接可能被窃听或篡改，只应在无法提供 CA 证书时临时使用。
+    pub danger_accept_invalid_certs: bool,
+    /// 最多跟随的重定向次数，超过时请求失败（[`Error::TooManyRedirects`](crate::Error::TooManyRedirects)）
+    ///
+    /// 重定向回到已经访问过的地址时不等达到上限，直接失败（[`Error::RedirectLoop`](crate::Error::RedirectLoop)）。
+    pub max_redirects: usize,
     /// 连接超时（秒）
     pub timeout: u64,
     /// 用户代理
     pub user_agent: Option<String>,
+    /// 校验被暂停打断时任务回到 `Paused`（恢复后重新校验），为 false 时标记为失败
+    pub resume_interrupted_verification: bool,
+    /// 整个任务超过该时间没有收到任何数据时，中断当前连接并重试，None 表示不检测
+    pub stall_timeout: Option<Duration>,
+    /// 单个连接超过该时间没有收到数据时断开，分块从当前位置重新请求，None 表示不检测
+    ///
+    /// 流式下载的连接停滞时和整个任务停滞一样重新开始（需要设置
+    /// [`Config::stall_timeout`]，否则任务失败），服务器支持 Range 时从已写入的位置继续。
+    pub connection_stall_timeout: Option<Duration>,
+    /// 审计日志文件（JSON Lines），None 表示不记录
+    pub audit_log: Option<PathBuf>,
+    /// 审计日志超过该大小（字节）后轮转为 `<文件名>.1`、`<文件名>.2`……
+    pub audit_log_max_size: u64,
+    /// 同时进行的文件校验数（包括下载完成后的校验和 [`YuShi::verify_all`]），至少为 1
+    ///
+    /// [`YuShi::verify_all`]: crate::YuShi::verify_all
+    pub verify_concurrency: usize,
+    /// HTTP 连接池设置
+    pub pool: PoolConfig,
+    /// 下载中的文件和状态文件存放的目录，下载完成后才移动到目标位置，None 表示直接写入目标位置
+    pub temp_dir: Option<PathBuf>,
+    /// 下载中的文件在目标路径后追加的后缀，下载完成并通过校验后才重命名为目标路径
+    ///
+    /// 默认为 `.part`，None 表示直接写入目标路径。设置了 [`Config::temp_dir`] 时不使用。
+    pub part_suffix: Option<String>,
+    /// 分块下载新建文件时预先分配空间的方式
+    pub preallocate: PreallocateMode,
+    /// 所有连接已收到、尚未写入存储后端的数据总量上限（字节），None 表示不限制
+    ///
+    /// 达到上限时连接暂停读取，写入慢的磁盘会让下载变慢而不是占用更多内存。
+    pub max_buffered_bytes: Option<u64>,
+    /// [`YuShi::download_bytes`](crate::YuShi::download_bytes) 读入内存的最大字节数
+    pub max_memory_download: u64,
+    /// 磁盘写入延迟过高时自动降低总速度，None 表示不检测
+    pub disk_pressure: Option<DiskPressureConfig>,
+    /// 检查目标文件冲突时是否不区分文件名大小写
+    pub case_insensitive_fs: CaseInsensitiveFs,
+    /// 先按顺序逐个下载文件开头的分块，连续前缀达到 `sequential_prefix` 后再并发下载其余分块
+    ///
+    /// 用于边下边播：播放器可以尽早读取文件开头。
+    pub sequential_first: bool,
+    /// `sequential_first` 开启时按顺序下载的前缀长度（字节）
+    pub sequential_prefix: u64,
+    /// 新任务的分块下载顺序
+    ///
+    /// 为 [`ChunkOrder::Parallel`] 且开启了 `sequential_first` 时，按
+    /// `SequentialFirst(sequential_prefix)` 处理。
+    pub chunk_order: ChunkOrder,
+    /// 发送 [`GlobalEvent::TasksChanged`] 的间隔，None 表示不发送
+    pub tasks_changed_interval: Option<Duration>,
+    /// 为后来的订阅者保留的最近事件数（不含进度，进度每个任务只保留最新的一个），
+    /// 见 [`YuShi::subscribe_with_replay`](crate::YuShi::subscribe_with_replay)
+    pub event_replay_capacity: usize,
+    /// 发送 [`GlobalEvent::Stats`] 的间隔，None 表示不发送
+    pub stats_interval: Option<Duration>,
+    /// 逐步增加连接数：分块下载从一个连接开始，每隔这么久增加一个，直到任务的连接数上限
+    ///
+    /// 服务器返回 429 或 503 时停止增加，保持当前的连接数。None 表示一开始就使用全部连接。
+    pub ramp_up: Option<Duration>,
+    /// [`YuShi::forecast`](crate::YuShi::forecast) 每次最多探测的大小未知的任务数
+    ///
+    /// 探测结果缓存在任务上，多次预估会逐步覆盖所有任务。
+    pub forecast_probe_limit: usize,
+    /// 错误信息等文本使用的语言，如 `en`、`zh`
+    ///
+    /// 语言在进程内共享，创建下载器时设置。None 表示沿用当前语言，默认取自 `LANG`
+    /// 环境变量，见 [`crate::i18n`]。
+    pub locale: Option<String>,
+    /// 已归档任务的保留时间，加载队列状态时删除归档超过该时间的任务，None 表示一直保留
+    pub archive_retention: Option<Duration>,
+    /// 两次写入队列状态文件的最短间隔，间隔内的修改合并到间隔结束时一起写入，
+    /// None 表示每次修改后立即写入
+    pub queue_save_interval: Option<Duration>,
+    /// 连接服务器时使用的 IP 协议版本
+    pub ip_policy: IpPolicy,
+    /// 固定的域名解析结果，类似 curl 的 `--resolve`：连接这些主机（不区分大小写）时使用
+    /// 给定的地址，不经过系统解析
+    ///
+    /// 同一主机有多条时按顺序尝试，同样按 `ip_policy` 排序和过滤。URL 中写明了端口时
+    /// 连接该端口，否则连接地址中的端口（为 0 时使用协议的默认端口）。
+    pub resolve: Vec<(String, SocketAddr)>,
+    /// 连接时绑定的本地地址，None 表示由系统选择
+    pub local_address: Option<IpAddr>,
+    /// 分块重试次数用尽时的处理方式
+    pub on_chunk_exhausted: ChunkFailurePolicy,
+    /// 是否在任务上记录最近的事件（见 [`Task::recent_events`]）
+    pub event_journal: bool,
+    /// 任务完成（并通过校验）后在这个目录中创建指向最终文件的链接，None 表示不创建
+    ///
+    /// 用于把分散在各个目录中的下载集中到一个目录中，例如给媒体服务器监视。优先创建
+    /// 符号链接，失败时（例如 Windows 上没有权限）创建硬链接，仍然失败且
+    /// `completed_link_copy` 为 true 时复制文件。链接名称冲突时按任务的
+    /// [`ConflictPolicy`] 处理。创建失败不影响任务状态，只发送 [`TaskEvent::LinkFailed`]。
+    pub completed_link_dir: Option<PathBuf>,
+    /// 无法创建链接时是否复制文件到 `completed_link_dir`
+    pub completed_link_copy: bool,
+}
+
+/// 目标文件系统是否不区分文件名大小写
+///
+/// 不区分大小写时 `Report.PDF` 和 `report.pdf` 是同一个文件，自动重命名和冲突检查
+/// 需要按不区分大小写比较文件名。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaseInsensitiveFs {
+    /// 在目标目录中创建探测文件检测，无法检测时按平台默认（macOS 和 Windows 不区分）
+    #[default]
+    Auto,
+    /// 总是不区分大小写
+    On,
+    /// 总是区分大小写
+    Off,
+}
+
+/// 分块的下载顺序
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChunkOrder {
+    /// 按分块顺序分配给连接，并发下载
+    #[default]
+    Parallel,
+    /// 严格按顺序逐个下载，不论连接数设置只使用一个连接
+    Sequential,
+    /// 先按顺序逐个下载文件开头这么多字节的分块，之后并发下载其余分块
+    SequentialFirst(u64),
+    /// 按打乱的顺序并发下载，下载了一部分的文件难以直接使用
+    ///
+    /// 顺序由任务 ID 决定，续传时与上次相同。
+    Random,
+}
+
+/// 分块重试次数用尽时的处理方式，见 [`Config::on_chunk_exhausted`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChunkFailurePolicy {
+    /// 整个任务失败
+    #[default]
+    FailTask,
+    /// 继续下载其他分块，结束后任务为 [`TaskStatus::PartiallyCompleted`]
+    ///
+    /// 适合个别字节范围长期无法下载（例如对象存储中损坏的分片）的情况，
+    /// 已下载的部分不会丢弃。只对分块下载有效，流式下载仍然失败。
+    CompleteWithHoles,
+}
+
+/// 为下载中的文件预先分配空间的方式，见 [`Config::preallocate`]
+///
+/// 在一些文件系统（以及网络共享）上 `set_len` 会把整个文件写满零，几个 GB 的文件要
+/// 等上几分钟，这时可以选择 `None`。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PreallocateMode {
+    /// 真正分配磁盘空间（Linux 上使用 `fallocate`），不支持时退回 `Sparse`
+    Full,
+    /// 只设置文件长度，支持稀疏文件的文件系统不会立即占用空间
+    #[default]
+    Sparse,
+    /// 不预先分配，文件随写入增长，分块仍然定位到各自的偏移写入
+    None,
+}
+
+/// 连接服务器时使用的 IP 协议版本
+///
+/// 域名解析到 IPv4 和 IPv6 两种地址时，先连接排在前面的一种，连接失败或 300 毫秒内
+/// 没有连上时同时尝试另一种（Happy Eyeballs），不会等到连接超时，也不占用重试次数。
+/// 只使用一种协议时另一种地址直接丢弃，没有可用地址时解析失败。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IpPolicy {
+    /// 按系统解析结果的顺序
+    #[default]
+    Auto,
+    /// 优先 IPv4
+    PreferIpv4,
+    /// 优先 IPv6
+    PreferIpv6,
+    /// 只使用 IPv4
+    Ipv4Only,
+    /// 只使用 IPv6
+    Ipv6Only,
+}
+
+/// Cookie 的来源，见 [`Config::cookies`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieSource {
+    /// 从空的 Cookie 存储开始，保存响应设置的 Cookie
+    Store,
+    /// 先从 Netscape 格式的 cookies.txt（浏览器导出的格式）加载，之后同样保存响应设置的
+    /// Cookie；文件无法读取时从空的存储开始，Cookie 不写回文件
+    File(PathBuf),
+}
+
+/// 磁盘写入压力检测设置，见 [`GlobalEvent::DiskPressure`]
+///
+/// 写入存储后端的平均耗时超过阈值时，每隔 `adjust_interval` 把总速度降为实际写入速度的
+/// 一半（不低于 `min_rate`）；平均耗时低于阈值的一半后逐步提高，恢复到开始限速前的速度时
+/// 解除限速。
+#[derive(Debug, Clone, Copy)]
+pub struct DiskPressureConfig {
+    /// 平均写入耗时的阈值
+    pub latency_threshold: Duration,
+    /// 限速的下限（字节/秒）
+    pub min_rate: u64,
+    /// 调整速度的最短间隔
+    pub adjust_interval: Duration,
+}
+
+impl Default for DiskPressureConfig {
+    fn default() -> Self {
+        Self {
+            latency_threshold: Duration::from_millis(200),
+            min_rate: XByte::new(1, 0, Unit::MB).to_bytes(),
+            adjust_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// HTTP 连接池设置
+///
+/// 下载大量小文件时，复用连接可以省去大部分 TCP 和 TLS 握手。
+#[derive(Debug, Clone, Copy)]
+pub struct PoolConfig {
+    /// 每个主机最多保留的空闲连接数
+    pub max_idle_per_host: usize,
+    /// 空闲连接的保留时间，None 表示不过期
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: 32,
+            idle_timeout: Some(Duration::from_secs(90)),
+        }
+    }
 }
 
 impl Default for Config {
@@ -204,14 +1880,164 @@ impl Default for Config {
         Self {
             max_concurrent: 4,
             chunk_size: XByte::new(10, 0, Unit::MB).to_bytes(),
+            min_chunk_size: None,
+            max_chunks: 1024,
+            single_connection_threshold: None,
             speed_limit: None,
+            global_speed_limit: None,
+            per_host_max_connections: None,
+            per_host_speed_limit: None,
             headers: HashMap::new(),
+            auth: None,
+            cookies: None,
             proxy: None,
+            proxy_user: None,
+            proxy_password: None,
+            extra_root_certs: Vec::new(),
+            danger_accept_invalid_certs: false,
+            max_redirects: 10,
             timeout: 30,
             user_agent: Some("YuShi/1.0".to_string()),
+            resume_interrupted_verification: true,
+            stall_timeout: Some(Duration::from_secs(60)),
+            connection_stall_timeout: Some(Duration::from_secs(30)),
+            audit_log: None,
+            audit_log_max_size: XByte::new(10, 0, Unit::MB).to_bytes(),
+            // 校验受磁盘读取速度限制，并发过多反而更慢
+            verify_concurrency: 2,
+            pool: PoolConfig::default(),
+            disk_pressure: None,
+            temp_dir: None,
+            part_suffix: Some(".part".to_string()),
+            preallocate: PreallocateMode::Sparse,
+            max_buffered_bytes: None,
+            max_memory_download: 16 * 1024 * 1024,
+            case_insensitive_fs: CaseInsensitiveFs::Auto,
+            sequential_first: false,
+            sequential_prefix: XByte::new(8, 0, Unit::MB).to_bytes(),
+            chunk_order: ChunkOrder::Parallel,
+            tasks_changed_interval: None,
+            event_replay_capacity: 256,
+            stats_interval: None,
+            ramp_up: None,
+            forecast_probe_limit: 8,
+            locale: None,
+            archive_retention: Some(Duration::from_secs(30 * 24 * 3600)),
+            queue_save_interval: Some(Duration::from_millis(200)),
+            ip_policy: IpPolicy::Auto,
+            resolve: Vec::new(),
+            local_address: None,
+            on_chunk_exhausted: ChunkFailurePolicy::FailTask,
+            event_journal: true,
+            completed_link_dir: None,
+            completed_link_copy: false,
         }
     }
 }
 
 /// 下载配置（向后兼容）
 pub type DownloadConfig = Config;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_priority_parse_and_display() {
+        for priority in [TaskPriority::Low, TaskPriority::Normal, TaskPriority::High] {
+            assert_eq!(
+                priority.to_string().parse::<TaskPriority>().unwrap(),
+                priority
+            );
+            assert_eq!(
+                (priority as u8)
+                    .to_string()
+                    .parse::<TaskPriority>()
+                    .unwrap(),
+                priority
+            );
+        }
+        assert_eq!(
+            " HIGH ".parse::<TaskPriority>().unwrap(),
+            TaskPriority::High
+        );
+        assert_eq!(TaskPriority::Normal.to_string(), "normal");
+        let error = "urgent".parse::<TaskPriority>().unwrap_err();
+        assert!(matches!(error, crate::Error::InvalidPriority(ref s) if s == "urgent"));
+
+        // 队列状态文件中保存的是变体名称，格式不能变
+        for (priority, json) in [
+            (TaskPriority::Low, "\"Low\""),
+            (TaskPriority::Normal, "\"Normal\""),
+            (TaskPriority::High, "\"High\""),
+        ] {
+            assert_eq!(serde_json::to_string(&priority).unwrap(), json);
+            assert_eq!(
+                serde_json::from_str::<TaskPriority>(json).unwrap(),
+                priority
+            );
+        }
+    }
+
+    #[test]
+    fn test_record_event_is_bounded() {
+        let mut task: Task = serde_json::from_value(json!({
+            "id": "t",
+            "url": "https://example.com/a.bin",
+            "dest": "/downloads/a.bin",
+            "status": "Pending",
+            "total_size": 0,
+            "downloaded": 0,
+            "created_at": 0,
+            "error": null,
+        }))
+        .unwrap();
+        for attempt in 1..=MAX_RECENT_EVENTS as u32 + 5 {
+            task.record_event(CompactEventKind::Retrying {
+                attempt,
+                reason: RetryReason::Stalled,
+            });
+        }
+        assert_eq!(task.recent_events.len(), MAX_RECENT_EVENTS);
+        assert_eq!(
+            task.recent_events[0].kind,
+            CompactEventKind::Retrying {
+                attempt: 6,
+                reason: RetryReason::Stalled,
+            }
+        );
+    }
+
+    #[test]
+    fn test_checksum_parse_auto() {
+        let md5 = "d41d8cd98f00b204e9800998ecf8427e";
+        let sha256 = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+        let sha512 = "ab".repeat(64);
+        let cases: &[(&str, Option<ChecksumType>)] = &[
+            (md5, Some(ChecksumType::Md5(md5.to_string()))),
+            (sha256, Some(ChecksumType::Sha256(sha256.to_string()))),
+            (&sha512, Some(ChecksumType::Sha512(sha512.clone()))),
+            // 前后的空白被去掉
+            (
+                "  d41d8cd98f00b204e9800998ecf8427e\n",
+                Some(ChecksumType::Md5(md5.to_string())),
+            ),
+            // SHA-1
+            ("da39a3ee5e6b4b0d3255bfef95601890afd80709", None),
+            ("abc", None),
+            ("", None),
+            ("g41d8cd98f00b204e9800998ecf8427e", None),
+            ("sha256:e3b0c44298fc1c149afbf4c8996fb924", None),
+        ];
+        for (input, expected) in cases {
+            match (ChecksumType::parse_auto(input), expected) {
+                (Ok(checksum), Some(expected)) => assert_eq!(&checksum, expected),
+                (Err(crate::Error::InvalidChecksum(_)), None) => {}
+                (result, _) => panic!("{:?}: {:?}", input, result),
+            }
+        }
+        let error = ChecksumType::parse_auto(&"0".repeat(40)).unwrap_err();
+        assert!(error.to_string().contains("SHA-1"));
+    }
+}
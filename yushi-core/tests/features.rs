@@ -0,0 +1,55 @@
+//! 特性组合的测试
+//!
+//! 每个组合单独编译并运行单元测试和文档测试，用到某个特性的测试只在开启该特性时运行。
+//! 每个组合都要单独编译一遍依赖，耗时较长，默认忽略：
+//!
+//! ```sh
+//! cargo test -p yushi-core --test features -- --ignored
+//! ```
+
+use std::{path::Path, process::Command};
+
+/// 关闭默认特性后依次只开启其中一个
+const FEATURES: &[&str] = &[
+    "",
+    "checksums",
+    "clap",
+    "compat",
+    "queue-persistence",
+    "uuid-ids",
+];
+
+/// 只开启 `features` 运行 `cargo test`，`target` 为 `--lib` 或 `--doc`
+fn cargo_test(features: &str, target: &str) {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    // 与外层 cargo 使用不同的目标目录，避免等待构建锁
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("features");
+    let output = Command::new(env!("CARGO"))
+        .arg("test")
+        .arg(target)
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .args(["--no-default-features", "--features", features])
+        .env("RUSTFLAGS", "-D warnings")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "features {:?} ({}):\n{}\n{}",
+        features,
+        target,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+#[ignore = "slow: builds and tests yushi-core once per feature set"]
+fn test_each_feature_passes_alone() {
+    for features in FEATURES {
+        cargo_test(features, "--lib");
+        cargo_test(features, "--doc");
+    }
+}